@@ -0,0 +1,143 @@
+//! Append-only flat file holding the blocks moved out of the key-value store
+//!
+//! Blocks deep enough below the tip are never modified again, so they are moved out of the
+//! key-value store into a flat file, keeping only their position in the file in the key-value
+//! store. The file is only ever appended to: a block is written to the file and synced before its
+//! position is committed, so a transaction that fails after the block was written leaves just
+//! unreferenced bytes behind.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use common::primitives::BlockDistance;
+use serialization::{Decode, Encode};
+
+/// Position of a block in the block file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct BlockFilePosition {
+    offset: u64,
+    len: u32,
+}
+
+/// Path of the block file belonging to the storage persisted at `storage_path`
+pub fn block_file_path(storage_path: &Path) -> PathBuf {
+    let mut path = OsString::from(storage_path.as_os_str());
+    path.push(".blocks");
+    PathBuf::from(path)
+}
+
+/// Block file of a persisted storage
+pub struct BlockFile {
+    path: PathBuf,
+    /// The file, opened on first use since it only exists once a block has been moved to it
+    file: Mutex<Option<File>>,
+    /// How deep below the tip the main chain blocks are moved to the file, if at all
+    cold_block_depth: Option<BlockDistance>,
+}
+
+impl BlockFile {
+    pub fn new(path: PathBuf, cold_block_depth: Option<BlockDistance>) -> Self {
+        Self {
+            path,
+            file: Mutex::new(None),
+            cold_block_depth,
+        }
+    }
+
+    pub fn cold_block_depth(&self) -> Option<BlockDistance> {
+        self.cold_block_depth
+    }
+
+    // Run `f` on the file, opening it first if needed
+    //
+    // The file is only opened for writing if blocks are moved to it.
+    fn with_file<R>(&self, f: impl FnOnce(&mut File) -> io::Result<R>) -> io::Result<R> {
+        let writable = self.cold_block_depth.is_some();
+        let mut file = self.file.lock().expect("Mutex locked by a crashed thread");
+        let file = match &mut *file {
+            Some(file) => file,
+            None => file.insert(
+                OpenOptions::new()
+                    .read(true)
+                    .append(writable)
+                    .create(writable)
+                    .open(&self.path)?,
+            ),
+        };
+        f(file)
+    }
+
+    /// Durably append an encoded block to the file
+    pub fn append(&self, data: &[u8]) -> io::Result<BlockFilePosition> {
+        let len = data
+            .len()
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Block too large"))?;
+        self.with_file(|file| {
+            let offset = file.seek(SeekFrom::End(0))?;
+            file.write_all(data)?;
+            file.sync_data()?;
+            Ok(BlockFilePosition { offset, len })
+        })
+    }
+
+    /// Read the encoded block at given position
+    pub fn read(&self, position: &BlockFilePosition) -> io::Result<Vec<u8>> {
+        self.with_file(|file| {
+            let mut data = vec![0; position.len as usize];
+            file.seek(SeekFrom::Start(position.offset))?;
+            file.read_exact(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    /// Copy the file to a new file at `path`, if any block has been moved to it
+    ///
+    /// Fails if there is a file at `path` already.
+    pub fn copy_to(&self, path: &Path) -> io::Result<()> {
+        // Hold the lock so that no block is appended while the file is being copied
+        let _file = self.file.lock().expect("Mutex locked by a crashed thread");
+        let mut source = match File::open(&self.path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut target = OpenOptions::new().write(true).create_new(true).open(path)?;
+        let result = io::copy(&mut source, &mut target).and_then(|_| target.sync_all());
+        if result.is_err() {
+            let _ = fs::remove_file(path);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks");
+        let file = BlockFile::new(path.clone(), Some(BlockDistance::new(10)));
+
+        let pos1 = file.append(b"first block").unwrap();
+        let pos2 = file.append(b"second").unwrap();
+        assert_eq!(file.read(&pos2).unwrap(), b"second");
+        assert_eq!(file.read(&pos1).unwrap(), b"first block");
+        drop(file);
+
+        let file = BlockFile::new(path.clone(), None);
+        assert_eq!(file.read(&pos2).unwrap(), b"second");
+        drop(file);
+
+        let file = BlockFile::new(path, Some(BlockDistance::new(10)));
+        assert_eq!(
+            file.append(b"third").unwrap(),
+            BlockFilePosition { offset: 17, len: 5 }
+        );
+    }
+}
@@ -5,16 +5,21 @@ use common::chain::block::Block;
 use common::chain::transaction::{Transaction, TxMainChainIndex, TxMainChainPosition};
 use common::chain::OutPoint;
 use common::chain::OutPointSourceId;
-use common::primitives::{BlockHeight, Id};
+use common::primitives::{BlockDistance, BlockHeight, Id};
 use storage::traits;
 use utxo::{BlockUndo, Utxo};
 
+mod block_file;
+pub mod main_chain;
 #[cfg(any(test, feature = "mock"))]
 pub mod mock;
 mod store;
+pub mod undo;
 mod utxo_db;
+pub mod verify;
 
 pub use storage::transaction::{TransactionRo, TransactionRw};
+pub use storage::MapStats as ColumnStats;
 pub use store::Store;
 
 /// Blockchain storage error
@@ -22,6 +27,9 @@ pub use store::Store;
 pub enum Error {
     #[error("Storage error: {0}")]
     Storage(storage::error::Recoverable),
+    /// A stored block doesn't match its ID or merkle roots
+    #[error("Stored block data is corrupted")]
+    BlockCorrupted,
 }
 
 impl From<storage::Error> for Error {
@@ -33,6 +41,12 @@ impl From<storage::Error> for Error {
 /// Possibly failing result of blockchain storage query
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Depth of the deepest reorg the undo data is kept for
+///
+/// Adding the undo data of a block prunes the undo data of the main chain block this many blocks
+/// below it.
+pub const MAX_REORG_DEPTH: BlockDistance = BlockDistance::new(1000);
+
 /// Queries on persistent blockchain data
 pub trait BlockchainStorageRead {
     /// Get storage version
@@ -117,11 +131,18 @@ pub(crate) trait UtxoWrite: UtxoRead {
     fn set_best_block_for_utxos(&mut self, block_id: &Id<Block>) -> crate::Result<()>;
 }
 
-pub(crate) trait UndoRead {
+/// Queries on the per-block undo data
+pub trait UndoRead {
+    /// Get the undo data of given block
     fn get_undo_data(&self, id: Id<Block>) -> crate::Result<Option<BlockUndo>>;
 }
-pub(crate) trait UndoWrite: UndoRead {
+
+/// Modifying operations on the per-block undo data
+pub trait UndoWrite: UndoRead {
+    /// Add the undo data of given block, pruning the undo data older than [`MAX_REORG_DEPTH`]
     fn add_undo_data(&mut self, id: Id<Block>, undo: &BlockUndo) -> crate::Result<()>;
+
+    /// Remove the undo data of given block
     fn del_undo_data(&mut self, id: Id<Block>) -> crate::Result<()>;
 }
 
@@ -140,4 +161,40 @@ pub trait Transactional<'t> {
     fn transaction_rw<'s: 't>(&'s self) -> Self::TransactionRw;
 }
 
-pub trait BlockchainStorage: BlockchainStorageWrite + for<'tx> Transactional<'tx> {}
+/// Number of entries and approximate size of the storage columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Statistics {
+    pub blocks: ColumnStats,
+    pub block_index: ColumnStats,
+    pub block_by_height: ColumnStats,
+    pub tx_index: ColumnStats,
+    pub utxo: ColumnStats,
+    pub undo: ColumnStats,
+    pub block_file_index: ColumnStats,
+}
+
+impl Statistics {
+    /// Approximate size of all the columns in bytes
+    pub fn total_bytes(&self) -> usize {
+        [
+            self.blocks,
+            self.block_index,
+            self.block_by_height,
+            self.tx_index,
+            self.utxo,
+            self.undo,
+            self.block_file_index,
+        ]
+        .iter()
+        .map(|column| column.bytes)
+        .sum()
+    }
+}
+
+pub trait BlockchainStorage: BlockchainStorageWrite + for<'tx> Transactional<'tx> {
+    /// Write a consistent copy of the storage to `path`, to be opened as a persistent storage
+    fn backup_to(&self, path: &std::path::Path) -> std::io::Result<()>;
+
+    /// Get the number of entries and approximate size of the storage columns
+    fn statistics(&self) -> Statistics;
+}
@@ -0,0 +1,104 @@
+//! Iteration over the main chain
+//!
+//! The block indices of the main chain blocks are iterated in the order of their height, stopping
+//! at the first height without a stored block index. Together with
+//! [`Store::open_read_only`](crate::Store::open_read_only) this lets external tools, such as
+//! indexers, walk the blocks stored by a node.
+
+use common::chain::block::block_index::BlockIndex;
+use common::primitives::BlockHeight;
+
+use crate::BlockchainStorageRead;
+
+/// Iterator over the block indices of the main chain blocks, in the order of their height
+pub struct MainChainBlockIndex<'a, S> {
+    storage: &'a S,
+    height: Option<BlockHeight>,
+}
+
+/// Iterate over the block indices of the main chain blocks, starting at `start_height`
+pub fn main_chain_block_index<S: BlockchainStorageRead>(
+    storage: &S,
+    start_height: BlockHeight,
+) -> MainChainBlockIndex<'_, S> {
+    MainChainBlockIndex {
+        storage,
+        height: Some(start_height),
+    }
+}
+
+impl<'a, S: BlockchainStorageRead> MainChainBlockIndex<'a, S> {
+    fn next_index(&mut self) -> crate::Result<Option<BlockIndex>> {
+        let height = match self.height.take() {
+            Some(height) => height,
+            None => return Ok(None),
+        };
+        let block_id = match self.storage.get_block_id_by_height(&height)? {
+            Some(block_id) => block_id,
+            None => return Ok(None),
+        };
+        let block_index = self.storage.get_block_index(&block_id)?;
+        if block_index.is_some() {
+            self.height = height.checked_add(1);
+        }
+        Ok(block_index)
+    }
+}
+
+impl<'a, S: BlockchainStorageRead> Iterator for MainChainBlockIndex<'a, S> {
+    type Item = crate::Result<BlockIndex>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_index().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BlockchainStorageWrite, Store};
+    use common::chain::block::{Block, ConsensusData};
+    use common::chain::Transaction;
+    use common::primitives::{Id, Idable};
+
+    #[test]
+    #[cfg(not(loom))]
+    fn iterate_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blockchain");
+
+        let mut store = Store::open(&path).unwrap();
+        let mut ids: Vec<Id<Block>> = Vec::new();
+        for height in 0..4 {
+            let tx = Transaction::new(0, vec![], vec![], height as u32).unwrap();
+            let block = Block::new(vec![tx], ids.last().cloned(), 0, ConsensusData::None).unwrap();
+            let height = BlockHeight::new(height);
+            store.add_block(&block).unwrap();
+            store.set_block_index(&BlockIndex::new(&block, 1, height, 0)).unwrap();
+            store.set_block_id_at_height(&height, &block.get_id()).unwrap();
+            ids.push(block.get_id());
+        }
+        drop(store);
+
+        let mut store = Store::open_read_only(&path).unwrap();
+        let indices = main_chain_block_index(&store, BlockHeight::new(1))
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        let index_ids =
+            indices.iter().map(|index| index.get_block_id().clone()).collect::<Vec<_>>();
+        assert_eq!(index_ids, ids[1..]);
+        assert_eq!(
+            indices
+                .iter()
+                .map(|index| u64::from(index.get_block_height()))
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            main_chain_block_index(&store, BlockHeight::new(4)).count(),
+            0
+        );
+
+        assert!(store.set_best_block_id(&ids[0]).is_err());
+    }
+}
@@ -63,7 +63,10 @@ mockall::mock! {
         fn transaction_rw<'st>(&'st self) -> MockStoreTxRw where 'st: 'tx;
     }
 
-    impl crate::BlockchainStorage for Store {}
+    impl crate::BlockchainStorage for Store {
+        fn backup_to(&self, path: &std::path::Path) -> std::io::Result<()>;
+        fn statistics(&self) -> crate::Statistics;
+    }
 }
 
 mockall::mock! {
@@ -1,13 +1,16 @@
 use common::chain::block::block_index::BlockIndex;
-use common::chain::block::Block;
+use common::chain::block::{calculate_tx_merkle_root, calculate_witness_merkle_root, Block};
 use common::chain::transaction::{Transaction, TxMainChainIndex, TxMainChainPosition};
 use common::chain::OutPoint;
 use common::chain::OutPointSourceId;
-use common::primitives::{BlockHeight, Id, Idable};
+use common::primitives::{BlockDistance, BlockHeight, Id, Idable};
+use logging::log;
 use serialization::{Codec, Decode, DecodeAll, Encode};
+use std::sync::Arc;
 use storage::traits::{self, MapMut, MapRef, TransactionRo, TransactionRw};
 use utxo::{BlockUndo, Utxo};
 
+use crate::block_file::{block_file_path, BlockFile, BlockFilePosition};
 use crate::{
     BlockchainStorage, BlockchainStorageRead, BlockchainStorageWrite, Transactional, UndoRead,
     UndoWrite, UtxoRead, UtxoWrite,
@@ -55,7 +58,9 @@ storage::decl_schema! {
         // Store for Utxo Entries
         pub DBUtxo: Single,
         // Store for BlockUndo
-        pub DBBlockUndo: Single
+        pub DBBlockUndo: Single,
+        // Positions of the blocks moved to the block file.
+        pub DBBlockFilePos: Single
     }
 }
 
@@ -64,17 +69,62 @@ type RoTxImpl<'tx> = <StoreImpl as traits::Transactional<'tx, Schema>>::Transact
 type RwTxImpl<'tx> = <StoreImpl as traits::Transactional<'tx, Schema>>::TransactionRw;
 
 /// Persistent store for blockchain data
+///
+/// Persisted stores may hold the blocks deep below the tip in a block file, see
+/// [`Store::open_with_cold_blocks`].
 #[derive(Clone)]
-pub struct Store(StoreImpl);
+pub struct Store(StoreImpl, Option<Arc<BlockFile>>);
 
 /// Store for blockchain data
 impl Store {
     /// New empty storage
     pub fn new_empty() -> crate::Result<Self> {
-        let mut store = Self(storage::Store::default());
+        let mut store = Self(storage::Store::default(), None);
         store.set_storage_version(1)?;
         Ok(store)
     }
+
+    /// Open the storage persisted in the file at `path`, creating an empty one if missing
+    pub fn open(path: &std::path::Path) -> crate::Result<Self> {
+        Self::open_impl(path, None)
+    }
+
+    /// Open the storage persisted in the file at `path`, creating an empty one if missing, and
+    /// move the main chain blocks `cold_block_depth` below the tip to a block file
+    ///
+    /// The blocks are appended to the file at `path` with the `.blocks` extension added, keeping
+    /// the key-value store small. The storage can be opened with [`Store::open`] again later,
+    /// the blocks already in the file remain readable.
+    pub fn open_with_cold_blocks(
+        path: &std::path::Path,
+        cold_block_depth: BlockDistance,
+    ) -> crate::Result<Self> {
+        Self::open_impl(path, Some(cold_block_depth))
+    }
+
+    fn open_impl(
+        path: &std::path::Path,
+        cold_block_depth: Option<BlockDistance>,
+    ) -> crate::Result<Self> {
+        let block_file = BlockFile::new(block_file_path(path), cold_block_depth);
+        let mut store = Self(storage::Store::open(path)?, Some(Arc::new(block_file)));
+        if store.get_storage_version()? == 0 {
+            store.set_storage_version(1)?;
+        }
+        Ok(store)
+    }
+
+    /// Open the storage persisted in the file at `path` for reading only
+    ///
+    /// Meant for external tools reading the storage of a node that is not running. The file is
+    /// left unmodified and any attempt to change the storage fails.
+    pub fn open_read_only(path: &std::path::Path) -> crate::Result<Self> {
+        let block_file = BlockFile::new(block_file_path(path), None);
+        Ok(Self(
+            storage::Store::open_read_only(path)?,
+            Some(Arc::new(block_file)),
+        ))
+    }
 }
 
 impl<'tx> crate::Transactional<'tx> for Store {
@@ -82,15 +132,54 @@ impl<'tx> crate::Transactional<'tx> for Store {
     type TransactionRw = StoreTx<RwTxImpl<'tx>>;
 
     fn transaction_ro<'st: 'tx>(&'st self) -> Self::TransactionRo {
-        StoreTx(traits::Transactional::transaction_ro(&self.0))
+        StoreTx(
+            traits::Transactional::transaction_ro(&self.0),
+            self.1.clone(),
+        )
     }
 
     fn transaction_rw<'st: 'tx>(&'st self) -> Self::TransactionRw {
-        StoreTx(traits::Transactional::transaction_rw(&self.0))
+        StoreTx(
+            traits::Transactional::transaction_rw(&self.0),
+            self.1.clone(),
+        )
     }
 }
 
-impl BlockchainStorage for Store {}
+impl BlockchainStorage for Store {
+    fn backup_to(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let block_file_backup = block_file_path(path);
+        if self.1.is_some() && block_file_backup.symlink_metadata().is_ok() {
+            return Err(std::io::ErrorKind::AlreadyExists.into());
+        }
+        self.0.backup_to(path)?;
+        // Blocks are moved to the block file before their position is stored, so the file
+        // copied afterwards holds all the blocks the backup refers to
+        let result = match &self.1 {
+            Some(block_file) => block_file.copy_to(&block_file_backup),
+            None => Ok(()),
+        };
+        if result.is_err() {
+            let _ = std::fs::remove_file(path);
+        }
+        result
+    }
+
+    fn statistics(&self) -> crate::Statistics {
+        use storage::schema::DBIndex;
+        let stats = self.0.map_stats();
+        let column = |name| stats.get(name).copied().unwrap_or_default();
+        crate::Statistics {
+            blocks: column(DBBlock::NAME),
+            block_index: column(DBBlockIndex::NAME),
+            block_by_height: column(DBBlockByHeight::NAME),
+            tx_index: column(DBTxIndex::NAME),
+            utxo: column(DBUtxo::NAME),
+            undo: column(DBBlockUndo::NAME),
+            block_file_index: column(DBBlockFilePos::NAME),
+        }
+    }
+}
 
 macro_rules! delegate_to_transaction {
     ($(fn $f:ident $args:tt -> $ret:ty;)*) => {
@@ -195,8 +284,22 @@ impl UndoWrite for Store {
     }
 }
 
-/// A wrapper around a storage transaction type
-pub struct StoreTx<T>(T);
+/// A wrapper around a storage transaction type, with the block file of the store if any
+pub struct StoreTx<T>(T, Option<Arc<BlockFile>>);
+
+// Decode a stored block, checking it against its ID and merkle roots to detect corrupted data
+fn decode_block(id: &Id<Block>, data: &[u8]) -> crate::Result<Block> {
+    let block = Block::decode_all(&mut &*data).map_err(|_| crate::Error::BlockCorrupted)?;
+    let intact = block.get_id() == *id
+        && calculate_tx_merkle_root(block.transactions()).ok() == Some(block.merkle_root())
+        && calculate_witness_merkle_root(block.transactions()).ok()
+            == Some(block.witness_merkle_root());
+    if intact {
+        Ok(block)
+    } else {
+        Err(crate::Error::BlockCorrupted)
+    }
+}
 
 /// Blockchain data storage transaction
 impl<Tx: for<'a> traits::GetMapRef<'a, Schema>> BlockchainStorageRead for StoreTx<Tx> {
@@ -214,7 +317,7 @@ impl<Tx: for<'a> traits::GetMapRef<'a, Schema>> BlockchainStorageRead for StoreT
     }
 
     fn get_block(&self, id: Id<Block>) -> crate::Result<Option<Block>> {
-        self.read::<DBBlock, _, _>(id.as_ref())
+        self.with_block_data(&id, |data| decode_block(&id, data))?.transpose()
     }
 
     fn get_mainchain_tx_index(
@@ -229,18 +332,13 @@ impl<Tx: for<'a> traits::GetMapRef<'a, Schema>> BlockchainStorageRead for StoreT
         tx_index: &TxMainChainPosition,
     ) -> crate::Result<Option<Transaction>> {
         let block_id = tx_index.get_block_id();
-        match self.0.get::<DBBlock, _>().get(block_id.as_ref()) {
-            Err(e) => Err(e.into()),
-            Ok(None) => Ok(None),
-            Ok(Some(block)) => {
-                let begin = tx_index.get_byte_offset_in_block() as usize;
-                let end = begin + tx_index.get_serialized_size() as usize;
-                let encoded_tx = block.get(begin..end).expect("Transaction outside of block range");
-                let tx =
-                    Transaction::decode_all(&mut &*encoded_tx).expect("Invalid tx encoding in DB");
-                Ok(Some(tx))
-            }
-        }
+        self.with_block_data(block_id, |block| {
+            let begin = tx_index.get_byte_offset_in_block() as usize;
+            let end = begin + tx_index.get_serialized_size() as usize;
+            let mut encoded_tx = block.get(begin..end).ok_or(crate::Error::BlockCorrupted)?;
+            Transaction::decode_all(&mut encoded_tx).map_err(|_| crate::Error::BlockCorrupted)
+        })?
+        .transpose()
     }
 
     fn get_block_id_by_height(&self, height: &BlockHeight) -> crate::Result<Option<Id<Block>>> {
@@ -279,6 +377,7 @@ impl<Tx: for<'a> traits::GetMapMut<'a, Schema>> BlockchainStorageWrite for Store
     }
 
     fn del_block(&mut self, id: Id<Block>) -> crate::Result<()> {
+        self.0.get_mut::<DBBlockFilePos, _>().del(id.as_ref())?;
         self.0.get_mut::<DBBlock, _>().del(id.as_ref()).map_err(Into::into)
     }
 
@@ -303,7 +402,16 @@ impl<Tx: for<'a> traits::GetMapMut<'a, Schema>> BlockchainStorageWrite for Store
         height: &BlockHeight,
         block_id: &Id<Block>,
     ) -> crate::Result<()> {
-        self.write::<DBBlockByHeight, _, _>(height.encode(), block_id)
+        self.write::<DBBlockByHeight, _, _>(height.encode(), block_id)?;
+
+        // The main chain block deep enough below the new one is moved to the block file
+        let cold_depth = self.1.as_ref().and_then(|block_file| block_file.cold_block_depth());
+        if let Some(cold_height) = cold_depth.and_then(|depth| *height - depth) {
+            if let Some(cold_id) = self.get_block_id_by_height(&cold_height)? {
+                self.move_block_to_file(&cold_id)?;
+            }
+        }
+        Ok(())
     }
 
     fn del_block_id_at_height(&mut self, height: &BlockHeight) -> crate::Result<()> {
@@ -329,7 +437,18 @@ impl<Tx: for<'a> traits::GetMapMut<'a, Schema>> UtxoWrite for StoreTx<Tx> {
 
 impl<Tx: for<'a> traits::GetMapMut<'a, Schema>> UndoWrite for StoreTx<Tx> {
     fn add_undo_data(&mut self, id: Id<Block>, undo: &BlockUndo) -> crate::Result<()> {
-        self.write::<DBBlockUndo, _, _>(id.encode(), undo)
+        self.write::<DBBlockUndo, _, _>(id.encode(), undo)?;
+
+        // Blocks deeper than the reorg window are never disconnected, drop their undo data
+        let pruned_height = undo.height() - crate::MAX_REORG_DEPTH;
+        if let Some(pruned_id) = pruned_height
+            .map(|height| self.get_block_id_by_height(&height))
+            .transpose()?
+            .flatten()
+        {
+            self.del_undo_data(pruned_id)?;
+        }
+        Ok(())
     }
 
     fn del_undo_data(&mut self, id: Id<Block>) -> crate::Result<()> {
@@ -354,6 +473,32 @@ impl<'a, Tx: traits::GetMapRef<'a, Schema>> StoreTx<Tx> {
     fn read_value<E: well_known::Entry>(&'a self) -> crate::Result<Option<E::Value>> {
         self.read::<DBValue, _, _>(E::KEY)
     }
+
+    // Apply `f` to the encoded block, read from the database or the block file
+    fn with_block_data<R>(
+        &'a self,
+        id: &Id<Block>,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> crate::Result<Option<R>> {
+        let col = self.0.get::<DBBlock, _>();
+        if let Some(data) = col.get(id.as_ref()).map_err(crate::Error::from)? {
+            return Ok(Some(f(data)));
+        }
+        let position = match self.read::<DBBlockFilePos, _, BlockFilePosition>(id.as_ref())? {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let data =
+            self.1
+                .as_ref()
+                .ok_or(crate::Error::BlockCorrupted)?
+                .read(&position)
+                .map_err(|e| {
+                    log::error!("Failed to read block {:?} from the block file: {}", id, e);
+                    crate::Error::BlockCorrupted
+                })?;
+        Ok(Some(f(&data)))
+    }
 }
 
 impl<'a, Tx: traits::GetMapMut<'a, Schema>> StoreTx<Tx> {
@@ -373,6 +518,30 @@ impl<'a, Tx: traits::GetMapMut<'a, Schema>> StoreTx<Tx> {
     }
 }
 
+impl<Tx: for<'a> traits::GetMapMut<'a, Schema>> StoreTx<Tx> {
+    // Move a block from the database to the block file
+    //
+    // The block stays in the database if it can't be written to the file.
+    fn move_block_to_file(&mut self, id: &Id<Block>) -> crate::Result<()> {
+        let block_file = match &self.1 {
+            Some(block_file) => Arc::clone(block_file),
+            None => return Ok(()),
+        };
+        let position = match self.0.get::<DBBlock, _>().get(id.as_ref())? {
+            Some(data) => match block_file.append(data) {
+                Ok(position) => position,
+                Err(e) => {
+                    log::warn!("Failed to move block {:?} to the block file: {}", id, e);
+                    return Ok(());
+                }
+            },
+            None => return Ok(()),
+        };
+        self.0.get_mut::<DBBlockFilePos, _>().put(id.encode(), position.encode())?;
+        self.0.get_mut::<DBBlock, _>().del(id.as_ref()).map_err(Into::into)
+    }
+}
+
 impl<T: traits::TransactionRw<Error = storage::Error>> traits::TransactionRw for StoreTx<T> {
     type Error = crate::Error;
 
@@ -402,6 +571,14 @@ pub(crate) mod test {
     use crypto::random::{make_pseudo_rng, Rng};
     use utxo::{BlockUndo, TxUndo};
 
+    /// Overwrite the stored data of a block
+    pub(crate) fn corrupt_block(store: &Store, id: &Id<Block>, data: Vec<u8>) {
+        use storage::traits::GetMapMut;
+        let mut tx = store.transaction_rw();
+        tx.0.get_mut::<DBBlock, _>().put(id.encode(), data).unwrap();
+        tx.commit().unwrap();
+    }
+
     #[test]
     fn test_storage_get_default_version_in_tx() {
         common::concurrency::model(|| {
@@ -413,6 +590,173 @@ pub(crate) mod test {
         })
     }
 
+    #[test]
+    #[cfg(not(loom))]
+    fn test_storage_persistence() {
+        use common::chain::block::ConsensusData;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blockchain");
+        let block = Block::new(vec![], None, 12, ConsensusData::None).unwrap();
+
+        let mut store = Store::open(&path).unwrap();
+        assert_eq!(store.get_storage_version(), Ok(1));
+        assert_eq!(store.add_block(&block), Ok(()));
+        assert_eq!(store.set_best_block_id(&block.get_id()), Ok(()));
+        drop(store);
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.get_storage_version(), Ok(1));
+        assert_eq!(store.get_best_block_id(), Ok(Some(block.get_id())));
+        assert_eq!(store.get_block(block.get_id()), Ok(Some(block)));
+    }
+
+    #[test]
+    #[cfg(not(loom))]
+    fn test_cold_blocks() {
+        use common::chain::block::ConsensusData;
+        use storage::traits::GetMapRef;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blockchain");
+        let backup_path = dir.path().join("backup");
+
+        let mut store = Store::open_with_cold_blocks(&path, BlockDistance::new(2)).unwrap();
+        let mut blocks: Vec<Block> = Vec::new();
+        for height in 0..5 {
+            let tx = Transaction::new(0, vec![], vec![], height as u32).unwrap();
+            let prev_id = blocks.last().map(|block| block.get_id());
+            let block = Block::new(vec![tx], prev_id, 0, ConsensusData::None).unwrap();
+            assert_eq!(store.add_block(&block), Ok(()));
+            assert_eq!(
+                store.set_block_id_at_height(&BlockHeight::new(height), &block.get_id()),
+                Ok(())
+            );
+            blocks.push(block);
+        }
+
+        // Blocks at heights 0 to 2 are in the block file
+        let tx = store.transaction_ro();
+        let kv_blocks = blocks
+            .iter()
+            .map(|block| tx.0.get::<DBBlock, _>().get(block.get_id().as_ref()).unwrap().is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(kv_blocks, [false, false, false, true, true]);
+        drop(tx);
+        assert!(block_file_path(&path).exists());
+
+        let check_blocks = |store: &Store| {
+            for block in &blocks {
+                assert_eq!(store.get_block(block.get_id()), Ok(Some(block.clone())));
+            }
+            let tx = &blocks[1].transactions()[0];
+            let (enc_tx, enc_block) = (tx.encode(), blocks[1].encode());
+            let offset = enc_block.windows(enc_tx.len()).position(|d| d == enc_tx).unwrap();
+            let position =
+                TxMainChainPosition::new(blocks[1].get_id(), offset as u32, enc_tx.len() as u32);
+            assert_eq!(
+                store.get_mainchain_tx_by_position(&position),
+                Ok(Some(tx.clone()))
+            );
+        };
+        check_blocks(&store);
+        store.backup_to(&backup_path).unwrap();
+        drop(store);
+
+        check_blocks(&Store::open(&path).unwrap());
+        check_blocks(&Store::open_read_only(&path).unwrap());
+        check_blocks(&Store::open(&backup_path).unwrap());
+    }
+
+    #[test]
+    #[cfg(not(loom))]
+    fn test_backup_onto_live_store() {
+        use common::chain::block::ConsensusData;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blockchain");
+
+        let mut store = Store::open(&path).unwrap();
+        let tx = Transaction::new(0, vec![], vec![], 0).unwrap();
+        let block = Block::new(vec![tx], None, 0, ConsensusData::None).unwrap();
+        assert_eq!(store.add_block(&block), Ok(()));
+        assert_eq!(
+            store.backup_to(&path).unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+
+        // The live journal still takes the commits
+        assert_eq!(store.set_best_block_id(&block.get_id()), Ok(()));
+        drop(store);
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.get_block(block.get_id()), Ok(Some(block.clone())));
+        assert_eq!(store.get_best_block_id(), Ok(Some(block.get_id())));
+    }
+
+    #[test]
+    #[cfg(not(loom))]
+    fn test_statistics() {
+        use common::chain::block::ConsensusData;
+
+        let block = Block::new(vec![], None, 12, ConsensusData::None).unwrap();
+        let mut store = Store::new_empty().unwrap();
+        assert_eq!(store.statistics().blocks, crate::ColumnStats::default());
+
+        assert_eq!(store.add_block(&block), Ok(()));
+        let stats = store.statistics();
+        assert_eq!(stats.blocks.entries, 1);
+        assert_eq!(
+            stats.blocks.bytes,
+            block.get_id().encode().len() + block.encode().len()
+        );
+        assert_eq!(stats.block_index, crate::ColumnStats::default());
+        assert_eq!(stats.total_bytes(), stats.blocks.bytes);
+    }
+
+    #[test]
+    #[cfg(not(loom))]
+    fn test_corrupted_block() {
+        use common::chain::block::ConsensusData;
+
+        let tx = Transaction::new(0xaabbccdd, vec![], vec![], 12).unwrap();
+        let block = Block::new(vec![tx], None, 12, ConsensusData::None).unwrap();
+        let other_block = Block::new(vec![], None, 34, ConsensusData::None).unwrap();
+        let mut store = Store::new_empty().unwrap();
+        assert_eq!(store.add_block(&block), Ok(()));
+
+        let mut data = block.encode();
+        *data.last_mut().unwrap() ^= 1;
+        corrupt_block(&store, &block.get_id(), data);
+        assert_eq!(
+            store.get_block(block.get_id()),
+            Err(crate::Error::BlockCorrupted)
+        );
+
+        corrupt_block(&store, &block.get_id(), other_block.encode());
+        assert_eq!(
+            store.get_block(block.get_id()),
+            Err(crate::Error::BlockCorrupted)
+        );
+
+        corrupt_block(&store, &block.get_id(), vec![1, 2, 3]);
+        assert_eq!(
+            store.get_block(block.get_id()),
+            Err(crate::Error::BlockCorrupted)
+        );
+
+        // Reading a transaction by position doesn't panic on corrupted data either
+        let tx_position = TxMainChainPosition::new(block.get_id(), 1, 100);
+        assert_eq!(
+            store.get_mainchain_tx_by_position(&tx_position),
+            Err(crate::Error::BlockCorrupted)
+        );
+        let tx_position = TxMainChainPosition::new(block.get_id(), 0, 3);
+        assert_eq!(
+            store.get_mainchain_tx_by_position(&tx_position),
+            Err(crate::Error::BlockCorrupted)
+        );
+    }
+
     #[test]
     #[cfg(not(loom))]
     fn test_storage_manipulation() {
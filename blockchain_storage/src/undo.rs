@@ -0,0 +1,120 @@
+//! Iteration over the per-block undo data
+//!
+//! The undo data is kept for the main chain blocks within [`MAX_REORG_DEPTH`](crate::MAX_REORG_DEPTH)
+//! of the tip. It's iterated from the best block towards genesis, stopping at the first main chain
+//! block whose undo data has been pruned or was never stored.
+
+use common::chain::block::Block;
+use common::primitives::{BlockHeight, Id};
+use utxo::BlockUndo;
+
+use crate::{BlockchainStorageRead, UndoRead};
+
+/// Iterator over the undo data of the main chain blocks, from the best block down
+pub struct MainChainUndo<'a, S> {
+    storage: &'a S,
+    height: Option<BlockHeight>,
+}
+
+/// Iterate over the undo data of the main chain blocks, from the best block down
+///
+/// Yields the height and ID of each block along with its undo data.
+pub fn main_chain_undo<S>(storage: &S) -> crate::Result<MainChainUndo<'_, S>>
+where
+    S: BlockchainStorageRead + UndoRead,
+{
+    let height = match storage.get_best_block_id()? {
+        Some(best_block_id) => {
+            storage.get_block_index(&best_block_id)?.map(|index| index.get_block_height())
+        }
+        None => None,
+    };
+    Ok(MainChainUndo { storage, height })
+}
+
+impl<'a, S: BlockchainStorageRead + UndoRead> MainChainUndo<'a, S> {
+    fn next_undo(&mut self) -> crate::Result<Option<(BlockHeight, Id<Block>, BlockUndo)>> {
+        let height = match self.height.take() {
+            Some(height) => height,
+            None => return Ok(None),
+        };
+        let block_id = match self.storage.get_block_id_by_height(&height)? {
+            Some(block_id) => block_id,
+            None => return Ok(None),
+        };
+        let undo = match self.storage.get_undo_data(block_id.clone())? {
+            Some(undo) => undo,
+            None => return Ok(None),
+        };
+        self.height =
+            (height > BlockHeight::zero()).then(|| BlockHeight::new(u64::from(height) - 1));
+        Ok(Some((height, block_id, undo)))
+    }
+}
+
+impl<'a, S: BlockchainStorageRead + UndoRead> Iterator for MainChainUndo<'a, S> {
+    type Item = crate::Result<(BlockHeight, Id<Block>, BlockUndo)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_undo().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::test::create_rand_block_undo;
+    use crate::{BlockchainStorageWrite, Store, UndoWrite, MAX_REORG_DEPTH};
+    use common::chain::block::block_index::BlockIndex;
+    use common::chain::block::ConsensusData;
+    use common::chain::Transaction;
+    use common::primitives::Idable;
+
+    // Build a main chain of `len` blocks with undo data, returning the block IDs
+    fn build_chain(store: &mut Store, len: u64) -> Vec<Id<Block>> {
+        let mut ids: Vec<Id<Block>> = Vec::new();
+        for height in 0..len {
+            let tx = Transaction::new(0, vec![], vec![], height as u32).unwrap();
+            let block = Block::new(vec![tx], ids.last().cloned(), 0, ConsensusData::None).unwrap();
+            let height = BlockHeight::new(height);
+            store.add_block(&block).unwrap();
+            store.set_block_index(&BlockIndex::new(&block, 1, height, 0)).unwrap();
+            store.set_block_id_at_height(&height, &block.get_id()).unwrap();
+            store.set_best_block_id(&block.get_id()).unwrap();
+            store
+                .add_undo_data(block.get_id(), &create_rand_block_undo(3, 3, height))
+                .unwrap();
+            ids.push(block.get_id());
+        }
+        ids
+    }
+
+    #[test]
+    fn iterate_from_tip() {
+        let mut store = Store::new_empty().unwrap();
+        assert_eq!(main_chain_undo(&store).unwrap().count(), 0);
+
+        let ids = build_chain(&mut store, 5);
+        let undos = main_chain_undo(&store).unwrap().collect::<crate::Result<Vec<_>>>().unwrap();
+        let heights = undos.iter().map(|(height, _, _)| u64::from(*height)).collect::<Vec<_>>();
+        assert_eq!(heights, vec![4, 3, 2, 1, 0]);
+        assert_eq!(undos[0].1, ids[4]);
+        assert!(undos.iter().all(|(height, _, undo)| undo.height() == *height));
+
+        // Iteration stops at the first block without undo data
+        store.del_undo_data(ids[2].clone()).unwrap();
+        assert_eq!(main_chain_undo(&store).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn pruned_beyond_reorg_depth() {
+        let depth = i64::from(MAX_REORG_DEPTH) as u64;
+        let mut store = Store::new_empty().unwrap();
+        let ids = build_chain(&mut store, depth + 3);
+
+        assert_eq!(store.get_undo_data(ids[0].clone()), Ok(None));
+        assert_eq!(store.get_undo_data(ids[2].clone()), Ok(None));
+        assert!(store.get_undo_data(ids[3].clone()).unwrap().is_some());
+        assert_eq!(main_chain_undo(&store).unwrap().count() as u64, depth);
+    }
+}
@@ -0,0 +1,128 @@
+//! Offline verification of the stored blockchain
+//!
+//! Walks the main chain from genesis to the best block, checking that each block is stored
+//! intact together with its index. Reading a block checks it against its ID and merkle roots,
+//! see [`Error::BlockCorrupted`](crate::Error::BlockCorrupted). The undo data kept for the blocks
+//! near the tip is checked to belong to the block it's stored for.
+
+use common::chain::block::Block;
+use common::primitives::{BlockHeight, Id};
+
+use crate::{undo::main_chain_undo, BlockchainStorageRead, UndoRead};
+
+/// Problem found with a main chain block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The block is not stored
+    MissingBlock,
+    /// The stored block doesn't match its ID or merkle roots
+    CorruptedBlock,
+    /// The block index is not stored or belongs to another block
+    InvalidBlockIndex,
+    /// The undo data was recorded for another height
+    InvalidUndoData,
+}
+
+/// Result of verifying the stored main chain
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Report {
+    /// Number of main chain blocks checked
+    pub blocks_checked: u64,
+    /// Blocks with problems, with their heights
+    pub problems: Vec<(BlockHeight, Id<Block>, Problem)>,
+    /// The best block is not the last main chain block
+    pub best_block_mismatch: bool,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty() && !self.best_block_mismatch
+    }
+}
+
+/// Verify the main chain blocks of the storage
+pub fn verify_main_chain(
+    storage: &(impl BlockchainStorageRead + UndoRead),
+) -> crate::Result<Report> {
+    let mut report = Report::default();
+    let mut last_block_id = None;
+
+    let mut height = BlockHeight::new(0);
+    while let Some(block_id) = storage.get_block_id_by_height(&height)? {
+        let problem = match storage.get_block(block_id.clone()) {
+            Ok(Some(_)) => None,
+            Ok(None) => Some(Problem::MissingBlock),
+            Err(crate::Error::BlockCorrupted) => Some(Problem::CorruptedBlock),
+            Err(e) => return Err(e),
+        };
+        let problem = problem.or_else(|| match storage.get_block_index(&block_id) {
+            Ok(Some(index)) if index.get_block_id() == &block_id => None,
+            _ => Some(Problem::InvalidBlockIndex),
+        });
+        if let Some(problem) = problem {
+            report.problems.push((height, block_id.clone(), problem));
+        }
+
+        report.blocks_checked += 1;
+        last_block_id = Some(block_id);
+        height = height.next_height();
+    }
+
+    report.best_block_mismatch = storage.get_best_block_id()? != last_block_id;
+
+    for undo in main_chain_undo(storage)? {
+        let (height, block_id, undo) = undo?;
+        if undo.height() != height {
+            report.problems.push((height, block_id, Problem::InvalidUndoData));
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{store::test::corrupt_block, BlockchainStorageWrite, Store};
+    use common::chain::block::block_index::BlockIndex;
+    use common::chain::block::ConsensusData;
+    use common::chain::Transaction;
+    use common::primitives::Idable;
+    use serialization::Encode;
+
+    fn add_main_chain_block(store: &mut Store, block: &Block, height: u64) {
+        let block_index = BlockIndex::new(block, 1, BlockHeight::new(height), 0);
+        store.add_block(block).unwrap();
+        store.set_block_index(&block_index).unwrap();
+        store
+            .set_block_id_at_height(&BlockHeight::new(height), &block.get_id())
+            .unwrap();
+        store.set_best_block_id(&block.get_id()).unwrap();
+    }
+
+    #[test]
+    fn detects_corrupted_block() {
+        let mut store = Store::new_empty().unwrap();
+        let tx0 = Transaction::new(0, vec![], vec![], 0).unwrap();
+        let block0 = Block::new(vec![tx0], None, 12, ConsensusData::None).unwrap();
+        let tx1 = Transaction::new(0, vec![], vec![], 1).unwrap();
+        let block1 = Block::new(vec![tx1], Some(block0.get_id()), 34, ConsensusData::None).unwrap();
+        add_main_chain_block(&mut store, &block0, 0);
+        add_main_chain_block(&mut store, &block1, 1);
+
+        let report = verify_main_chain(&store).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.blocks_checked, 2);
+
+        corrupt_block(&store, &block1.get_id(), block0.encode());
+        let report = verify_main_chain(&store).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.problems,
+            vec![(
+                BlockHeight::new(1),
+                block1.get_id(),
+                Problem::CorruptedBlock
+            )]
+        );
+    }
+}
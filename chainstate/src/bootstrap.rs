@@ -0,0 +1,182 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bootstrap files
+//!
+//! A bootstrap file holds the main chain blocks in order of height as concatenated SCALE encoded
+//! blocks, without any header. The genesis block is left out as every chainstate starts with it.
+//! Importing a bootstrap file processes its blocks as local blocks, so a chainstate can be synced
+//! from a file without a network or the throughput of the block validation measured.
+
+use std::io::{BufRead, Write};
+
+use common::{
+    chain::block::Block,
+    primitives::{BlockHeight, Idable},
+};
+use serialization::{Decode, Encode};
+
+use crate::{chainstate_interface::ChainstateInterface, BlockError, ChainstateError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum BootstrapError {
+    #[error("Bootstrap file access failed: `{0}`")]
+    Io(#[from] std::io::Error),
+    #[error("Block {0} of the bootstrap file cannot be decoded: `{1}`")]
+    Decode(u64, serialization::Error),
+    #[error("Block {0} of the bootstrap file was rejected: `{1}`")]
+    Rejected(u64, ChainstateError),
+    #[error("Chainstate error: `{0}`")]
+    Chainstate(#[from] ChainstateError),
+}
+
+/// Number of blocks handled by an import or export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BootstrapStats {
+    /// Blocks written, or processed by the chainstate
+    pub blocks: u64,
+    /// Blocks of the file that the chainstate already had
+    pub skipped: u64,
+}
+
+/// Write the main chain blocks of the chainstate to `writer`
+pub fn export_bootstrap_file(
+    chainstate: &dyn ChainstateInterface,
+    writer: &mut impl Write,
+) -> Result<BootstrapStats, BootstrapError> {
+    let mut stats = BootstrapStats::default();
+    let mut height = BlockHeight::new(1);
+    while let Some(block_id) = chainstate.get_block_id_from_height(&height)? {
+        let block = chainstate.get_block(block_id)?.expect("Main chain block to be stored");
+        writer.write_all(&block.encode())?;
+        stats.blocks += 1;
+        height = height.next_height();
+    }
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Process the blocks read from `reader` until the end of the file
+///
+/// The blocks the chainstate already has are skipped, so an interrupted import can be resumed
+/// with the same file.
+pub fn import_bootstrap_file(
+    chainstate: &mut dyn ChainstateInterface,
+    reader: &mut impl BufRead,
+) -> Result<BootstrapStats, BootstrapError> {
+    let mut stats = BootstrapStats::default();
+    let mut position = 0;
+    while !reader.fill_buf()?.is_empty() {
+        let block = Block::decode(&mut serialization::IoReader(&mut *reader))
+            .map_err(|err| BootstrapError::Decode(position, err))?;
+        let block_id = block.get_id();
+        match chainstate.process_block(block, crate::BlockSource::Local) {
+            Ok(()) => stats.blocks += 1,
+            Err(ChainstateError::ProcessBlockError(BlockError::BlockAlreadyExists(id)))
+                if id == block_id =>
+            {
+                stats.skipped += 1
+            }
+            Err(err) => return Err(BootstrapError::Rejected(position, err)),
+        }
+        position += 1;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::chain::Destination;
+    use std::sync::Arc;
+
+    fn make_chainstate() -> Box<dyn ChainstateInterface> {
+        let chain_config = Arc::new(common::chain::config::create_regtest());
+        let storage = blockchain_storage::Store::new_empty().unwrap();
+        crate::make_chainstate(chain_config, storage, None).unwrap()
+    }
+
+    #[test]
+    fn export_and_import() {
+        let mut source = make_chainstate();
+        source.generate_blocks(5, Destination::AnyoneCanSpend).unwrap();
+        let mut file = Vec::new();
+        let stats = export_bootstrap_file(source.as_ref(), &mut file).unwrap();
+        assert_eq!(stats.blocks, 5);
+
+        let mut target = make_chainstate();
+        let stats = import_bootstrap_file(target.as_mut(), &mut file.as_slice()).unwrap();
+        assert_eq!(
+            stats,
+            BootstrapStats {
+                blocks: 5,
+                skipped: 0
+            }
+        );
+        assert_eq!(
+            target.get_best_block_id().unwrap(),
+            source.get_best_block_id().unwrap()
+        );
+
+        // importing again finds all the blocks in place
+        let stats = import_bootstrap_file(target.as_mut(), &mut file.as_slice()).unwrap();
+        assert_eq!(
+            stats,
+            BootstrapStats {
+                blocks: 0,
+                skipped: 5
+            }
+        );
+    }
+
+    #[test]
+    fn empty_chain() {
+        let source = make_chainstate();
+        let mut file = Vec::new();
+        let stats = export_bootstrap_file(source.as_ref(), &mut file).unwrap();
+        assert_eq!(stats.blocks, 0);
+        assert!(file.is_empty());
+
+        let mut target = make_chainstate();
+        let stats = import_bootstrap_file(target.as_mut(), &mut file.as_slice()).unwrap();
+        assert_eq!(stats, BootstrapStats::default());
+    }
+
+    #[test]
+    fn truncated_file() {
+        let mut source = make_chainstate();
+        source.generate_blocks(2, Destination::AnyoneCanSpend).unwrap();
+        let mut file = Vec::new();
+        export_bootstrap_file(source.as_ref(), &mut file).unwrap();
+        file.truncate(file.len() - 1);
+
+        let mut target = make_chainstate();
+        let result = import_bootstrap_file(target.as_mut(), &mut file.as_slice());
+        assert!(matches!(result, Err(BootstrapError::Decode(1, _))));
+        assert_eq!(target.get_best_block_height().unwrap(), BlockHeight::new(1));
+    }
+
+    #[test]
+    fn unconnected_blocks() {
+        let mut source = make_chainstate();
+        source.generate_blocks(2, Destination::AnyoneCanSpend).unwrap();
+        let block_id = source.get_block_id_from_height(&BlockHeight::new(2)).unwrap().unwrap();
+        let file = source.get_block(block_id).unwrap().unwrap().encode();
+
+        let mut target = make_chainstate();
+        let result = import_bootstrap_file(target.as_mut(), &mut file.as_slice());
+        assert!(matches!(result, Err(BootstrapError::Rejected(0, _))));
+    }
+}
@@ -1,20 +1,29 @@
 #[cfg(any(test, feature = "mock"))]
 pub mod mock;
 
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use common::{
-    chain::block::{Block, BlockHeader},
+    chain::{
+        block::{Block, BlockHeader, BlockIndex},
+        config::ChainConfig,
+        Destination, OutPoint, Transaction, TxOutput,
+    },
     primitives::{BlockHeight, Id},
 };
 
-use crate::{detail::BlockSource, ChainstateError, ChainstateEvent};
+use crate::{
+    detail::{BlockSource, UtxoSetInfo},
+    ChainstateError, ChainstateEvent,
+};
 
 pub trait ChainstateInterface: Send {
     fn subscribe_to_events(&mut self, handler: Arc<dyn Fn(ChainstateEvent) + Send + Sync>);
     fn process_block(&mut self, block: Block, source: BlockSource) -> Result<(), ChainstateError>;
     fn preliminary_block_check(&self, block: Block) -> Result<(), ChainstateError>;
     fn get_best_block_id(&self) -> Result<Id<Block>, ChainstateError>;
+    fn get_best_block_height(&self) -> Result<BlockHeight, ChainstateError>;
+    fn is_initial_block_download(&self) -> bool;
     fn is_block_in_main_chain(&self, block_id: &Id<Block>) -> Result<bool, ChainstateError>;
     fn get_block_height_in_main_chain(
         &self,
@@ -24,11 +33,32 @@ pub trait ChainstateInterface: Send {
         &self,
         height: &BlockHeight,
     ) -> Result<Option<Id<Block>>, ChainstateError>;
+    fn get_block_index(&self, block_id: &Id<Block>) -> Result<Option<BlockIndex>, ChainstateError>;
     fn get_block(&self, block_id: Id<Block>) -> Result<Option<Block>, ChainstateError>;
+    fn get_mainchain_tx(
+        &self,
+        tx_id: &Id<Transaction>,
+    ) -> Result<Option<(Transaction, Id<Block>)>, ChainstateError>;
+    fn get_utxo(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<(TxOutput, Id<Block>)>, ChainstateError>;
+    fn get_utxo_set_info(&self) -> Result<UtxoSetInfo, ChainstateError>;
     fn get_locator(&self) -> Result<Vec<BlockHeader>, ChainstateError>;
     fn get_headers(&self, locator: Vec<BlockHeader>) -> Result<Vec<BlockHeader>, ChainstateError>;
     fn filter_already_existing_blocks(
         &self,
         headers: Vec<BlockHeader>,
     ) -> Result<Vec<BlockHeader>, ChainstateError>;
+    fn get_difficulty(&self) -> Result<f64, ChainstateError>;
+    fn get_block_template(&self, reward_destination: Destination)
+        -> Result<Block, ChainstateError>;
+    fn generate_blocks(
+        &mut self,
+        count: usize,
+        reward_destination: Destination,
+    ) -> Result<Vec<Id<Block>>, ChainstateError>;
+    fn get_chain_config(&self) -> Arc<ChainConfig>;
+    fn backup_storage(&self, path: &Path) -> Result<(), ChainstateError>;
+    fn get_storage_statistics(&self) -> blockchain_storage::Statistics;
 }
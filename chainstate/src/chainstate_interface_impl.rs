@@ -1,25 +1,32 @@
+use std::{path::Path, sync::Arc};
+
+use blockchain_storage::BlockchainStorage;
 use common::{
-    chain::block::{Block, BlockHeader},
+    chain::{
+        block::{Block, BlockHeader, BlockIndex},
+        config::ChainConfig,
+        Destination, OutPoint, Transaction, TxOutput,
+    },
     primitives::{BlockHeight, Id},
 };
 use utils::eventhandler::EventHandler;
 
 use crate::{
-    detail::{self, BlockSource},
+    detail::{self, BlockSource, UtxoSetInfo},
     ChainstateError, ChainstateEvent, ChainstateInterface,
 };
 
-pub struct ChainstateInterfaceImpl {
-    chainstate: detail::Chainstate,
+pub struct ChainstateInterfaceImpl<S = blockchain_storage::Store> {
+    chainstate: detail::Chainstate<S>,
 }
 
-impl ChainstateInterfaceImpl {
-    pub fn new(chainstate: detail::Chainstate) -> Self {
+impl<S> ChainstateInterfaceImpl<S> {
+    pub fn new(chainstate: detail::Chainstate<S>) -> Self {
         Self { chainstate }
     }
 }
 
-impl ChainstateInterface for ChainstateInterfaceImpl {
+impl<S: BlockchainStorage + Send> ChainstateInterface for ChainstateInterfaceImpl<S> {
     fn subscribe_to_events(&mut self, handler: EventHandler<ChainstateEvent>) {
         self.chainstate.subscribe_to_events(handler)
     }
@@ -45,6 +52,17 @@ impl ChainstateInterface for ChainstateInterfaceImpl {
             .expect("There always must be a best block"))
     }
 
+    fn get_best_block_height(&self) -> Result<BlockHeight, ChainstateError> {
+        let best_block_id = self.get_best_block_id()?;
+        Ok(self
+            .get_block_height_in_main_chain(&best_block_id)?
+            .expect("Best block must be in the main chain"))
+    }
+
+    fn is_initial_block_download(&self) -> bool {
+        self.chainstate.is_initial_block_download()
+    }
+
     fn is_block_in_main_chain(&self, block_id: &Id<Block>) -> Result<bool, ChainstateError> {
         Ok(self
             .chainstate
@@ -71,12 +89,42 @@ impl ChainstateInterface for ChainstateInterfaceImpl {
             .map_err(ChainstateError::FailedToReadProperty)
     }
 
+    fn get_block_index(&self, block_id: &Id<Block>) -> Result<Option<BlockIndex>, ChainstateError> {
+        self.chainstate
+            .get_block_index(block_id)
+            .map_err(ChainstateError::FailedToReadProperty)
+    }
+
     fn get_block(&self, block_id: Id<Block>) -> Result<Option<Block>, ChainstateError> {
         self.chainstate
             .get_block(block_id)
             .map_err(ChainstateError::FailedToReadProperty)
     }
 
+    fn get_mainchain_tx(
+        &self,
+        tx_id: &Id<Transaction>,
+    ) -> Result<Option<(Transaction, Id<Block>)>, ChainstateError> {
+        self.chainstate
+            .get_mainchain_tx(tx_id)
+            .map_err(ChainstateError::FailedToReadProperty)
+    }
+
+    fn get_utxo(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<(TxOutput, Id<Block>)>, ChainstateError> {
+        self.chainstate
+            .get_utxo(outpoint)
+            .map_err(ChainstateError::FailedToReadProperty)
+    }
+
+    fn get_utxo_set_info(&self) -> Result<UtxoSetInfo, ChainstateError> {
+        self.chainstate
+            .get_utxo_set_info()
+            .map_err(ChainstateError::FailedToReadProperty)
+    }
+
     fn get_locator(&self) -> Result<Vec<BlockHeader>, ChainstateError> {
         self.chainstate.get_locator().map_err(ChainstateError::FailedToReadProperty)
     }
@@ -95,4 +143,45 @@ impl ChainstateInterface for ChainstateInterfaceImpl {
             .filter_already_existing_blocks(headers)
             .map_err(ChainstateError::FailedToReadProperty)
     }
+
+    fn get_difficulty(&self) -> Result<f64, ChainstateError> {
+        self.chainstate.get_difficulty().map_err(ChainstateError::FailedToReadProperty)
+    }
+
+    fn get_block_template(
+        &self,
+        reward_destination: Destination,
+    ) -> Result<Block, ChainstateError> {
+        self.chainstate
+            .get_block_template(reward_destination)
+            .map_err(ChainstateError::FailedToReadProperty)
+    }
+
+    fn generate_blocks(
+        &mut self,
+        count: usize,
+        reward_destination: Destination,
+    ) -> Result<Vec<Id<Block>>, ChainstateError> {
+        (0..count)
+            .map(|_| {
+                self.chainstate
+                    .generate_block(reward_destination.clone())
+                    .map_err(ChainstateError::ProcessBlockError)
+            })
+            .collect()
+    }
+
+    fn get_chain_config(&self) -> Arc<ChainConfig> {
+        self.chainstate.chain_config()
+    }
+
+    fn backup_storage(&self, path: &Path) -> Result<(), ChainstateError> {
+        self.chainstate
+            .backup_storage(path)
+            .map_err(|e| ChainstateError::StorageBackupFailed(e.to_string()))
+    }
+
+    fn get_storage_statistics(&self) -> blockchain_storage::Statistics {
+        self.chainstate.storage_statistics()
+    }
 }
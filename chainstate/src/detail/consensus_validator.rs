@@ -31,10 +31,8 @@ pub(crate) fn validate_consensus(
     let block_height = if header.is_genesis(chain_config) {
         BlockHeight::from(0)
     } else {
-        let prev_block_id = header
-            .get_prev_block_id()
-            .clone()
-            .expect("Block not genesis so must have a prev_block_id");
+        let prev_block_id =
+            header.get_prev_block_id().clone().ok_or(BlockError::InvalidBlockNoPrevBlock)?;
         block_index_handle
             .get_block_index(&prev_block_id)?
             .ok_or(BlockError::IllegalOrphan)?
@@ -26,7 +26,8 @@ use thiserror::Error;
 
 use super::orphan_blocks::OrphanAddError;
 
-#[derive(Error, Debug, PartialEq, Eq, Clone)]
+#[derive(Error, Debug, PartialEq, Eq, Clone, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
 pub enum BlockError {
     #[error("Illegal orphan that was submitted by non-local source, e.g., a peer")]
     IllegalOrphan,
@@ -69,6 +70,10 @@ pub enum BlockError {
     InvalidBlockSource,
     #[error("Duplicate transaction found in block")]
     DuplicatedTransactionInBlock,
+    #[error("Transaction {0:?} is already in the main chain with unspent outputs")]
+    DuplicateTransaction(Id<Transaction>),
+    #[error("Transaction {0:?} has no inputs, only the genesis and block rewards create coins")]
+    TransactionWithoutInputs(Id<Transaction>),
     #[error("Outputs already in the inputs cache")]
     OutputAlreadyPresentInInputsCache,
     #[error("Output is not found in the cache or database")]
@@ -90,6 +95,10 @@ pub enum BlockError {
     BlockHeightArithmeticError,
     #[error("Block reward spent immaturely")]
     ImmatureBlockRewardSpend,
+    #[error("Transaction `{0:?}` spends an output whose timelock has not expired")]
+    TimeLockViolation(Id<Transaction>),
+    #[error("Transaction `{0:?}` has timelocked outputs before timelocks are activated")]
+    TimeLockNotActivated(Id<Transaction>),
     #[error("Invalid output count")]
     InvalidOutputCount,
     #[error("Input was cached, but could not be found")]
@@ -98,6 +107,8 @@ pub enum BlockError {
     PreviouslyCachedInputWasErased,
     #[error("Signature verification failed in transaction with id: {0:?}")]
     SignatureVerificationFailed(Id<Transaction>),
+    #[error("Transaction {0:?} uses a kind of key that is not activated at this height")]
+    KeyKindNotActivated(Id<Transaction>),
     #[error("Transaction index found but transaction not found")]
     InvariantErrorTransactionCouldNotBeLoaded,
     #[error("Input addition error")]
@@ -106,6 +117,10 @@ pub enum BlockError {
     OutputAdditionError,
     #[error("Attempt to print money (total inputs: `{0:?}` vs total outputs `{1:?}`")]
     AttemptToPrintMoney(Amount, Amount),
+    #[error("Outputs of transaction {0:?} exceed the total supply")]
+    MoneyOutOfRange(Id<Transaction>),
+    #[error("Block reward `{0:?}` exceeds the block subsidy plus fees `{1:?}`")]
+    BlockRewardTooHigh(Amount, Amount),
     #[error("Duplicate input in transaction")]
     DuplicateInputInTransaction(Id<Transaction>),
     #[error("Duplicate input in block")]
@@ -128,6 +143,8 @@ pub enum BlockError {
     UnsupportedConsensusType,
     #[error("Block {0:?} already exists")]
     BlockAlreadyExists(Id<Block>),
+    #[error("Blockchain storage is corrupted, restore it from a backup or reindex the blockchain by syncing it into an empty storage")]
+    StorageCorrupted,
     // To be expanded
 }
 
@@ -135,7 +152,10 @@ impl From<blockchain_storage::Error> for BlockError {
     fn from(err: blockchain_storage::Error) -> Self {
         // On storage level called err.recoverable(), if an error is unrecoverable then it calls panic!
         // We don't need to cause panic here
-        BlockError::StorageError(err)
+        match err {
+            blockchain_storage::Error::BlockCorrupted => BlockError::StorageCorrupted,
+            err => BlockError::StorageError(err),
+        }
     }
 }
 
@@ -17,21 +17,29 @@
 
 use crate::detail::orphan_blocks::OrphanBlocksPool;
 use crate::ChainstateEvent;
+use blockchain_storage::BlockchainStorage;
 use blockchain_storage::BlockchainStorageRead;
 use blockchain_storage::BlockchainStorageWrite;
 use blockchain_storage::TransactionRw;
 use blockchain_storage::Transactional;
 use common::chain::block::block_index::BlockIndex;
+use common::chain::block::consensus_data::PoWData;
 use common::chain::block::{
-    calculate_tx_merkle_root, calculate_witness_merkle_root, Block, BlockHeader,
+    calculate_tx_merkle_root, calculate_witness_merkle_root, Block, BlockHeader, ConsensusData,
 };
 use common::chain::calculate_tx_index_from_block;
 use common::chain::config::ChainConfig;
 use common::chain::config::MAX_BLOCK_WEIGHT;
-use common::chain::{OutPointSourceId, Transaction};
-use common::primitives::{time, BlockDistance, BlockHeight, Id, Idable};
+use common::chain::{
+    Destination, OutPoint, OutPointSourceId, OutputSpentState, RequiredConsensus,
+    SpendablePosition, Transaction, TxOutput,
+};
+use common::primitives::id::{hash_encoded_to, DefaultHashAlgoStream};
+use common::primitives::{time, Amount, BlockDistance, BlockHeight, Id, Idable, H256};
+use crypto::hash::StreamHasher;
 use itertools::Itertools;
 use std::collections::BTreeSet;
+use std::path::Path;
 use std::sync::Arc;
 use utils::eventhandler::{EventHandler, EventsController};
 mod consensus_validator;
@@ -42,12 +50,18 @@ mod error;
 pub use error::*;
 mod pow;
 
-type TxRw<'a> = <blockchain_storage::Store as Transactional<'a>>::TransactionRw;
-type TxRo<'a> = <blockchain_storage::Store as Transactional<'a>>::TransactionRo;
+type TxRw<'a, S> = <S as Transactional<'a>>::TransactionRw;
+type TxRo<'a, S> = <S as Transactional<'a>>::TransactionRo;
 type ChainstateEventHandler = EventHandler<ChainstateEvent>;
 
 const HEADER_LIMIT: BlockDistance = BlockDistance::new(2000);
 
+/// The node is in initial block download while its best block is older than this, in seconds
+const MAX_TIP_AGE: i64 = 24 * 60 * 60;
+
+/// Number of previous blocks whose timestamps make up the median time past
+const MEDIAN_TIME_SPAN: usize = 11;
+
 mod spend_cache;
 use spend_cache::CachedInputs;
 
@@ -56,12 +70,33 @@ use consensus_validator::BlockIndexHandle;
 pub type OrphanErrorHandler = dyn Fn(&BlockError) + Send + Sync;
 
 // TODO: ISSUE #129 - https://github.com/mintlayer/mintlayer-core/issues/129
-pub struct Chainstate {
+/// Chainstate over a blockchain storage backend
+///
+/// Any [`BlockchainStorage`] implementation can be used, the node uses
+/// [`blockchain_storage::Store`] which is either kept in memory or persisted to a file.
+pub struct Chainstate<S = blockchain_storage::Store> {
     chain_config: Arc<ChainConfig>,
-    blockchain_storage: blockchain_storage::Store,
+    blockchain_storage: S,
     orphan_blocks: OrphanBlocksPool,
     custom_orphan_error_hook: Option<Arc<OrphanErrorHandler>>,
     events_controller: EventsController<ChainstateEvent>,
+    initial_block_download: bool,
+}
+
+/// Statistics of the set of unspent main chain outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoSetInfo {
+    /// Best block the statistics were computed at
+    pub best_block_id: Id<Block>,
+    pub height: BlockHeight,
+    /// Number of unspent outputs
+    pub utxo_count: u64,
+    /// Sum of the values of the unspent outputs
+    pub total_amount: Amount,
+    /// Size of the encoded outpoints and outputs in bytes
+    pub serialized_size: u64,
+    /// Hash of the encoded outpoints and outputs, in main chain order
+    pub utxo_set_hash: H256,
 }
 
 #[derive(Copy, Clone, Eq, Debug, PartialEq)]
@@ -70,12 +105,12 @@ pub enum BlockSource {
     Local,
 }
 
-impl Chainstate {
+impl<S: BlockchainStorage> Chainstate<S> {
     pub fn wait_for_all_events(&self) {
         self.events_controller.wait_for_all_events();
     }
 
-    fn make_db_tx(&mut self) -> ChainstateRef {
+    fn make_db_tx(&mut self) -> ChainstateRef<'_, TxRw<'_, S>> {
         let db_tx = self.blockchain_storage.transaction_rw();
         ChainstateRef {
             chain_config: &self.chain_config,
@@ -84,7 +119,7 @@ impl Chainstate {
         }
     }
 
-    fn make_ro_db_tx(&self) -> ChainstateRefRo {
+    fn make_ro_db_tx(&self) -> ChainstateRefRo<'_, TxRo<'_, S>> {
         let db_tx = self.blockchain_storage.transaction_ro();
         ChainstateRefRo {
             chain_config: &self.chain_config,
@@ -99,7 +134,7 @@ impl Chainstate {
 
     pub fn new(
         chain_config: Arc<ChainConfig>,
-        blockchain_storage: blockchain_storage::Store,
+        blockchain_storage: S,
         custom_orphan_error_hook: Option<Arc<OrphanErrorHandler>>,
     ) -> Result<Self, crate::ChainstateError> {
         use crate::ChainstateError;
@@ -121,12 +156,13 @@ impl Chainstate {
                 ))
             })?;
         }
+        cons.latch_initial_block_download_done(time::get());
         Ok(cons)
     }
 
     fn new_no_genesis(
         chain_config: Arc<ChainConfig>,
-        blockchain_storage: blockchain_storage::Store,
+        blockchain_storage: S,
         custom_orphan_error_hook: Option<Arc<OrphanErrorHandler>>,
     ) -> Result<Self, crate::ChainstateError> {
         let cons = Self {
@@ -135,6 +171,7 @@ impl Chainstate {
             orphan_blocks: OrphanBlocksPool::new_default(),
             custom_orphan_error_hook,
             events_controller: EventsController::new(),
+            initial_block_download: true,
         };
         Ok(cons)
     }
@@ -144,6 +181,7 @@ impl Chainstate {
             Some(ref new_block_index) => {
                 let new_height = new_block_index.get_block_height();
                 let new_id = new_block_index.get_block_id().clone();
+                logging::debug!(block_id = new_id, height = new_height; "new tip");
                 self.events_controller.broadcast(ChainstateEvent::NewTip(new_id, new_height))
             }
             None => (),
@@ -168,6 +206,11 @@ impl Chainstate {
     }
 
     /// returns the block index of the new tip
+    ///
+    /// The block, its index and the updated transaction indices are written in one storage
+    /// transaction, as is any reorganization the block causes, so a block that fails to connect
+    /// or a crash leaves no partial writes behind. The orphans connected afterwards are each
+    /// written in a transaction of their own.
     pub fn process_block(
         &mut self,
         block: Block,
@@ -192,6 +235,9 @@ impl Chainstate {
             None => result,
         };
 
+        if result.is_some() {
+            self.latch_initial_block_download_done(time::get());
+        }
         self.broadcast_new_tip_event(&result);
 
         Ok(result)
@@ -202,6 +248,50 @@ impl Chainstate {
         Ok(())
     }
 
+    /// Whether the node is still catching up with the rest of the network
+    ///
+    /// The node is in initial block download until its best block is less than a day old and
+    /// at or above the last checkpoint. Leaving initial block download is a one-way latch: the
+    /// node doesn't return to it, even after a long stall, so that the best block getting old
+    /// while no new blocks are found doesn't switch the behaviour of the node back and forth.
+    pub fn is_initial_block_download(&self) -> bool {
+        self.initial_block_download
+    }
+
+    /// Leave initial block download for good once the best block has caught up
+    fn latch_initial_block_download_done(&mut self, now: i64) {
+        if !self.initial_block_download {
+            return;
+        }
+        match self.is_best_block_behind(now) {
+            Ok(true) => {}
+            Ok(false) => {
+                logging::log::info!("initial block download done");
+                self.initial_block_download = false;
+            }
+            Err(e) => logging::log::error!("failed to check the best block age: {}", e),
+        }
+    }
+
+    /// Whether the best block is older than [`MAX_TIP_AGE`] or below the last checkpoint
+    fn is_best_block_behind(&self, now: i64) -> Result<bool, BlockError> {
+        let best_block_id = match self.get_best_block_id()? {
+            Some(best_block_id) => best_block_id,
+            None => return Ok(true),
+        };
+        let best_block_index = self.get_block_index(&best_block_id)?.ok_or(BlockError::NotFound)?;
+
+        let below_checkpoint = self
+            .chain_config
+            .height_checkpoints()
+            .keys()
+            .next_back()
+            .map_or(false, |height| {
+                best_block_index.get_block_height() < *height
+            });
+        Ok(below_checkpoint || i64::from(best_block_index.get_block_time()) < now - MAX_TIP_AGE)
+    }
+
     pub fn get_best_block_id(&self) -> Result<Option<Id<Block>>, BlockError> {
         let chainstate_ref = self.make_ro_db_tx();
         // Reasonable reduce amount of calls to DB
@@ -242,6 +332,118 @@ impl Chainstate {
         Ok(block)
     }
 
+    pub fn chain_config(&self) -> Arc<ChainConfig> {
+        Arc::clone(&self.chain_config)
+    }
+
+    /// Write a consistent copy of the blockchain storage to `path`
+    pub fn backup_storage(&self, path: &Path) -> std::io::Result<()> {
+        self.blockchain_storage.backup_to(path)
+    }
+
+    /// Get the number of entries and approximate size of the storage columns
+    pub fn storage_statistics(&self) -> blockchain_storage::Statistics {
+        self.blockchain_storage.statistics()
+    }
+
+    /// Get a main chain transaction together with the ID of the block containing it
+    pub fn get_mainchain_tx(
+        &self,
+        tx_id: &Id<Transaction>,
+    ) -> Result<Option<(Transaction, Id<Block>)>, BlockError> {
+        let chainstate_ref = self.make_ro_db_tx();
+        let tx_index = chainstate_ref
+            .db_tx
+            .get_mainchain_tx_index(&OutPointSourceId::from(tx_id.clone()))?;
+        let position = match tx_index.as_ref().map(|index| index.get_position()) {
+            Some(SpendablePosition::Transaction(position)) => position,
+            Some(SpendablePosition::BlockReward(_)) | None => return Ok(None),
+        };
+        let tx = chainstate_ref.db_tx.get_mainchain_tx_by_position(position)?;
+        Ok(tx.map(|tx| (tx, position.get_block_id().clone())))
+    }
+
+    /// Get an unspent main chain output together with the ID of the block containing it
+    pub fn get_utxo(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<(TxOutput, Id<Block>)>, BlockError> {
+        let chainstate_ref = self.make_ro_db_tx();
+        let tx_index = match chainstate_ref.db_tx.get_mainchain_tx_index(&outpoint.get_tx_id())? {
+            Some(tx_index) => tx_index,
+            None => return Ok(None),
+        };
+        if tx_index.get_spent_state(outpoint.get_output_index()) != Ok(OutputSpentState::Unspent) {
+            return Ok(None);
+        }
+
+        let (outputs, block_id) = chainstate_ref.get_outputs(tx_index.get_position())?;
+        Ok(outputs
+            .into_iter()
+            .nth(outpoint.get_output_index() as usize)
+            .map(|output| (output, block_id)))
+    }
+
+    /// Statistics of the unspent outputs of the main chain
+    ///
+    /// All main chain blocks are read, so this is slow for long chains.
+    pub fn get_utxo_set_info(&self) -> Result<UtxoSetInfo, BlockError> {
+        let chainstate_ref = self.make_ro_db_tx();
+        let best_block_id =
+            chainstate_ref.db_tx.get_best_block_id()?.ok_or(BlockError::NotFound)?;
+        let height = chainstate_ref
+            .db_tx
+            .get_block_index(&best_block_id)?
+            .ok_or(BlockError::NotFound)?
+            .get_block_height();
+
+        let mut info = UtxoSetInfo {
+            best_block_id,
+            height,
+            utxo_count: 0,
+            total_amount: Amount::from_atoms(0),
+            serialized_size: 0,
+            utxo_set_hash: H256::zero(),
+        };
+        let mut hasher = DefaultHashAlgoStream::new();
+
+        for block_height in 0..=u64::from(height) {
+            let block_id = chainstate_ref
+                .db_tx
+                .get_block_id_by_height(&BlockHeight::new(block_height))?
+                .ok_or(BlockError::NotFound)?;
+            let block =
+                chainstate_ref.db_tx.get_block(block_id.clone())?.ok_or(BlockError::NotFound)?;
+            let sources = std::iter::once(OutPointSourceId::from(block_id))
+                .chain(block.transactions().iter().map(|tx| OutPointSourceId::from(tx.get_id())));
+
+            for source in sources {
+                let tx_index = match chainstate_ref.db_tx.get_mainchain_tx_index(&source)? {
+                    Some(tx_index) => tx_index,
+                    None => continue,
+                };
+                let (outputs, _) = chainstate_ref.get_outputs(tx_index.get_position())?;
+
+                for (index, output) in outputs.iter().enumerate() {
+                    let index = index as u32;
+                    if tx_index.get_spent_state(index) != Ok(OutputSpentState::Unspent) {
+                        continue;
+                    }
+
+                    let utxo = (OutPoint::new(source.clone(), index), output);
+                    hash_encoded_to(&utxo, &mut hasher);
+                    info.utxo_count += 1;
+                    info.serialized_size += utxo.encoded_size() as u64;
+                    info.total_amount = (info.total_amount + output.get_value())
+                        .ok_or(BlockError::OutputAdditionError)?;
+                }
+            }
+        }
+
+        info.utxo_set_hash = hasher.finalize().into();
+        Ok(info)
+    }
+
     pub fn get_block_index(&self, id: &Id<Block>) -> Result<Option<BlockIndex>, BlockError> {
         self.make_ro_db_tx().db_tx.get_block_index(id).map_err(BlockError::from)
     }
@@ -266,12 +468,8 @@ impl Chainstate {
         let height = self.get_block_height_in_main_chain(&id)?.ok_or(BlockError::NotFound)?;
 
         let headers = itertools::iterate(0, |&i| if i == 0 { 1 } else { i * 2 })
-            .take_while(|i| (height - BlockDistance::new(*i)).is_some())
-            .map(|i| {
-                self.get_header_from_height(
-                    &(height - BlockDistance::new(i)).expect("distance to be valid"),
-                )
-            });
+            .map_while(|i| height.checked_sub_distance(BlockDistance::new(i)).ok())
+            .map(|height| self.get_header_from_height(&height));
 
         itertools::process_results(headers, |iter| iter.flatten().collect::<Vec<_>>())
     }
@@ -313,6 +511,87 @@ impl Chainstate {
         itertools::process_results(headers, |iter| iter.flatten().collect::<Vec<_>>())
     }
 
+    /// Difficulty of the best block, see [`pow::work::difficulty()`]
+    ///
+    /// Blocks without proof of work have the lowest difficulty of 1.
+    pub fn get_difficulty(&self) -> Result<f64, BlockError> {
+        let id = self.get_best_block_id()?.ok_or(BlockError::NotFound)?;
+        let block_index = self.get_block_index(&id)?.ok_or(BlockError::NotFound)?;
+
+        match block_index.get_block_header().consensus_data() {
+            ConsensusData::PoW(data) => pow::work::difficulty(&self.chain_config, data.bits()),
+            ConsensusData::None => Ok(1.0),
+        }
+    }
+
+    /// Block on top of the best block, to be completed by a miner
+    ///
+    /// The block reward goes to `reward_destination`. It consists of the block subsidy and the
+    /// fees of the included transactions, which for now is none of them since there is no mempool
+    /// to select them from. Proof of work blocks still need a nonce that satisfies the target.
+    pub fn get_block_template(&self, reward_destination: Destination) -> Result<Block, BlockError> {
+        let chainstate_ref = self.make_ro_db_tx();
+        let best_block_id =
+            chainstate_ref.db_tx.get_best_block_id()?.ok_or(BlockError::NotFound)?;
+        let best_block_index = chainstate_ref
+            .db_tx
+            .get_block_index(&best_block_id)?
+            .ok_or(BlockError::NotFound)?;
+        let height = best_block_index
+            .get_block_height()
+            .checked_add(1)
+            .expect("max block height reached");
+
+        // The block time must not be lower than the one of the previous block
+        let block_time = std::cmp::max(time::get() as u32, best_block_index.get_block_time());
+        let mut block = Block::new(vec![], Some(best_block_id), block_time, ConsensusData::None)
+            .expect("A block without transactions has no merkle tree to build");
+
+        match self.chain_config.net_upgrade().consensus_status(height) {
+            RequiredConsensus::PoW(pow_status) => {
+                let bits = pow::work::calculate_work_required(
+                    &self.chain_config,
+                    block.header(),
+                    &pow_status,
+                    &chainstate_ref,
+                )?;
+                let reward =
+                    TxOutput::new(self.chain_config.block_subsidy(height), reward_destination);
+                block.update_consensus_data(ConsensusData::PoW(PoWData::new(
+                    bits,
+                    0,
+                    vec![reward],
+                )));
+            }
+            RequiredConsensus::IgnoreConsensus => {}
+            RequiredConsensus::PoS | RequiredConsensus::DSA => {
+                return Err(BlockError::UnsupportedConsensusType)
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Mine a block template and add it to the chain, returns the ID of the block
+    ///
+    /// The nonce is searched synchronously, which is only practical at the lowest difficulty,
+    /// such as on regtest.
+    pub fn generate_block(
+        &mut self,
+        reward_destination: Destination,
+    ) -> Result<Id<Block>, BlockError> {
+        let mut block = self.get_block_template(reward_destination)?;
+        if let ConsensusData::PoW(data) = block.consensus_data() {
+            let (bits, reward) = (data.bits(), data.outputs().to_vec());
+            if !pow::work::mine(&mut block, u128::MAX, bits, reward)? {
+                return Err(BlockError::InvalidPoW);
+            }
+        }
+        let block_id = block.get_id();
+        self.process_block(block, BlockSource::Local)?;
+        Ok(block_id)
+    }
+
     pub fn filter_already_existing_blocks(
         &self,
         headers: Vec<BlockHeader>,
@@ -337,23 +616,23 @@ impl Chainstate {
     }
 }
 
-pub(crate) struct ChainstateRef<'a> {
+pub(crate) struct ChainstateRef<'a, Tx> {
     chain_config: &'a ChainConfig,
-    // TODO: make this generic over Rw and Ro
-    db_tx: TxRw<'a>,
+    db_tx: Tx,
     orphan_blocks: &'a mut OrphanBlocksPool,
 }
 
-struct ChainstateRefRo<'a> {
+struct ChainstateRefRo<'a, Tx> {
     #[allow(dead_code)]
     chain_config: &'a ChainConfig,
-    // TODO: make this generic over Rw and Ro
-    db_tx: TxRo<'a>,
+    db_tx: Tx,
     #[allow(dead_code)]
     orphan_blocks: &'a OrphanBlocksPool,
 }
 
-impl<'a> BlockIndexHandle for ChainstateRef<'a> {
+impl<'a, Tx: BlockchainStorageWrite + TransactionRw<Error = blockchain_storage::Error>>
+    BlockIndexHandle for ChainstateRef<'a, Tx>
+{
     fn get_block_index(
         &self,
         block_index: &Id<Block>,
@@ -369,7 +648,67 @@ impl<'a> BlockIndexHandle for ChainstateRef<'a> {
     }
 }
 
-impl<'a> ChainstateRef<'a> {
+impl<'a, Tx: BlockchainStorageRead> BlockIndexHandle for ChainstateRefRo<'a, Tx> {
+    fn get_block_index(
+        &self,
+        block_index: &Id<Block>,
+    ) -> blockchain_storage::Result<Option<BlockIndex>> {
+        self.db_tx.get_block_index(block_index)
+    }
+    fn get_ancestor(
+        &self,
+        block_index: &BlockIndex,
+        ancestor_height: BlockHeight,
+    ) -> Result<BlockIndex, BlockError> {
+        if ancestor_height > block_index.get_block_height() {
+            return Err(BlockError::InvalidAncestorHeight {
+                block_height: block_index.get_block_height(),
+                ancestor_height,
+            });
+        }
+
+        let mut block_index_walk = block_index.clone();
+        while block_index_walk.get_block_height() > ancestor_height {
+            let prev_block_id =
+                block_index_walk.get_prev_block_id().as_ref().ok_or(BlockError::NotFound)?;
+            block_index_walk =
+                self.db_tx.get_block_index(prev_block_id)?.ok_or(BlockError::NotFound)?;
+        }
+        Ok(block_index_walk)
+    }
+}
+
+impl<'a, Tx: BlockchainStorageRead> ChainstateRefRo<'a, Tx> {
+    /// Outputs of the transaction or block reward at a main chain position, together with
+    /// the ID of the block containing them
+    fn get_outputs(
+        &self,
+        position: &SpendablePosition,
+    ) -> Result<(Vec<TxOutput>, Id<Block>), BlockError> {
+        match position {
+            SpendablePosition::Transaction(position) => {
+                let tx = self
+                    .db_tx
+                    .get_mainchain_tx_by_position(position)?
+                    .ok_or(BlockError::InvariantErrorTransactionCouldNotBeLoaded)?;
+                Ok((tx.get_outputs().clone(), position.get_block_id().clone()))
+            }
+            SpendablePosition::BlockReward(block_id) => {
+                let block_index =
+                    self.db_tx.get_block_index(block_id)?.ok_or(BlockError::NotFound)?;
+                let outputs = block_index
+                    .get_block_header()
+                    .block_reward_destinations()
+                    .map_or_else(Vec::new, <[TxOutput]>::to_vec);
+                Ok((outputs, block_id.clone()))
+            }
+        }
+    }
+}
+
+impl<'a, Tx: BlockchainStorageWrite + TransactionRw<Error = blockchain_storage::Error>>
+    ChainstateRef<'a, Tx>
+{
     fn check_legitimate_orphan(
         &mut self,
         block_source: BlockSource,
@@ -397,6 +736,26 @@ impl<'a> ChainstateRef<'a> {
         self.db_tx.get_block_index(prev_block_id)?.ok_or(BlockError::NotFound)
     }
 
+    /// Median of the timestamps of the last `MEDIAN_TIME_SPAN` blocks up to and including
+    /// the given previous block. Unlike the header time of the block being connected, it
+    /// can't be pushed ahead by the block producer.
+    fn get_median_time_past(&self, prev_block_id: &Id<Block>) -> Result<u32, BlockError> {
+        let mut times = Vec::with_capacity(MEDIAN_TIME_SPAN);
+        let mut block_index_walk = self
+            .db_tx
+            .get_block_index(prev_block_id)?
+            .ok_or(BlockError::InvariantErrorPrevBlockNotFound)?;
+        loop {
+            times.push(block_index_walk.get_block_time());
+            if times.len() == MEDIAN_TIME_SPAN || block_index_walk.get_prev_block_id().is_none() {
+                break;
+            }
+            block_index_walk = self.get_previous_block_index(&block_index_walk)?;
+        }
+        times.sort_unstable();
+        Ok(times[times.len() / 2])
+    }
+
     // TODO improve using pskip
     fn get_ancestor(
         &self,
@@ -520,14 +879,74 @@ impl<'a> ChainstateRef<'a> {
         block: &Block,
         spend_height: &BlockHeight,
         blockreward_maturity: &BlockDistance,
-    ) -> Result<CachedInputs, BlockError> {
+    ) -> Result<CachedInputs<Tx>, BlockError> {
         let mut cached_inputs = CachedInputs::new(&self.db_tx);
-        for (tx_num, _tx) in block.transactions().iter().enumerate() {
-            cached_inputs.spend(block, tx_num, spend_height, blockreward_maturity)?;
+        let script_flags = self.chain_config.net_upgrade().script_flags(*spend_height);
+        let active_key_kinds = self.chain_config.net_upgrade().active_key_kinds(*spend_height);
+        let check_duplicate_txs =
+            self.chain_config.net_upgrade().duplicate_tx_check_active(*spend_height);
+        // The block reward is paid out by the consensus data of the header, so apart from the
+        // genesis premine every transaction spends something. This also keeps the transaction
+        // IDs unique, as an outpoint can only be spent once in the main chain.
+        let check_inputless_txs = !block.is_genesis(self.chain_config)
+            && self.chain_config.net_upgrade().inputless_tx_check_active(*spend_height);
+        let timelocks_active = self.chain_config.net_upgrade().timelocks_active(*spend_height);
+        // Time locks are checked against the previous blocks, the genesis has nothing to spend
+        let median_time_past = match block.prev_block_id() {
+            Some(prev_block_id) => self.get_median_time_past(&prev_block_id)?,
+            None => block.block_time(),
+        };
+        let mut total_fees = Amount::from_atoms(0);
+        for (tx_num, tx) in block.transactions().iter().enumerate() {
+            if check_inputless_txs && tx.get_inputs().is_empty() {
+                return Err(BlockError::TransactionWithoutInputs(tx.get_id()));
+            }
+            if !timelocks_active && tx.get_outputs().iter().any(|out| out.get_timelock().is_some())
+            {
+                return Err(BlockError::TimeLockNotActivated(tx.get_id()));
+            }
+            if check_duplicate_txs {
+                cached_inputs.check_duplicate_tx(tx)?;
+            }
+            let fee = cached_inputs.spend(
+                block,
+                tx_num,
+                spend_height,
+                blockreward_maturity,
+                script_flags,
+                &active_key_kinds,
+                median_time_past,
+            )?;
+            total_fees = (total_fees + fee).ok_or(BlockError::InputAdditionError)?;
         }
+        cached_inputs.verify_signatures_parallel()?;
+        self.check_block_reward(block, spend_height, total_fees)?;
+        cached_inputs.add_block_reward_outputs(block)?;
         Ok(cached_inputs)
     }
 
+    /// The block reward outputs must not pay more than the subsidy and the transaction fees
+    /// once the block reward upgrade is active
+    fn check_block_reward(
+        &self,
+        block: &Block,
+        height: &BlockHeight,
+        total_fees: Amount,
+    ) -> Result<(), BlockError> {
+        if !self.chain_config.net_upgrade().block_reward_check_active(*height) {
+            return Ok(());
+        }
+        let reward_outputs = block.header().block_reward_destinations().unwrap_or_default();
+        let reward = Amount::sum(reward_outputs.iter().map(|out| out.get_value()))
+            .ok_or(BlockError::OutputAdditionError)?;
+        let allowed = (self.chain_config.block_subsidy(*height) + total_fees)
+            .ok_or(BlockError::InputAdditionError)?;
+        if reward > allowed {
+            return Err(BlockError::BlockRewardTooHigh(reward, allowed));
+        }
+        Ok(())
+    }
+
     fn connect_transactions(
         &mut self,
         block: &Block,
@@ -544,30 +963,22 @@ impl<'a> ChainstateRef<'a> {
 
     fn disconnect_transactions_inner(
         &mut self,
-        transactions: &[Transaction],
-    ) -> Result<CachedInputs, BlockError> {
+        block: &Block,
+    ) -> Result<CachedInputs<Tx>, BlockError> {
         let mut cached_inputs = CachedInputs::new(&self.db_tx);
-        transactions.iter().try_for_each(|tx| cached_inputs.unspend(tx))?;
+        cached_inputs.remove_block_reward_outputs(block)?;
+        block.transactions().iter().try_for_each(|tx| cached_inputs.unspend(tx))?;
         Ok(cached_inputs)
     }
 
-    fn disconnect_transactions(&mut self, transactions: &[Transaction]) -> Result<(), BlockError> {
-        let cached_inputs = self.disconnect_transactions_inner(transactions)?;
+    fn disconnect_transactions(&mut self, block: &Block) -> Result<(), BlockError> {
+        let cached_inputs = self.disconnect_transactions_inner(block)?;
         let cached_inputs = cached_inputs.consume()?;
 
         CachedInputs::flush_to_storage(&mut self.db_tx, cached_inputs)?;
         Ok(())
     }
 
-    fn check_tx_outputs(&self, transactions: &[Transaction]) -> Result<(), BlockError> {
-        for tx in transactions {
-            for _output in tx.get_outputs() {
-                // TODO: Check tx outputs to prevent the overwriting of the transaction
-            }
-        }
-        Ok(())
-    }
-
     fn connect_genesis_transactions(&mut self, block: &Block) -> Result<(), BlockError> {
         for (num, tx) in block.transactions().iter().enumerate() {
             self.db_tx.set_mainchain_tx_index(
@@ -584,7 +995,6 @@ impl<'a> ChainstateRef<'a> {
             return Err(BlockError::InvariantErrorInvalidTip);
         }
         let block = self.get_block_from_index(new_tip_block_index)?.expect("Inconsistent DB");
-        self.check_tx_outputs(block.transactions())?;
 
         if block.is_genesis(self.chain_config) {
             self.connect_genesis_transactions(&block)?
@@ -628,7 +1038,7 @@ impl<'a> ChainstateRef<'a> {
             .expect("Also only genesis fails at this");
         let block = self.get_block_from_index(&block_index)?.expect("Inconsistent DB");
         // Disconnect transactions
-        self.disconnect_transactions(block.transactions())?;
+        self.disconnect_transactions(&block)?;
         self.db_tx.set_best_block_id(
             block_index
                 .get_prev_block_id()
@@ -798,6 +1208,19 @@ impl<'a> ChainstateRef<'a> {
     }
 
     fn check_transactions(&self, block: &Block) -> Result<(), BlockError> {
+        // No output or transaction can carry more than the total supply. This keeps the sums of
+        // amounts far from overflowing, whatever the inputs turn out to be.
+        let max_money = self.chain_config.max_money();
+        for tx in block.transactions() {
+            let outputs_total =
+                tx.get_outputs().iter().try_fold(Amount::from_atoms(0), |total, out| {
+                    (total + out.get_value()).filter(|total| *total <= max_money)
+                });
+            if outputs_total.is_none() {
+                return Err(BlockError::MoneyOutOfRange(tx.get_id()));
+            }
+        }
+
         // check for duplicate inputs (see CVE-2018-17144)
         {
             let mut block_inputs = BTreeSet::new();
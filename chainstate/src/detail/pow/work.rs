@@ -66,7 +66,7 @@ pub(crate) fn check_pow_consensus(
     }
 }
 
-fn calculate_work_required(
+pub(crate) fn calculate_work_required(
     chain_config: &ChainConfig,
     header: &BlockHeader,
     pow_status: &PoWStatus,
@@ -191,6 +191,30 @@ impl PoW {
     }
 }
 
+/// Difficulty of the target as a multiple of the lowest possible difficulty of the chain
+///
+/// The lowest difficulty is taken in its compact form, like the targets of the blocks,
+/// so that blocks of minimum difficulty have a difficulty of exactly 1.
+pub(crate) fn difficulty(chain_config: &ChainConfig, bits: Compact) -> Result<f64, BlockError> {
+    let to_uint256 = |bits: Compact| {
+        Uint256::try_from(bits).map_err(|e| {
+            BlockError::Conversion(format!("conversion of {:?} to Uint256 type: {:?}", bits, e))
+        })
+    };
+
+    let target = to_uint256(bits)?;
+    if target == Uint256::default() {
+        return Err(BlockError::Conversion(format!("zero target {:?}", bits)));
+    }
+    let limit = to_uint256(Compact::from(PoW::new(chain_config).difficulty_limit()))?;
+
+    Ok(uint256_to_f64(limit) / uint256_to_f64(target))
+}
+
+fn uint256_to_f64(value: Uint256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, word| acc * 2f64.powi(64) + *word as f64)
+}
+
 pub(crate) fn mine(
     block: &mut Block,
     max_nonce: u128,
@@ -213,7 +237,8 @@ pub(crate) fn mine(
 
 #[cfg(test)]
 mod tests {
-    use crate::detail::pow::work::check_proof_of_work;
+    use crate::detail::pow::work::{check_proof_of_work, difficulty};
+    use crate::BlockError;
     use common::chain::config::create_mainnet;
     use common::primitives::{Compact, H256};
     use std::str::FromStr;
@@ -278,4 +303,20 @@ mod tests {
             assert!(!res);
         }
     }
+
+    #[test]
+    fn difficulty_test() {
+        let cfg = create_mainnet();
+        let pow_limit = cfg.get_proof_of_work_config().limit();
+
+        assert_eq!(difficulty(&cfg, Compact::from(pow_limit)), Ok(1.0));
+        // bitcoin block 722731
+        let res = difficulty(&cfg, Compact(386_567_092)).expect("should not fail");
+        assert!((res - 26_690_525_287_405.5).abs() < 1.0);
+
+        assert!(matches!(
+            difficulty(&cfg, Compact(0)),
+            Err(BlockError::Conversion(_))
+        ));
+    }
 }
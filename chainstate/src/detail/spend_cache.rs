@@ -15,20 +15,27 @@
 //
 // Author(s): S. Afach
 
-use common::chain::transaction::signature::verify_signature;
+use common::chain::transaction::signature::{
+    inputsig::authorize_script_spend::ScriptSpendContext,
+    verify_signature_parallel_with_script_context,
+};
 use common::chain::SpendablePosition;
-use std::collections::{btree_map::Entry, BTreeMap};
+use crypto::key::{
+    parallel::{ParallelVerificationError, ParallelVerifier},
+    KeyKind,
+};
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
 
 use blockchain_storage::{BlockchainStorageRead, BlockchainStorageWrite};
 use common::{
     chain::{
-        block::Block, calculate_tx_index_from_block, OutPoint, OutPointSourceId, Spender,
-        Transaction,
+        block::Block, calculate_tx_index_from_block, OutPoint, OutPointSourceId, ScriptFlags,
+        Spender, Transaction, TxMainChainIndex, TxOutput,
     },
     primitives::{Amount, BlockDistance, BlockHeight, Id, Idable},
 };
 
-use crate::detail::{BlockError, TxRw};
+use crate::detail::BlockError;
 
 mod cached_operation;
 use cached_operation::CachedInputsOperation;
@@ -37,21 +44,25 @@ pub struct ConsumedCachedInputs {
     data: BTreeMap<OutPointSourceId, CachedInputsOperation>,
 }
 
-pub struct CachedInputs<'a> {
-    db_tx: &'a TxRw<'a>,
+pub struct CachedInputs<'a, P> {
+    db_tx: &'a P,
     inputs: BTreeMap<OutPointSourceId, CachedInputsOperation>,
+    /// Signatures of the spent transactions that have not been verified yet
+    parallel_verifier: ParallelVerifier,
+    /// Transaction of each signature in the parallel verifier
+    parallel_verifier_txs: Vec<Id<Transaction>>,
 }
 
-impl<'a> CachedInputs<'a> {
-    pub fn new(db_tx: &'a TxRw<'a>) -> Self {
+impl<'a, P: BlockchainStorageRead> CachedInputs<'a, P> {
+    pub fn new(db_tx: &'a P) -> Self {
         Self {
             db_tx,
             inputs: BTreeMap::new(),
+            parallel_verifier: ParallelVerifier::new(),
+            parallel_verifier_txs: Vec::new(),
         }
     }
 
-    // TODO: add block reward outputs
-
     fn add_outputs(&mut self, block: &Block, tx_num: usize) -> Result<(), BlockError> {
         let tx_index = CachedInputsOperation::Write(calculate_tx_index_from_block(block, tx_num)?);
         let tx = block
@@ -66,6 +77,25 @@ impl<'a> CachedInputs<'a> {
         Ok(())
     }
 
+    /// A transaction can't replace the index of a main chain transaction with the same ID while
+    /// the outputs of the latter are not all spent, otherwise those outputs would be lost
+    pub fn check_duplicate_tx(&self, tx: &Transaction) -> Result<(), BlockError> {
+        let source_id = OutPointSourceId::from(tx.get_id());
+        let unspent = match self.inputs.get(&source_id) {
+            Some(tx_index_op) => tx_index_op
+                .get_tx_index()
+                .map_or(false, |tx_index| !tx_index.all_outputs_spent()),
+            None => self
+                .db_tx
+                .get_mainchain_tx_index(&source_id)?
+                .map_or(false, |tx_index| !tx_index.all_outputs_spent()),
+        };
+        if unspent {
+            return Err(BlockError::DuplicateTransaction(tx.get_id()));
+        }
+        Ok(())
+    }
+
     fn remove_outputs(&mut self, tx: &Transaction) -> Result<(), BlockError> {
         self.inputs.insert(
             OutPointSourceId::from(tx.get_id()),
@@ -74,6 +104,70 @@ impl<'a> CachedInputs<'a> {
         Ok(())
     }
 
+    /// Make the block reward outputs of the block spendable, once they are mature
+    pub fn add_block_reward_outputs(&mut self, block: &Block) -> Result<(), BlockError> {
+        let reward_outputs = block.header().block_reward_destinations().unwrap_or_default();
+        if reward_outputs.is_empty() {
+            return Ok(());
+        }
+        let block_id = block.get_id();
+        let tx_index = TxMainChainIndex::new(
+            SpendablePosition::BlockReward(block_id.clone()),
+            reward_outputs.len() as u32,
+        )?;
+        match self.inputs.entry(OutPointSourceId::BlockReward(block_id)) {
+            Entry::Occupied(_) => return Err(BlockError::OutputAlreadyPresentInInputsCache),
+            Entry::Vacant(entry) => entry.insert(CachedInputsOperation::Write(tx_index)),
+        };
+        Ok(())
+    }
+
+    pub fn remove_block_reward_outputs(&mut self, block: &Block) -> Result<(), BlockError> {
+        if !block.header().block_reward_destinations().unwrap_or_default().is_empty() {
+            self.inputs.insert(
+                OutPointSourceId::BlockReward(block.get_id()),
+                CachedInputsOperation::Erase,
+            );
+        }
+        Ok(())
+    }
+
+    /// Load the output the input of the transaction spends, along with the block it is in
+    fn get_spent_output(
+        &self,
+        tx: &Transaction,
+        outpoint: &OutPoint,
+        position: &SpendablePosition,
+    ) -> Result<(TxOutput, Id<Block>), BlockError> {
+        let output_index = outpoint.get_output_index() as usize;
+        let out_of_range = || BlockError::OutputIndexOutOfRange {
+            tx_id: Some(tx.get_id().into()),
+            source_output_index: output_index,
+        };
+        match position {
+            SpendablePosition::Transaction(tx_pos) => {
+                let prev_tx = self
+                    .db_tx
+                    .get_mainchain_tx_by_position(tx_pos)?
+                    .ok_or(BlockError::InvariantErrorTransactionCouldNotBeLoaded)?;
+                let output = prev_tx.get_outputs().get(output_index).ok_or_else(out_of_range)?;
+                Ok((output.clone(), tx_pos.get_block_id().clone()))
+            }
+            SpendablePosition::BlockReward(block_id) => {
+                let block_index = self
+                    .db_tx
+                    .get_block_index(block_id)?
+                    .ok_or(BlockError::InvariantBrokenSourceBlockIndexNotFound)?;
+                let output = block_index
+                    .get_block_header()
+                    .block_reward_destinations()
+                    .and_then(|outputs| outputs.get(output_index))
+                    .ok_or_else(out_of_range)?;
+                Ok((output.clone(), block_id.clone()))
+            }
+        }
+    }
+
     fn check_blockreward_maturity(
         &self,
         spending_block_id: &Id<Block>,
@@ -92,6 +186,38 @@ impl<'a> CachedInputs<'a> {
         Ok(())
     }
 
+    fn check_timelocks(
+        &self,
+        tx: &Transaction,
+        spend_height: &BlockHeight,
+        median_time_past: u32,
+    ) -> Result<(), BlockError> {
+        for input in tx.get_inputs() {
+            let outpoint = input.get_outpoint();
+            let tx_index = self
+                .get_from_cached(outpoint)?
+                .get_tx_index()
+                .ok_or(BlockError::PreviouslyCachedInputNotFound)?;
+
+            let (output, source_block_id) =
+                self.get_spent_output(tx, outpoint, tx_index.get_position())?;
+            let timelock = match output.get_timelock() {
+                Some(timelock) => timelock,
+                None => continue,
+            };
+
+            let source_height = self
+                .db_tx
+                .get_block_index(&source_block_id)?
+                .ok_or(BlockError::InvariantBrokenSourceBlockIndexNotFound)?
+                .get_block_height();
+            if !timelock.is_unlocked(source_height, *spend_height, median_time_past) {
+                return Err(BlockError::TimeLockViolation(tx.get_id()));
+            }
+        }
+        Ok(())
+    }
+
     fn get_from_cached_mut(
         &mut self,
         outpoint: &OutPoint,
@@ -143,42 +269,30 @@ impl<'a> CachedInputs<'a> {
                 },
                 None => return Err(BlockError::PreviouslyCachedInputNotFound),
             };
-            let output_amount = match tx_index.get_position() {
-                common::chain::SpendablePosition::Transaction(tx_pos) => {
-                    match self.db_tx.get_mainchain_tx_by_position(tx_pos)? {
-                        Some(tx) => tx
-                            .get_outputs()
-                            .get(outpoint.get_output_index() as usize)
-                            .ok_or(BlockError::OutputIndexOutOfRange {
-                                tx_id: Some(tx.get_id().into()),
-                                source_output_index: outpoint.get_output_index() as usize,
-                            })?
-                            .get_value(),
-                        None => return Err(BlockError::InvariantErrorTransactionCouldNotBeLoaded),
-                    }
-                }
-                common::chain::SpendablePosition::BlockReward(_) => unimplemented!(),
-            };
-            total = (total + output_amount).ok_or(BlockError::InputAdditionError)?;
+            let (output, _) = self.get_spent_output(tx, outpoint, tx_index.get_position())?;
+            total = (total + output.get_value()).ok_or(BlockError::InputAdditionError)?;
         }
         Ok(total)
     }
 
-    fn check_inputs_amounts(&self, tx: &Transaction) -> Result<(), BlockError> {
+    /// Check the transaction doesn't print money and return its fee
+    fn check_inputs_amounts(&self, tx: &Transaction) -> Result<Amount, BlockError> {
         let inputs_total = self.calculate_total_inputs(tx)?;
-        let outputs_total = tx
-            .get_outputs()
-            .iter()
-            .try_fold(Amount::from_atoms(0), |accum, out| accum + out.get_value())
+        let outputs_total = Amount::sum(tx.get_outputs().iter().map(|out| out.get_value()))
             .ok_or(BlockError::OutputAdditionError)?;
 
         if outputs_total > inputs_total {
             return Err(BlockError::AttemptToPrintMoney(inputs_total, outputs_total));
         }
-        Ok(())
+        Ok((inputs_total - outputs_total).expect("outputs are not greater than inputs"))
     }
 
-    fn verify_signatures(&self, tx: &Transaction) -> Result<(), BlockError> {
+    fn verify_signatures(
+        &mut self,
+        tx: &Transaction,
+        script_context: Option<&ScriptSpendContext>,
+        active_key_kinds: &BTreeSet<KeyKind>,
+    ) -> Result<(), BlockError> {
         for (input_idx, input) in tx.get_inputs().iter().enumerate() {
             let outpoint = input.get_outpoint();
             let prev_tx_index_op = self.get_from_cached(outpoint)?;
@@ -187,35 +301,44 @@ impl<'a> CachedInputs<'a> {
                 .get_tx_index()
                 .ok_or(BlockError::PreviouslyCachedInputNotFound)?;
 
-            let input_outpoint_script = match tx_index.get_position() {
-                SpendablePosition::Transaction(tx_pos) => {
-                    let prev_tx = self
-                        .db_tx
-                        .get_mainchain_tx_by_position(tx_pos)
-                        .map_err(BlockError::from)?
-                        .ok_or(BlockError::InvariantErrorTransactionCouldNotBeLoaded)?;
-                    let output = prev_tx
-                        .get_outputs()
-                        .get(input.get_outpoint().get_output_index() as usize)
-                        .ok_or(BlockError::OutputIndexOutOfRange {
-                            tx_id: Some(tx.get_id().into()),
-                            source_output_index: outpoint.get_output_index() as usize,
-                        })?;
-                    output.get_destination().clone()
-                }
-                SpendablePosition::BlockReward(_reward_pos) => {
-                    // TODO(Roy): fill this with the block reward that the user now is spending
-                    todo!()
-                }
-            };
+            let (output, _) = self.get_spent_output(tx, outpoint, tx_index.get_position())?;
+            let input_outpoint_script = output.get_destination().clone();
+
+            if !input
+                .get_witness()
+                .key_kinds(&input_outpoint_script)
+                .is_subset(active_key_kinds)
+            {
+                return Err(BlockError::KeyKindNotActivated(tx.get_id()));
+            }
 
-            verify_signature(&input_outpoint_script, tx, input_idx)
-                .map_err(|_| BlockError::SignatureVerificationFailed(tx.get_id()))?;
+            verify_signature_parallel_with_script_context(
+                &input_outpoint_script,
+                tx,
+                input_idx,
+                script_context,
+                &mut self.parallel_verifier,
+            )
+            .map_err(|_| BlockError::SignatureVerificationFailed(tx.get_id()))?;
         }
 
+        self.parallel_verifier_txs.resize(self.parallel_verifier.len(), tx.get_id());
+
         Ok(())
     }
 
+    /// Verify the signatures that `spend` added to the parallel verifier
+    pub fn verify_signatures_parallel(&mut self) -> Result<(), BlockError> {
+        let result = self.parallel_verifier.verify().map_err(|e| match e {
+            ParallelVerificationError::InvalidSignature(index) => {
+                BlockError::SignatureVerificationFailed(self.parallel_verifier_txs[index].clone())
+            }
+        });
+        self.parallel_verifier.clear();
+        self.parallel_verifier_txs.clear();
+        result
+    }
+
     fn apply_spend(
         &mut self,
         tx: &Transaction,
@@ -241,24 +364,21 @@ impl<'a> CachedInputs<'a> {
         Ok(())
     }
 
+    /// Spend the inputs of the transaction and return its fee
+    ///
+    /// Script hash outputs can only be spent if the script rules are given. Only keys of the
+    /// active kinds can be used by the inputs or be locked to by the outputs. Single key
+    /// signatures are queued to be checked in parallel by `verify_signatures_parallel`.
     pub fn spend(
         &mut self,
         block: &Block,
         tx_num: usize,
         spend_height: &BlockHeight,
         blockreward_maturity: &BlockDistance,
-    ) -> Result<(), BlockError> {
-        /*
-        TODO(Roy): Add consensus data's output(s) here to spendable in the database
-        Whether we want to create a separate function for that is up to you, however,
-        it seems it's a bad idea because it's the same stuff for transactions and
-        block rewards, and repeating code is bad. Maybe change tx_num to Option<usize>,
-        and then None would mean we're going for the block reward. OR... better, an enum.
-
-        Don't forget that users are not allowed to spend the block reward before blockreward_maturity
-        passes; the check is down there. Feel free to move stuff around.
-        */
-
+        script_flags: Option<ScriptFlags>,
+        active_key_kinds: &BTreeSet<KeyKind>,
+        median_time_past: u32,
+    ) -> Result<Amount, BlockError> {
         let tx = block
             .transactions()
             .get(tx_num)
@@ -270,10 +390,24 @@ impl<'a> CachedInputs<'a> {
             .try_for_each(|input| self.fetch_and_cache(input.get_outpoint()))?;
 
         // check for attempted money printing
-        self.check_inputs_amounts(tx)?;
+        let fee = self.check_inputs_amounts(tx)?;
 
         // verify input signatures
-        self.verify_signatures(tx)?;
+        let script_context =
+            script_flags.map(|flags| ScriptSpendContext::new(flags, *spend_height));
+        self.verify_signatures(tx, script_context.as_ref(), active_key_kinds)?;
+
+        // outputs can't be locked to keys that are not activated yet
+        if !tx
+            .get_outputs()
+            .iter()
+            .all(|output| output.get_destination().key_kinds().is_subset(active_key_kinds))
+        {
+            return Err(BlockError::KeyKindNotActivated(tx.get_id()));
+        }
+
+        // check that no timelocked output is spent too early
+        self.check_timelocks(tx, spend_height, median_time_past)?;
 
         // spend inputs of this transaction
         self.apply_spend(tx, spend_height, blockreward_maturity)?;
@@ -281,7 +415,7 @@ impl<'a> CachedInputs<'a> {
         // add the outputs of this transaction to the cache
         self.add_outputs(block, tx_num)?;
 
-        Ok(())
+        Ok(fee)
     }
 
     pub fn unspend(&mut self, tx: &Transaction) -> Result<(), BlockError> {
@@ -306,13 +440,20 @@ impl<'a> CachedInputs<'a> {
     }
 
     pub fn consume(self) -> Result<ConsumedCachedInputs, BlockError> {
+        debug_assert!(
+            self.parallel_verifier.is_empty(),
+            "queued signatures must be verified with `verify_signatures_parallel` before consuming"
+        );
         Ok(ConsumedCachedInputs { data: self.inputs })
     }
 
     pub fn flush_to_storage(
-        db_tx: &mut TxRw<'a>,
+        db_tx: &mut P,
         input_data: ConsumedCachedInputs,
-    ) -> Result<(), BlockError> {
+    ) -> Result<(), BlockError>
+    where
+        P: BlockchainStorageWrite,
+    {
         for (tx_id, tx_index_op) in input_data.data {
             match tx_index_op {
                 CachedInputsOperation::Write(ref tx_index) => {
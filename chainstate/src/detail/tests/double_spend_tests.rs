@@ -15,9 +15,13 @@
 //
 // Author(s): A. Sinitsyn
 
+use crate::detail::tests::test_framework::BlockTestFramework;
 use crate::detail::tests::*;
 use common::chain::block::{Block, ConsensusData};
-use common::chain::{Transaction, TxInput, TxOutput};
+use common::chain::config::ChainConfigBuilder;
+use common::chain::{
+    ConsensusUpgrade, NetUpgrades, Transaction, TxInput, TxOutput, UpgradeVersion,
+};
 use common::primitives::{time, Amount, Id};
 
 #[test]
@@ -317,3 +321,153 @@ fn double_spend_tx_in_another_block() {
         );
     });
 }
+
+// A transaction repeated in a later block is rejected as a duplicate while its outputs are
+// unspent, otherwise its index would be replaced. Once they are spent, the repeated transaction
+// fails as a double spend as every transaction spends something.
+#[test]
+fn duplicate_tx_with_unspent_outputs() {
+    common::concurrency::model(|| {
+        let make_block = |transactions: Vec<Transaction>, prev_block_id: Id<Block>| {
+            Block::new(
+                transactions,
+                Some(prev_block_id),
+                time::get() as u32,
+                ConsensusData::None,
+            )
+            .expect(ERR_CREATE_BLOCK_FAIL)
+        };
+        let make_tx = |genesis: &Block| {
+            let genesis_tx = &genesis.transactions()[0];
+            Transaction::new(
+                0,
+                vec![TxInput::new(
+                    OutPointSourceId::Transaction(genesis_tx.get_id()),
+                    0,
+                    empty_witness(),
+                )],
+                vec![TxOutput::new(
+                    genesis_tx.get_outputs()[0].get_value(),
+                    anyonecanspend_address(),
+                )],
+                0,
+            )
+            .expect(ERR_CREATE_TX_FAIL)
+        };
+
+        let mut btf = BlockTestFramework::new();
+        let tx = make_tx(btf.genesis());
+        let tx_id = tx.get_id();
+        let first_block = make_block(vec![tx.clone()], btf.genesis().get_id());
+        let first_block_id = first_block.get_id();
+        btf.add_special_block(first_block).unwrap();
+        assert_eq!(
+            btf.add_special_block(make_block(vec![tx.clone()], first_block_id.clone()))
+                .unwrap_err(),
+            BlockError::DuplicateTransaction(tx_id.clone())
+        );
+
+        let spend_tx = Transaction::new(
+            0,
+            vec![TxInput::new(tx_id.clone().into(), 0, empty_witness())],
+            vec![TxOutput::new(Amount::from_atoms(0), anyonecanspend_address())],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let spend_block = make_block(vec![spend_tx], first_block_id);
+        let spend_block_id = spend_block.get_id();
+        btf.add_special_block(spend_block).unwrap();
+        assert!(matches!(
+            btf.add_special_block(make_block(vec![tx], spend_block_id)),
+            Err(BlockError::DoubleSpendAttempt(_))
+        ));
+
+        // before the net upgrade, only the double spend is detected
+        let upgrades = vec![(
+            BlockHeight::new(0),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+        )];
+        let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
+        let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
+        let chainstate = ChainstateBuilder::new().with_config(config).build();
+        let mut btf = BlockTestFramework::with_chainstate(chainstate);
+        let tx = make_tx(btf.genesis());
+        let first_block = make_block(vec![tx.clone()], btf.genesis().get_id());
+        let first_block_id = first_block.get_id();
+        btf.add_special_block(first_block).unwrap();
+        assert!(matches!(
+            btf.add_special_block(make_block(vec![tx], first_block_id)),
+            Err(BlockError::DoubleSpendAttempt(_))
+        ));
+    });
+}
+
+// Before the inputless transaction upgrade, a transaction without inputs and with a zero valued
+// output can be repeated in later blocks, doing so must not replace the index of the earlier
+// transaction while its output is unspent
+#[test]
+fn duplicate_inputless_tx_with_unspent_outputs() {
+    common::concurrency::model(|| {
+        let make_block = |transactions: Vec<Transaction>, prev_block_id: Id<Block>| {
+            Block::new(
+                transactions,
+                Some(prev_block_id),
+                time::get() as u32,
+                ConsensusData::None,
+            )
+            .expect(ERR_CREATE_BLOCK_FAIL)
+        };
+        let make_btf = |upgrades: Vec<(BlockHeight, UpgradeVersion)>| {
+            let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
+            let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
+            let chainstate = ChainstateBuilder::new().with_config(config).build();
+            BlockTestFramework::with_chainstate(chainstate)
+        };
+        let ignore_consensus = (
+            BlockHeight::new(0),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+        );
+        let tx = Transaction::new(
+            0,
+            vec![],
+            vec![TxOutput::new(Amount::from_atoms(0), anyonecanspend_address())],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let tx_id = tx.get_id();
+
+        let mut btf = make_btf(vec![
+            ignore_consensus,
+            (BlockHeight::new(0), UpgradeVersion::DuplicateTxUpgrade),
+        ]);
+        let first_block = make_block(vec![tx.clone()], btf.genesis().get_id());
+        let first_block_id = first_block.get_id();
+        btf.add_special_block(first_block).unwrap();
+        assert_eq!(
+            btf.add_special_block(make_block(vec![tx.clone()], first_block_id.clone()))
+                .unwrap_err(),
+            BlockError::DuplicateTransaction(tx_id.clone())
+        );
+
+        // once its output is spent, the transaction can be repeated
+        let spend_tx = Transaction::new(
+            0,
+            vec![TxInput::new(tx_id.clone().into(), 0, empty_witness())],
+            vec![TxOutput::new(Amount::from_atoms(0), anyonecanspend_address())],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let spend_block = make_block(vec![spend_tx], first_block_id);
+        let spend_block_id = spend_block.get_id();
+        btf.add_special_block(spend_block).unwrap();
+        assert!(btf.add_special_block(make_block(vec![tx.clone()], spend_block_id)).is_ok());
+
+        // before the duplicate transaction upgrade, the index of the earlier transaction is
+        // replaced
+        let mut btf = make_btf(vec![ignore_consensus]);
+        let first_block = make_block(vec![tx.clone()], btf.genesis().get_id());
+        let first_block_id = first_block.get_id();
+        btf.add_special_block(first_block).unwrap();
+        assert!(btf.add_special_block(make_block(vec![tx], first_block_id)).is_ok());
+    });
+}
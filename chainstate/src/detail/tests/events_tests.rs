@@ -121,10 +121,7 @@ fn test_events_a_bunch_of_events() {
         for height in 0..COUNT_EVENTS {
             rand_block = produce_test_block(&rand_block, false);
             blocks.push(rand_block.clone());
-            map_heights.insert(
-                rand_block.get_id(),
-                BlockHeight::new(height.try_into().unwrap()),
-            );
+            map_heights.insert(rand_block.get_id(), BlockHeight::try_from(height).unwrap());
         }
 
         // Event handler
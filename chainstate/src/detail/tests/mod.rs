@@ -37,6 +37,8 @@ mod events_tests;
 #[cfg(test)]
 mod processing_tests;
 #[cfg(test)]
+mod reorgs_property_tests;
+#[cfg(test)]
 mod reorgs_tests;
 #[cfg(test)]
 mod signature_tests;
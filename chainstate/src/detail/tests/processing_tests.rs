@@ -21,10 +21,11 @@ use crate::make_chainstate;
 use blockchain_storage::Store;
 use common::chain::block::consensus_data::PoWData;
 use common::chain::config::create_unit_test_config;
-use common::chain::config::TestChainConfig;
+use common::chain::config::ChainConfigBuilder;
 use common::chain::ConsensusUpgrade;
 use common::chain::NetUpgrades;
 use common::chain::OutputSpentState;
+use common::chain::OutputTimeLock;
 use common::chain::UpgradeVersion;
 use common::primitives::Compact;
 use common::Uint256;
@@ -118,11 +119,11 @@ fn test_orphans_chains() {
         let last_block_index_in_db = chainstate.get_block_index(&current_best).unwrap().unwrap();
         assert_eq!(
             last_block_index_in_db.get_block_height(),
-            (MAX_ORPHANS_COUNT_IN_TEST as u64).into()
+            BlockHeight::try_from(MAX_ORPHANS_COUNT_IN_TEST).unwrap()
         );
         assert_eq!(
             last_block_index.get_block_height(),
-            (MAX_ORPHANS_COUNT_IN_TEST as u64).into()
+            BlockHeight::try_from(MAX_ORPHANS_COUNT_IN_TEST).unwrap()
         );
 
         // no more orphan blocks left
@@ -289,6 +290,86 @@ fn test_straight_chain() {
     });
 }
 
+#[test]
+#[cfg(not(loom))]
+fn test_failed_connect_rolled_back() {
+    // A block failing in the middle of being connected leaves no writes in the storage
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blockchain");
+    let config = Arc::new(create_unit_test_config());
+
+    let storage = Store::open(&path).unwrap();
+    let mut chainstate = Chainstate::new(Arc::clone(&config), storage, None).unwrap();
+
+    let valid_tx = produce_test_block(config.genesis_block(), false).transactions()[0].clone();
+    let invalid_tx = Transaction::new(
+        0,
+        vec![TxInput::new(
+            OutPointSourceId::Transaction(Id::new(&H256::random())),
+            0,
+            empty_witness(),
+        )],
+        vec![TxOutput::new(Amount::from_atoms(1), anyonecanspend_address())],
+        0,
+    )
+    .expect(ERR_CREATE_TX_FAIL);
+    let block = Block::new(
+        vec![valid_tx.clone(), invalid_tx],
+        Some(config.genesis_block_id()),
+        time::get() as u32,
+        ConsensusData::None,
+    )
+    .expect(ERR_CREATE_BLOCK_FAIL);
+    let block_id = block.get_id();
+    assert_eq!(
+        chainstate.process_block(block, BlockSource::Local).unwrap_err(),
+        BlockError::MissingOutputOrSpent
+    );
+    drop(chainstate);
+
+    let storage = Store::open(&path).unwrap();
+    assert_eq!(
+        storage.get_best_block_id(),
+        Ok(Some(config.genesis_block_id()))
+    );
+    assert_eq!(storage.get_block(block_id.clone()), Ok(None));
+    assert!(storage.get_block_index(&block_id).unwrap().is_none());
+    assert_eq!(
+        storage.get_mainchain_tx_index(&OutPointSourceId::Transaction(valid_tx.get_id())),
+        Ok(None)
+    );
+}
+
+#[test]
+#[cfg(not(loom))]
+fn test_persisted_chain() {
+    // The chain processed with a persistent storage is still there after reopening it
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blockchain");
+    let config = Arc::new(create_unit_test_config());
+
+    let storage = Store::open(&path).unwrap();
+    let mut chainstate = Chainstate::new(Arc::clone(&config), storage, None).unwrap();
+    let mut prev_block = config.genesis_block().clone();
+    for _ in 0..10 {
+        let block = produce_test_block(&prev_block, false);
+        chainstate.process_block(block.clone(), BlockSource::Local).unwrap();
+        prev_block = block;
+    }
+    drop(chainstate);
+
+    let storage = Store::open(&path).unwrap();
+    let chainstate = Chainstate::new(config, storage, None).unwrap();
+    assert_eq!(
+        chainstate.get_best_block_id().unwrap(),
+        Some(prev_block.get_id())
+    );
+    assert_eq!(
+        chainstate.get_block_height_in_main_chain(&prev_block.get_id()).unwrap(),
+        Some(BlockHeight::new(10))
+    );
+}
+
 #[test]
 fn test_get_ancestor() {
     use crate::detail::tests::test_framework::BlockTestFramework;
@@ -324,7 +405,7 @@ fn test_get_ancestor() {
             .make_db_tx()
             .get_ancestor(
                 &last_block_in_first_chain,
-                u64::try_from(FIRST_CHAIN_HEIGHT).unwrap().into()
+                BlockHeight::try_from(FIRST_CHAIN_HEIGHT).unwrap()
             )
             .expect("ancestor")
             .get_block_id()
@@ -336,7 +417,7 @@ fn test_get_ancestor() {
             .make_db_tx()
             .get_ancestor(
                 &last_block_in_first_chain,
-                u64::try_from(ANCESTOR_HEIGHT).unwrap().into()
+                BlockHeight::try_from(ANCESTOR_HEIGHT).unwrap()
             )
             .expect("ancestor")
             .get_block_id()
@@ -348,7 +429,7 @@ fn test_get_ancestor() {
             .make_db_tx()
             .get_ancestor(
                 &last_block_in_first_chain,
-                u64::try_from(ANCESTOR_IN_FIRST_CHAIN_HEIGHT).unwrap().into()
+                BlockHeight::try_from(ANCESTOR_IN_FIRST_CHAIN_HEIGHT).unwrap()
             )
             .expect("ancestor in first chain")
             .get_block_id()
@@ -365,7 +446,7 @@ fn test_get_ancestor() {
             .make_db_tx()
             .get_ancestor(
                 &last_block_in_second_chain,
-                u64::try_from(ANCESTOR_HEIGHT).unwrap().into()
+                BlockHeight::try_from(ANCESTOR_HEIGHT).unwrap()
             )
             .expect("ancestor")
             .get_block_id()
@@ -373,14 +454,14 @@ fn test_get_ancestor() {
 
     assert_eq!(
         BlockError::InvalidAncestorHeight {
-            ancestor_height: u64::try_from(SECOND_CHAIN_LENGTH + 1).unwrap().into(),
-            block_height: u64::try_from(SECOND_CHAIN_LENGTH).unwrap().into(),
+            ancestor_height: BlockHeight::try_from(SECOND_CHAIN_LENGTH + 1).unwrap(),
+            block_height: BlockHeight::try_from(SECOND_CHAIN_LENGTH).unwrap(),
         },
         btf.chainstate
             .make_db_tx()
             .get_ancestor(
                 &last_block_in_second_chain,
-                u64::try_from(SECOND_CHAIN_LENGTH + 1).unwrap().into()
+                BlockHeight::try_from(SECOND_CHAIN_LENGTH + 1).unwrap()
             )
             .unwrap_err()
     );
@@ -490,10 +571,9 @@ fn test_consensus_type() {
     let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
 
     // Internally this calls Consensus::new, which processes the genesis block
-    // This should succeed because TestChainConfig by default uses create_mainnet_genesis to
-    // create the genesis_block, and this function creates a genesis block with
-    // ConsenssuData::None, which agreess with the net_upgrades we defined above.
-    let config = TestChainConfig::new().with_net_upgrades(net_upgrades).build();
+    // This should succeed because ChainConfigBuilder::test_chain by default creates the
+    // genesis_block with ConsenssuData::None, which agreess with the net_upgrades we defined above.
+    let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
     let chainstate = ChainstateBuilder::new().with_config(config).build();
 
     let mut btf = BlockTestFramework::with_chainstate(chainstate);
@@ -619,10 +699,9 @@ fn test_pow() {
     let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
 
     // Internally this calls Consensus::new, which processes the genesis block
-    // This should succeed because TestChainConfig by default uses create_mainnet_genesis to
-    // create the genesis_block, and this function creates a genesis block with
-    // ConsenssuData::None, which agreess with the net_upgrades we defined above.
-    let config = TestChainConfig::new().with_net_upgrades(net_upgrades).build();
+    // This should succeed because ChainConfigBuilder::test_chain by default creates the
+    // genesis_block with ConsenssuData::None, which agreess with the net_upgrades we defined above.
+    let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
     let chainstate = ChainstateBuilder::new().with_config(config).build();
 
     let mut btf = BlockTestFramework::with_chainstate(chainstate);
@@ -655,9 +734,497 @@ fn test_pow() {
     btf.add_special_block(valid_block.clone()).unwrap();
 }
 
+#[test]
+fn test_block_template() {
+    let difficulty =
+        Uint256([0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x0FFFFFFFFFFFFFFF]);
+    let upgrades = vec![
+        (
+            BlockHeight::new(0),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+        ),
+        (
+            BlockHeight::new(2),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoW {
+                initial_difficulty: difficulty.into(),
+            }),
+        ),
+    ];
+    let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
+    let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
+    let mut chainstate = ChainstateBuilder::new().with_config(config).build();
+
+    // Block 1 ignores consensus, so the template is complete as is
+    let template = chainstate.get_block_template(Destination::AnyoneCanSpend).unwrap();
+    assert_eq!(
+        template.prev_block_id(),
+        Some(chainstate.chain_config.genesis_block_id())
+    );
+    assert_eq!(template.consensus_data(), &ConsensusData::None);
+    chainstate.process_block(template.clone(), BlockSource::Local).unwrap();
+
+    // Block 2 needs proof of work with the reward going to the given destination
+    let mut template = chainstate.get_block_template(Destination::AnyoneCanSpend).unwrap();
+    assert_eq!(
+        template.prev_block_id(),
+        Some(chainstate.get_best_block_id().unwrap().unwrap())
+    );
+    let reward = match template.consensus_data() {
+        ConsensusData::PoW(data) => {
+            assert_eq!(data.bits(), difficulty.into());
+            data.outputs().to_vec()
+        }
+        ConsensusData::None => panic!("expected a proof of work template"),
+    };
+    let subsidy = chainstate.chain_config.block_subsidy(BlockHeight::new(2));
+    assert_eq!(
+        reward,
+        vec![TxOutput::new(subsidy, Destination::AnyoneCanSpend)]
+    );
+    assert!(
+        crate::detail::pow::work::mine(&mut template, u128::MAX, difficulty.into(), reward)
+            .expect("Unexpected conversion error")
+    );
+    chainstate.process_block(template.clone(), BlockSource::Local).unwrap();
+    assert_eq!(
+        chainstate.get_best_block_id().unwrap(),
+        Some(template.get_id())
+    );
+}
+
+#[test]
+fn test_block_reward_above_subsidy() {
+    let difficulty =
+        Uint256([0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x0FFFFFFFFFFFFFFF]);
+    let upgrades = vec![
+        (
+            BlockHeight::new(0),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+        ),
+        (
+            BlockHeight::new(1),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoW {
+                initial_difficulty: difficulty.into(),
+            }),
+        ),
+        (BlockHeight::new(2), UpgradeVersion::BlockRewardUpgrade),
+    ];
+    let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
+    let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
+    let mut chainstate = ChainstateBuilder::new().with_config(config).build();
+
+    // Rewards are not limited before the upgrade
+    let subsidy = chainstate.chain_config.block_subsidy(BlockHeight::new(1));
+    let too_high = (subsidy + Amount::from_atoms(1)).unwrap();
+    let mut block = chainstate.get_block_template(Destination::AnyoneCanSpend).unwrap();
+    let reward = vec![TxOutput::new(too_high, Destination::AnyoneCanSpend)];
+    assert!(
+        crate::detail::pow::work::mine(&mut block, u128::MAX, difficulty.into(), reward)
+            .expect("Unexpected conversion error")
+    );
+    chainstate.process_block(block, BlockSource::Local).unwrap();
+
+    let subsidy = chainstate.chain_config.block_subsidy(BlockHeight::new(2));
+    let too_high = (subsidy + Amount::from_atoms(1)).unwrap();
+    let mut block = chainstate.get_block_template(Destination::AnyoneCanSpend).unwrap();
+    let reward = vec![TxOutput::new(too_high, Destination::AnyoneCanSpend)];
+    assert!(
+        crate::detail::pow::work::mine(&mut block, u128::MAX, difficulty.into(), reward)
+            .expect("Unexpected conversion error")
+    );
+    assert_eq!(
+        chainstate.process_block(block, BlockSource::Local).unwrap_err(),
+        BlockError::BlockRewardTooHigh(too_high, subsidy)
+    );
+}
+
+// Block reward outputs can be spent once they are mature, like the outputs of transactions
+#[test]
+fn test_spend_block_reward() {
+    let difficulty =
+        Uint256([0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x0FFFFFFFFFFFFFFF]);
+    let upgrades = vec![
+        (
+            BlockHeight::new(0),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+        ),
+        (
+            BlockHeight::new(1),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoW {
+                initial_difficulty: difficulty.into(),
+            }),
+        ),
+    ];
+    let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
+    let config = ChainConfigBuilder::test_chain()
+        .with_net_upgrades(net_upgrades)
+        .with_blockreward_maturity(BlockDistance::new(2))
+        .build();
+    let mut chainstate = ChainstateBuilder::new().with_config(config).build();
+
+    let mine_block = |prev_block_id: Id<Block>, transactions, reward| {
+        let mut block = Block::new(
+            transactions,
+            Some(prev_block_id),
+            time::get() as u32,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+        assert!(
+            crate::detail::pow::work::mine(&mut block, u128::MAX, difficulty.into(), reward)
+                .expect("Unexpected conversion error")
+        );
+        block
+    };
+
+    let subsidy = chainstate.chain_config.block_subsidy(BlockHeight::new(1));
+    let reward_output = TxOutput::new(subsidy, anyonecanspend_address());
+    let block1 = mine_block(
+        chainstate.chain_config.genesis_block_id(),
+        vec![],
+        vec![reward_output.clone()],
+    );
+    let block1_id = block1.get_id();
+    chainstate.process_block(block1, BlockSource::Local).unwrap();
+
+    let reward_outpoint = OutPoint::new(OutPointSourceId::BlockReward(block1_id.clone()), 0);
+    assert_eq!(
+        chainstate.get_utxo(&reward_outpoint).unwrap(),
+        Some((reward_output, block1_id.clone()))
+    );
+
+    let spend_tx = Transaction::new(
+        0,
+        vec![TxInput::new(
+            reward_outpoint.get_tx_id(),
+            reward_outpoint.get_output_index(),
+            empty_witness(),
+        )],
+        vec![TxOutput::new(subsidy, anyonecanspend_address())],
+        0,
+    )
+    .expect(ERR_CREATE_TX_FAIL);
+
+    // The reward of block 1 is not mature at height 2
+    let immature = mine_block(block1_id.clone(), vec![spend_tx.clone()], vec![]);
+    assert_eq!(
+        chainstate.process_block(immature, BlockSource::Local).unwrap_err(),
+        BlockError::ImmatureBlockRewardSpend
+    );
+
+    let block2 = mine_block(block1_id, vec![], vec![]);
+    let block2_id = block2.get_id();
+    chainstate.process_block(block2, BlockSource::Local).unwrap();
+    let block3 = mine_block(block2_id, vec![spend_tx.clone()], vec![]);
+    chainstate.process_block(block3, BlockSource::Local).unwrap();
+
+    assert_eq!(chainstate.get_utxo(&reward_outpoint).unwrap(), None);
+    let spend_outpoint = OutPoint::new(OutPointSourceId::from(spend_tx.get_id()), 0);
+    assert!(chainstate.get_utxo(&spend_outpoint).unwrap().is_some());
+}
+
+// Only the genesis transactions and the block reward create coins, a transaction without inputs
+// is rejected even if its outputs are worth nothing
+#[test]
+fn test_transaction_without_inputs() {
+    common::concurrency::model(|| {
+        let mut btf = BlockTestFramework::new();
+        let loose_tx = Transaction::new(
+            0,
+            vec![],
+            vec![TxOutput::new(Amount::from_atoms(0), anyonecanspend_address())],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let loose_tx_id = loose_tx.get_id();
+
+        let mut block = btf.random_block(btf.genesis(), None);
+        let mut transactions = block.transactions().clone();
+        transactions.push(loose_tx);
+        block = Block::new(
+            transactions,
+            block.prev_block_id(),
+            block.block_time(),
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+        assert_eq!(
+            btf.add_special_block(block).unwrap_err(),
+            BlockError::TransactionWithoutInputs(loose_tx_id)
+        );
+        assert_eq!(
+            btf.chainstate.get_best_block_id().unwrap(),
+            Some(btf.genesis().get_id())
+        );
+    });
+}
+
+// A single output or the outputs of a transaction together can't exceed the total supply
+#[test]
+fn test_outputs_above_max_money() {
+    common::concurrency::model(|| {
+        let mut btf = BlockTestFramework::new();
+        let max_money = btf.chainstate.chain_config.max_money();
+        let genesis_tx_id = btf.genesis().transactions()[0].get_id();
+
+        let too_much = vec![TxOutput::new(
+            (max_money + Amount::from_atoms(1)).unwrap(),
+            anyonecanspend_address(),
+        )];
+        let together_too_much = vec![
+            TxOutput::new(max_money, anyonecanspend_address()),
+            TxOutput::new(Amount::from_atoms(1), anyonecanspend_address()),
+        ];
+        for outputs in [too_much, together_too_much] {
+            let input = TxInput::new(genesis_tx_id.clone().into(), 0, empty_witness());
+            let tx = Transaction::new(0, vec![input], outputs, 0).expect(ERR_CREATE_TX_FAIL);
+            let tx_id = tx.get_id();
+            let block = Block::new(
+                vec![tx],
+                Some(btf.genesis().get_id()),
+                time::get() as u32,
+                ConsensusData::None,
+            )
+            .expect(ERR_CREATE_BLOCK_FAIL);
+            assert_eq!(
+                btf.add_special_block(block).unwrap_err(),
+                BlockError::MoneyOutOfRange(tx_id)
+            );
+        }
+        assert_eq!(
+            btf.chainstate.get_best_block_id().unwrap(),
+            Some(btf.genesis().get_id())
+        );
+    });
+}
+
+#[test]
+fn test_timelocked_output_spend() {
+    common::concurrency::model(|| {
+        let mut chainstate = setup_chainstate();
+        let genesis_tx_id = chainstate.chain_config.genesis_block().transactions()[0].get_id();
+
+        let spend_block = |prev_block_id: Id<Block>, tx_id: Id<Transaction>, index: u32| {
+            let tx = Transaction::new(
+                0,
+                vec![TxInput::new(tx_id.into(), index, empty_witness())],
+                vec![TxOutput::new(Amount::from_atoms(1), anyonecanspend_address())],
+                0,
+            )
+            .expect(ERR_CREATE_TX_FAIL);
+            Block::new(
+                vec![tx],
+                Some(prev_block_id),
+                time::get() as u32,
+                ConsensusData::None,
+            )
+            .expect(ERR_CREATE_BLOCK_FAIL)
+        };
+
+        // The first output can't be spent before height 3
+        let tx = Transaction::new(
+            0,
+            vec![TxInput::new(genesis_tx_id.into(), 0, empty_witness())],
+            vec![
+                TxOutput::new_with_timelock(
+                    Amount::from_atoms(1000),
+                    anyonecanspend_address(),
+                    OutputTimeLock::UntilHeight(BlockHeight::new(3)),
+                ),
+                TxOutput::new(Amount::from_atoms(1000), anyonecanspend_address()),
+            ],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let tx_id = tx.get_id();
+        let block = Block::new(
+            vec![tx],
+            Some(chainstate.chain_config.genesis_block_id()),
+            time::get() as u32,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+        let block_1_id = block.get_id();
+        chainstate.process_block(block, BlockSource::Local).unwrap();
+
+        let block = spend_block(block_1_id.clone(), tx_id.clone(), 0);
+        assert!(matches!(
+            chainstate.process_block(block, BlockSource::Local),
+            Err(BlockError::TimeLockViolation(_))
+        ));
+
+        let block = spend_block(block_1_id, tx_id.clone(), 1);
+        let block_2_id = block.get_id();
+        chainstate.process_block(block, BlockSource::Local).unwrap();
+
+        let block = spend_block(block_2_id, tx_id, 0);
+        let block_3_id = block.get_id();
+        chainstate.process_block(block, BlockSource::Local).unwrap();
+        assert_eq!(
+            chainstate
+                .blockchain_storage
+                .get_best_block_id()
+                .expect(ERR_BEST_BLOCK_NOT_FOUND),
+            Some(block_3_id)
+        );
+    });
+}
+
+#[test]
+fn test_timelocked_output_median_time_past() {
+    common::concurrency::model(|| {
+        let mut chainstate = setup_chainstate();
+        let genesis_tx_id = chainstate.chain_config.genesis_block().transactions()[0].get_id();
+        let lock_time = time::get() as u32;
+
+        // The output is locked until now, but the only block after genesis is older
+        let tx = Transaction::new(
+            0,
+            vec![TxInput::new(genesis_tx_id.into(), 0, empty_witness())],
+            vec![TxOutput::new_with_timelock(
+                Amount::from_atoms(1000),
+                anyonecanspend_address(),
+                OutputTimeLock::UntilTime(lock_time),
+            )],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let tx_id = tx.get_id();
+        let block = Block::new(
+            vec![tx],
+            Some(chainstate.chain_config.genesis_block_id()),
+            lock_time - 1000,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+        let mut prev_block_id = block.get_id();
+        chainstate.process_block(block, BlockSource::Local).unwrap();
+
+        let spend_tx = Transaction::new(
+            0,
+            vec![TxInput::new(tx_id.into(), 0, empty_witness())],
+            vec![TxOutput::new(Amount::from_atoms(1), anyonecanspend_address())],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let spend_tx_id = spend_tx.get_id();
+
+        // The header time has reached the lock, the median time past of the previous blocks
+        // has not
+        let block = Block::new(
+            vec![spend_tx.clone()],
+            Some(prev_block_id.clone()),
+            time::get() as u32,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+        assert_eq!(
+            chainstate.process_block(block, BlockSource::Local).unwrap_err(),
+            BlockError::TimeLockViolation(spend_tx_id)
+        );
+
+        // Move the median time past up to the lock
+        for _ in 0..2 {
+            let block = Block::new(vec![], Some(prev_block_id), lock_time, ConsensusData::None)
+                .expect(ERR_CREATE_BLOCK_FAIL);
+            prev_block_id = block.get_id();
+            chainstate.process_block(block, BlockSource::Local).unwrap();
+        }
+
+        let block = Block::new(
+            vec![spend_tx],
+            Some(prev_block_id),
+            time::get() as u32,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+        let block_id = block.get_id();
+        chainstate.process_block(block, BlockSource::Local).unwrap();
+        assert_eq!(
+            chainstate
+                .blockchain_storage
+                .get_best_block_id()
+                .expect(ERR_BEST_BLOCK_NOT_FOUND),
+            Some(block_id)
+        );
+    });
+}
+
+#[test]
+fn test_timelocked_output_before_upgrade() {
+    common::concurrency::model(|| {
+        let upgrades = vec![
+            (
+                BlockHeight::new(0),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (BlockHeight::new(2), UpgradeVersion::TimeLockUpgrade),
+        ];
+        let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
+        let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
+        let mut chainstate = ChainstateBuilder::new().with_config(config).build();
+        let genesis_tx_id = chainstate.chain_config.genesis_block().transactions()[0].get_id();
+
+        let tx = Transaction::new(
+            0,
+            vec![TxInput::new(genesis_tx_id.into(), 0, empty_witness())],
+            vec![TxOutput::new_with_timelock(
+                Amount::from_atoms(1000),
+                anyonecanspend_address(),
+                OutputTimeLock::UntilHeight(BlockHeight::new(3)),
+            )],
+            0,
+        )
+        .expect(ERR_CREATE_TX_FAIL);
+        let tx_id = tx.get_id();
+        let block = Block::new(
+            vec![tx],
+            Some(chainstate.chain_config.genesis_block_id()),
+            time::get() as u32,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+        assert_eq!(
+            chainstate.process_block(block, BlockSource::Local).unwrap_err(),
+            BlockError::TimeLockNotActivated(tx_id)
+        );
+    });
+}
+
 #[test]
 fn test_mainnet_initialization() {
     let config = Arc::new(common::chain::config::create_mainnet());
     let storage = Store::new_empty().unwrap();
     let _chainstate = make_chainstate(config, storage, None).unwrap();
 }
+
+#[test]
+fn initial_block_download() {
+    common::concurrency::model(|| {
+        let mut chainstate = setup_chainstate();
+        assert!(chainstate.is_initial_block_download());
+
+        // blocks are produced at the current time
+        let block = produce_test_block(chainstate.chain_config.genesis_block(), false);
+        chainstate.process_block(block, BlockSource::Local).unwrap();
+        assert!(!chainstate.is_initial_block_download());
+
+        // the best block getting old doesn't bring the node back to initial block download
+        chainstate.latch_initial_block_download_done(time::get() + 2 * MAX_TIP_AGE);
+        assert!(!chainstate.is_initial_block_download());
+    });
+}
+
+#[test]
+fn initial_block_download_until_last_checkpoint() {
+    common::concurrency::model(|| {
+        let checkpoints = [(BlockHeight::new(3), Id::new(&H256::random()))].into_iter().collect();
+        let config = ChainConfigBuilder::test_chain().with_height_checkpoints(checkpoints).build();
+        let mut chainstate = ChainstateBuilder::new().with_config(config).build();
+
+        let block = produce_test_block(chainstate.chain_config.genesis_block(), false);
+        chainstate.process_block(block, BlockSource::Local).unwrap();
+        assert!(chainstate.is_initial_block_download());
+        assert!(chainstate.is_best_block_behind(time::get()).unwrap());
+    });
+}
@@ -0,0 +1,235 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Property tests of the reorganizations
+//!
+//! Random block trees are processed in random orders, after which the chainstate must be on a
+//! chain of maximum trust and hold the same state as a fresh chainstate that only processed the
+//! blocks of that chain. As the blocks of each branch spend the outputs of their parents, any
+//! output that a disconnected block doesn't restore exactly shows up as a difference between the
+//! two.
+
+use crate::detail::tests::*;
+use proptest::{collection::vec, prelude::*, sample::Index};
+
+/// Maximum number of blocks of a generated tree, not counting the genesis
+const MAX_TREE_SIZE: usize = 24;
+
+/// Maximum number of outputs of the transaction of a block
+const MAX_OUTPUTS: usize = 3;
+
+/// Blocks of a tree rooted at the genesis, each with one transaction spending all the outputs of
+/// the transaction of its parent
+struct BlockTree {
+    blocks: Vec<Block>,
+    parents: Vec<usize>,
+}
+
+impl BlockTree {
+    fn new(genesis: &Block) -> Self {
+        Self {
+            blocks: vec![genesis.clone()],
+            parents: vec![0],
+        }
+    }
+
+    /// Add a child to the block at `parent`, returns the position of the new block
+    fn add(&mut self, parent: usize, output_count: usize) -> usize {
+        let position = self.blocks.len();
+        let parent_block = &self.blocks[parent];
+        let parent_tx = &parent_block.transactions()[0];
+
+        let inputs: Vec<TxInput> = (0..parent_tx.get_outputs().len())
+            .map(|index| {
+                TxInput::new(
+                    OutPointSourceId::Transaction(parent_tx.get_id()),
+                    index as u32,
+                    InputWitness::NoSignature(None),
+                )
+            })
+            .collect();
+        let total: u128 = parent_tx
+            .get_outputs()
+            .iter()
+            .map(|output| output.get_value().into_atoms())
+            .sum();
+        let outputs = (0..output_count)
+            .map(|index| {
+                let mut atoms = total / output_count as u128;
+                if index == 0 {
+                    atoms += total % output_count as u128;
+                }
+                TxOutput::new(Amount::from_atoms(atoms), anyonecanspend_address())
+            })
+            .collect();
+
+        // The lock time tells apart the transactions of the siblings, which spend the same outputs
+        let tx = Transaction::new(0, inputs, outputs, position as u32).expect(ERR_CREATE_TX_FAIL);
+        let block = Block::new(
+            vec![tx],
+            Some(parent_block.get_id()),
+            parent_block.block_time() + 1,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL);
+
+        self.blocks.push(block);
+        self.parents.push(parent);
+        position
+    }
+
+    fn position(&self, block_id: &Id<Block>) -> usize {
+        self.blocks
+            .iter()
+            .position(|block| &block.get_id() == block_id)
+            .expect("Block is not in the tree")
+    }
+
+    /// Positions of the blocks from the genesis to the block at `tip`
+    fn chain(&self, tip: usize) -> Vec<usize> {
+        let mut chain = vec![tip];
+        while let Some(&position) = chain.last().filter(|&&position| position != 0) {
+            chain.push(self.parents[position]);
+        }
+        chain.reverse();
+        chain
+    }
+}
+
+/// A tree of blocks and an order in which to process them
+fn gen_tree() -> impl Strategy<Value = (Vec<(usize, usize)>, Vec<usize>)> {
+    vec((any::<Index>(), 1..=MAX_OUTPUTS), 1..=MAX_TREE_SIZE)
+        .prop_map(|nodes| {
+            // the parent of a block comes before it, the genesis being at position 0
+            nodes
+                .into_iter()
+                .enumerate()
+                .map(|(position, (parent, output_count))| {
+                    (parent.index(position + 1), output_count)
+                })
+                .collect::<Vec<_>>()
+        })
+        .prop_flat_map(|nodes| {
+            let order = Just((1..=nodes.len()).collect::<Vec<_>>()).prop_shuffle();
+            (Just(nodes), order)
+        })
+}
+
+fn process(chainstate: &mut Chainstate, block: &Block) {
+    match chainstate.process_block(block.clone(), BlockSource::Local) {
+        Ok(_) | Err(BlockError::LocalOrphan) => {}
+        Err(e) => panic!("Block processing failed: {}", e),
+    }
+}
+
+/// Check the invariants of the chainstate that has processed all the blocks of the tree
+fn check_invariants(chainstate: &Chainstate, tree: &BlockTree) {
+    let best_block_id = chainstate.get_best_block_id().unwrap().expect(ERR_BEST_BLOCK_NOT_FOUND);
+    let best_block_trust =
+        chainstate.get_block_index(&best_block_id).unwrap().unwrap().get_chain_trust();
+
+    // the best chain has the maximum trust
+    for block in &tree.blocks {
+        let block_index = chainstate
+            .get_block_index(&block.get_id())
+            .unwrap()
+            .expect("Processed block has no index");
+        assert!(block_index.get_chain_trust() <= best_block_trust);
+    }
+
+    // the state is the one of the best chain processed from scratch
+    let mut replayed = setup_chainstate();
+    let chain = tree.chain(tree.position(&best_block_id));
+    for &position in &chain[1..] {
+        process(&mut replayed, &tree.blocks[position]);
+    }
+    assert_eq!(
+        chainstate.get_utxo_set_info().unwrap(),
+        replayed.get_utxo_set_info().unwrap()
+    );
+    for height in 0..=chain.len() as u64 {
+        let height = BlockHeight::new(height);
+        assert_eq!(
+            chainstate.get_block_id_from_height(&height).unwrap(),
+            replayed.get_block_id_from_height(&height).unwrap()
+        );
+    }
+    for block in &tree.blocks {
+        let tx_source = OutPointSourceId::from(block.transactions()[0].get_id());
+        assert_eq!(
+            chainstate.blockchain_storage.get_mainchain_tx_index(&tx_source).unwrap(),
+            replayed.blockchain_storage.get_mainchain_tx_index(&tx_source).unwrap()
+        );
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn prop_random_tree((nodes, order) in gen_tree()) {
+        let mut chainstate = setup_chainstate();
+        let mut tree = BlockTree::new(chainstate.chain_config.genesis_block());
+        for (parent, output_count) in nodes {
+            tree.add(parent, output_count);
+        }
+
+        for position in order {
+            process(&mut chainstate, &tree.blocks[position]);
+        }
+        check_invariants(&chainstate, &tree);
+    }
+
+    #[test]
+    fn prop_reorg_there_and_back(
+        main_length in 1..8usize,
+        fork_point in any::<Index>(),
+        output_counts in vec(1..=MAX_OUTPUTS, 32),
+    ) {
+        let mut chainstate = setup_chainstate();
+        let mut tree = BlockTree::new(chainstate.chain_config.genesis_block());
+        let mut output_counts = output_counts.into_iter().cycle();
+        let mut extend =
+            |tree: &mut BlockTree, chainstate: &mut Chainstate, tip: usize, length: usize| {
+                (0..length).fold(tip, |tip, _| {
+                    let position = tree.add(tip, output_counts.next().unwrap());
+                    process(chainstate, &tree.blocks[position]);
+                    position
+                })
+            };
+
+        let main_tip = extend(&mut tree, &mut chainstate, 0, main_length);
+        check_invariants(&chainstate, &tree);
+
+        // a fork one block longer than the main chain becomes the best chain
+        let fork_height = fork_point.index(main_length);
+        let fork_start = tree.chain(main_tip)[fork_height];
+        let fork_length = main_length + 1 - fork_height;
+        let fork_tip = extend(&mut tree, &mut chainstate, fork_start, fork_length);
+        prop_assert_eq!(
+            chainstate.get_best_block_id().unwrap(),
+            Some(tree.blocks[fork_tip].get_id())
+        );
+        check_invariants(&chainstate, &tree);
+
+        // the old main chain overtakes the fork again
+        let main_tip = extend(&mut tree, &mut chainstate, main_tip, 2);
+        prop_assert_eq!(
+            chainstate.get_best_block_id().unwrap(),
+            Some(tree.blocks[main_tip].get_id())
+        );
+        check_invariants(&chainstate, &tree);
+    }
+}
@@ -1 +1,148 @@
 // TODO: add tests that cover signing transactions properly in blocks to spend outputs
+
+use crate::detail::tests::test_framework::BlockTestFramework;
+use crate::detail::tests::*;
+use common::chain::config::ChainConfigBuilder;
+use common::chain::signature::inputsig::StandardInputSignature;
+use common::chain::signature::sighashtype::SigHashType;
+use common::chain::{ConsensusUpgrade, NetUpgrades, OutPointSourceId, UpgradeVersion};
+use crypto::key::{KeyKind, PrivateKey, PublicKey};
+
+#[test]
+fn secp256k1_schnorr_activation() {
+    let upgrades = vec![
+        (
+            BlockHeight::new(0),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+        ),
+        (
+            BlockHeight::new(2),
+            UpgradeVersion::SignatureUpgrade(KeyKind::Secp256k1Schnorr),
+        ),
+    ];
+    let net_upgrades = NetUpgrades::initialize(upgrades).expect("valid netupgrades");
+    let config = ChainConfigBuilder::test_chain().with_net_upgrades(net_upgrades).build();
+    let chainstate = ChainstateBuilder::new().with_config(config).build();
+    let mut btf = BlockTestFramework::with_chainstate(chainstate);
+
+    let (_, public_key) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+    let pay_to_secp256k1_key = |prev_block: &Block| {
+        let prev_tx = &prev_block.transactions()[0];
+        let input = TxInput::new(
+            OutPointSourceId::Transaction(prev_tx.get_id()),
+            0,
+            empty_witness(),
+        );
+        let output = TxOutput::new(
+            prev_tx.get_outputs()[0].get_value(),
+            Destination::PublicKey(public_key.clone()),
+        );
+        Block::new(
+            vec![Transaction::new(0, vec![input], vec![output], 0).expect(ERR_CREATE_TX_FAIL)],
+            Some(Id::new(&prev_block.get_id().get())),
+            time::get() as u32,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL)
+    };
+
+    // Outputs can't be locked to secp256k1 keys below the activation height
+    let block = pay_to_secp256k1_key(btf.genesis());
+    let tx_id = block.transactions()[0].get_id();
+    assert_eq!(
+        btf.add_special_block(block).unwrap_err(),
+        BlockError::KeyKindNotActivated(tx_id)
+    );
+
+    btf.create_chain(&btf.genesis().get_id(), 1).expect("chain creation");
+    let prev_block = btf.get_block(btf.block_indexes[0].get_block_id().clone()).unwrap().unwrap();
+    let block = pay_to_secp256k1_key(&prev_block);
+    assert!(btf.add_special_block(block).is_ok());
+}
+
+#[test]
+fn parallel_signature_verification() {
+    const TX_COUNT: usize = 40;
+    const INVALID_TX: usize = 27;
+
+    let mut btf = BlockTestFramework::new();
+    let (private_key, public_key) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+    let (other_private_key, _) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+
+    // Split the genesis output into outputs locked to the key
+    let genesis_tx = &btf.genesis().transactions()[0];
+    let output_value =
+        Amount::from_atoms(genesis_tx.get_outputs()[0].get_value().into_atoms() / TX_COUNT as u128);
+    let funding_tx = Transaction::new(
+        0,
+        vec![TxInput::new(
+            OutPointSourceId::Transaction(genesis_tx.get_id()),
+            0,
+            empty_witness(),
+        )],
+        vec![TxOutput::new(output_value, Destination::PublicKey(public_key)); TX_COUNT],
+        0,
+    )
+    .expect(ERR_CREATE_TX_FAIL);
+    let funding_tx_id = funding_tx.get_id();
+    let funding_block = Block::new(
+        vec![funding_tx],
+        Some(btf.genesis().get_id()),
+        time::get() as u32,
+        ConsensusData::None,
+    )
+    .expect(ERR_CREATE_BLOCK_FAIL);
+    let funding_block_id = funding_block.get_id();
+    btf.add_special_block(funding_block).expect("funding block");
+
+    let spend = |output_index: usize, private_key: &PrivateKey| {
+        let input = TxInput::new(
+            OutPointSourceId::Transaction(funding_tx_id.clone()),
+            output_index as u32,
+            InputWitness::NoSignature(None),
+        );
+        let output = TxOutput::new(output_value, anyonecanspend_address());
+        let mut tx = Transaction::new(0, vec![input], vec![output], 0).expect(ERR_CREATE_TX_FAIL);
+        let witness = StandardInputSignature::produce_signature_for_input(
+            private_key,
+            SigHashType::try_from(SigHashType::ALL).unwrap(),
+            Destination::PublicKey(PublicKey::from_private_key(private_key)),
+            &tx,
+            0,
+        )
+        .unwrap();
+        tx.update_witness(0, InputWitness::Standard(witness)).unwrap();
+        tx
+    };
+    let spending_block = |transactions| {
+        Block::new(
+            transactions,
+            Some(funding_block_id.clone()),
+            time::get() as u32,
+            ConsensusData::None,
+        )
+        .expect(ERR_CREATE_BLOCK_FAIL)
+    };
+
+    // The invalid signature is found by the parallel verifier and reported for its transaction
+    let transactions: Vec<_> = (0..TX_COUNT)
+        .map(|i| {
+            spend(
+                i,
+                if i == INVALID_TX {
+                    &other_private_key
+                } else {
+                    &private_key
+                },
+            )
+        })
+        .collect();
+    let invalid_tx_id = transactions[INVALID_TX].get_id();
+    assert_eq!(
+        btf.add_special_block(spending_block(transactions)).unwrap_err(),
+        BlockError::SignatureVerificationFailed(invalid_tx_id)
+    );
+
+    let transactions = (0..TX_COUNT).map(|i| spend(i, &private_key)).collect();
+    assert!(btf.add_special_block(spending_block(transactions)).is_ok());
+}
@@ -16,7 +16,7 @@
 // Author(s): A. Altonen
 #![allow(warnings)]
 use crate::detail::tests::{test_framework::BlockTestFramework, *};
-use common::chain::config::TestChainConfig;
+use common::chain::config::ChainConfigBuilder;
 use rand::Rng;
 
 #[test]
@@ -172,7 +172,7 @@ fn test_get_headers_different_chains() {
         // create two chains which both have unique blocks and share some blocks
         // Verify that the first returned header attaches before genesis
         {
-            let config = TestChainConfig::new().build();
+            let config = ChainConfigBuilder::test_chain().build();
             let consensus1 = ChainstateBuilder::new().with_config(config.clone()).build();
             let consensus2 = ChainstateBuilder::new().with_config(config).build();
             let mut btf1 = BlockTestFramework::with_chainstate(consensus1);
@@ -218,7 +218,7 @@ fn test_get_headers_different_chains() {
 fn test_filter_already_existing_blocks() {
     common::concurrency::model(|| {
         {
-            let config = TestChainConfig::new().build();
+            let config = ChainConfigBuilder::test_chain().build();
             let consensus1 = ChainstateBuilder::new().with_config(config.clone()).build();
             let consensus2 = ChainstateBuilder::new().with_config(config).build();
             let mut btf1 = BlockTestFramework::with_chainstate(consensus1);
@@ -266,7 +266,7 @@ fn test_filter_already_existing_blocks() {
 
         // try to offer headers that don't attach to local chain
         {
-            let config = TestChainConfig::new().build();
+            let config = ChainConfigBuilder::test_chain().build();
             let consensus1 = ChainstateBuilder::new().with_config(config.clone()).build();
             let consensus2 = ChainstateBuilder::new().with_config(config).build();
             let mut btf1 = BlockTestFramework::with_chainstate(consensus1);
@@ -0,0 +1,108 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Entry points of the fuzz targets validating blocks and transactions
+//!
+//! Each input is processed by a fresh chainstate of the unit test chain. See [common::fuzz] for
+//! how the targets and their corpora are organized.
+
+use std::sync::Arc;
+
+use common::{
+    chain::{
+        block::{Block, ConsensusData},
+        config::create_unit_test_config,
+        Transaction,
+    },
+    primitives::{BlockHeight, Idable},
+};
+use serialization::Decode;
+
+use crate::{chainstate_interface::ChainstateInterface, make_chainstate, BlockSource};
+
+fn new_chainstate() -> Box<dyn ChainstateInterface> {
+    let storage = blockchain_storage::Store::new_empty().expect("Storage creation failed");
+    make_chainstate(Arc::new(create_unit_test_config()), storage, None)
+        .expect("Chainstate creation failed")
+}
+
+/// Process a block received from a peer
+///
+/// A block that is accepted must extend the genesis, the only block the chainstate has.
+pub fn process_block(data: &[u8]) {
+    let block = match Block::decode(&mut &data[..]) {
+        Ok(block) => block,
+        Err(_) => return,
+    };
+    check_processing(new_chainstate(), block);
+}
+
+/// Validate transactions by processing a block holding them on top of the genesis
+///
+/// There is no mempool to add the transactions to yet, so this target checks the transactions
+/// the way a block including them is checked.
+pub fn connect_transactions(data: &[u8]) {
+    let transactions = match Vec::<Transaction>::decode(&mut &data[..]) {
+        Ok(transactions) => transactions,
+        Err(_) => return,
+    };
+    let chainstate = new_chainstate();
+    let chain_config = chainstate.get_chain_config();
+    let genesis = chain_config.genesis_block();
+    let block = match Block::new(
+        transactions,
+        Some(genesis.get_id()),
+        genesis.block_time(),
+        ConsensusData::None,
+    ) {
+        Ok(block) => block,
+        Err(_) => return,
+    };
+    check_processing(chainstate, block);
+}
+
+fn check_processing(mut chainstate: Box<dyn ChainstateInterface>, block: Block) {
+    let block_id = block.get_id();
+    if chainstate.process_block(block, BlockSource::Peer).is_err() {
+        return;
+    }
+
+    let best_block_id = chainstate.get_best_block_id().expect("Best block ID lookup failed");
+    if best_block_id != chainstate.get_chain_config().genesis_block_id() {
+        assert_eq!(best_block_id, block_id);
+        assert_eq!(
+            chainstate.get_best_block_height().expect("Best block height lookup failed"),
+            BlockHeight::new(1)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::fuzz::corpus;
+
+    #[test]
+    fn block_corpus() {
+        corpus("process_block").iter().for_each(|input| process_block(input));
+    }
+
+    #[test]
+    fn transaction_corpus() {
+        corpus("connect_transactions")
+            .iter()
+            .for_each(|input| connect_transactions(input));
+    }
+}
@@ -17,6 +17,10 @@
 
 mod detail;
 
+pub mod bootstrap;
+
+pub mod fuzz;
+
 pub mod rpc;
 
 pub mod chainstate_interface_impl;
@@ -25,21 +29,23 @@ pub mod chainstate_interface;
 
 use std::sync::Arc;
 
+use blockchain_storage::BlockchainStorage;
+use chainstate_interface::ChainstateInterface;
 pub use chainstate_interface_impl::ChainstateInterfaceImpl;
 use common::{
     chain::{block::Block, ChainConfig},
     primitives::{BlockHeight, Id},
 };
-use chainstate_interface::ChainstateInterface;
 pub use detail::BlockError;
-pub use detail::{BlockSource, Chainstate};
+pub use detail::{BlockSource, Chainstate, UtxoSetInfo};
 
 #[derive(Debug, Clone)]
 pub enum ChainstateEvent {
     NewTip(Id<Block>, BlockHeight),
 }
 
-#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[derive(thiserror::Error, Debug, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
 pub enum ChainstateError {
     #[error("Initialization error")]
     FailedToInitializeChainstate(String),
@@ -47,15 +53,17 @@ pub enum ChainstateError {
     ProcessBlockError(BlockError),
     #[error("Property read error: `{0}`")]
     FailedToReadProperty(BlockError),
+    #[error("Storage backup failed: `{0}`")]
+    StorageBackupFailed(String),
 }
 
 impl subsystem::Subsystem for Box<dyn ChainstateInterface> {}
 
-type ChainstateHandle = subsystem::Handle<Box<dyn ChainstateInterface>>;
+pub type ChainstateHandle = subsystem::Handle<Box<dyn ChainstateInterface>>;
 
-pub fn make_chainstate(
+pub fn make_chainstate<S: BlockchainStorage + Send + 'static>(
     chain_config: Arc<ChainConfig>,
-    blockchain_storage: blockchain_storage::Store,
+    blockchain_storage: S,
     custom_orphan_error_hook: Option<Arc<detail::OrphanErrorHandler>>,
 ) -> Result<Box<dyn ChainstateInterface>, ChainstateError> {
     let cons = Chainstate::new(chain_config, blockchain_storage, custom_orphan_error_hook)?;
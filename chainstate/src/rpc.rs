@@ -1,70 +1,1300 @@
 //! Chainstate subsystem RPC handler
 
-use crate::ChainstateError;
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{BlockError, ChainstateError, ChainstateEvent, UtxoSetInfo};
 
 use crate::{Block, BlockSource};
-use common::primitives::BlockHeight;
-use serialization::Decode;
+use common::{
+    address::{Address, AddressError},
+    chain::{
+        block::{calculate_tx_merkle_proof, ConsensusData},
+        config::ChainType,
+        ChainConfig, ConsensusUpgrade, Destination, OutPoint, OutPointSourceId, Transaction,
+        TxInput, TxOutput, UpgradeVersion,
+    },
+    primitives::{BlockHeight, Id, Idable, H256},
+};
+use crypto::key::KeyKind;
+use jsonrpsee::types::{error::CALL_EXECUTION_FAILED_CODE, ErrorObject};
+use serialization::{DecodeAll, Encode};
 use subsystem::subsystem::CallError;
 
 type BlockId = common::primitives::Id<common::chain::block::Block>;
 
+/// How long a long-polling `block_template` call waits for a new tip at most
+const LONGPOLL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a long-polling `block_template` call checks for a new tip
+const LONGPOLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Error codes of the chainstate RPC errors, see [`rpc::error_code`] for the code space
+///
+/// The errors carry [`rpc::ErrorData`] with the name of the [`BlockError`] or
+/// [`ChainstateError`] variant, or `invalid_encoding` for the decoding failures.
+pub mod error_code {
+    /// The block couldn't be decoded from the hex-encoded SCALE encoding
+    pub const BLOCK_DECODE_FAILED: i32 = -1001;
+    /// The block is already known
+    pub const BLOCK_DUPLICATE: i32 = -1002;
+    /// The previous block is unknown, the block is kept as an orphan
+    pub const BLOCK_ORPHAN: i32 = -1003;
+    /// The block failed validation
+    pub const BLOCK_INVALID: i32 = -1004;
+    /// The block couldn't be processed because of an internal error, such as a storage failure
+    pub const BLOCK_INTERNAL_ERROR: i32 = -1005;
+    /// Chainstate data couldn't be read, such as because of a storage failure
+    pub const READ_FAILED: i32 = -1006;
+    /// The transaction couldn't be decoded from the hex-encoded SCALE encoding
+    pub const TX_DECODE_FAILED: i32 = -1007;
+    /// An output destination couldn't be rendered as an address
+    pub const ADDRESS_ENCODING_FAILED: i32 = -1008;
+    /// The storage backup couldn't be written
+    pub const BACKUP_FAILED: i32 = -1009;
+    /// The blockchain storage is corrupted and has to be restored or reindexed
+    pub const STORAGE_CORRUPTED: i32 = -1010;
+    /// Blocks can only be generated on regtest
+    pub const GENERATE_NOT_ALLOWED: i32 = -1011;
+}
+
+/// Kind reported with the decoding failures
+const INVALID_ENCODING: &str = "invalid_encoding";
+
+/// Kind reported when generating blocks on a chain other than regtest
+const GENERATE_NOT_ALLOWED: &str = "generate_not_allowed";
+
+/// Kind reported when the storage backup couldn't be written
+const STORAGE_BACKUP_FAILED: &str = "storage_backup_failed";
+
+/// Maximum number of blocks mined by one `generate` or `generate_to_address` call
+pub const MAX_GENERATE_BLOCKS: u32 = 1000;
+
+/// Block returned by the `block` method, depending on the requested verbosity
+#[derive(Debug, serde::Serialize)]
+#[serde(untagged)]
+pub enum BlockResponse {
+    /// Hex-encoded SCALE encoding of the block
+    Hex(String),
+    /// Decoded block
+    Decoded(BlockInfo),
+}
+
+/// Decoded block
+#[derive(Debug, serde::Serialize)]
+pub struct BlockInfo {
+    id: BlockId,
+    /// Height of the block, `None` if the block is not in the main chain
+    height: Option<BlockHeight>,
+    /// Number of main chain blocks starting from this block, 0 if not in the main chain
+    confirmations: u64,
+    prev_block_id: Option<BlockId>,
+    version: u32,
+    timestamp: u32,
+    tx_merkle_root: Option<H256>,
+    witness_merkle_root: Option<H256>,
+    /// Compact target of the block, `None` for blocks without proof of work
+    bits: Option<u32>,
+    /// Size of the encoded block in bytes
+    size: usize,
+    transactions: Vec<Id<Transaction>>,
+}
+
+impl BlockInfo {
+    fn new(block: Block, height: Option<BlockHeight>, best_height: BlockHeight) -> Self {
+        let confirmations = height.map_or(0, |height| {
+            u64::from(best_height).saturating_sub(u64::from(height)) + 1
+        });
+        Self {
+            id: block.get_id(),
+            height,
+            confirmations,
+            prev_block_id: block.prev_block_id(),
+            version: block.version(),
+            timestamp: block.block_time(),
+            tx_merkle_root: block.merkle_root(),
+            witness_merkle_root: block.witness_merkle_root(),
+            bits: pow_bits(&block),
+            size: block.encoded_size(),
+            transactions: block.transactions().iter().map(|tx| tx.get_id()).collect(),
+        }
+    }
+}
+
+/// Block decoded by the `decode_block` method, without any chain context
+#[derive(Debug, serde::Serialize)]
+pub struct DecodedBlock {
+    id: BlockId,
+    prev_block_id: Option<BlockId>,
+    version: u32,
+    timestamp: u32,
+    tx_merkle_root: Option<H256>,
+    witness_merkle_root: Option<H256>,
+    /// Compact target of the block, `None` for blocks without proof of work
+    bits: Option<u32>,
+    /// Outputs paying the block reward
+    reward_outputs: Vec<OutputInfo>,
+    /// Size of the encoded block in bytes
+    size: usize,
+    transactions: Vec<DecodedTransaction>,
+}
+
+impl DecodedBlock {
+    fn new(chain_config: &ChainConfig, block: &Block) -> rpc::Result<Self> {
+        let reward_outputs = block
+            .header()
+            .block_reward_destinations()
+            .unwrap_or_default()
+            .iter()
+            .map(|output| OutputInfo::new(chain_config, output))
+            .collect::<rpc::Result<_>>()?;
+        let transactions = block
+            .transactions()
+            .iter()
+            .map(|tx| DecodedTransaction::new(chain_config, tx))
+            .collect::<rpc::Result<_>>()?;
+
+        Ok(Self {
+            id: block.get_id(),
+            prev_block_id: block.prev_block_id(),
+            version: block.version(),
+            timestamp: block.block_time(),
+            tx_merkle_root: block.merkle_root(),
+            witness_merkle_root: block.witness_merkle_root(),
+            bits: pow_bits(block),
+            reward_outputs,
+            size: block.encoded_size(),
+            transactions,
+        })
+    }
+}
+
+/// Notification sent to `subscribe_new_block` subscribers when the best block changes
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NewBlock {
+    id: BlockId,
+    height: BlockHeight,
+}
+
+/// Warning reported while the node is in initial block download
+const INITIAL_BLOCK_DOWNLOAD_WARNING: &str =
+    "The node is still downloading the blockchain, its best block may be out of date";
+
+/// Chainstate summary returned by the `info` method
+#[derive(Debug, serde::Serialize)]
+pub struct ChainstateInfo {
+    best_block_id: BlockId,
+    best_block_height: BlockHeight,
+    best_block_timestamp: u32,
+    /// Whether the node is still catching up with the rest of the network
+    initial_block_download: bool,
+    /// Conditions the results of the other methods should be read with
+    warnings: Vec<String>,
+}
+
+/// Block template returned by the `block_template` method
+#[derive(Debug, serde::Serialize)]
+pub struct BlockTemplate {
+    /// Hex-encoded SCALE encoding of the block, to be completed with a valid nonce if
+    /// proof of work is required
+    block: String,
+    /// ID of the block the template builds on, to be passed back as `longpoll_id`
+    prev_block_id: BlockId,
+    height: BlockHeight,
+    /// Compact target the block ID must meet, `None` if no proof of work is required
+    bits: Option<u32>,
+    /// Conditions the template should be mined with, such as the node still catching up
+    warnings: Vec<String>,
+}
+
+/// Net upgrade returned by the `net_upgrades` method
+#[derive(Debug, serde::Serialize)]
+pub struct NetUpgradeInfo {
+    /// Height of the first block the upgrade applies to
+    height: BlockHeight,
+    /// Whether the upgrade applies to the next block, later upgrades of the same type replace it
+    active: bool,
+    #[serde(flatten)]
+    upgrade: UpgradeInfo,
+}
+
+/// Rules introduced by a net upgrade
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UpgradeInfo {
+    Consensus {
+        /// Named as in the chain configuration file
+        consensus: &'static str,
+        /// Hex-encoded compact initial difficulty of PoW upgrades
+        initial_difficulty: Option<String>,
+    },
+    Script {
+        enforce_minimal_push: bool,
+        enforce_minimal_if: bool,
+    },
+    Signature {
+        /// Kind of keys activated by the upgrade
+        key_kind: &'static str,
+    },
+    /// Transactions can't duplicate the ID of a main chain transaction with unspent outputs
+    DuplicateTx,
+    /// Transactions other than the genesis ones must have inputs
+    InputlessTx,
+    /// Block rewards can't pay more than the subsidy plus the transaction fees
+    BlockReward,
+    /// Outputs can be timelocked
+    TimeLock,
+    Other,
+}
+
+/// Transaction returned by the `transaction` method, depending on the requested verbosity
+#[derive(Debug, serde::Serialize)]
+#[serde(untagged)]
+pub enum TransactionResponse {
+    /// Hex-encoded SCALE encoding of the transaction
+    Hex(String),
+    /// Decoded transaction
+    Decoded(TransactionInfo),
+}
+
+/// Decoded main chain transaction
+#[derive(Debug, serde::Serialize)]
+pub struct TransactionInfo {
+    #[serde(flatten)]
+    transaction: DecodedTransaction,
+    /// ID of the block containing the transaction
+    block_id: BlockId,
+    /// Number of main chain blocks starting from the one containing the transaction
+    confirmations: u64,
+}
+
+/// Proof returned by the `merkle_proof` method
+#[derive(Debug, serde::Serialize)]
+pub struct MerkleProofInfo {
+    /// ID of the block containing the transaction
+    block_id: BlockId,
+    /// Position of the transaction in the block
+    tx_index: usize,
+    /// Transaction merkle root of the block
+    merkle_root: H256,
+    /// Hashes of the merkle tree nodes next to the path from the transaction ID to the root,
+    /// starting from the bottom; at each level the transaction side is given by the bits of
+    /// `tx_index`, starting from the least significant one
+    siblings: Vec<H256>,
+}
+
+/// Decoded transaction, without any chain context
+#[derive(Debug, serde::Serialize)]
+pub struct DecodedTransaction {
+    id: Id<Transaction>,
+    version: u8,
+    flags: u32,
+    lock_time: u32,
+    inputs: Vec<InputInfo>,
+    outputs: Vec<OutputInfo>,
+    /// Size of the encoded transaction in bytes
+    size: usize,
+}
+
+/// Decoded transaction input
+#[derive(Debug, serde::Serialize)]
+pub struct InputInfo {
+    /// Either `transaction` or `block_reward`
+    source: &'static str,
+    /// ID of the transaction or block whose output is spent
+    source_id: H256,
+    index: u32,
+    /// Hex-encoded SCALE encoding of the input witness
+    witness: String,
+}
+
+/// Decoded transaction output
+#[derive(Debug, serde::Serialize)]
+pub struct OutputInfo {
+    /// Value in atoms, as a string since it may not fit a JSON number
+    value: String,
+    destination: DestinationInfo,
+}
+
+/// Output destination, with public keys and their hashes rendered as addresses
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum DestinationInfo {
+    Address(String),
+    ScriptHash(H256),
+    AnyoneCanSpend,
+}
+
+/// Unspent output returned by the `utxo` method
+#[derive(Debug, serde::Serialize)]
+pub struct UtxoInfo {
+    /// ID of the block containing the output
+    block_id: BlockId,
+    /// Number of main chain blocks starting from the one containing the output
+    confirmations: u64,
+    /// Whether the output is a block reward
+    block_reward: bool,
+    #[serde(flatten)]
+    output: OutputInfo,
+}
+
+/// Statistics of the unspent outputs returned by the `utxo_set_info` method
+#[derive(Debug, serde::Serialize)]
+pub struct UtxoSetStats {
+    /// Best block the statistics were computed at
+    best_block_id: BlockId,
+    height: BlockHeight,
+    /// Number of unspent outputs
+    utxo_count: u64,
+    /// Sum of the values of the unspent outputs in atoms, as a string since it may not fit
+    /// a JSON number
+    total_amount: String,
+    /// Size of the SCALE-encoded outpoints and outputs in bytes
+    serialized_size: u64,
+    /// Hash of the SCALE-encoded outpoints and outputs, in main chain order
+    utxo_set_hash: H256,
+}
+
+impl From<UtxoSetInfo> for UtxoSetStats {
+    fn from(info: UtxoSetInfo) -> Self {
+        Self {
+            best_block_id: info.best_block_id,
+            height: info.height,
+            utxo_count: info.utxo_count,
+            total_amount: info.total_amount.into_atoms().to_string(),
+            serialized_size: info.serialized_size,
+            utxo_set_hash: info.utxo_set_hash,
+        }
+    }
+}
+
+/// Size of the blockchain storage returned by the `storage_statistics` method
+///
+/// The sizes are the total size of the keys and values stored in each column, the size of the
+/// storage on disk differs by the storage overhead.
+#[derive(Debug, serde::Serialize)]
+pub struct StorageStatistics {
+    blocks: ColumnStatistics,
+    block_index: ColumnStatistics,
+    block_by_height: ColumnStatistics,
+    tx_index: ColumnStatistics,
+    utxo: ColumnStatistics,
+    undo: ColumnStatistics,
+    /// Positions of the blocks moved to the block file, the size of the file is not included
+    block_file_index: ColumnStatistics,
+    /// Approximate size of all the columns in bytes
+    total_bytes: u64,
+}
+
+/// Size of a storage column
+#[derive(Debug, serde::Serialize)]
+pub struct ColumnStatistics {
+    /// Number of entries in the column
+    entries: u64,
+    /// Approximate size of the column in bytes
+    bytes: u64,
+}
+
+impl From<blockchain_storage::ColumnStats> for ColumnStatistics {
+    fn from(stats: blockchain_storage::ColumnStats) -> Self {
+        Self {
+            entries: stats.entries as u64,
+            bytes: stats.bytes as u64,
+        }
+    }
+}
+
+impl From<blockchain_storage::Statistics> for StorageStatistics {
+    fn from(stats: blockchain_storage::Statistics) -> Self {
+        Self {
+            blocks: stats.blocks.into(),
+            block_index: stats.block_index.into(),
+            block_by_height: stats.block_by_height.into(),
+            tx_index: stats.tx_index.into(),
+            utxo: stats.utxo.into(),
+            undo: stats.undo.into(),
+            block_file_index: stats.block_file_index.into(),
+            total_bytes: stats.total_bytes() as u64,
+        }
+    }
+}
+
+impl TransactionInfo {
+    fn new(
+        chain_config: &ChainConfig,
+        tx: Transaction,
+        block_id: BlockId,
+        confirmations: u64,
+    ) -> rpc::Result<Self> {
+        Ok(Self {
+            transaction: DecodedTransaction::new(chain_config, &tx)?,
+            block_id,
+            confirmations,
+        })
+    }
+}
+
+impl NetUpgradeInfo {
+    fn new(height: BlockHeight, upgrade: &UpgradeVersion, active: bool) -> Self {
+        let upgrade = match upgrade {
+            UpgradeVersion::ConsensusUpgrade(consensus) => {
+                let (consensus, initial_difficulty) = match consensus {
+                    ConsensusUpgrade::PoW { initial_difficulty } => {
+                        ("pow", Some(format!("{:x}", initial_difficulty.0)))
+                    }
+                    ConsensusUpgrade::PoS => ("pos", None),
+                    ConsensusUpgrade::DSA => ("dsa", None),
+                    ConsensusUpgrade::IgnoreConsensus => ("ignore-consensus", None),
+                };
+                UpgradeInfo::Consensus {
+                    consensus,
+                    initial_difficulty,
+                }
+            }
+            UpgradeVersion::ScriptUpgrade(flags) => UpgradeInfo::Script {
+                enforce_minimal_push: flags.enforce_minimal_push,
+                enforce_minimal_if: flags.enforce_minimal_if,
+            },
+            UpgradeVersion::SignatureUpgrade(kind) => UpgradeInfo::Signature {
+                key_kind: match kind {
+                    KeyKind::RistrettoSchnorr => "ristretto-schnorr",
+                    KeyKind::Secp256k1Schnorr => "secp256k1-schnorr",
+                },
+            },
+            UpgradeVersion::DuplicateTxUpgrade => UpgradeInfo::DuplicateTx,
+            UpgradeVersion::InputlessTxUpgrade => UpgradeInfo::InputlessTx,
+            UpgradeVersion::BlockRewardUpgrade => UpgradeInfo::BlockReward,
+            UpgradeVersion::TimeLockUpgrade => UpgradeInfo::TimeLock,
+            UpgradeVersion::SomeUpgrade => UpgradeInfo::Other,
+        };
+        Self {
+            height,
+            active,
+            upgrade,
+        }
+    }
+}
+
+impl MerkleProofInfo {
+    /// Prove that the transaction is in the block the transaction index points to
+    ///
+    /// The transaction missing from the block means the storage is corrupted.
+    fn new(block_id: BlockId, block: &Block, tx_id: &Id<Transaction>) -> Result<Self, BlockError> {
+        let tx_index = block
+            .transactions()
+            .iter()
+            .position(|tx| tx.get_id() == *tx_id)
+            .ok_or(BlockError::StorageCorrupted)?;
+        let proof = calculate_tx_merkle_proof(block.transactions(), tx_index)
+            .map_err(|_| BlockError::StorageCorrupted)?;
+        let merkle_root = block.merkle_root().ok_or(BlockError::StorageCorrupted)?;
+        Ok(Self {
+            block_id,
+            tx_index,
+            merkle_root,
+            siblings: proof.siblings().to_vec(),
+        })
+    }
+}
+
+impl DecodedTransaction {
+    fn new(chain_config: &ChainConfig, tx: &Transaction) -> rpc::Result<Self> {
+        let outputs = tx
+            .get_outputs()
+            .iter()
+            .map(|output| OutputInfo::new(chain_config, output))
+            .collect::<rpc::Result<_>>()?;
+
+        Ok(Self {
+            id: tx.get_id(),
+            version: tx.version_byte(),
+            flags: tx.get_flags(),
+            lock_time: tx.get_lock_time(),
+            inputs: tx.get_inputs().iter().map(InputInfo::new).collect(),
+            outputs,
+            size: tx.encoded_size(),
+        })
+    }
+}
+
+impl InputInfo {
+    fn new(input: &TxInput) -> Self {
+        let outpoint = input.get_outpoint();
+        let (source, source_id) = match outpoint.get_tx_id() {
+            OutPointSourceId::Transaction(id) => ("transaction", id.get()),
+            OutPointSourceId::BlockReward(id) => ("block_reward", id.get()),
+        };
+
+        Self {
+            source,
+            source_id,
+            index: outpoint.get_output_index(),
+            witness: hex::encode(input.get_witness().encode()),
+        }
+    }
+}
+
+impl OutputInfo {
+    fn new(chain_config: &ChainConfig, output: &TxOutput) -> rpc::Result<Self> {
+        let address = match output.get_destination() {
+            destination @ (Destination::Address(_)
+            | Destination::PublicKey(_)
+            | Destination::ClassicMultisig(_)) => {
+                Address::from_destination(chain_config, destination)
+            }
+            Destination::ScriptHash(id) => {
+                return Ok(Self::with_destination(
+                    output,
+                    DestinationInfo::ScriptHash(id.get()),
+                ))
+            }
+            Destination::AnyoneCanSpend => {
+                return Ok(Self::with_destination(
+                    output,
+                    DestinationInfo::AnyoneCanSpend,
+                ))
+            }
+        }
+        .map_err(|e| {
+            let kind = match e {
+                AddressError::Bech32EncodingError(_) => "bech32_encoding_error",
+                AddressError::InvalidPrefix(_) => "invalid_prefix",
+                AddressError::InvalidDestination => "invalid_destination",
+            };
+            let message = format!("Failed to encode address: {:?}", e);
+            rpc::error(error_code::ADDRESS_ENCODING_FAILED, message, kind)
+        })?;
+
+        Ok(Self::with_destination(
+            output,
+            DestinationInfo::Address(address.get().to_owned()),
+        ))
+    }
+
+    fn with_destination(output: &TxOutput, destination: DestinationInfo) -> Self {
+        Self {
+            value: output.get_value().into_atoms().to_string(),
+            destination,
+        }
+    }
+}
+
 #[rpc::rpc(server, namespace = "chainstate")]
 trait ChainstateRpc {
     /// Get the best block ID
     #[method(name = "best_block_id")]
     async fn best_block_id(&self) -> rpc::Result<BlockId>;
 
+    /// Get the height of the best block, i.e. the number of blocks on top of genesis
+    #[method(name = "best_block_height")]
+    async fn best_block_height(&self) -> rpc::Result<BlockHeight>;
+
+    /// Get the best block and whether the node is still in initial block download
+    #[method(name = "info")]
+    async fn info(&self) -> rpc::Result<ChainstateInfo>;
+
     /// Get block ID at given height in the mainchain
     #[method(name = "block_id_at_height")]
     async fn block_id_at_height(&self, height: BlockHeight) -> rpc::Result<Option<BlockId>>;
 
-    /// Submit a block to be included in the chain
+    /// Get a block by its ID
+    ///
+    /// With verbosity 0 the block is returned hex-encoded, with verbosity 1 it's decoded.
+    #[method(name = "block", resources("expensive" = 1))]
+    async fn block(&self, block_id: BlockId, verbosity: u8) -> rpc::Result<Option<BlockResponse>>;
+
+    /// Get a main chain transaction by its ID
+    ///
+    /// With verbosity 0 the transaction is returned hex-encoded, with verbosity 1 it's decoded.
+    #[method(name = "transaction", resources("expensive" = 1))]
+    async fn transaction(
+        &self,
+        tx_id: Id<Transaction>,
+        verbosity: u8,
+    ) -> rpc::Result<Option<TransactionResponse>>;
+
+    /// Get a proof that a main chain transaction is committed to by the merkle root of its block
+    #[method(name = "merkle_proof", resources("expensive" = 1))]
+    async fn merkle_proof(&self, tx_id: Id<Transaction>) -> rpc::Result<Option<MerkleProofInfo>>;
+
+    /// Get an unspent main chain output
+    ///
+    /// The output is identified by the ID of the transaction that created it, or of the block
+    /// if `block_reward` is set, and its index. Returns `None` if the output is spent or unknown.
+    #[method(name = "utxo")]
+    async fn utxo(
+        &self,
+        source_id: H256,
+        index: u32,
+        block_reward: Option<bool>,
+    ) -> rpc::Result<Option<UtxoInfo>>;
+
+    /// Get statistics of the unspent main chain outputs
+    ///
+    /// All main chain blocks are read, so this may take a while.
+    #[method(name = "utxo_set_info", resources("expensive" = 1))]
+    async fn utxo_set_info(&self) -> rpc::Result<UtxoSetStats>;
+
+    /// Decode a hex-encoded transaction
+    ///
+    /// The transaction is only decoded, it's neither validated nor looked up in the chain.
+    #[method(name = "decode_transaction")]
+    async fn decode_transaction(&self, tx_hex: String) -> rpc::Result<DecodedTransaction>;
+
+    /// Decode a hex-encoded block
+    ///
+    /// The block is only decoded, it's neither validated nor looked up in the chain.
+    #[method(name = "decode_block", resources("expensive" = 1))]
+    async fn decode_block(&self, block_hex: String) -> rpc::Result<DecodedBlock>;
+
+    /// Get the difficulty of the best block as a multiple of the lowest possible difficulty
+    #[method(name = "difficulty")]
+    async fn difficulty(&self) -> rpc::Result<f64>;
+
+    /// Get a block on top of the best block for mining, rewarding the given address
+    ///
+    /// If `longpoll_id` is the `prev_block_id` of the current template, the call waits for a new
+    /// tip, or a minute at most, before returning a new template.
+    #[method(name = "block_template")]
+    async fn block_template(
+        &self,
+        reward_address: String,
+        longpoll_id: Option<BlockId>,
+    ) -> rpc::Result<BlockTemplate>;
+
+    /// Submit a hex-encoded block to be included in the chain
+    ///
+    /// Rejected blocks are reported with one of the codes in [`error_code`].
     #[method(name = "submit_block")]
     async fn submit_block(&self, block_hex: String) -> rpc::Result<()>;
 
+    /// Mine `count` blocks on top of the best block at once, paying the block rewards to the
+    /// hex-encoded SCALE encoding of a destination, or to anyone if none is given
+    ///
+    /// Only allowed on regtest, where the difficulty is low enough to mine on the spot. Returns
+    /// the IDs of the blocks once they are all in the chain. At most [`MAX_GENERATE_BLOCKS`]
+    /// blocks are mined at once. If mining fails partway, the blocks mined before stay in the
+    /// chain and their IDs are listed in the error message.
+    #[method(name = "generate")]
+    async fn generate(&self, count: u32, destination: Option<String>) -> rpc::Result<Vec<BlockId>>;
+
+    /// Mine `count` blocks like `generate`, paying the block rewards to the address
+    #[method(name = "generate_to_address")]
+    async fn generate_to_address(&self, count: u32, address: String) -> rpc::Result<Vec<BlockId>>;
+
+    /// Get the net upgrade schedule of the chain, ordered by activation height
+    #[method(name = "net_upgrades")]
+    async fn net_upgrades(&self) -> rpc::Result<Vec<NetUpgradeInfo>>;
+
     /// Get block height in main chain
     #[method(name = "block_height_in_main_chain")]
     async fn block_height_in_main_chain(
         &self,
         block_id: BlockId,
     ) -> rpc::Result<Option<BlockHeight>>;
+
+    /// Write a consistent copy of the blockchain storage to a new file called `name` in the
+    /// backup directory of the node
+    ///
+    /// Existing files are never overwritten and the call fails if the node has no backup
+    /// directory configured. The node keeps running while the copy is written, other chainstate
+    /// calls wait until it's done. The copy can be used as the journal file of the journal
+    /// storage backend.
+    #[method(name = "backup_storage")]
+    async fn backup_storage(&self, name: String) -> rpc::Result<()>;
+
+    /// Get the number of entries and approximate size of the blockchain storage columns
+    #[method(name = "storage_statistics")]
+    async fn storage_statistics(&self) -> rpc::Result<StorageStatistics>;
+
+    /// Subscribe to new best blocks, only available over WebSocket
+    #[subscription(name = "subscribe_new_block", item = NewBlock)]
+    fn subscribe_new_block(&self);
+}
+
+/// Chainstate subsystem RPC handler
+pub struct ChainstateRpc {
+    handle: super::ChainstateHandle,
+    backup_dir: Option<PathBuf>,
+}
+
+impl ChainstateRpc {
+    /// Storage backups are written to `backup_dir`, the `backup_storage` method fails without it
+    pub fn new(handle: super::ChainstateHandle, backup_dir: Option<PathBuf>) -> Self {
+        Self { handle, backup_dir }
+    }
+
+    /// Path of a new backup file called `name` in the backup directory
+    fn backup_path(&self, name: &str) -> rpc::Result<PathBuf> {
+        let backup_dir = self.backup_dir.as_ref().ok_or_else(|| {
+            rpc::error(
+                error_code::BACKUP_FAILED,
+                "No backup directory is configured",
+                STORAGE_BACKUP_FAILED,
+            )
+        })?;
+        let mut components = Path::new(name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => (),
+            _ => {
+                return Err(rpc::invalid_params(
+                    "Expected a file name without a directory",
+                ))
+            }
+        }
+        let path = backup_dir.join(name);
+        if path.symlink_metadata().is_ok() {
+            return Err(rpc::error(
+                error_code::BACKUP_FAILED,
+                format!("{} already exists", path.display()),
+                STORAGE_BACKUP_FAILED,
+            ));
+        }
+        Ok(path)
+    }
 }
 
 #[async_trait::async_trait]
-impl ChainstateRpcServer for super::ChainstateHandle {
+impl ChainstateRpcServer for ChainstateRpc {
     async fn best_block_id(&self) -> rpc::Result<BlockId> {
-        handle_error(self.call(|this| this.get_best_block_id()).await)
+        handle_error(self.handle.call(|this| this.get_best_block_id()).await)
+    }
+
+    async fn best_block_height(&self) -> rpc::Result<BlockHeight> {
+        handle_error(self.handle.call(|this| this.get_best_block_height()).await)
+    }
+
+    async fn info(&self) -> rpc::Result<ChainstateInfo> {
+        let res = self
+            .handle
+            .call(|this| {
+                let best_block_id = this.get_best_block_id()?;
+                // The best block missing from the storage means the storage is corrupted
+                let best_block = this.get_block(best_block_id.clone())?.ok_or(
+                    ChainstateError::FailedToReadProperty(BlockError::StorageCorrupted),
+                )?;
+                Ok((
+                    best_block_id,
+                    this.get_best_block_height()?,
+                    best_block.block_time(),
+                    this.is_initial_block_download(),
+                ))
+            })
+            .await;
+        let (best_block_id, best_block_height, best_block_timestamp, initial_block_download) =
+            handle_error(res)?;
+
+        Ok(ChainstateInfo {
+            best_block_id,
+            best_block_height,
+            best_block_timestamp,
+            initial_block_download,
+            warnings: warnings(initial_block_download),
+        })
     }
 
     async fn block_id_at_height(&self, height: BlockHeight) -> rpc::Result<Option<BlockId>> {
-        handle_error(self.call(move |this| this.get_block_id_from_height(&height)).await)
+        handle_error(self.handle.call(move |this| this.get_block_id_from_height(&height)).await)
+    }
+
+    async fn block(&self, block_id: BlockId, verbosity: u8) -> rpc::Result<Option<BlockResponse>> {
+        if verbosity > 1 {
+            return Err(rpc::invalid_params(format!(
+                "Invalid verbosity: {}",
+                verbosity
+            )));
+        }
+
+        let res = self
+            .handle
+            .call(move |this| {
+                let block = match this.get_block(block_id.clone())? {
+                    Some(block) => block,
+                    None => return Ok(None),
+                };
+                let height = this.get_block_height_in_main_chain(&block_id)?;
+                let best_height = this.get_best_block_height()?;
+                Ok(Some((block, height, best_height)))
+            })
+            .await;
+
+        Ok(
+            handle_error(res)?.map(|(block, height, best_height)| match verbosity {
+                0 => BlockResponse::Hex(hex::encode(block.encode())),
+                _ => BlockResponse::Decoded(BlockInfo::new(block, height, best_height)),
+            }),
+        )
+    }
+
+    async fn transaction(
+        &self,
+        tx_id: Id<Transaction>,
+        verbosity: u8,
+    ) -> rpc::Result<Option<TransactionResponse>> {
+        if verbosity > 1 {
+            return Err(rpc::invalid_params(format!(
+                "Invalid verbosity: {}",
+                verbosity
+            )));
+        }
+
+        let res = self
+            .handle
+            .call(move |this| {
+                let (tx, block_id) = match this.get_mainchain_tx(&tx_id)? {
+                    Some(found) => found,
+                    None => return Ok(None),
+                };
+                // The index pointing to a block outside of the main chain means the storage is
+                // corrupted
+                let height = this.get_block_height_in_main_chain(&block_id)?.ok_or(
+                    ChainstateError::FailedToReadProperty(BlockError::StorageCorrupted),
+                )?;
+                let best_height = this.get_best_block_height()?;
+                let confirmations = u64::from(best_height).saturating_sub(u64::from(height)) + 1;
+                Ok(Some((tx, block_id, confirmations, this.get_chain_config())))
+            })
+            .await;
+
+        handle_error(res)?
+            .map(
+                |(tx, block_id, confirmations, chain_config)| match verbosity {
+                    0 => Ok(TransactionResponse::Hex(hex::encode(tx.encode()))),
+                    _ => TransactionInfo::new(&chain_config, tx, block_id, confirmations)
+                        .map(TransactionResponse::Decoded),
+                },
+            )
+            .transpose()
+    }
+
+    async fn merkle_proof(&self, tx_id: Id<Transaction>) -> rpc::Result<Option<MerkleProofInfo>> {
+        let res = self
+            .handle
+            .call(move |this| {
+                let block_id = match this.get_mainchain_tx(&tx_id)? {
+                    Some((_, block_id)) => block_id,
+                    None => return Ok(None),
+                };
+                let block = this.get_block(block_id.clone())?.ok_or(
+                    ChainstateError::FailedToReadProperty(BlockError::StorageCorrupted),
+                )?;
+                MerkleProofInfo::new(block_id, &block, &tx_id)
+                    .map(Some)
+                    .map_err(ChainstateError::FailedToReadProperty)
+            })
+            .await;
+        handle_error(res)
+    }
+
+    async fn utxo(
+        &self,
+        source_id: H256,
+        index: u32,
+        block_reward: Option<bool>,
+    ) -> rpc::Result<Option<UtxoInfo>> {
+        let block_reward = block_reward.unwrap_or(false);
+        let source = if block_reward {
+            OutPointSourceId::BlockReward(Id::new(&source_id))
+        } else {
+            OutPointSourceId::Transaction(Id::new(&source_id))
+        };
+        let outpoint = OutPoint::new(source, index);
+
+        let res = self
+            .handle
+            .call(move |this| {
+                let (output, block_id) = match this.get_utxo(&outpoint)? {
+                    Some(found) => found,
+                    None => return Ok(None),
+                };
+                // The UTXO set pointing to a block outside of the main chain means the storage
+                // is corrupted
+                let height = this.get_block_height_in_main_chain(&block_id)?.ok_or(
+                    ChainstateError::FailedToReadProperty(BlockError::StorageCorrupted),
+                )?;
+                let best_height = this.get_best_block_height()?;
+                let confirmations = u64::from(best_height).saturating_sub(u64::from(height)) + 1;
+                Ok(Some((
+                    output,
+                    block_id,
+                    confirmations,
+                    this.get_chain_config(),
+                )))
+            })
+            .await;
+
+        handle_error(res)?
+            .map(|(output, block_id, confirmations, chain_config)| {
+                Ok(UtxoInfo {
+                    block_id,
+                    confirmations,
+                    block_reward,
+                    output: OutputInfo::new(&chain_config, &output)?,
+                })
+            })
+            .transpose()
+    }
+
+    async fn utxo_set_info(&self) -> rpc::Result<UtxoSetStats> {
+        handle_error(self.handle.call(|this| this.get_utxo_set_info()).await)
+            .map(UtxoSetStats::from)
+    }
+
+    async fn decode_transaction(&self, tx_hex: String) -> rpc::Result<DecodedTransaction> {
+        let tx = decode_hex::<Transaction>(&tx_hex).map_err(|e| {
+            let message = format!("Invalid transaction: {}", e);
+            rpc::error(error_code::TX_DECODE_FAILED, message, INVALID_ENCODING)
+        })?;
+        let chain_config = self
+            .handle
+            .call(|this| this.get_chain_config())
+            .await
+            .map_err(rpc::subsystem_unavailable)?;
+        DecodedTransaction::new(&chain_config, &tx)
+    }
+
+    async fn decode_block(&self, block_hex: String) -> rpc::Result<DecodedBlock> {
+        let block = decode_hex::<Block>(&block_hex).map_err(|e| {
+            let message = format!("Invalid block: {}", e);
+            rpc::error(error_code::BLOCK_DECODE_FAILED, message, INVALID_ENCODING)
+        })?;
+        let chain_config = self
+            .handle
+            .call(|this| this.get_chain_config())
+            .await
+            .map_err(rpc::subsystem_unavailable)?;
+        DecodedBlock::new(&chain_config, &block)
+    }
+
+    async fn difficulty(&self) -> rpc::Result<f64> {
+        handle_error(self.handle.call(|this| this.get_difficulty()).await)
+    }
+
+    async fn block_template(
+        &self,
+        reward_address: String,
+        longpoll_id: Option<BlockId>,
+    ) -> rpc::Result<BlockTemplate> {
+        let chain_config = self
+            .handle
+            .call(|this| this.get_chain_config())
+            .await
+            .map_err(rpc::subsystem_unavailable)?;
+        let reward_destination = Address::from_str(&chain_config, &reward_address)
+            .and_then(|address| address.destination(&chain_config))
+            .map_err(|_| {
+                rpc::invalid_params(format!("Invalid reward address: {}", reward_address))
+            })?;
+
+        if let Some(longpoll_id) = longpoll_id {
+            let wait_for_tip = async {
+                while handle_error(self.handle.call(|this| this.get_best_block_id()).await)?
+                    == longpoll_id
+                {
+                    tokio::time::sleep(LONGPOLL_INTERVAL).await;
+                }
+                Ok::<_, rpc::Error>(())
+            };
+            if let Ok(res) = tokio::time::timeout(LONGPOLL_TIMEOUT, wait_for_tip).await {
+                res?;
+            }
+        }
+
+        let res = self
+            .handle
+            .call(move |this| {
+                let block = this.get_block_template(reward_destination)?;
+                let prev_block_id =
+                    block.prev_block_id().ok_or(ChainstateError::FailedToReadProperty(
+                        BlockError::InvariantErrorPrevBlockNotFound,
+                    ))?;
+                let height = this.get_best_block_height()?.checked_add(1).ok_or(
+                    ChainstateError::FailedToReadProperty(BlockError::BlockHeightArithmeticError),
+                )?;
+                Ok((
+                    block,
+                    prev_block_id,
+                    height,
+                    this.is_initial_block_download(),
+                ))
+            })
+            .await;
+        let (block, prev_block_id, height, initial_block_download) = handle_error(res)?;
+
+        Ok(BlockTemplate {
+            block: hex::encode(block.encode()),
+            prev_block_id,
+            height,
+            bits: pow_bits(&block),
+            warnings: warnings(initial_block_download),
+        })
     }
 
     async fn submit_block(&self, block_hex: String) -> rpc::Result<()> {
-        // TODO there should be a generic way of decoding SCALE-encoded hex json strings
-        let block_data = hex::decode(block_hex).map_err(rpc::Error::to_call_error)?;
-        let block = Block::decode(&mut &block_data[..]).map_err(rpc::Error::to_call_error)?;
-        let res = self.call_mut(move |this| this.process_block(block, BlockSource::Local)).await;
+        let block = decode_hex::<Block>(&block_hex)
+            .map_err(|e| rpc::error(error_code::BLOCK_DECODE_FAILED, e, INVALID_ENCODING))?;
+        let res = self
+            .handle
+            .call_mut(move |this| this.process_block(block, BlockSource::Local))
+            .await;
         handle_error(res)
     }
 
+    async fn generate(&self, count: u32, destination: Option<String>) -> rpc::Result<Vec<BlockId>> {
+        let destination = match destination {
+            Some(destination) => decode_hex::<Destination>(&destination).map_err(|e| {
+                rpc::invalid_params(format!("Invalid destination {}: {}", destination, e))
+            })?,
+            None => Destination::AnyoneCanSpend,
+        };
+        generate_blocks(&self.handle, count, destination).await
+    }
+
+    async fn generate_to_address(&self, count: u32, address: String) -> rpc::Result<Vec<BlockId>> {
+        let chain_config = self
+            .handle
+            .call(|this| this.get_chain_config())
+            .await
+            .map_err(rpc::subsystem_unavailable)?;
+        let destination = Address::from_str(&chain_config, &address)
+            .and_then(|address| address.destination(&chain_config))
+            .map_err(|_| rpc::invalid_params(format!("Invalid address: {}", address)))?;
+        generate_blocks(&self.handle, count, destination).await
+    }
+
+    async fn net_upgrades(&self) -> rpc::Result<Vec<NetUpgradeInfo>> {
+        let res = self
+            .handle
+            .call(|this| Ok((this.get_chain_config(), this.get_best_block_height()?)))
+            .await;
+        let (chain_config, best_height) = handle_error(res)?;
+        let net_upgrades = chain_config.net_upgrade();
+        let active = net_upgrades.active_upgrades(best_height.next_height());
+        Ok(net_upgrades
+            .schedule()
+            .iter()
+            .map(|entry| NetUpgradeInfo::new(entry.0, &entry.1, active.contains(entry)))
+            .collect())
+    }
+
     async fn block_height_in_main_chain(
         &self,
         block_id: BlockId,
     ) -> rpc::Result<Option<BlockHeight>> {
-        handle_error(self.call(move |this| this.get_block_height_in_main_chain(&block_id)).await)
+        handle_error(
+            self.handle
+                .call(move |this| this.get_block_height_in_main_chain(&block_id))
+                .await,
+        )
+    }
+
+    async fn backup_storage(&self, name: String) -> rpc::Result<()> {
+        let path = self.backup_path(&name)?;
+        handle_error(self.handle.call(move |this| this.backup_storage(&path)).await)
+    }
+
+    async fn storage_statistics(&self) -> rpc::Result<StorageStatistics> {
+        let stats = self
+            .handle
+            .call(|this| this.get_storage_statistics())
+            .await
+            .map_err(rpc::subsystem_unavailable)?;
+        Ok(stats.into())
+    }
+
+    fn subscribe_new_block(&self, pending: rpc::PendingSubscription) {
+        let handle = self.handle.clone();
+        tokio::spawn(async move {
+            let (tx, rx) = futures::channel::mpsc::unbounded();
+            // Chainstate event handlers can't be removed, so once the subscription is closed
+            // the handler keeps failing to send, which is harmless.
+            let handler = Arc::new(move |event: ChainstateEvent| match event {
+                ChainstateEvent::NewTip(id, height) => {
+                    let _ = tx.unbounded_send(NewBlock { id, height });
+                }
+            });
+
+            match handle.call_mut(|this| this.subscribe_to_events(handler)).await {
+                Ok(()) => {
+                    if let Some(mut sink) = pending.accept() {
+                        sink.pipe_from_stream(rx).await;
+                    }
+                }
+                Err(e) => {
+                    pending.reject(ErrorObject::owned(
+                        CALL_EXECUTION_FAILED_CODE,
+                        e.to_string(),
+                        None::<()>,
+                    ));
+                }
+            }
+        });
+    }
+}
+
+/// Warnings reported with the results that depend on the node being synced
+fn warnings(initial_block_download: bool) -> Vec<String> {
+    if initial_block_download {
+        vec![INITIAL_BLOCK_DOWNLOAD_WARNING.to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Mine the blocks in the chainstate subsystem, refusing to on chains other than regtest
+///
+/// The blocks are mined one per call, so the other chainstate calls don't wait for all of them.
+async fn generate_blocks(
+    handle: &super::ChainstateHandle,
+    count: u32,
+    destination: Destination,
+) -> rpc::Result<Vec<BlockId>> {
+    if count > MAX_GENERATE_BLOCKS {
+        return Err(rpc::invalid_params(format!(
+            "At most {} blocks can be generated at once",
+            MAX_GENERATE_BLOCKS
+        )));
+    }
+
+    let chain_config = handle
+        .call(|this| this.get_chain_config())
+        .await
+        .map_err(rpc::subsystem_unavailable)?;
+    if *chain_config.chain_type() != ChainType::Regtest {
+        return Err(rpc::error(
+            error_code::GENERATE_NOT_ALLOWED,
+            "Blocks can only be generated on regtest",
+            GENERATE_NOT_ALLOWED,
+        ));
+    }
+
+    let mut generated = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let destination = destination.clone();
+        let res = handle
+            .call_mut(move |this| this.generate_blocks(1, destination))
+            .await
+            .map_err(rpc::subsystem_unavailable)?;
+        match res {
+            Ok(block_ids) => generated.extend(block_ids),
+            Err(e) if generated.is_empty() => return Err(chainstate_error(e)),
+            Err(e) => {
+                let (code, kind) = chainstate_error_code(&e);
+                let generated =
+                    generated.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+                let message = format!("{}, blocks generated before the failure: {}", e, generated);
+                return Err(rpc::error(code, message, kind));
+            }
+        }
+    }
+    Ok(generated)
+}
+
+/// Compact target of the block, `None` for blocks without proof of work
+fn pow_bits(block: &Block) -> Option<u32> {
+    match block.consensus_data() {
+        ConsensusData::PoW(data) => Some(data.bits().0),
+        ConsensusData::None => None,
+    }
+}
+
+/// Decode a hex-encoded SCALE encoding, which must not be followed by any other data
+fn decode_hex<T: DecodeAll>(data: &str) -> Result<T, String> {
+    let data = hex::decode(data).map_err(|e| e.to_string())?;
+    T::decode_all(&mut &data[..]).map_err(|e| e.to_string())
+}
+
+/// Report a chainstate error with its code and the name of the error as its kind
+fn chainstate_error(e: ChainstateError) -> rpc::Error {
+    let (code, kind) = chainstate_error_code(&e);
+    rpc::error(code, e, kind)
+}
+
+/// Code and kind the error is reported with
+fn chainstate_error_code(e: &ChainstateError) -> (i32, &'static str) {
+    match e {
+        ChainstateError::ProcessBlockError(e) => (block_error_code(e), e.into()),
+        ChainstateError::FailedToReadProperty(e @ BlockError::StorageCorrupted) => {
+            (error_code::STORAGE_CORRUPTED, e.into())
+        }
+        ChainstateError::FailedToReadProperty(e) => (error_code::READ_FAILED, e.into()),
+        ChainstateError::FailedToInitializeChainstate(_) => (error_code::READ_FAILED, e.into()),
+        ChainstateError::StorageBackupFailed(_) => (error_code::BACKUP_FAILED, e.into()),
+    }
+}
+
+fn block_error_code(e: &BlockError) -> i32 {
+    match e {
+        BlockError::BlockAlreadyExists(_) => error_code::BLOCK_DUPLICATE,
+        BlockError::LocalOrphan | BlockError::IllegalOrphan => error_code::BLOCK_ORPHAN,
+        BlockError::StorageCorrupted => error_code::STORAGE_CORRUPTED,
+        BlockError::InvalidBlockNoPrevBlock
+        | BlockError::MerkleRootMismatch
+        | BlockError::WitnessMerkleRootMismatch
+        | BlockError::BlockTimeOrderInvalid
+        | BlockError::BlockFromTheFuture
+        | BlockError::BlockTooLarge
+        | BlockError::InvalidBlockHeight(_)
+        | BlockError::InvalidPoW
+        | BlockError::PrevBlockInvalid
+        | BlockError::InvalidBlockSource
+        | BlockError::DuplicatedTransactionInBlock
+        | BlockError::MissingOutputOrSpent
+        | BlockError::OutputIndexOutOfRange { .. }
+        | BlockError::DoubleSpendAttempt(_)
+        | BlockError::ImmatureBlockRewardSpend
+        | BlockError::TimeLockViolation(_)
+        | BlockError::TimeLockNotActivated(_)
+        | BlockError::InvalidOutputCount
+        | BlockError::SignatureVerificationFailed(_)
+        | BlockError::KeyKindNotActivated(_)
+        | BlockError::DuplicateTransaction(_)
+        | BlockError::TransactionWithoutInputs(_)
+        | BlockError::InputAdditionError
+        | BlockError::OutputAdditionError
+        | BlockError::AttemptToPrintMoney(_, _)
+        | BlockError::MoneyOutOfRange(_)
+        | BlockError::BlockRewardTooHigh(_, _)
+        | BlockError::DuplicateInputInTransaction(_)
+        | BlockError::DuplicateInputInBlock(_)
+        | BlockError::BlockConsistencyError(_)
+        | BlockError::NoPowDataInPreviousBlock
+        | BlockError::ConsensusTypeMismatch(_)
+        | BlockError::UnsupportedConsensusType => error_code::BLOCK_INVALID,
+        BlockError::InvariantErrorInvalidTip
+        | BlockError::InvariantErrorPrevBlockNotFound
+        | BlockError::StorageError(_)
+        | BlockError::InvalidAncestorHeight { .. }
+        | BlockError::StorageFailure(_)
+        | BlockError::NotFound
+        | BlockError::OutputAlreadyPresentInInputsCache
+        | BlockError::MissingOutputOrSpentOutputErased
+        | BlockError::InvariantBrokenAlreadyUnspent
+        | BlockError::InvariantBrokenSourceBlockIndexNotFound
+        | BlockError::BlockHeightArithmeticError
+        | BlockError::PreviouslyCachedInputNotFound
+        | BlockError::PreviouslyCachedInputWasErased
+        | BlockError::InvariantErrorTransactionCouldNotBeLoaded
+        | BlockError::TxNumWrongInBlock(_, _)
+        | BlockError::SerializationInvariantError(_)
+        | BlockError::InternalNumTypeConversionError(_)
+        | BlockError::Conversion(_) => error_code::BLOCK_INTERNAL_ERROR,
     }
 }
 
 fn handle_error<T>(e: Result<Result<T, ChainstateError>, CallError>) -> rpc::Result<T> {
-    e.map_err(rpc::Error::to_call_error)?.map_err(rpc::Error::to_call_error)
+    e.map_err(rpc::subsystem_unavailable)?.map_err(chainstate_error)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use blockchain_storage::BlockchainStorageRead;
     use serde_json::Value;
+    use serialization::Decode;
     use std::{future::Future, sync::Arc};
 
     async fn with_chainstate<F: 'static + Send + Future<Output = ()>>(
@@ -84,10 +1314,49 @@ mod test {
         man.main().await;
     }
 
+    /// Block spending the first output of the first transaction of `parent`
+    fn child_block(parent: &Block, prev_block_id: BlockId) -> Block {
+        let parent_tx = &parent.transactions()[0];
+        let tx = Transaction::new(
+            0,
+            vec![TxInput::new(
+                OutPointSourceId::Transaction(parent_tx.get_id()),
+                0,
+                common::chain::signature::inputsig::InputWitness::NoSignature(None),
+            )],
+            vec![TxOutput::new(
+                parent_tx.get_outputs()[0].get_value(),
+                Destination::AnyoneCanSpend,
+            )],
+            0,
+        )
+        .unwrap();
+        Block::new(
+            vec![tx],
+            Some(prev_block_id),
+            common::primitives::time::get() as u32,
+            ConsensusData::None,
+        )
+        .unwrap()
+    }
+
+    /// Code and kind of an error reported by a method
+    fn error_code_and_kind(res: rpc::Result<Value>) -> (i32, Value) {
+        match res {
+            Err(rpc::Error::Call(jsonrpsee::types::error::CallError::Custom(e))) => {
+                let data = e.data().map_or(Value::Null, |data| {
+                    serde_json::from_str::<Value>(data.get()).unwrap()["kind"].clone()
+                });
+                (e.code(), data)
+            }
+            res => panic!("expected a custom error, got {:?}", res),
+        }
+    }
+
     #[tokio::test]
     async fn rpc_requests() {
         with_chainstate(|handle| async {
-            let rpc = handle.into_rpc();
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
 
             let res = rpc.call("chainstate_best_block_id", [(); 0]).await;
             let genesis_hash = match res {
@@ -104,7 +1373,645 @@ mod test {
 
             let res: rpc::Result<Value> = rpc.call("chainstate_block_id_at_height", [1u32]).await;
             assert!(matches!(res, Ok(Value::Null)));
+
+            let res: rpc::Result<Value> = rpc.call("chainstate_best_block_height", [(); 0]).await;
+            assert_eq!(res.unwrap(), Value::from(0));
+
+            let res: rpc::Result<Value> = rpc.call("chainstate_difficulty", [(); 0]).await;
+            assert_eq!(res.unwrap(), Value::from(1.0));
+
+            // the genesis block is too old for the node to be synced
+            let res: rpc::Result<Value> = rpc.call("chainstate_info", [(); 0]).await;
+            let info = res.unwrap();
+            assert_eq!(info["best_block_id"], Value::from(genesis_hash));
+            assert_eq!(info["best_block_height"], Value::from(0));
+            assert_eq!(info["initial_block_download"], Value::from(true));
+            assert_eq!(
+                info["warnings"],
+                serde_json::json!([INITIAL_BLOCK_DOWNLOAD_WARNING])
+            );
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_backup_storage() {
+        with_chainstate(|handle| async {
+            let dir = tempfile::tempdir().unwrap();
+            let best_block_id =
+                handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+
+            let rpc = ChainstateRpc::new(handle.clone(), None).into_rpc();
+            let res = rpc.call("chainstate_backup_storage", ["backup"]).await;
+            assert_eq!(
+                error_code_and_kind(res),
+                (
+                    error_code::BACKUP_FAILED,
+                    Value::from(STORAGE_BACKUP_FAILED)
+                )
+            );
+
+            let rpc = ChainstateRpc::new(handle, Some(dir.path().to_owned())).into_rpc();
+            let res: rpc::Result<Value> = rpc.call("chainstate_backup_storage", ["backup"]).await;
+            assert_eq!(res.unwrap(), Value::Null);
+            let backup = blockchain_storage::Store::open(&dir.path().join("backup")).unwrap();
+            assert_eq!(backup.get_best_block_id(), Ok(Some(best_block_id)));
+            drop(backup);
+
+            // Existing files are not overwritten
+            let res = rpc.call("chainstate_backup_storage", ["backup"]).await;
+            assert_eq!(
+                error_code_and_kind(res),
+                (
+                    error_code::BACKUP_FAILED,
+                    Value::from(STORAGE_BACKUP_FAILED)
+                )
+            );
+
+            // Backups stay in the backup directory
+            for name in ["../backup", "sub/backup", "/tmp/backup", ".", ""] {
+                let res = rpc.call("chainstate_backup_storage", [name]).await;
+                assert_eq!(
+                    error_code_and_kind(res),
+                    (jsonrpsee::types::error::INVALID_PARAMS_CODE, Value::Null)
+                );
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_storage_statistics() {
+        with_chainstate(|handle| async {
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+
+            let res: rpc::Result<Value> = rpc.call("chainstate_storage_statistics", [(); 0]).await;
+            let stats = res.unwrap();
+            assert_eq!(stats["blocks"]["entries"], Value::from(1));
+            assert_eq!(stats["block_by_height"]["entries"], Value::from(1));
+            assert!(stats["blocks"]["bytes"].as_u64().unwrap() > 0);
+            assert!(stats["total_bytes"].as_u64() >= stats["blocks"]["bytes"].as_u64());
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_get_block() {
+        with_chainstate(|handle| async {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let genesis = handle
+                .call({
+                    let id = genesis_id.clone();
+                    move |this| this.get_block(id)
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_block", (genesis_id.clone(), 0)).await;
+            let block_hex = res.unwrap();
+            let block_data = hex::decode(block_hex.as_str().unwrap()).unwrap();
+            assert_eq!(Block::decode(&mut &block_data[..]).unwrap(), genesis);
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_block", (genesis_id.clone(), 1)).await;
+            let block = res.unwrap();
+            assert_eq!(block["id"], serde_json::to_value(&genesis_id).unwrap());
+            assert_eq!(block["height"], Value::from(0));
+            assert_eq!(block["confirmations"], Value::from(1));
+            assert_eq!(block["prev_block_id"], Value::Null);
+            assert_eq!(block["size"], Value::from(genesis.encoded_size()));
+            assert_eq!(
+                block["transactions"].as_array().unwrap().len(),
+                genesis.transactions().len()
+            );
+
+            let unknown = BlockId::new(&H256::zero());
+            let res: rpc::Result<Value> = rpc.call("chainstate_block", (unknown, 1)).await;
+            assert!(matches!(res, Ok(Value::Null)));
+
+            let res: rpc::Result<Value> = rpc.call("chainstate_block", (genesis_id, 2)).await;
+            assert_eq!(
+                error_code_and_kind(res),
+                (jsonrpsee::types::error::INVALID_PARAMS_CODE, Value::Null)
+            );
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_get_transaction() {
+        with_chainstate(|handle| async {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let genesis = handle
+                .call({
+                    let id = genesis_id.clone();
+                    move |this| this.get_block(id)
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let tx = genesis.transactions()[0].clone();
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_transaction", (tx.get_id(), 0)).await;
+            let tx_hex = res.unwrap();
+            let tx_data = hex::decode(tx_hex.as_str().unwrap()).unwrap();
+            assert_eq!(Transaction::decode(&mut &tx_data[..]).unwrap(), tx);
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_transaction", (tx.get_id(), 1)).await;
+            let decoded = res.unwrap();
+            assert_eq!(decoded["id"], serde_json::to_value(tx.get_id()).unwrap());
+            assert_eq!(
+                decoded["block_id"],
+                serde_json::to_value(&genesis_id).unwrap()
+            );
+            assert_eq!(decoded["confirmations"], Value::from(1));
+            assert_eq!(decoded["size"], Value::from(tx.encoded_size()));
+            assert_eq!(
+                decoded["outputs"][0]["value"],
+                Value::from(tx.get_outputs()[0].get_value().into_atoms().to_string())
+            );
+            assert_eq!(
+                decoded["outputs"][0]["destination"]["type"],
+                Value::from("anyone_can_spend")
+            );
+
+            let unknown = Id::<Transaction>::new(&H256::zero());
+            let res: rpc::Result<Value> = rpc.call("chainstate_transaction", (unknown, 1)).await;
+            assert!(matches!(res, Ok(Value::Null)));
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_transaction", (tx.get_id(), 2)).await;
+            assert!(res.is_err());
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_net_upgrades() {
+        with_chainstate(|handle| async {
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+            let res: rpc::Result<Value> = rpc.call("chainstate_net_upgrades", [(); 0]).await;
+            assert_eq!(
+                res.unwrap(),
+                serde_json::json!([
+                    {
+                        "height": 0,
+                        "active": true,
+                        "type": "consensus",
+                        "consensus": "ignore-consensus",
+                        "initial_difficulty": null,
+                    },
+                    {
+                        "height": 0,
+                        "active": true,
+                        "type": "script",
+                        "enforce_minimal_push": true,
+                        "enforce_minimal_if": true,
+                    },
+                    {
+                        "height": 0,
+                        "active": true,
+                        "type": "signature",
+                        "key_kind": "secp256k1-schnorr",
+                    },
+                    {
+                        "height": 0,
+                        "active": true,
+                        "type": "duplicate_tx",
+                    },
+                    {
+                        "height": 0,
+                        "active": true,
+                        "type": "inputless_tx",
+                    },
+                    {
+                        "height": 0,
+                        "active": true,
+                        "type": "block_reward",
+                    },
+                    {
+                        "height": 0,
+                        "active": true,
+                        "type": "time_lock",
+                    },
+                ])
+            );
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_merkle_proof() {
+        with_chainstate(|handle| async {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let genesis = handle
+                .call({
+                    let id = genesis_id.clone();
+                    move |this| this.get_block(id)
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let tx_id = genesis.transactions()[0].get_id();
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+
+            // The only transaction of a block is its merkle root
+            let res: rpc::Result<Value> = rpc.call("chainstate_merkle_proof", [&tx_id]).await;
+            let proof = res.unwrap();
+            assert_eq!(
+                proof["block_id"],
+                serde_json::to_value(&genesis_id).unwrap()
+            );
+            assert_eq!(proof["tx_index"], Value::from(0));
+            assert_eq!(
+                proof["merkle_root"],
+                serde_json::to_value(tx_id.get()).unwrap()
+            );
+            assert_eq!(proof["siblings"], Value::Array(vec![]));
+
+            let unknown = Id::<Transaction>::new(&H256::zero());
+            let res: rpc::Result<Value> = rpc.call("chainstate_merkle_proof", [unknown]).await;
+            assert!(matches!(res, Ok(Value::Null)));
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_utxo() {
+        with_chainstate(|handle| async move {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let genesis = handle
+                .call({
+                    let id = genesis_id.clone();
+                    move |this| this.get_block(id)
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let genesis_tx = genesis.transactions()[0].clone();
+            let genesis_outputs = genesis_tx.get_outputs().len() as u64;
+            let rpc = ChainstateRpc::new(handle.clone(), None).into_rpc();
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_utxo", (genesis_tx.get_id().get(), 0, false)).await;
+            let utxo = res.unwrap();
+            assert_eq!(utxo["block_id"], serde_json::to_value(&genesis_id).unwrap());
+            assert_eq!(utxo["confirmations"], Value::from(1));
+            assert_eq!(utxo["block_reward"], Value::from(false));
+            assert_eq!(
+                utxo["value"],
+                Value::from(genesis_tx.get_outputs()[0].get_value().into_atoms().to_string())
+            );
+            assert_eq!(utxo["destination"]["type"], Value::from("anyone_can_spend"));
+
+            let res: rpc::Result<Value> = rpc.call("chainstate_utxo_set_info", [(); 0]).await;
+            let stats = res.unwrap();
+            assert_eq!(stats["height"], Value::from(0));
+            assert_eq!(stats["utxo_count"], Value::from(genesis_outputs));
+            let genesis_hash = stats["utxo_set_hash"].clone();
+
+            // spend the first genesis output
+            let block = child_block(&genesis, genesis_id);
+            let block_tx = block.transactions()[0].clone();
+            handle
+                .call_mut(move |this| this.process_block(block, BlockSource::Local))
+                .await
+                .unwrap()
+                .unwrap();
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_utxo", (genesis_tx.get_id().get(), 0, false)).await;
+            assert!(matches!(res, Ok(Value::Null)));
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_utxo", (block_tx.get_id().get(), 0, false)).await;
+            assert_eq!(res.unwrap()["confirmations"], Value::from(1));
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_utxo", (genesis_tx.get_id().get(), 0, true)).await;
+            assert!(matches!(res, Ok(Value::Null)));
+
+            let res: rpc::Result<Value> = rpc.call("chainstate_utxo_set_info", [(); 0]).await;
+            let stats = res.unwrap();
+            assert_eq!(stats["height"], Value::from(1));
+            assert_eq!(stats["utxo_count"], Value::from(genesis_outputs));
+            assert_ne!(stats["utxo_set_hash"], genesis_hash);
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_decode() {
+        with_chainstate(|handle| async move {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let genesis = handle
+                .call({
+                    let id = genesis_id.clone();
+                    move |this| this.get_block(id)
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+
+            // The block is decoded even though it's not part of the chain
+            let block = child_block(&genesis, BlockId::new(&H256::random()));
+            let tx = block.transactions()[0].clone();
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_decode_block", [hex::encode(block.encode())]).await;
+            let decoded = res.unwrap();
+            assert_eq!(decoded["id"], serde_json::to_value(block.get_id()).unwrap());
+            assert_eq!(
+                decoded["prev_block_id"],
+                serde_json::to_value(block.prev_block_id()).unwrap()
+            );
+            assert_eq!(decoded["bits"], Value::Null);
+            assert_eq!(decoded["reward_outputs"], Value::Array(vec![]));
+            assert_eq!(decoded["size"], Value::from(block.encoded_size()));
+            assert_eq!(
+                decoded["transactions"][0]["id"],
+                serde_json::to_value(tx.get_id()).unwrap()
+            );
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_decode_transaction", [hex::encode(tx.encode())]).await;
+            let decoded_tx = res.unwrap();
+            assert_eq!(decoded_tx, decoded["transactions"][0]);
+            assert_eq!(decoded_tx["inputs"][0]["index"], Value::from(0));
+            assert_eq!(
+                decoded_tx["outputs"][0]["destination"]["type"],
+                Value::from("anyone_can_spend")
+            );
+            assert!(decoded_tx.get("block_id").is_none());
+
+            let res: rpc::Result<Value> = rpc.call("chainstate_decode_transaction", ["zz"]).await;
+            assert_eq!(
+                error_code_and_kind(res),
+                (error_code::TX_DECODE_FAILED, Value::from(INVALID_ENCODING))
+            );
+
+            let trailing = hex::encode(tx.encode()) + "00";
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_decode_transaction", [trailing]).await;
+            assert!(res.is_err());
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_decode_block", [hex::encode(tx.encode())]).await;
+            assert!(res.is_err());
+        })
+        .await
+    }
+
+    #[test]
+    fn output_destination_as_address() {
+        let cfg = common::chain::config::create_mainnet();
+        let (_, public_key) = crypto::key::PrivateKey::new(crypto::key::KeyKind::RistrettoSchnorr);
+        let output = TxOutput::new(
+            common::primitives::Amount::from_atoms(100),
+            Destination::PublicKey(public_key.clone()),
+        );
+
+        let info = serde_json::to_value(OutputInfo::new(&cfg, &output).unwrap()).unwrap();
+        let address = Address::from_destination(&cfg, output.get_destination()).unwrap();
+        assert_eq!(info["value"], Value::from("100"));
+        assert_eq!(info["destination"]["type"], Value::from("address"));
+        assert_eq!(info["destination"]["value"], Value::from(address.get()));
+    }
+
+    #[tokio::test]
+    async fn rpc_subscribe_new_block() {
+        with_chainstate(|handle| async move {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let genesis = handle
+                .call({
+                    let id = genesis_id.clone();
+                    move |this| this.get_block(id)
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let rpc = ChainstateRpc::new(handle.clone(), None).into_rpc();
+
+            let mut sub = rpc.subscribe("chainstate_subscribe_new_block", [(); 0]).await.unwrap();
+
+            let block = child_block(&genesis, genesis_id);
+            let block_id = block.get_id();
+            handle
+                .call_mut(move |this| this.process_block(block, BlockSource::Local))
+                .await
+                .unwrap()
+                .unwrap();
+
+            let (notification, _) = sub.next::<NewBlock>().await.unwrap().unwrap();
+            assert_eq!(
+                notification,
+                NewBlock {
+                    id: block_id,
+                    height: BlockHeight::new(1),
+                }
+            );
         })
         .await
     }
+
+    #[tokio::test]
+    async fn rpc_submit_block() {
+        fn error_code(res: rpc::Result<Value>) -> i32 {
+            error_code_and_kind(res).0
+        }
+
+        with_chainstate(|handle| async move {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let genesis = handle
+                .call({
+                    let id = genesis_id.clone();
+                    move |this| this.get_block(id)
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+
+            let res = rpc.call("chainstate_submit_block", ["zz"]).await;
+            assert_eq!(error_code(res), error_code::BLOCK_DECODE_FAILED);
+
+            let res = rpc.call("chainstate_submit_block", ["00"]).await;
+            assert_eq!(error_code(res), error_code::BLOCK_DECODE_FAILED);
+
+            let orphan = child_block(&genesis, BlockId::new(&H256::random()));
+            let res = rpc.call("chainstate_submit_block", [hex::encode(orphan.encode())]).await;
+            assert_eq!(error_code(res), error_code::BLOCK_ORPHAN);
+
+            let block = child_block(&genesis, genesis_id);
+            let block_hex = hex::encode(block.encode());
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_submit_block", [block_hex.clone()]).await;
+            assert_eq!(res.unwrap(), Value::Null);
+            let res: rpc::Result<Value> = rpc.call("chainstate_best_block_height", [(); 0]).await;
+            assert_eq!(res.unwrap(), Value::from(1));
+
+            let res = rpc.call("chainstate_submit_block", [block_hex]).await;
+            assert_eq!(
+                error_code_and_kind(res),
+                (
+                    error_code::BLOCK_DUPLICATE,
+                    Value::from("block_already_exists")
+                )
+            );
+
+            // Spends the same output as `block`
+            let double_spend = child_block(&genesis, block.get_id());
+            let res = rpc
+                .call(
+                    "chainstate_submit_block",
+                    [hex::encode(double_spend.encode())],
+                )
+                .await;
+            assert_eq!(error_code(res), error_code::BLOCK_INVALID);
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_block_template() {
+        with_chainstate(|handle| async move {
+            let genesis_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+            let cfg = common::chain::config::create_unit_test_config();
+            let (_, public_key) =
+                crypto::key::PrivateKey::new(crypto::key::KeyKind::RistrettoSchnorr);
+            let address = Address::from_public_key(&cfg, &public_key).unwrap();
+            let rpc = Arc::new(ChainstateRpc::new(handle, None).into_rpc());
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_block_template", ("invalid", Value::Null)).await;
+            assert!(res.is_err());
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_block_template", (address.get(), Value::Null)).await;
+            let template = res.unwrap();
+            assert_eq!(
+                template["prev_block_id"],
+                serde_json::to_value(&genesis_id).unwrap()
+            );
+            assert_eq!(template["height"], Value::from(1));
+            assert_eq!(template["bits"], Value::Null);
+            let block_data = hex::decode(template["block"].as_str().unwrap()).unwrap();
+            let block = Block::decode(&mut &block_data[..]).unwrap();
+            assert_eq!(block.prev_block_id(), Some(genesis_id.clone()));
+
+            // A long-polling call returns once the template is outdated by a new tip
+            let mut longpoll = tokio::spawn({
+                let rpc = Arc::clone(&rpc);
+                let address = address.get().to_owned();
+                async move {
+                    let res: rpc::Result<Value> =
+                        rpc.call("chainstate_block_template", (address, genesis_id)).await;
+                    res
+                }
+            });
+            assert!(tokio::time::timeout(LONGPOLL_INTERVAL * 2, &mut longpoll).await.is_err());
+
+            let res: rpc::Result<Value> =
+                rpc.call("chainstate_submit_block", [template["block"].clone()]).await;
+            assert_eq!(res.unwrap(), Value::Null);
+
+            let template = longpoll.await.unwrap().unwrap();
+            assert_eq!(
+                template["prev_block_id"],
+                serde_json::to_value(block.get_id()).unwrap()
+            );
+            assert_eq!(template["height"], Value::from(2));
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rpc_generate() {
+        with_chainstate(|handle| async move {
+            let rpc = ChainstateRpc::new(handle, None).into_rpc();
+            let res = rpc.call("chainstate_generate", (1, Value::Null)).await;
+            assert_eq!(
+                error_code_and_kind(res),
+                (
+                    error_code::GENERATE_NOT_ALLOWED,
+                    Value::from(GENERATE_NOT_ALLOWED)
+                )
+            );
+        })
+        .await;
+
+        let storage = blockchain_storage::Store::new_empty().unwrap();
+        let cfg = Arc::new(common::chain::config::create_regtest());
+        let mut man = subsystem::Manager::new("rpctest");
+        let handle = man.add_subsystem(
+            "chainstate",
+            crate::make_chainstate(Arc::clone(&cfg), storage, None).unwrap(),
+        );
+        let _ = man.add_raw_subsystem(
+            "test",
+            move |_: subsystem::subsystem::CallRequest<()>, _| async move {
+                let (_, public_key) =
+                    crypto::key::PrivateKey::new(crypto::key::KeyKind::Secp256k1Schnorr);
+                let address = Address::from_public_key(&cfg, &public_key).unwrap();
+                let rpc = ChainstateRpc::new(handle.clone(), None).into_rpc();
+
+                let res: rpc::Result<Vec<BlockId>> =
+                    rpc.call("chainstate_generate", (2, Value::Null)).await;
+                let ids = res.unwrap();
+                assert_eq!(ids.len(), 2);
+                let res: rpc::Result<Vec<BlockId>> =
+                    rpc.call("chainstate_generate_to_address", (3, address.get())).await;
+                let ids = res.unwrap();
+                assert_eq!(ids.len(), 3);
+
+                let best_id = handle.call(|this| this.get_best_block_id()).await.unwrap().unwrap();
+                assert_eq!(ids.last(), Some(&best_id));
+                let res: rpc::Result<Value> =
+                    rpc.call("chainstate_best_block_height", [(); 0]).await;
+                assert_eq!(res.unwrap(), Value::from(5));
+
+                // The block rewards of the last blocks go to the address
+                let block = handle.call(move |this| this.get_block(best_id)).await.unwrap();
+                let reward = match block.unwrap().expect("best block exists").consensus_data() {
+                    ConsensusData::PoW(data) => data.outputs().to_vec(),
+                    ConsensusData::None => panic!("expected a proof of work block"),
+                };
+                assert_eq!(
+                    reward[0].get_destination(),
+                    &address.destination(&cfg).unwrap()
+                );
+
+                let res: rpc::Result<Value> = rpc
+                    .call(
+                        "chainstate_generate",
+                        (MAX_GENERATE_BLOCKS + 1, Value::Null),
+                    )
+                    .await;
+                assert!(res.is_err());
+                let res: rpc::Result<Value> =
+                    rpc.call("chainstate_best_block_height", [(); 0]).await;
+                assert_eq!(res.unwrap(), Value::from(5));
+
+                let res: rpc::Result<Value> =
+                    rpc.call("chainstate_generate_to_address", (1, "invalid")).await;
+                assert!(res.is_err());
+                let res: rpc::Result<Value> = rpc.call("chainstate_generate", (1, "zz")).await;
+                assert!(res.is_err());
+            },
+        );
+        man.main().await;
+    }
 }
@@ -1,9 +1,14 @@
+//! Bech32m addresses of output destinations
+//!
+//! An address is the SCALE-encoded `Destination` with the chain's address prefix as the
+//! human-readable part, so it can be decoded back into the destination it was made from.
+
 use self::pubkeyhash::PublicKeyHash;
-use crate::chain::ChainConfig;
+use crate::chain::{ChainConfig, Destination};
 use crate::primitives::{encoding, Bech32Error, DecodedArbitraryDataFromBech32};
 use crypto::key::PublicKey;
 pub mod pubkeyhash;
-use serialization::Encode;
+use serialization::{DecodeAll, Encode};
 
 pub trait AddressableData<T: AsRef<[u8]>> {
     fn encode(&self) -> Result<String, Bech32Error> {
@@ -25,6 +30,7 @@ pub trait AddressableData<T: AsRef<[u8]>> {
 pub enum AddressError {
     Bech32EncodingError(Bech32Error),
     InvalidPrefix(String),
+    InvalidDestination,
 }
 
 impl From<Bech32Error> for AddressError {
@@ -49,6 +55,15 @@ impl Address {
         Ok(Self { address: d })
     }
 
+    /// Parse an address, checking that it is valid for the chain
+    pub fn from_str(cfg: &ChainConfig, address: &str) -> Result<Self, AddressError> {
+        let address = Self {
+            address: address.to_owned(),
+        };
+        address.data(cfg)?;
+        Ok(address)
+    }
+
     pub fn data(&self, cfg: &ChainConfig) -> Result<Vec<u8>, AddressError> {
         let data = encoding::decode(&self.address)?;
         if data.hrp() != cfg.address_prefix() {
@@ -65,12 +80,24 @@ impl Address {
         Ok(data.data().to_owned())
     }
 
+    pub fn from_destination(
+        cfg: &ChainConfig,
+        destination: &Destination,
+    ) -> Result<Self, AddressError> {
+        Address::new(cfg, destination.encode())
+    }
+
+    /// Decode the destination the address was made from
+    pub fn destination(&self, cfg: &ChainConfig) -> Result<Destination, AddressError> {
+        let data = self.data(cfg)?;
+        Destination::decode_all(&mut data.as_slice()).map_err(|_| AddressError::InvalidDestination)
+    }
+
     pub fn from_public_key_hash(
         cfg: &ChainConfig,
         public_key_hash: &PublicKeyHash,
     ) -> Result<Self, AddressError> {
-        let encoded = public_key_hash.encode();
-        Address::new(cfg, encoded)
+        Self::from_destination(cfg, &Destination::Address(*public_key_hash))
     }
 
     pub fn from_public_key(
@@ -90,6 +117,7 @@ impl Address {
 mod tests {
     use super::*;
     use crate::chain::config::create_mainnet;
+    use crate::primitives::{Id, H256};
     use crypto::key::{KeyKind, PrivateKey};
 
     #[test]
@@ -99,11 +127,82 @@ mod tests {
         let public_key_hash = PublicKeyHash::from(&pub_key);
         let address = Address::from_public_key_hash(&cfg, &public_key_hash)
             .expect("Address from pubkeyhash failed");
-        let public_key_hash_restored_vec =
-            address.data(&cfg).expect("Failed to extract public key hash from address");
-        let public_key_hash_restored = PublicKeyHash::try_from(public_key_hash_restored_vec)
-            .expect("Restoring public key hash from vec failed");
-        assert_eq!(public_key_hash_restored, public_key_hash);
+        let destination_restored =
+            address.destination(&cfg).expect("Failed to extract destination from address");
+        assert_eq!(destination_restored, Destination::Address(public_key_hash));
+    }
+
+    #[test]
+    fn destination_round_trip() {
+        let cfg = create_mainnet();
+        let (_priv_key, pub_key) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let destinations = [
+            Destination::Address(PublicKeyHash::from(&pub_key)),
+            Destination::PublicKey(pub_key),
+            Destination::ScriptHash(Id::new(&H256::random())),
+            Destination::AnyoneCanSpend,
+        ];
+
+        for destination in destinations {
+            let address = Address::from_destination(&cfg, &destination).unwrap();
+            assert!(address.get().starts_with(cfg.address_prefix()));
+            let parsed = Address::from_str(&cfg, address.get()).unwrap();
+            assert_eq!(parsed.destination(&cfg), Ok(destination));
+        }
+    }
+
+    #[test]
+    fn error_detection() {
+        let cfg = create_mainnet();
+        let (_priv_key, pub_key) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let address = Address::from_public_key(&cfg, &pub_key).unwrap();
+
+        // Any single character substitution is caught by the checksum
+        let data_start = cfg.address_prefix().len() + 1;
+        for pos in data_start..address.get().len() {
+            let mut chars: Vec<char> = address.get().chars().collect();
+            chars[pos] = if chars[pos] == 'q' { 'p' } else { 'q' };
+            let corrupted: String = chars.into_iter().collect();
+            assert!(matches!(
+                Address::from_str(&cfg, &corrupted),
+                Err(AddressError::Bech32EncodingError(_))
+            ));
+        }
+
+        // Valid bech32m data that is not a destination
+        let not_destination = Address::new(&cfg, [0xff, 0x00]).unwrap();
+        assert_eq!(
+            not_destination.destination(&cfg),
+            Err(AddressError::InvalidDestination)
+        );
+
+        // Addresses of other chains are rejected
+        let testnet = crate::chain::config::create_testnet();
+        let testnet_address = Address::from_public_key(&testnet, &pub_key).unwrap();
+        assert_eq!(
+            testnet_address.destination(&cfg),
+            Err(AddressError::InvalidPrefix(
+                testnet.address_prefix().to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_address() {
+        let cfg = create_mainnet();
+        let (_priv_key, pub_key) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let address = Address::from_public_key(&cfg, &pub_key).unwrap();
+        assert_eq!(Address::from_str(&cfg, address.get()), Ok(address.clone()));
+
+        let other_address = Address::new_with_hrp("other", address.data(&cfg).unwrap()).unwrap();
+        assert_eq!(
+            Address::from_str(&cfg, other_address.get()),
+            Err(AddressError::InvalidPrefix("other".to_owned()))
+        );
+        assert!(matches!(
+            Address::from_str(&cfg, "not an address"),
+            Err(AddressError::Bech32EncodingError(_))
+        ));
     }
 
     #[test]
@@ -0,0 +1,88 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::chain::block::Block;
+use crate::chain::block::ConsensusData;
+use crate::chain::ChainConfig;
+use crate::chain::TxOutput;
+use crate::primitives::id;
+use crate::primitives::id::Idable;
+use crate::primitives::{Id, H256};
+
+use serialization::{Decode, Encode};
+
+/// Header of a block, committing to the transactions of the block through the merkle roots
+///
+/// The header is encoded the same way on its own as at the start of a block, so headers can be
+/// exchanged without the transactions. The ID of a block is the hash of its header.
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Encode, Decode)]
+pub struct BlockHeader {
+    #[codec(compact)]
+    pub(super) block_version: u32,
+    pub(super) prev_block_hash: Option<Id<Block>>,
+    pub(super) tx_merkle_root: Option<H256>,
+    pub(super) witness_merkle_root: Option<H256>,
+    #[codec(compact)]
+    pub(super) time: u32,
+    pub(super) consensus_data: ConsensusData,
+}
+
+impl BlockHeader {
+    pub fn version(&self) -> u32 {
+        self.block_version
+    }
+
+    pub fn tx_merkle_root(&self) -> Option<H256> {
+        self.tx_merkle_root
+    }
+
+    pub fn witness_merkle_root(&self) -> Option<H256> {
+        self.witness_merkle_root
+    }
+
+    pub fn consensus_data(&self) -> &ConsensusData {
+        &self.consensus_data
+    }
+
+    pub fn block_id(&self) -> Id<Block> {
+        Id::new(&id::hash_encoded(self))
+    }
+
+    pub fn is_genesis(&self, chain_config: &ChainConfig) -> bool {
+        self.prev_block_hash.is_none() && chain_config.genesis_block_id() == self.block_id()
+    }
+
+    pub fn get_prev_block_id(&self) -> &Option<Id<Block>> {
+        &self.prev_block_hash
+    }
+
+    pub fn block_time(&self) -> u32 {
+        self.time
+    }
+
+    pub fn block_reward_destinations(&self) -> Option<&[TxOutput]> {
+        match &self.consensus_data {
+            ConsensusData::None => None,
+            ConsensusData::PoW(pow_data) => Some(pow_data.outputs()),
+        }
+    }
+}
+
+impl Idable for BlockHeader {
+    type Tag = Block;
+    fn get_id(&self) -> Id<Block> {
+        Id::new(&id::hash_encoded(self))
+    }
+}
@@ -1,5 +1,5 @@
-use crate::chain::block::block_v1::BlockHeader;
 use crate::chain::block::Block;
+use crate::chain::block::BlockHeader;
 use crate::chain::ChainConfig;
 use crate::primitives::{BlockHeight, Id, Idable};
 // use crate::Uint256;
@@ -0,0 +1,119 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Merkle roots committed to in the block header
+//!
+//! The transaction merkle root commits to the transaction IDs, which don't cover the witnesses.
+//! The witness merkle root commits separately to the full serialized transactions.
+
+use crate::chain::transaction::Transaction;
+use crate::primitives::merkle::{self, MerkleProof, MerkleTreeFormError};
+use crate::primitives::{Idable, H256};
+
+fn tx_hash(tx: &Transaction) -> H256 {
+    tx.get_id().get()
+}
+
+fn witness_hash(tx: &Transaction) -> H256 {
+    tx.get_witness_id().get()
+}
+
+pub fn calculate_tx_merkle_root(
+    transactions: &[Transaction],
+) -> Result<Option<H256>, MerkleTreeFormError> {
+    calculate_generic_merkle_root(tx_hash, transactions)
+}
+
+pub fn calculate_witness_merkle_root(
+    transactions: &[Transaction],
+) -> Result<Option<H256>, MerkleTreeFormError> {
+    calculate_generic_merkle_root(witness_hash, transactions)
+}
+
+/// Proof that the transaction at the index is committed to by the transaction merkle root
+pub fn calculate_tx_merkle_proof(
+    transactions: &[Transaction],
+    tx_index: usize,
+) -> Result<MerkleProof, MerkleTreeFormError> {
+    match transactions.len() {
+        0 => Err(MerkleTreeFormError::TooSmall(0)),
+        // A single transaction is the root itself
+        1 if tx_index == 0 => Ok(MerkleProof::new(0, Vec::new())),
+        1 => Err(MerkleTreeFormError::LeafIndexOutOfRange(tx_index, 1)),
+        _ => {
+            let hashes: Vec<H256> = transactions.iter().map(tx_hash).collect();
+            merkle::merkle_proof_from_vec(&hashes, tx_index)
+        }
+    }
+}
+
+fn calculate_generic_merkle_root(
+    tx_hasher: fn(&Transaction) -> H256,
+    transactions: &[Transaction],
+) -> Result<Option<H256>, MerkleTreeFormError> {
+    if transactions.is_empty() {
+        return Ok(None);
+    }
+
+    if transactions.len() == 1 {
+        // using bitcoin's way, blocks that only have the coinbase (or a single tx in general) use their coinbase as the merkleroot
+        return Ok(Some(tx_hasher(&transactions[0])));
+    }
+    let hashes: Vec<H256> = transactions.iter().map(tx_hasher).collect();
+    let t = merkle::merkletree_from_vec(&hashes)?;
+    Ok(Some(t.root()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::{Destination, TxOutput};
+    use crate::primitives::Amount;
+
+    fn make_transactions(count: u32) -> Vec<Transaction> {
+        (0..count)
+            .map(|i| {
+                let output =
+                    TxOutput::new(Amount::from_atoms(i as u128), Destination::AnyoneCanSpend);
+                Transaction::new(0, vec![], vec![output], i).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tx_merkle_proofs() {
+        assert_eq!(calculate_tx_merkle_root(&[]), Ok(None));
+        assert_eq!(
+            calculate_tx_merkle_proof(&[], 0),
+            Err(MerkleTreeFormError::TooSmall(0))
+        );
+
+        for count in 1..10 {
+            let transactions = make_transactions(count);
+            let root = calculate_tx_merkle_root(&transactions).unwrap().unwrap();
+            for (index, tx) in transactions.iter().enumerate() {
+                let proof = calculate_tx_merkle_proof(&transactions, index).unwrap();
+                assert!(proof.verify(tx.get_id().get(), root));
+            }
+            assert_eq!(
+                calculate_tx_merkle_proof(&transactions, count as usize),
+                Err(MerkleTreeFormError::LeafIndexOutOfRange(
+                    count as usize,
+                    count as usize
+                ))
+            );
+        }
+    }
+}
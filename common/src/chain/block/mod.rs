@@ -17,15 +17,19 @@
 
 use crate::chain::transaction::Transaction;
 
-use crate::primitives::merkle;
 use crate::primitives::merkle::MerkleTreeFormError;
 use crate::primitives::{Id, Idable, H256};
 pub mod block_index;
 pub use block_index::*;
+mod block_merkle;
+pub use block_merkle::{
+    calculate_tx_merkle_proof, calculate_tx_merkle_root, calculate_witness_merkle_root,
+};
+mod block_header;
 mod block_v1;
 pub mod consensus_data;
 
-pub use block_v1::BlockHeader;
+pub use block_header::BlockHeader;
 use block_v1::BlockV1;
 pub use consensus_data::ConsensusData;
 use serialization::{Decode, Encode};
@@ -38,37 +42,6 @@ pub enum BlockConsistencyError {
     VersionMismatch(u32, u32),
 }
 
-pub fn calculate_tx_merkle_root(
-    transactions: &[Transaction],
-) -> Result<Option<H256>, merkle::MerkleTreeFormError> {
-    const TX_HASHER: fn(&Transaction) -> H256 = |tx: &Transaction| tx.get_id().get();
-    calculate_generic_merkle_root(&TX_HASHER, transactions)
-}
-
-pub fn calculate_witness_merkle_root(
-    transactions: &[Transaction],
-) -> Result<Option<H256>, merkle::MerkleTreeFormError> {
-    const TX_HASHER: fn(&Transaction) -> H256 = |tx: &Transaction| tx.get_serialized_hash().get();
-    calculate_generic_merkle_root(&TX_HASHER, transactions)
-}
-
-fn calculate_generic_merkle_root(
-    tx_hasher: &fn(&Transaction) -> H256,
-    transactions: &[Transaction],
-) -> Result<Option<H256>, merkle::MerkleTreeFormError> {
-    if transactions.is_empty() {
-        return Ok(None);
-    }
-
-    if transactions.len() == 1 {
-        // using bitcoin's way, blocks that only have the coinbase (or a single tx in general) use their coinbase as the merkleroot
-        return Ok(Some(tx_hasher(&transactions[0])));
-    }
-    let hashes: Vec<H256> = transactions.iter().map(tx_hasher).collect();
-    let t = merkle::merkletree_from_vec(&hashes)?;
-    Ok(Some(t.root()))
-}
-
 trait BlockVersion {
     const BLOCK_VERSION: u32;
     // self is not required in implementations, but unfortunately, we need it because rust doesn't have a decltype operator
@@ -80,6 +53,10 @@ trait BlockVersion {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BlockCreationError {
     MerkleTreeError(MerkleTreeFormError),
+    /// The transactions don't match the merkle roots of the header
+    MerkleRootMismatch,
+    /// The header has a version no block is encoded with
+    UnsupportedVersion(u32),
 }
 
 impl From<MerkleTreeFormError> for BlockCreationError {
@@ -155,6 +132,36 @@ impl Block {
         Ok(block)
     }
 
+    /// Assemble a block from a header and the transactions it commits to
+    ///
+    /// This is the counterpart of [`Block::into_parts`] for headers and transactions that were
+    /// transferred separately.
+    pub fn new_from_header(
+        header: BlockHeader,
+        transactions: Vec<Transaction>,
+    ) -> Result<Self, BlockCreationError> {
+        if header.version() != <BlockV1 as BlockVersion>::BLOCK_VERSION {
+            return Err(BlockCreationError::UnsupportedVersion(header.version()));
+        }
+        if header.tx_merkle_root() != calculate_tx_merkle_root(&transactions)?
+            || header.witness_merkle_root() != calculate_witness_merkle_root(&transactions)?
+        {
+            return Err(BlockCreationError::MerkleRootMismatch);
+        }
+
+        Ok(Block::V1(BlockV1 {
+            header,
+            transactions,
+        }))
+    }
+
+    /// Split the block into its header and transactions
+    pub fn into_parts(self) -> (BlockHeader, Vec<Transaction>) {
+        match self {
+            Block::V1(blk) => blk.into_parts(),
+        }
+    }
+
     pub fn update_consensus_data(&mut self, consensus_data: ConsensusData) {
         match self {
             Block::V1(blk) => blk.update_consensus_data(consensus_data),
@@ -351,4 +358,50 @@ mod tests {
 
         assert_eq!(1, first_byte)
     }
+
+    #[test]
+    fn header_encoded_separately() {
+        let inputs = vec![TxInput::new(
+            OutPointSourceId::Transaction(H256::random().into()),
+            0,
+            InputWitness::NoSignature(Some(b"abc".to_vec())),
+        )];
+        let transactions = vec![Transaction::new(0, inputs, Vec::new(), 0).unwrap()];
+        let block = Block::new(
+            transactions.clone(),
+            Some(H256::random().into()),
+            12345,
+            ConsensusData::None,
+        )
+        .unwrap();
+
+        // the block is encoded as its version, its header and its transactions
+        let encoded_header = block.header().encode();
+        let encoded_block = block.encode();
+        assert_eq!(
+            &encoded_block[1..encoded_header.len() + 1],
+            &encoded_header[..]
+        );
+        let header = BlockHeader::decode(&mut &encoded_header[..]).unwrap();
+        assert_eq!(header.get_id(), block.get_id());
+
+        assert_eq!(
+            Block::new_from_header(header.clone(), transactions.clone()),
+            Ok(block.clone())
+        );
+        assert_eq!(block.into_parts(), (header.clone(), transactions));
+        assert_eq!(
+            Block::new_from_header(header.clone(), Vec::new()),
+            Err(BlockCreationError::MerkleRootMismatch)
+        );
+
+        let header = BlockHeader {
+            block_version: 2,
+            ..header
+        };
+        assert_eq!(
+            Block::new_from_header(header, Vec::new()),
+            Err(BlockCreationError::UnsupportedVersion(2))
+        );
+    }
 }
@@ -5,15 +5,22 @@ use crate::chain::block::ConsensusData;
 use crate::chain::signature::inputsig::InputWitness;
 use crate::chain::transaction::Destination;
 use crate::chain::transaction::Transaction;
-use crate::chain::upgrades::ConsensusUpgrade;
 use crate::chain::upgrades::NetUpgrades;
 use crate::chain::{PoWChainConfig, UpgradeVersion};
 use crate::primitives::id::{Id, H256};
+use crate::primitives::Amount;
 use crate::primitives::BlockDistance;
-use crate::primitives::Idable;
 use crate::primitives::{version::SemVer, BlockHeight};
 use std::collections::BTreeMap;
 
+mod builder;
+mod emission_schedule;
+mod file;
+
+pub use builder::{ChainConfigBuildError, ChainConfigBuilder};
+pub use emission_schedule::EmissionSchedule;
+pub use file::ChainConfigFileError;
+
 #[derive(
     Debug,
     Copy,
@@ -31,7 +38,6 @@ pub enum ChainType {
     Mainnet,
     Testnet,
     Regtest,
-    Signet,
 }
 
 #[derive(Debug, Clone)]
@@ -40,13 +46,20 @@ pub struct ChainConfig {
     address_prefix: String,
     rpc_port: u16,
     p2p_port: u16,
+    dns_seeds: Vec<String>,
+    fixed_seeds: Vec<String>,
     height_checkpoint_data: BTreeMap<BlockHeight, Id<Block>>,
     net_upgrades: NetUpgrades<UpgradeVersion>,
     magic_bytes: [u8; 4],
     genesis_block: Block,
     genesis_block_id: Id<Block>,
     blockreward_maturity: BlockDistance,
+    emission_schedule: EmissionSchedule,
+    max_money: Amount,
+    coin_decimals: u8,
     version: SemVer,
+    min_peer_version: SemVer,
+    required_peer_protocols: Vec<String>,
 }
 
 impl ChainConfig {
@@ -74,6 +87,16 @@ impl ChainConfig {
         &self.version
     }
 
+    /// Oldest version of the node software that peers are allowed to run
+    pub fn min_peer_version(&self) -> &SemVer {
+        &self.min_peer_version
+    }
+
+    /// Protocols that a peer must support for the connection to be accepted
+    pub fn required_peer_protocols(&self) -> &[String] {
+        &self.required_peer_protocols
+    }
+
     pub fn chain_type(&self) -> &ChainType {
         &self.chain_type
     }
@@ -90,6 +113,16 @@ impl ChainConfig {
         self.rpc_port
     }
 
+    /// Hostnames that resolve to addresses of nodes on this chain
+    pub fn dns_seeds(&self) -> &[String] {
+        &self.dns_seeds
+    }
+
+    /// Addresses of nodes on this chain that are used if no other peers are known
+    pub fn fixed_seeds(&self) -> &[String] {
+        &self.fixed_seeds
+    }
+
     pub fn height_checkpoints(&self) -> &BTreeMap<BlockHeight, Id<Block>> {
         &self.height_checkpoint_data
     }
@@ -102,23 +135,49 @@ impl ChainConfig {
     pub const fn get_blockreward_maturity(&self) -> &BlockDistance {
         &self.blockreward_maturity
     }
-}
 
-const MAINNET_ADDRESS_PREFIX: &str = "mtc";
-#[allow(dead_code)]
-const TESTNET_ADDRESS_PREFIX: &str = "tmt";
-#[allow(dead_code)]
-const REGTEST_ADDRESS_PREFIX: &str = "rmt";
+    pub fn emission_schedule(&self) -> &EmissionSchedule {
+        &self.emission_schedule
+    }
+
+    /// Amount of new coins the block at the height may pay to its reward outputs, on top of the
+    /// fees of its transactions
+    pub fn block_subsidy(&self, height: BlockHeight) -> Amount {
+        self.emission_schedule.block_subsidy(height)
+    }
+
+    /// Total supply of the coin: the outputs of the genesis block and every block subsidy
+    ///
+    /// No output and no transaction can carry more than that.
+    pub fn max_money(&self) -> Amount {
+        self.max_money
+    }
+
+    /// Number of decimal places of the coin, used to convert between coins and atoms
+    pub fn coin_decimals(&self) -> u8 {
+        self.coin_decimals
+    }
+
+    /// Render the amount in coins, e.g. `1.5` for 1.5 * 10^coin_decimals atoms
+    pub fn amount_to_coin_str(&self, amount: Amount) -> String {
+        amount.into_fixedpoint_str(self.coin_decimals)
+    }
+
+    /// Parse an amount of coins, `None` if it is malformed, too precise or too large
+    pub fn coin_str_to_amount(&self, amount_str: &str) -> Option<Amount> {
+        Amount::from_fixedpoint_str(amount_str, self.coin_decimals)
+    }
+}
 
-// If block time is 2 minutes (which is my goal eventually), then 500 is equivalent to 100 in bitcoin's 10 minutes.
-const MAINNET_BLOCKREWARD_MATURITY: BlockDistance = BlockDistance::new(500);
 // DSA allows us to have blocks up to 1mb
 pub const MAX_BLOCK_WEIGHT: usize = 1_048_576;
 
-fn create_mainnet_genesis() -> Block {
-    use crate::chain::transaction::{TxInput, TxOutput};
-    use crate::primitives::Amount;
+/// Block syncing and gossiping are needed to participate in the network
+fn default_peer_protocols() -> Vec<String> {
+    vec!["/mintlayer/sync/0.1.0".to_string(), "/meshsub/1.1.0".to_string()]
+}
 
+fn genesis_mint_destination() -> Destination {
     // TODO: replace this with our mint key
     // Private key: "0080732e24bb0b704cb455e233b539f2c63ab411989a54984f84a6a2eb2e933e160f"
     // Pubub key:  "008090f5aee58be97ce2f7c014fa97ffff8c459a0c491f8124950724a187d134e25c"
@@ -127,182 +186,88 @@ fn create_mainnet_genesis() -> Block {
     let genesis_mint_pubkeyhash_hex_encoded = "008640e6a3d3d53c7dffe2790b0e147c9a77197033";
     let genesis_mint_pubkeyhash_encoded = Vec::from_hex(genesis_mint_pubkeyhash_hex_encoded)
         .expect("Hex decoding of pubkeyhash shouldn't fail");
-    let genesis_mint_destination = <Destination as parity_scale_codec::DecodeAll>::decode_all(
+    <Destination as parity_scale_codec::DecodeAll>::decode_all(
         &mut genesis_mint_pubkeyhash_encoded.as_slice(),
     )
-    .expect("Decoding genesis mint destination shouldn't fail");
-
-    let genesis_message = b"".to_vec();
-    let input = TxInput::new(
-        Id::<Transaction>::new(&H256::zero()).into(),
-        0,
-        InputWitness::NoSignature(Some(genesis_message)),
-    );
-    // TODO: replace this with the real genesis mint value
-    let output = TxOutput::new(
-        Amount::from_atoms(100000000000000),
-        genesis_mint_destination,
-    );
-    let tx = Transaction::new(0, vec![input], vec![output], 0)
-        .expect("Failed to create genesis coinbase transaction");
-
-    Block::new(vec![tx], None, 1639975460, ConsensusData::None)
-        .expect("Error creating genesis block")
+    .expect("Decoding genesis mint destination shouldn't fail")
 }
 
-fn create_unit_test_genesis(premine_destination: Destination) -> Block {
+/// Genesis block with a single transaction minting the premine to the destination
+fn genesis_block(
+    genesis_message: Vec<u8>,
+    premine: Amount,
+    premine_destination: Destination,
+    timestamp: u32,
+) -> Block {
     use crate::chain::transaction::{TxInput, TxOutput};
-    use crate::primitives::Amount;
 
-    let genesis_message = b"".to_vec();
     let input = TxInput::new(
         Id::<Transaction>::new(&H256::zero()).into(),
         0,
         InputWitness::NoSignature(Some(genesis_message)),
     );
-
-    let output = TxOutput::new(Amount::from_atoms(100000000000000), premine_destination);
+    let output = TxOutput::new(premine, premine_destination);
     let tx = Transaction::new(0, vec![input], vec![output], 0)
         .expect("Failed to create genesis coinbase transaction");
 
-    Block::new(vec![tx], None, 1639975460, ConsensusData::None)
+    Block::new(vec![tx], None, timestamp, ConsensusData::None)
         .expect("Error creating genesis block")
 }
 
 pub fn create_mainnet() -> ChainConfig {
-    let chain_type = ChainType::Mainnet;
-    let pow_config = PoWChainConfig::new(chain_type);
-
-    let upgrades = vec![
-        (
-            BlockHeight::new(0),
-            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
-        ),
-        (
-            BlockHeight::new(1),
-            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoW {
-                initial_difficulty: pow_config.limit().into(),
-            }),
-        ),
-    ];
-
-    let genesis_block = create_mainnet_genesis();
-    let genesis_block_id = genesis_block.get_id();
-
-    ChainConfig {
-        chain_type,
-        address_prefix: MAINNET_ADDRESS_PREFIX.to_owned(),
-        height_checkpoint_data: BTreeMap::<BlockHeight, Id<Block>>::new(),
-        net_upgrades: NetUpgrades::initialize(upgrades).expect("Should not fail"),
-        rpc_port: 15234,
-        p2p_port: 8978,
-        magic_bytes: [0x1a, 0x64, 0xe5, 0xf1],
-        genesis_block,
-        genesis_block_id,
-        version: SemVer::new(0, 1, 0),
-        blockreward_maturity: MAINNET_BLOCKREWARD_MATURITY,
-    }
+    ChainConfigBuilder::new(ChainType::Mainnet).build()
+}
+
+pub fn create_testnet() -> ChainConfig {
+    ChainConfigBuilder::new(ChainType::Testnet).build()
 }
 
 pub fn create_regtest() -> ChainConfig {
-    let chain_type = ChainType::Regtest;
-    let pow_config = PoWChainConfig::new(chain_type);
-
-    let upgrades = vec![
-        (
-            BlockHeight::new(0),
-            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
-        ),
-        (
-            BlockHeight::new(1),
-            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoW {
-                initial_difficulty: pow_config.limit().into(),
-            }),
-        ),
-    ];
-
-    let genesis_block = create_unit_test_genesis(Destination::AnyoneCanSpend);
-    let genesis_block_id = genesis_block.get_id();
-
-    ChainConfig {
-        chain_type,
-        address_prefix: REGTEST_ADDRESS_PREFIX.to_owned(),
-        height_checkpoint_data: BTreeMap::<BlockHeight, Id<Block>>::new(),
-        net_upgrades: NetUpgrades::initialize(upgrades).expect("Should not fail"),
-        rpc_port: 11111,
-        p2p_port: 22222,
-        magic_bytes: [0xaa, 0xbb, 0xcc, 0xdd],
-        genesis_block,
-        genesis_block_id,
-        version: SemVer::new(0, 1, 0),
-        blockreward_maturity: MAINNET_BLOCKREWARD_MATURITY,
-    }
+    ChainConfigBuilder::new(ChainType::Regtest).build()
 }
 
 pub fn create_unit_test_config() -> ChainConfig {
-    let genesis_block = create_unit_test_genesis(Destination::AnyoneCanSpend);
-    let genesis_block_id = genesis_block.get_id();
-    ChainConfig {
-        chain_type: ChainType::Mainnet,
-        address_prefix: MAINNET_ADDRESS_PREFIX.to_owned(),
-        height_checkpoint_data: BTreeMap::<BlockHeight, Id<Block>>::new(),
-        net_upgrades: NetUpgrades::unit_tests(),
-        rpc_port: 15234,
-        p2p_port: 8978,
-        magic_bytes: [0x1a, 0x64, 0xe5, 0xf1],
-        genesis_block,
-        genesis_block_id,
-        version: SemVer::new(0, 1, 0),
-        blockreward_maturity: MAINNET_BLOCKREWARD_MATURITY,
-    }
+    ChainConfigBuilder::test_chain().build()
 }
 
-pub struct TestChainConfig {
-    net_upgrades: NetUpgrades<UpgradeVersion>,
-    magic_bytes: [u8; 4],
-}
+#[deprecated(note = "use `chain::config::ChainConfigBuilder::test_chain` instead")]
+pub struct TestChainConfig(ChainConfigBuilder);
 
+#[allow(deprecated)]
 impl Default for TestChainConfig {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[allow(deprecated)]
 impl TestChainConfig {
     pub fn new() -> Self {
-        Self {
-            net_upgrades: NetUpgrades::unit_tests(),
-            magic_bytes: [0x1a, 0x64, 0xe5, 0xf1],
-        }
+        Self(ChainConfigBuilder::test_chain())
     }
 
-    pub fn with_net_upgrades(mut self, net_upgrades: NetUpgrades<UpgradeVersion>) -> Self {
-        self.net_upgrades = net_upgrades;
-        self
+    pub fn with_net_upgrades(self, net_upgrades: NetUpgrades<UpgradeVersion>) -> Self {
+        Self(self.0.with_net_upgrades(net_upgrades))
     }
 
-    pub fn with_magic_bytes(mut self, magic_bytes: [u8; 4]) -> Self {
-        self.magic_bytes = magic_bytes;
-        self
+    pub fn with_magic_bytes(self, magic_bytes: [u8; 4]) -> Self {
+        Self(self.0.with_magic_bytes(magic_bytes))
+    }
+
+    pub fn with_fixed_seeds(self, fixed_seeds: Vec<String>) -> Self {
+        Self(self.0.with_fixed_seeds(fixed_seeds))
+    }
+
+    pub fn with_min_peer_version(self, min_peer_version: SemVer) -> Self {
+        Self(self.0.with_min_peer_version(min_peer_version))
+    }
+
+    pub fn with_required_peer_protocols(self, required_peer_protocols: Vec<String>) -> Self {
+        Self(self.0.with_required_peer_protocols(required_peer_protocols))
     }
 
     pub fn build(self) -> ChainConfig {
-        let genesis_block = create_unit_test_genesis(Destination::AnyoneCanSpend);
-        let genesis_block_id = genesis_block.get_id();
-
-        ChainConfig {
-            chain_type: ChainType::Mainnet,
-            address_prefix: MAINNET_ADDRESS_PREFIX.to_owned(),
-            height_checkpoint_data: BTreeMap::<BlockHeight, Id<Block>>::new(),
-            net_upgrades: self.net_upgrades,
-            rpc_port: 15234,
-            p2p_port: 8978,
-            magic_bytes: self.magic_bytes,
-            genesis_block,
-            genesis_block_id,
-            version: SemVer::new(0, 1, 0),
-            blockreward_maturity: MAINNET_BLOCKREWARD_MATURITY,
-        }
+        self.0.build()
     }
 }
 
@@ -315,10 +280,44 @@ mod tests {
         let config = create_mainnet();
 
         assert!(!config.net_upgrades.is_empty());
-        assert_eq!(2, config.net_upgrades.len());
+        assert_eq!(6, config.net_upgrades.len());
         assert_eq!(config.chain_type(), &ChainType::Mainnet);
     }
 
+    #[test]
+    fn testnet_creation() {
+        let mainnet = create_mainnet();
+        let config = create_testnet();
+
+        assert_eq!(config.chain_type(), &ChainType::Testnet);
+        assert_eq!(config.address_prefix(), "tmt");
+        assert_eq!(6, config.net_upgrades.len());
+        assert_ne!(config.magic_bytes(), mainnet.magic_bytes());
+        assert_ne!(config.p2p_port(), mainnet.p2p_port());
+        assert_ne!(config.rpc_port(), mainnet.rpc_port());
+        assert_ne!(config.genesis_block_id(), mainnet.genesis_block_id());
+        assert!(config.get_proof_of_work_config().allow_min_difficulty_blocks());
+    }
+
+    #[test]
+    fn coin_amounts() {
+        let config = create_mainnet();
+
+        assert_eq!(
+            config.coin_str_to_amount("1.5"),
+            Some(Amount::from_atoms(150_000_000_000))
+        );
+        assert_eq!(config.coin_str_to_amount("0.000000000001"), None);
+        assert_eq!(
+            config.amount_to_coin_str(Amount::from_atoms(150_000_000_000)),
+            "1.5"
+        );
+        assert_eq!(
+            config.amount_to_coin_str(Amount::from_atoms(1)),
+            "0.00000000001"
+        );
+    }
+
     #[test]
     fn chain_type_names() {
         use strum::VariantNames;
@@ -334,8 +333,8 @@ mod tests {
 
     #[test]
     fn different_magic_bytes() {
-        let config1 = TestChainConfig::new().build();
-        let config2 = TestChainConfig::new().with_magic_bytes([1, 2, 3, 4]).build();
+        let config1 = ChainConfigBuilder::test_chain().build();
+        let config2 = ChainConfigBuilder::test_chain().with_magic_bytes([1, 2, 3, 4]).build();
 
         assert_ne!(config1.magic_bytes(), config2.magic_bytes(),);
     }
@@ -0,0 +1,436 @@
+//! Builder of chain configurations
+//!
+//! The builder starts from the built-in parameters of a chain type, any of which can then be
+//! overridden, for example to run a private network or a test with a custom genesis block.
+
+use std::collections::BTreeMap;
+
+use crypto::key::KeyKind;
+
+use super::{
+    default_peer_protocols, genesis_block, genesis_mint_destination, ChainConfig, ChainType,
+    EmissionSchedule,
+};
+use crate::chain::block::Block;
+use crate::chain::transaction::Destination;
+use crate::chain::upgrades::{ConsensusUpgrade, NetUpgrades};
+use crate::chain::{PoWChainConfig, UpgradeVersion};
+use crate::primitives::{version::SemVer, Amount, BlockDistance, BlockHeight, Id, Idable};
+
+const MAINNET_ADDRESS_PREFIX: &str = "mtc";
+const TESTNET_ADDRESS_PREFIX: &str = "tmt";
+const REGTEST_ADDRESS_PREFIX: &str = "rmt";
+
+// If block time is 2 minutes (which is my goal eventually), then 500 is equivalent to 100 in bitcoin's 10 minutes.
+const MAINNET_BLOCKREWARD_MATURITY: BlockDistance = BlockDistance::new(500);
+
+// Emission parameters of regtest and of the test chain. Mainnet and testnet pay no subsidy until
+// their emission parameters are decided.
+// TODO: set the emission schedule of mainnet and testnet
+const INITIAL_BLOCK_SUBSIDY: Amount = Amount::from_atoms(5_000_000_000_000);
+const SUBSIDY_SUPPLY_CAP: Amount = Amount::from_atoms(10_000_000_000_000_000_000);
+// Four years of 2 minute blocks
+const TEST_SUBSIDY_HALVING_INTERVAL: BlockDistance = BlockDistance::new(1_051_200);
+const REGTEST_SUBSIDY_HALVING_INTERVAL: BlockDistance = BlockDistance::new(150);
+
+// One coin is 10^11 atoms
+const COIN_DECIMALS: u8 = 11;
+
+// TODO: replace this with the real genesis mint value
+const GENESIS_PREMINE: Amount = Amount::from_atoms(100000000000000);
+
+/// Ignore consensus for the genesis block and switch to PoW right after it
+///
+/// Duplicate transactions, transactions without inputs and block rewards above the subsidy plus
+/// fees are rejected from genesis on. Timelocked outputs are allowed from genesis on.
+/// Secp256k1 Schnorr signatures are only activated from genesis on regtest, other chains get
+/// their activation height once it is decided.
+fn default_net_upgrades(chain_type: ChainType) -> NetUpgrades<UpgradeVersion> {
+    let pow_config = PoWChainConfig::new(chain_type);
+    let mut upgrades = vec![
+        (
+            BlockHeight::new(0),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+        ),
+        (
+            BlockHeight::new(1),
+            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoW {
+                initial_difficulty: pow_config.limit().into(),
+            }),
+        ),
+        (BlockHeight::new(0), UpgradeVersion::DuplicateTxUpgrade),
+        (BlockHeight::new(0), UpgradeVersion::InputlessTxUpgrade),
+        (BlockHeight::new(0), UpgradeVersion::BlockRewardUpgrade),
+        (BlockHeight::new(0), UpgradeVersion::TimeLockUpgrade),
+    ];
+    if chain_type == ChainType::Regtest {
+        upgrades.push((
+            BlockHeight::new(0),
+            UpgradeVersion::SignatureUpgrade(KeyKind::Secp256k1Schnorr),
+        ));
+    }
+    NetUpgrades::initialize(upgrades).expect("Should not fail")
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ChainConfigBuildError {
+    #[error("The genesis outputs and the emission schedule supply cap overflow the total supply")]
+    TotalSupplyOverflow,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainConfigBuilder {
+    chain_type: ChainType,
+    address_prefix: String,
+    rpc_port: u16,
+    p2p_port: u16,
+    dns_seeds: Vec<String>,
+    fixed_seeds: Vec<String>,
+    height_checkpoint_data: BTreeMap<BlockHeight, Id<Block>>,
+    net_upgrades: NetUpgrades<UpgradeVersion>,
+    magic_bytes: [u8; 4],
+    genesis_block: Option<Block>,
+    genesis_message: Vec<u8>,
+    genesis_premine: Amount,
+    genesis_premine_destination: Destination,
+    genesis_timestamp: u32,
+    blockreward_maturity: BlockDistance,
+    emission_schedule: EmissionSchedule,
+    coin_decimals: u8,
+    version: SemVer,
+    min_peer_version: SemVer,
+    required_peer_protocols: Vec<String>,
+}
+
+impl ChainConfigBuilder {
+    /// Start from the built-in parameters of the chain type
+    pub fn new(chain_type: ChainType) -> Self {
+        let (address_prefix, rpc_port, p2p_port, magic_bytes) = match chain_type {
+            ChainType::Mainnet => (
+                MAINNET_ADDRESS_PREFIX,
+                15234,
+                8978,
+                [0x1a, 0x64, 0xe5, 0xf1],
+            ),
+            ChainType::Testnet => (
+                TESTNET_ADDRESS_PREFIX,
+                25234,
+                18978,
+                [0x2b, 0x7e, 0x19, 0xf8],
+            ),
+            ChainType::Regtest => (
+                REGTEST_ADDRESS_PREFIX,
+                11111,
+                22222,
+                [0xaa, 0xbb, 0xcc, 0xdd],
+            ),
+        };
+        let (genesis_message, genesis_premine_destination, genesis_timestamp) = match chain_type {
+            ChainType::Mainnet => (b"".to_vec(), genesis_mint_destination(), 1639975460),
+            ChainType::Testnet => (
+                b"Mintlayer testnet".to_vec(),
+                genesis_mint_destination(),
+                1656590400,
+            ),
+            ChainType::Regtest => (b"".to_vec(), Destination::AnyoneCanSpend, 1639975460),
+        };
+
+        let emission_schedule = match chain_type {
+            ChainType::Mainnet | ChainType::Testnet => EmissionSchedule::without_subsidy(),
+            ChainType::Regtest => EmissionSchedule::new(
+                INITIAL_BLOCK_SUBSIDY,
+                REGTEST_SUBSIDY_HALVING_INTERVAL,
+                SUBSIDY_SUPPLY_CAP,
+            ),
+        };
+
+        Self {
+            chain_type,
+            address_prefix: address_prefix.to_owned(),
+            rpc_port,
+            p2p_port,
+            // TODO: add seed nodes once public nodes are running
+            dns_seeds: Vec::new(),
+            fixed_seeds: Vec::new(),
+            height_checkpoint_data: BTreeMap::new(),
+            net_upgrades: default_net_upgrades(chain_type),
+            magic_bytes,
+            genesis_block: None,
+            genesis_message,
+            genesis_premine: GENESIS_PREMINE,
+            genesis_premine_destination,
+            genesis_timestamp,
+            blockreward_maturity: MAINNET_BLOCKREWARD_MATURITY,
+            emission_schedule,
+            coin_decimals: COIN_DECIMALS,
+            version: SemVer::new(0, 1, 0),
+            min_peer_version: SemVer::new(0, 1, 0),
+            required_peer_protocols: default_peer_protocols(),
+        }
+    }
+
+    /// Mainnet parameters with consensus ignored, a block subsidy and a genesis premine anyone can
+    /// spend
+    pub fn test_chain() -> Self {
+        Self::new(ChainType::Mainnet)
+            .with_net_upgrades(NetUpgrades::unit_tests())
+            .with_genesis_premine_destination(Destination::AnyoneCanSpend)
+            .with_emission_schedule(EmissionSchedule::new(
+                INITIAL_BLOCK_SUBSIDY,
+                TEST_SUBSIDY_HALVING_INTERVAL,
+                SUBSIDY_SUPPLY_CAP,
+            ))
+    }
+
+    pub fn with_address_prefix(mut self, address_prefix: String) -> Self {
+        self.address_prefix = address_prefix;
+        self
+    }
+
+    pub fn with_rpc_port(mut self, rpc_port: u16) -> Self {
+        self.rpc_port = rpc_port;
+        self
+    }
+
+    pub fn with_p2p_port(mut self, p2p_port: u16) -> Self {
+        self.p2p_port = p2p_port;
+        self
+    }
+
+    pub fn with_dns_seeds(mut self, dns_seeds: Vec<String>) -> Self {
+        self.dns_seeds = dns_seeds;
+        self
+    }
+
+    pub fn with_fixed_seeds(mut self, fixed_seeds: Vec<String>) -> Self {
+        self.fixed_seeds = fixed_seeds;
+        self
+    }
+
+    pub fn with_height_checkpoints(
+        mut self,
+        height_checkpoint_data: BTreeMap<BlockHeight, Id<Block>>,
+    ) -> Self {
+        self.height_checkpoint_data = height_checkpoint_data;
+        self
+    }
+
+    pub fn with_net_upgrades(mut self, net_upgrades: NetUpgrades<UpgradeVersion>) -> Self {
+        self.net_upgrades = net_upgrades;
+        self
+    }
+
+    pub fn with_magic_bytes(mut self, magic_bytes: [u8; 4]) -> Self {
+        self.magic_bytes = magic_bytes;
+        self
+    }
+
+    /// Use the block as genesis, ignoring the other genesis parameters
+    pub fn with_genesis_block(mut self, genesis_block: Block) -> Self {
+        self.genesis_block = Some(genesis_block);
+        self
+    }
+
+    /// Message embedded in the input of the genesis transaction
+    pub fn with_genesis_message(mut self, genesis_message: Vec<u8>) -> Self {
+        self.genesis_message = genesis_message;
+        self
+    }
+
+    /// Amount minted by the genesis transaction
+    pub fn with_genesis_premine(mut self, genesis_premine: Amount) -> Self {
+        self.genesis_premine = genesis_premine;
+        self
+    }
+
+    /// Destination the amount minted by the genesis transaction is sent to
+    pub fn with_genesis_premine_destination(mut self, destination: Destination) -> Self {
+        self.genesis_premine_destination = destination;
+        self
+    }
+
+    pub fn with_genesis_timestamp(mut self, genesis_timestamp: u32) -> Self {
+        self.genesis_timestamp = genesis_timestamp;
+        self
+    }
+
+    pub fn with_blockreward_maturity(mut self, blockreward_maturity: BlockDistance) -> Self {
+        self.blockreward_maturity = blockreward_maturity;
+        self
+    }
+
+    pub fn with_emission_schedule(mut self, emission_schedule: EmissionSchedule) -> Self {
+        self.emission_schedule = emission_schedule;
+        self
+    }
+
+    pub fn with_coin_decimals(mut self, coin_decimals: u8) -> Self {
+        self.coin_decimals = coin_decimals;
+        self
+    }
+
+    pub fn with_version(mut self, version: SemVer) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_min_peer_version(mut self, min_peer_version: SemVer) -> Self {
+        self.min_peer_version = min_peer_version;
+        self
+    }
+
+    pub fn with_required_peer_protocols(mut self, required_peer_protocols: Vec<String>) -> Self {
+        self.required_peer_protocols = required_peer_protocols;
+        self
+    }
+
+    /// Build the chain configuration, panics if the parameters are invalid
+    ///
+    /// Use [`Self::try_build`] when the parameters come from the user.
+    pub fn build(self) -> ChainConfig {
+        self.try_build().expect("Chain config parameters to be valid")
+    }
+
+    /// Build the chain configuration
+    pub fn try_build(self) -> Result<ChainConfig, ChainConfigBuildError> {
+        let genesis_block = self.genesis_block.unwrap_or_else(|| {
+            genesis_block(
+                self.genesis_message,
+                self.genesis_premine,
+                self.genesis_premine_destination,
+                self.genesis_timestamp,
+            )
+        });
+        let genesis_block_id = genesis_block.get_id();
+        let genesis_outputs = genesis_block
+            .transactions()
+            .iter()
+            .flat_map(|tx| tx.get_outputs())
+            .map(|output| output.get_value());
+        let max_money = Amount::sum(genesis_outputs)
+            .and_then(|premine| premine + self.emission_schedule.supply_cap())
+            .ok_or(ChainConfigBuildError::TotalSupplyOverflow)?;
+
+        Ok(ChainConfig {
+            chain_type: self.chain_type,
+            address_prefix: self.address_prefix,
+            height_checkpoint_data: self.height_checkpoint_data,
+            net_upgrades: self.net_upgrades,
+            rpc_port: self.rpc_port,
+            p2p_port: self.p2p_port,
+            dns_seeds: self.dns_seeds,
+            fixed_seeds: self.fixed_seeds,
+            magic_bytes: self.magic_bytes,
+            genesis_block,
+            genesis_block_id,
+            version: self.version,
+            min_peer_version: self.min_peer_version,
+            required_peer_protocols: self.required_peer_protocols,
+            blockreward_maturity: self.blockreward_maturity,
+            emission_schedule: self.emission_schedule,
+            max_money,
+            coin_decimals: self.coin_decimals,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let checkpoints = BTreeMap::from([(BlockHeight::new(5), Id::new(&Default::default()))]);
+        let config = ChainConfigBuilder::new(ChainType::Regtest)
+            .with_address_prefix("pmt".to_owned())
+            .with_rpc_port(1)
+            .with_p2p_port(2)
+            .with_height_checkpoints(checkpoints.clone())
+            .with_blockreward_maturity(BlockDistance::new(10))
+            .with_version(SemVer::new(1, 2, 3))
+            .build();
+
+        assert_eq!(config.chain_type(), &ChainType::Regtest);
+        assert_eq!(config.address_prefix(), "pmt");
+        assert_eq!(config.rpc_port(), 1);
+        assert_eq!(config.p2p_port(), 2);
+        assert_eq!(config.height_checkpoints(), &checkpoints);
+        assert_eq!(config.get_blockreward_maturity(), &BlockDistance::new(10));
+        assert_eq!(config.version(), &SemVer::new(1, 2, 3));
+    }
+
+    #[test]
+    fn genesis_parameters() {
+        let default = ChainConfigBuilder::new(ChainType::Regtest).build();
+        let timestamp =
+            ChainConfigBuilder::new(ChainType::Regtest).with_genesis_timestamp(1).build();
+        let message = ChainConfigBuilder::new(ChainType::Regtest)
+            .with_genesis_message(b"private".to_vec())
+            .build();
+        let premine = ChainConfigBuilder::new(ChainType::Regtest)
+            .with_genesis_premine(Amount::from_atoms(1))
+            .build();
+
+        assert_eq!(timestamp.genesis_block().block_time(), 1);
+        assert_ne!(timestamp.genesis_block_id(), default.genesis_block_id());
+        assert_ne!(message.genesis_block_id(), default.genesis_block_id());
+        assert_ne!(premine.genesis_block_id(), default.genesis_block_id());
+        assert_eq!(
+            (premine.max_money() + GENESIS_PREMINE).unwrap(),
+            (default.max_money() + Amount::from_atoms(1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn total_supply_overflow() {
+        let res = ChainConfigBuilder::new(ChainType::Regtest)
+            .with_emission_schedule(EmissionSchedule::new(
+                INITIAL_BLOCK_SUBSIDY,
+                REGTEST_SUBSIDY_HALVING_INTERVAL,
+                Amount::from_atoms(u128::MAX),
+            ))
+            .try_build();
+        assert_eq!(
+            res.map(|config| config.max_money()),
+            Err(ChainConfigBuildError::TotalSupplyOverflow)
+        );
+    }
+
+    #[test]
+    fn subsidy_only_on_regtest_and_test_chain() {
+        let mainnet = ChainConfigBuilder::new(ChainType::Mainnet).build();
+        let testnet = ChainConfigBuilder::new(ChainType::Testnet).build();
+        let regtest = ChainConfigBuilder::new(ChainType::Regtest).build();
+        let test_chain = ChainConfigBuilder::test_chain().build();
+
+        assert_eq!(
+            mainnet.block_subsidy(BlockHeight::new(1)),
+            Amount::from_atoms(0)
+        );
+        assert_eq!(
+            testnet.block_subsidy(BlockHeight::new(1)),
+            Amount::from_atoms(0)
+        );
+        assert_eq!(
+            regtest.block_subsidy(BlockHeight::new(1)),
+            INITIAL_BLOCK_SUBSIDY
+        );
+        assert_eq!(
+            test_chain.block_subsidy(BlockHeight::new(1)),
+            INITIAL_BLOCK_SUBSIDY
+        );
+        assert_eq!(mainnet.max_money(), GENESIS_PREMINE);
+    }
+
+    #[test]
+    fn custom_genesis_block() {
+        let genesis = ChainConfigBuilder::new(ChainType::Testnet).build().genesis_block().clone();
+        let config = ChainConfigBuilder::new(ChainType::Regtest)
+            .with_genesis_timestamp(1)
+            .with_genesis_block(genesis.clone())
+            .build();
+
+        assert_eq!(config.genesis_block(), &genesis);
+        assert_eq!(config.genesis_block_id(), genesis.get_id());
+    }
+}
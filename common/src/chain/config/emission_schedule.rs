@@ -0,0 +1,170 @@
+//! Amount of coins created by each block
+//!
+//! The subsidy starts at an initial reward that halves every `halving_interval` blocks. No
+//! subsidy is paid once the total amount issued through block subsidies reaches the supply cap.
+
+use crate::primitives::{Amount, BlockDistance, BlockHeight};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmissionSchedule {
+    initial_subsidy: Amount,
+    halving_interval: BlockDistance,
+    supply_cap: Amount,
+}
+
+impl EmissionSchedule {
+    /// The halving interval must be positive
+    pub fn new(
+        initial_subsidy: Amount,
+        halving_interval: BlockDistance,
+        supply_cap: Amount,
+    ) -> Self {
+        assert!(
+            halving_interval > BlockDistance::new(0),
+            "Halving interval must be positive"
+        );
+        Self {
+            initial_subsidy,
+            halving_interval,
+            supply_cap,
+        }
+    }
+
+    /// Schedule without any subsidy, block rewards can only claim the transaction fees
+    pub fn without_subsidy() -> Self {
+        Self::new(
+            Amount::from_atoms(0),
+            BlockDistance::new(1),
+            Amount::from_atoms(0),
+        )
+    }
+
+    pub fn initial_subsidy(&self) -> Amount {
+        self.initial_subsidy
+    }
+
+    pub fn halving_interval(&self) -> BlockDistance {
+        self.halving_interval
+    }
+
+    /// Total amount of coins issued through block subsidies
+    pub fn supply_cap(&self) -> Amount {
+        self.supply_cap
+    }
+
+    fn interval(&self) -> u128 {
+        let interval: i64 = self.halving_interval.into();
+        interval as u128
+    }
+
+    /// Subsidy of blocks in the given halving era, not taking the supply cap into account
+    fn era_subsidy(&self, era: u128) -> u128 {
+        u32::try_from(era)
+            .ok()
+            .and_then(|era| self.initial_subsidy.into_atoms().checked_shr(era))
+            .unwrap_or(0)
+    }
+
+    /// Amount issued by the subsidies of all blocks below the height
+    pub fn total_issued_before(&self, height: BlockHeight) -> Amount {
+        let height: u64 = height.into();
+        let height = height as u128;
+        let interval = self.interval();
+        let cap = self.supply_cap.into_atoms();
+
+        let mut issued: u128 = 0;
+        let mut era = 0;
+        while era * interval < height && issued < cap {
+            let era_subsidy = self.era_subsidy(era);
+            if era_subsidy == 0 {
+                break;
+            }
+            let blocks = std::cmp::min(interval, height - era * interval);
+            issued = issued.saturating_add(era_subsidy.saturating_mul(blocks));
+            era += 1;
+        }
+        Amount::from_atoms(std::cmp::min(issued, cap))
+    }
+
+    /// Subsidy the block at the height is allowed to claim on top of the transaction fees
+    pub fn block_subsidy(&self, height: BlockHeight) -> Amount {
+        let height_value: u64 = height.into();
+        let era_subsidy = self.era_subsidy(height_value as u128 / self.interval());
+        let remaining =
+            self.supply_cap.into_atoms() - self.total_issued_before(height).into_atoms();
+        Amount::from_atoms(std::cmp::min(era_subsidy, remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(initial: u128, interval: i64, cap: u128) -> EmissionSchedule {
+        EmissionSchedule::new(
+            Amount::from_atoms(initial),
+            BlockDistance::new(interval),
+            Amount::from_atoms(cap),
+        )
+    }
+
+    #[test]
+    fn halving() {
+        let schedule = schedule(100, 10, u128::MAX);
+
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(0)),
+            Amount::from_atoms(100)
+        );
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(9)),
+            Amount::from_atoms(100)
+        );
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(10)),
+            Amount::from_atoms(50)
+        );
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(25)),
+            Amount::from_atoms(25)
+        );
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(70)),
+            Amount::from_atoms(0)
+        );
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(u64::MAX)),
+            Amount::from_atoms(0)
+        );
+        assert_eq!(
+            schedule.total_issued_before(BlockHeight::new(15)),
+            Amount::from_atoms(1250)
+        );
+        assert_eq!(
+            schedule.total_issued_before(BlockHeight::new(u64::MAX)),
+            Amount::from_atoms(1000 + 500 + 250 + 120 + 60 + 30 + 10)
+        );
+    }
+
+    #[test]
+    fn supply_cap() {
+        let schedule = schedule(100, 10, 250);
+
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(1)),
+            Amount::from_atoms(100)
+        );
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(2)),
+            Amount::from_atoms(50)
+        );
+        assert_eq!(
+            schedule.block_subsidy(BlockHeight::new(3)),
+            Amount::from_atoms(0)
+        );
+        assert_eq!(
+            schedule.total_issued_before(BlockHeight::new(100)),
+            Amount::from_atoms(250)
+        );
+    }
+}
@@ -0,0 +1,620 @@
+//! Chain configuration loaded from a file
+//!
+//! Custom private networks are described by a TOML document, or a JSON one if the file name
+//! ends with `.json`, for example:
+//!
+//! ```toml
+//! chain_type = "regtest"
+//! address_prefix = "pmt"
+//! magic_bytes = "0a0b0c0d"
+//! rpc_port = 13030
+//! p2p_port = 13031
+//!
+//! [[net_upgrades]]
+//! height = 0
+//! consensus = "ignore-consensus"
+//!
+//! [[net_upgrades]]
+//! height = 1
+//! consensus = "pow"
+//!
+//! [[net_upgrades]]
+//! height = 0
+//! upgrade = "duplicate-tx"
+//!
+//! [[net_upgrades]]
+//! height = 0
+//! upgrade = "inputless-tx"
+//!
+//! [[net_upgrades]]
+//! height = 0
+//! upgrade = "block-reward"
+//!
+//! [[net_upgrades]]
+//! height = 0
+//! upgrade = "time-lock"
+//!
+//! [genesis]
+//! timestamp = 1656590400
+//! message = "Private network"
+//! premine_atoms = 100000000000000
+//!
+//! [emission_schedule]
+//! initial_subsidy_atoms = 5000000000000
+//! halving_interval = 210000
+//! supply_cap_atoms = 2100000000000000000
+//! ```
+//!
+//! Each net upgrade either switches the consensus or activates one of the transaction checks.
+//! Values that are left out are taken from the built-in configuration of the chain type.
+
+use std::{collections::BTreeMap, path::Path};
+
+use hex::FromHex;
+use serialization::DecodeAll;
+
+use super::{
+    genesis_block, ChainConfig, ChainConfigBuildError, ChainConfigBuilder, ChainType,
+    EmissionSchedule,
+};
+use crate::chain::block::Block;
+use crate::chain::transaction::Destination;
+use crate::chain::upgrades::{ConsensusUpgrade, NetUpgrades};
+use crate::chain::{PoWChainConfig, UpgradeVersion};
+use crate::primitives::{id::H256, Amount, BlockDistance, BlockHeight, Compact, Id};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChainConfigFileError {
+    #[error("Failed to read the chain config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse the chain config file: {0}")]
+    Parse(String),
+    #[error("Invalid chain config field `{field}`: {reason}")]
+    InvalidField { field: String, reason: String },
+}
+
+impl ChainConfigFileError {
+    fn invalid(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::InvalidField {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Consensus introduced by a net upgrade
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum ConsensusKind {
+    Pow,
+    Pos,
+    Dsa,
+    IgnoreConsensus,
+}
+
+/// Transaction check activated by a net upgrade
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum UpgradeKind {
+    DuplicateTx,
+    InputlessTx,
+    BlockReward,
+    TimeLock,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct NetUpgradeEntry {
+    height: u64,
+    consensus: Option<ConsensusKind>,
+    upgrade: Option<UpgradeKind>,
+    /// Compact-encoded difficulty as a hex string, the chain type's limit if left out
+    initial_difficulty: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CheckpointEntry {
+    height: u64,
+    block_id: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct GenesisEntry {
+    timestamp: u32,
+    #[serde(default)]
+    message: String,
+    premine_atoms: u128,
+    /// SCALE-encoded destination as a hex string, anyone can spend the premine if left out
+    premine_destination: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct EmissionScheduleEntry {
+    initial_subsidy_atoms: u128,
+    halving_interval: u64,
+    supply_cap_atoms: u128,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ChainConfigFile {
+    chain_type: String,
+    address_prefix: Option<String>,
+    magic_bytes: String,
+    rpc_port: Option<u16>,
+    p2p_port: Option<u16>,
+    dns_seeds: Option<Vec<String>>,
+    fixed_seeds: Option<Vec<String>>,
+    net_upgrades: Option<Vec<NetUpgradeEntry>>,
+    #[serde(default)]
+    checkpoints: Vec<CheckpointEntry>,
+    genesis: Option<GenesisEntry>,
+    emission_schedule: Option<EmissionScheduleEntry>,
+}
+
+impl ChainConfig {
+    /// Load the chain configuration from a TOML file, or a JSON file if its name ends with `.json`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ChainConfigFileError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        if path.extension().map_or(false, |ext| ext == "json") {
+            Self::from_json_str(&content)
+        } else {
+            Self::from_toml_str(&content)
+        }
+    }
+
+    /// Load the chain configuration from a TOML document
+    pub fn from_toml_str(content: &str) -> Result<Self, ChainConfigFileError> {
+        // The toml deserializer doesn't support u128 fields, go through a JSON value instead
+        let value: serde_json::Value =
+            toml::from_str(content).map_err(|e| ChainConfigFileError::Parse(e.to_string()))?;
+        let file: ChainConfigFile = serde_json::from_value(value)
+            .map_err(|e| ChainConfigFileError::Parse(e.to_string()))?;
+        file.into_chain_config()
+    }
+
+    /// Load the chain configuration from a JSON document
+    pub fn from_json_str(content: &str) -> Result<Self, ChainConfigFileError> {
+        let file: ChainConfigFile = serde_json::from_str(content)
+            .map_err(|e| ChainConfigFileError::Parse(e.to_string()))?;
+        file.into_chain_config()
+    }
+}
+
+impl ChainConfigFile {
+    fn into_chain_config(self) -> Result<ChainConfig, ChainConfigFileError> {
+        let chain_type: ChainType = self
+            .chain_type
+            .parse()
+            .map_err(|_| ChainConfigFileError::invalid("chain_type", "unknown chain type"))?;
+        let mut builder = ChainConfigBuilder::new(chain_type);
+
+        if let Some(address_prefix) = self.address_prefix {
+            if address_prefix.is_empty() {
+                return Err(ChainConfigFileError::invalid(
+                    "address_prefix",
+                    "must not be empty",
+                ));
+            }
+            builder = builder.with_address_prefix(address_prefix);
+        }
+
+        let magic_bytes = <[u8; 4]>::from_hex(&self.magic_bytes).map_err(|_| {
+            ChainConfigFileError::invalid("magic_bytes", "expected 4 hex-encoded bytes")
+        })?;
+        builder = builder.with_magic_bytes(magic_bytes);
+
+        if let Some(rpc_port) = self.rpc_port {
+            builder = builder.with_rpc_port(rpc_port);
+        }
+        if let Some(p2p_port) = self.p2p_port {
+            builder = builder.with_p2p_port(p2p_port);
+        }
+
+        if let Some(dns_seeds) = self.dns_seeds {
+            builder = builder.with_dns_seeds(dns_seeds);
+        }
+        if let Some(fixed_seeds) = self.fixed_seeds {
+            builder = builder.with_fixed_seeds(fixed_seeds);
+        }
+
+        if let Some(net_upgrades) = self.net_upgrades {
+            builder = builder.with_net_upgrades(parse_net_upgrades(chain_type, net_upgrades)?);
+        }
+
+        builder = builder.with_height_checkpoints(parse_checkpoints(self.checkpoints)?);
+
+        if let Some(emission_schedule) = self.emission_schedule {
+            builder = builder.with_emission_schedule(parse_emission_schedule(emission_schedule)?);
+        }
+
+        if let Some(genesis) = self.genesis {
+            builder = builder.with_genesis_block(parse_genesis(genesis)?);
+        }
+
+        let config = builder.try_build().map_err(|e| match e {
+            ChainConfigBuildError::TotalSupplyOverflow => {
+                ChainConfigFileError::invalid("emission_schedule.supply_cap_atoms", e.to_string())
+            }
+        })?;
+
+        if config.rpc_port() == 0 {
+            return Err(ChainConfigFileError::invalid(
+                "rpc_port",
+                "must not be zero",
+            ));
+        }
+        if config.p2p_port() == 0 {
+            return Err(ChainConfigFileError::invalid(
+                "p2p_port",
+                "must not be zero",
+            ));
+        }
+        if config.rpc_port() == config.p2p_port() {
+            return Err(ChainConfigFileError::invalid(
+                "p2p_port",
+                "must differ from rpc_port",
+            ));
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_net_upgrades(
+    chain_type: ChainType,
+    entries: Vec<NetUpgradeEntry>,
+) -> Result<NetUpgrades<UpgradeVersion>, ChainConfigFileError> {
+    let mut upgrades: Vec<(BlockHeight, UpgradeVersion)> = Vec::with_capacity(entries.len());
+    for (idx, entry) in entries.into_iter().enumerate() {
+        let field = |name: &str| format!("net_upgrades[{}].{}", idx, name);
+
+        if entry.initial_difficulty.is_some() && entry.consensus != Some(ConsensusKind::Pow) {
+            return Err(ChainConfigFileError::invalid(
+                field("initial_difficulty"),
+                "only allowed for PoW upgrades",
+            ));
+        }
+
+        let upgrade = match (entry.consensus, entry.upgrade) {
+            (Some(consensus), None) => UpgradeVersion::ConsensusUpgrade(parse_consensus(
+                chain_type,
+                consensus,
+                entry.initial_difficulty,
+                || field("initial_difficulty"),
+            )?),
+            (None, Some(UpgradeKind::DuplicateTx)) => UpgradeVersion::DuplicateTxUpgrade,
+            (None, Some(UpgradeKind::InputlessTx)) => UpgradeVersion::InputlessTxUpgrade,
+            (None, Some(UpgradeKind::BlockReward)) => UpgradeVersion::BlockRewardUpgrade,
+            (None, Some(UpgradeKind::TimeLock)) => UpgradeVersion::TimeLockUpgrade,
+            (None, None) | (Some(_), Some(_)) => {
+                return Err(ChainConfigFileError::invalid(
+                    field("upgrade"),
+                    "expected exactly one of `consensus` and `upgrade`",
+                ))
+            }
+        };
+
+        let height = BlockHeight::new(entry.height);
+        let same_kind_at_height = upgrades.iter().any(|(other_height, other)| {
+            *other_height == height
+                && std::mem::discriminant(other) == std::mem::discriminant(&upgrade)
+        });
+        if same_kind_at_height {
+            return Err(ChainConfigFileError::invalid(
+                field("height"),
+                "more than one upgrade of this kind at this height",
+            ));
+        }
+        upgrades.push((height, upgrade));
+    }
+
+    let genesis_consensus = upgrades.iter().any(|(height, upgrade)| {
+        *height == BlockHeight::zero() && matches!(upgrade, UpgradeVersion::ConsensusUpgrade(_))
+    });
+    if !upgrades.is_empty() && !genesis_consensus {
+        return Err(ChainConfigFileError::invalid(
+            "net_upgrades",
+            "no consensus upgrade at height 0",
+        ));
+    }
+
+    NetUpgrades::initialize(upgrades)
+        .map_err(|e| ChainConfigFileError::invalid("net_upgrades", e.to_string()))
+}
+
+fn parse_consensus(
+    chain_type: ChainType,
+    consensus: ConsensusKind,
+    initial_difficulty: Option<String>,
+    field: impl FnOnce() -> String,
+) -> Result<ConsensusUpgrade, ChainConfigFileError> {
+    let upgrade = match consensus {
+        ConsensusKind::Pow => {
+            let initial_difficulty = match initial_difficulty {
+                Some(difficulty) => {
+                    u32::from_str_radix(&difficulty, 16).map(Compact).map_err(|_| {
+                        ChainConfigFileError::invalid(
+                            field(),
+                            "expected a hex-encoded compact difficulty",
+                        )
+                    })?
+                }
+                None => PoWChainConfig::new(chain_type).limit().into(),
+            };
+            ConsensusUpgrade::PoW { initial_difficulty }
+        }
+        ConsensusKind::Pos => ConsensusUpgrade::PoS,
+        ConsensusKind::Dsa => ConsensusUpgrade::DSA,
+        ConsensusKind::IgnoreConsensus => ConsensusUpgrade::IgnoreConsensus,
+    };
+    Ok(upgrade)
+}
+
+fn parse_checkpoints(
+    entries: Vec<CheckpointEntry>,
+) -> Result<BTreeMap<BlockHeight, Id<Block>>, ChainConfigFileError> {
+    let mut checkpoints = BTreeMap::new();
+    for (idx, entry) in entries.into_iter().enumerate() {
+        let block_id: H256 = entry.block_id.parse().map_err(|_| {
+            ChainConfigFileError::invalid(
+                format!("checkpoints[{}].block_id", idx),
+                "expected a hex-encoded block id",
+            )
+        })?;
+        if checkpoints.insert(BlockHeight::new(entry.height), Id::new(&block_id)).is_some() {
+            return Err(ChainConfigFileError::invalid(
+                format!("checkpoints[{}].height", idx),
+                "more than one checkpoint at this height",
+            ));
+        }
+    }
+    Ok(checkpoints)
+}
+
+fn parse_emission_schedule(
+    entry: EmissionScheduleEntry,
+) -> Result<EmissionSchedule, ChainConfigFileError> {
+    let halving_interval = i64::try_from(entry.halving_interval)
+        .ok()
+        .filter(|&interval| interval > 0)
+        .ok_or_else(|| {
+            ChainConfigFileError::invalid("emission_schedule.halving_interval", "must be positive")
+        })?;
+    Ok(EmissionSchedule::new(
+        Amount::from_atoms(entry.initial_subsidy_atoms),
+        BlockDistance::new(halving_interval),
+        Amount::from_atoms(entry.supply_cap_atoms),
+    ))
+}
+
+fn parse_genesis(entry: GenesisEntry) -> Result<Block, ChainConfigFileError> {
+    let premine_destination = match entry.premine_destination {
+        Some(destination) => Vec::from_hex(&destination)
+            .ok()
+            .and_then(|bytes| Destination::decode_all(&mut bytes.as_slice()).ok())
+            .ok_or_else(|| {
+                ChainConfigFileError::invalid(
+                    "genesis.premine_destination",
+                    "expected a hex-encoded destination",
+                )
+            })?,
+        None => Destination::AnyoneCanSpend,
+    };
+    Ok(genesis_block(
+        entry.message.into_bytes(),
+        Amount::from_atoms(entry.premine_atoms),
+        premine_destination,
+        entry.timestamp,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::config::{create_regtest, create_testnet};
+    use crate::primitives::Idable;
+
+    const PRIVATE_NET: &str = r#"
+        chain_type = "regtest"
+        address_prefix = "pmt"
+        magic_bytes = "0a0b0c0d"
+        rpc_port = 13030
+        p2p_port = 13031
+        fixed_seeds = ["10.0.0.1:13031"]
+
+        [[net_upgrades]]
+        height = 0
+        consensus = "ignore-consensus"
+
+        [[net_upgrades]]
+        height = 10
+        consensus = "pow"
+        initial_difficulty = "1d00ffff"
+
+        [[net_upgrades]]
+        height = 0
+        upgrade = "duplicate-tx"
+
+        [[net_upgrades]]
+        height = 10
+        upgrade = "inputless-tx"
+
+        [[net_upgrades]]
+        height = 0
+        upgrade = "block-reward"
+
+        [[checkpoints]]
+        height = 5
+        block_id = "000000000000000000000000000000000000000000000000000000000000abcd"
+
+        [genesis]
+        timestamp = 1656590400
+        message = "Private network"
+        premine_atoms = 5000
+
+        [emission_schedule]
+        initial_subsidy_atoms = 100
+        halving_interval = 10
+        supply_cap_atoms = 1000
+    "#;
+
+    #[test]
+    fn load_toml() {
+        let config = ChainConfig::from_toml_str(PRIVATE_NET).unwrap();
+
+        assert_eq!(config.chain_type(), &ChainType::Regtest);
+        assert_eq!(config.address_prefix(), "pmt");
+        assert_eq!(config.magic_bytes(), &[0x0a, 0x0b, 0x0c, 0x0d]);
+        assert_eq!(config.rpc_port(), 13030);
+        assert_eq!(config.p2p_port(), 13031);
+        assert_eq!(config.fixed_seeds(), &["10.0.0.1:13031".to_string()]);
+        assert_eq!(config.net_upgrade().len(), 5);
+        assert!(config.net_upgrade().block_reward_check_active(BlockHeight::new(0)));
+        assert!(config.net_upgrade().duplicate_tx_check_active(BlockHeight::new(0)));
+        assert!(!config.net_upgrade().inputless_tx_check_active(BlockHeight::new(9)));
+        assert!(config.net_upgrade().inputless_tx_check_active(BlockHeight::new(10)));
+        assert_eq!(config.height_checkpoints().len(), 1);
+        assert_ne!(
+            config.genesis_block_id(),
+            create_regtest().genesis_block_id()
+        );
+        assert_eq!(config.genesis_block().get_id(), config.genesis_block_id());
+        // The first ten blocks use up the supply cap
+        assert_eq!(
+            config.block_subsidy(BlockHeight::new(9)),
+            Amount::from_atoms(100)
+        );
+        assert_eq!(
+            config.block_subsidy(BlockHeight::new(10)),
+            Amount::from_atoms(0)
+        );
+    }
+
+    #[test]
+    fn load_json() {
+        let json = r#"{"chain_type": "testnet", "magic_bytes": "01020304"}"#;
+        let config = ChainConfig::from_json_str(json).unwrap();
+        let testnet = create_testnet();
+
+        assert_eq!(config.chain_type(), &ChainType::Testnet);
+        assert_eq!(config.magic_bytes(), &[1, 2, 3, 4]);
+        assert_eq!(config.p2p_port(), testnet.p2p_port());
+        assert_eq!(config.genesis_block_id(), testnet.genesis_block_id());
+    }
+
+    fn invalid_field(content: &str) -> String {
+        match ChainConfig::from_toml_str(content) {
+            Err(ChainConfigFileError::InvalidField { field, .. }) => field,
+            res => panic!("unexpected result: {:?}", res.map(|c| c.chain_type)),
+        }
+    }
+
+    #[test]
+    fn invalid_fields() {
+        assert_eq!(
+            invalid_field("chain_type = \"foonet\"\nmagic_bytes = \"01020304\""),
+            "chain_type"
+        );
+        assert_eq!(
+            invalid_field("chain_type = \"regtest\"\nmagic_bytes = \"0102\""),
+            "magic_bytes"
+        );
+        assert_eq!(
+            invalid_field("chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\np2p_port = 11111"),
+            "p2p_port"
+        );
+        assert_eq!(
+            invalid_field(
+                "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\n\
+                [[net_upgrades]]\nheight = 1\nconsensus = \"pos\""
+            ),
+            "net_upgrades"
+        );
+        assert_eq!(
+            invalid_field(
+                "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\n\
+                [[net_upgrades]]\nheight = 0\nconsensus = \"pos\"\ninitial_difficulty = \"1d00ffff\""
+            ),
+            "net_upgrades[0].initial_difficulty"
+        );
+        assert_eq!(
+            invalid_field(
+                "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\n\
+                [[net_upgrades]]\nheight = 0\nconsensus = \"pos\"\nupgrade = \"duplicate-tx\""
+            ),
+            "net_upgrades[0].upgrade"
+        );
+        assert_eq!(
+            invalid_field(
+                "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\n\
+                [[net_upgrades]]\nheight = 0\nupgrade = \"inputless-tx\"\n\
+                [[net_upgrades]]\nheight = 0\nupgrade = \"inputless-tx\""
+            ),
+            "net_upgrades[1].height"
+        );
+        assert_eq!(
+            invalid_field(
+                "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\n\
+                [[net_upgrades]]\nheight = 0\nupgrade = \"duplicate-tx\"\n\
+                [[net_upgrades]]\nheight = 1\nconsensus = \"pow\""
+            ),
+            "net_upgrades"
+        );
+        assert_eq!(
+            invalid_field(
+                "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\n\
+                [[checkpoints]]\nheight = 1\nblock_id = \"xyz\""
+            ),
+            "checkpoints[0].block_id"
+        );
+        assert_eq!(
+            invalid_field(
+                "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\n\
+                [emission_schedule]\ninitial_subsidy_atoms = 1\nhalving_interval = 0\n\
+                supply_cap_atoms = 1"
+            ),
+            "emission_schedule.halving_interval"
+        );
+    }
+
+    #[test]
+    fn total_supply_overflow() {
+        let json = r#"{
+            "chain_type": "regtest",
+            "magic_bytes": "01020304",
+            "emission_schedule": {
+                "initial_subsidy_atoms": 1,
+                "halving_interval": 1,
+                "supply_cap_atoms": 340282366920938463463374607431768211455
+            }
+        }"#;
+        match ChainConfig::from_json_str(json) {
+            Err(ChainConfigFileError::InvalidField { field, .. }) => {
+                assert_eq!(field, "emission_schedule.supply_cap_atoms")
+            }
+            res => panic!("unexpected result: {:?}", res.map(|c| c.chain_type)),
+        }
+    }
+
+    #[test]
+    fn max_money_follows_overrides() {
+        let config = ChainConfig::from_toml_str(PRIVATE_NET).unwrap();
+        assert_eq!(config.max_money(), Amount::from_atoms(6000));
+    }
+
+    #[test]
+    fn unknown_field_rejected() {
+        let res = ChainConfig::from_toml_str(
+            "chain_type = \"regtest\"\nmagic_bytes = \"01020304\"\nrpc_prot = 1",
+        );
+        match res {
+            Err(ChainConfigFileError::Parse(msg)) => assert!(msg.contains("rpc_prot")),
+            res => panic!("unexpected result: {:?}", res.map(|c| c.chain_type)),
+        }
+    }
+}
@@ -1,8 +1,10 @@
 pub mod block;
 pub mod config;
 mod pow;
+pub mod services;
 pub mod transaction;
 mod upgrades;
+pub mod whitelist;
 
 pub use transaction::*;
 
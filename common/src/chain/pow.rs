@@ -55,14 +55,14 @@ impl PoWChainConfig {
 
 const fn no_retargeting(chain_type: ChainType) -> bool {
     match chain_type {
-        ChainType::Mainnet | ChainType::Testnet | ChainType::Signet => false,
+        ChainType::Mainnet | ChainType::Testnet => false,
         ChainType::Regtest => true,
     }
 }
 
 const fn allow_min_difficulty_blocks(chain_type: ChainType) -> bool {
     match chain_type {
-        ChainType::Mainnet | ChainType::Signet => false,
+        ChainType::Mainnet => false,
         ChainType::Testnet | ChainType::Regtest => true,
     }
 }
@@ -75,12 +75,6 @@ pub(crate) const fn limit(chain_type: ChainType) -> Uint256 {
             0xFFFFFFFFFFFFFFFF,
             0x00000000FFFFFFFF,
         ]),
-        ChainType::Signet => Uint256([
-            0x0000000000000000,
-            0x0000000000000000,
-            0x0000000000000000,
-            0x00000377AE000000,
-        ]),
         ChainType::Regtest => Uint256([
             0xFFFFFFFFFFFFFFFF,
             0xFFFFFFFFFFFFFFFF,
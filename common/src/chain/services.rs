@@ -0,0 +1,163 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Services a node provides to its peers
+//!
+//! The services are advertised in the handshake as a set of flags. In configuration
+//! they are written as a comma-separated list of service names, for example
+//! `full-blocks,headers`, or `none` for the empty set.
+
+use serialization::{Decode, Encode};
+use std::{fmt, ops::BitOr, str::FromStr};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq, Clone)]
+pub enum ServicesError {
+    #[error("Unknown service: {0}")]
+    UnknownService(String),
+}
+
+/// Set of service flags
+///
+/// Flags that are not known to the local node are preserved so that they
+/// can be told apart from services the peer doesn't provide.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Services(u64);
+
+impl Services {
+    /// Full blocks can be downloaded from the node
+    pub const FULL_BLOCKS: Services = Services(1 << 0);
+
+    /// Block headers can be downloaded from the node
+    pub const HEADERS: Services = Services(1 << 1);
+
+    /// Names of the known services, used when parsing and displaying the flags
+    const NAMES: [(Services, &'static str); 2] =
+        [(Self::FULL_BLOCKS, "full-blocks"), (Self::HEADERS, "headers")];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Check whether all services of `other` are also in `self`
+    pub const fn contains(&self, other: Services) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Services {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl FromStr for Services {
+    type Err = ServicesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(Self::empty());
+        }
+
+        s.split(',').try_fold(Self::empty(), |services, name| {
+            Self::NAMES
+                .iter()
+                .find(|(_, known)| *known == name)
+                .map(|(service, _)| services | *service)
+                .ok_or_else(|| ServicesError::UnknownService(name.to_string()))
+        })
+    }
+}
+
+impl fmt::Display for Services {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = Self::NAMES
+            .iter()
+            .filter(|(service, _)| self.contains(*service))
+            .map(|(_, name)| name.to_string())
+            .collect::<Vec<_>>();
+
+        let known = Self::NAMES.iter().fold(Self::empty(), |all, (service, _)| all | *service);
+        let unknown = self.0 & !known.0;
+        if unknown != 0 {
+            names.push(format!("{:#x}", unknown));
+        }
+
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(","))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_services() {
+        assert_eq!("none".parse(), Ok(Services::empty()));
+        assert_eq!("headers".parse(), Ok(Services::HEADERS));
+        assert_eq!(
+            "headers,full-blocks".parse(),
+            Ok(Services::FULL_BLOCKS | Services::HEADERS)
+        );
+        assert_eq!(
+            "full-blocks,utxos".parse::<Services>(),
+            Err(ServicesError::UnknownService("utxos".to_string()))
+        );
+        assert_eq!(
+            "".parse::<Services>(),
+            Err(ServicesError::UnknownService("".to_string()))
+        );
+    }
+
+    #[test]
+    fn display_services() {
+        assert_eq!(Services::empty().to_string(), "none");
+        assert_eq!(
+            (Services::FULL_BLOCKS | Services::HEADERS).to_string(),
+            "full-blocks,headers"
+        );
+        assert_eq!(
+            (Services::HEADERS | Services::from_bits(1 << 8)).to_string(),
+            "headers,0x100"
+        );
+    }
+
+    #[test]
+    fn contains_services() {
+        let services = Services::FULL_BLOCKS | Services::HEADERS;
+
+        assert!(services.contains(Services::HEADERS));
+        assert!(services.contains(Services::empty()));
+        assert!(!Services::HEADERS.contains(services));
+        assert!(!Services::empty().contains(Services::FULL_BLOCKS));
+    }
+}
@@ -31,7 +31,7 @@ pub mod signature;
 pub mod transaction_index;
 pub use transaction_index::*;
 
-use self::signature::inputsig::InputWitness;
+use self::signature::inputsig::{InputSignatureKind, InputWitness};
 
 mod transaction_v1;
 
@@ -56,6 +56,13 @@ impl Idable for Transaction {
     }
 }
 
+/// Tag of the witness transaction IDs
+///
+/// Unlike the transaction ID, the witness transaction ID covers the witnesses, so it tells apart
+/// transactions that only differ in their signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitnessTransaction {}
+
 #[derive(Debug, Clone)]
 pub enum TransactionCreationError {
     Unknown,
@@ -119,9 +126,9 @@ impl Transaction {
     }
 
     /// provides the hash of a transaction including the witness (malleable)
-    pub fn get_serialized_hash(&self) -> Id<Transaction> {
+    pub fn get_witness_id(&self) -> Id<WitnessTransaction> {
         match &self {
-            Transaction::V1(tx) => tx.get_serialized_hash(),
+            Transaction::V1(tx) => tx.get_witness_id(),
         }
     }
 
@@ -134,11 +141,36 @@ impl Transaction {
             Transaction::V1(tx) => tx.update_witness(input_index, witness),
         }
     }
+
+    /// Weight of the transaction counted against `MAX_BLOCK_WEIGHT`, its encoded size
+    pub fn weight(&self) -> usize {
+        self.encoded_size()
+    }
+
+    /// Encoded size of the transaction once each input is signed with the witness of the given
+    /// kind, replacing the current witnesses; `None` if the number of kinds and inputs differ
+    pub fn estimated_signed_size(&self, input_kinds: &[InputSignatureKind]) -> Option<usize> {
+        if input_kinds.len() != self.get_inputs().len() {
+            return None;
+        }
+        let current_witnesses: usize =
+            self.get_inputs().iter().map(|input| input.get_witness().encoded_size()).sum();
+        let signed_witnesses: usize =
+            input_kinds.iter().map(|kind| InputWitness::estimated_size(*kind)).sum();
+        Some(self.encoded_size() - current_witnesses + signed_witnesses)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::address::pubkeyhash::PublicKeyHash;
+    use crate::chain::signature::inputsig::{
+        classic_multisig::ClassicMultisigChallenge, StandardInputSignature,
+    };
+    use crate::chain::signature::sighashtype::SigHashType;
+    use crate::primitives::{Amount, H256};
+    use crypto::key::{KeyKind, PrivateKey};
     use crypto::random::{RngCore, SeedableRng};
 
     #[test]
@@ -156,4 +188,86 @@ mod test {
         // let's ensure that flags comes right after that
         assert_eq!(u32::decode(&mut &encoded_tx[1..5]).unwrap(), flags);
     }
+
+    #[test]
+    fn witness_id() {
+        let input = TxInput::new(
+            OutPointSourceId::Transaction(Id::new(&H256::random())),
+            0,
+            InputWitness::NoSignature(None),
+        );
+        let mut tx = Transaction::new(0, vec![input], vec![], 0).unwrap();
+        let (id, witness_id) = (tx.get_id(), tx.get_witness_id());
+        assert_ne!(id.get(), witness_id.get());
+
+        // only the witness ID covers the witnesses
+        tx.update_witness(0, InputWitness::NoSignature(Some(vec![1]))).unwrap();
+        assert_eq!(tx.get_id(), id);
+        assert_ne!(tx.get_witness_id(), witness_id);
+    }
+
+    #[test]
+    fn estimated_signed_size() {
+        let keys: Vec<_> = (0..3).map(|_| PrivateKey::new(KeyKind::RistrettoSchnorr)).collect();
+        let challenge =
+            ClassicMultisigChallenge::new(2, keys.iter().map(|(_, pk)| pk.clone()).collect())
+                .unwrap();
+        let destinations = [
+            Destination::AnyoneCanSpend,
+            Destination::PublicKey(keys[0].1.clone()),
+            Destination::Address(PublicKeyHash::from(&keys[0].1)),
+            Destination::ClassicMultisig(challenge.clone()),
+        ];
+        let kinds = [
+            InputSignatureKind::NoSignature,
+            InputSignatureKind::PublicKey,
+            InputSignatureKind::PublicKeyHash,
+            InputSignatureKind::ClassicMultisig(2),
+        ];
+
+        let inputs = (0..destinations.len())
+            .map(|i| {
+                TxInput::new(
+                    OutPointSourceId::Transaction(Id::new(&H256::random())),
+                    i as u32,
+                    InputWitness::NoSignature(None),
+                )
+            })
+            .collect();
+        let outputs = vec![TxOutput::new(
+            Amount::from_atoms(100),
+            Destination::PublicKey(keys[1].1.clone()),
+        )];
+        let mut tx = Transaction::new(0, inputs, outputs, 0).unwrap();
+        assert_eq!(tx.estimated_signed_size(&kinds[1..]), None);
+        let estimate = tx.estimated_signed_size(&kinds).unwrap();
+
+        let sighash_type = SigHashType::try_from(SigHashType::ALL).unwrap();
+        for (input_num, destination) in destinations.iter().enumerate().skip(1) {
+            let mut signature = StandardInputSignature::produce_signature_for_input(
+                &keys[0].0,
+                sighash_type,
+                destination.clone(),
+                &tx,
+                input_num,
+            )
+            .unwrap();
+            if let Destination::ClassicMultisig(challenge) = destination {
+                signature = StandardInputSignature::produce_classical_multisig_signature_for_input(
+                    &keys[2].0,
+                    challenge,
+                    Some(&signature),
+                    sighash_type,
+                    &tx,
+                    input_num,
+                )
+                .unwrap();
+            }
+            tx.update_witness(input_num, InputWitness::Standard(signature)).unwrap();
+        }
+
+        assert_eq!(tx.encoded_size(), estimate);
+        assert_eq!(tx.weight(), estimate);
+        assert_eq!(tx.estimated_signed_size(&kinds), Some(estimate));
+    }
 }
@@ -1,10 +1,19 @@
+use std::collections::BTreeSet;
+
+use crypto::key::{KeyKind, PublicKey};
+
 use crate::{
     address::pubkeyhash::PublicKeyHash,
+    chain::signature::inputsig::classic_multisig::ClassicMultisigChallenge,
     primitives::{Amount, Id},
 };
 use script::Script;
-use serialization::{Decode, Encode};
+use serialization::{Decode, Encode, Error, Input, Output};
+
+mod timelock;
+pub use timelock::OutputTimeLock;
 
+// Codec index 255 is reserved, it marks timelocked outputs in the encoding of `TxOutput`
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
 pub enum Destination {
     #[codec(index = 0)]
@@ -15,12 +24,41 @@ pub enum Destination {
     ScriptHash(Id<Script>),
     #[codec(index = 3)]
     AnyoneCanSpend, // zero verification; used primarily for testing. Never use this for real money
+    #[codec(index = 4)]
+    ClassicMultisig(ClassicMultisigChallenge),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+impl Destination {
+    /// Kinds of the public keys the destination is locked to
+    ///
+    /// Keys behind address and script hashes are only revealed by the witness spending them.
+    pub fn key_kinds(&self) -> BTreeSet<KeyKind> {
+        match self {
+            Destination::PublicKey(public_key) => BTreeSet::from([public_key.kind()]),
+            Destination::ClassicMultisig(challenge) => {
+                challenge.public_keys().iter().map(PublicKey::kind).collect()
+            }
+            Destination::Address(_) | Destination::ScriptHash(_) | Destination::AnyoneCanSpend => {
+                BTreeSet::new()
+            }
+        }
+    }
+}
+
+/// Takes the place of the destination index in the encoding of timelocked outputs
+const TIMELOCK_MARKER: u8 = u8::MAX;
+
+/// Output of a transaction
+///
+/// Outputs without a timelock encode as the value followed by the destination, like they did
+/// before timelocks were added, so their transaction IDs didn't change. Timelocked outputs insert
+/// `TIMELOCK_MARKER` and the timelock before the destination. They are only valid in blocks once
+/// `UpgradeVersion::TimeLockUpgrade` is active.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TxOutput {
     value: Amount,
     dest: Destination,
+    timelock: Option<OutputTimeLock>,
 }
 
 impl TxOutput {
@@ -28,6 +66,19 @@ impl TxOutput {
         TxOutput {
             value,
             dest: destination,
+            timelock: None,
+        }
+    }
+
+    pub fn new_with_timelock(
+        value: Amount,
+        destination: Destination,
+        timelock: OutputTimeLock,
+    ) -> Self {
+        TxOutput {
+            value,
+            dest: destination,
+            timelock: Some(timelock),
         }
     }
 
@@ -38,4 +89,105 @@ impl TxOutput {
     pub fn get_destination(&self) -> &Destination {
         &self.dest
     }
+
+    pub fn get_timelock(&self) -> Option<OutputTimeLock> {
+        self.timelock
+    }
+}
+
+impl Encode for TxOutput {
+    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+        self.value.encode_to(dest);
+        if let Some(timelock) = &self.timelock {
+            dest.push_byte(TIMELOCK_MARKER);
+            timelock.encode_to(dest);
+        }
+        self.dest.encode_to(dest);
+    }
+}
+
+impl Decode for TxOutput {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+        let value = Amount::decode(input)?;
+        let first = input.read_byte()?;
+        let (timelock, dest) = if first == TIMELOCK_MARKER {
+            let timelock = OutputTimeLock::decode(input)?;
+            (Some(timelock), Destination::decode(input)?)
+        } else {
+            let mut input = Prepended {
+                byte: Some(first),
+                input,
+            };
+            (None, Destination::decode(&mut input)?)
+        };
+        Ok(TxOutput {
+            value,
+            dest,
+            timelock,
+        })
+    }
+}
+
+/// Input that yields a byte already read from the inner input before the rest of it
+struct Prepended<'a, I> {
+    byte: Option<u8>,
+    input: &'a mut I,
+}
+
+impl<I: Input> Input for Prepended<'_, I> {
+    fn remaining_len(&mut self) -> Result<Option<usize>, Error> {
+        let remaining = self.input.remaining_len()?;
+        Ok(remaining.map(|len| len + usize::from(self.byte.is_some())))
+    }
+
+    fn read(&mut self, into: &mut [u8]) -> Result<(), Error> {
+        match (self.byte, into.split_first_mut()) {
+            (Some(byte), Some((first, rest))) => {
+                self.byte = None;
+                *first = byte;
+                self.input.read(rest)
+            }
+            _ => self.input.read(into),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::primitives::BlockHeight;
+    use serialization::DecodeAll;
+
+    #[test]
+    fn encoding() {
+        let output = TxOutput::new(Amount::from_atoms(5), Destination::AnyoneCanSpend);
+        let encoded = output.encode();
+        assert_eq!(
+            encoded,
+            (Amount::from_atoms(5), Destination::AnyoneCanSpend).encode()
+        );
+        assert_eq!(
+            TxOutput::decode_all(&mut encoded.as_slice()).unwrap(),
+            output
+        );
+
+        let timelock = OutputTimeLock::UntilHeight(BlockHeight::new(10));
+        let output = TxOutput::new_with_timelock(
+            Amount::from_atoms(5),
+            Destination::AnyoneCanSpend,
+            timelock,
+        );
+        let encoded = output.encode();
+        let value_len = Amount::from_atoms(5).encoded_size();
+        assert_eq!(encoded[value_len], TIMELOCK_MARKER);
+        assert_eq!(
+            TxOutput::decode_all(&mut encoded.as_slice()).unwrap(),
+            output
+        );
+
+        let mut invalid =
+            TxOutput::new(Amount::from_atoms(5), Destination::AnyoneCanSpend).encode();
+        invalid[value_len] = TIMELOCK_MARKER - 1;
+        assert!(TxOutput::decode_all(&mut invalid.as_slice()).is_err());
+    }
 }
@@ -0,0 +1,75 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::primitives::BlockHeight;
+use serialization::{Decode, Encode};
+
+/// Condition that has to be met before an output can be spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+pub enum OutputTimeLock {
+    /// Spendable in blocks at this height or above
+    #[codec(index = 0)]
+    UntilHeight(BlockHeight),
+    /// Spendable once the median time past of the previous blocks reaches this timestamp
+    #[codec(index = 1)]
+    UntilTime(u32),
+    /// Spendable once the given number of blocks passed since the output was confirmed
+    #[codec(index = 2)]
+    ForBlockCount(#[codec(compact)] u64),
+}
+
+impl OutputTimeLock {
+    /// Whether an output confirmed at `source_height` can be spent in a block at `spend_height`
+    /// whose previous blocks have the median time past `median_time_past`
+    pub fn is_unlocked(
+        &self,
+        source_height: BlockHeight,
+        spend_height: BlockHeight,
+        median_time_past: u32,
+    ) -> bool {
+        match self {
+            OutputTimeLock::UntilHeight(height) => spend_height >= *height,
+            OutputTimeLock::UntilTime(time) => median_time_past >= *time,
+            OutputTimeLock::ForBlockCount(count) => source_height
+                .checked_add(*count)
+                .map_or(false, |unlock_height| spend_height >= unlock_height),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unlocking() {
+        let source = BlockHeight::new(10);
+
+        let lock = OutputTimeLock::UntilHeight(BlockHeight::new(20));
+        assert!(!lock.is_unlocked(source, BlockHeight::new(19), u32::MAX));
+        assert!(lock.is_unlocked(source, BlockHeight::new(20), 0));
+
+        let lock = OutputTimeLock::UntilTime(1000);
+        assert!(!lock.is_unlocked(source, BlockHeight::max(), 999));
+        assert!(lock.is_unlocked(source, BlockHeight::new(10), 1000));
+
+        let lock = OutputTimeLock::ForBlockCount(5);
+        assert!(!lock.is_unlocked(source, BlockHeight::new(14), u32::MAX));
+        assert!(lock.is_unlocked(source, BlockHeight::new(15), 0));
+
+        let lock = OutputTimeLock::ForBlockCount(u64::MAX);
+        assert!(!lock.is_unlocked(source, BlockHeight::max(), u32::MAX));
+    }
+}
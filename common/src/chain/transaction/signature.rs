@@ -15,14 +15,17 @@
 //
 // Author(s): S. Afach & L. Kuklinek
 
-use crypto::hash::StreamHasher;
+use crypto::{hash::StreamHasher, key::parallel::ParallelVerifier};
 
 use crate::primitives::{
     id::{hash_encoded_to, DefaultHashAlgoStream},
     H256,
 };
 
-use self::inputsig::StandardInputSignature;
+use self::inputsig::{
+    authorize_script_spend::{verify_script_spending, ScriptSpendContext},
+    StandardInputSignature,
+};
 
 use super::{Destination, Transaction};
 
@@ -59,6 +62,20 @@ pub enum TransactionSigError {
     AttemptedToVerifyStandardSignatureForAnyoneCanSpend,
     #[error("AnyoneCanSpend should not use standard signatures, so producing a signature for it is not possible")]
     AttemptedToProduceSignatureForAnyoneCanSpend,
+    #[error("Scripts are not activated at this height")]
+    ScriptsNotActivated,
+    #[error("Lock script does not match the script hash of the spent output")]
+    ScriptHashMismatch,
+    #[error("Script verification failed: {0}")]
+    ScriptVerificationFailed(script::Error),
+    #[error("Script witness can only be used to spend script hash outputs")]
+    ScriptWitnessForNonScriptDestination,
+    #[error("Invalid classical multisig challenge: {0}")]
+    InvalidClassicalMultisig(inputsig::classic_multisig::ClassicMultisigChallengeError),
+    #[error("Classical multisig requires {0} signatures but only {1} were provided")]
+    IncompleteClassicalMultisigSignature(u8, usize),
+    #[error("Classical multisig signature for key index {0} but the challenge has {1} keys")]
+    InvalidClassicalMultisigKeyIndex(u8, usize),
     #[error("Unsupported yet!")]
     Unsupported,
 }
@@ -67,6 +84,27 @@ pub fn signature_hash(
     mode: sighashtype::SigHashType,
     tx: &Transaction,
     input_num: usize,
+) -> Result<H256, TransactionSigError> {
+    signature_hash_impl(mode, tx, input_num, None)
+}
+
+/// Signature hash for signature checks in scripts, which also commits to the executed part of
+/// the script following the last OP_CODESEPARATOR
+pub fn script_signature_hash(
+    mode: sighashtype::SigHashType,
+    tx: &Transaction,
+    input_num: usize,
+    subscript: &[u8],
+    codesep_idx: u32,
+) -> Result<H256, TransactionSigError> {
+    signature_hash_impl(mode, tx, input_num, Some((subscript, codesep_idx)))
+}
+
+fn signature_hash_impl(
+    mode: sighashtype::SigHashType,
+    tx: &Transaction,
+    input_num: usize,
+    script_code: Option<(&[u8], u32)>,
 ) -> Result<H256, TransactionSigError> {
     let mut stream = DefaultHashAlgoStream::new();
 
@@ -110,8 +148,13 @@ pub fn signature_hash(
 
     hash_encoded_to(&tx.get_lock_time(), &mut stream);
 
-    // TODO: for P2SH add OP_CODESEPARATOR position
-    hash_encoded_to(&u32::MAX, &mut stream);
+    match script_code {
+        None => hash_encoded_to(&u32::MAX, &mut stream),
+        Some((subscript, codesep_idx)) => {
+            hash_encoded_to(&codesep_idx, &mut stream);
+            hash_encoded_to(&subscript, &mut stream);
+        }
+    }
 
     let result = stream.finalize().into();
     Ok(result)
@@ -122,9 +165,15 @@ fn verify_standard_input_signature(
     witness: &StandardInputSignature,
     tx: &Transaction,
     input_num: usize,
+    verifier: Option<&mut ParallelVerifier>,
 ) -> Result<(), TransactionSigError> {
     let sighash = signature_hash(witness.sighash_type(), tx, input_num)?;
-    witness.verify_signature(outpoint_destination, &sighash)?;
+    match verifier {
+        Some(verifier) => {
+            witness.verify_signature_parallel(outpoint_destination, &sighash, verifier)?
+        }
+        None => witness.verify_signature(outpoint_destination, &sighash)?,
+    }
     Ok(())
 }
 
@@ -132,6 +181,44 @@ pub fn verify_signature(
     outpoint_destination: &Destination,
     tx: &Transaction,
     input_num: usize,
+) -> Result<(), TransactionSigError> {
+    verify_signature_with_script_context(outpoint_destination, tx, input_num, None)
+}
+
+/// Like `verify_signature`, but script hash outputs can be spent if the script context is given
+pub fn verify_signature_with_script_context(
+    outpoint_destination: &Destination,
+    tx: &Transaction,
+    input_num: usize,
+    script_context: Option<&ScriptSpendContext>,
+) -> Result<(), TransactionSigError> {
+    verify_signature_impl(outpoint_destination, tx, input_num, script_context, None)
+}
+
+/// Like `verify_signature_with_script_context`, but the single key signatures are queued in
+/// the parallel verifier and only checked when it runs
+pub fn verify_signature_parallel_with_script_context(
+    outpoint_destination: &Destination,
+    tx: &Transaction,
+    input_num: usize,
+    script_context: Option<&ScriptSpendContext>,
+    verifier: &mut ParallelVerifier,
+) -> Result<(), TransactionSigError> {
+    verify_signature_impl(
+        outpoint_destination,
+        tx,
+        input_num,
+        script_context,
+        Some(verifier),
+    )
+}
+
+fn verify_signature_impl(
+    outpoint_destination: &Destination,
+    tx: &Transaction,
+    input_num: usize,
+    script_context: Option<&ScriptSpendContext>,
+    verifier: Option<&mut ParallelVerifier>,
 ) -> Result<(), TransactionSigError> {
     let target_input = tx
         .get_inputs()
@@ -143,11 +230,23 @@ pub fn verify_signature(
             Destination::Address(_) => return Err(TransactionSigError::SignatureNotFound),
             Destination::PublicKey(_) => return Err(TransactionSigError::SignatureNotFound),
             Destination::ScriptHash(_) => return Err(TransactionSigError::SignatureNotFound),
+            Destination::ClassicMultisig(_) => return Err(TransactionSigError::SignatureNotFound),
             Destination::AnyoneCanSpend => {}
         },
         inputsig::InputWitness::Standard(witness) => {
-            verify_standard_input_signature(outpoint_destination, witness, tx, input_num)?
+            verify_standard_input_signature(outpoint_destination, witness, tx, input_num, verifier)?
         }
+        inputsig::InputWitness::Script(witness) => match outpoint_destination {
+            Destination::ScriptHash(script_hash) => {
+                verify_script_spending(script_hash, witness, tx, input_num, script_context)?
+            }
+            Destination::Address(_)
+            | Destination::PublicKey(_)
+            | Destination::AnyoneCanSpend
+            | Destination::ClassicMultisig(_) => {
+                return Err(TransactionSigError::ScriptWitnessForNonScriptDestination)
+            }
+        },
     }
     Ok(())
 }
@@ -156,18 +255,28 @@ pub fn verify_signature(
 #[cfg(test)]
 mod test {
     use super::{
-        inputsig::{InputWitness, StandardInputSignature},
+        inputsig::{
+            authorize_script_spend::{
+                script_hash, sign_script_spending, ScriptInputWitness, ScriptSpendContext,
+            },
+            classic_multisig::ClassicMultisigChallenge,
+            InputWitness, StandardInputSignature,
+        },
         sighashtype::SigHashType,
     };
     use crate::{
         chain::{
-            signature::{verify_signature, TransactionSigError},
-            Destination, OutPointSourceId, Transaction, TransactionCreationError, TxInput,
-            TxOutput,
+            signature::{
+                verify_signature, verify_signature_with_script_context, TransactionSigError,
+            },
+            Destination, OutPointSourceId, ScriptFlags, Transaction, TransactionCreationError,
+            TxInput, TxOutput,
         },
-        primitives::{Amount, Id, H256},
+        primitives::{Amount, BlockHeight, Id, H256},
     };
-    use crypto::key::{KeyKind, PrivateKey};
+    use crypto::key::{KeyKind, PrivateKey, PublicKey};
+    use script::{opcodes::all::OP_CHECKSIG, Builder};
+    use serialization::Encode;
     use std::vec;
 
     // This is required because we can't access private fields of the Transaction class
@@ -485,7 +594,7 @@ mod test {
                 raw_signature.extend(body_signature);
                 StandardInputSignature::new(signature.sighash_type(), raw_signature)
             }
-            InputWitness::NoSignature(_) => unreachable!(),
+            InputWitness::NoSignature(_) | InputWitness::Script(_) => unreachable!(),
         };
         tx_updater.inputs[0].update_witness(InputWitness::Standard(signature));
         let tx = tx_updater.generate_tx().unwrap();
@@ -517,4 +626,218 @@ mod test {
             Err(TransactionSigError::SignatureVerificationFailed)
         );
     }
+
+    #[test]
+    fn sign_and_verify_classic_multisig() {
+        let keys: Vec<_> = (0..3).map(|_| PrivateKey::new(KeyKind::RistrettoSchnorr)).collect();
+        let challenge =
+            ClassicMultisigChallenge::new(2, keys.iter().map(|(_, pk)| pk.clone()).collect())
+                .unwrap();
+        let outpoint_dest = Destination::ClassicMultisig(challenge.clone());
+        let sighash_type = SigHashType::try_from(SigHashType::ALL).unwrap();
+        let mut tx = generate_unsigned_tx(outpoint_dest.clone()).unwrap();
+        assert_eq!(
+            verify_signature(&outpoint_dest, &tx, 0),
+            Err(TransactionSigError::SignatureNotFound)
+        );
+
+        // A single signature is not enough
+        sign_tx(&mut tx, &keys[1].0, sighash_type, outpoint_dest.clone());
+        assert_eq!(
+            verify_signature(&outpoint_dest, &tx, 0),
+            Err(TransactionSigError::IncompleteClassicalMultisigSignature(
+                2, 1
+            ))
+        );
+
+        let current_signature = match tx.get_inputs()[0].get_witness() {
+            InputWitness::Standard(signature) => signature.clone(),
+            InputWitness::NoSignature(_) | InputWitness::Script(_) => unreachable!(),
+        };
+        let input_sign = StandardInputSignature::produce_classical_multisig_signature_for_input(
+            &keys[0].0,
+            &challenge,
+            Some(&current_signature),
+            sighash_type,
+            &tx,
+            0,
+        )
+        .unwrap();
+        tx.update_witness(0, InputWitness::Standard(input_sign)).unwrap();
+        assert_eq!(verify_signature(&outpoint_dest, &tx, 0), Ok(()));
+    }
+
+    #[test]
+    fn sign_and_verify_script_spend() {
+        let (private_key, public_key) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let lock_script = Builder::new()
+            .push_slice(&public_key.encode())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let outpoint_dest = Destination::ScriptHash(script_hash(&lock_script));
+        let sighash_type = SigHashType::try_from(SigHashType::ALL).unwrap();
+        let context = ScriptSpendContext::new(ScriptFlags::default(), BlockHeight::new(1));
+        let mut tx = generate_unsigned_tx(outpoint_dest.clone()).unwrap();
+
+        let sig = sign_script_spending(&private_key, sighash_type, &lock_script, &tx, 0).unwrap();
+        let witness_script = Builder::new().push_slice(&sig).into_script();
+        let witness = ScriptInputWitness::new(lock_script.clone(), witness_script.clone());
+        tx.update_witness(0, InputWitness::Script(witness)).unwrap();
+        assert_eq!(
+            verify_signature_with_script_context(&outpoint_dest, &tx, 0, Some(&context)),
+            Ok(())
+        );
+        assert_eq!(
+            verify_signature(&outpoint_dest, &tx, 0),
+            Err(TransactionSigError::ScriptsNotActivated)
+        );
+
+        // A signature for the wrong key
+        let (other_private_key, _) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let sig =
+            sign_script_spending(&other_private_key, sighash_type, &lock_script, &tx, 0).unwrap();
+        let witness = ScriptInputWitness::new(
+            lock_script.clone(),
+            Builder::new().push_slice(&sig).into_script(),
+        );
+        tx.update_witness(0, InputWitness::Script(witness)).unwrap();
+        assert_eq!(
+            verify_signature_with_script_context(&outpoint_dest, &tx, 0, Some(&context)),
+            Err(TransactionSigError::ScriptVerificationFailed(
+                script::Error::VerifyFail
+            ))
+        );
+
+        // The lock script has to match the script hash
+        let other_script = Builder::new().push_int(1).into_script();
+        let witness = ScriptInputWitness::new(other_script, witness_script);
+        tx.update_witness(0, InputWitness::Script(witness)).unwrap();
+        assert_eq!(
+            verify_signature_with_script_context(&outpoint_dest, &tx, 0, Some(&context)),
+            Err(TransactionSigError::ScriptHashMismatch)
+        );
+    }
+
+    #[test]
+    fn secp256k1_schnorr_key_kinds() {
+        use crate::address::pubkeyhash::PublicKeyHash;
+        use std::collections::BTreeSet;
+
+        let (private_key, public_key) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+        let sighash_type = SigHashType::try_from(SigHashType::ALL).unwrap();
+        let secp_kinds = BTreeSet::from([KeyKind::Secp256k1Schnorr]);
+
+        for outpoint_dest in [
+            Destination::PublicKey(public_key.clone()),
+            Destination::Address(PublicKeyHash::from(&public_key)),
+        ] {
+            let mut tx = generate_unsigned_tx(outpoint_dest.clone()).unwrap();
+            assert_eq!(
+                tx.get_inputs()[0].get_witness().key_kinds(&outpoint_dest).is_empty(),
+                matches!(outpoint_dest, Destination::Address(_))
+            );
+            sign_tx(&mut tx, &private_key, sighash_type, outpoint_dest.clone());
+            assert_eq!(verify_signed_tx(&tx, &outpoint_dest), Ok(()));
+            assert_eq!(
+                tx.get_inputs()[0].get_witness().key_kinds(&outpoint_dest),
+                secp_kinds
+            );
+        }
+
+        let lock_script = Builder::new()
+            .push_slice(&public_key.encode())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let witness = ScriptInputWitness::new(lock_script.clone(), Builder::new().into_script());
+        assert_eq!(witness.key_kinds(), secp_kinds);
+        assert_eq!(
+            InputWitness::Script(witness)
+                .key_kinds(&Destination::ScriptHash(script_hash(&lock_script))),
+            secp_kinds
+        );
+    }
+
+    #[test]
+    fn verify_signatures_parallel() {
+        use super::verify_signature_parallel_with_script_context;
+        use crate::address::pubkeyhash::PublicKeyHash;
+        use crypto::key::parallel::{ParallelVerificationError, ParallelVerifier};
+
+        let (private_key, public_key) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+        let (other_private_key, _) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+        let sighash_type = SigHashType::try_from(SigHashType::ALL).unwrap();
+
+        for outpoint_dest in [
+            Destination::PublicKey(public_key.clone()),
+            Destination::Address(PublicKeyHash::from(&public_key)),
+        ] {
+            let mut tx = generate_unsigned_tx(outpoint_dest.clone()).unwrap();
+            sign_tx(&mut tx, &private_key, sighash_type, outpoint_dest.clone());
+            let mut verifier = ParallelVerifier::new();
+            assert_eq!(
+                verify_signature_parallel_with_script_context(
+                    &outpoint_dest,
+                    &tx,
+                    0,
+                    None,
+                    &mut verifier
+                ),
+                Ok(())
+            );
+            assert_eq!(verifier.len(), 1);
+            assert_eq!(verifier.verify(), Ok(()));
+        }
+
+        // A wrong signature for a public key is only detected when the parallel verifier runs
+        let outpoint_dest = Destination::PublicKey(public_key.clone());
+        let mut tx = generate_unsigned_tx(outpoint_dest.clone()).unwrap();
+        let input_sign = StandardInputSignature::produce_signature_for_input(
+            &other_private_key,
+            sighash_type,
+            Destination::PublicKey(PublicKey::from_private_key(&other_private_key)),
+            &tx,
+            0,
+        )
+        .unwrap();
+        tx.update_witness(0, InputWitness::Standard(input_sign)).unwrap();
+        let mut verifier = ParallelVerifier::new();
+        assert_eq!(
+            verify_signature_parallel_with_script_context(
+                &outpoint_dest,
+                &tx,
+                0,
+                None,
+                &mut verifier
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify(),
+            Err(ParallelVerificationError::InvalidSignature(0))
+        );
+        assert_eq!(
+            verify_signature(&outpoint_dest, &tx, 0),
+            Err(TransactionSigError::SignatureVerificationFailed)
+        );
+
+        // Address mismatches are detected right away
+        let outpoint_dest = Destination::Address(PublicKeyHash::from(&public_key));
+        let other_dest = Destination::Address(PublicKeyHash::from(&PublicKey::from_private_key(
+            &other_private_key,
+        )));
+        let mut tx = generate_unsigned_tx(outpoint_dest.clone()).unwrap();
+        sign_tx(&mut tx, &other_private_key, sighash_type, other_dest);
+        let mut verifier = ParallelVerifier::new();
+        assert_eq!(
+            verify_signature_parallel_with_script_context(
+                &outpoint_dest,
+                &tx,
+                0,
+                None,
+                &mut verifier
+            ),
+            Err(TransactionSigError::PublicKeyToAddressMismatch)
+        );
+        assert!(verifier.is_empty());
+    }
 }
@@ -17,9 +17,13 @@
 
 mod authorize_pubkey_spend;
 mod authorize_pubkeyhash_spend;
+pub mod authorize_script_spend;
+pub mod classic_multisig;
 
-use std::io::BufWriter;
+use std::{collections::BTreeSet, io::BufWriter};
 
+use crypto::key::{parallel::ParallelVerifier, KeyKind, PrivateKey, PublicKey, Signature};
+use lazy_static::lazy_static;
 use parity_scale_codec::{Decode, DecodeAll, Encode};
 
 use crate::{
@@ -29,10 +33,16 @@ use crate::{
 
 use self::{
     authorize_pubkey_spend::{
-        sign_pubkey_spending, verify_public_key_spending, AuthorizedPublicKeySpend,
+        sign_pubkey_spending, verify_public_key_spending, verify_public_key_spending_parallel,
+        AuthorizedPublicKeySpend,
     },
     authorize_pubkeyhash_spend::{
-        sign_address_spending, verify_address_spending, AuthorizedPublicKeyHashSpend,
+        sign_address_spending, verify_address_spending, verify_address_spending_parallel,
+        AuthorizedPublicKeyHashSpend,
+    },
+    classic_multisig::{
+        sign_classical_multisig_spending, verify_classical_multisig_spending,
+        AuthorizedClassicalMultisigSpend, ClassicMultisigChallenge,
     },
 };
 
@@ -47,6 +57,8 @@ pub enum InputWitness {
     NoSignature(Option<Vec<u8>>),
     #[codec(index = 1)]
     Standard(StandardInputSignature),
+    #[codec(index = 2)]
+    Script(authorize_script_spend::ScriptInputWitness),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
@@ -88,6 +100,11 @@ impl StandardInputSignature {
                 verify_public_key_spending(pubkey, &sig_components, sighash)?
             }
             Destination::ScriptHash(_) => return Err(TransactionSigError::Unsupported),
+            Destination::ClassicMultisig(challenge) => {
+                let sig_components =
+                    AuthorizedClassicalMultisigSpend::from_data(&self.raw_signature)?;
+                verify_classical_multisig_spending(challenge, &sig_components, sighash)?
+            }
             Destination::AnyoneCanSpend => {
                 // AnyoneCanSpend must use InputWitness::NoSignature, so this is unreachable
                 return Err(
@@ -98,6 +115,30 @@ impl StandardInputSignature {
         Ok(())
     }
 
+    /// Like `verify_signature`, but single key signatures are queued in the parallel verifier
+    /// instead of being checked, multisig signatures are still checked here
+    pub fn verify_signature_parallel(
+        &self,
+        outpoint_destination: &Destination,
+        sighash: &H256,
+        verifier: &mut ParallelVerifier,
+    ) -> Result<(), TransactionSigError> {
+        match outpoint_destination {
+            Destination::Address(addr) => {
+                let sig_components = AuthorizedPublicKeyHashSpend::from_data(&self.raw_signature)?;
+                verify_address_spending_parallel(addr, sig_components, sighash, verifier)
+            }
+            Destination::PublicKey(pubkey) => {
+                let sig_components = AuthorizedPublicKeySpend::from_data(&self.raw_signature)?;
+                verify_public_key_spending_parallel(pubkey, sig_components, sighash, verifier);
+                Ok(())
+            }
+            Destination::ScriptHash(_)
+            | Destination::ClassicMultisig(_)
+            | Destination::AnyoneCanSpend => self.verify_signature(outpoint_destination, sighash),
+        }
+    }
+
     pub fn produce_signature_for_input(
         private_key: &crypto::key::PrivateKey,
         sighash_type: sighashtype::SigHashType,
@@ -116,6 +157,15 @@ impl StandardInputSignature {
                 sig.encode()
             }
             Destination::ScriptHash(_) => return Err(TransactionSigError::Unsupported),
+            Destination::ClassicMultisig(ref challenge) => {
+                let sig = sign_classical_multisig_spending(
+                    private_key,
+                    challenge,
+                    AuthorizedClassicalMultisigSpend::new(),
+                    &sighash,
+                )?;
+                sig.encode()
+            }
 
             Destination::AnyoneCanSpend => {
                 // AnyoneCanSpend must use InputWitness::NoSignature, so this is unreachable
@@ -128,6 +178,36 @@ impl StandardInputSignature {
         })
     }
 
+    /// Adds a signature from another key of the multisig challenge to the signatures already
+    /// collected in `current_signature`, which must use the same sighash type
+    pub fn produce_classical_multisig_signature_for_input(
+        private_key: &crypto::key::PrivateKey,
+        challenge: &ClassicMultisigChallenge,
+        current_signature: Option<&Self>,
+        sighash_type: sighashtype::SigHashType,
+        tx: &Transaction,
+        input_num: usize,
+    ) -> Result<Self, TransactionSigError> {
+        let current_signatures = match current_signature {
+            Some(current) => {
+                if current.sighash_type != sighash_type {
+                    return Err(TransactionSigError::InvalidSigHashValue(
+                        current.sighash_type.get(),
+                    ));
+                }
+                AuthorizedClassicalMultisigSpend::from_data(&current.raw_signature)?
+            }
+            None => AuthorizedClassicalMultisigSpend::new(),
+        };
+        let sighash = signature_hash(sighash_type, tx, input_num)?;
+        let sig =
+            sign_classical_multisig_spending(private_key, challenge, current_signatures, &sighash)?;
+        Ok(Self {
+            sighash_type,
+            raw_signature: sig.encode(),
+        })
+    }
+
     pub fn get_raw_signature(&self) -> &Vec<u8> {
         &self.raw_signature
     }
@@ -171,6 +251,77 @@ impl Encode for StandardInputSignature {
     }
 }
 
+/// Kind of the witness an input is going to be signed with, to estimate transaction sizes
+/// before signing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSignatureKind {
+    /// `InputWitness::NoSignature` without data, for `Destination::AnyoneCanSpend`
+    NoSignature,
+    /// Signature for `Destination::PublicKey`
+    PublicKey,
+    /// Public key and signature for `Destination::Address`
+    PublicKeyHash,
+    /// The given number of signatures for `Destination::ClassicMultisig`
+    ClassicMultisig(u8),
+}
+
+lazy_static! {
+    // Signatures and public keys of a kind have a fixed size, so any key can stand in for the real one
+    static ref PLACEHOLDER_SIGNATURE: (PublicKey, Signature) = {
+        let (private_key, public_key) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let signature = private_key
+            .sign_message(&H256::zero().encode())
+            .expect("Signing a placeholder message should not fail");
+        (public_key, signature)
+    };
+}
+
+impl InputWitness {
+    /// Encoded size of a witness of the given kind
+    pub fn estimated_size(kind: InputSignatureKind) -> usize {
+        let (public_key, signature) = &*PLACEHOLDER_SIGNATURE;
+        let raw_signature = match kind {
+            InputSignatureKind::NoSignature => {
+                return InputWitness::NoSignature(None).encoded_size()
+            }
+            InputSignatureKind::PublicKey => {
+                AuthorizedPublicKeySpend::new(signature.clone()).encode()
+            }
+            InputSignatureKind::PublicKeyHash => {
+                AuthorizedPublicKeyHashSpend::new(public_key.clone(), signature.clone()).encode()
+            }
+            InputSignatureKind::ClassicMultisig(num_signatures) => {
+                let mut spend = AuthorizedClassicalMultisigSpend::new();
+                (0..num_signatures)
+                    .for_each(|key_index| spend.add_signature(key_index, signature.clone()));
+                spend.encode()
+            }
+        };
+        InputWitness::Standard(StandardInputSignature::new(
+            SigHashType::default(),
+            raw_signature,
+        ))
+        .encoded_size()
+    }
+
+    /// Kinds of the public keys involved in spending an output locked to the destination
+    ///
+    /// Witnesses that can't be decoded add no kinds, they fail signature verification anyway.
+    pub fn key_kinds(&self, outpoint_destination: &Destination) -> BTreeSet<KeyKind> {
+        let mut kinds = outpoint_destination.key_kinds();
+        match (self, outpoint_destination) {
+            (InputWitness::Standard(witness), Destination::Address(_)) => {
+                if let Ok(spend) = AuthorizedPublicKeyHashSpend::from_data(&witness.raw_signature) {
+                    kinds.insert(spend.public_key().kind());
+                }
+            }
+            (InputWitness::Script(witness), _) => kinds.extend(witness.key_kinds()),
+            (InputWitness::NoSignature(_), _) | (InputWitness::Standard(_), _) => {}
+        }
+        kinds
+    }
+}
+
 // TODO: write tests
 
 #[cfg(test)]
@@ -15,7 +15,7 @@
 //
 // Author(s): S. Afach & L. Kuklinek
 
-use crypto::key::Signature;
+use crypto::key::{parallel::ParallelVerifier, Signature};
 use parity_scale_codec::{Decode, Encode};
 
 use crate::{chain::signature::TransactionSigError, primitives::H256};
@@ -49,6 +49,21 @@ pub fn verify_public_key_spending(
     Ok(())
 }
 
+/// Like `verify_public_key_spending`, but the signature is only checked when the parallel
+/// verifier runs
+pub fn verify_public_key_spending_parallel(
+    spendee_pubkey: &crypto::key::PublicKey,
+    spender_signature: AuthorizedPublicKeySpend,
+    sighash: &H256,
+    verifier: &mut ParallelVerifier,
+) {
+    verifier.push(
+        spendee_pubkey.clone(),
+        spender_signature.signature,
+        sighash.encode(),
+    );
+}
+
 pub fn sign_pubkey_spending(
     private_key: &crypto::key::PrivateKey,
     spendee_pubkey: &crypto::key::PublicKey,
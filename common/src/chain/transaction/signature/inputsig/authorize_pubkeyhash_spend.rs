@@ -15,7 +15,7 @@
 //
 // Author(s): S. Afach & L. Kuklinek
 
-use crypto::key::{PublicKey, Signature};
+use crypto::key::{parallel::ParallelVerifier, PublicKey, Signature};
 use parity_scale_codec::{Decode, DecodeAll, Encode};
 
 use crate::{
@@ -41,6 +41,10 @@ impl AuthorizedPublicKeyHashSpend {
             signature,
         }
     }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
 }
 
 pub fn verify_address_spending(
@@ -59,6 +63,26 @@ pub fn verify_address_spending(
     Ok(())
 }
 
+/// Like `verify_address_spending`, but the signature is only checked when the parallel
+/// verifier runs
+pub fn verify_address_spending_parallel(
+    spendee_addr: &PublicKeyHash,
+    sig_components: AuthorizedPublicKeyHashSpend,
+    sighash: &H256,
+    verifier: &mut ParallelVerifier,
+) -> Result<(), TransactionSigError> {
+    let calculated_addr = PublicKeyHash::from(&sig_components.public_key);
+    if calculated_addr != *spendee_addr {
+        return Err(TransactionSigError::PublicKeyToAddressMismatch);
+    }
+    verifier.push(
+        sig_components.public_key,
+        sig_components.signature,
+        sighash.encode(),
+    );
+    Ok(())
+}
+
 pub fn sign_address_spending(
     private_key: &crypto::key::PrivateKey,
     spendee_addr: &PublicKeyHash,
@@ -0,0 +1,201 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+
+use crypto::key::{KeyKind, PrivateKey, PublicKey, Signature};
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use script::{context::ParseResult, script::Instruction, Script};
+
+use crate::{
+    chain::{
+        signature::{script_signature_hash, sighashtype::SigHashType, TransactionSigError},
+        ScriptFlags, Transaction,
+    },
+    primitives::{id::default_hash, BlockHeight, Id},
+};
+
+use super::classic_multisig::MAX_MULTISIG_PUBLIC_KEYS;
+
+/// Id of the lock script that a `Destination::ScriptHash` output commits to
+pub fn script_hash(lock_script: &Script) -> Id<Script> {
+    Id::new(&default_hash(lock_script.as_bytes()))
+}
+
+/// Rules and chain state that script execution depends on, taken from the spending block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptSpendContext {
+    flags: ScriptFlags,
+    spend_height: BlockHeight,
+}
+
+impl ScriptSpendContext {
+    pub fn new(flags: ScriptFlags, spend_height: BlockHeight) -> Self {
+        Self {
+            flags,
+            spend_height,
+        }
+    }
+}
+
+/// Witness of a script spend: the lock script the output commits to and a push-only script
+/// providing its inputs
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+pub struct ScriptInputWitness {
+    lock_script: Script,
+    witness_script: Script,
+}
+
+impl ScriptInputWitness {
+    pub fn from_data<T: AsRef<[u8]>>(data: T) -> Result<Self, TransactionSigError> {
+        let decoded = ScriptInputWitness::decode_all(&mut data.as_ref())
+            .map_err(|_| TransactionSigError::DecodingWitnessFailed)?;
+        Ok(decoded)
+    }
+
+    pub fn new(lock_script: Script, witness_script: Script) -> Self {
+        Self {
+            lock_script,
+            witness_script,
+        }
+    }
+
+    pub fn lock_script(&self) -> &Script {
+        &self.lock_script
+    }
+
+    pub fn witness_script(&self) -> &Script {
+        &self.witness_script
+    }
+
+    /// Kinds of the public keys pushed by the lock script or the witness script
+    pub fn key_kinds(&self) -> BTreeSet<KeyKind> {
+        [&self.lock_script, &self.witness_script]
+            .into_iter()
+            .flat_map(|script| script.instructions())
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(data)) => PublicKey::decode_all(&mut &data[..]).ok(),
+                Ok(Instruction::Op(_)) | Err(_) => None,
+            })
+            .map(|public_key| public_key.kind())
+            .collect()
+    }
+}
+
+/// Signatures in scripts are the encoded signature followed by the sighash type byte
+struct TransactionScriptContext<'a> {
+    tx: &'a Transaction,
+    input_num: usize,
+    spend_context: &'a ScriptSpendContext,
+}
+
+impl<'a> script::Context for TransactionScriptContext<'a> {
+    const MAX_PUBKEYS_PER_MULTISIG: usize = MAX_MULTISIG_PUBLIC_KEYS;
+    const MAX_SCRIPT_SIZE: usize = 10000;
+
+    type Public = PublicKey;
+    type SignatureData = (PublicKey, Signature, SigHashType);
+
+    fn parse_pubkey(&self, pk: &[u8]) -> ParseResult<Self::Public> {
+        PublicKey::decode_all(&mut &pk[..]).ok().into()
+    }
+
+    fn parse_signature(&self, pk: Self::Public, sig: &[u8]) -> Option<Self::SignatureData> {
+        let (sighash_byte, sig) = sig.split_last()?;
+        let sighash_type = SigHashType::try_from(*sighash_byte).ok()?;
+        let signature = Signature::decode_all(&mut &sig[..]).ok()?;
+        Some((pk, signature, sighash_type))
+    }
+
+    fn verify_signature(
+        &self,
+        (pk, signature, sighash_type): &Self::SignatureData,
+        subscript: &[u8],
+        codesep_idx: u32,
+    ) -> bool {
+        match script_signature_hash(
+            *sighash_type,
+            self.tx,
+            self.input_num,
+            subscript,
+            codesep_idx,
+        ) {
+            Ok(sighash) => pk.verify_message(signature, &sighash.encode()),
+            Err(_) => false,
+        }
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        u64::try_from(lock_time).map_or(false, |lock_time| {
+            self.spend_context.spend_height >= lock_time.into()
+        })
+    }
+
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        // The height of the spent output is not known here, use OutputTimeLock::ForBlockCount
+        false
+    }
+
+    fn enforce_minimal_push(&self) -> bool {
+        self.spend_context.flags.enforce_minimal_push
+    }
+
+    fn enforce_minimal_if(&self) -> bool {
+        self.spend_context.flags.enforce_minimal_if
+    }
+}
+
+pub fn verify_script_spending(
+    spendee_script_hash: &Id<Script>,
+    witness: &ScriptInputWitness,
+    tx: &Transaction,
+    input_num: usize,
+    spend_context: Option<&ScriptSpendContext>,
+) -> Result<(), TransactionSigError> {
+    let spend_context = spend_context.ok_or(TransactionSigError::ScriptsNotActivated)?;
+    if script_hash(&witness.lock_script) != *spendee_script_hash {
+        return Err(TransactionSigError::ScriptHashMismatch);
+    }
+    let ctx = TransactionScriptContext {
+        tx,
+        input_num,
+        spend_context,
+    };
+    script::verify_witness_lock(&ctx, &witness.witness_script, &witness.lock_script)
+        .map_err(TransactionSigError::ScriptVerificationFailed)
+}
+
+/// Produce a signature to be pushed by the witness script for a signature check in `lock_script`
+pub fn sign_script_spending(
+    private_key: &PrivateKey,
+    sighash_type: SigHashType,
+    lock_script: &Script,
+    tx: &Transaction,
+    input_num: usize,
+) -> Result<Vec<u8>, TransactionSigError> {
+    let sighash = script_signature_hash(
+        sighash_type,
+        tx,
+        input_num,
+        lock_script.as_bytes(),
+        u32::MAX,
+    )?;
+    let signature = private_key
+        .sign_message(&sighash.encode())
+        .map_err(TransactionSigError::ProducingSignatureFailed)?;
+    let mut sig = signature.encode();
+    sig.push(sighash_type.get());
+    Ok(sig)
+}
@@ -0,0 +1,282 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crypto::key::{PrivateKey, PublicKey, Signature};
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use thiserror::Error;
+
+use crate::{chain::signature::TransactionSigError, primitives::H256};
+
+/// Maximum number of public keys a multisig challenge can contain
+pub const MAX_MULTISIG_PUBLIC_KEYS: usize = 20;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum ClassicMultisigChallengeError {
+    #[error("At least one signature must be required")]
+    NoSignaturesRequired,
+    #[error("{0} signatures required but only {1} public keys provided")]
+    MoreSignaturesThanKeys(u8, usize),
+    #[error("Too many public keys: {0} (maximum is {MAX_MULTISIG_PUBLIC_KEYS})")]
+    TooManyPublicKeys(usize),
+    #[error("Public key at index {0} appears more than once")]
+    DuplicatePublicKey(usize),
+}
+
+/// Outputs locked with this challenge can be spent with signatures from at least
+/// `min_required_signatures` of the listed public keys
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+pub struct ClassicMultisigChallenge {
+    min_required_signatures: u8,
+    public_keys: Vec<PublicKey>,
+}
+
+impl ClassicMultisigChallenge {
+    pub fn new(
+        min_required_signatures: u8,
+        public_keys: Vec<PublicKey>,
+    ) -> Result<Self, ClassicMultisigChallengeError> {
+        let challenge = Self {
+            min_required_signatures,
+            public_keys,
+        };
+        challenge.check()?;
+        Ok(challenge)
+    }
+
+    /// Decoded challenges are not validated, so this has to be checked before spending
+    pub fn check(&self) -> Result<(), ClassicMultisigChallengeError> {
+        if self.min_required_signatures == 0 {
+            return Err(ClassicMultisigChallengeError::NoSignaturesRequired);
+        }
+        if self.public_keys.len() > MAX_MULTISIG_PUBLIC_KEYS {
+            return Err(ClassicMultisigChallengeError::TooManyPublicKeys(
+                self.public_keys.len(),
+            ));
+        }
+        if self.min_required_signatures as usize > self.public_keys.len() {
+            return Err(ClassicMultisigChallengeError::MoreSignaturesThanKeys(
+                self.min_required_signatures,
+                self.public_keys.len(),
+            ));
+        }
+        let mut seen = BTreeSet::new();
+        for (index, public_key) in self.public_keys.iter().enumerate() {
+            if !seen.insert(public_key) {
+                return Err(ClassicMultisigChallengeError::DuplicatePublicKey(index));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn min_required_signatures(&self) -> u8 {
+        self.min_required_signatures
+    }
+
+    pub fn public_keys(&self) -> &[PublicKey] {
+        &self.public_keys
+    }
+}
+
+/// Signatures for a multisig challenge, indexed by the position of the signing key in the challenge
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct AuthorizedClassicalMultisigSpend {
+    signatures: BTreeMap<u8, Signature>,
+}
+
+impl AuthorizedClassicalMultisigSpend {
+    pub fn from_data<T: AsRef<[u8]>>(data: T) -> Result<Self, TransactionSigError> {
+        let decoded = AuthorizedClassicalMultisigSpend::decode_all(&mut data.as_ref())
+            .map_err(|_| TransactionSigError::InvalidSignatureEncoding)?;
+        Ok(decoded)
+    }
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn signatures(&self) -> &BTreeMap<u8, Signature> {
+        &self.signatures
+    }
+
+    pub fn add_signature(&mut self, key_index: u8, signature: Signature) {
+        self.signatures.insert(key_index, signature);
+    }
+}
+
+pub fn verify_classical_multisig_spending(
+    challenge: &ClassicMultisigChallenge,
+    spender_signatures: &AuthorizedClassicalMultisigSpend,
+    sighash: &H256,
+) -> Result<(), TransactionSigError> {
+    challenge.check().map_err(TransactionSigError::InvalidClassicalMultisig)?;
+
+    let provided = spender_signatures.signatures.len();
+    if provided < challenge.min_required_signatures as usize {
+        return Err(TransactionSigError::IncompleteClassicalMultisigSignature(
+            challenge.min_required_signatures,
+            provided,
+        ));
+    }
+
+    let msg = sighash.encode();
+    for (key_index, signature) in &spender_signatures.signatures {
+        let public_key = challenge.public_keys.get(*key_index as usize).ok_or(
+            TransactionSigError::InvalidClassicalMultisigKeyIndex(
+                *key_index,
+                challenge.public_keys.len(),
+            ),
+        )?;
+        if !public_key.verify_message(signature, &msg) {
+            return Err(TransactionSigError::SignatureVerificationFailed);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the signature of the given private key to the signatures collected so far
+pub fn sign_classical_multisig_spending(
+    private_key: &PrivateKey,
+    challenge: &ClassicMultisigChallenge,
+    current_signatures: AuthorizedClassicalMultisigSpend,
+    sighash: &H256,
+) -> Result<AuthorizedClassicalMultisigSpend, TransactionSigError> {
+    challenge.check().map_err(TransactionSigError::InvalidClassicalMultisig)?;
+
+    let public_key = PublicKey::from_private_key(private_key);
+    let key_index = challenge
+        .public_keys
+        .iter()
+        .position(|k| *k == public_key)
+        .ok_or(TransactionSigError::SpendeePrivatePublicKeyMismatch)?;
+
+    let msg = sighash.encode();
+    let signature = private_key
+        .sign_message(&msg)
+        .map_err(TransactionSigError::ProducingSignatureFailed)?;
+
+    let mut signatures = current_signatures;
+    signatures.add_signature(key_index as u8, signature);
+    Ok(signatures)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crypto::key::KeyKind;
+
+    fn make_keys(count: usize) -> Vec<(PrivateKey, PublicKey)> {
+        (0..count).map(|_| PrivateKey::new(KeyKind::RistrettoSchnorr)).collect()
+    }
+
+    fn make_challenge(
+        min_required_signatures: u8,
+        keys: &[(PrivateKey, PublicKey)],
+    ) -> ClassicMultisigChallenge {
+        let public_keys = keys.iter().map(|(_, pk)| pk.clone()).collect();
+        ClassicMultisigChallenge::new(min_required_signatures, public_keys).unwrap()
+    }
+
+    #[test]
+    fn challenge_limits() {
+        let keys = make_keys(MAX_MULTISIG_PUBLIC_KEYS + 1);
+        let public_keys: Vec<_> = keys.iter().map(|(_, pk)| pk.clone()).collect();
+
+        assert_eq!(
+            ClassicMultisigChallenge::new(0, public_keys[..2].to_vec()),
+            Err(ClassicMultisigChallengeError::NoSignaturesRequired)
+        );
+        assert_eq!(
+            ClassicMultisigChallenge::new(3, public_keys[..2].to_vec()),
+            Err(ClassicMultisigChallengeError::MoreSignaturesThanKeys(3, 2))
+        );
+        assert_eq!(
+            ClassicMultisigChallenge::new(1, public_keys.clone()),
+            Err(ClassicMultisigChallengeError::TooManyPublicKeys(
+                MAX_MULTISIG_PUBLIC_KEYS + 1
+            ))
+        );
+        assert_eq!(
+            ClassicMultisigChallenge::new(1, vec![public_keys[0].clone(), public_keys[0].clone()]),
+            Err(ClassicMultisigChallengeError::DuplicatePublicKey(1))
+        );
+        assert!(ClassicMultisigChallenge::new(
+            MAX_MULTISIG_PUBLIC_KEYS as u8,
+            public_keys[..MAX_MULTISIG_PUBLIC_KEYS].to_vec()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let keys = make_keys(3);
+        let challenge = make_challenge(2, &keys);
+        let sighash = H256::random();
+
+        let one = sign_classical_multisig_spending(
+            &keys[0].0,
+            &challenge,
+            AuthorizedClassicalMultisigSpend::new(),
+            &sighash,
+        )
+        .unwrap();
+        assert_eq!(
+            verify_classical_multisig_spending(&challenge, &one, &sighash),
+            Err(TransactionSigError::IncompleteClassicalMultisigSignature(
+                2, 1
+            ))
+        );
+
+        let two = sign_classical_multisig_spending(&keys[2].0, &challenge, one, &sighash).unwrap();
+        assert_eq!(
+            verify_classical_multisig_spending(&challenge, &two, &sighash),
+            Ok(())
+        );
+
+        let decoded = AuthorizedClassicalMultisigSpend::from_data(two.encode()).unwrap();
+        assert_eq!(decoded, two);
+
+        assert_eq!(
+            verify_classical_multisig_spending(&challenge, &two, &H256::random()),
+            Err(TransactionSigError::SignatureVerificationFailed)
+        );
+    }
+
+    #[test]
+    fn foreign_key() {
+        let keys = make_keys(3);
+        let challenge = make_challenge(1, &keys[..2]);
+        let sighash = H256::random();
+
+        assert_eq!(
+            sign_classical_multisig_spending(
+                &keys[2].0,
+                &challenge,
+                AuthorizedClassicalMultisigSpend::new(),
+                &sighash,
+            ),
+            Err(TransactionSigError::SpendeePrivatePublicKeyMismatch)
+        );
+
+        // A signature claiming to be from a key index that is not in the challenge
+        let mut spend = AuthorizedClassicalMultisigSpend::new();
+        spend.add_signature(2, keys[2].0.sign_message(&sighash.encode()).unwrap());
+        assert_eq!(
+            verify_classical_multisig_spending(&challenge, &spend, &sighash),
+            Err(TransactionSigError::InvalidClassicalMultisigKeyIndex(2, 2))
+        );
+    }
+}
@@ -8,6 +8,7 @@ use serialization::{Decode, Encode};
 use super::signature::inputsig::InputWitness;
 use super::Transaction;
 use super::TransactionUpdateError;
+use super::WitnessTransaction;
 
 #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
 pub struct TransactionV1 {
@@ -56,7 +57,7 @@ impl TransactionV1 {
         self.lock_time
     }
 
-    pub fn get_serialized_hash(&self) -> Id<Transaction> {
+    pub fn get_witness_id(&self) -> Id<WitnessTransaction> {
         Id::new(&id::hash_encoded(self))
     }
 
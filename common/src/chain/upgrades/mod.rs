@@ -1,7 +1,3 @@
 mod netupgrade;
 
 pub use netupgrade::*;
-
-pub enum NetUpgradeError {
-    GenerateConfigFailed,
-}
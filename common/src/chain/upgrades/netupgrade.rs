@@ -1,5 +1,9 @@
 #![allow(clippy::upper_case_acronyms, clippy::needless_doctest_main)]
 
+use std::collections::BTreeSet;
+
+use crypto::key::KeyKind;
+
 use crate::chain::config::ChainType;
 use crate::chain::pow::limit;
 use crate::primitives::{BlockDistance, BlockHeight, Compact};
@@ -7,6 +11,14 @@ use crate::primitives::{BlockDistance, BlockHeight, Compact};
 #[derive(Debug, Clone)]
 pub struct NetUpgrades<T>(Vec<(BlockHeight, T)>);
 
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum NetUpgradeError {
+    #[error("Net upgrades must include an upgrade at genesis")]
+    NoGenesisUpgrade,
+    #[error("More than one upgrade of the same kind at height {0}")]
+    DuplicateUpgradeHeight(BlockHeight),
+}
+
 impl NetUpgrades<UpgradeVersion> {
     pub fn new(chain_type: ChainType) -> Self {
         Self(vec![(
@@ -20,10 +32,24 @@ impl NetUpgrades<UpgradeVersion> {
 
 impl NetUpgrades<UpgradeVersion> {
     pub fn unit_tests() -> Self {
-        Self(vec![(
-            BlockHeight::zero(),
-            UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
-        )])
+        Self(vec![
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ScriptUpgrade(ScriptFlags::default()),
+            ),
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::SignatureUpgrade(KeyKind::Secp256k1Schnorr),
+            ),
+            (BlockHeight::zero(), UpgradeVersion::DuplicateTxUpgrade),
+            (BlockHeight::zero(), UpgradeVersion::InputlessTxUpgrade),
+            (BlockHeight::zero(), UpgradeVersion::BlockRewardUpgrade),
+            (BlockHeight::zero(), UpgradeVersion::TimeLockUpgrade),
+        ])
     }
 }
 
@@ -42,9 +68,38 @@ pub trait Activate {
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub enum UpgradeVersion {
     ConsensusUpgrade(ConsensusUpgrade),
+    ScriptUpgrade(ScriptFlags),
+    /// Outputs can be locked to keys of the kind and spent with its signatures from this height on
+    SignatureUpgrade(KeyKind),
+    /// Blocks can't contain a transaction whose ID is in the main chain with unspent outputs
+    /// from this height on
+    DuplicateTxUpgrade,
+    /// Blocks other than genesis can't contain transactions without inputs from this height on
+    InputlessTxUpgrade,
+    /// Block rewards can't pay more than the emission schedule subsidy plus the transaction fees
+    /// from this height on
+    BlockRewardUpgrade,
+    /// Blocks can contain transactions with timelocked outputs from this height on
+    TimeLockUpgrade,
     SomeUpgrade,
 }
 
+/// Rules of the script interpreter used to spend `Destination::ScriptHash` outputs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct ScriptFlags {
+    pub enforce_minimal_push: bool,
+    pub enforce_minimal_if: bool,
+}
+
+impl Default for ScriptFlags {
+    fn default() -> Self {
+        Self {
+            enforce_minimal_push: true,
+            enforce_minimal_if: true,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub enum ConsensusUpgrade {
     PoW { initial_difficulty: Compact },
@@ -84,17 +139,34 @@ impl From<ConsensusUpgrade> for RequiredConsensus {
 impl Activate for UpgradeVersion {}
 
 impl<T: Ord + Copy> NetUpgrades<T> {
-    #[allow(dead_code)]
-    pub fn initialize(upgrades: Vec<(BlockHeight, T)>) -> anyhow::Result<Self> {
+    /// Upgrades of the same kind must have distinct heights, the order they are given in doesn't matter
+    pub fn initialize(upgrades: Vec<(BlockHeight, T)>) -> Result<Self, NetUpgradeError> {
         let mut upgrades = upgrades;
         upgrades.sort_unstable();
 
         match upgrades.first() {
-            Some(&(height, _)) if height == BlockHeight::zero() =>
-                Ok(Self(upgrades)),
-                _ =>
-                Err(anyhow::Error::msg("NetUpgrades must be initialized with a nonempty vector of upgrades with an upgrade at genesis"))
+            Some(&(height, _)) if height == BlockHeight::zero() => (),
+            _ => return Err(NetUpgradeError::NoGenesisUpgrade),
+        }
+
+        // Sorted by height, so each kind has strictly increasing heights unless two of a kind
+        // share a height
+        for (idx, (height, upgrade)) in upgrades.iter().enumerate() {
+            let same_kind_at_height =
+                upgrades[..idx].iter().rev().take_while(|(h, _)| h == height).any(|(_, other)| {
+                    std::mem::discriminant(other) == std::mem::discriminant(upgrade)
+                });
+            if same_kind_at_height {
+                return Err(NetUpgradeError::DuplicateUpgradeHeight(*height));
+            }
         }
+
+        Ok(Self(upgrades))
+    }
+
+    /// All upgrades, ordered by their activation height
+    pub fn schedule(&self) -> &[(BlockHeight, T)] {
+        &self.0
     }
 
     pub fn is_empty(&self) -> bool {
@@ -105,6 +177,7 @@ impl<T: Ord + Copy> NetUpgrades<T> {
         self.0.len()
     }
 
+    /// Heights at which the upgrade is in effect, until the next upgrade of the same kind
     pub fn height_range(&self, version: T) -> Option<(BlockHeight, BlockHeight)> {
         let res = self
             .0
@@ -113,14 +186,15 @@ impl<T: Ord + Copy> NetUpgrades<T> {
             .find(|&(_, &(_, elem_version))| elem_version == version);
 
         res.map(|(idx, &(start_h, _))| {
+            let next_of_kind = self.0[idx + 1..].iter().find(|(_, other)| {
+                std::mem::discriminant(other) == std::mem::discriminant(&version)
+            });
             (
                 start_h,
-                if idx == (self.0.len() - 1) {
-                    BlockHeight::max()
-                } else {
-                    (self.0[idx + 1].0 - BlockDistance::new(1)).expect(
-                        "Upgrade heights should never overflow/underflow as they're chosen by us",
-                    )
+                match next_of_kind {
+                    None => BlockHeight::max(),
+                    Some(&(next_h, _)) => (next_h - BlockDistance::new(1))
+                        .expect("Upgrades of the same kind have strictly increasing heights"),
                 },
             )
         })
@@ -128,29 +202,46 @@ impl<T: Ord + Copy> NetUpgrades<T> {
 }
 
 impl NetUpgrades<UpgradeVersion> {
-    pub fn consensus_status(&self, height: BlockHeight) -> RequiredConsensus {
-        let (last_upgrade_height, last_consensus_upgrade) = self
-            .0
+    /// Consensus upgrade in effect at the given height and the height it was activated at
+    pub fn consensus_upgrade(&self, height: BlockHeight) -> (BlockHeight, ConsensusUpgrade) {
+        self.0
             .iter()
             .rev()
             .filter(|(block_height, _upgrade)| *block_height <= height)
             .find_map(|(block_height, upgrade)| {
                 if let UpgradeVersion::ConsensusUpgrade(consensus_upgrade) = upgrade {
-                    Some((block_height, consensus_upgrade))
+                    Some((*block_height, *consensus_upgrade))
                 } else {
                     None
                 }
             })
-            .expect("Some consensus must have been set");
+            .expect("Some consensus must have been set")
+    }
+
+    /// Latest upgrade of each kind activated at or below the given height
+    pub fn active_upgrades(&self, height: BlockHeight) -> Vec<(BlockHeight, UpgradeVersion)> {
+        let mut active: Vec<(BlockHeight, UpgradeVersion)> = Vec::new();
+        for &(block_height, upgrade) in self.0.iter().rev() {
+            let kind_seen = active.iter().any(|(_, other)| {
+                std::mem::discriminant(other) == std::mem::discriminant(&upgrade)
+            });
+            if block_height <= height && !kind_seen {
+                active.push((block_height, upgrade));
+            }
+        }
+        active.reverse();
+        active
+    }
+
+    pub fn consensus_status(&self, height: BlockHeight) -> RequiredConsensus {
+        let (last_upgrade_height, last_consensus_upgrade) = self.consensus_upgrade(height);
         match last_consensus_upgrade {
             ConsensusUpgrade::PoW { initial_difficulty } => {
-                if *last_upgrade_height < height {
+                if last_upgrade_height < height {
                     RequiredConsensus::PoW(PoWStatus::Ongoing)
                 } else {
-                    debug_assert_eq!(*last_upgrade_height, height);
-                    RequiredConsensus::PoW(PoWStatus::Threshold {
-                        initial_difficulty: *initial_difficulty,
-                    })
+                    debug_assert_eq!(last_upgrade_height, height);
+                    RequiredConsensus::PoW(PoWStatus::Threshold { initial_difficulty })
                 }
             }
             ConsensusUpgrade::PoS => RequiredConsensus::PoS,
@@ -158,11 +249,69 @@ impl NetUpgrades<UpgradeVersion> {
             ConsensusUpgrade::IgnoreConsensus => RequiredConsensus::IgnoreConsensus,
         }
     }
+
+    /// Script rules at the given height, `None` if scripts are not activated yet
+    pub fn script_flags(&self, height: BlockHeight) -> Option<ScriptFlags> {
+        self.0
+            .iter()
+            .rev()
+            .filter(|(block_height, _upgrade)| *block_height <= height)
+            .find_map(|(_block_height, upgrade)| match upgrade {
+                UpgradeVersion::ScriptUpgrade(flags) => Some(*flags),
+                _ => None,
+            })
+    }
+
+    /// Whether transactions duplicating the ID of a main chain transaction with unspent outputs
+    /// are rejected at the given height
+    pub fn duplicate_tx_check_active(&self, height: BlockHeight) -> bool {
+        self.0.iter().any(|(block_height, upgrade)| {
+            *block_height <= height && *upgrade == UpgradeVersion::DuplicateTxUpgrade
+        })
+    }
+
+    /// Whether transactions without inputs are rejected at the given height
+    pub fn inputless_tx_check_active(&self, height: BlockHeight) -> bool {
+        self.0.iter().any(|(block_height, upgrade)| {
+            *block_height <= height && *upgrade == UpgradeVersion::InputlessTxUpgrade
+        })
+    }
+
+    /// Whether block rewards are limited to the subsidy plus the fees at the given height
+    pub fn block_reward_check_active(&self, height: BlockHeight) -> bool {
+        self.0.iter().any(|(block_height, upgrade)| {
+            *block_height <= height && *upgrade == UpgradeVersion::BlockRewardUpgrade
+        })
+    }
+
+    /// Whether outputs can be timelocked at the given height
+    pub fn timelocks_active(&self, height: BlockHeight) -> bool {
+        self.0.iter().any(|(block_height, upgrade)| {
+            *block_height <= height && *upgrade == UpgradeVersion::TimeLockUpgrade
+        })
+    }
+
+    /// Kinds of keys that can be used in transactions at the given height
+    ///
+    /// Unlike other upgrades, signature upgrades add to the ones before them. Ristretto keys
+    /// are always allowed.
+    pub fn active_key_kinds(&self, height: BlockHeight) -> BTreeSet<KeyKind> {
+        let upgraded = self
+            .0
+            .iter()
+            .filter(|(block_height, _upgrade)| *block_height <= height)
+            .filter_map(|(_block_height, upgrade)| match upgrade {
+                UpgradeVersion::SignatureUpgrade(kind) => Some(*kind),
+                _ => None,
+            });
+        std::iter::once(KeyKind::RistrettoSchnorr).chain(upgraded).collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chain::config::ChainConfigBuilder;
     use crate::chain::upgrades::netupgrade::NetUpgrades;
     use crate::chain::Activate;
     use crate::primitives::BlockHeight;
@@ -240,21 +389,48 @@ mod tests {
             assert_eq!(Some((height, end_range)), res);
         };
 
-        check(MockVersion::Zero, BlockHeight::zero(), BlockHeight::zero());
-        check(
-            MockVersion::One,
-            BlockHeight::one(),
-            (two_height - BlockDistance::new(1)).unwrap(),
+        // Each mock version is a kind of its own, so none of them is superseded
+        check(MockVersion::Zero, BlockHeight::zero(), BlockHeight::max());
+        check(MockVersion::One, BlockHeight::one(), BlockHeight::max());
+        check(MockVersion::Two, two_height, BlockHeight::max());
+        check(MockVersion::Three, three_height, BlockHeight::max());
+        assert_eq!(upgrades.height_range(MockVersion::Four), None);
+
+        // Upgrades of other kinds, even at the same height, don't end the range
+        let upgrades = NetUpgrades::unit_tests();
+        for &(height, upgrade) in upgrades.schedule() {
+            assert_eq!(
+                upgrades.height_range(upgrade),
+                Some((height, BlockHeight::max()))
+            );
+        }
+
+        let upgrades = mock_consensus_upgrades().unwrap();
+        let pos = UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoS);
+        let upgrades = NetUpgrades::initialize(
+            upgrades
+                .schedule()
+                .iter()
+                .copied()
+                .chain([(BlockHeight::new(10_000), UpgradeVersion::DuplicateTxUpgrade)])
+                .collect(),
+        )
+        .unwrap();
+        assert_eq!(
+            upgrades.height_range(pos),
+            Some((BlockHeight::new(10_000), BlockHeight::new(14_999)))
         );
-        check(
-            MockVersion::Two,
-            two_height,
-            (three_height - BlockDistance::new(1)).unwrap(),
+        assert_eq!(
+            upgrades.height_range(upgrades.schedule()[0].1),
+            Some((BlockHeight::zero(), BlockHeight::new(9_999)))
+        );
+        assert_eq!(
+            upgrades.height_range(UpgradeVersion::DuplicateTxUpgrade),
+            Some((BlockHeight::new(10_000), BlockHeight::max()))
         );
-        check(MockVersion::Three, three_height, BlockHeight::max());
     }
 
-    fn mock_consensus_upgrades() -> anyhow::Result<NetUpgrades<UpgradeVersion>> {
+    fn mock_consensus_upgrades() -> Result<NetUpgrades<UpgradeVersion>, NetUpgradeError> {
         let genesis_pow = BlockHeight::new(0);
         let first_pos_upgrade = BlockHeight::new(10_000);
         let back_to_pow = BlockHeight::new(15_000);
@@ -317,4 +493,208 @@ mod tests {
             RequiredConsensus::PoW(PoWStatus::Ongoing)
         );
     }
+
+    #[test]
+    fn script_upgrade() {
+        let flags = ScriptFlags {
+            enforce_minimal_push: false,
+            enforce_minimal_if: true,
+        };
+        let upgrades = NetUpgrades::initialize(vec![
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (BlockHeight::new(100), UpgradeVersion::ScriptUpgrade(flags)),
+            (
+                BlockHeight::new(200),
+                UpgradeVersion::ScriptUpgrade(ScriptFlags::default()),
+            ),
+        ])
+        .expect("valid netupgrades");
+
+        assert_eq!(upgrades.script_flags(BlockHeight::new(99)), None);
+        assert_eq!(upgrades.script_flags(BlockHeight::new(100)), Some(flags));
+        assert_eq!(upgrades.script_flags(BlockHeight::new(199)), Some(flags));
+        assert_eq!(
+            upgrades.script_flags(BlockHeight::max()),
+            Some(ScriptFlags::default())
+        );
+        assert_eq!(
+            upgrades.consensus_status(BlockHeight::new(150)),
+            RequiredConsensus::IgnoreConsensus
+        );
+    }
+
+    #[test]
+    fn initialize_validation() {
+        let pos = UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoS);
+        let ignore = UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus);
+        let script = UpgradeVersion::ScriptUpgrade(ScriptFlags::default());
+
+        assert_eq!(
+            NetUpgrades::<UpgradeVersion>::initialize(vec![]).unwrap_err(),
+            NetUpgradeError::NoGenesisUpgrade
+        );
+        assert_eq!(
+            NetUpgrades::initialize(vec![(BlockHeight::new(1), ignore)]).unwrap_err(),
+            NetUpgradeError::NoGenesisUpgrade
+        );
+        assert_eq!(
+            NetUpgrades::initialize(vec![
+                (BlockHeight::zero(), ignore),
+                (BlockHeight::new(5), pos),
+                (BlockHeight::new(5), ignore),
+            ])
+            .unwrap_err(),
+            NetUpgradeError::DuplicateUpgradeHeight(BlockHeight::new(5))
+        );
+
+        // Upgrades of different kinds can share a height and are sorted by height
+        let upgrades = NetUpgrades::initialize(vec![
+            (BlockHeight::new(5), pos),
+            (BlockHeight::new(5), script),
+            (BlockHeight::zero(), ignore),
+        ])
+        .unwrap();
+        assert_eq!(
+            upgrades.schedule(),
+            [
+                (BlockHeight::zero(), ignore),
+                (BlockHeight::new(5), pos),
+                (BlockHeight::new(5), script)
+            ]
+        );
+    }
+
+    #[test]
+    fn active_upgrades() {
+        let upgrades = mock_consensus_upgrades().expect("valid netupgrades");
+        let pow_1000 = ConsensusUpgrade::PoW {
+            initial_difficulty: Uint256::from_u64(1000).unwrap().into(),
+        };
+
+        assert_eq!(
+            upgrades.consensus_upgrade(BlockHeight::new(9_999)),
+            (BlockHeight::zero(), pow_1000)
+        );
+        assert_eq!(
+            upgrades.consensus_upgrade(BlockHeight::new(10_000)),
+            (BlockHeight::new(10_000), ConsensusUpgrade::PoS)
+        );
+        assert_eq!(
+            upgrades.active_upgrades(BlockHeight::new(12_000)),
+            vec![(
+                BlockHeight::new(10_000),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::PoS)
+            )]
+        );
+
+        let upgrades = NetUpgrades::unit_tests();
+        assert_eq!(
+            upgrades.active_upgrades(BlockHeight::new(1)),
+            upgrades.schedule().to_vec()
+        );
+    }
+
+    #[test]
+    fn active_key_kinds() {
+        let upgrades = NetUpgrades::initialize(vec![
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (
+                BlockHeight::new(100),
+                UpgradeVersion::SignatureUpgrade(KeyKind::Secp256k1Schnorr),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            upgrades.active_key_kinds(BlockHeight::new(99)),
+            BTreeSet::from([KeyKind::RistrettoSchnorr])
+        );
+        assert_eq!(
+            upgrades.active_key_kinds(BlockHeight::new(100)),
+            BTreeSet::from([KeyKind::RistrettoSchnorr, KeyKind::Secp256k1Schnorr])
+        );
+        let mainnet = ChainConfigBuilder::new(ChainType::Mainnet).build();
+        assert_eq!(
+            mainnet.net_upgrade().active_key_kinds(BlockHeight::max()),
+            BTreeSet::from([KeyKind::RistrettoSchnorr])
+        );
+    }
+
+    #[test]
+    fn duplicate_tx_check_active() {
+        let upgrades = NetUpgrades::initialize(vec![
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (BlockHeight::new(100), UpgradeVersion::DuplicateTxUpgrade),
+        ])
+        .unwrap();
+
+        assert!(!upgrades.duplicate_tx_check_active(BlockHeight::new(99)));
+        assert!(upgrades.duplicate_tx_check_active(BlockHeight::new(100)));
+        let mainnet = ChainConfigBuilder::new(ChainType::Mainnet).build();
+        assert!(mainnet.net_upgrade().duplicate_tx_check_active(BlockHeight::zero()));
+        assert!(NetUpgrades::unit_tests().duplicate_tx_check_active(BlockHeight::zero()));
+    }
+
+    #[test]
+    fn inputless_tx_check_active() {
+        let upgrades = NetUpgrades::initialize(vec![
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (BlockHeight::new(100), UpgradeVersion::InputlessTxUpgrade),
+        ])
+        .unwrap();
+
+        assert!(!upgrades.inputless_tx_check_active(BlockHeight::new(99)));
+        assert!(upgrades.inputless_tx_check_active(BlockHeight::new(100)));
+        let mainnet = ChainConfigBuilder::new(ChainType::Mainnet).build();
+        assert!(mainnet.net_upgrade().inputless_tx_check_active(BlockHeight::zero()));
+        assert!(NetUpgrades::unit_tests().inputless_tx_check_active(BlockHeight::zero()));
+    }
+
+    #[test]
+    fn block_reward_check_active() {
+        let upgrades = NetUpgrades::initialize(vec![
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (BlockHeight::new(100), UpgradeVersion::BlockRewardUpgrade),
+        ])
+        .unwrap();
+
+        assert!(!upgrades.block_reward_check_active(BlockHeight::new(99)));
+        assert!(upgrades.block_reward_check_active(BlockHeight::new(100)));
+        let mainnet = ChainConfigBuilder::new(ChainType::Mainnet).build();
+        assert!(mainnet.net_upgrade().block_reward_check_active(BlockHeight::zero()));
+        assert!(NetUpgrades::unit_tests().block_reward_check_active(BlockHeight::zero()));
+    }
+
+    #[test]
+    fn timelocks_active() {
+        let upgrades = NetUpgrades::initialize(vec![
+            (
+                BlockHeight::zero(),
+                UpgradeVersion::ConsensusUpgrade(ConsensusUpgrade::IgnoreConsensus),
+            ),
+            (BlockHeight::new(100), UpgradeVersion::TimeLockUpgrade),
+        ])
+        .unwrap();
+
+        assert!(!upgrades.timelocks_active(BlockHeight::new(99)));
+        assert!(upgrades.timelocks_active(BlockHeight::new(100)));
+        let mainnet = ChainConfigBuilder::new(ChainType::Mainnet).build();
+        assert!(mainnet.net_upgrade().timelocks_active(BlockHeight::zero()));
+        assert!(NetUpgrades::unit_tests().timelocks_active(BlockHeight::zero()));
+    }
 }
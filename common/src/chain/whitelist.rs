@@ -0,0 +1,229 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Peers that are trusted by the node operator
+//!
+//! A whitelist entry is written as `[permission,...@]subnet`, for example
+//! `192.168.0.0/16` or `forcerelay,noban@::1`. If no permissions are listed,
+//! the peer is granted `noban` and `nolimit`.
+
+use std::{collections::BTreeSet, net::IpAddr, str::FromStr};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq, Clone)]
+pub enum WhitelistError {
+    #[error("Invalid IP address: {0}")]
+    InvalidAddress(String),
+    #[error("Invalid subnet prefix length: {0}")]
+    InvalidPrefixLength(String),
+    #[error("Unknown permission: {0}")]
+    UnknownPermission(String),
+}
+
+/// Permission granted to a whitelisted peer
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum PeerPermission {
+    /// The peer is not disconnected for misbehaving
+    NoBan,
+
+    /// The peer is not subject to the inbound connection limit or eviction
+    NoLimit,
+
+    /// Messages received from the peer are relayed even if they would otherwise be held back
+    ForceRelay,
+}
+
+/// IP network given as an address and a prefix length
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    fn max_prefix_len(addr: &IpAddr) -> u8 {
+        match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    /// Network part of `addr` when using the prefix length of the subnet
+    fn network_bits(&self, addr: &IpAddr) -> u128 {
+        let (bits, len) = match addr {
+            IpAddr::V4(ip) => (u32::from(*ip) as u128, 32),
+            IpAddr::V6(ip) => (u128::from(*ip), 128),
+        };
+
+        bits.checked_shr(len - self.prefix_len as u32).unwrap_or(0)
+    }
+
+    /// Check whether `addr` belongs to the subnet
+    ///
+    /// IPv4-mapped IPv6 addresses are treated as IPv4 addresses.
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(*addr, IpAddr::V4),
+            IpAddr::V4(_) => *addr,
+        };
+
+        if addr.is_ipv4() != self.addr.is_ipv4() {
+            return false;
+        }
+        self.network_bits(&addr) == self.network_bits(&self.addr)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = WhitelistError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| WhitelistError::InvalidAddress(addr.to_string()))?;
+        let max_prefix_len = Self::max_prefix_len(&addr);
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_prefix_len)
+                .ok_or_else(|| WhitelistError::InvalidPrefixLength(len.to_string()))?,
+            None => max_prefix_len,
+        };
+
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+/// Subnet of whitelisted peers and the permissions granted to them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WhitelistEntry {
+    subnet: Subnet,
+    permissions: BTreeSet<PeerPermission>,
+}
+
+impl WhitelistEntry {
+    pub fn subnet(&self) -> &Subnet {
+        &self.subnet
+    }
+
+    pub fn permissions(&self) -> &BTreeSet<PeerPermission> {
+        &self.permissions
+    }
+}
+
+impl FromStr for WhitelistEntry {
+    type Err = WhitelistError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (permissions, subnet) = match s.split_once('@') {
+            Some((permissions, subnet)) => (
+                permissions
+                    .split(',')
+                    .map(|perm| {
+                        perm.trim()
+                            .parse::<PeerPermission>()
+                            .map_err(|_| WhitelistError::UnknownPermission(perm.to_string()))
+                    })
+                    .collect::<Result<BTreeSet<_>, _>>()?,
+                subnet,
+            ),
+            None => (
+                BTreeSet::from([PeerPermission::NoBan, PeerPermission::NoLimit]),
+                s,
+            ),
+        };
+
+        Ok(Self {
+            subnet: subnet.parse()?,
+            permissions,
+        })
+    }
+}
+
+/// Collect the permissions of all whitelist entries that `addr` belongs to
+pub fn permissions_of(whitelist: &[WhitelistEntry], addr: &IpAddr) -> BTreeSet<PeerPermission> {
+    whitelist
+        .iter()
+        .filter(|entry| entry.subnet.contains(addr))
+        .flat_map(|entry| entry.permissions.iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subnets() {
+        let contains = |subnet: &str, ip: &str| {
+            subnet.parse::<Subnet>().unwrap().contains(&ip.parse().unwrap())
+        };
+
+        assert!(contains("10.0.0.0/8", "10.1.2.3"));
+        assert!(!contains("10.0.0.0/8", "11.1.2.3"));
+        assert!(contains("192.168.1.1", "192.168.1.1"));
+        assert!(!contains("192.168.1.1", "192.168.1.2"));
+        assert!(contains("0.0.0.0/0", "8.8.8.8"));
+        assert!(!contains("0.0.0.0/0", "::1"));
+        assert!(contains("10.0.0.0/8", "::ffff:10.0.0.1"));
+        assert!(contains("2001:db8::/32", "2001:db8:1::1"));
+        assert!(!contains("2001:db8::/32", "2001:db9::1"));
+        assert!(contains("::1", "::1"));
+
+        assert_eq!(
+            "10.0.0.0/33".parse::<Subnet>(),
+            Err(WhitelistError::InvalidPrefixLength("33".to_string()))
+        );
+        assert_eq!(
+            "10.0.0/8".parse::<Subnet>(),
+            Err(WhitelistError::InvalidAddress("10.0.0".to_string()))
+        );
+    }
+
+    #[test]
+    fn entries() {
+        let entry = "10.0.0.0/8".parse::<WhitelistEntry>().unwrap();
+        assert_eq!(
+            entry.permissions(),
+            &BTreeSet::from([PeerPermission::NoBan, PeerPermission::NoLimit])
+        );
+
+        let entry = "forcerelay,noban@::1".parse::<WhitelistEntry>().unwrap();
+        assert_eq!(entry.subnet(), &"::1/128".parse::<Subnet>().unwrap());
+        assert_eq!(
+            entry.permissions(),
+            &BTreeSet::from([PeerPermission::ForceRelay, PeerPermission::NoBan])
+        );
+
+        assert_eq!(
+            "admin@::1".parse::<WhitelistEntry>(),
+            Err(WhitelistError::UnknownPermission("admin".to_string()))
+        );
+
+        let whitelist =
+            vec!["forcerelay@10.0.0.0/8".parse().unwrap(), "nolimit@10.1.0.0/16".parse().unwrap()];
+        assert_eq!(
+            permissions_of(&whitelist, &"10.1.2.3".parse().unwrap()),
+            BTreeSet::from([PeerPermission::NoLimit, PeerPermission::ForceRelay])
+        );
+        assert!(permissions_of(&whitelist, &"11.1.2.3".parse().unwrap()).is_empty());
+    }
+}
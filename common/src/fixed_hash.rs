@@ -527,30 +527,36 @@ macro_rules! impl_cmp_for_fixed_hash {
 macro_rules! impl_rustc_hex_for_fixed_hash {
     ( $name:ident ) => {
         impl core::str::FromStr for $name {
-            type Err = rustc_hex::FromHexError;
+            type Err = $crate::primitives::id::HashParseError;
 
             /// Creates a hash type instance from the given string.
             ///
             /// # Note
             ///
-            /// The given input string is interpreted in little endian.
+            /// The given input string is interpreted in little endian and may start with `0x`.
             ///
             /// # Errors
             ///
             /// - When encountering invalid non hex-digits
             /// - Upon empty string input or invalid input length in general
-            fn from_str(input: &str) -> core::result::Result<$name, rustc_hex::FromHexError> {
+            fn from_str(input: &str) -> core::result::Result<$name, Self::Err> {
+                let expected = Self::len_bytes() * 2;
                 let input = input.strip_prefix("0x").unwrap_or(input);
+                if input.len() != expected {
+                    return Err(Self::Err::InvalidLength(expected));
+                }
                 let mut iter = rustc_hex::FromHexIter::new(input);
 
                 let mut result = Self::zero();
 
                 for byte in result.as_mut().iter_mut().rev() {
-                    *byte = iter.next().ok_or(Self::Err::InvalidHexLength)??;
-                }
-
-                if iter.next().is_some() {
-                    return Err(Self::Err::InvalidHexLength);
+                    *byte = match iter.next() {
+                        Some(Ok(byte)) => byte,
+                        Some(Err(rustc_hex::FromHexError::InvalidHexCharacter(c, pos))) => {
+                            return Err(Self::Err::InvalidCharacter(c, pos))
+                        }
+                        _ => return Err(Self::Err::InvalidLength(expected)),
+                    };
                 }
                 Ok(result)
             }
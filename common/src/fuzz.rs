@@ -0,0 +1,87 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Entry points of the fuzz targets
+//!
+//! The targets in the `fuzz` directory of the repository only forward their input to these
+//! functions, which panic if the input uncovers a bug. The inputs kept in the corpus of a target
+//! are replayed by the unit tests, so the bugs found by fuzzing stay fixed.
+
+use std::path::{Path, PathBuf};
+
+use serialization::{Decode, Encode};
+
+use crate::chain::{block::Block, Transaction};
+
+/// Decode a block the way it arrives from a peer
+pub fn decode_block(data: &[u8]) {
+    check_round_trip::<Block>(data);
+}
+
+/// Decode a transaction
+pub fn decode_transaction(data: &[u8]) {
+    check_round_trip::<Transaction>(data);
+}
+
+/// Decode a value and, if that succeeds, check that encoding it again gives back the same value
+pub fn check_round_trip<T: Encode + Decode + PartialEq + std::fmt::Debug>(data: &[u8]) {
+    if let Ok(value) = T::decode(&mut &data[..]) {
+        let encoded = value.encode();
+        assert_eq!(T::decode(&mut &encoded[..]).ok(), Some(value));
+    }
+}
+
+/// Directory holding the corpus of a fuzz target
+pub fn corpus_dir(target: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../fuzz/corpus").join(target)
+}
+
+/// Inputs in the corpus of a fuzz target, empty if the target has no corpus yet
+pub fn corpus(target: &str) -> Vec<Vec<u8>> {
+    let entries = match std::fs::read_dir(corpus_dir(target)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths: Vec<PathBuf> =
+        entries.map(|entry| entry.expect("Corpus entry read failed").path()).collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| std::fs::read(path).expect("Corpus input read failed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_corpus() {
+        corpus("decode_block").iter().for_each(|input| decode_block(input));
+    }
+
+    #[test]
+    fn transaction_corpus() {
+        corpus("decode_transaction").iter().for_each(|input| decode_transaction(input));
+    }
+
+    #[test]
+    fn arbitrary_bytes() {
+        for input in [&[][..], &[0], &[1], &[1, 0xff, 0xff, 0xff, 0xff]] {
+            decode_block(input);
+            decode_transaction(input);
+        }
+    }
+}
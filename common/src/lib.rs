@@ -17,6 +17,7 @@
 
 pub mod address;
 pub mod chain;
+pub mod fuzz;
 pub mod primitives;
 pub mod uint;
 
@@ -21,7 +21,7 @@ use std::iter::Sum;
 // Author(s): S. Afach
 
 // use only unsigned types
-// if you need a signed amount, we should create a separate type for it and implement proper conversion
+// for signed amounts use SignedAmount, which converts from and to Amount
 pub type IntType = u128;
 
 /// An unsigned fixed-point type for amounts
@@ -43,7 +43,7 @@ fn remove_right_most_zeros_and_decimal_point(s: String) -> String {
 }
 
 impl Amount {
-    pub fn from_atoms(v: IntType) -> Self {
+    pub const fn from_atoms(v: IntType) -> Self {
         Amount { val: v }
     }
 
@@ -51,6 +51,28 @@ impl Amount {
         self.val
     }
 
+    /// Sum of the amounts, `None` on overflow
+    pub fn sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Option<Self> {
+        amounts.into_iter().sum()
+    }
+
+    pub fn checked_mul(self, other: IntType) -> Option<Self> {
+        self * other
+    }
+
+    pub fn checked_div(self, other: IntType) -> Option<Self> {
+        self / other
+    }
+
+    /// Difference between the amounts regardless of which one is larger
+    pub fn abs_diff(self, other: Self) -> Self {
+        Amount {
+            val: self.val.abs_diff(other.val),
+        }
+    }
+
+    /// Render the amount as a decimal number of coins with `decimals` digits after the decimal
+    /// point, without trailing zeros
     pub fn into_fixedpoint_str(self, decimals: u8) -> String {
         let amount_str = self.val.to_string();
         let decimals = decimals as usize;
@@ -70,46 +92,49 @@ impl Amount {
         }
     }
 
+    /// Parse a decimal amount of coins with `decimals` digits after the decimal point into atoms
+    ///
+    /// `None` if the string is not a plain non-negative decimal number, has more fractional
+    /// digits than `decimals` or the amount does not fit into an `Amount`.
     pub fn from_fixedpoint_str(amount_str: &str, decimals: u8) -> Option<Self> {
         let decimals = decimals as usize;
         let amount_str = amount_str.trim_matches(' '); // trim spaces
 
-        // empty not allowed
-        if amount_str.is_empty() {
+        // too long, the fraction alone can take up to `decimals` characters
+        if amount_str.len() > 100 + decimals {
             return None;
         }
-        // too long
-        if amount_str.len() > 100 {
+        // must be only digits or decimal point
+        if !amount_str.chars().all(|c| c.is_ascii_digit() || c == '.') {
             return None;
         }
-        // must be only numbers or decimal point
-        if !amount_str.chars().all(|c| char::is_numeric(c) || c == '.') {
+
+        let (whole, fraction) = match amount_str.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (amount_str, ""),
+        };
+        // only 1 decimal point allowed
+        if fraction.contains('.') {
+            return None;
+        }
+        // there must be at least one digit
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        // there cannot be more decimals than the assumed amount
+        if fraction.len() > decimals {
             return None;
         }
 
-        if amount_str.matches('.').count() > 1 {
-            // only 1 decimal point allowed
-            None
-        } else if amount_str.matches('.').count() == 0 {
-            // if there is no decimal point, then just add N zeros to the right and we're done
-            let zeros = "0".repeat(decimals);
-            let amount_str = amount_str.to_owned() + &zeros;
-
-            amount_str.parse::<IntType>().ok().map(|v| Amount { val: v })
-        } else {
-            // if there's 1 decomal point, split, join the numbers, then add zeros to the right
-            let amount_split = amount_str.split('.').collect::<Vec<&str>>();
-            debug_assert!(amount_split.len() == 2); // we already checked we have 1 decimal exactly
-            if amount_split[1].len() > decimals {
-                // there cannot be more decimals than the assumed amount
-                return None;
-            }
-            let zeros = "0".repeat(decimals - amount_split[1].len());
-            let atoms_str = amount_split[0].to_owned() + amount_split[1] + &zeros;
-            let atoms_str = atoms_str.trim_start_matches('0');
-
-            atoms_str.parse::<IntType>().ok().map(|v| Amount { val: v })
+        // join the numbers, then add zeros to the right
+        let zeros = "0".repeat(decimals - fraction.len());
+        let atoms_str = whole.to_owned() + fraction + &zeros;
+        let atoms_str = atoms_str.trim_start_matches('0');
+        if atoms_str.is_empty() {
+            return Some(Amount { val: 0 });
         }
+
+        atoms_str.parse::<IntType>().ok().map(|v| Amount { val: v })
     }
 }
 
@@ -153,86 +178,40 @@ impl std::ops::Rem<IntType> for Amount {
     }
 }
 
-impl std::ops::BitAnd for Amount {
-    type Output = Self;
-
-    fn bitand(self, other: Self) -> Self {
-        Amount {
-            val: self.val.bitand(other.val),
-        }
-    }
-}
-
-impl std::ops::BitAndAssign for Amount {
-    fn bitand_assign(&mut self, other: Self) {
-        self.val.bitand_assign(other.val)
-    }
-}
-
-impl std::ops::BitOr for Amount {
-    type Output = Self;
-
-    fn bitor(self, other: Self) -> Self {
-        Amount {
-            val: self.val.bitor(other.val),
-        }
-    }
-}
-
-impl std::ops::BitOrAssign for Amount {
-    fn bitor_assign(&mut self, other: Self) {
-        self.val.bitor_assign(other.val)
-    }
-}
-
-impl std::ops::BitXor for Amount {
-    type Output = Self;
-
-    fn bitxor(self, other: Self) -> Self {
-        Amount {
-            val: self.val.bitxor(other.val),
-        }
+impl Sum<Amount> for Option<Amount> {
+    fn sum<I>(mut iter: I) -> Self
+    where
+        I: Iterator<Item = Amount>,
+    {
+        iter.try_fold(Amount::from_atoms(0), std::ops::Add::add)
     }
 }
 
-impl std::ops::BitXorAssign for Amount {
-    fn bitxor_assign(&mut self, other: Self) {
-        self.val.bitxor_assign(other.val)
+// Atoms are serialized as a decimal string since JSON numbers can't hold every u128
+#[cfg(feature = "serde-primitives")]
+impl serde::Serialize for Amount {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.val.to_string())
     }
 }
 
-impl std::ops::Not for Amount {
-    type Output = Self;
-
-    fn not(self) -> Self {
-        Amount {
-            val: self.val.not(),
+#[cfg(feature = "serde-primitives")]
+impl<'de> serde::Deserialize<'de> for Amount {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct AmountVisitor;
+        impl<'de> serde::de::Visitor<'de> for AmountVisitor {
+            type Value = Amount;
+            fn expecting(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                fmt.write_str("an amount of atoms as a decimal string")
+            }
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                if !s.bytes().all(|c| c.is_ascii_digit()) {
+                    return Err(E::invalid_value(serde::de::Unexpected::Str(s), &self));
+                }
+                s.parse().map(Amount::from_atoms).map_err(serde::de::Error::custom)
+            }
         }
-    }
-}
-
-impl std::ops::Shl<u32> for Amount {
-    type Output = Option<Self>;
-
-    fn shl(self, other: u32) -> Option<Self> {
-        self.val.checked_shl(other).map(|v| Amount { val: v })
-    }
-}
-
-impl std::ops::Shr<u32> for Amount {
-    type Output = Option<Self>;
-
-    fn shr(self, other: u32) -> Option<Self> {
-        self.val.checked_shr(other).map(|v| Amount { val: v })
-    }
-}
-
-impl Sum<Amount> for Option<Amount> {
-    fn sum<I>(mut iter: I) -> Self
-    where
-        I: Iterator<Item = Amount>,
-    {
-        iter.try_fold(Amount::from_atoms(0), std::ops::Add::add)
+        d.deserialize_str(AmountVisitor)
     }
 }
 
@@ -312,44 +291,43 @@ mod tests {
     }
 
     #[test]
-    fn bit_ops() {
-        let x = Amount { val: 5 };
-        let y = Amount { val: 1 };
-        let z = Amount { val: 2 };
-        let zero: IntType = 0;
-        assert_eq!(x | y, Amount { val: 5 });
-        assert_eq!(x & z, Amount { val: 0 });
-        assert_eq!(x ^ y, Amount { val: 4 });
-        assert!(!zero == IntType::MAX);
+    fn sum() {
+        let amounts = [Amount { val: 1 }, Amount { val: 2 }, Amount { val: 3 }];
+        assert_eq!(Amount::sum(amounts), Some(Amount { val: 6 }));
+        assert_eq!(
+            amounts.into_iter().sum::<Option<Amount>>(),
+            Some(Amount { val: 6 })
+        );
+        assert_eq!(Amount::sum(Vec::<Amount>::new()), Some(Amount { val: 0 }));
+        assert_eq!(
+            Amount::sum([Amount { val: IntType::MAX }, Amount { val: 1 }]),
+            None
+        );
     }
 
     #[test]
-    fn bit_ops_assign() {
-        let mut x = Amount { val: 5 };
-
-        x ^= Amount { val: 1 };
-        assert_eq!(x, Amount { val: 4 });
-
-        x |= Amount { val: 2 };
-        assert_eq!(x, Amount { val: 6 });
-
-        x &= Amount { val: 5 };
-        assert_eq!(x, Amount { val: 4 });
+    fn checked_scalar_ops() {
+        let x = Amount { val: 10 };
+        assert_eq!(x.checked_mul(3), Some(Amount { val: 30 }));
+        assert_eq!(x.checked_mul(IntType::MAX), None);
+        assert_eq!(x.checked_div(3), Some(Amount { val: 3 }));
+        assert_eq!(x.checked_div(0), None);
     }
 
     #[test]
-    fn bit_shifts() {
-        let x = Amount { val: 1 };
-        assert_eq!(x << 1, Some(Amount { val: 2 }));
-        assert_eq!(x << 2, Some(Amount { val: 4 }));
-        assert_eq!(x << 4, Some(Amount { val: 16 }));
-        assert_eq!(x << 6, Some(Amount { val: 64 }));
-
-        let y = Amount { val: 128 };
-        assert_eq!(y >> 1, Some(Amount { val: 64 }));
-        assert_eq!(y >> 2, Some(Amount { val: 32 }));
-        assert_eq!(y >> 4, Some(Amount { val: 8 }));
-        assert_eq!(y >> 6, Some(Amount { val: 2 }));
+    fn abs_diff() {
+        assert_eq!(
+            Amount { val: 3 }.abs_diff(Amount { val: 10 }),
+            Amount { val: 7 }
+        );
+        assert_eq!(
+            Amount { val: 10 }.abs_diff(Amount { val: 3 }),
+            Amount { val: 7 }
+        );
+        assert_eq!(
+            Amount { val: 0 }.abs_diff(Amount { val: IntType::MAX }),
+            Amount { val: IntType::MAX }
+        );
     }
 
     #[rustfmt::skip]
@@ -419,6 +397,56 @@ mod tests {
         assert!(Amount::from_fixedpoint_str("-1.23e4567891", 8).is_none());
     }
 
+    #[test]
+    fn from_fixedpoint_zero_and_malformed() {
+        assert_eq!(Amount::from_fixedpoint_str("0", 8), Some(Amount { val: 0 }));
+        assert_eq!(
+            Amount::from_fixedpoint_str("0.0", 8),
+            Some(Amount { val: 0 })
+        );
+        assert_eq!(
+            Amount::from_fixedpoint_str("000.00000000", 8),
+            Some(Amount { val: 0 })
+        );
+        assert_eq!(
+            Amount::from_fixedpoint_str(".5", 1),
+            Some(Amount { val: 5 })
+        );
+        assert_eq!(
+            Amount::from_fixedpoint_str("0.00000001", 8),
+            Some(Amount { val: 1 })
+        );
+        assert_eq!(Amount::from_fixedpoint_str(".", 8), None);
+        assert_eq!(Amount::from_fixedpoint_str("1.2.3", 8), None);
+        // Non-ASCII digits
+        assert_eq!(Amount::from_fixedpoint_str("\u{0663}", 0), None);
+        assert_eq!(Amount::from_fixedpoint_str("1.\u{0663}", 8), None);
+    }
+
+    #[test]
+    fn fixedpoint_overflow() {
+        let max_str = IntType::MAX.to_string();
+        assert_eq!(
+            Amount::from_fixedpoint_str(&max_str, 0),
+            Some(Amount { val: IntType::MAX })
+        );
+        assert_eq!(Amount::from_fixedpoint_str(&max_str, 1), None);
+        assert_eq!(Amount::from_fixedpoint_str(&format!("{max_str}0"), 0), None);
+
+        // Round trip with more decimals than digits in the largest amount
+        for decimals in [0, 11, 38, 39, 50, u8::MAX] {
+            for val in [0, 1, 12345, IntType::MAX] {
+                let amount = Amount { val };
+                let amount_str = amount.into_fixedpoint_str(decimals);
+                assert_eq!(
+                    Amount::from_fixedpoint_str(&amount_str, decimals),
+                    Some(amount),
+                    "{amount_str} with {decimals} decimals"
+                );
+            }
+        }
+    }
+
     #[rustfmt::skip]
     #[test]
     fn from_fixedpoint_0_decimals() {
@@ -692,4 +720,28 @@ mod tests {
         assert_eq!(Amount { val: 12345678901200 }.into_fixedpoint_str(1), "1234567890120");
         assert_eq!(Amount { val: 123456789012300 }.into_fixedpoint_str(1), "12345678901230");
     }
+
+    #[test]
+    #[cfg(feature = "serde-primitives")]
+    fn amount_json() {
+        fn check(atoms: &'static str) {
+            let amount = Amount::from_atoms(atoms.parse().unwrap());
+            // Amount should serialize into its number of atoms as a string
+            serde_test::assert_tokens(&amount, &[serde_test::Token::Str(atoms)]);
+            assert_eq!(
+                serde_json::to_value(amount).ok(),
+                Some(serde_json::Value::String(atoms.to_owned()))
+            );
+        }
+        check("0");
+        check("1");
+        check("18446744073709551616");
+        check("340282366920938463463374607431768211455");
+
+        assert!(serde_json::from_str::<Amount>("\"-1\"").is_err());
+        assert!(serde_json::from_str::<Amount>("\"+1\"").is_err());
+        assert!(serde_json::from_str::<Amount>("\"1.5\"").is_err());
+        assert!(serde_json::from_str::<Amount>("\"\"").is_err());
+        assert!(serde_json::from_str::<Amount>("1").is_err());
+    }
 }
@@ -2,22 +2,15 @@ use std::fmt;
 use std::ops::{Add, Sub};
 
 use serialization::{Decode, Encode};
+use thiserror::Error;
 
 type HeightIntType = u64;
 type DistanceIntType = i64;
 
-#[derive(
-    Debug,
-    Copy,
-    Clone,
-    PartialOrd,
-    Ord,
-    PartialEq,
-    Eq,
-    Encode,
-    Decode,
-    serde::Serialize,
-    serde::Deserialize,
+#[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(
+    feature = "serde-primitives",
+    derive(serde::Serialize, serde::Deserialize)
 )]
 pub struct BlockHeight(#[codec(compact)] HeightIntType);
 
@@ -134,6 +127,72 @@ impl BlockHeight {
     }
 }
 
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockHeightError {
+    #[error("Block height {0} moved by {1} blocks is out of range")]
+    DistanceOutOfRange(BlockHeight, BlockDistance),
+    #[error("Value {0} does not fit in a block height")]
+    ValueOutOfRange(usize),
+}
+
+impl TryFrom<usize> for BlockHeight {
+    type Error = BlockHeightError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        HeightIntType::try_from(value)
+            .map(Self)
+            .map_err(|_| BlockHeightError::ValueOutOfRange(value))
+    }
+}
+
+impl BlockHeight {
+    // i128 holds every height and distance, so moving a height can't overflow before the range check
+    fn moved_by(&self, distance: i128) -> Result<Self, HeightIntType> {
+        let result = self.0 as i128 + distance;
+        HeightIntType::try_from(result).map(Self).map_err(|_| {
+            if result < 0 {
+                0
+            } else {
+                HeightIntType::MAX
+            }
+        })
+    }
+
+    pub fn checked_add_distance(&self, distance: BlockDistance) -> Result<Self, BlockHeightError> {
+        self.moved_by(distance.0 as i128)
+            .map_err(|_| BlockHeightError::DistanceOutOfRange(*self, distance))
+    }
+
+    pub fn checked_sub_distance(&self, distance: BlockDistance) -> Result<Self, BlockHeightError> {
+        self.moved_by(-(distance.0 as i128)).map_err(|_| {
+            BlockHeightError::DistanceOutOfRange(*self, BlockDistance(distance.0.saturating_neg()))
+        })
+    }
+
+    /// Like `checked_add_distance`, but clamps the result to the valid heights
+    pub fn saturating_add_distance(&self, distance: BlockDistance) -> Self {
+        self.moved_by(distance.0 as i128).unwrap_or_else(Self)
+    }
+
+    /// Like `checked_sub_distance`, but clamps the result to the valid heights
+    pub fn saturating_sub_distance(&self, distance: BlockDistance) -> Self {
+        self.moved_by(-(distance.0 as i128)).unwrap_or_else(Self)
+    }
+
+    /// Heights from this one up to, but not including, `end`
+    pub fn iter_up_to(&self, end: BlockHeight) -> impl DoubleEndedIterator<Item = BlockHeight> {
+        (self.0..end.0).map(Self)
+    }
+
+    /// Heights from this one up to and including `end`
+    pub fn iter_up_to_inclusive(
+        &self,
+        end: BlockHeight,
+    ) -> impl DoubleEndedIterator<Item = BlockHeight> {
+        (self.0..=end.0).map(Self)
+    }
+}
+
 /////////////////////////////
 
 #[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Encode, Decode)]
@@ -228,6 +287,102 @@ mod tests {
     }
 
     #[test]
+    fn checked_distance_arithmetic() {
+        let h_5 = BlockHeight::new(5);
+        assert_eq!(
+            h_5.checked_add_distance(BlockDistance::new(3)),
+            Ok(BlockHeight::new(8))
+        );
+        assert_eq!(
+            h_5.checked_add_distance(BlockDistance::new(-5)),
+            Ok(BlockHeight::zero())
+        );
+        assert_eq!(
+            h_5.checked_add_distance(BlockDistance::new(-6)),
+            Err(BlockHeightError::DistanceOutOfRange(
+                h_5,
+                BlockDistance::new(-6)
+            ))
+        );
+        assert_eq!(
+            h_5.checked_sub_distance(BlockDistance::new(6)),
+            Err(BlockHeightError::DistanceOutOfRange(
+                h_5,
+                BlockDistance::new(-6)
+            ))
+        );
+        assert_eq!(
+            h_5.checked_sub_distance(BlockDistance::new(-3)),
+            Ok(BlockHeight::new(8))
+        );
+
+        // Heights above the largest distance can still be moved
+        assert_eq!(
+            BlockHeight::max().checked_sub_distance(BlockDistance::new(1)),
+            Ok(BlockHeight::new(HeightIntType::MAX - 1))
+        );
+        assert_eq!(
+            BlockHeight::max().checked_add_distance(BlockDistance::new(1)),
+            Err(BlockHeightError::DistanceOutOfRange(
+                BlockHeight::max(),
+                BlockDistance::new(1)
+            ))
+        );
+        assert_eq!(
+            BlockHeight::zero().checked_sub_distance(BlockDistance::new(DistanceIntType::MIN)),
+            Ok(BlockHeight::new(DistanceIntType::MAX as HeightIntType + 1))
+        );
+    }
+
+    #[test]
+    fn saturating_distance_arithmetic() {
+        let h_5 = BlockHeight::new(5);
+        assert_eq!(
+            h_5.saturating_sub_distance(BlockDistance::new(6)),
+            BlockHeight::zero()
+        );
+        assert_eq!(
+            h_5.saturating_add_distance(BlockDistance::new(DistanceIntType::MIN)),
+            BlockHeight::zero()
+        );
+        assert_eq!(
+            BlockHeight::max().saturating_add_distance(BlockDistance::new(1)),
+            BlockHeight::max()
+        );
+        assert_eq!(
+            h_5.saturating_add_distance(BlockDistance::new(2)),
+            BlockHeight::new(7)
+        );
+    }
+
+    #[test]
+    fn height_ranges() {
+        let start = BlockHeight::new(3);
+        let end = BlockHeight::new(6);
+        assert_eq!(
+            start.iter_up_to(end).collect::<Vec<_>>(),
+            [3, 4, 5].map(BlockHeight::new)
+        );
+        assert_eq!(
+            start.iter_up_to_inclusive(end).rev().collect::<Vec<_>>(),
+            [6, 5, 4, 3].map(BlockHeight::new)
+        );
+        assert_eq!(end.iter_up_to(start).count(), 0);
+        assert_eq!(start.iter_up_to(start).count(), 0);
+        assert_eq!(start.iter_up_to_inclusive(start).count(), 1);
+    }
+
+    #[test]
+    fn from_usize() {
+        assert_eq!(BlockHeight::try_from(0usize), Ok(BlockHeight::zero()));
+        assert_eq!(
+            BlockHeight::try_from(544221usize),
+            Ok(BlockHeight::new(544221))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde-primitives")]
     fn blockheight_json() {
         fn check(height: BlockHeight) {
             use serde_test::{
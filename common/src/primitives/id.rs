@@ -18,6 +18,15 @@
 use crate::{construct_fixed_hash, Uint256};
 use generic_array::{typenum, GenericArray};
 use serialization::{Decode, Encode};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum HashParseError {
+    #[error("Invalid hex character {0:?} at position {1}")]
+    InvalidCharacter(char, usize),
+    #[error("Expected {0} hex digits")]
+    InvalidLength(usize),
+}
 
 construct_fixed_hash! {
     #[derive(Encode, Decode)]
@@ -42,12 +51,14 @@ impl From<Uint256> for H256 {
     }
 }
 
+#[cfg(feature = "serde-primitives")]
 impl serde::Serialize for H256 {
     fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
         s.serialize_str(&format!("{:x}", self))
     }
 }
 
+#[cfg(feature = "serde-primitives")]
 impl<'de> serde::Deserialize<'de> for H256 {
     fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
         struct HashVisitor;
@@ -64,11 +75,15 @@ impl<'de> serde::Deserialize<'de> for H256 {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, serde::Serialize, serde::Deserialize)]
-#[serde(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(
+    feature = "serde-primitives",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Id<T: ?Sized> {
     id: H256,
-    #[serde(skip)]
+    #[cfg_attr(feature = "serde-primitives", serde(skip))]
     _shadow: std::marker::PhantomData<fn() -> T>,
 }
 
@@ -105,6 +120,24 @@ impl<T> Id<T> {
     }
 }
 
+/// Full hex of the id, as accepted by `FromStr`
+impl<T: ?Sized> std::fmt::Display for Id<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:x}", self.id)
+    }
+}
+
+impl<T: ?Sized> std::str::FromStr for Id<T> {
+    type Err = HashParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            id: s.parse()?,
+            _shadow: std::marker::PhantomData,
+        })
+    }
+}
+
 impl<T> AsRef<[u8]> for Id<T> {
     fn as_ref(&self) -> &[u8] {
         &self.id[..]
@@ -213,6 +246,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "serde-primitives")]
     fn h256_json() {
         fn check(hex: &'static str) {
             use serde_json::Value;
@@ -228,6 +262,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "serde-primitives")]
     fn id_json() {
         fn check(hex: &'static str) {
             use serde_json::Value;
@@ -241,4 +276,38 @@ mod tests {
         }
         SAMPLE_HASHES.iter().cloned().for_each(check)
     }
+
+    #[test]
+    fn id_display_and_parse() {
+        fn check(hex: &'static str) {
+            let id: Id<()> = hex.parse().unwrap();
+            assert_eq!(id.get(), H256::from_str(hex).unwrap());
+            assert_eq!(id.to_string(), hex);
+            assert_eq!(format!("0x{}", id).parse::<Id<()>>(), Ok(id));
+        }
+        SAMPLE_HASHES.iter().cloned().for_each(check)
+    }
+
+    #[test]
+    fn hash_parse_errors() {
+        let hex = SAMPLE_HASHES[2];
+        assert_eq!(H256::from_str(""), Err(HashParseError::InvalidLength(64)));
+        assert_eq!(H256::from_str("0x"), Err(HashParseError::InvalidLength(64)));
+        assert_eq!(
+            H256::from_str(&hex[1..]),
+            Err(HashParseError::InvalidLength(64))
+        );
+        assert_eq!(
+            H256::from_str(&format!("{}00", hex)),
+            Err(HashParseError::InvalidLength(64))
+        );
+        assert!(matches!(
+            H256::from_str(&format!("{}g", &hex[1..])),
+            Err(HashParseError::InvalidCharacter('g', _))
+        ));
+        assert!(matches!(
+            Id::<()>::from_str(&format!("0x{}", hex.replace('6', "x"))),
+            Err(HashParseError::InvalidCharacter('x', _))
+        ));
+    }
 }
@@ -42,6 +42,7 @@ fn next_pow2(n: usize) -> usize {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MerkleTreeFormError {
     TooSmall(usize),
+    LeafIndexOutOfRange(usize, usize),
     Unknown(String),
 }
 
@@ -96,6 +97,82 @@ pub fn merkletree_from_vec(
     Ok(tree)
 }
 
+fn merkle_node_hash(left: &H256, right: &H256) -> H256 {
+    let mut hasher = DefaultHashAlgoStream::new();
+    hasher.write(left);
+    hasher.write(right);
+    hasher.finalize().into()
+}
+
+/// Path from a leaf to the root of a merkle tree, proving the leaf is part of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    leaf_index: usize,
+    siblings: Vec<H256>,
+}
+
+impl MerkleProof {
+    pub fn new(leaf_index: usize, siblings: Vec<H256>) -> Self {
+        Self {
+            leaf_index,
+            siblings,
+        }
+    }
+
+    pub fn leaf_index(&self) -> usize {
+        self.leaf_index
+    }
+
+    /// Hashes of the sibling nodes, starting from the sibling of the leaf
+    pub fn siblings(&self) -> &[H256] {
+        &self.siblings
+    }
+
+    /// Root of the tree the proof leads to when starting from the given leaf
+    pub fn root_from_leaf(&self, leaf: H256) -> H256 {
+        self.siblings.iter().enumerate().fold(leaf, |node, (level, sibling)| {
+            let is_left = self.leaf_index.checked_shr(level as u32).unwrap_or(0) & 1 == 0;
+            if is_left {
+                merkle_node_hash(&node, sibling)
+            } else {
+                merkle_node_hash(sibling, &node)
+            }
+        })
+    }
+
+    pub fn verify(&self, leaf: H256, root: H256) -> bool {
+        self.root_from_leaf(leaf) == root
+    }
+}
+
+/// Given a set of leaf hashes, extract the proof for the leaf at the index from the tree
+/// calculated by `merkletree_from_vec`
+pub fn merkle_proof_from_vec(
+    elements: &[H256],
+    leaf_index: usize,
+) -> Result<MerkleProof, MerkleTreeFormError> {
+    if elements.len() < 2 {
+        return Err(MerkleTreeFormError::TooSmall(elements.len()));
+    }
+    if leaf_index >= elements.len() {
+        return Err(MerkleTreeFormError::LeafIndexOutOfRange(
+            leaf_index,
+            elements.len(),
+        ));
+    }
+
+    let mut level: Vec<H256> =
+        elements.iter().cloned().chain(merkletree_get_pad_data(elements)).collect();
+    let mut index = leaf_index;
+    let mut siblings = Vec::new();
+    while level.len() > 1 {
+        siblings.push(level[index ^ 1]);
+        level = level.chunks(2).map(|pair| merkle_node_hash(&pair[0], &pair[1])).collect();
+        index /= 2;
+    }
+    Ok(MerkleProof::new(leaf_index, siblings))
+}
+
 impl Default for BlockchainHashAlgorithm {
     fn default() -> BlockchainHashAlgorithm {
         BlockchainHashAlgorithm(DefaultHashAlgoStream::new())
@@ -436,4 +513,41 @@ mod tests {
         (257..513).for_each(|n| assert_eq!(next_pow2(n), 512));
         (513..1025).for_each(|n| assert_eq!(next_pow2(n), 1024));
     }
+
+    #[test]
+    fn merkle_proofs() {
+        for count in 2..20u64 {
+            let leaves: Vec<H256> =
+                (0..count).map(|i| default_hash(H256::from_low_u64_be(i))).collect();
+            let root = merkletree_from_vec(&leaves).unwrap().root();
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof_from_vec(&leaves, index).unwrap();
+                assert_eq!(proof.leaf_index(), index);
+                assert_eq!(
+                    proof.siblings().len(),
+                    next_pow2(leaves.len()).trailing_zeros() as usize
+                );
+                assert!(proof.verify(*leaf, root));
+                assert!(!proof.verify(H256::zero(), root));
+
+                let other = (index + 1) % leaves.len();
+                let wrong_index = MerkleProof::new(other, proof.siblings().to_vec());
+                assert!(!wrong_index.verify(*leaf, root));
+            }
+
+            assert_eq!(
+                merkle_proof_from_vec(&leaves, leaves.len()),
+                Err(MerkleTreeFormError::LeafIndexOutOfRange(
+                    leaves.len(),
+                    leaves.len()
+                ))
+            );
+        }
+
+        assert_eq!(
+            merkle_proof_from_vec(&[H256::zero()], 0),
+            Err(MerkleTreeFormError::TooSmall(1))
+        );
+    }
 }
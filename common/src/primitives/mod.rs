@@ -23,6 +23,7 @@ mod hash_encoded;
 pub mod height;
 pub mod id;
 pub mod merkle;
+pub mod signed_amount;
 pub mod time;
 
 pub mod version;
@@ -32,3 +33,4 @@ pub use compact::Compact;
 pub use encoding::{Bech32Error, DecodedArbitraryDataFromBech32};
 pub use height::{BlockDistance, BlockHeight};
 pub use id::{Id, Idable, H256};
+pub use signed_amount::SignedAmount;
@@ -0,0 +1,167 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serialization::{Decode, Encode};
+
+use super::Amount;
+
+pub type SignedIntType = i128;
+
+/// A signed fixed-point type for differences between amounts, such as fee deltas
+/// The smallest unit of count is an atom, the same as for `Amount`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+pub struct SignedAmount {
+    val: SignedIntType,
+}
+
+impl SignedAmount {
+    pub const ZERO: SignedAmount = SignedAmount::from_atoms(0);
+
+    pub const fn from_atoms(v: SignedIntType) -> Self {
+        SignedAmount { val: v }
+    }
+
+    pub fn into_atoms(&self) -> SignedIntType {
+        self.val
+    }
+
+    /// `None` if the amount is negative
+    pub fn into_unsigned(self) -> Option<Amount> {
+        u128::try_from(self.val).ok().map(Amount::from_atoms)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.val < 0
+    }
+
+    /// `None` if the amount is `SignedIntType::MIN`, which has no positive counterpart
+    pub fn abs(self) -> Option<Self> {
+        self.val.checked_abs().map(|v| SignedAmount { val: v })
+    }
+}
+
+impl Amount {
+    /// `None` if the amount doesn't fit into a `SignedAmount`
+    pub fn into_signed(self) -> Option<SignedAmount> {
+        SignedIntType::try_from(self.into_atoms()).ok().map(SignedAmount::from_atoms)
+    }
+
+    /// Signed difference `self - other`
+    pub fn signed_diff(self, other: Amount) -> Option<SignedAmount> {
+        self.into_signed()? - other.into_signed()?
+    }
+}
+
+impl std::ops::Add for SignedAmount {
+    type Output = Option<Self>;
+
+    fn add(self, other: Self) -> Option<Self> {
+        self.val.checked_add(other.val).map(|n| SignedAmount { val: n })
+    }
+}
+
+impl std::ops::Sub for SignedAmount {
+    type Output = Option<Self>;
+
+    fn sub(self, other: Self) -> Option<Self> {
+        self.val.checked_sub(other.val).map(|n| SignedAmount { val: n })
+    }
+}
+
+impl std::ops::Neg for SignedAmount {
+    type Output = Option<Self>;
+
+    fn neg(self) -> Option<Self> {
+        self.val.checked_neg().map(|n| SignedAmount { val: n })
+    }
+}
+
+impl std::ops::Mul<SignedIntType> for SignedAmount {
+    type Output = Option<Self>;
+
+    fn mul(self, other: SignedIntType) -> Option<Self> {
+        self.val.checked_mul(other).map(|n| SignedAmount { val: n })
+    }
+}
+
+impl std::ops::Div<SignedIntType> for SignedAmount {
+    type Output = Option<Self>;
+
+    fn div(self, other: SignedIntType) -> Option<Self> {
+        self.val.checked_div(other).map(|n| SignedAmount { val: n })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let a = SignedAmount::from_atoms(5);
+        let b = SignedAmount::from_atoms(-7);
+
+        assert_eq!(a + b, Some(SignedAmount::from_atoms(-2)));
+        assert_eq!(a - b, Some(SignedAmount::from_atoms(12)));
+        assert_eq!(-b, Some(SignedAmount::from_atoms(7)));
+        assert_eq!(b * 3, Some(SignedAmount::from_atoms(-21)));
+        assert_eq!(b / 2, Some(SignedAmount::from_atoms(-3)));
+        assert_eq!(b / 0, None);
+        assert!(b.is_negative());
+        assert!(!SignedAmount::ZERO.is_negative());
+        assert!(b < a);
+    }
+
+    #[test]
+    fn overflow() {
+        let min = SignedAmount::from_atoms(SignedIntType::MIN);
+        let max = SignedAmount::from_atoms(SignedIntType::MAX);
+
+        assert_eq!(max + SignedAmount::from_atoms(1), None);
+        assert_eq!(min - SignedAmount::from_atoms(1), None);
+        assert_eq!(-min, None);
+        assert_eq!(min.abs(), None);
+        assert_eq!(min * -1, None);
+        assert_eq!(min / -1, None);
+        assert_eq!(-max, Some(SignedAmount::from_atoms(-SignedIntType::MAX)));
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(
+            Amount::from_atoms(10).into_signed(),
+            Some(SignedAmount::from_atoms(10))
+        );
+        assert_eq!(Amount::from_atoms(u128::MAX).into_signed(), None);
+        assert_eq!(
+            SignedAmount::from_atoms(10).into_unsigned(),
+            Some(Amount::from_atoms(10))
+        );
+        assert_eq!(SignedAmount::from_atoms(-1).into_unsigned(), None);
+
+        assert_eq!(
+            Amount::from_atoms(3).signed_diff(Amount::from_atoms(10)),
+            Some(SignedAmount::from_atoms(-7))
+        );
+        assert_eq!(
+            Amount::from_atoms(10).signed_diff(Amount::from_atoms(3)),
+            Some(SignedAmount::from_atoms(7))
+        );
+        assert_eq!(
+            Amount::from_atoms(u128::MAX).signed_diff(Amount::from_atoms(3)),
+            None
+        );
+    }
+}
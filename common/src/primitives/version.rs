@@ -16,7 +16,7 @@
 // Author(s): A. Altonen
 use serialization::{Decode, Encode};
 
-#[derive(Debug, PartialEq, Eq, Encode, Decode, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, Copy, Clone)]
 pub struct SemVer {
     pub major: u8,
     pub minor: u8,
@@ -138,4 +138,15 @@ mod tests {
             Ok(SemVer::new(1, 2, 0x500))
         );
     }
+
+    #[test]
+    fn vertest_ordering() {
+        assert!(SemVer::new(0, 1, 0) < SemVer::new(0, 1, 1));
+        assert!(SemVer::new(0, 1, 0xffff) < SemVer::new(0, 2, 0));
+        assert!(SemVer::new(0, 0xff, 0xffff) < SemVer::new(1, 0, 0));
+        assert_eq!(
+            SemVer::new(1, 2, 3).cmp(&SemVer::new(1, 2, 3)),
+            std::cmp::Ordering::Equal
+        );
+    }
 }
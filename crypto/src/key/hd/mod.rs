@@ -0,0 +1,577 @@
+//! Hierarchical deterministic key derivation as specified by BIP32
+//!
+//! Keys are derived for the secp256k1 curve, so the derived keys are of the
+//! [`KeyKind::Secp256k1Schnorr`](super::KeyKind::Secp256k1Schnorr) kind. Extended keys use the
+//! BIP32 serialization, which makes them interchangeable with other BIP32 implementations.
+
+use std::{fmt, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use secp256k1::{Scalar, SecretKey, SECP256K1};
+
+use crate::hash::{hash, Ripemd160, Sha256};
+
+use super::{
+    secp256k1schnorr::{MLSecp256k1PrivateKey, MLSecp256k1PublicKey},
+    PrivateKey, PrivateKeyHolder, PublicKey, PublicKeyHolder,
+};
+
+type HmacSha512 = Hmac<sha2::Sha512>;
+
+/// Key of the HMAC used to derive the master key from the seed
+const MASTER_KEY_HMAC_KEY: &[u8] = b"Bitcoin seed";
+
+/// Version bytes of serialized extended private keys, "xprv" in base58
+const EXTENDED_PRIVATE_KEY_VERSION: [u8; 4] = [0x04, 0x88, 0xAD, 0xE4];
+/// Version bytes of serialized extended public keys, "xpub" in base58
+const EXTENDED_PUBLIC_KEY_VERSION: [u8; 4] = [0x04, 0x88, 0xB2, 0x1E];
+
+/// Length of a serialized extended key without the base58 checksum
+pub const EXTENDED_KEY_LENGTH: usize = 78;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum DerivationError {
+    InvalidSeedLength(usize),
+    InvalidChildNumber(u32),
+    HardenedDerivationFromPublicKey(ChildNumber),
+    MaxDepthExceeded,
+    /// The derived key is invalid, which happens with a probability lower than 1 in 2^127.
+    /// BIP32 says to proceed with the next child number.
+    InvalidDerivedKey(ChildNumber),
+    InvalidPath(String),
+    InvalidExtendedKey(String),
+}
+
+/// Index of a child key, hardened children can only be derived from private keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChildNumber(u32);
+
+impl ChildNumber {
+    const HARDENED_BIT: u32 = 1 << 31;
+
+    pub fn normal(index: u32) -> Result<Self, DerivationError> {
+        if index & Self::HARDENED_BIT != 0 {
+            return Err(DerivationError::InvalidChildNumber(index));
+        }
+        Ok(Self(index))
+    }
+
+    pub fn hardened(index: u32) -> Result<Self, DerivationError> {
+        if index & Self::HARDENED_BIT != 0 {
+            return Err(DerivationError::InvalidChildNumber(index));
+        }
+        Ok(Self(index | Self::HARDENED_BIT))
+    }
+
+    /// The child number as serialized, with the top bit set for hardened children
+    pub fn from_encoded(encoded: u32) -> Self {
+        Self(encoded)
+    }
+
+    pub fn encoded(&self) -> u32 {
+        self.0
+    }
+
+    pub fn index(&self) -> u32 {
+        self.0 & !Self::HARDENED_BIT
+    }
+
+    pub fn is_hardened(&self) -> bool {
+        self.0 & Self::HARDENED_BIT != 0
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_hardened() {
+            write!(f, "{}'", self.index())
+        } else {
+            write!(f, "{}", self.index())
+        }
+    }
+}
+
+impl FromStr for ChildNumber {
+    type Err = DerivationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DerivationError::InvalidPath(s.to_owned());
+        match s.strip_suffix(['\'', 'h', 'H']) {
+            Some(index) => Self::hardened(index.parse().map_err(|_| invalid())?),
+            None => Self::normal(s.parse().map_err(|_| invalid())?),
+        }
+    }
+}
+
+/// Path of child numbers from the master key, written as `m/44'/0'/0'/0/1`
+///
+/// Hardened children may also be marked with `h` or `H` instead of `'`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DerivationPath(Vec<ChildNumber>);
+
+impl DerivationPath {
+    pub fn master() -> Self {
+        Self::default()
+    }
+
+    pub fn child(&self, child_number: ChildNumber) -> Self {
+        let mut path = self.0.clone();
+        path.push(child_number);
+        Self(path)
+    }
+
+    pub fn as_slice(&self) -> &[ChildNumber] {
+        &self.0
+    }
+}
+
+impl From<Vec<ChildNumber>> for DerivationPath {
+    fn from(path: Vec<ChildNumber>) -> Self {
+        Self(path)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        self.0.iter().try_for_each(|child| write!(f, "/{}", child))
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = DerivationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(DerivationError::InvalidPath(s.to_owned()));
+        }
+        parts.map(ChildNumber::from_str).collect::<Result<_, _>>().map(Self)
+    }
+}
+
+/// First 4 bytes of the HASH160 of the compressed public key
+fn fingerprint(public_key: &secp256k1::PublicKey) -> [u8; 4] {
+    let key_hash = hash::<Ripemd160, _>(hash::<Sha256, _>(public_key.serialize()));
+    [key_hash[0], key_hash[1], key_hash[2], key_hash[3]]
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts keys of any length");
+    data.iter().for_each(|part| mac.update(part));
+    let result = mac.finalize().into_bytes();
+    let (left, right) = result.split_at(32);
+    (
+        left.try_into().expect("left half is 32 bytes"),
+        right.try_into().expect("right half is 32 bytes"),
+    )
+}
+
+/// Fields shared by the serialization of extended private and public keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExtendedKeyInfo {
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: ChildNumber,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKeyInfo {
+    fn child(
+        &self,
+        parent_fingerprint: [u8; 4],
+        child_number: ChildNumber,
+        chain_code: [u8; 32],
+    ) -> Result<Self, DerivationError> {
+        let depth = self.depth.checked_add(1).ok_or(DerivationError::MaxDepthExceeded)?;
+        Ok(Self {
+            depth,
+            parent_fingerprint,
+            child_number,
+            chain_code,
+        })
+    }
+
+    fn encode(&self, version: [u8; 4], key_data: &[u8; 33]) -> [u8; EXTENDED_KEY_LENGTH] {
+        let mut result = [0; EXTENDED_KEY_LENGTH];
+        result[0..4].copy_from_slice(&version);
+        result[4] = self.depth;
+        result[5..9].copy_from_slice(&self.parent_fingerprint);
+        result[9..13].copy_from_slice(&self.child_number.encoded().to_be_bytes());
+        result[13..45].copy_from_slice(&self.chain_code);
+        result[45..78].copy_from_slice(key_data);
+        result
+    }
+
+    /// Returns the info and the key data if the version matches
+    fn decode(version: [u8; 4], bytes: &[u8]) -> Result<(Self, [u8; 33]), DerivationError> {
+        let invalid = |reason: &str| DerivationError::InvalidExtendedKey(reason.to_owned());
+        let bytes: &[u8; EXTENDED_KEY_LENGTH] =
+            bytes.try_into().map_err(|_| invalid("invalid length"))?;
+        if bytes[0..4] != version {
+            return Err(invalid("unexpected version"));
+        }
+        let info = Self {
+            depth: bytes[4],
+            parent_fingerprint: bytes[5..9].try_into().expect("4 bytes"),
+            child_number: ChildNumber::from_encoded(u32::from_be_bytes(
+                bytes[9..13].try_into().expect("4 bytes"),
+            )),
+            chain_code: bytes[13..45].try_into().expect("32 bytes"),
+        };
+        if info.depth == 0
+            && (info.parent_fingerprint != [0; 4] || info.child_number.encoded() != 0)
+        {
+            return Err(invalid("master key with a parent"));
+        }
+        Ok((info, bytes[45..78].try_into().expect("33 bytes")))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPrivateKey {
+    info: ExtendedKeyInfo,
+    private_key: SecretKey,
+}
+
+impl ExtendedPrivateKey {
+    /// Derive the master key from a seed of 16 to 64 bytes
+    pub fn new_master(seed: &[u8]) -> Result<Self, DerivationError> {
+        if !(16..=64).contains(&seed.len()) {
+            return Err(DerivationError::InvalidSeedLength(seed.len()));
+        }
+        let (key, chain_code) = hmac_sha512(MASTER_KEY_HMAC_KEY, &[seed]);
+        let private_key = SecretKey::from_slice(&key)
+            .map_err(|_| DerivationError::InvalidSeedLength(seed.len()))?;
+        Ok(Self {
+            info: ExtendedKeyInfo {
+                depth: 0,
+                parent_fingerprint: [0; 4],
+                child_number: ChildNumber::from_encoded(0),
+                chain_code,
+            },
+            private_key,
+        })
+    }
+
+    pub fn derive_child(&self, child_number: ChildNumber) -> Result<Self, DerivationError> {
+        let public_key = self.native_public_key();
+        let (tweak, chain_code) = if child_number.is_hardened() {
+            hmac_sha512(
+                &self.info.chain_code,
+                &[&[0], &self.private_key.secret_bytes(), &child_number.encoded().to_be_bytes()],
+            )
+        } else {
+            hmac_sha512(
+                &self.info.chain_code,
+                &[&public_key.serialize(), &child_number.encoded().to_be_bytes()],
+            )
+        };
+        let private_key = Scalar::from_be_bytes(tweak)
+            .ok()
+            .and_then(|tweak| self.private_key.add_tweak(&tweak).ok())
+            .ok_or(DerivationError::InvalidDerivedKey(child_number))?;
+        Ok(Self {
+            info: self.info.child(fingerprint(&public_key), child_number, chain_code)?,
+            private_key,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, DerivationError> {
+        path.as_slice().iter().try_fold(self.clone(), |key, child_number| {
+            key.derive_child(*child_number)
+        })
+    }
+
+    pub fn to_extended_public_key(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            info: self.info.clone(),
+            public_key: self.native_public_key(),
+        }
+    }
+
+    fn native_public_key(&self) -> secp256k1::PublicKey {
+        secp256k1::PublicKey::from_secret_key(SECP256K1, &self.private_key)
+    }
+
+    pub fn private_key(&self) -> PrivateKey {
+        PrivateKey {
+            key: PrivateKeyHolder::Secp256k1Schnorr(MLSecp256k1PrivateKey::from_native(
+                self.private_key,
+            )),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.to_extended_public_key().public_key()
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.info.depth
+    }
+
+    pub fn child_number(&self) -> ChildNumber {
+        self.info.child_number
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.info.chain_code
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint(&self.native_public_key())
+    }
+
+    pub fn encode(&self) -> [u8; EXTENDED_KEY_LENGTH] {
+        let mut key_data = [0; 33];
+        key_data[1..].copy_from_slice(&self.private_key.secret_bytes());
+        self.info.encode(EXTENDED_PRIVATE_KEY_VERSION, &key_data)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, DerivationError> {
+        let (info, key_data) = ExtendedKeyInfo::decode(EXTENDED_PRIVATE_KEY_VERSION, bytes)?;
+        if key_data[0] != 0 {
+            return Err(DerivationError::InvalidExtendedKey(
+                "private key not prefixed by zero".to_owned(),
+            ));
+        }
+        let private_key = SecretKey::from_slice(&key_data[1..])
+            .map_err(|_| DerivationError::InvalidExtendedKey("invalid private key".to_owned()))?;
+        Ok(Self { info, private_key })
+    }
+}
+
+/// Base58check encoding, starting with "xprv"
+impl fmt::Display for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            bs58::encode(self.encode()).with_check().into_string()
+        )
+    }
+}
+
+impl FromStr for ExtendedPrivateKey {
+    type Err = DerivationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(s)
+            .with_check(None)
+            .into_vec()
+            .map_err(|e| DerivationError::InvalidExtendedKey(e.to_string()))?;
+        Self::decode(&bytes)
+    }
+}
+
+/// Extended public keys can derive the public keys of non-hardened children without the
+/// private key, for example to generate receiving addresses on a watch-only wallet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    info: ExtendedKeyInfo,
+    public_key: secp256k1::PublicKey,
+}
+
+impl ExtendedPublicKey {
+    pub fn derive_child(&self, child_number: ChildNumber) -> Result<Self, DerivationError> {
+        if child_number.is_hardened() {
+            return Err(DerivationError::HardenedDerivationFromPublicKey(
+                child_number,
+            ));
+        }
+        let (tweak, chain_code) = hmac_sha512(
+            &self.info.chain_code,
+            &[&self.public_key.serialize(), &child_number.encoded().to_be_bytes()],
+        );
+        let public_key = Scalar::from_be_bytes(tweak)
+            .ok()
+            .and_then(|tweak| self.public_key.add_exp_tweak(SECP256K1, &tweak).ok())
+            .ok_or(DerivationError::InvalidDerivedKey(child_number))?;
+        Ok(Self {
+            info: self.info.child(fingerprint(&self.public_key), child_number, chain_code)?,
+            public_key,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, DerivationError> {
+        path.as_slice().iter().try_fold(self.clone(), |key, child_number| {
+            key.derive_child(*child_number)
+        })
+    }
+
+    /// The x-only public key used to verify signatures of the derived private key
+    pub fn public_key(&self) -> PublicKey {
+        let (x_only, _parity) = self.public_key.x_only_public_key();
+        PublicKey {
+            pub_key: PublicKeyHolder::Secp256k1Schnorr(MLSecp256k1PublicKey::from_native(x_only)),
+        }
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.info.depth
+    }
+
+    pub fn child_number(&self) -> ChildNumber {
+        self.info.child_number
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.info.chain_code
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint(&self.public_key)
+    }
+
+    pub fn encode(&self) -> [u8; EXTENDED_KEY_LENGTH] {
+        self.info.encode(EXTENDED_PUBLIC_KEY_VERSION, &self.public_key.serialize())
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, DerivationError> {
+        let (info, key_data) = ExtendedKeyInfo::decode(EXTENDED_PUBLIC_KEY_VERSION, bytes)?;
+        let public_key = secp256k1::PublicKey::from_slice(&key_data)
+            .map_err(|_| DerivationError::InvalidExtendedKey("invalid public key".to_owned()))?;
+        Ok(Self { info, public_key })
+    }
+}
+
+/// Base58check encoding, starting with "xpub"
+impl fmt::Display for ExtendedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            bs58::encode(self.encode()).with_check().into_string()
+        )
+    }
+}
+
+impl FromStr for ExtendedPublicKey {
+    type Err = DerivationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(s)
+            .with_check(None)
+            .into_vec()
+            .map_err(|e| DerivationError::InvalidExtendedKey(e.to_string()))?;
+        Self::decode(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex::FromHex;
+
+    #[test]
+    fn paths() {
+        let path: DerivationPath = "m/44'/0h/1H/2/3".parse().unwrap();
+        assert_eq!(
+            path.as_slice(),
+            [
+                ChildNumber::hardened(44).unwrap(),
+                ChildNumber::hardened(0).unwrap(),
+                ChildNumber::hardened(1).unwrap(),
+                ChildNumber::normal(2).unwrap(),
+                ChildNumber::normal(3).unwrap(),
+            ]
+        );
+        assert_eq!(path.to_string(), "m/44'/0'/1'/2/3");
+        assert_eq!(
+            "m".parse::<DerivationPath>().unwrap(),
+            DerivationPath::master()
+        );
+
+        for invalid in ["", "44'/0'", "m/", "m/x", "m/2147483648", "m/1''"] {
+            assert!(invalid.parse::<DerivationPath>().is_err(), "{}", invalid);
+        }
+        assert_eq!(
+            ChildNumber::hardened(1 << 31),
+            Err(DerivationError::InvalidChildNumber(1 << 31))
+        );
+    }
+
+    // Test vector 1 from the BIP32 specification
+    #[test]
+    fn bip32_vector() {
+        let seed = Vec::from_hex("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed).unwrap();
+
+        let vectors = [
+            (
+                "m",
+                "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+                "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+            ),
+            (
+                "m/0'",
+                "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7",
+                "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+            ),
+            (
+                "m/0'/1",
+                "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs",
+                "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+            ),
+        ];
+
+        for (path, xprv, xpub) in vectors {
+            let key = master.derive_path(&path.parse().unwrap()).unwrap();
+            assert_eq!(key.to_string(), xprv);
+            assert_eq!(key.to_extended_public_key().to_string(), xpub);
+            assert_eq!(xprv.parse::<ExtendedPrivateKey>().unwrap(), key);
+            assert_eq!(
+                xpub.parse::<ExtendedPublicKey>().unwrap(),
+                key.to_extended_public_key()
+            );
+        }
+    }
+
+    #[test]
+    fn public_derivation() {
+        let master = ExtendedPrivateKey::new_master(&[7; 32]).unwrap();
+        let account = master.derive_path(&"m/44'/0'/0'".parse().unwrap()).unwrap();
+        let account_public = account.to_extended_public_key();
+
+        let path: DerivationPath = "m/0/5".parse().unwrap();
+        let child = account.derive_path(&path).unwrap();
+        let child_public = account_public.derive_path(&path).unwrap();
+        assert_eq!(child.to_extended_public_key(), child_public);
+        assert_eq!(child.public_key(), child_public.public_key());
+        assert_eq!(
+            child.public_key(),
+            PublicKey::from_private_key(&child.private_key())
+        );
+        assert_eq!(child.depth(), 5);
+
+        let hardened = ChildNumber::hardened(0).unwrap();
+        assert_eq!(
+            account_public.derive_child(hardened),
+            Err(DerivationError::HardenedDerivationFromPublicKey(hardened))
+        );
+
+        let sig = child.private_key().sign_message(b"abc").unwrap();
+        assert!(child_public.public_key().verify_message(&sig, b"abc"));
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        assert_eq!(
+            ExtendedPrivateKey::new_master(&[0; 15]),
+            Err(DerivationError::InvalidSeedLength(15))
+        );
+        assert_eq!(
+            ExtendedPrivateKey::new_master(&[0; 65]),
+            Err(DerivationError::InvalidSeedLength(65))
+        );
+
+        let key = ExtendedPrivateKey::new_master(&[1; 16]).unwrap();
+        let xpub = key.to_extended_public_key().to_string();
+        assert!(xpub.parse::<ExtendedPrivateKey>().is_err());
+        assert!(key.to_string().parse::<ExtendedPublicKey>().is_err());
+
+        let mut corrupted = xpub.into_bytes();
+        corrupted[20] = if corrupted[20] == b'a' { b'b' } else { b'a' };
+        assert!(String::from_utf8(corrupted).unwrap().parse::<ExtendedPublicKey>().is_err());
+    }
+}
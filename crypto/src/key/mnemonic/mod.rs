@@ -0,0 +1,353 @@
+//! Mnemonic sentences encoding wallet seeds as specified by BIP39
+//!
+//! A mnemonic encodes 128 to 256 bits of entropy together with a checksum as 12 to 24 words from
+//! the English wordlist. The seed derived from a mnemonic and an optional passphrase is suitable
+//! as the seed of [`ExtendedPrivateKey::new_master`](super::hd::ExtendedPrivateKey::new_master).
+//!
+//! Intermediate buffers holding the entropy or the seed are zeroized when dropped.
+
+use std::{fmt, str::FromStr};
+
+use hmac::Hmac;
+use lazy_static::lazy_static;
+use rand::{CryptoRng, Rng};
+use unicode_normalization::UnicodeNormalization;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::hash::{hash, Sha256};
+
+/// Length of the seed derived from a mnemonic
+pub const SEED_LENGTH: usize = 64;
+
+const WORDLIST_LENGTH: usize = 2048;
+const BITS_PER_WORD: usize = 11;
+const PBKDF2_ROUNDS: u32 = 2048;
+const SALT_PREFIX: &str = "mnemonic";
+
+lazy_static! {
+    /// The English BIP39 wordlist, sorted
+    static ref WORDLIST: Vec<&'static str> = {
+        let words: Vec<_> = include_str!("english.txt").lines().collect();
+        assert_eq!(words.len(), WORDLIST_LENGTH);
+        words
+    };
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum MnemonicError {
+    InvalidEntropyLength(usize),
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    InvalidChecksum,
+}
+
+/// Number of words of a mnemonic, each one adds 32 bits of entropy to the shortest mnemonic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WordCount {
+    Words12,
+    Words15,
+    Words18,
+    Words21,
+    Words24,
+}
+
+impl WordCount {
+    pub fn words(&self) -> usize {
+        match self {
+            WordCount::Words12 => 12,
+            WordCount::Words15 => 15,
+            WordCount::Words18 => 18,
+            WordCount::Words21 => 21,
+            WordCount::Words24 => 24,
+        }
+    }
+
+    pub fn entropy_length(&self) -> usize {
+        self.words() * 4 / 3
+    }
+
+    fn from_words(words: usize) -> Result<Self, MnemonicError> {
+        match words {
+            12 => Ok(WordCount::Words12),
+            15 => Ok(WordCount::Words15),
+            18 => Ok(WordCount::Words18),
+            21 => Ok(WordCount::Words21),
+            24 => Ok(WordCount::Words24),
+            _ => Err(MnemonicError::InvalidWordCount(words)),
+        }
+    }
+
+    fn checksum_bits(&self) -> usize {
+        self.words() / 3
+    }
+}
+
+/// A mnemonic sentence with a valid checksum
+///
+/// The words are kept normalized: lowercase and separated by single spaces.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    phrase: String,
+}
+
+impl Mnemonic {
+    pub fn generate<R: Rng + CryptoRng>(rng: &mut R, word_count: WordCount) -> Self {
+        let mut entropy = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(&mut entropy[..]);
+        Self::from_entropy(&entropy[..word_count.entropy_length()])
+            .expect("entropy length is valid for the word count")
+    }
+
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, MnemonicError> {
+        let word_count = WordCount::from_words(entropy.len() * 3 / 4)
+            .ok()
+            .filter(|word_count| word_count.entropy_length() == entropy.len())
+            .ok_or(MnemonicError::InvalidEntropyLength(entropy.len()))?;
+
+        // At most 8 checksum bits, taken from the first byte of the hash
+        let mut data = Zeroizing::new(entropy.to_vec());
+        data.push(hash::<Sha256, _>(entropy)[0]);
+
+        let phrase = (0..word_count.words())
+            .map(|word| {
+                let index = (0..BITS_PER_WORD)
+                    .map(|bit| get_bit(&data, word * BITS_PER_WORD + bit))
+                    .fold(0, |index, bit| index << 1 | usize::from(bit));
+                WORDLIST[index]
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(Self { phrase })
+    }
+
+    /// Parse a mnemonic sentence, words may be separated by any whitespace and in any case
+    pub fn parse(phrase: &str) -> Result<Self, MnemonicError> {
+        let phrase = Zeroizing::new(phrase.nfkd().collect::<String>().to_lowercase());
+        let words = phrase.split_whitespace().collect::<Vec<_>>();
+        let word_count = WordCount::from_words(words.len())?;
+
+        let bits = words_to_bits(&words)?;
+
+        let entropy_length = word_count.entropy_length();
+        let checksum_shift = 8 - word_count.checksum_bits();
+        let checksum = hash::<Sha256, _>(&bits[..entropy_length])[0] >> checksum_shift;
+        if bits[entropy_length] >> checksum_shift != checksum {
+            return Err(MnemonicError::InvalidChecksum);
+        }
+
+        Ok(Self {
+            phrase: words.join(" "),
+        })
+    }
+
+    pub fn phrase(&self) -> &str {
+        &self.phrase
+    }
+
+    pub fn word_count(&self) -> WordCount {
+        WordCount::from_words(self.words().count()).expect("mnemonic is valid")
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.phrase.split(' ')
+    }
+
+    pub fn entropy(&self) -> Zeroizing<Vec<u8>> {
+        let words = self.words().collect::<Vec<_>>();
+        let mut bits = words_to_bits(&words).expect("mnemonic is valid");
+        bits.truncate(self.word_count().entropy_length());
+        bits
+    }
+
+    /// Derive the seed, the passphrase may be empty
+    pub fn to_seed(&self, passphrase: &str) -> Zeroizing<[u8; SEED_LENGTH]> {
+        let salt =
+            Zeroizing::new(format!("{}{}", SALT_PREFIX, passphrase).nfkd().collect::<String>());
+        let mut seed = Zeroizing::new([0u8; SEED_LENGTH]);
+        pbkdf2::pbkdf2::<Hmac<sha2::Sha512>>(
+            self.phrase.as_bytes(),
+            salt.as_bytes(),
+            PBKDF2_ROUNDS,
+            &mut seed[..],
+        );
+        seed
+    }
+}
+
+impl Drop for Mnemonic {
+    fn drop(&mut self) {
+        self.phrase.zeroize();
+    }
+}
+
+/// The phrase is not printed to keep it out of logs
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mnemonic").field("word_count", &self.word_count()).finish()
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = MnemonicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Concatenate the 11 bit indexes of the words, the last byte is padded with zeros
+fn words_to_bits(words: &[&str]) -> Result<Zeroizing<Vec<u8>>, MnemonicError> {
+    let mut bits = Zeroizing::new(vec![0u8; (words.len() * BITS_PER_WORD + 7) / 8]);
+    for (word_num, word) in words.iter().enumerate() {
+        let index = WORDLIST
+            .binary_search(word)
+            .map_err(|_| MnemonicError::UnknownWord((*word).to_owned()))?;
+        for bit in 0..BITS_PER_WORD {
+            if index & (1 << (BITS_PER_WORD - 1 - bit)) != 0 {
+                set_bit(&mut bits, word_num * BITS_PER_WORD + bit);
+            }
+        }
+    }
+    Ok(bits)
+}
+
+fn get_bit(data: &[u8], bit: usize) -> bool {
+    data[bit / 8] & (0x80 >> (bit % 8)) != 0
+}
+
+fn set_bit(data: &mut [u8], bit: usize) {
+    data[bit / 8] |= 0x80 >> (bit % 8);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::hd::ExtendedPrivateKey;
+    use crate::random::make_true_rng;
+    use hex::FromHex;
+
+    // From the reference test vectors, all seeds use the passphrase "TREZOR"
+    const VECTORS: [(&str, &str, &str); 6] = [
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+        (
+            "80808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage above",
+            "d71de856f81a8acc65e6fc851a38d4d7ec216fd0796d0a6827a3ad6ed5511a30fa280f12eb2e47ed2ac03b5c462a0358d18d69fe4f985ec81778c1b370b652a8",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+            "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f0f8e5a589620c6f15b11c61dee327651a14c34e18231052e48c069",
+        ),
+        (
+            "808080808080808080808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic avoid letter always",
+            "107d7c02a5aa6f38c58083ff74f04c607c2d2c0ecc55501dadd72d025b751bc27fe913ffb796f841c49b1d33b610cf0e91d3aa239027f5e99fe4ce9e5088cd65",
+        ),
+        (
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+            "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+        ),
+    ];
+
+    #[test]
+    fn wordlist() {
+        assert_eq!(WORDLIST.len(), WORDLIST_LENGTH);
+        assert!(WORDLIST.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_vectors() {
+        for (entropy, phrase, seed) in VECTORS {
+            let entropy = Vec::from_hex(entropy).unwrap();
+            let mnemonic = Mnemonic::from_entropy(&entropy).unwrap();
+            assert_eq!(mnemonic.phrase(), phrase);
+            assert_eq!(*mnemonic.entropy(), entropy);
+            assert_eq!(Mnemonic::parse(phrase).unwrap(), mnemonic);
+            assert_eq!(
+                mnemonic.to_seed("TREZOR").to_vec(),
+                Vec::from_hex(seed).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn hd_master_key() {
+        let mnemonic = Mnemonic::parse(VECTORS[0].1).unwrap();
+        let master = ExtendedPrivateKey::new_master(&mnemonic.to_seed("")[..]).unwrap();
+        assert_eq!(
+            master.to_string(),
+            "xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu"
+        );
+    }
+
+    #[test]
+    fn generate() {
+        let mut rng = make_true_rng();
+        for word_count in [
+            WordCount::Words12,
+            WordCount::Words15,
+            WordCount::Words18,
+            WordCount::Words21,
+            WordCount::Words24,
+        ] {
+            let mnemonic = Mnemonic::generate(&mut rng, word_count);
+            assert_eq!(mnemonic.word_count(), word_count);
+            assert_eq!(mnemonic.entropy().len(), word_count.entropy_length());
+            assert_eq!(Mnemonic::parse(mnemonic.phrase()).unwrap(), mnemonic);
+        }
+    }
+
+    #[test]
+    fn normalized_parsing() {
+        let mnemonic = Mnemonic::parse(VECTORS[1].1).unwrap();
+        let messy = format!("  {}\n", VECTORS[1].1.to_uppercase().replace(' ', " \t "));
+        assert_eq!(Mnemonic::parse(&messy).unwrap(), mnemonic);
+        assert_eq!(messy.parse::<Mnemonic>().unwrap().phrase(), VECTORS[1].1);
+    }
+
+    #[test]
+    fn invalid_mnemonics() {
+        assert_eq!(
+            Mnemonic::from_entropy(&[0; 15]),
+            Err(MnemonicError::InvalidEntropyLength(15))
+        );
+        assert_eq!(
+            Mnemonic::from_entropy(&[0; 33]),
+            Err(MnemonicError::InvalidEntropyLength(33))
+        );
+        assert_eq!(
+            Mnemonic::parse("abandon abandon abandon"),
+            Err(MnemonicError::InvalidWordCount(3))
+        );
+        assert_eq!(
+            Mnemonic::parse(
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon mintlayer"
+            ),
+            Err(MnemonicError::UnknownWord("mintlayer".to_owned()))
+        );
+        assert_eq!(
+            Mnemonic::parse(
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+            ),
+            Err(MnemonicError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn debug_hides_phrase() {
+        let mnemonic = Mnemonic::parse(VECTORS[0].1).unwrap();
+        assert!(!format!("{:?}", mnemonic).contains("abandon"));
+    }
+}
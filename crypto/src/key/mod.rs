@@ -1,4 +1,8 @@
+pub mod hd;
+pub mod mnemonic;
+pub mod parallel;
 pub mod rschnorr;
+pub mod secp256k1schnorr;
 pub mod signature;
 
 use serialization::{Decode, Encode};
@@ -15,9 +19,13 @@ pub enum SignatureError {
     SignatureConstructionError,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Decode, Encode)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Decode, Encode)]
 pub enum KeyKind {
+    #[codec(index = 0)]
     RistrettoSchnorr,
+    /// BIP340 Schnorr signatures over secp256k1
+    #[codec(index = 1)]
+    Secp256k1Schnorr,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Decode, Encode)]
@@ -32,12 +40,18 @@ pub struct PublicKey {
 
 #[derive(Debug, PartialEq, Eq, Clone, Decode, Encode)]
 pub(crate) enum PrivateKeyHolder {
+    #[codec(index = 0)]
     RistrettoSchnorr(rschnorr::MLRistrettoPrivateKey),
+    #[codec(index = 1)]
+    Secp256k1Schnorr(secp256k1schnorr::MLSecp256k1PrivateKey),
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Decode, Encode)]
 pub(crate) enum PublicKeyHolder {
+    #[codec(index = 0)]
     RistrettoSchnorr(rschnorr::MLRistrettoPublicKey),
+    #[codec(index = 1)]
+    Secp256k1Schnorr(secp256k1schnorr::MLSecp256k1PublicKey),
 }
 
 impl From<RistrittoSignatureError> for SignatureError {
@@ -65,12 +79,24 @@ impl PrivateKey {
                     },
                 )
             }
+            KeyKind::Secp256k1Schnorr => {
+                let k = secp256k1schnorr::MLSecp256k1PrivateKey::new(&mut rng);
+                (
+                    PrivateKey {
+                        key: PrivateKeyHolder::Secp256k1Schnorr(k.0),
+                    },
+                    crate::key::PublicKey {
+                        pub_key: PublicKeyHolder::Secp256k1Schnorr(k.1),
+                    },
+                )
+            }
         }
     }
 
     pub fn kind(&self) -> KeyKind {
         match self.key {
             PrivateKeyHolder::RistrettoSchnorr(_) => KeyKind::RistrettoSchnorr,
+            PrivateKeyHolder::Secp256k1Schnorr(_) => KeyKind::Secp256k1Schnorr,
         }
     }
 
@@ -80,9 +106,31 @@ impl PrivateKey {
 
     pub fn sign_message(&self, msg: &[u8]) -> Result<Signature, SignatureError> {
         let mut rng = make_true_rng();
-        let PrivateKeyHolder::RistrettoSchnorr(k) = &self.key;
-        let sig = k.sign_message(&mut rng, msg)?;
-        Ok(Signature::RistrettoSchnorr(sig))
+        match &self.key {
+            PrivateKeyHolder::RistrettoSchnorr(k) => {
+                let sig = k.sign_message(&mut rng, msg)?;
+                Ok(Signature::RistrettoSchnorr(sig))
+            }
+            PrivateKeyHolder::Secp256k1Schnorr(k) => {
+                Ok(Signature::Secp256k1Schnorr(k.sign_message(&mut rng, msg)))
+            }
+        }
+    }
+
+    /// Compute a Diffie-Hellman shared secret with the public key of the other party
+    ///
+    /// Returns `None` if the keys are of different kinds.
+    pub fn shared_secret(&self, other: &PublicKey) -> Option<[u8; 32]> {
+        match (&self.key, &other.pub_key) {
+            (PrivateKeyHolder::RistrettoSchnorr(k), PublicKeyHolder::RistrettoSchnorr(pk)) => {
+                Some(k.shared_secret(pk))
+            }
+            (PrivateKeyHolder::Secp256k1Schnorr(k), PublicKeyHolder::Secp256k1Schnorr(pk)) => {
+                Some(k.shared_secret(pk))
+            }
+            (PrivateKeyHolder::RistrettoSchnorr(_), PublicKeyHolder::Secp256k1Schnorr(_))
+            | (PrivateKeyHolder::Secp256k1Schnorr(_), PublicKeyHolder::RistrettoSchnorr(_)) => None,
+        }
     }
 }
 
@@ -94,21 +142,39 @@ impl PublicKey {
                     rschnorr::MLRistrettoPublicKey::from_private_key(k),
                 ),
             },
+            PrivateKeyHolder::Secp256k1Schnorr(ref k) => crate::key::PublicKey {
+                pub_key: PublicKeyHolder::Secp256k1Schnorr(
+                    secp256k1schnorr::MLSecp256k1PublicKey::from_private_key(k),
+                ),
+            },
         }
     }
 
-    pub fn verify_message(&self, signature: &Signature, msg: &[u8]) -> bool {
-        use crate::key::Signature::RistrettoSchnorr;
+    pub fn kind(&self) -> KeyKind {
+        match self.pub_key {
+            PublicKeyHolder::RistrettoSchnorr(_) => KeyKind::RistrettoSchnorr,
+            PublicKeyHolder::Secp256k1Schnorr(_) => KeyKind::Secp256k1Schnorr,
+        }
+    }
 
-        let PublicKeyHolder::RistrettoSchnorr(k) = &self.pub_key;
-        match signature {
-            RistrettoSchnorr(s) => k.verify_message(s, msg),
+    /// Signatures only verify with public keys of the same kind
+    pub fn verify_message(&self, signature: &Signature, msg: &[u8]) -> bool {
+        match (&self.pub_key, signature) {
+            (PublicKeyHolder::RistrettoSchnorr(k), Signature::RistrettoSchnorr(s)) => {
+                k.verify_message(s, msg)
+            }
+            (PublicKeyHolder::Secp256k1Schnorr(k), Signature::Secp256k1Schnorr(s)) => {
+                k.verify_message(s, msg)
+            }
+            (PublicKeyHolder::RistrettoSchnorr(_), Signature::Secp256k1Schnorr(_))
+            | (PublicKeyHolder::Secp256k1Schnorr(_), Signature::RistrettoSchnorr(_)) => false,
         }
     }
 
     pub fn is_aggregable(&self) -> bool {
         match self.pub_key {
             PublicKeyHolder::RistrettoSchnorr(_) => true,
+            PublicKeyHolder::Secp256k1Schnorr(_) => true,
         }
     }
 }
@@ -119,11 +185,30 @@ mod test {
 
     #[test]
     fn sign_and_verify() {
-        let (sk, pk) = PrivateKey::new(KeyKind::RistrettoSchnorr);
-        assert_eq!(sk.kind(), KeyKind::RistrettoSchnorr);
-        let msg_size = 1 + rand::random::<usize>() % 10000;
-        let msg: Vec<u8> = (0..msg_size).map(|_| rand::random::<u8>()).collect();
-        let sig = sk.sign_message(&msg).unwrap();
-        assert!(pk.verify_message(&sig, &msg));
+        for kind in [KeyKind::RistrettoSchnorr, KeyKind::Secp256k1Schnorr] {
+            let (sk, pk) = PrivateKey::new(kind);
+            assert_eq!(sk.kind(), kind);
+            assert_eq!(pk.kind(), kind);
+            let msg_size = 1 + rand::random::<usize>() % 10000;
+            let msg: Vec<u8> = (0..msg_size).map(|_| rand::random::<u8>()).collect();
+            let sig = sk.sign_message(&msg).unwrap();
+            assert!(pk.verify_message(&sig, &msg));
+        }
+    }
+
+    #[test]
+    fn mixed_kinds() {
+        let (r_sk, r_pk) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let (s_sk, s_pk) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+        let msg = b"abc";
+
+        assert!(!s_pk.verify_message(&r_sk.sign_message(msg).unwrap(), msg));
+        assert!(!r_pk.verify_message(&s_sk.sign_message(msg).unwrap(), msg));
+        assert_eq!(r_sk.shared_secret(&s_pk), None);
+        assert_eq!(s_sk.shared_secret(&r_pk), None);
+
+        let (s_sk2, s_pk2) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+        assert!(s_sk.shared_secret(&s_pk2).is_some());
+        assert_eq!(s_sk.shared_secret(&s_pk2), s_sk2.shared_secret(&s_pk));
     }
 }
@@ -0,0 +1,161 @@
+//! Parallel verification of many signatures
+//!
+//! Signatures are collected first and checked when the verifier runs. Each signature is still
+//! verified on its own, there is no batch verification in the cryptographic sense: the key kinds
+//! can be mixed and the secp256k1 library has no Schnorr batch API. The speedup comes from
+//! spreading the signatures over the available cores. This is how blocks with many inputs are
+//! validated.
+//!
+//! The threads are spawned for each verification and joined before it returns, so validating a
+//! block briefly uses every core. Small sets of signatures are checked on the calling thread,
+//! where spawning would cost more than it saves.
+
+use std::{num::NonZeroUsize, thread};
+
+use super::{PublicKey, Signature};
+
+/// Sets of signatures smaller than this are verified on the calling thread
+const MIN_SIGNATURES_PER_THREAD: usize = 16;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum ParallelVerificationError {
+    /// Index of the first collected signature that is invalid
+    InvalidSignature(usize),
+}
+
+/// Signatures with the public keys and messages they have to be verified with
+#[derive(Debug, Default, Clone)]
+pub struct ParallelVerifier {
+    entries: Vec<(PublicKey, Signature, Vec<u8>)>,
+}
+
+impl ParallelVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, public_key: PublicKey, signature: Signature, msg: Vec<u8>) {
+        self.entries.push((public_key, signature, msg));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    /// Verify each signature on its own, spread over threads. An empty verifier is valid
+    pub fn verify(&self) -> Result<(), ParallelVerificationError> {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let chunk_size = std::cmp::max(
+            MIN_SIGNATURES_PER_THREAD,
+            (self.entries.len() + threads - 1) / threads,
+        );
+
+        let first_invalid = if self.entries.len() <= chunk_size {
+            first_invalid(&self.entries, 0)
+        } else {
+            thread::scope(|scope| {
+                let handles = self
+                    .entries
+                    .chunks(chunk_size)
+                    .enumerate()
+                    .map(|(chunk_num, chunk)| {
+                        scope.spawn(move || first_invalid(chunk, chunk_num * chunk_size))
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .filter_map(|handle| handle.join().expect("verification thread panicked"))
+                    .min()
+            })
+        };
+
+        match first_invalid {
+            None => Ok(()),
+            Some(index) => Err(ParallelVerificationError::InvalidSignature(index)),
+        }
+    }
+}
+
+fn first_invalid(entries: &[(PublicKey, Signature, Vec<u8>)], offset: usize) -> Option<usize> {
+    entries
+        .iter()
+        .position(|(public_key, signature, msg)| !public_key.verify_message(signature, msg))
+        .map(|index| index + offset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::{KeyKind, PrivateKey};
+
+    fn signed_verifier(size: usize) -> ParallelVerifier {
+        let mut verifier = ParallelVerifier::new();
+        for i in 0..size {
+            let key_kind = if i % 2 == 0 {
+                KeyKind::RistrettoSchnorr
+            } else {
+                KeyKind::Secp256k1Schnorr
+            };
+            let (sk, pk) = PrivateKey::new(key_kind);
+            let msg = format!("message {}", i).into_bytes();
+            verifier.push(pk, sk.sign_message(&msg).unwrap(), msg);
+        }
+        verifier
+    }
+
+    #[test]
+    fn valid_signatures() {
+        assert_eq!(ParallelVerifier::new().verify(), Ok(()));
+        for size in [1, MIN_SIGNATURES_PER_THREAD, 5 * MIN_SIGNATURES_PER_THREAD + 3] {
+            let verifier = signed_verifier(size);
+            assert_eq!(verifier.len(), size);
+            assert_eq!(verifier.verify(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn invalid_signature_is_reported() {
+        let size = 5 * MIN_SIGNATURES_PER_THREAD + 3;
+        for invalid in [0, 7, MIN_SIGNATURES_PER_THREAD + 1, size - 1] {
+            let mut verifier = signed_verifier(size);
+            verifier.entries[invalid].2.push(0);
+            assert_eq!(
+                verifier.verify(),
+                Err(ParallelVerificationError::InvalidSignature(invalid))
+            );
+        }
+
+        // The first invalid signature is reported even if its thread finishes last
+        let mut verifier = signed_verifier(size);
+        verifier.entries[3].2.push(0);
+        verifier.entries[size - 1].2.push(0);
+        assert_eq!(
+            verifier.verify(),
+            Err(ParallelVerificationError::InvalidSignature(3))
+        );
+    }
+
+    #[test]
+    fn mismatched_kinds() {
+        let (sk, _) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let (_, pk) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+        let mut verifier = signed_verifier(3);
+        verifier.push(pk, sk.sign_message(b"msg").unwrap(), b"msg".to_vec());
+        assert_eq!(
+            verifier.verify(),
+            Err(ParallelVerificationError::InvalidSignature(3))
+        );
+
+        verifier.clear();
+        assert!(verifier.is_empty());
+        assert_eq!(verifier.verify(), Ok(()));
+    }
+}
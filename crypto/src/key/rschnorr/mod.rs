@@ -6,7 +6,10 @@ pub use internal::RistrettoSchnorrSignature;
 use internal::*;
 use rand::{CryptoRng, Rng};
 use serialization::{Decode, Encode};
-use tari_crypto::{keys::PublicKey, tari_utilities::ByteArray};
+use tari_crypto::{
+    keys::{DiffieHellmanSharedSecret, PublicKey},
+    tari_utilities::ByteArray,
+};
 
 use crate::hash::{Blake2b32Stream, StreamHasher};
 
@@ -102,6 +105,14 @@ impl MLRistrettoPrivateKey {
         debug_assert_eq!(*sig.get_public_nonce(), r_pub);
         Ok(sig)
     }
+
+    /// Compute a Diffie-Hellman shared secret with the public key of the other party
+    ///
+    /// The shared point is hashed so that the result can be used as key material.
+    pub fn shared_secret(&self, other: &MLRistrettoPublicKey) -> [u8; 32] {
+        let point = RistrettoPublicKey::shared_secret(&self.key_data, other.as_native());
+        Blake2b32Stream::new().write(point.as_bytes()).finalize().into()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -279,6 +290,17 @@ mod test {
         assert!(pk.verify_message(&sig, &msg));
     }
 
+    #[test]
+    fn shared_secret() {
+        let mut rng = make_true_rng();
+        let (sk1, pk1) = MLRistrettoPrivateKey::new(&mut rng);
+        let (sk2, pk2) = MLRistrettoPrivateKey::new(&mut rng);
+        let (sk3, _pk3) = MLRistrettoPrivateKey::new(&mut rng);
+
+        assert_eq!(sk1.shared_secret(&pk2), sk2.shared_secret(&pk1));
+        assert_ne!(sk1.shared_secret(&pk2), sk3.shared_secret(&pk1));
+    }
+
     #[test]
     fn sign_empty() {
         let mut rng = make_true_rng();
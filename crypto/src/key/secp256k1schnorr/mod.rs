@@ -0,0 +1,346 @@
+//! BIP340 Schnorr signatures over secp256k1
+//!
+//! Public keys are x-only (32 bytes) and signatures are 64 bytes, which makes witnesses smaller
+//! than with Ristretto keys. Messages are hashed to 32 bytes before signing, the functions that
+//! sign and verify the digest directly are compatible with BIP340 implementations elsewhere.
+
+use rand::{CryptoRng, Rng};
+use secp256k1::{schnorr, KeyPair, Message, Parity, SecretKey, XOnlyPublicKey, SECP256K1};
+use serialization::{Decode, Encode};
+
+use crate::hash::{Blake2b32Stream, StreamHasher};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum Secp256k1KeyError {
+    InvalidData,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MLSecp256k1PrivateKey {
+    key_data: SecretKey,
+}
+
+impl Encode for MLSecp256k1PrivateKey {
+    fn encode_to<T: serialization::Output + ?Sized>(&self, dest: &mut T) {
+        self.to_bytes().as_slice().encode_to(dest)
+    }
+
+    fn size_hint(&self) -> usize {
+        self.to_bytes().as_slice().size_hint()
+    }
+}
+
+impl Decode for MLSecp256k1PrivateKey {
+    fn decode<I: serialization::Input>(input: &mut I) -> Result<Self, serialization::Error> {
+        let v = Vec::decode(input)?;
+        Self::from_bytes(&v)
+            .map_err(|_| serialization::Error::from("Private Key deserialization failed"))
+    }
+}
+
+impl MLSecp256k1PrivateKey {
+    pub fn new<R: Rng + CryptoRng>(rng: &mut R) -> (MLSecp256k1PrivateKey, MLSecp256k1PublicKey) {
+        // Almost every 32 byte string is a valid key, the loop is for the remaining ones
+        let key_data = loop {
+            if let Ok(key) = SecretKey::from_slice(&rng.gen::<[u8; 32]>()) {
+                break key;
+            }
+        };
+        let sk = Self { key_data };
+        let pk = MLSecp256k1PublicKey::from_private_key(&sk);
+        (sk, pk)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.key_data.secret_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Secp256k1KeyError> {
+        let key_data = SecretKey::from_slice(bytes).map_err(|_| Secp256k1KeyError::InvalidData)?;
+        Ok(Self { key_data })
+    }
+
+    pub fn as_native(&self) -> &SecretKey {
+        &self.key_data
+    }
+
+    pub fn from_native(native: SecretKey) -> Self {
+        Self { key_data: native }
+    }
+
+    fn key_pair(&self) -> KeyPair {
+        KeyPair::from_secret_key(SECP256K1, &self.key_data)
+    }
+
+    /// Sign a 32 byte digest as specified by BIP340
+    pub fn sign_digest(&self, digest: &[u8; 32], aux_rand: &[u8; 32]) -> Secp256k1SchnorrSignature {
+        let msg = Message::from_slice(digest).expect("digest is 32 bytes");
+        let sig = SECP256K1.sign_schnorr_with_aux_rand(&msg, &self.key_pair(), aux_rand);
+        Secp256k1SchnorrSignature::from_native(sig)
+    }
+
+    pub(crate) fn sign_message<R: Rng + CryptoRng>(
+        &self,
+        rng: &mut R,
+        msg: &[u8],
+    ) -> Secp256k1SchnorrSignature {
+        let digest = Blake2b32Stream::new().write(msg).finalize().into();
+        self.sign_digest(&digest, &rng.gen())
+    }
+
+    /// Compute a Diffie-Hellman shared secret with the public key of the other party
+    ///
+    /// Public keys are x-only, so only the x coordinate of the shared point is used. It doesn't
+    /// depend on the parity of either key. The coordinate is hashed so that the result can be
+    /// used as key material.
+    pub fn shared_secret(&self, other: &MLSecp256k1PublicKey) -> [u8; 32] {
+        let point = secp256k1::PublicKey::from_x_only_public_key(other.pubkey_data, Parity::Even);
+        let shared_point = secp256k1::ecdh::shared_secret_point(&point, &self.key_data);
+        Blake2b32Stream::new().write(&shared_point[..32]).finalize().into()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MLSecp256k1PublicKey {
+    pubkey_data: XOnlyPublicKey,
+}
+
+impl PartialOrd for MLSecp256k1PublicKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MLSecp256k1PublicKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.to_bytes().cmp(&other.to_bytes())
+    }
+}
+
+impl Encode for MLSecp256k1PublicKey {
+    fn encode_to<T: serialization::Output + ?Sized>(&self, dest: &mut T) {
+        self.to_bytes().as_slice().encode_to(dest)
+    }
+
+    fn size_hint(&self) -> usize {
+        self.to_bytes().as_slice().size_hint()
+    }
+}
+
+impl Decode for MLSecp256k1PublicKey {
+    fn decode<I: serialization::Input>(input: &mut I) -> Result<Self, serialization::Error> {
+        let v = Vec::decode(input)?;
+        Self::from_bytes(&v)
+            .map_err(|_| serialization::Error::from("Public Key deserialization failed"))
+    }
+}
+
+impl MLSecp256k1PublicKey {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.pubkey_data.serialize()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Secp256k1KeyError> {
+        let pubkey_data =
+            XOnlyPublicKey::from_slice(bytes).map_err(|_| Secp256k1KeyError::InvalidData)?;
+        Ok(Self { pubkey_data })
+    }
+
+    pub fn as_native(&self) -> &XOnlyPublicKey {
+        &self.pubkey_data
+    }
+
+    pub fn from_native(native: XOnlyPublicKey) -> Self {
+        Self {
+            pubkey_data: native,
+        }
+    }
+
+    pub fn from_private_key(private_key: &MLSecp256k1PrivateKey) -> Self {
+        let (pubkey_data, _parity) = XOnlyPublicKey::from_keypair(&private_key.key_pair());
+        Self { pubkey_data }
+    }
+
+    /// Verify a signature of a 32 byte digest as specified by BIP340
+    pub fn verify_digest(&self, signature: &Secp256k1SchnorrSignature, digest: &[u8; 32]) -> bool {
+        let msg = Message::from_slice(digest).expect("digest is 32 bytes");
+        SECP256K1
+            .verify_schnorr(&signature.as_native(), &msg, &self.pubkey_data)
+            .is_ok()
+    }
+
+    pub(crate) fn verify_message(&self, signature: &Secp256k1SchnorrSignature, msg: &[u8]) -> bool {
+        let digest = Blake2b32Stream::new().write(msg).finalize().into();
+        self.verify_digest(signature, &digest)
+    }
+}
+
+/// The 64 bytes of a BIP340 signature, the nonce point x coordinate followed by the scalar
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Secp256k1SchnorrSignature {
+    data: [u8; 64],
+}
+
+impl Secp256k1SchnorrSignature {
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.data
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Secp256k1KeyError> {
+        let data = bytes.try_into().map_err(|_| Secp256k1KeyError::InvalidData)?;
+        Ok(Self { data })
+    }
+
+    fn from_native(sig: schnorr::Signature) -> Self {
+        Self::from_bytes(&sig[..]).expect("schnorr signatures are 64 bytes")
+    }
+
+    fn as_native(&self) -> schnorr::Signature {
+        schnorr::Signature::from_slice(&self.data).expect("schnorr signatures are 64 bytes")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::random::make_true_rng;
+    use hex::FromHex;
+    use parity_scale_codec::DecodeAll;
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        Vec::from_hex(s).unwrap()
+    }
+
+    fn digest_from_hex(s: &str) -> [u8; 32] {
+        from_hex(s).try_into().unwrap()
+    }
+
+    #[test]
+    fn basic() {
+        let mut rng = make_true_rng();
+        let (sk, pk) = MLSecp256k1PrivateKey::new(&mut rng);
+        let pk2 = MLSecp256k1PublicKey::from_private_key(&sk);
+        assert_eq!(pk, pk2);
+    }
+
+    #[test]
+    fn serialize() {
+        let mut rng = make_true_rng();
+        let (sk, pk) = MLSecp256k1PrivateKey::new(&mut rng);
+        let sk_encoded = sk.encode();
+        let pk_encoded = pk.encode();
+        assert_eq!(pk_encoded.len(), 33);
+        let sk2 = MLSecp256k1PrivateKey::decode_all(&mut sk_encoded.as_slice()).unwrap();
+        let pk2 = MLSecp256k1PublicKey::decode_all(&mut pk_encoded.as_slice()).unwrap();
+        assert_eq!(sk, sk2);
+        assert_eq!(pk, pk2);
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let mut rng = make_true_rng();
+        let msg_size = rand::random::<usize>() % 10000;
+        let msg: Vec<u8> = (0..msg_size).map(|_| rand::random::<u8>()).collect();
+        let (sk, pk) = MLSecp256k1PrivateKey::new(&mut rng);
+        let sig = sk.sign_message(&mut rng, &msg);
+        assert!(pk.verify_message(&sig, &msg));
+
+        let (_sk2, pk2) = MLSecp256k1PrivateKey::new(&mut rng);
+        assert!(!pk2.verify_message(&sig, &msg));
+        assert!(!pk.verify_message(&sig, b"other message"));
+    }
+
+    #[test]
+    fn shared_secret() {
+        let mut rng = make_true_rng();
+        let (sk1, pk1) = MLSecp256k1PrivateKey::new(&mut rng);
+        let (sk2, pk2) = MLSecp256k1PrivateKey::new(&mut rng);
+        let (sk3, _pk3) = MLSecp256k1PrivateKey::new(&mut rng);
+
+        assert_eq!(sk1.shared_secret(&pk2), sk2.shared_secret(&pk1));
+        assert_ne!(sk1.shared_secret(&pk2), sk3.shared_secret(&pk1));
+    }
+
+    // Test vectors from the BIP340 specification
+    #[test]
+    fn bip340_signing_vectors() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000003",
+                "F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA821525F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0",
+            ),
+            (
+                "B7E151628AED2A6ABF7158809CF4F3C762E7160F38B4DA56A784D9045190CFEF",
+                "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE33418906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A",
+            ),
+            (
+                "C90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B14E5C9",
+                "DD308AFEC5777E13121FA72B9CC1B7CC0139715309B086C960E18FD969774EB8",
+                "C87AA53824B4D7AE2EB035A2B5BBBCCC080E76CDC6D1692C4B0B62D798E6D906",
+                "7E2D58D8B3BCDF1ABADEC7829054F90DDA9805AAB56C77333024B9D0A508B75C",
+                "5831AAEED7B44BB74E5EAB94BA9D4294C49BCF2A60728D8B4C200F50DD313C1BAB745879A5AD954A72C45A91C3A51D3C7ADEA98D82F8481E0E1E03674A6F3FB7",
+            ),
+            (
+                "0B432B2677937381AEF05BB02A66ECD012773062CF3FA2549E44F58ED2401710",
+                "25D1DFF95105F5253C4022F628A996AD3A0D95FBF21D468A1B33F8C160D8F517",
+                "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+                "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+                "7EB0509757E246F19449885651611CB965ECC1A187DD51B64FDA1EDC9637D5EC97582B9CB13DB3933705B32BA982AF5AF25FD78881EBB32771FC5922EFC66EA3",
+            ),
+        ];
+
+        for (sk, pk, aux_rand, msg, sig) in vectors {
+            let sk = MLSecp256k1PrivateKey::from_bytes(&from_hex(sk)).unwrap();
+            let pk = MLSecp256k1PublicKey::from_bytes(&from_hex(pk)).unwrap();
+            let sig = Secp256k1SchnorrSignature::from_bytes(&from_hex(sig)).unwrap();
+            let msg = digest_from_hex(msg);
+
+            assert_eq!(MLSecp256k1PublicKey::from_private_key(&sk), pk);
+            assert_eq!(sk.sign_digest(&msg, &digest_from_hex(aux_rand)), sig);
+            assert!(pk.verify_digest(&sig, &msg));
+        }
+    }
+
+    #[test]
+    fn bip340_verification_vectors() {
+        // Nonce point with a small x coordinate, valid
+        let pk = MLSecp256k1PublicKey::from_bytes(&from_hex(
+            "D69C3509BB99E412E68B0FE8544E72837DFA30746D8BE2AA65975F29D22DC7B9",
+        ))
+        .unwrap();
+        let msg =
+            digest_from_hex("4DF3C3F68FCC83B27E9D42C90431A72499F17875C81A599B566C9889B9696703");
+        let sig = Secp256k1SchnorrSignature::from_bytes(&from_hex(
+            "00000000000000000000003B78CE563F89A0ED9414F5AA28AD0D96D6795F9C6376AFB1548AF603B3EB45C9F8207DEE1060CB71C04E80F593060B07D28308D7F4",
+        ))
+        .unwrap();
+        assert!(pk.verify_digest(&sig, &msg));
+
+        // Public key not on the curve
+        assert_eq!(
+            MLSecp256k1PublicKey::from_bytes(&from_hex(
+                "EEFDEA4CDB677750A420FEE807EACF21EB9898AE79B9768766E4FAA04A2D4A34"
+            )),
+            Err(Secp256k1KeyError::InvalidData)
+        );
+
+        // Nonce point with an odd y coordinate, invalid
+        let pk = MLSecp256k1PublicKey::from_bytes(&from_hex(
+            "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+        ))
+        .unwrap();
+        let msg =
+            digest_from_hex("243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89");
+        let sig = Secp256k1SchnorrSignature::from_bytes(&from_hex(
+            "FFF97BD5755EEEA420453A14355235D382F6472F8568A18B2F057A14602975563CC27944640AC607CD107AE10923D9EF7A73C643E166BE5EBEAFA34B1AC553E2",
+        ))
+        .unwrap();
+        assert!(!pk.verify_digest(&sig, &msg));
+    }
+}
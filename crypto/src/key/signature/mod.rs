@@ -1,6 +1,7 @@
 use std::io::BufWriter;
 
 use crate::key::rschnorr::RistrettoSchnorrSignature;
+use crate::key::secp256k1schnorr::Secp256k1SchnorrSignature;
 use num_derive::FromPrimitive;
 use serialization::{Decode, DecodeAll, Encode};
 use tari_crypto::tari_utilities::message_format::MessageFormat;
@@ -8,11 +9,13 @@ use tari_crypto::tari_utilities::message_format::MessageFormat;
 #[derive(FromPrimitive)]
 pub enum SignatureKind {
     RistrettoSchnorr = 0,
+    Secp256k1Schnorr = 1,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum Signature {
     RistrettoSchnorr(RistrettoSchnorrSignature),
+    Secp256k1Schnorr(Secp256k1SchnorrSignature),
 }
 
 impl Encode for Signature {
@@ -30,6 +33,10 @@ impl Encode for Signature {
                 let sig_data = s.to_binary().expect("Signature serialization should never fail");
                 sig_data.encode_to(dest);
             }
+            Signature::Secp256k1Schnorr(s) => {
+                dest.write(&[(SignatureKind::Secp256k1Schnorr as u8)]);
+                s.as_bytes().as_slice().encode_to(dest);
+            }
         }
     }
 }
@@ -49,6 +56,11 @@ impl Decode for Signature {
                 })?;
                 Ok(Signature::RistrettoSchnorr(sig))
             }
+            SignatureKind::Secp256k1Schnorr => {
+                let sig = Secp256k1SchnorrSignature::from_bytes(&data)
+                    .map_err(|_| serialization::Error::from("Signature deserialization failed"))?;
+                Ok(Signature::Secp256k1Schnorr(sig))
+            }
         }
     }
 }
@@ -62,12 +74,14 @@ impl Signature {
     pub fn is_aggregable(&self) -> bool {
         match self {
             Self::RistrettoSchnorr(_) => true,
+            Self::Secp256k1Schnorr(_) => true,
         }
     }
 
     pub fn kind(&self) -> SignatureKind {
         match self {
             Self::RistrettoSchnorr(_) => SignatureKind::RistrettoSchnorr,
+            Self::Secp256k1Schnorr(_) => SignatureKind::Secp256k1Schnorr,
         }
     }
 }
@@ -90,6 +104,23 @@ mod test {
         assert_eq!(decoded_sig, sig);
     }
 
+    #[test]
+    fn serialize_secp256k1_schnorr() {
+        let (sk, pk) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+        let msg = b"abc";
+        let sig = sk.sign_message(msg).unwrap();
+
+        // kind byte, two-byte compact length and the 64 signature bytes
+        let encoded_sig = sig.encode();
+        assert_eq!(encoded_sig.len(), 67);
+        let decoded_sig = Signature::decode_all(&mut encoded_sig.as_slice()).unwrap();
+        assert_eq!(decoded_sig, sig);
+        assert!(pk.verify_message(&decoded_sig, msg));
+
+        let truncated = &encoded_sig[..encoded_sig.len() - 1];
+        assert!(Signature::decode_all(&mut &truncated[..]).is_err());
+    }
+
     #[test]
     fn serialize_chosen_data() {
         let msg = b"abc";
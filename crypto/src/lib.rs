@@ -1,3 +1,5 @@
 pub mod hash;
 pub mod key;
 pub mod random;
+pub mod symkey;
+pub mod vrf;
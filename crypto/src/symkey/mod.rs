@@ -0,0 +1,101 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod sealed;
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+/// Number of bytes an encrypted message is longer than the plaintext
+pub const TAG_SIZE: usize = 16;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum SymmetricKeyError {
+    EncryptionError,
+    DecryptionError,
+}
+
+/// Key for authenticated encryption with ChaCha20-Poly1305
+///
+/// The caller is responsible for never using the same nonce twice with the same key,
+/// for example by using a counter.
+pub struct SymmetricKey {
+    cipher: ChaCha20Poly1305,
+}
+
+impl SymmetricKey {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    fn make_nonce(nonce: u64) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[4..].copy_from_slice(&nonce.to_le_bytes());
+        bytes
+    }
+
+    pub fn encrypt(&self, nonce: u64, plaintext: &[u8]) -> Result<Vec<u8>, SymmetricKeyError> {
+        self.cipher
+            .encrypt(Nonce::from_slice(&Self::make_nonce(nonce)), plaintext)
+            .map_err(|_| SymmetricKeyError::EncryptionError)
+    }
+
+    /// Decrypt the ciphertext, fails if it has been tampered with or the key or nonce is wrong
+    pub fn decrypt(&self, nonce: u64, ciphertext: &[u8]) -> Result<Vec<u8>, SymmetricKeyError> {
+        self.cipher
+            .decrypt(Nonce::from_slice(&Self::make_nonce(nonce)), ciphertext)
+            .map_err(|_| SymmetricKeyError::DecryptionError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let key = SymmetricKey::new(&rand::random());
+        let msg: Vec<u8> = (0..rand::random::<usize>() % 1000).map(|_| rand::random()).collect();
+
+        let encrypted = key.encrypt(1, &msg).unwrap();
+        assert_eq!(encrypted.len(), msg.len() + TAG_SIZE);
+        assert_eq!(key.decrypt(1, &encrypted), Ok(msg));
+    }
+
+    #[test]
+    fn decrypt_failures() {
+        let key = SymmetricKey::new(&rand::random());
+        let mut encrypted = key.encrypt(1, b"message").unwrap();
+
+        assert_eq!(
+            key.decrypt(2, &encrypted),
+            Err(SymmetricKeyError::DecryptionError)
+        );
+        assert_eq!(
+            SymmetricKey::new(&rand::random()).decrypt(1, &encrypted),
+            Err(SymmetricKeyError::DecryptionError)
+        );
+
+        encrypted[0] ^= 1;
+        assert_eq!(
+            key.decrypt(1, &encrypted),
+            Err(SymmetricKeyError::DecryptionError)
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Secrets encrypted with a password, for private keys stored at rest
+//!
+//! The encryption key is derived from the password with Argon2id and a random salt, so every
+//! sealed secret is encrypted with a different key. Buffers holding the derived key and the
+//! unsealed secret are zeroized when dropped.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::{CryptoRng, Rng};
+use serialization::{Decode, DecodeAll, Encode};
+use zeroize::Zeroizing;
+
+use crate::key::PrivateKey;
+use crate::random::make_true_rng;
+
+use super::SymmetricKey;
+
+const SALT_LENGTH: usize = 16;
+/// Every sealed secret has its own key, so the nonce doesn't have to change
+const NONCE: u64 = 0;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum SealError {
+    InvalidKdfParams(String),
+    EncryptionError,
+    /// The password is wrong or the sealed data has been tampered with
+    DecryptionError,
+    InvalidSecret,
+}
+
+/// Cost of deriving the encryption key from the password with Argon2id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct KdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, SealError> {
+        let params = Self {
+            memory_kib,
+            iterations,
+            parallelism,
+        };
+        params.argon2()?;
+        Ok(params)
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>, SealError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| SealError::InvalidKdfParams(e.to_string()))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    fn derive_key(
+        &self,
+        password: &[u8],
+        salt: &[u8; SALT_LENGTH],
+    ) -> Result<SymmetricKey, SealError> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        self.argon2()?
+            .hash_password_into(password, salt, &mut key[..])
+            .map_err(|e| SealError::InvalidKdfParams(e.to_string()))?;
+        Ok(SymmetricKey::new(&key))
+    }
+}
+
+/// 19 MiB of memory and 2 passes, as recommended by OWASP for Argon2id
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// A secret encrypted with a key derived from a password
+///
+/// The KDF parameters and the salt are stored alongside the ciphertext, changing any of them
+/// changes the derived key and makes unsealing fail.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct SealedSecret {
+    kdf_params: KdfParams,
+    salt: [u8; SALT_LENGTH],
+    ciphertext: Vec<u8>,
+}
+
+impl SealedSecret {
+    pub fn seal<R: Rng + CryptoRng>(
+        rng: &mut R,
+        secret: &[u8],
+        password: &[u8],
+        kdf_params: KdfParams,
+    ) -> Result<Self, SealError> {
+        let salt = rng.gen::<[u8; SALT_LENGTH]>();
+        let ciphertext = kdf_params
+            .derive_key(password, &salt)?
+            .encrypt(NONCE, secret)
+            .map_err(|_| SealError::EncryptionError)?;
+        Ok(Self {
+            kdf_params,
+            salt,
+            ciphertext,
+        })
+    }
+
+    pub fn unseal(&self, password: &[u8]) -> Result<Zeroizing<Vec<u8>>, SealError> {
+        self.kdf_params
+            .derive_key(password, &self.salt)?
+            .decrypt(NONCE, &self.ciphertext)
+            .map(Zeroizing::new)
+            .map_err(|_| SealError::DecryptionError)
+    }
+
+    pub fn seal_private_key(
+        private_key: &PrivateKey,
+        password: &[u8],
+        kdf_params: KdfParams,
+    ) -> Result<Self, SealError> {
+        let encoded = Zeroizing::new(private_key.encode());
+        Self::seal(&mut make_true_rng(), &encoded, password, kdf_params)
+    }
+
+    pub fn unseal_private_key(&self, password: &[u8]) -> Result<PrivateKey, SealError> {
+        let encoded = self.unseal(password)?;
+        PrivateKey::decode_all(&mut &encoded[..]).map_err(|_| SealError::InvalidSecret)
+    }
+
+    pub fn kdf_params(&self) -> &KdfParams {
+        &self.kdf_params
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::{KeyKind, PublicKey};
+
+    /// Cheap parameters to keep the tests fast
+    fn test_params() -> KdfParams {
+        KdfParams::new(64, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn seal_and_unseal() {
+        let mut rng = make_true_rng();
+        let secret = b"correct horse battery staple";
+        let sealed = SealedSecret::seal(&mut rng, secret, b"password", test_params()).unwrap();
+        assert_eq!(&sealed.unseal(b"password").unwrap()[..], secret);
+
+        // A fresh salt is used every time
+        let other = SealedSecret::seal(&mut rng, secret, b"password", test_params()).unwrap();
+        assert_ne!(sealed.ciphertext, other.ciphertext);
+
+        let decoded = SealedSecret::decode_all(&mut &sealed.encode()[..]).unwrap();
+        assert_eq!(decoded, sealed);
+        assert_eq!(&decoded.unseal(b"password").unwrap()[..], secret);
+    }
+
+    #[test]
+    fn unseal_failures() {
+        let mut rng = make_true_rng();
+        let sealed = SealedSecret::seal(&mut rng, b"secret", b"password", test_params()).unwrap();
+        assert_eq!(sealed.unseal(b"passw0rd"), Err(SealError::DecryptionError));
+
+        let mut tampered = sealed.clone();
+        tampered.salt[0] ^= 1;
+        assert_eq!(
+            tampered.unseal(b"password"),
+            Err(SealError::DecryptionError)
+        );
+
+        let mut tampered = sealed.clone();
+        tampered.kdf_params.iterations += 1;
+        assert_eq!(
+            tampered.unseal(b"password"),
+            Err(SealError::DecryptionError)
+        );
+
+        let mut tampered = sealed;
+        tampered.ciphertext[0] ^= 1;
+        assert_eq!(
+            tampered.unseal(b"password"),
+            Err(SealError::DecryptionError)
+        );
+    }
+
+    #[test]
+    fn private_keys() {
+        for key_kind in [KeyKind::RistrettoSchnorr, KeyKind::Secp256k1Schnorr] {
+            let (private_key, public_key) = PrivateKey::new(key_kind);
+            let sealed =
+                SealedSecret::seal_private_key(&private_key, b"password", test_params()).unwrap();
+            let unsealed = sealed.unseal_private_key(b"password").unwrap();
+            assert_eq!(unsealed, private_key);
+            assert_eq!(PublicKey::from_private_key(&unsealed), public_key);
+        }
+    }
+
+    #[test]
+    fn invalid_params() {
+        assert!(matches!(
+            KdfParams::new(64, 0, 1),
+            Err(SealError::InvalidKdfParams(_))
+        ));
+        assert!(KdfParams::new(
+            KdfParams::default().memory_kib,
+            KdfParams::default().iterations,
+            KdfParams::default().parallelism
+        )
+        .is_ok());
+    }
+}
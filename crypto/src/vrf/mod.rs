@@ -0,0 +1,156 @@
+//! Verifiable random functions, used to elect block producers in Proof-of-Stake
+//!
+//! The holder of a private key evaluates the function on a message and publishes the result with
+//! a proof. Anyone with the public key can verify the proof and obtain the same [`VRFOutput`],
+//! which cannot be predicted without the private key nor chosen by its holder.
+
+pub mod schnorrkel;
+
+use serialization::{Decode, Encode};
+
+use crate::random::make_true_rng;
+
+use self::schnorrkel::{SchnorrkelPrivateKey, SchnorrkelPublicKey, SchnorrkelVRFReturn};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum VRFError {
+    InvalidData,
+    VerificationError(String),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Decode, Encode)]
+pub enum VRFKeyKind {
+    /// VRF over Ristretto255 as implemented by schnorrkel
+    #[codec(index = 0)]
+    Schnorrkel,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Decode, Encode)]
+pub struct VRFPrivateKey {
+    key: VRFPrivateKeyHolder,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Decode, Encode)]
+pub struct VRFPublicKey {
+    pub_key: VRFPublicKeyHolder,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Decode, Encode)]
+pub(crate) enum VRFPrivateKeyHolder {
+    #[codec(index = 0)]
+    Schnorrkel(SchnorrkelPrivateKey),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Decode, Encode)]
+pub(crate) enum VRFPublicKeyHolder {
+    #[codec(index = 0)]
+    Schnorrkel(SchnorrkelPublicKey),
+}
+
+/// Result of evaluating the VRF with a private key, to be published along with the message
+#[derive(Debug, PartialEq, Eq, Clone, Decode, Encode)]
+pub enum VRFReturn {
+    #[codec(index = 0)]
+    Schnorrkel(SchnorrkelVRFReturn),
+}
+
+/// Output of the VRF, uniformly distributed and determined by the key and the message
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Decode, Encode)]
+pub struct VRFOutput([u8; 32]);
+
+impl VRFOutput {
+    pub(crate) fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl VRFPrivateKey {
+    pub fn new(key_kind: VRFKeyKind) -> (VRFPrivateKey, VRFPublicKey) {
+        let mut rng = make_true_rng();
+        match key_kind {
+            VRFKeyKind::Schnorrkel => {
+                let k = SchnorrkelPrivateKey::new(&mut rng);
+                (
+                    VRFPrivateKey {
+                        key: VRFPrivateKeyHolder::Schnorrkel(k.0),
+                    },
+                    VRFPublicKey {
+                        pub_key: VRFPublicKeyHolder::Schnorrkel(k.1),
+                    },
+                )
+            }
+        }
+    }
+
+    pub fn kind(&self) -> VRFKeyKind {
+        match self.key {
+            VRFPrivateKeyHolder::Schnorrkel(_) => VRFKeyKind::Schnorrkel,
+        }
+    }
+
+    pub fn produce_vrf_data(&self, message: &[u8]) -> VRFReturn {
+        match &self.key {
+            VRFPrivateKeyHolder::Schnorrkel(k) => {
+                VRFReturn::Schnorrkel(k.produce_vrf_data(message))
+            }
+        }
+    }
+}
+
+impl VRFPublicKey {
+    pub fn from_private_key(private_key: &VRFPrivateKey) -> Self {
+        match &private_key.key {
+            VRFPrivateKeyHolder::Schnorrkel(k) => VRFPublicKey {
+                pub_key: VRFPublicKeyHolder::Schnorrkel(SchnorrkelPublicKey::from_private_key(k)),
+            },
+        }
+    }
+
+    pub fn kind(&self) -> VRFKeyKind {
+        match self.pub_key {
+            VRFPublicKeyHolder::Schnorrkel(_) => VRFKeyKind::Schnorrkel,
+        }
+    }
+
+    /// Verify the data produced with the matching private key and return the output
+    pub fn verify_vrf_data(
+        &self,
+        message: &[u8],
+        vrf_data: &VRFReturn,
+    ) -> Result<VRFOutput, VRFError> {
+        match (&self.pub_key, vrf_data) {
+            (VRFPublicKeyHolder::Schnorrkel(k), VRFReturn::Schnorrkel(d)) => {
+                k.verify_vrf_data(message, d)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn produce_and_verify() {
+        let (sk, pk) = VRFPrivateKey::new(VRFKeyKind::Schnorrkel);
+        assert_eq!(pk, VRFPublicKey::from_private_key(&sk));
+        assert_eq!(sk.kind(), VRFKeyKind::Schnorrkel);
+        assert_eq!(pk.kind(), VRFKeyKind::Schnorrkel);
+
+        let vrf_data = sk.produce_vrf_data(b"epoch 3 slot 7");
+        let encoded = vrf_data.encode();
+        let decoded = VRFReturn::decode(&mut &encoded[..]).unwrap();
+        let output = pk.verify_vrf_data(b"epoch 3 slot 7", &decoded).unwrap();
+        assert_eq!(
+            pk.verify_vrf_data(b"epoch 3 slot 7", &sk.produce_vrf_data(b"epoch 3 slot 7")),
+            Ok(output)
+        );
+
+        let (_, other_pk) = VRFPrivateKey::new(VRFKeyKind::Schnorrkel);
+        assert!(other_pk.verify_vrf_data(b"epoch 3 slot 7", &vrf_data).is_err());
+    }
+}
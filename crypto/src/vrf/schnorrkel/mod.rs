@@ -0,0 +1,230 @@
+use rand::{CryptoRng, Rng};
+use schnorrkel::{
+    vrf::{VRFPreOut, VRFProof},
+    PublicKey, SecretKey,
+};
+use serialization::{Decode, Encode};
+
+use super::{VRFError, VRFOutput};
+
+/// Context of the transcripts the VRF is evaluated on, separates them from signatures
+const VRF_SIGNING_CONTEXT: &[u8] = b"mintlayer-vrf";
+/// Context of the output derived from the VRF input and pre-output
+const VRF_OUTPUT_CONTEXT: &[u8] = b"mintlayer-vrf-output";
+
+#[derive(Debug, Clone)]
+pub struct SchnorrkelPrivateKey {
+    key_data: SecretKey,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchnorrkelPublicKey {
+    pubkey_data: PublicKey,
+}
+
+/// The VRF pre-output together with the proof that it was computed from the input and the key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchnorrkelVRFReturn {
+    preout: VRFPreOut,
+    proof: VRFProof,
+}
+
+impl SchnorrkelPrivateKey {
+    pub fn new<R: Rng + CryptoRng>(rng: &mut R) -> (SchnorrkelPrivateKey, SchnorrkelPublicKey) {
+        let key_data = SecretKey::generate_with(rng);
+        let pubkey_data = key_data.to_public();
+        (
+            SchnorrkelPrivateKey { key_data },
+            SchnorrkelPublicKey { pubkey_data },
+        )
+    }
+
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.key_data.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VRFError> {
+        SecretKey::from_bytes(bytes)
+            .map(|key_data| Self { key_data })
+            .map_err(|_| VRFError::InvalidData)
+    }
+
+    pub fn produce_vrf_data(&self, message: &[u8]) -> SchnorrkelVRFReturn {
+        let keypair = self.key_data.clone().to_keypair();
+        let transcript = schnorrkel::signing_context(VRF_SIGNING_CONTEXT).bytes(message);
+        let (inout, proof, _) = keypair.vrf_sign(transcript);
+        SchnorrkelVRFReturn {
+            preout: inout.to_preout(),
+            proof,
+        }
+    }
+}
+
+impl PartialEq for SchnorrkelPrivateKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.key_data.to_bytes() == other.key_data.to_bytes()
+    }
+}
+
+impl Eq for SchnorrkelPrivateKey {}
+
+impl SchnorrkelPublicKey {
+    pub fn from_private_key(private_key: &SchnorrkelPrivateKey) -> Self {
+        Self {
+            pubkey_data: private_key.key_data.to_public(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.pubkey_data.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VRFError> {
+        PublicKey::from_bytes(bytes)
+            .map(|pubkey_data| Self { pubkey_data })
+            .map_err(|_| VRFError::InvalidData)
+    }
+
+    /// Verify the proof and derive the output, which only depends on the key and the message
+    pub fn verify_vrf_data(
+        &self,
+        message: &[u8],
+        vrf_data: &SchnorrkelVRFReturn,
+    ) -> Result<VRFOutput, VRFError> {
+        let transcript = schnorrkel::signing_context(VRF_SIGNING_CONTEXT).bytes(message);
+        let (inout, _) = self
+            .pubkey_data
+            .vrf_verify(transcript, &vrf_data.preout, &vrf_data.proof)
+            .map_err(|e| VRFError::VerificationError(e.to_string()))?;
+        Ok(VRFOutput::new(inout.make_bytes(VRF_OUTPUT_CONTEXT)))
+    }
+}
+
+impl PartialOrd for SchnorrkelPublicKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SchnorrkelPublicKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.to_bytes().cmp(&other.to_bytes())
+    }
+}
+
+impl SchnorrkelVRFReturn {
+    pub fn preout_bytes(&self) -> [u8; 32] {
+        self.preout.to_bytes()
+    }
+
+    pub fn proof_bytes(&self) -> [u8; 64] {
+        self.proof.to_bytes()
+    }
+}
+
+impl Encode for SchnorrkelPrivateKey {
+    fn encode_to<T: serialization::Output + ?Sized>(&self, dest: &mut T) {
+        self.to_bytes().to_vec().encode_to(dest)
+    }
+}
+
+impl Decode for SchnorrkelPrivateKey {
+    fn decode<I: serialization::Input>(input: &mut I) -> Result<Self, serialization::Error> {
+        let v = Vec::<u8>::decode(input)?;
+        Self::from_bytes(&v)
+            .map_err(|_| serialization::Error::from("VRF private key deserialization failed"))
+    }
+}
+
+impl Encode for SchnorrkelPublicKey {
+    fn encode_to<T: serialization::Output + ?Sized>(&self, dest: &mut T) {
+        self.to_bytes().to_vec().encode_to(dest)
+    }
+}
+
+impl Decode for SchnorrkelPublicKey {
+    fn decode<I: serialization::Input>(input: &mut I) -> Result<Self, serialization::Error> {
+        let v = Vec::<u8>::decode(input)?;
+        Self::from_bytes(&v)
+            .map_err(|_| serialization::Error::from("VRF public key deserialization failed"))
+    }
+}
+
+impl Encode for SchnorrkelVRFReturn {
+    fn encode_to<T: serialization::Output + ?Sized>(&self, dest: &mut T) {
+        self.preout_bytes().encode_to(dest);
+        self.proof_bytes().encode_to(dest);
+    }
+}
+
+impl Decode for SchnorrkelVRFReturn {
+    fn decode<I: serialization::Input>(input: &mut I) -> Result<Self, serialization::Error> {
+        let preout = <[u8; 32]>::decode(input)?;
+        let proof = <[u8; 64]>::decode(input)?;
+        Ok(Self {
+            preout: VRFPreOut::from_bytes(&preout)
+                .map_err(|_| serialization::Error::from("VRF pre-output deserialization failed"))?,
+            proof: VRFProof::from_bytes(&proof)
+                .map_err(|_| serialization::Error::from("VRF proof deserialization failed"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::random::make_true_rng;
+
+    #[test]
+    fn produce_and_verify() {
+        let mut rng = make_true_rng();
+        let (sk, pk) = SchnorrkelPrivateKey::new(&mut rng);
+        assert_eq!(pk, SchnorrkelPublicKey::from_private_key(&sk));
+
+        let vrf_data = sk.produce_vrf_data(b"slot 1");
+        let output = pk.verify_vrf_data(b"slot 1", &vrf_data).unwrap();
+
+        // The proof is randomized but the output is not
+        let other_vrf_data = sk.produce_vrf_data(b"slot 1");
+        assert_ne!(vrf_data.proof_bytes(), other_vrf_data.proof_bytes());
+        assert_eq!(pk.verify_vrf_data(b"slot 1", &other_vrf_data), Ok(output));
+
+        let next_output = pk.verify_vrf_data(b"slot 2", &sk.produce_vrf_data(b"slot 2")).unwrap();
+        assert_ne!(output, next_output);
+    }
+
+    #[test]
+    fn verification_failures() {
+        let mut rng = make_true_rng();
+        let (sk, pk) = SchnorrkelPrivateKey::new(&mut rng);
+        let (_, other_pk) = SchnorrkelPrivateKey::new(&mut rng);
+        let vrf_data = sk.produce_vrf_data(b"slot 1");
+
+        assert!(pk.verify_vrf_data(b"slot 2", &vrf_data).is_err());
+        assert!(other_pk.verify_vrf_data(b"slot 1", &vrf_data).is_err());
+
+        let forged = SchnorrkelVRFReturn {
+            preout: sk.produce_vrf_data(b"slot 2").preout,
+            proof: vrf_data.proof.clone(),
+        };
+        assert!(pk.verify_vrf_data(b"slot 1", &forged).is_err());
+    }
+
+    #[test]
+    fn serialization() {
+        let mut rng = make_true_rng();
+        let (sk, pk) = SchnorrkelPrivateKey::new(&mut rng);
+        let vrf_data = sk.produce_vrf_data(b"slot 1");
+
+        let decoded_sk = SchnorrkelPrivateKey::decode(&mut &sk.encode()[..]).unwrap();
+        assert_eq!(decoded_sk, sk);
+        let decoded_pk = SchnorrkelPublicKey::decode(&mut &pk.encode()[..]).unwrap();
+        assert_eq!(decoded_pk, pk);
+
+        let encoded = vrf_data.encode();
+        assert_eq!(encoded.len(), 96);
+        let decoded = SchnorrkelVRFReturn::decode(&mut &encoded[..]).unwrap();
+        assert_eq!(decoded, vrf_data);
+        assert!(pk.verify_vrf_data(b"slot 1", &decoded).is_ok());
+    }
+}
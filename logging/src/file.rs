@@ -0,0 +1,125 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Log file rotated by size
+
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Log file, moved aside once it reaches its maximum size
+///
+/// The rotated files are named after the log file with the suffixes `.1` (the most recent) to
+/// `.N`, the older ones are deleted.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl RotatingFile {
+    /// Open the log file at `path` for appending, keeping `max_files` rotated files
+    pub fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            // Not all platforms can rename over an existing file
+            match fs::remove_file(self.rotated_path(self.max_files)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = open_append(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Append a log record, rotating the file first if the record doesn't fit
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        let len = record.len() as u64;
+        if self.size > 0 && self.size.saturating_add(len) > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(record)?;
+        self.size += len;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.log");
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+
+        for record in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_record(record.as_bytes()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(file.rotated_path(1)).unwrap(), "third\n");
+        assert_eq!(
+            fs::read_to_string(file.rotated_path(2)).unwrap(),
+            "second\n"
+        );
+        assert!(!file.rotated_path(3).exists());
+
+        // Appends to the existing file
+        let mut file = RotatingFile::open(path.clone(), 20, 2).unwrap();
+        file.write_record(b"fifth\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\nfifth\n");
+    }
+}
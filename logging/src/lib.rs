@@ -1,17 +1,365 @@
+//! Logging to the terminal and optionally to a file
+//!
+//! The log levels are set per target with filters in the syntax of the `RUST_LOG` environment
+//! variable, such as `info,p2p=debug,chainstate=trace`. The filters can be changed at run time
+//! with [set_log_filters].
+//!
+//! The logs are written as text or, for ingestion by log processors, as JSON objects, one per
+//! line. The [error], [warn], [info], [debug] and [trace] macros of this crate attach fields to
+//! the record, such as `logging::info!(peer_id = peer_id; "peer connected")`. They are separate
+//! keys of the JSON objects and are appended to the text logs as `peer_id=...`.
+
+mod file;
+
+use std::{
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use env_logger::filter::{Builder as FilterBuilder, Filter};
 pub use log;
+use log::LevelFilter;
+
+use crate::file::RotatingFile;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum LogError {
+    #[error("Invalid log level `{0}`")]
+    InvalidLevel(String),
+    #[error("Failed to open log file `{0}`: {1}")]
+    FileError(PathBuf, String),
+}
+
+/// Format of the logs, on the terminal and in the log file alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// JSON objects with the timestamp, level, target, message and fields as keys, one per line
+    Json,
+}
+
+/// Logging configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    /// Log format
+    format: LogFormat,
+    /// Log filters, taken from `RUST_LOG` if not set
+    filters: Option<String>,
+    /// File to write logs to in addition to the terminal
+    file: Option<PathBuf>,
+    /// Size in bytes the log file is rotated at
+    max_file_size: u64,
+    /// Number of rotated log files kept
+    max_files: usize,
+}
+
+impl LogConfig {
+    pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+    pub const DEFAULT_MAX_FILES: usize = 5;
+
+    pub fn new() -> Self {
+        Self {
+            format: LogFormat::Text,
+            filters: None,
+            file: None,
+            max_file_size: Self::DEFAULT_MAX_FILE_SIZE,
+            max_files: Self::DEFAULT_MAX_FILES,
+        }
+    }
+
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_filters(mut self, filters: String) -> Self {
+        self.filters = Some(filters);
+        self
+    }
+
+    pub fn with_file(mut self, path: PathBuf) -> Self {
+        self.file = Some(path);
+        self
+    }
+
+    /// Rotate the log file once it reaches `max_file_size` bytes, keeping `max_files` old files
+    pub fn with_rotation(mut self, max_file_size: u64, max_files: usize) -> Self {
+        self.max_file_size = max_file_size;
+        self.max_files = max_files;
+        self
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Filter of the installed logger, replaced by `set_log_filters`
+    static ref FILTER: RwLock<Filter> = RwLock::new(FilterBuilder::from_env("RUST_LOG").build());
+}
+
+/// Parse log filters, a comma-separated list of `target=level`, `level` and `target` directives
+fn parse_filters(filters: &str) -> Result<Filter, LogError> {
+    let mut builder = FilterBuilder::new();
+    for directive in filters.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            Some((target, level)) => {
+                let level = level
+                    .trim()
+                    .parse()
+                    .map_err(|_| LogError::InvalidLevel(level.trim().to_owned()))?;
+                builder.filter_module(target.trim(), level)
+            }
+            None => match directive.parse() {
+                Ok(level) => builder.filter_level(level),
+                // A target alone enables all its logs
+                Err(_) => builder.filter_module(directive, LevelFilter::Trace),
+            },
+        };
+    }
+    Ok(builder.build())
+}
+
+/// Replace the log filters, effective immediately
+pub fn set_log_filters(filters: &str) -> Result<(), LogError> {
+    let filter = parse_filters(filters)?;
+    log::set_max_level(filter.filter());
+    *FILTER.write().expect("Log filter lock poisoned") = filter;
+    Ok(())
+}
+
+/// Log a record with fields, used by the logging macros of this crate
+#[doc(hidden)]
+pub fn log_with_fields(
+    level: log::Level,
+    target: &str,
+    fields: &[(&str, String)],
+    args: fmt::Arguments,
+) {
+    let key_values: Vec<(&str, &str)> =
+        fields.iter().map(|(key, value)| (*key, value.as_str())).collect();
+    let key_values: &[(&str, &str)] = &key_values;
+    log::logger().log(
+        &log::Record::builder()
+            .level(level)
+            .target(target)
+            .args(args)
+            .key_values(&key_values)
+            .build(),
+    );
+}
+
+/// Log a record with fields at the given level, see the other macros of this crate
+#[macro_export]
+macro_rules! event {
+    ($level:expr, $($key:ident = $value:expr),+ ; $($arg:tt)+) => {{
+        let level = $level;
+        if level <= $crate::log::STATIC_MAX_LEVEL && level <= $crate::log::max_level() {
+            $crate::log_with_fields(
+                level,
+                module_path!(),
+                &[$((stringify!($key), ($value).to_string())),+],
+                format_args!($($arg)+),
+            );
+        }
+    }};
+}
+
+/// Log an error with fields, given before the message like `error!(peer_id = id; "message")`
+///
+/// The field values are converted with `ToString`.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::event!($crate::log::Level::Error, $($arg)+) };
+}
+
+/// Log a warning with fields, see [error]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::event!($crate::log::Level::Warn, $($arg)+) };
+}
+
+/// Log an information with fields, see [error]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::event!($crate::log::Level::Info, $($arg)+) };
+}
+
+/// Log a debug message with fields, see [error]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::event!($crate::log::Level::Debug, $($arg)+) };
+}
+
+/// Log a trace message with fields, see [error]
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::event!($crate::log::Level::Trace, $($arg)+) };
+}
+
+/// Collects the fields of a record as strings
+struct FieldCollector(Vec<(String, String)>);
+
+impl<'kvs> log::kv::Visitor<'kvs> for FieldCollector {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+fn record_fields(record: &log::Record) -> Vec<(String, String)> {
+    let mut collector = FieldCollector(Vec::new());
+    // The collector never fails
+    let _ = record.key_values().visit(&mut collector);
+    collector.0
+}
+
+/// Fields appended to a text log line
+struct TextFields<'a>(&'a [(String, String)]);
+
+impl fmt::Display for TextFields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|(key, value)| write!(f, " {}={}", key, value))
+    }
+}
+
+fn text_line(record: &log::Record) -> String {
+    format!(
+        "[{} {:<5} {}] {}{}\n",
+        humantime::format_rfc3339_seconds(std::time::SystemTime::now()),
+        record.level(),
+        record.target(),
+        record.args(),
+        TextFields(&record_fields(record)),
+    )
+}
+
+fn json_line(record: &log::Record) -> String {
+    let mut object = serde_json::Map::new();
+    let timestamp = humantime::format_rfc3339_millis(std::time::SystemTime::now());
+    object.insert("timestamp".into(), timestamp.to_string().into());
+    object.insert("level".into(), record.level().as_str().into());
+    object.insert("target".into(), record.target().into());
+    object.insert("message".into(), record.args().to_string().into());
+    for (key, value) in record_fields(record) {
+        object.entry(key).or_insert_with(|| value.into());
+    }
+    let mut line = serde_json::Value::Object(object).to_string();
+    line.push('\n');
+    line
+}
+
+struct Logger {
+    format: LogFormat,
+    terminal: env_logger::Logger,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        FILTER.read().expect("Log filter lock poisoned").enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !FILTER.read().expect("Log filter lock poisoned").matches(record) {
+            return;
+        }
+        let line = match self.format {
+            LogFormat::Text => {
+                self.terminal.log(record);
+                match &self.file {
+                    Some(_) => text_line(record),
+                    None => return,
+                }
+            }
+            LogFormat::Json => {
+                let line = json_line(record);
+                let _ = std::io::stderr().lock().write_all(line.as_bytes());
+                line
+            }
+        };
+        if let Some(file) = &self.file {
+            // There is nowhere to report the failure to write a log
+            let _ = file.lock().expect("Log file lock poisoned").write_record(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+        if let Some(file) = &self.file {
+            let _ = file.lock().expect("Log file lock poisoned").flush();
+        }
+    }
+}
 
 pub fn is_only_terminal_output_logging() -> bool {
-    true
+    false
 }
 
 pub fn is_file_output_supported() -> bool {
-    false
+    true
 }
 
 static INITIALIZE_LOGGER_ONCE_FLAG: std::sync::Once = std::sync::Once::new();
 
-pub fn init_logging<P: AsRef<std::path::Path>>(_log_file_path: Option<P>) {
-    INITIALIZE_LOGGER_ONCE_FLAG.call_once(env_logger::init);
+/// Install the logger, later calls have no effect
+pub fn init_logging_with_config(config: &LogConfig) -> Result<(), LogError> {
+    if let Some(filters) = &config.filters {
+        *FILTER.write().expect("Log filter lock poisoned") = parse_filters(filters)?;
+    }
+    let file = match &config.file {
+        Some(path) => Some(Mutex::new(
+            RotatingFile::open(path.clone(), config.max_file_size, config.max_files)
+                .map_err(|e| LogError::FileError(path.clone(), e.to_string()))?,
+        )),
+        None => None,
+    };
+
+    INITIALIZE_LOGGER_ONCE_FLAG.call_once(|| {
+        // The terminal logger leaves the filtering to the filter above
+        let terminal = env_logger::Builder::new()
+            .filter_level(LevelFilter::Trace)
+            .format(|buf, record| {
+                writeln!(
+                    buf,
+                    "[{} {:<5} {}] {}{}",
+                    buf.timestamp(),
+                    buf.default_styled_level(record.level()),
+                    record.target(),
+                    record.args(),
+                    TextFields(&record_fields(record)),
+                )
+            })
+            .build();
+        let logger = Logger {
+            format: config.format,
+            terminal,
+            file,
+        };
+        let max_level = FILTER.read().expect("Log filter lock poisoned").filter();
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            log::set_max_level(max_level);
+        }
+    });
+    Ok(())
+}
+
+pub fn init_logging<P: AsRef<Path>>(log_file_path: Option<P>) {
+    let mut config = LogConfig::new();
+    if let Some(path) = log_file_path {
+        config = config.with_file(path.as_ref().to_owned());
+    }
+    init_logging_with_config(&config).expect("Logging initialization failed")
 }
 
 #[cfg(test)]
@@ -24,4 +372,49 @@ mod tests {
         init_logging::<&std::path::Path>(None);
         init_logging::<&std::path::Path>(None);
     }
+
+    #[test]
+    fn filters() {
+        let filter = parse_filters("info, p2p=debug,chainstate=warn,wallet").unwrap();
+        let enabled = |target, level| {
+            filter.enabled(&log::Metadata::builder().target(target).level(level).build())
+        };
+        assert!(enabled("node", log::Level::Info));
+        assert!(!enabled("node", log::Level::Debug));
+        assert!(enabled("p2p::swarm", log::Level::Debug));
+        assert!(!enabled("chainstate", log::Level::Info));
+        assert!(enabled("wallet", log::Level::Trace));
+        assert_eq!(filter.filter(), LevelFilter::Trace);
+
+        assert_eq!(
+            parse_filters("p2p=loud").err(),
+            Some(LogError::InvalidLevel("loud".to_owned()))
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let check = |record: &log::Record| {
+            assert!(text_line(record)
+                .ends_with(" INFO  p2p::sync] block received peer_id=abc height=5\n"));
+
+            let json: serde_json::Value = serde_json::from_str(&json_line(record)).unwrap();
+            assert_eq!(json["level"], "INFO");
+            assert_eq!(json["target"], "p2p::sync");
+            assert_eq!(json["message"], "block received");
+            assert_eq!(json["peer_id"], "abc");
+            assert_eq!(json["height"], "5");
+            assert!(json["timestamp"].is_string());
+        };
+
+        let key_values: &[(&str, &str)] = &[("peer_id", "abc"), ("height", "5")];
+        check(
+            &log::Record::builder()
+                .level(log::Level::Info)
+                .target("p2p::sync")
+                .args(format_args!("block {}", "received"))
+                .key_values(&key_values)
+                .build(),
+        );
+    }
 }
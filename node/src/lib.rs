@@ -1,13 +1,11 @@
 //! Top-level node runner as a library
 
+mod metrics;
 mod options;
 mod runner;
+mod stratum;
 
 pub type Error = anyhow::Error;
 
 pub use options::Options;
-pub use runner::{initialize, run};
-
-pub fn init_logging(opts: &Options) {
-    logging::init_logging(opts.log_path.as_ref())
-}
+pub use runner::{export_chain, import_chain, init_logging, initialize, run, verify_storage};
@@ -1,14 +1,26 @@
 //! Top-level node binary
 
+mod metrics;
 mod options;
 mod runner;
+mod stratum;
 
 async fn run() -> anyhow::Result<()> {
     let opts = options::Options::from_args(std::env::args_os());
 
-    logging::init_logging(opts.log_path.as_ref());
+    runner::init_logging(&opts)?;
     logging::log::trace!("Command line options: {:?}", opts);
 
+    if opts.verify_storage {
+        return runner::verify_storage(&opts);
+    }
+    if let Some(path) = &opts.export_chain {
+        return runner::export_chain(&opts, path);
+    }
+    if let Some(path) = &opts.import_chain {
+        return runner::import_chain(&opts, path);
+    }
+
     runner::run(opts).await
 }
 
@@ -0,0 +1,406 @@
+//! Prometheus metrics endpoint
+//!
+//! When enabled, the node serves `GET /metrics` in the Prometheus text exposition format. The
+//! values are collected from the subsystems on each request, except for the p2p counters which
+//! the p2p subsystem reports to [`p2p::metrics::Counters`] as they change.
+
+use std::{convert::Infallible, fmt::Write, net::SocketAddr, sync::Arc};
+
+use chainstate::{chainstate_interface::ChainstateInterface, ChainstateError};
+use hyper::{
+    header,
+    server::{conn::AddrIncoming, Builder},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use logging::log;
+use p2p::{
+    metrics::{Counters, Direction, MetricValues},
+    net::libp2p::Libp2pService,
+    sync::SyncState,
+};
+use subsystem::subsystem::{CallRequest, ShutdownRequest};
+
+/// Content type of the Prometheus text format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Subsystems the metrics are collected from
+pub struct Sources {
+    pub chainstate: subsystem::Handle<Box<dyn ChainstateInterface>>,
+    pub p2p: p2p::P2pHandle<Libp2pService>,
+    pub p2p_counters: Arc<Counters>,
+    pub rpc: subsystem::Handle<rpc::Rpc>,
+}
+
+/// Values exported at the endpoint
+#[derive(Debug, Default)]
+struct Snapshot {
+    best_block_height: u64,
+    best_block_time: u32,
+    storage: blockchain_storage::Statistics,
+    peers: usize,
+    p2p: MetricValues,
+    pending_calls: Vec<(&'static str, usize)>,
+}
+
+impl Sources {
+    async fn snapshot(&self) -> anyhow::Result<Snapshot> {
+        let (best_block_height, best_block_time, storage) = self
+            .chainstate
+            .call(|this| -> Result<_, ChainstateError> {
+                let best_block_id = this.get_best_block_id()?;
+                let height = this.get_best_block_height()?;
+                let time = this.get_block(best_block_id)?.map_or(0, |block| block.block_time());
+                Ok((height.into(), time, this.get_storage_statistics()))
+            })
+            .await??;
+        let peers = self.p2p.call_async(|this| Box::pin(this.get_peer_count())).await??;
+
+        Ok(Snapshot {
+            best_block_height,
+            best_block_time,
+            storage,
+            peers,
+            p2p: self.p2p_counters.values(),
+            pending_calls: vec![
+                ("chainstate", self.chainstate.pending_calls()),
+                ("p2p", self.p2p.pending_calls()),
+                ("rpc", self.rpc.pending_calls()),
+            ],
+        })
+    }
+}
+
+/// Bind the metrics server to `address`
+///
+/// Binding is done before the server is run so that an unusable address is reported
+/// when the node is initialized.
+pub fn bind(address: &SocketAddr) -> anyhow::Result<Builder<AddrIncoming>> {
+    Ok(hyper::Server::try_bind(address)?)
+}
+
+/// Run the metrics subsystem, serving the metrics until the node shuts down
+pub async fn run(
+    server: Builder<AddrIncoming>,
+    sources: Sources,
+    mut call_rq: CallRequest<()>,
+    mut shutdown_rq: ShutdownRequest,
+) {
+    let sources = Arc::new(sources);
+    let make_service = make_service_fn(move |_| {
+        let sources = Arc::clone(&sources);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let sources = Arc::clone(&sources);
+                async move { Ok::<_, Infallible>(handle_request(&sources, request).await) }
+            }))
+        }
+    });
+
+    let server = server.serve(make_service).with_graceful_shutdown(shutdown_rq.recv());
+    tokio::pin!(server);
+
+    loop {
+        tokio::select! {
+            result = &mut server => {
+                if let Err(e) = result {
+                    log::error!("Metrics server error: {}", e);
+                }
+                break;
+            }
+            call = call_rq.recv() => call(&mut ()).await,
+        }
+    }
+}
+
+async fn handle_request(sources: &Sources, request: Request<Body>) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return response(StatusCode::NOT_FOUND, "Not found\n".into());
+    }
+
+    match sources.snapshot().await {
+        Ok(snapshot) => response(StatusCode::OK, render(&snapshot)),
+        Err(e) => {
+            log::error!("Failed to collect metrics: {}", e);
+            response(StatusCode::INTERNAL_SERVER_ERROR, format!("{}\n", e))
+        }
+    }
+}
+
+fn response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, CONTENT_TYPE)
+        .body(body.into())
+        .expect("Response with a valid status and header")
+}
+
+fn direction_label(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Inbound => "inbound",
+        Direction::Outbound => "outbound",
+    }
+}
+
+fn sync_state_label(state: SyncState) -> &'static str {
+    match state {
+        SyncState::Uninitialized => "uninitialized",
+        SyncState::DownloadingBlocks => "downloading_blocks",
+        SyncState::Stalled => "stalled",
+        SyncState::Idle => "idle",
+    }
+}
+
+/// Write a metric family, the samples are given as `(label value, value)` pairs
+fn write_metric<'a>(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    label: &str,
+    samples: impl IntoIterator<Item = (&'a str, u64)>,
+) {
+    writeln!(out, "# HELP {} {}", name, help).expect("Writing to a string to succeed");
+    writeln!(out, "# TYPE {} {}", name, kind).expect("Writing to a string to succeed");
+    for (label_value, value) in samples {
+        writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, label_value, value)
+            .expect("Writing to a string to succeed");
+    }
+}
+
+/// Write a metric family consisting of a single sample without labels
+fn write_value(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}", name, help).expect("Writing to a string to succeed");
+    writeln!(out, "# TYPE {} {}", name, kind).expect("Writing to a string to succeed");
+    writeln!(out, "{} {}", name, value).expect("Writing to a string to succeed");
+}
+
+/// Labeled samples in a stable order
+fn sorted<K: Copy>(
+    values: &std::collections::HashMap<K, u64>,
+    label: impl Fn(K) -> &'static str,
+) -> Vec<(&'static str, u64)> {
+    let mut samples = values.iter().map(|(key, value)| (label(*key), *value)).collect::<Vec<_>>();
+    samples.sort_unstable();
+    samples
+}
+
+/// Storage columns with their label values
+fn storage_columns(
+    storage: &blockchain_storage::Statistics,
+) -> [(&'static str, blockchain_storage::ColumnStats); 7] {
+    [
+        ("blocks", storage.blocks),
+        ("block_index", storage.block_index),
+        ("block_by_height", storage.block_by_height),
+        ("tx_index", storage.tx_index),
+        ("utxo", storage.utxo),
+        ("undo", storage.undo),
+        ("block_file_index", storage.block_file_index),
+    ]
+}
+
+/// Render the snapshot in the Prometheus text format
+fn render(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    let p2p = &snapshot.p2p;
+    let storage = storage_columns(&snapshot.storage);
+
+    write_value(
+        &mut out,
+        "mintlayer_chainstate_best_block_height",
+        "gauge",
+        "Height of the best block",
+        snapshot.best_block_height,
+    );
+    write_value(
+        &mut out,
+        "mintlayer_chainstate_best_block_time_seconds",
+        "gauge",
+        "Timestamp of the best block",
+        snapshot.best_block_time.into(),
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_storage_entries",
+        "gauge",
+        "Entries in the blockchain storage column",
+        "column",
+        storage.iter().map(|(column, stats)| (*column, stats.entries as u64)),
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_storage_bytes",
+        "gauge",
+        "Approximate size of the blockchain storage column",
+        "column",
+        storage.iter().map(|(column, stats)| (*column, stats.bytes as u64)),
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_p2p_sync_state",
+        "gauge",
+        "Sync state of the node, 1 for the current state",
+        "state",
+        [
+            SyncState::Uninitialized,
+            SyncState::DownloadingBlocks,
+            SyncState::Stalled,
+            SyncState::Idle,
+        ]
+        .into_iter()
+        .map(|state| {
+            let current = p2p.sync_state.unwrap_or(SyncState::Uninitialized) == state;
+            (sync_state_label(state), current.into())
+        }),
+    );
+    write_value(
+        &mut out,
+        "mintlayer_p2p_peers",
+        "gauge",
+        "Number of connected peers",
+        snapshot.peers as u64,
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_p2p_connections_opened_total",
+        "counter",
+        "Connections to peers opened",
+        "direction",
+        sorted(&p2p.connections_opened, direction_label),
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_p2p_connections_closed_total",
+        "counter",
+        "Connections to peers closed",
+        "direction",
+        sorted(&p2p.connections_closed, direction_label),
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_p2p_sent_bytes_total",
+        "counter",
+        "Bytes sent to peers",
+        "message",
+        sorted(&p2p.bytes_sent, |kind| kind.as_str()),
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_p2p_received_bytes_total",
+        "counter",
+        "Bytes received from peers",
+        "message",
+        sorted(&p2p.bytes_received, |kind| kind.as_str()),
+    );
+    write_value(
+        &mut out,
+        "mintlayer_p2p_misbehavior_reports_total",
+        "counter",
+        "Misbehavior reports of peers",
+        p2p.misbehavior_reports,
+    );
+    write_value(
+        &mut out,
+        "mintlayer_p2p_misbehavior_score_total",
+        "counter",
+        "Misbehavior score assigned to peers",
+        p2p.misbehavior_score,
+    );
+    write_value(
+        &mut out,
+        "mintlayer_p2p_blocks_downloaded_total",
+        "counter",
+        "Blocks downloaded during syncing",
+        p2p.blocks_downloaded,
+    );
+    write_value(
+        &mut out,
+        "mintlayer_p2p_orphan_blocks",
+        "gauge",
+        "Downloaded blocks waiting for their parent",
+        p2p.orphan_blocks as u64,
+    );
+    write_value(
+        &mut out,
+        "mintlayer_p2p_orphan_blocks_announced_total",
+        "counter",
+        "Announced blocks rejected because their parent is unknown",
+        p2p.orphan_blocks_announced,
+    );
+    write_metric(
+        &mut out,
+        "mintlayer_subsystem_pending_calls",
+        "gauge",
+        "Calls waiting in the subsystem call queue",
+        "subsystem",
+        snapshot.pending_calls.iter().map(|(name, pending)| (*name, *pending as u64)),
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p2p::metrics::MessageKind;
+
+    #[test]
+    fn render_snapshot() {
+        let mut p2p = MetricValues {
+            sync_state: Some(SyncState::Idle),
+            misbehavior_reports: 1,
+            misbehavior_score: 100,
+            orphan_blocks: 3,
+            ..Default::default()
+        };
+        p2p.connections_opened.insert(Direction::Outbound, 4);
+        p2p.connections_opened.insert(Direction::Inbound, 2);
+        p2p.bytes_sent.insert(MessageKind::Headers, 1000);
+
+        let storage = blockchain_storage::Statistics {
+            blocks: blockchain_storage::ColumnStats {
+                entries: 13,
+                bytes: 4096,
+            },
+            ..Default::default()
+        };
+
+        let rendered = render(&Snapshot {
+            best_block_height: 12,
+            best_block_time: 1_650_000_000,
+            storage,
+            peers: 5,
+            p2p,
+            pending_calls: vec![("chainstate", 1), ("p2p", 0)],
+        });
+        let lines = rendered.lines().collect::<Vec<_>>();
+
+        for expected in [
+            "# TYPE mintlayer_chainstate_best_block_height gauge",
+            "mintlayer_chainstate_best_block_height 12",
+            "mintlayer_chainstate_best_block_time_seconds 1650000000",
+            "mintlayer_storage_entries{column=\"blocks\"} 13",
+            "mintlayer_storage_bytes{column=\"blocks\"} 4096",
+            "mintlayer_storage_bytes{column=\"utxo\"} 0",
+            "mintlayer_p2p_sync_state{state=\"idle\"} 1",
+            "mintlayer_p2p_sync_state{state=\"stalled\"} 0",
+            "mintlayer_p2p_peers 5",
+            "# TYPE mintlayer_p2p_connections_opened_total counter",
+            "mintlayer_p2p_connections_opened_total{direction=\"inbound\"} 2",
+            "mintlayer_p2p_connections_opened_total{direction=\"outbound\"} 4",
+            "mintlayer_p2p_sent_bytes_total{message=\"headers\"} 1000",
+            "mintlayer_p2p_misbehavior_score_total 100",
+            "mintlayer_p2p_orphan_blocks 3",
+            "mintlayer_subsystem_pending_calls{subsystem=\"chainstate\"} 1",
+            "mintlayer_subsystem_pending_calls{subsystem=\"p2p\"} 0",
+        ] {
+            assert!(lines.contains(&expected), "missing {:?}", expected);
+        }
+
+        let inbound = lines.iter().position(|line| line.contains("direction=\"inbound\""));
+        let outbound = lines.iter().position(|line| line.contains("direction=\"outbound\""));
+        assert!(inbound < outbound);
+        assert!(!rendered.contains("mintlayer_p2p_received_bytes_total{"));
+    }
+}
@@ -2,10 +2,42 @@
 
 use std::ffi::OsString;
 use std::net::SocketAddr;
+use std::num::NonZeroU32;
 use std::path::PathBuf;
 use strum::VariantNames;
 
-use common::chain::config::ChainType;
+use common::chain::{config::ChainType, services::Services, whitelist::WhitelistEntry};
+use p2p::config::IpPreference;
+
+/// Storage backend of the blockchain data
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// Kept in memory, lost when the node stops
+    InMemory,
+    /// Kept in memory and persisted to an append-only journal file, see `--storage-path`.
+    /// The journal is replayed into memory when the node starts.
+    Journal,
+}
+
+/// Format of the logs
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// JSON objects, one per line
+    Json,
+}
+
+/// What the watchdog does with a stalled subsystem
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Log the stalled subsystem
+    Log,
+    /// Shut the node down
+    Shutdown,
+    /// Abort the node process
+    Abort,
+}
 
 /// Mintlayer node executable
 #[derive(clap::Parser, Debug)]
@@ -15,17 +47,173 @@ pub struct Options {
     #[clap(long, value_name = "PATH")]
     pub log_path: Option<PathBuf>,
 
+    /// Log levels per target, such as `info,p2p=debug,chainstate=trace`. Taken from the
+    /// `RUST_LOG` environment variable if not given. Can be changed with the `set_log_level`
+    /// RPC method.
+    #[clap(long, value_name = "FILTERS")]
+    pub log_filters: Option<String>,
+
+    /// Format of the logs, on the terminal and in the log file
+    #[clap(long, arg_enum, default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Size in MiB the log file is rotated at
+    #[clap(long, value_name = "MIB", default_value = "10", requires = "log-path")]
+    pub log_max_file_size: NonZeroU32,
+
+    /// Number of rotated log files kept
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = logging::LogConfig::DEFAULT_MAX_FILES,
+        requires = "log-path"
+    )]
+    pub log_max_files: usize,
+
     /// Address to bind RPC to
     #[clap(long, value_name = "ADDR", default_value = "127.0.0.1:3030")]
     pub rpc_addr: SocketAddr,
 
+    /// Address to bind the RPC WebSocket server to, required for subscriptions
+    #[clap(long, value_name = "ADDR")]
+    pub rpc_ws_addr: Option<SocketAddr>,
+
+    /// Address to bind the read-only REST server to
+    #[clap(long, value_name = "ADDR")]
+    pub rpc_rest_addr: Option<SocketAddr>,
+
+    /// Reject JSON-RPC batch requests
+    #[clap(long)]
+    pub rpc_no_batch: bool,
+
+    /// Number of expensive RPC calls, such as decoding blocks, served at once.
+    /// Further calls are rejected until one of them completes.
+    #[clap(long, value_name = "N", default_value_t = rpc::DEFAULT_MAX_EXPENSIVE_CALLS)]
+    pub rpc_max_expensive_calls: u16,
+
+    /// Address to serve Prometheus metrics at, under `/metrics`
+    #[clap(long, value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Address to serve the Stratum mining protocol at, for external miners
+    #[clap(long, value_name = "ADDR")]
+    pub stratum_addr: Option<SocketAddr>,
+
+    /// Difficulty of the mining shares, relative to the lowest possible block difficulty
+    #[clap(long, value_name = "N", default_value = "1", requires = "stratum-addr")]
+    pub stratum_share_difficulty: NonZeroU32,
+
+    /// Report the subsystems that leave their pending calls unhandled for this many seconds as
+    /// stalled and act on them according to `--watchdog-action`
+    #[clap(long, value_name = "SECS")]
+    pub watchdog_timeout: Option<NonZeroU32>,
+
+    /// What the watchdog does with a stalled subsystem
+    #[clap(long, arg_enum, default_value = "log", requires = "watchdog-timeout")]
+    pub watchdog_action: WatchdogAction,
+
     /// Blockchain type
     #[clap(long, possible_values = ChainType::VARIANTS, default_value = "mainnet")]
     pub net: ChainType,
 
+    /// Load the chain configuration of a custom network from a TOML or JSON file instead of
+    /// using the one built in for `--net`
+    #[clap(long, value_name = "PATH")]
+    pub chain_config: Option<PathBuf>,
+
+    /// Where the blockchain is stored
+    #[clap(long, arg_enum, default_value = "in-memory")]
+    pub storage_backend: StorageBackend,
+
+    /// Journal file the blockchain is stored in by the journal storage backend
+    #[clap(long, value_name = "PATH", default_value = "blockchain.db")]
+    pub storage_path: PathBuf,
+
+    /// Move the main chain blocks this deep below the tip out of the journal storage into an
+    /// append-only block file next to it
+    #[clap(long, value_name = "BLOCKS")]
+    pub cold_block_depth: Option<u32>,
+
+    /// Directory the `chainstate_backup_storage` RPC method writes storage backups to, the
+    /// method is disabled if not set
+    #[clap(long, value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Verify the blockchain stored at `--storage-path` and exit instead of running the node
+    #[clap(long)]
+    pub verify_storage: bool,
+
+    /// Write the main chain blocks stored at `--storage-path` to a bootstrap file of
+    /// concatenated blocks and exit instead of running the node
+    #[clap(long, value_name = "PATH", conflicts_with_all = &["verify-storage", "import-chain"])]
+    pub export_chain: Option<PathBuf>,
+
+    /// Process the blocks of a bootstrap file written by `--export-chain` into the storage and
+    /// exit instead of running the node. The blocks are validated as they would be when
+    /// received from peers, the other subsystems are not started.
+    #[clap(long, value_name = "PATH", conflicts_with = "verify-storage")]
+    pub import_chain: Option<PathBuf>,
+
     /// Address to bind P2P to
     #[clap(long, value_name = "ADDR", default_value = "/ip6/::1/tcp/3031")]
     pub p2p_addr: String,
+
+    /// SOCKS5 proxy, such as Tor, used for outbound P2P connections
+    #[clap(long, value_name = "ADDR")]
+    pub p2p_proxy: Option<SocketAddr>,
+
+    /// Subnet of trusted peers, optionally prefixed with the granted permissions,
+    /// such as `noban,forcerelay@10.0.0.0/8`. Can be given multiple times.
+    #[clap(long, value_name = "[PERMISSIONS@]SUBNET")]
+    pub p2p_whitelist: Vec<WhitelistEntry>,
+
+    /// Address the node is reachable at from other nodes, advertised to peers.
+    /// Can be given multiple times.
+    #[clap(long = "p2p-external-address", value_name = "ADDR")]
+    pub p2p_external_addresses: Vec<String>,
+
+    /// IP version used to connect to peers that are reachable over both IPv4 and IPv6
+    #[clap(long, possible_values = IpPreference::VARIANTS, default_value = "prefer-ipv6")]
+    pub p2p_ip_preference: IpPreference,
+
+    /// User agent sent to peers, defaults to the name of the node software
+    #[clap(long, value_name = "AGENT")]
+    pub p2p_user_agent: Option<String>,
+
+    /// Services advertised to peers, such as `full-blocks,headers`, or `none`
+    #[clap(long, value_name = "SERVICES", default_value = "full-blocks,headers")]
+    pub p2p_services: Services,
+
+    /// Services a peer must advertise to be selected for an automatic outbound connection
+    #[clap(long, value_name = "SERVICES", default_value = "full-blocks,headers")]
+    pub p2p_required_services: Services,
+
+    /// Ask the local gateway to forward the P2P port to the node using UPnP
+    #[clap(long)]
+    pub p2p_upnp: bool,
+
+    /// File the P2P identity key is kept in, so the peer ID stays the same across restarts.
+    /// A new identity is written to the file if it doesn't exist.
+    #[clap(long, value_name = "PATH")]
+    pub p2p_identity_file: Option<PathBuf>,
+
+    /// File containing the password the P2P identity key is encrypted with
+    #[clap(long, value_name = "PATH", requires = "p2p-identity-file")]
+    pub p2p_identity_password_file: Option<PathBuf>,
+
+    /// Wallet file, the wallet is enabled if given.
+    /// A new wallet is written to the file if it doesn't exist.
+    #[clap(long, value_name = "PATH")]
+    pub wallet_file: Option<PathBuf>,
+
+    /// File containing the password the wallet seed is encrypted with
+    #[clap(long, value_name = "PATH", requires = "wallet-file")]
+    pub wallet_password_file: Option<PathBuf>,
+
+    /// Extended public key of the account to create a watch-only wallet with, if the wallet file
+    /// doesn't exist. Its transactions are signed offline by the wallet holding the keys.
+    #[clap(long, value_name = "XPUB", requires = "wallet-file")]
+    pub wallet_watch_only: Option<String>,
 }
 
 impl Options {
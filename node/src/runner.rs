@@ -1,34 +1,180 @@
 //! Node initialisation routine.
 
-use crate::options::Options;
+use crate::{
+    metrics,
+    options::{LogFormat, Options, StorageBackend, WatchdogAction},
+    stratum,
+};
 use chainstate::rpc::ChainstateRpcServer;
-use common::chain::config::ChainType;
-use p2p::rpc::P2pRpcServer;
-use std::sync::Arc;
-
-#[derive(Debug, Ord, PartialOrd, PartialEq, Eq, Clone, Copy, thiserror::Error)]
-enum Error {
-    #[error("Chain type '{0}' not yet supported")]
-    UnsupportedChain(ChainType),
+use common::{chain::config::ChainType, primitives::BlockDistance};
+use p2p::{
+    config::{P2pConfig, P2pIdentity},
+    rpc::P2pRpcServer,
+};
+use std::{path::Path, sync::Arc, time::Duration};
+use subsystem::health::{HealthMonitor, StallAction, Status};
+use wallet::rpc::WalletRpcServer;
+
+/// Initialize logging as configured in the options
+pub fn init_logging(opts: &Options) -> anyhow::Result<()> {
+    let mut config = logging::LogConfig::new()
+        .with_format(match opts.log_format {
+            LogFormat::Text => logging::LogFormat::Text,
+            LogFormat::Json => logging::LogFormat::Json,
+        })
+        .with_rotation(
+            u64::from(opts.log_max_file_size.get()) * 1024 * 1024,
+            opts.log_max_files,
+        );
+    if let Some(filters) = &opts.log_filters {
+        config = config.with_filters(filters.clone());
+    }
+    if let Some(path) = &opts.log_path {
+        config = config.with_file(path.clone());
+    }
+    Ok(logging::init_logging_with_config(&config)?)
+}
+
+/// Verify the blockchain stored at the storage path, reporting the blocks found corrupted
+pub fn verify_storage(opts: &Options) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        opts.storage_path.exists(),
+        "Storage file {} not found",
+        opts.storage_path.display()
+    );
+    let storage = blockchain_storage::Store::open_read_only(&opts.storage_path)?;
+    let report = blockchain_storage::verify::verify_main_chain(&storage)?;
+
+    println!("Checked {} main chain blocks", report.blocks_checked);
+    for (height, block_id, problem) in &report.problems {
+        println!("Block {:?} at height {}: {:?}", block_id, height, problem);
+    }
+    if report.best_block_mismatch {
+        println!("The best block is not the last main chain block");
+    }
+
+    anyhow::ensure!(
+        report.is_ok(),
+        "Storage is corrupted, restore it from a backup or resync the blockchain into an empty storage"
+    );
+    Ok(())
+}
+
+/// Open the storage selected by the options
+fn open_storage(opts: &Options) -> anyhow::Result<blockchain_storage::Store> {
+    Ok(match opts.storage_backend {
+        StorageBackend::InMemory => blockchain_storage::Store::new_empty()?,
+        StorageBackend::Journal => match opts.cold_block_depth {
+            Some(depth) => blockchain_storage::Store::open_with_cold_blocks(
+                &opts.storage_path,
+                BlockDistance::new(depth.into()),
+            )?,
+            None => blockchain_storage::Store::open(&opts.storage_path)?,
+        },
+    })
+}
+
+/// Chain configuration of the network selected by the options
+fn load_chain_config(opts: &Options) -> anyhow::Result<common::chain::ChainConfig> {
+    Ok(match (&opts.chain_config, opts.net) {
+        (Some(path), _) => common::chain::ChainConfig::from_file(path)?,
+        (None, ChainType::Mainnet) => common::chain::config::create_mainnet(),
+        (None, ChainType::Testnet) => common::chain::config::create_testnet(),
+        (None, ChainType::Regtest) => common::chain::config::create_regtest(),
+    })
+}
+
+/// Write the main chain stored at the storage path to a bootstrap file
+///
+/// The storage is opened read-only, so it's left as it is.
+pub fn export_chain(opts: &Options, path: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        opts.storage_backend == StorageBackend::Journal && opts.storage_path.exists(),
+        "Storage file {} not found",
+        opts.storage_path.display()
+    );
+    let chain_config = Arc::new(load_chain_config(opts)?);
+    let storage = blockchain_storage::Store::open_read_only(&opts.storage_path)?;
+    let chainstate = chainstate::make_chainstate(chain_config, storage, None)?;
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let stats = chainstate::bootstrap::export_bootstrap_file(chainstate.as_ref(), &mut file)?;
+    println!("Exported {} blocks to {}", stats.blocks, path.display());
+    Ok(())
+}
+
+/// Process the blocks of a bootstrap file into the storage, without starting the other
+/// subsystems
+pub fn import_chain(opts: &Options, path: &Path) -> anyhow::Result<()> {
+    let chain_config = Arc::new(load_chain_config(opts)?);
+    let mut chainstate = chainstate::make_chainstate(chain_config, open_storage(opts)?, None)?;
+
+    let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let start = std::time::Instant::now();
+    let result = chainstate::bootstrap::import_bootstrap_file(chainstate.as_mut(), &mut file);
+    let elapsed = start.elapsed();
+    println!(
+        "Best block height {} after the import",
+        chainstate.get_best_block_height()?
+    );
+    let stats = result?;
+    println!(
+        "Imported {} blocks in {:.1}s ({:.1} blocks/s), skipped {} known blocks",
+        stats.blocks,
+        elapsed.as_secs_f64(),
+        stats.blocks as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        stats.skipped
+    );
+    Ok(())
 }
 
 /// Initialize the node, giving caller the opportunity to add more subsystems before start.
 pub async fn initialize(opts: Options) -> anyhow::Result<subsystem::Manager> {
     // Initialize storage and chain configuration
-    let storage = blockchain_storage::Store::new_empty()?;
-
-    // Chain configuration
-    let chain_config = match opts.net {
-        ChainType::Mainnet => Arc::new(common::chain::config::create_mainnet()),
-        ChainType::Regtest => Arc::new(common::chain::config::create_regtest()),
-        chain_ty => return Err(Error::UnsupportedChain(chain_ty).into()),
+    let storage = open_storage(&opts)?;
+    let chain_config = Arc::new(load_chain_config(&opts)?);
+    let p2p_identity = match &opts.p2p_identity_file {
+        Some(path) => {
+            let password = read_password_file(opts.p2p_identity_password_file.as_deref())?;
+            Some(P2pIdentity::new(path.clone(), password))
+        }
+        None => None,
     };
+    let default_p2p_config = P2pConfig::default();
+    let p2p_config = Arc::new(P2pConfig {
+        proxy: opts.p2p_proxy,
+        whitelist: opts.p2p_whitelist,
+        external_addresses: opts.p2p_external_addresses,
+        ip_preference: opts.p2p_ip_preference,
+        user_agent: opts.p2p_user_agent.unwrap_or(default_p2p_config.user_agent),
+        services: opts.p2p_services,
+        required_services: opts.p2p_required_services,
+        identity: p2p_identity,
+    });
 
     // INITIALIZE SUBSYSTEMS
 
-    let mut manager = subsystem::Manager::new("mintlayer");
+    let mut manager_config = subsystem::manager::ManagerConfig::named("mintlayer");
+    if let Some(timeout) = opts.watchdog_timeout {
+        manager_config = manager_config.with_watchdog(subsystem::health::WatchdogConfig {
+            stall_timeout: Duration::from_secs(timeout.get().into()),
+            action: match opts.watchdog_action {
+                WatchdogAction::Log => StallAction::Log,
+                WatchdogAction::Shutdown => StallAction::Shutdown,
+                WatchdogAction::Abort => StallAction::Abort,
+            },
+        });
+    }
+    let mut manager = subsystem::Manager::new_with_config(manager_config);
     manager.install_signal_handlers();
 
+    // Metrics are collected before any subsystem is created
+    let p2p_counters = opts.metrics_addr.map(|_| {
+        let counters = Arc::new(p2p::metrics::Counters::new());
+        p2p::metrics::set_metrics(Arc::clone(&counters) as Arc<dyn p2p::metrics::P2pMetrics>);
+        counters
+    });
+
     // Chainstate subsystem
     let chainstate = manager.add_subsystem(
         "chainstate",
@@ -40,6 +186,7 @@ pub async fn initialize(opts: Options) -> anyhow::Result<subsystem::Manager> {
         "p2p",
         p2p::make_p2p::<p2p::net::libp2p::Libp2pService>(
             Arc::clone(&chain_config),
+            p2p_config,
             chainstate.clone(),
             opts.p2p_addr,
         )
@@ -47,20 +194,93 @@ pub async fn initialize(opts: Options) -> anyhow::Result<subsystem::Manager> {
         .expect("The p2p subsystem initialization failed"),
     );
 
-    // RPC subsystem
-    let _rpc = manager.add_subsystem(
-        "rpc",
-        rpc::Builder::new(opts.rpc_addr)
-            .register(chainstate.clone().into_rpc())
-            .register(NodeRpc::new(manager.make_shutdown_trigger()).into_rpc())
-            .register(p2p.clone().into_rpc())
-            .build()
-            .await?,
-    );
+    // Wallet subsystem
+    let wallet = if let Some(path) = &opts.wallet_file {
+        let password = read_password_file(opts.wallet_password_file.as_deref())?;
+        let mut config = wallet::WalletConfig::new(path.clone(), password);
+        if let Some(xpub) = &opts.wallet_watch_only {
+            let account_public_key = xpub
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid watch-only account key: {:?}", e))?;
+            config = config.with_watch_only(account_public_key);
+        }
+        let wallet = wallet::make_wallet(Arc::clone(&chain_config), chainstate.clone(), &config)?;
+        Some(
+            manager.add_raw_subsystem("wallet", move |call_rq, shutdown_rq| {
+                wallet::run(wallet, call_rq, shutdown_rq)
+            }),
+        )
+    } else {
+        None
+    };
+
+    // Port mapping subsystem
+    if opts.p2p_upnp {
+        let port = chain_config.p2p_port();
+        manager.add_raw_subsystem("portmap", move |call_rq, shutdown_rq| {
+            p2p::portmap::run(port, call_rq, shutdown_rq)
+        });
+    }
+
+    // RPC subsystem, the wallet methods are served along with the others when the wallet is enabled
+    let mut rpc_builder = rpc::Builder::new(opts.rpc_addr)
+        .with_ws_address(opts.rpc_ws_addr)
+        .with_rest_address(opts.rpc_rest_addr)
+        .with_batch_requests(!opts.rpc_no_batch)
+        .with_max_expensive_calls(opts.rpc_max_expensive_calls)
+        .register(
+            chainstate::rpc::ChainstateRpc::new(chainstate.clone(), opts.backup_dir.clone())
+                .into_rpc(),
+        )
+        .register(
+            NodeRpc::new(manager.make_shutdown_trigger(), manager.health_monitor()).into_rpc(),
+        )
+        .register(p2p.clone().into_rpc());
+    if let Some(wallet) = &wallet {
+        rpc_builder = rpc_builder.register(wallet.clone().into_rpc());
+    }
+    let rpc = manager.add_subsystem("rpc", rpc_builder.build().await?);
+
+    // Stratum mining server
+    if let Some(addr) = opts.stratum_addr {
+        let listener = stratum::bind(&addr).await?;
+        let chainstate = chainstate.clone();
+        let share_difficulty = opts.stratum_share_difficulty;
+        manager.add_raw_subsystem("stratum", move |call_rq, shutdown_rq| {
+            stratum::run(listener, chainstate, share_difficulty, call_rq, shutdown_rq)
+        });
+    }
+
+    // Metrics subsystem
+    if let (Some(addr), Some(p2p_counters)) = (opts.metrics_addr, p2p_counters) {
+        let server = metrics::bind(&addr)?;
+        let sources = metrics::Sources {
+            chainstate,
+            p2p,
+            p2p_counters,
+            rpc,
+        };
+        manager.add_raw_subsystem("metrics", move |call_rq, shutdown_rq| {
+            metrics::run(server, sources, call_rq, shutdown_rq)
+        });
+    }
 
     Ok(manager)
 }
 
+/// Read a password from the file, without the line break at its end
+fn read_password_file(path: Option<&Path>) -> anyhow::Result<Option<String>> {
+    match path {
+        Some(path) => {
+            let password = std::fs::read_to_string(path)?;
+            Ok(Some(
+                password.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+            ))
+        }
+        None => Ok(None),
+    }
+}
+
 /// Initialize and run the node
 pub async fn run(opts: Options) -> anyhow::Result<()> {
     let manager = initialize(opts).await?;
@@ -78,15 +298,47 @@ trait NodeRpc {
     /// Get node software version
     #[method(name = "version")]
     fn version(&self) -> rpc::Result<String>;
+
+    /// Get node information, including the health of the subsystems
+    #[method(name = "info")]
+    fn info(&self) -> rpc::Result<NodeInfo>;
+
+    /// Replace the log levels per target, given like `info,p2p=debug,chainstate=trace`
+    #[method(name = "set_log_level")]
+    fn set_log_level(&self, filters: String) -> rpc::Result<()>;
+}
+
+/// Node information returned by the `info` method
+#[derive(Debug, serde::Serialize)]
+pub struct NodeInfo {
+    version: &'static str,
+    /// Subsystems in the order they were started in
+    subsystems: Vec<SubsystemInfo>,
+}
+
+/// Subsystem health, part of [`NodeInfo`]
+#[derive(Debug, serde::Serialize)]
+struct SubsystemInfo {
+    name: &'static str,
+    /// One of `running`, `stalled` and `terminated`
+    status: &'static str,
+    /// Number of calls waiting in the subsystem queue
+    pending_calls: usize,
+    /// Seconds since the subsystem last made progress or was idle
+    seconds_since_progress: u64,
 }
 
 struct NodeRpc {
     shutdown_trigger: subsystem::manager::ShutdownTrigger,
+    health: HealthMonitor,
 }
 
 impl NodeRpc {
-    fn new(shutdown_trigger: subsystem::manager::ShutdownTrigger) -> Self {
-        Self { shutdown_trigger }
+    fn new(shutdown_trigger: subsystem::manager::ShutdownTrigger, health: HealthMonitor) -> Self {
+        Self {
+            shutdown_trigger,
+            health,
+        }
     }
 }
 
@@ -99,4 +351,32 @@ impl NodeRpcServer for NodeRpc {
     fn version(&self) -> rpc::Result<String> {
         Ok(env!("CARGO_PKG_VERSION").into())
     }
+
+    fn info(&self) -> rpc::Result<NodeInfo> {
+        let subsystems = self
+            .health
+            .report()
+            .into_iter()
+            .map(|health| SubsystemInfo {
+                name: health.name,
+                status: match health.status {
+                    Status::Running => "running",
+                    Status::Stalled => "stalled",
+                    Status::Terminated => "terminated",
+                },
+                pending_calls: health.pending_calls,
+                seconds_since_progress: health.since_progress.as_secs(),
+            })
+            .collect();
+        Ok(NodeInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            subsystems,
+        })
+    }
+
+    fn set_log_level(&self, filters: String) -> rpc::Result<()> {
+        logging::set_log_filters(&filters).map_err(rpc::invalid_params)?;
+        logging::log::info!("Log filters set to `{}`", filters);
+        Ok(())
+    }
 }
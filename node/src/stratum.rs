@@ -0,0 +1,611 @@
+//! Stratum-style mining server
+//!
+//! When enabled, external miners connect over TCP and exchange newline-delimited JSON-RPC
+//! messages following the Stratum conventions, as far as the block format allows:
+//!
+//! - `mining.subscribe` starts the session. There is no extranonce, the nonce of a block is wide
+//!   enough for the miners to split the search space among themselves.
+//! - `mining.authorize` takes the address the block rewards are paid to as the worker name.
+//!   The server then sends `mining.set_target` with the share target and `mining.notify` with a
+//!   job, followed by a new job on each new tip.
+//! - `mining.submit` takes the worker name, the job ID and the hex-encoded nonce.
+//!
+//! The parameters of `mining.notify` are `[job_id, header, nonce_offset, height, clean_jobs]`,
+//! where `header` is the hex-encoded SCALE encoding of the block header with a zero nonce and
+//! `nonce_offset` the position of the little-endian 16-byte nonce in it. The block ID is the hash
+//! of the header with the nonce, a share is valid if it doesn't exceed the share target. Shares
+//! that also meet the target of the block are submitted to the chainstate as new blocks.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    num::NonZeroU32,
+    sync::Arc,
+};
+
+use chainstate::{BlockSource, ChainstateError, ChainstateEvent, ChainstateHandle};
+use common::{
+    address::Address,
+    chain::{
+        block::{consensus_data::PoWData, Block, ConsensusData},
+        ChainConfig, Destination,
+    },
+    primitives::Idable,
+    Uint256,
+};
+use logging::log;
+use serde_json::{json, Value};
+use serialization::Encode;
+use subsystem::subsystem::{CallRequest, ShutdownRequest};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch},
+};
+
+/// Longest message accepted from a miner, the connection is closed if a line is longer
+const MAX_LINE_LENGTH: usize = 16 * 1024;
+
+/// Error codes of the rejected requests, following the Stratum conventions
+mod error_code {
+    pub const OTHER: i64 = 20;
+    pub const JOB_NOT_FOUND: i64 = 21;
+    pub const DUPLICATE_SHARE: i64 = 22;
+    pub const LOW_DIFFICULTY_SHARE: i64 = 23;
+    pub const UNAUTHORIZED_WORKER: i64 = 24;
+    pub const NOT_SUBSCRIBED: i64 = 25;
+}
+
+/// Request from a miner
+#[derive(serde::Deserialize)]
+struct Request {
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+/// Error code and message of a rejected request
+type RequestError = (i64, String);
+
+/// Block template handed out to the miner
+struct Job {
+    block: Block,
+    pow_data: PoWData,
+    block_target: Uint256,
+    share_target: Uint256,
+    /// Nonces of the shares accepted so far
+    nonces: BTreeSet<u128>,
+}
+
+/// State of the connection with a miner
+struct Session {
+    id: u64,
+    chainstate: ChainstateHandle,
+    chain_config: Arc<ChainConfig>,
+    share_difficulty: NonZeroU32,
+    subscribed: bool,
+    /// Name of the authorized worker and the destination of its block rewards
+    worker: Option<(String, Destination)>,
+    /// Jobs on top of the current tip, by ID
+    jobs: BTreeMap<String, Job>,
+    next_job_id: u64,
+    /// Share target last sent to the miner
+    share_target: Option<Uint256>,
+    /// Messages to be sent to the miner
+    outbox: Vec<Value>,
+}
+
+impl Session {
+    fn new(
+        id: u64,
+        chainstate: ChainstateHandle,
+        chain_config: Arc<ChainConfig>,
+        share_difficulty: NonZeroU32,
+    ) -> Self {
+        Self {
+            id,
+            chainstate,
+            chain_config,
+            share_difficulty,
+            subscribed: false,
+            worker: None,
+            jobs: BTreeMap::new(),
+            next_job_id: 0,
+            share_target: None,
+            outbox: Vec::new(),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.outbox
+            .push(json!({ "id": Value::Null, "method": method, "params": params }));
+    }
+
+    async fn handle_line(&mut self, line: &str) {
+        let request = match serde_json::from_str::<Request>(line) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = (error_code::OTHER, format!("Invalid request: {}", e));
+                self.outbox.push(response(Value::Null, Err(error)));
+                return;
+            }
+        };
+        let result = match request.method.as_str() {
+            "mining.subscribe" => Ok(self.subscribe()),
+            "mining.authorize" => self.authorize(&request.params),
+            "mining.submit" => self.submit(&request.params).await,
+            method => Err((error_code::OTHER, format!("Unknown method {}", method))),
+        };
+        let authorized = request.method == "mining.authorize" && result.is_ok();
+        self.outbox.push(response(request.id, result));
+        // The first job follows the authorization
+        if authorized {
+            self.new_job().await;
+        }
+    }
+
+    fn subscribe(&mut self) -> Value {
+        self.subscribed = true;
+        let session_id = format!("{:08x}", self.id);
+        json!([[["mining.set_target", session_id], ["mining.notify", session_id]], "", 0])
+    }
+
+    fn authorize(&mut self, params: &[Value]) -> Result<Value, RequestError> {
+        if !self.subscribed {
+            return Err((error_code::NOT_SUBSCRIBED, "Not subscribed".to_owned()));
+        }
+        let worker = str_param(params, 0)?;
+        let destination = Address::from_str(&self.chain_config, worker)
+            .and_then(|address| address.destination(&self.chain_config))
+            .map_err(|_| {
+                let message = format!("The worker {} is not a valid address", worker);
+                (error_code::UNAUTHORIZED_WORKER, message)
+            })?;
+        log::info!("Stratum session {:08x} authorized as {}", self.id, worker);
+        self.worker = Some((worker.to_owned(), destination));
+        Ok(Value::Bool(true))
+    }
+
+    async fn submit(&mut self, params: &[Value]) -> Result<Value, RequestError> {
+        let worker = str_param(params, 0)?;
+        if self.worker.as_ref().map_or(true, |(name, _)| name != worker) {
+            let message = format!("Unauthorized worker {}", worker);
+            return Err((error_code::UNAUTHORIZED_WORKER, message));
+        }
+        let job_id = str_param(params, 1)?;
+        let nonce = u128::from_str_radix(str_param(params, 2)?, 16)
+            .map_err(|e| (error_code::OTHER, format!("Invalid nonce: {}", e)))?;
+        let job = self
+            .jobs
+            .get_mut(job_id)
+            .ok_or_else(|| (error_code::JOB_NOT_FOUND, "Job not found".to_owned()))?;
+
+        let mut pow_data = job.pow_data.clone();
+        pow_data.update_nonce(nonce);
+        let mut block = job.block.clone();
+        block.update_consensus_data(ConsensusData::PoW(pow_data));
+        let block_id = block.get_id();
+        let hash: Uint256 = block_id.get().into();
+        if hash > job.share_target {
+            return Err((
+                error_code::LOW_DIFFICULTY_SHARE,
+                "Low difficulty share".to_owned(),
+            ));
+        }
+        if !job.nonces.insert(nonce) {
+            return Err((error_code::DUPLICATE_SHARE, "Duplicate share".to_owned()));
+        }
+        if hash > job.block_target {
+            return Ok(Value::Bool(true));
+        }
+
+        let res = self
+            .chainstate
+            .call_mut(move |this| this.process_block(block, BlockSource::Local))
+            .await;
+        match res {
+            Ok(Ok(())) => {
+                log::info!("Stratum worker {} found block {}", worker, block_id);
+                Ok(Value::Bool(true))
+            }
+            Ok(Err(e)) => Err((error_code::OTHER, format!("Block rejected: {}", e))),
+            Err(e) => Err((error_code::OTHER, e.to_string())),
+        }
+    }
+
+    /// Send a job on top of the current tip to the authorized miner, replacing the previous ones
+    async fn new_job(&mut self) {
+        let destination = match &self.worker {
+            Some((_, destination)) => destination.clone(),
+            None => return,
+        };
+        let res = self
+            .chainstate
+            .call(move |this| -> Result<_, ChainstateError> {
+                Ok((
+                    this.get_block_template(destination)?,
+                    this.get_best_block_height()?,
+                ))
+            })
+            .await;
+        let res = res.map_err(|e| e.to_string()).and_then(|res| res.map_err(|e| e.to_string()));
+        let (block, best_height) = match res {
+            Ok(template) => template,
+            Err(e) => {
+                log::error!("Failed to get a block template: {}", e);
+                return;
+            }
+        };
+        let pow_data = match block.consensus_data() {
+            ConsensusData::PoW(data) => data.clone(),
+            ConsensusData::None => {
+                log::warn!("The next block needs no proof of work, no job is sent");
+                return;
+            }
+        };
+        let block_target = match Uint256::try_from(pow_data.bits()) {
+            Ok(target) => target,
+            Err(e) => {
+                log::error!("Invalid target {:?}: {:?}", pow_data.bits(), e);
+                return;
+            }
+        };
+        let share_target = share_target(
+            self.chain_config.get_proof_of_work_config().limit(),
+            self.share_difficulty,
+            block_target,
+        );
+
+        if self.share_target != Some(share_target) {
+            self.share_target = Some(share_target);
+            self.notify("mining.set_target", json!([hex_target(share_target)]));
+        }
+        let job_id = format!("{:x}", self.next_job_id);
+        self.next_job_id += 1;
+        let header = block.header().encode();
+        let params = json!([
+            job_id,
+            hex::encode(&header),
+            nonce_offset(&block, &pow_data),
+            best_height.next_height(),
+            true,
+        ]);
+        self.jobs.clear();
+        self.jobs.insert(
+            job_id,
+            Job {
+                block,
+                pow_data,
+                block_target,
+                share_target,
+                nonces: BTreeSet::new(),
+            },
+        );
+        self.notify("mining.notify", params);
+    }
+}
+
+/// Target of the shares, `share_difficulty` times easier than the lowest possible difficulty
+/// but never harder than the target of the block
+fn share_target(limit: Uint256, share_difficulty: NonZeroU32, block_target: Uint256) -> Uint256 {
+    let divisor = Uint256::from_u64(share_difficulty.get().into()).expect("u64 fits");
+    std::cmp::max(limit / divisor, block_target)
+}
+
+/// Target as the hex-encoded big-endian 256-bit number
+fn hex_target(target: Uint256) -> String {
+    hex::encode(target.to_be_bytes())
+}
+
+/// Position of the nonce in the encoding of the header, found by changing it
+fn nonce_offset(block: &Block, pow_data: &PoWData) -> usize {
+    let mut changed = pow_data.clone();
+    changed.update_nonce(pow_data.nonce() ^ u128::MAX);
+    let mut changed_block = block.clone();
+    changed_block.update_consensus_data(ConsensusData::PoW(changed));
+    let header = block.header().encode();
+    let changed_header = changed_block.header().encode();
+    header
+        .iter()
+        .zip(changed_header.iter())
+        .position(|(a, b)| a != b)
+        .expect("The nonce is part of the header")
+}
+
+fn str_param(params: &[Value], index: usize) -> Result<&str, RequestError> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .ok_or_else(|| (error_code::OTHER, format!("Missing parameter {}", index)))
+}
+
+fn response(id: Value, result: Result<Value, RequestError>) -> Value {
+    match result {
+        Ok(result) => json!({ "id": id, "result": result, "error": Value::Null }),
+        Err((code, message)) => {
+            json!({ "id": id, "result": Value::Null, "error": [code, message, Value::Null] })
+        }
+    }
+}
+
+/// Serve a miner until it disconnects or the server shuts down
+async fn serve_connection(
+    stream: TcpStream,
+    mut session: Session,
+    mut tip_rx: watch::Receiver<()>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        let mut limited = (&mut reader).take((MAX_LINE_LENGTH - line.len()) as u64);
+        tokio::select! {
+            res = limited.read_until(b'\n', &mut line) => {
+                match res {
+                    Ok(_) if line.ends_with(b"\n") => {
+                        match std::str::from_utf8(&line) {
+                            Ok(text) => session.handle_line(text.trim()).await,
+                            Err(_) => break,
+                        }
+                        line.clear();
+                    }
+                    // The line is too long or the miner disconnected
+                    Ok(_) if line.len() >= MAX_LINE_LENGTH => break,
+                    Ok(0) => break,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        log::debug!("Stratum session {:08x} read error: {}", session.id, e);
+                        break;
+                    }
+                }
+            }
+            res = tip_rx.changed() => {
+                if res.is_err() {
+                    break;
+                }
+                session.new_job().await;
+            }
+        }
+
+        for message in session.outbox.drain(..) {
+            let mut message = message.to_string();
+            message.push('\n');
+            if let Err(e) = writer.write_all(message.as_bytes()).await {
+                log::debug!("Stratum session {:08x} write error: {}", session.id, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Bind the stratum server to `address`
+///
+/// Binding is done before the server is run so that an unusable address is reported
+/// when the node is initialized.
+pub async fn bind(address: &SocketAddr) -> anyhow::Result<TcpListener> {
+    Ok(TcpListener::bind(address).await?)
+}
+
+/// Run the stratum subsystem, serving miners until the node shuts down
+pub async fn run(
+    listener: TcpListener,
+    chainstate: ChainstateHandle,
+    share_difficulty: NonZeroU32,
+    mut call_rq: CallRequest<()>,
+    mut shutdown_rq: ShutdownRequest,
+) {
+    let chain_config = match chainstate.call(|this| this.get_chain_config()).await {
+        Ok(chain_config) => chain_config,
+        Err(e) => {
+            log::error!("Stratum server failed to start: {}", e);
+            return;
+        }
+    };
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let subscribe_func = Arc::new(move |event: ChainstateEvent| match event {
+        ChainstateEvent::NewTip(_, _) => {
+            // The receiver is only dropped on shutdown
+            let _ = event_tx.send(());
+        }
+    });
+    if chainstate
+        .call_mut(|this| this.subscribe_to_events(subscribe_func))
+        .await
+        .is_err()
+    {
+        log::error!("Stratum server failed to subscribe to chainstate events");
+        return;
+    }
+
+    // The connections are closed when the sender is dropped on shutdown
+    let (tip_tx, tip_rx) = watch::channel(());
+    let mut next_session_id = 0;
+    loop {
+        tokio::select! {
+            res = listener.accept() => match res {
+                Ok((stream, addr)) => {
+                    log::debug!("Stratum session {:08x} from {}", next_session_id, addr);
+                    let session = Session::new(
+                        next_session_id,
+                        chainstate.clone(),
+                        Arc::clone(&chain_config),
+                        share_difficulty,
+                    );
+                    next_session_id += 1;
+                    tokio::spawn(serve_connection(stream, session, tip_rx.clone()));
+                }
+                Err(e) => log::warn!("Stratum server failed to accept a connection: {}", e),
+            },
+            Some(()) = event_rx.recv() => {
+                let _ = tip_tx.send(());
+            }
+            () = shutdown_rq.recv() => break,
+            call = call_rq.recv() => call(&mut ()).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{chain::block::BlockHeader, primitives::BlockHeight};
+    use serialization::DecodeAll;
+    use tokio::{
+        io::Lines,
+        net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+    };
+
+    struct Miner {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+        next_id: u64,
+    }
+
+    impl Miner {
+        async fn connect(addr: SocketAddr) -> Self {
+            let (reader, writer) = TcpStream::connect(addr).await.unwrap().into_split();
+            Self {
+                lines: BufReader::new(reader).lines(),
+                writer,
+                next_id: 0,
+            }
+        }
+
+        async fn recv(&mut self) -> Value {
+            let line = self.lines.next_line().await.unwrap().unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        /// Send a request and get its response
+        async fn call(&mut self, method: &str, params: Value) -> Value {
+            self.next_id += 1;
+            let request = json!({ "id": self.next_id, "method": method, "params": params });
+            self.writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            let response = self.recv().await;
+            assert_eq!(response["id"], Value::from(self.next_id));
+            response
+        }
+    }
+
+    fn error_code(response: &Value) -> Value {
+        response["error"][0].clone()
+    }
+
+    /// First nonce for which the block ID meets the target or not, as requested
+    fn find_nonce(job: &Value, target: &str, meets_target: bool) -> String {
+        let mut header = hex::decode(job["params"][1].as_str().unwrap()).unwrap();
+        let offset = job["params"][2].as_u64().unwrap() as usize;
+        let target = Uint256::from_be_slice(&hex::decode(target).unwrap()).unwrap();
+        (0u128..)
+            .find(|nonce| {
+                header[offset..offset + 16].copy_from_slice(&nonce.to_le_bytes());
+                let header = BlockHeader::decode_all(&mut &header[..]).unwrap();
+                (Uint256::from(header.block_id().get()) <= target) == meets_target
+            })
+            .map(|nonce| format!("{:x}", nonce))
+            .unwrap()
+    }
+
+    #[test]
+    fn share_targets() {
+        let limit = Uint256::from_u64(1 << 40).unwrap();
+        let block_target = Uint256::from_u64(1 << 20).unwrap();
+        let difficulty = |d| NonZeroU32::new(d).unwrap();
+        assert_eq!(share_target(limit, difficulty(1), block_target), limit);
+        assert_eq!(
+            share_target(limit, difficulty(1 << 10), block_target),
+            Uint256::from_u64(1 << 30).unwrap()
+        );
+        assert_eq!(
+            share_target(limit, difficulty(1 << 30), block_target),
+            block_target
+        );
+    }
+
+    #[tokio::test]
+    async fn mining_session() {
+        let storage = blockchain_storage::Store::new_empty().unwrap();
+        let chain_config = Arc::new(common::chain::config::create_regtest());
+        let mut man = subsystem::Manager::new("stratumtest");
+        let chainstate = man.add_subsystem(
+            "chainstate",
+            chainstate::make_chainstate(Arc::clone(&chain_config), storage, None).unwrap(),
+        );
+        let listener = bind(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _ = man.add_raw_subsystem("stratum", {
+            let chainstate = chainstate.clone();
+            move |call_rq, shutdown_rq| {
+                run(
+                    listener,
+                    chainstate,
+                    NonZeroU32::new(1).unwrap(),
+                    call_rq,
+                    shutdown_rq,
+                )
+            }
+        });
+
+        let _ = man.add_raw_subsystem(
+            "test",
+            move |_: subsystem::subsystem::CallRequest<()>, _| async move {
+                let address =
+                    Address::from_destination(&chain_config, &Destination::AnyoneCanSpend).unwrap();
+                let mut miner = Miner::connect(addr).await;
+
+                let res = miner.call("mining.authorize", json!([address.get(), ""])).await;
+                assert_eq!(error_code(&res), Value::from(error_code::NOT_SUBSCRIBED));
+                let res = miner.call("mining.subscribe", json!([])).await;
+                assert_eq!(res["error"], Value::Null);
+                let res = miner.call("mining.authorize", json!(["invalid", ""])).await;
+                assert_eq!(
+                    error_code(&res),
+                    Value::from(error_code::UNAUTHORIZED_WORKER)
+                );
+                let res = miner.call("mining.authorize", json!([address.get(), ""])).await;
+                assert_eq!(res["result"], Value::Bool(true));
+
+                let set_target = miner.recv().await;
+                assert_eq!(set_target["method"], Value::from("mining.set_target"));
+                let target = set_target["params"][0].as_str().unwrap().to_owned();
+                let job = miner.recv().await;
+                assert_eq!(job["method"], Value::from("mining.notify"));
+                assert_eq!(job["params"][3], Value::from(1));
+                assert_eq!(job["params"][4], Value::Bool(true));
+                let job_id = job["params"][0].clone();
+
+                let nonce = find_nonce(&job, &target, false);
+                let res = miner.call("mining.submit", json!([address.get(), job_id, nonce])).await;
+                assert_eq!(
+                    error_code(&res),
+                    Value::from(error_code::LOW_DIFFICULTY_SHARE)
+                );
+                let res = miner.call("mining.submit", json!(["other", job_id, nonce])).await;
+                assert_eq!(
+                    error_code(&res),
+                    Value::from(error_code::UNAUTHORIZED_WORKER)
+                );
+
+                // The share target is the block target on regtest, so the share is a new block
+                let nonce = find_nonce(&job, &target, true);
+                let res = miner.call("mining.submit", json!([address.get(), job_id, nonce])).await;
+                assert_eq!(res["result"], Value::Bool(true));
+                let height = chainstate.call(|this| this.get_best_block_height()).await;
+                assert_eq!(height.unwrap().unwrap(), BlockHeight::new(1));
+
+                // The new tip replaces the job
+                let job = miner.recv().await;
+                assert_eq!(job["method"], Value::from("mining.notify"));
+                assert_eq!(job["params"][3], Value::from(2));
+                let res = miner.call("mining.submit", json!([address.get(), job_id, nonce])).await;
+                assert_eq!(error_code(&res), Value::from(error_code::JOB_NOT_FOUND));
+
+                // A line longer than the limit closes the connection
+                miner.writer.write_all(&[b' '; MAX_LINE_LENGTH]).await.unwrap();
+                assert!(miner.lines.next_line().await.unwrap().is_none());
+            },
+        );
+        man.main().await;
+    }
+}
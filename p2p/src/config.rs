@@ -0,0 +1,133 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Settings of the P2P subsystem chosen by the node operator
+//!
+//! Unlike the chain configuration, these are local to a node and don't have to agree between the
+//! nodes of a network.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use common::chain::{services::Services, whitelist::WhitelistEntry};
+
+/// User agent of this implementation of the node software
+const DEFAULT_USER_AGENT: &str = "mintlayer-core";
+
+/// Nodes store and serve the full chain unless configured otherwise
+fn default_services() -> Services {
+    Services::FULL_BLOCKS | Services::HEADERS
+}
+
+/// IP version used to connect to peers that are reachable over both
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    strum::Display,
+    strum::EnumVariantNames,
+    strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum IpPreference {
+    /// Either version, chosen at random
+    Any,
+
+    /// IPv4, unless the peer only has IPv6 addresses
+    PreferIpv4,
+
+    /// IPv6, unless the peer only has IPv4 addresses
+    PreferIpv6,
+}
+
+/// File the P2P identity key of the node is kept in, created on the first start
+///
+/// The key is encrypted with the password if one is given.
+#[derive(Clone, PartialEq, Eq)]
+pub struct P2pIdentity {
+    path: PathBuf,
+    password: Option<String>,
+}
+
+impl P2pIdentity {
+    pub fn new(path: PathBuf, password: Option<String>) -> Self {
+        Self { path, password }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+}
+
+/// The password is not printed to keep it out of logs
+impl std::fmt::Debug for P2pIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("P2pIdentity")
+            .field("path", &self.path)
+            .field("encrypted", &self.password.is_some())
+            .finish()
+    }
+}
+
+/// Settings the node passes to the P2P subsystem on start
+#[derive(Debug, Clone)]
+pub struct P2pConfig {
+    /// SOCKS5 proxy, such as Tor, that outbound connections are made through
+    pub proxy: Option<SocketAddr>,
+
+    /// Subnets of trusted peers and the permissions granted to them
+    pub whitelist: Vec<WhitelistEntry>,
+
+    /// Addresses the local node is reachable at, advertised in addition to the observed ones
+    pub external_addresses: Vec<String>,
+
+    /// IP version used to connect to peers that are reachable over both
+    pub ip_preference: IpPreference,
+
+    /// User agent sent to peers in the handshake
+    pub user_agent: String,
+
+    /// Services advertised to peers in the handshake
+    pub services: Services,
+
+    /// Services a peer must provide to be selected for an automatic outbound connection
+    pub required_services: Services,
+
+    /// File of the P2P identity key, a new identity is generated on every start if not set
+    pub identity: Option<P2pIdentity>,
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            whitelist: Vec::new(),
+            external_addresses: Vec::new(),
+            ip_preference: IpPreference::PreferIpv6,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            services: default_services(),
+            required_services: default_services(),
+            identity: None,
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Network crawler
+//!
+//! The crawler walks the network by connecting to the nodes it knows about one at a time,
+//! asking each of them for the addresses of other nodes and disconnecting again. A node is
+//! good if the last probe succeeded: the node accepted the connection, is on the same network,
+//! passes the checks [`PeerManager`](crate::swarm::PeerManager) applies to its peers and
+//! provides the services required of outbound peers.
+//!
+//! Good nodes are probed again every [`CrawlerConfig::recrawl_interval`], the nodes that
+//! couldn't be probed are retried with the backoff used for failed dials.
+
+use crate::{
+    config::P2pConfig,
+    error::{self, P2pError, ProtocolError},
+    message::{Message, MessageType, SyncingMessage, SyncingRequest, SyncingResponse},
+    net::{
+        ConnectivityEvent, ConnectivityService, NetworkingService, PubSubService, SyncingEvent,
+        SyncingService,
+    },
+    swarm::{self, dial},
+};
+use common::{
+    chain::{services::Services, ChainConfig},
+    primitives::version::SemVer,
+};
+use logging::log;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Time between two probes of a good node
+pub const DEFAULT_RECRAWL_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Time a node has to answer the address request
+pub const DEFAULT_ADDR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time the crawler waits for events between two crawls
+const IDLE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct CrawlerConfig {
+    /// Time between two probes of a good node
+    pub recrawl_interval: Duration,
+
+    /// Time a node has to answer the address request
+    pub addr_timeout: Duration,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            recrawl_interval: DEFAULT_RECRAWL_INTERVAL,
+            addr_timeout: DEFAULT_ADDR_TIMEOUT,
+        }
+    }
+}
+
+/// What the crawler knows about a node
+#[derive(Debug, Clone, Default)]
+pub struct NodeInfo {
+    /// Software version reported in the last successful probe
+    pub version: Option<SemVer>,
+
+    /// User agent reported in the last successful probe
+    pub agent: Option<String>,
+
+    /// Services advertised in the last successful probe
+    pub services: Services,
+
+    /// Whether the last probe succeeded
+    pub good: bool,
+
+    /// Number of probes that failed since the last successful one
+    pub failures: u32,
+
+    /// Time of the last successful probe
+    pub last_success: Option<Instant>,
+
+    /// Time the node is probed next, `None` if it hasn't been probed yet
+    next_probe: Option<Instant>,
+}
+
+pub struct Crawler<T: NetworkingService> {
+    /// Chain config
+    config: Arc<ChainConfig>,
+
+    /// P2P config
+    p2p_config: Arc<P2pConfig>,
+
+    /// Crawler config
+    crawler_config: CrawlerConfig,
+
+    /// Handle for opening and closing the connections
+    conn: T::ConnectivityHandle,
+
+    /// Handle for the pubsub messages, drained without being processed
+    pubsub: T::PubSubHandle,
+
+    /// Handle for requesting the addresses
+    sync: T::SyncingHandle,
+
+    /// Nodes the crawler knows about
+    nodes: HashMap<T::Address, NodeInfo>,
+}
+
+impl<T> Crawler<T>
+where
+    T: NetworkingService,
+    T::ConnectivityHandle: ConnectivityService<T>,
+    T::PubSubHandle: PubSubService<T>,
+    T::SyncingHandle: SyncingService<T>,
+    <T as NetworkingService>::Address: FromStr,
+    <<T as NetworkingService>::Address as FromStr>::Err: Debug,
+{
+    /// Start the networking backend and create a crawler over it
+    ///
+    /// The crawler starts with the fixed seeds of the chain config.
+    pub async fn start(
+        config: Arc<ChainConfig>,
+        p2p_config: Arc<P2pConfig>,
+        crawler_config: CrawlerConfig,
+        bind_addr: T::Address,
+        timeout: Duration,
+    ) -> error::Result<Self> {
+        let handles = T::start(
+            bind_addr,
+            &[],
+            &[],
+            Arc::clone(&config),
+            Arc::clone(&p2p_config),
+            timeout,
+        )
+        .await?;
+        Ok(Self::new(config, p2p_config, crawler_config, handles))
+    }
+
+    /// Create a crawler over the handles of a started networking backend
+    pub fn new(
+        config: Arc<ChainConfig>,
+        p2p_config: Arc<P2pConfig>,
+        crawler_config: CrawlerConfig,
+        (conn, pubsub, sync): (T::ConnectivityHandle, T::PubSubHandle, T::SyncingHandle),
+    ) -> Self {
+        let mut crawler = Self {
+            config,
+            p2p_config,
+            crawler_config,
+            conn,
+            pubsub,
+            sync,
+            nodes: HashMap::new(),
+        };
+        for seed in crawler.config.fixed_seeds().to_vec() {
+            crawler.add_address_str(&seed);
+        }
+        crawler
+    }
+
+    /// Add an address to be probed, returns `false` if it was already known
+    pub fn add_address(&mut self, addr: T::Address) -> bool {
+        if self.nodes.contains_key(&addr) || addr == *self.conn.local_addr() {
+            return false;
+        }
+        self.nodes.insert(addr, NodeInfo::default());
+        true
+    }
+
+    fn add_address_str(&mut self, addr: &str) -> bool {
+        match addr.parse::<T::Address>() {
+            Ok(addr) => self.add_address(addr),
+            Err(err) => {
+                log::debug!("ignore invalid address {}: {:?}", addr, err);
+                false
+            }
+        }
+    }
+
+    /// Nodes the crawler knows about
+    pub fn nodes(&self) -> impl Iterator<Item = (&T::Address, &NodeInfo)> {
+        self.nodes.iter()
+    }
+
+    /// Addresses of the nodes whose last probe succeeded
+    pub fn good_addresses(&self) -> Vec<T::Address> {
+        self.nodes
+            .iter()
+            .filter(|(_, info)| info.good)
+            .map(|(addr, _)| addr.clone())
+            .collect()
+    }
+
+    /// Probe the nodes that are due, returns the number of probed nodes
+    ///
+    /// The addresses learned from the probed nodes are probed in the same crawl.
+    pub async fn crawl(&mut self) -> error::Result<usize> {
+        let mut probed = 0;
+        loop {
+            let now = Instant::now();
+            let due: Vec<T::Address> = self
+                .nodes
+                .iter()
+                .filter(|(_, info)| info.next_probe.map_or(true, |next| next <= now))
+                .map(|(addr, _)| addr.clone())
+                .collect();
+            if due.is_empty() {
+                return Ok(probed);
+            }
+
+            for addr in due {
+                self.probe(addr).await?;
+                probed += 1;
+            }
+        }
+    }
+
+    /// Crawl the network until an error occurs, calling `on_crawled` after each crawl
+    pub async fn run(&mut self, mut on_crawled: impl FnMut(&Self)) -> error::Result<()> {
+        loop {
+            let probed = self.crawl().await?;
+            log::info!(
+                "crawled {} nodes, {} of {} known nodes are good",
+                probed,
+                self.nodes.values().filter(|info| info.good).count(),
+                self.nodes.len()
+            );
+            on_crawled(self);
+
+            let idle = tokio::time::sleep(IDLE_INTERVAL);
+            tokio::pin!(idle);
+            loop {
+                tokio::select! {
+                    _ = &mut idle => break,
+                    event = self.conn.poll_next() => self.on_connectivity_event(event?).await?,
+                    event = self.pubsub.poll_next() => {
+                        event?;
+                    }
+                    event = self.sync.poll_next() => {
+                        event?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Probe a node and record the result
+    async fn probe(&mut self, addr: T::Address) -> error::Result<()> {
+        let result = self.query(&addr).await;
+        let now = Instant::now();
+        let info = self.nodes.get_mut(&addr).expect("probed node to be known");
+
+        let addrs = match result {
+            Ok((version, agent, services, addrs)) => {
+                log::debug!("node {:?} is good, version {:?}", addr, version);
+                *info = NodeInfo {
+                    version: Some(version),
+                    agent,
+                    services,
+                    good: true,
+                    failures: 0,
+                    last_success: Some(now),
+                    next_probe: Some(now + self.crawler_config.recrawl_interval),
+                };
+                addrs
+            }
+            Err(P2pError::ChannelClosed) => return Err(P2pError::ChannelClosed),
+            Err(err) => {
+                log::debug!("probing node {:?} failed: {}", addr, err);
+                info.good = false;
+                info.failures += 1;
+                info.next_probe = Some(now + dial::backoff(info.failures));
+                vec![]
+            }
+        };
+
+        let added = addrs.iter().filter(|addr| self.add_address_str(addr)).count();
+        if added > 0 {
+            log::debug!("learned {} new addresses from node {:?}", added, addr);
+        }
+        Ok(())
+    }
+
+    /// Connect to a node, check its information and ask for the addresses it knows about
+    async fn query(
+        &mut self,
+        addr: &T::Address,
+    ) -> error::Result<(SemVer, Option<String>, Services, Vec<String>)> {
+        let peer_info = self.conn.connect(addr.clone()).await?;
+        let peer_id = peer_info.peer_id;
+
+        let result = self.check_and_request_addresses(&peer_info).await;
+        if let Err(err) = self.conn.disconnect(peer_id).await {
+            log::debug!("failed to disconnect from peer {:?}: {}", peer_id, err);
+        }
+
+        let addrs = result?;
+        Ok((
+            peer_info.version,
+            peer_info.agent,
+            peer_info.services,
+            addrs,
+        ))
+    }
+
+    async fn check_and_request_addresses(
+        &mut self,
+        peer_info: &crate::net::PeerInfo<T>,
+    ) -> error::Result<Vec<String>> {
+        if peer_info.magic_bytes != *self.config.magic_bytes() {
+            return Err(P2pError::ProtocolError(ProtocolError::DifferentNetwork));
+        }
+        swarm::validate_peer_info(&self.config, peer_info)?;
+        if !peer_info.services.contains(self.p2p_config.required_services) {
+            return Err(P2pError::ProtocolError(ProtocolError::MissingServices));
+        }
+
+        let request = Message {
+            magic: *self.config.magic_bytes(),
+            msg: MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetAddr)),
+        };
+        let request_id = self.sync.send_request(peer_info.peer_id, request).await?;
+
+        let timeout = tokio::time::sleep(self.crawler_config.addr_timeout);
+        tokio::pin!(timeout);
+        loop {
+            tokio::select! {
+                _ = &mut timeout => {
+                    return Err(P2pError::ProtocolError(ProtocolError::Unresponsive));
+                }
+                event = self.sync.poll_next() => match event? {
+                    SyncingEvent::Response { request_id: id, response, .. } if id == request_id => {
+                        return match response.msg {
+                            MessageType::Syncing(SyncingMessage::Response(
+                                SyncingResponse::Addr { addrs },
+                            )) => Ok(addrs),
+                            _ => Err(P2pError::ProtocolError(ProtocolError::InvalidMessage)),
+                        };
+                    }
+                    SyncingEvent::Error { request_id: id, .. } if id == request_id => {
+                        return Err(P2pError::ProtocolError(ProtocolError::Unresponsive));
+                    }
+                    // the requests of the peer are left unanswered
+                    _ => {}
+                },
+                event = self.conn.poll_next() => self.on_connectivity_event(event?).await?,
+                event = self.pubsub.poll_next() => {
+                    event?;
+                }
+            }
+        }
+    }
+
+    async fn on_connectivity_event(&mut self, event: ConnectivityEvent<T>) -> error::Result<()> {
+        match event {
+            // the crawler only makes outbound connections
+            ConnectivityEvent::IncomingConnection { peer_info, .. } => {
+                self.conn.disconnect(peer_info.peer_id).await
+            }
+            ConnectivityEvent::Discovered { peers } => {
+                for info in peers {
+                    for addr in info.ip4.iter().chain(info.ip6.iter()) {
+                        self.add_address((**addr).clone());
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -28,6 +28,9 @@ pub enum ProtocolError {
     InvalidProtocol,
     UnknownNetwork,
     InvalidState,
+    RateLimitExceeded,
+    AuthenticationFailed,
+    MissingServices,
 }
 
 // TODO: refactor error code
@@ -45,9 +48,12 @@ pub enum Libp2pError {
     PublishError(String),
     #[error("IdentifyError: `{0:?}`")]
     IdentifyError(String),
+    #[error("IdentityError: `{0:?}`")]
+    IdentityError(String),
 }
 
-#[derive(Error, Debug, PartialEq, Eq)]
+#[derive(Error, Debug, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
 pub enum P2pError {
     #[error("SocketError: `{0:?}`")]
     SocketError(std::io::ErrorKind),
@@ -83,6 +89,10 @@ pub enum P2pError {
     DatabaseFailure,
     #[error("InvalidPeerId")]
     InvalidPeerId,
+    #[error("TooManyPendingRequests")]
+    TooManyPendingRequests,
+    #[error("PeerBanned")]
+    PeerBanned,
 }
 
 // TODO: move this to src/lib.rs
@@ -204,6 +214,15 @@ impl std::fmt::Display for ProtocolError {
             ProtocolError::InvalidState => {
                 write!(f, "Invalid state")
             }
+            ProtocolError::RateLimitExceeded => {
+                write!(f, "Remote peer exceeded the rate limit")
+            }
+            ProtocolError::AuthenticationFailed => {
+                write!(f, "Remote peer failed to authenticate")
+            }
+            ProtocolError::MissingServices => {
+                write!(f, "Remote peer doesn't provide the required services")
+            }
         }
     }
 }
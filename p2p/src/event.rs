@@ -14,10 +14,84 @@
 // limitations under the License.
 //
 // Author(s): A. Altonen
-use crate::{error, net::NetworkingService};
-use common::chain::block::Block;
+use crate::{error, net::NetworkingService, sync::SyncState};
+use common::{chain::block::Block, primitives::Id};
+use std::{net::IpAddr, time::Duration};
 use tokio::sync::oneshot;
 
+/// Information about a connected peer
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ConnectedPeer {
+    /// Unique ID of the peer
+    pub peer_id: String,
+
+    /// Round-trip time of the connection in milliseconds, if it has been measured
+    pub ping_ms: Option<u64>,
+}
+
+/// Detailed information about a connected peer
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PeerInfo {
+    /// Unique ID of the peer
+    pub peer_id: String,
+
+    /// Address of the peer, if known
+    pub address: Option<String>,
+
+    /// Whether the connection was opened by the peer
+    pub inbound: bool,
+
+    /// Version of the node software the peer runs
+    pub version: String,
+
+    /// User agent of the peer, if it sent one
+    pub agent: Option<String>,
+
+    /// Services the peer advertised, such as `full-blocks,headers`
+    pub services: String,
+
+    /// Number of seconds the connection has been open
+    pub uptime_secs: u64,
+
+    /// Last valid block the peer announced to the local node
+    pub last_block: Option<Id<Block>>,
+
+    /// Round-trip time of the connection in milliseconds, if it has been measured
+    pub ping_ms: Option<u64>,
+
+    /// Sum of the misbehavior reported for the peer
+    pub misbehavior_score: u32,
+}
+
+/// Traffic exchanged with a connected peer over the syncing protocol
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PeerStats {
+    /// Unique ID of the peer
+    pub peer_id: String,
+
+    /// Number of bytes sent to the peer
+    pub bytes_sent: u64,
+
+    /// Number of bytes received from the peer
+    pub bytes_received: u64,
+
+    /// Number of messages sent to the peer
+    pub messages_sent: u64,
+
+    /// Number of messages received from the peer
+    pub messages_received: u64,
+}
+
+/// Address banned by the node operator
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BannedAddress {
+    /// Banned IP address
+    pub address: String,
+
+    /// Number of seconds until the ban expires
+    pub ban_remaining_secs: u64,
+}
+
 #[derive(Debug)]
 pub enum SwarmEvent<T: NetworkingService> {
     /// Try to establish connection with a remote peer
@@ -35,8 +109,71 @@ pub enum SwarmEvent<T: NetworkingService> {
     /// Get peer ID of the local node
     GetPeerId(oneshot::Sender<String>),
 
-    /// Get peer IDs of connected peers
-    GetConnectedPeers(oneshot::Sender<Vec<String>>),
+    /// Get information about connected peers
+    GetConnectedPeers(oneshot::Sender<Vec<ConnectedPeer>>),
+
+    /// Get detailed information about connected peers
+    GetPeerInfo(oneshot::Sender<Vec<PeerInfo>>),
+
+    /// Get traffic statistics of connected peers
+    GetPeerStats(oneshot::Sender<Vec<PeerStats>>),
+
+    /// Get the syncing state of the local node
+    GetSyncState(oneshot::Sender<SyncState>),
+
+    /// Ban an IP address for the given duration and disconnect the peers connected from it
+    Ban(IpAddr, Duration, oneshot::Sender<()>),
+
+    /// Lift the ban of an IP address, responds with `false` if the address wasn't banned
+    Unban(IpAddr, oneshot::Sender<bool>),
+
+    /// Get the banned addresses
+    GetBanned(oneshot::Sender<Vec<BannedAddress>>),
+
+    /// Peer misbehaved while syncing
+    Misbehaved {
+        /// Unique ID of the peer
+        peer_id: T::PeerId,
+
+        /// Misbehavior score added to the peer
+        score: u32,
+    },
+
+    /// Chainstate has left initial block download
+    InitialBlockDownloadDone,
+
+    /// Peer announced a valid block
+    BlockAnnounced {
+        /// Unique ID of the peer that announced the block
+        peer_id: T::PeerId,
+
+        /// ID of the announced block
+        block_id: Id<Block>,
+    },
+
+    /// Peer sent a block whose parent is not known
+    OrphanBlock {
+        /// Unique ID of the peer that sent the block
+        peer_id: T::PeerId,
+
+        /// ID of the orphan block
+        block_id: Id<Block>,
+    },
+
+    /// Get a random sample of known peer addresses that is sent to a remote peer
+    GetAddresses(T::PeerId, oneshot::Sender<Vec<String>>),
+
+    /// Peer addresses received from a remote peer
+    AddressesReceived {
+        /// Unique ID of the peer that sent the addresses
+        peer_id: T::PeerId,
+
+        /// Received addresses
+        addrs: Vec<String>,
+
+        /// Whether the addresses were sent as a response to our request
+        solicited: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -53,8 +190,29 @@ where
     /// Peer connected
     Connected(T::PeerId),
 
+    /// Connection to the peer is being closed, no more messages should be exchanged with it
+    Disconnecting(T::PeerId),
+
     /// Peer disconnected
     Disconnected(T::PeerId),
+
+    /// Ask the peer for addresses of other nodes it knows about
+    GetAddr(T::PeerId),
+
+    /// Request the missing ancestors of an orphan block from the peer that sent it
+    OrphanBlock {
+        /// Unique ID of the peer that sent the block
+        peer_id: T::PeerId,
+
+        /// ID of the orphan block
+        block_id: Id<Block>,
+    },
+
+    /// Get traffic statistics of the peers known to SyncManager
+    GetPeerStats(oneshot::Sender<Vec<PeerStats>>),
+
+    /// Get the syncing state of the local node
+    GetSyncState(oneshot::Sender<SyncState>),
 }
 
 #[derive(Debug, PartialEq)]
@@ -0,0 +1,70 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Entry points of the fuzz targets decoding the messages received from peers
+//!
+//! See [common::fuzz] for how the targets and their corpora are organized.
+
+use common::fuzz::check_round_trip;
+use serialization::Encode;
+
+use crate::{
+    message::Message,
+    net::mock::types::{HandshakeMessage, PeerMessage},
+};
+
+/// Decode a message of the handshake of the mock backend
+pub fn decode_handshake_message(data: &[u8]) {
+    check_round_trip::<HandshakeMessage>(data);
+}
+
+/// Decode a message of the mock backend received after the handshake
+pub fn decode_peer_message(data: &[u8]) {
+    check_round_trip::<PeerMessage>(data);
+}
+
+/// Decode a p2p message with its limits checked, as the backends do
+///
+/// A message that passes the checks must pass them again once encoded.
+pub fn decode_message(data: &[u8]) {
+    if let Ok(message) = Message::decode_checked(data) {
+        assert_eq!(Message::decode_checked(&message.encode()), Ok(message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::fuzz::corpus;
+
+    #[test]
+    fn handshake_message_corpus() {
+        corpus("decode_handshake_message")
+            .iter()
+            .for_each(|input| decode_handshake_message(input));
+    }
+
+    #[test]
+    fn peer_message_corpus() {
+        corpus("decode_peer_message")
+            .iter()
+            .for_each(|input| decode_peer_message(input));
+    }
+
+    #[test]
+    fn message_corpus() {
+        corpus("decode_message").iter().for_each(|input| decode_message(input));
+    }
+}
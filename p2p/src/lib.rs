@@ -15,20 +15,33 @@
 //
 // Author(s): A. Altonen
 use crate::{
+    config::P2pConfig,
     error::P2pError,
-    net::{ConnectivityService, NetworkingService, PubSubService, SyncingCodecService},
+    net::{ConnectivityService, NetworkingService, PubSubService, SyncingService},
 };
 use chainstate::chainstate_interface;
 use common::chain::ChainConfig;
 use logging::log;
-use std::{fmt::Debug, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    fmt::Debug,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::sync::{mpsc, oneshot};
 
+pub mod config;
+pub mod crawler;
 pub mod error;
 pub mod event;
+pub mod fuzz;
 pub mod message;
+pub mod metrics;
 pub mod net;
+pub mod portmap;
 pub mod pubsub;
+pub mod ratelimit;
 pub mod rpc;
 pub mod swarm;
 pub mod sync;
@@ -67,7 +80,8 @@ where
         rx.await.map_err(P2pError::from)?
     }
 
-    async fn disconnect(&self, peer_id: String) -> error::Result<()>
+    /// Close the connection to a peer
+    pub async fn disconnect(&self, peer_id: String) -> error::Result<()>
     where
         <T as NetworkingService>::PeerId: FromStr,
         <<T as NetworkingService>::PeerId as FromStr>::Err: Debug,
@@ -113,7 +127,7 @@ where
         rx.await.map_err(P2pError::from)
     }
 
-    pub async fn get_connected_peers(&self) -> error::Result<Vec<String>> {
+    pub async fn get_connected_peers(&self) -> error::Result<Vec<event::ConnectedPeer>> {
         let (tx, rx) = oneshot::channel();
         self.p2p
             .tx_swarm
@@ -122,6 +136,75 @@ where
             .map_err(P2pError::from)?;
         rx.await.map_err(P2pError::from)
     }
+
+    pub async fn get_peer_info(&self) -> error::Result<Vec<event::PeerInfo>> {
+        let (tx, rx) = oneshot::channel();
+        self.p2p
+            .tx_swarm
+            .send(event::SwarmEvent::GetPeerInfo(tx))
+            .await
+            .map_err(P2pError::from)?;
+        rx.await.map_err(P2pError::from)
+    }
+
+    pub async fn get_peer_stats(&self) -> error::Result<Vec<event::PeerStats>> {
+        let (tx, rx) = oneshot::channel();
+        self.p2p
+            .tx_swarm
+            .send(event::SwarmEvent::GetPeerStats(tx))
+            .await
+            .map_err(P2pError::from)?;
+        rx.await.map_err(P2pError::from)
+    }
+
+    pub async fn get_sync_state(&self) -> error::Result<sync::SyncState> {
+        let (tx, rx) = oneshot::channel();
+        self.p2p
+            .tx_swarm
+            .send(event::SwarmEvent::GetSyncState(tx))
+            .await
+            .map_err(P2pError::from)?;
+        rx.await.map_err(P2pError::from)
+    }
+
+    /// Ban an IP address for `duration_secs` seconds or, if not given, for
+    /// [`swarm::banlist::DEFAULT_BAN_DURATION`]
+    pub async fn ban(&self, addr: String, duration_secs: Option<u64>) -> error::Result<()> {
+        let ip = addr.parse::<IpAddr>().map_err(|_| P2pError::InvalidAddress)?;
+        let duration =
+            duration_secs.map_or(swarm::banlist::DEFAULT_BAN_DURATION, Duration::from_secs);
+
+        let (tx, rx) = oneshot::channel();
+        self.p2p
+            .tx_swarm
+            .send(event::SwarmEvent::Ban(ip, duration, tx))
+            .await
+            .map_err(P2pError::from)?;
+        rx.await.map_err(P2pError::from)
+    }
+
+    /// Lift the ban of an IP address, returns `false` if the address wasn't banned
+    pub async fn unban(&self, addr: String) -> error::Result<bool> {
+        let ip = addr.parse::<IpAddr>().map_err(|_| P2pError::InvalidAddress)?;
+
+        let (tx, rx) = oneshot::channel();
+        self.p2p
+            .tx_swarm
+            .send(event::SwarmEvent::Unban(ip, tx))
+            .await
+            .map_err(P2pError::from)?;
+        rx.await.map_err(P2pError::from)
+    }
+
+    pub async fn get_banned(&self) -> error::Result<Vec<event::BannedAddress>> {
+        let (tx, rx) = oneshot::channel();
+        self.p2p
+            .tx_swarm
+            .send(event::SwarmEvent::GetBanned(tx))
+            .await
+            .map_err(P2pError::from)?;
+        rx.await.map_err(P2pError::from)
+    }
 }
 
 struct P2P<T: NetworkingService> {
@@ -137,7 +220,7 @@ impl<T> P2P<T>
 where
     T: 'static + NetworkingService,
     T::ConnectivityHandle: ConnectivityService<T>,
-    T::SyncingCodecHandle: SyncingCodecService<T>,
+    T::SyncingHandle: SyncingService<T>,
     T::PubSubHandle: PubSubService<T>,
 {
     /// Start the P2P subsystem
@@ -146,21 +229,42 @@ where
     pub async fn new(
         bind_addr: String,
         config: Arc<ChainConfig>,
+        p2p_config: Arc<P2pConfig>,
         consensus_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
     ) -> error::Result<Self>
     where
         <T as NetworkingService>::Address: FromStr,
         <<T as NetworkingService>::Address as FromStr>::Err: Debug,
     {
-        let (conn, pubsub, sync) = T::start(
+        let handles = T::start(
             bind_addr.parse::<T::Address>().map_err(|_| P2pError::InvalidAddress)?,
             &[],
             &[net::PubSubTopic::Blocks],
             Arc::clone(&config),
+            Arc::clone(&p2p_config),
             TIMEOUT,
         )
         .await?;
 
+        Ok(Self::from_handles(
+            handles,
+            config,
+            p2p_config,
+            consensus_handle,
+        ))
+    }
+
+    /// Start the individual manager objects over the handles of a started networking backend
+    fn from_handles(
+        (conn, pubsub, sync): (T::ConnectivityHandle, T::PubSubHandle, T::SyncingHandle),
+        config: Arc<ChainConfig>,
+        p2p_config: Arc<P2pConfig>,
+        consensus_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
+    ) -> Self
+    where
+        <T as NetworkingService>::Address: FromStr,
+        <<T as NetworkingService>::Address as FromStr>::Err: Debug,
+    {
         // TODO: think about these channel sizes
         let (tx_swarm, rx_swarm) = mpsc::channel(CHANNEL_SIZE);
         let (tx_p2p_sync, rx_p2p_sync) = mpsc::channel(CHANNEL_SIZE);
@@ -169,9 +273,10 @@ where
 
         let swarm_config = Arc::clone(&config);
         tokio::spawn(async move {
-            if let Err(e) = swarm::PeerManager::<T>::new(swarm_config, conn, rx_swarm, tx_p2p_sync)
-                .run()
-                .await
+            if let Err(e) =
+                swarm::PeerManager::<T>::new(swarm_config, p2p_config, conn, rx_swarm, tx_p2p_sync)
+                    .run()
+                    .await
             {
                 log::error!("PeerManager failed: {:?}", e);
             }
@@ -197,17 +302,23 @@ where
         });
 
         // TODO: merge with syncmanager when appropriate
+        let swarm_tx = tx_swarm.clone();
         tokio::spawn(async move {
-            if let Err(e) =
-                pubsub::PubSubMessageHandler::<T>::new(config, pubsub, consensus_handle, rx_pubsub)
-                    .run()
-                    .await
+            if let Err(e) = pubsub::PubSubMessageHandler::<T>::new(
+                config,
+                pubsub,
+                consensus_handle,
+                rx_pubsub,
+                swarm_tx,
+            )
+            .run()
+            .await
             {
                 log::error!("PubSubMessageHandler failed: {:?}", e);
             }
         });
 
-        Ok(Self { tx_swarm, _tx_sync })
+        Self { tx_swarm, _tx_sync }
     }
 }
 
@@ -217,13 +328,14 @@ pub type P2pHandle<T> = subsystem::Handle<P2pInterface<T>>;
 
 pub async fn make_p2p<T>(
     chain_config: Arc<ChainConfig>,
+    p2p_config: Arc<P2pConfig>,
     consensus_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
     bind_addr: String,
 ) -> Result<P2pInterface<T>, P2pError>
 where
     T: NetworkingService + 'static,
     T::ConnectivityHandle: ConnectivityService<T>,
-    T::SyncingCodecHandle: SyncingCodecService<T>,
+    T::SyncingHandle: SyncingService<T>,
     T::PubSubHandle: PubSubService<T>,
     <T as NetworkingService>::Address: FromStr,
     <<T as NetworkingService>::Address as FromStr>::Err: Debug,
@@ -231,6 +343,30 @@ where
     <<T as NetworkingService>::PeerId as FromStr>::Err: Debug,
 {
     Ok(P2pInterface {
-        p2p: P2P::new(bind_addr, chain_config, consensus_handle).await?,
+        p2p: P2P::new(bind_addr, chain_config, p2p_config, consensus_handle).await?,
+    })
+}
+
+/// Make the p2p subsystem with the mock backend running over a simulated network
+///
+/// See [`net::mock::sim`] for how the simulation is controlled.
+pub fn make_p2p_simulated(
+    chain_config: Arc<ChainConfig>,
+    p2p_config: Arc<P2pConfig>,
+    consensus_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
+    network: &net::mock::sim::SimNetwork,
+    bind_addr: SocketAddr,
+) -> Result<P2pInterface<net::mock::MockService>, P2pError> {
+    let handles = net::mock::MockService::start_simulated(
+        network,
+        bind_addr,
+        &[net::PubSubTopic::Blocks],
+        Arc::clone(&chain_config),
+        Arc::clone(&p2p_config),
+        TIMEOUT,
+    )?;
+
+    Ok(P2pInterface {
+        p2p: P2P::from_handles(handles, chain_config, p2p_config, consensus_handle),
     })
 }
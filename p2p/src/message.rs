@@ -14,18 +14,44 @@
 // limitations under the License.
 //
 // Author(s): A. Altonen
+use crate::error::ProtocolError;
 use common::{
-    chain::block::{Block, BlockHeader},
+    chain::{
+        block::{Block, BlockHeader},
+        config::MAX_BLOCK_WEIGHT,
+    },
     primitives::Id,
 };
+use parity_scale_codec::Compact;
 use serialization::{Decode, Encode};
 
+/// Maximum number of headers in a locator or in a `Headers` response
+pub const MAX_HEADERS_PER_MESSAGE: usize = 2000;
+
+/// Maximum number of block IDs in a `GetBlocks` request or blocks in a `Blocks` response
+pub const MAX_BLOCKS_PER_MESSAGE: usize = 500;
+
+/// Maximum number of addresses in an `Addr` message
+pub const MAX_ADDR_PER_MESSAGE: usize = 1000;
+
+/// Size of the magic bytes that start every message
+const MAGIC_SIZE: usize = 4;
+
 #[derive(Debug, Encode, Decode, Clone, PartialEq, Eq)]
 pub enum SyncingRequest {
     #[codec(index = 0)]
     GetHeaders { locator: Vec<BlockHeader> },
     #[codec(index = 1)]
     GetBlocks { block_ids: Vec<Id<Block>> },
+    /// Ask for addresses of other nodes the peer knows about
+    #[codec(index = 2)]
+    GetAddr,
+    /// Advertise addresses of other nodes, acknowledged with an empty `Addr` response
+    #[codec(index = 3)]
+    Addr { addrs: Vec<String> },
+    /// Announce a new tip of the sender's chain, acknowledged with a `HeaderAck` response
+    #[codec(index = 4)]
+    AnnounceHeader { header: BlockHeader },
 }
 
 #[derive(Debug, Encode, Decode, Clone, PartialEq, Eq)]
@@ -34,6 +60,10 @@ pub enum SyncingResponse {
     Headers { headers: Vec<BlockHeader> },
     #[codec(index = 1)]
     Blocks { blocks: Vec<Block> },
+    #[codec(index = 2)]
+    Addr { addrs: Vec<String> },
+    #[codec(index = 3)]
+    HeaderAck,
 }
 
 #[derive(Debug, Encode, Decode, Clone, PartialEq, Eq)]
@@ -66,3 +96,151 @@ pub struct Message {
     /// Message (GetHeaders, Blocks, etc.)
     pub msg: MessageType,
 }
+
+impl Message {
+    /// Decode a message received from a peer, checking first that it is within the limits
+    /// of its type
+    ///
+    /// The number of items in the message is read from the encoded data before the items
+    /// are decoded, so a peer can't make the local node allocate memory for a huge number
+    /// of items by sending a small message.
+    pub fn decode_checked(data: &[u8]) -> Result<Self, ProtocolError> {
+        Self::check_limits(data)?;
+        Self::decode(&mut &data[..]).map_err(|_| ProtocolError::InvalidMessage)
+    }
+
+    /// Check the item count and the size of an encoded message against the limits of its type
+    ///
+    /// The magic bytes are followed by the codec indices of the `MessageType`, the
+    /// `SyncingMessage` and the request or response variant, and then by the length
+    /// of the list the variant carries.
+    pub fn check_limits(data: &[u8]) -> Result<(), ProtocolError> {
+        let body = data.get(MAGIC_SIZE..).ok_or(ProtocolError::InvalidMessage)?;
+
+        let (max_items, max_item_size) = match body {
+            // GetHeaders request and Headers response
+            [0, 0, 0, ..] | [0, 1, 0, ..] => (MAX_HEADERS_PER_MESSAGE, None),
+            // GetBlocks request
+            [0, 0, 1, ..] => (MAX_BLOCKS_PER_MESSAGE, None),
+            // Blocks response
+            [0, 1, 1, ..] => (MAX_BLOCKS_PER_MESSAGE, Some(MAX_BLOCK_WEIGHT)),
+            // Addr request and response
+            [0, 0, 3, ..] | [0, 1, 2, ..] => (MAX_ADDR_PER_MESSAGE, None),
+            // PubSub block
+            [1, 0, block @ ..] if block.len() > MAX_BLOCK_WEIGHT => {
+                return Err(ProtocolError::InvalidMessage)
+            }
+            // messages without lists, invalid variants are rejected when decoding
+            _ => return Ok(()),
+        };
+
+        let mut items = &body[3..];
+        let count = Compact::<u32>::decode(&mut items).map_err(|_| ProtocolError::InvalidMessage)?.0
+            as usize;
+
+        if count > max_items {
+            return Err(ProtocolError::InvalidMessage);
+        }
+        match max_item_size {
+            Some(size) if items.len() > count.saturating_mul(size) => {
+                Err(ProtocolError::InvalidMessage)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(msg: MessageType) -> Vec<u8> {
+        Message {
+            magic: [1, 2, 3, 4],
+            msg,
+        }
+        .encode()
+    }
+
+    fn addrs(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("/ip4/1.2.3.4/tcp/{}", i)).collect()
+    }
+
+    #[test]
+    fn item_counts_checked() {
+        let block_ids = |count| vec![Id::<Block>::new(&Default::default()); count];
+        let messages = [
+            (
+                MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetBlocks {
+                    block_ids: block_ids(MAX_BLOCKS_PER_MESSAGE),
+                })),
+                MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetBlocks {
+                    block_ids: block_ids(MAX_BLOCKS_PER_MESSAGE + 1),
+                })),
+            ),
+            (
+                MessageType::Syncing(SyncingMessage::Request(SyncingRequest::Addr {
+                    addrs: addrs(MAX_ADDR_PER_MESSAGE),
+                })),
+                MessageType::Syncing(SyncingMessage::Request(SyncingRequest::Addr {
+                    addrs: addrs(MAX_ADDR_PER_MESSAGE + 1),
+                })),
+            ),
+            (
+                MessageType::Syncing(SyncingMessage::Response(SyncingResponse::Addr {
+                    addrs: addrs(MAX_ADDR_PER_MESSAGE),
+                })),
+                MessageType::Syncing(SyncingMessage::Response(SyncingResponse::Addr {
+                    addrs: addrs(MAX_ADDR_PER_MESSAGE + 1),
+                })),
+            ),
+        ];
+
+        for (valid, invalid) in messages {
+            assert_eq!(
+                Message::decode_checked(&encode(valid.clone())).map(|msg| msg.msg),
+                Ok(valid)
+            );
+            assert_eq!(
+                Message::decode_checked(&encode(invalid)),
+                Err(ProtocolError::InvalidMessage)
+            );
+        }
+    }
+
+    #[test]
+    fn list_length_checked_before_decoding() {
+        // a header list claiming `u32::MAX` headers with no data following it
+        let mut data = encode(MessageType::Syncing(SyncingMessage::Response(
+            SyncingResponse::Headers { headers: vec![] },
+        )));
+        data.truncate(MAGIC_SIZE + 3);
+        Compact(u32::MAX).encode_to(&mut data);
+        assert_eq!(
+            Message::check_limits(&data),
+            Err(ProtocolError::InvalidMessage)
+        );
+
+        // blocks larger than the maximum block size
+        let mut data = encode(MessageType::Syncing(SyncingMessage::Response(
+            SyncingResponse::Blocks { blocks: vec![] },
+        )));
+        data.truncate(MAGIC_SIZE + 3);
+        Compact(1u32).encode_to(&mut data);
+        data.resize(data.len() + MAX_BLOCK_WEIGHT + 1, 0);
+        assert_eq!(
+            Message::check_limits(&data),
+            Err(ProtocolError::InvalidMessage)
+        );
+
+        let mut data = encode(MessageType::Syncing(SyncingMessage::Request(
+            SyncingRequest::GetAddr,
+        )));
+        assert_eq!(Message::check_limits(&data), Ok(()));
+        data.truncate(2);
+        assert_eq!(
+            Message::check_limits(&data),
+            Err(ProtocolError::InvalidMessage)
+        );
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics of the p2p subsystem
+//!
+//! The p2p code reports connections, traffic, misbehavior and sync progress through the
+//! [`P2pMetrics`] trait. The reports are discarded unless the node installs an implementation
+//! with [`set_metrics()`], e.g., one that exports the values to Prometheus. [`Counters`]
+//! is a simple implementation that keeps the values in memory.
+//!
+//! The implementation is installed globally so that the network backends can report the
+//! traffic without it being passed through [`crate::net::NetworkingService::start()`].
+
+use crate::{message, sync::SyncState};
+use lazy_static::lazy_static;
+use serialization::Encode;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// Which side opened a connection
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Type of a p2p message, used to account the traffic per message type
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    GetHeaders,
+    GetBlocks,
+    GetAddr,
+    Addr,
+    AnnounceHeader,
+    Headers,
+    Blocks,
+    AddrResponse,
+    HeaderAck,
+    BlockAnnouncement,
+}
+
+impl MessageKind {
+    pub fn of(message: &message::Message) -> Self {
+        use message::{
+            MessageType, PubSubMessage, SyncingMessage, SyncingRequest, SyncingResponse,
+        };
+
+        match &message.msg {
+            MessageType::Syncing(SyncingMessage::Request(request)) => match request {
+                SyncingRequest::GetHeaders { .. } => MessageKind::GetHeaders,
+                SyncingRequest::GetBlocks { .. } => MessageKind::GetBlocks,
+                SyncingRequest::GetAddr => MessageKind::GetAddr,
+                SyncingRequest::Addr { .. } => MessageKind::Addr,
+                SyncingRequest::AnnounceHeader { .. } => MessageKind::AnnounceHeader,
+            },
+            MessageType::Syncing(SyncingMessage::Response(response)) => match response {
+                SyncingResponse::Headers { .. } => MessageKind::Headers,
+                SyncingResponse::Blocks { .. } => MessageKind::Blocks,
+                SyncingResponse::Addr { .. } => MessageKind::AddrResponse,
+                SyncingResponse::HeaderAck => MessageKind::HeaderAck,
+            },
+            MessageType::PubSub(PubSubMessage::Block(_)) => MessageKind::BlockAnnouncement,
+        }
+    }
+
+    /// Name of the message type, suitable as a metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageKind::GetHeaders => "get_headers",
+            MessageKind::GetBlocks => "get_blocks",
+            MessageKind::GetAddr => "get_addr",
+            MessageKind::Addr => "addr",
+            MessageKind::AnnounceHeader => "announce_header",
+            MessageKind::Headers => "headers",
+            MessageKind::Blocks => "blocks",
+            MessageKind::AddrResponse => "addr_response",
+            MessageKind::HeaderAck => "header_ack",
+            MessageKind::BlockAnnouncement => "block_announcement",
+        }
+    }
+}
+
+/// Receiver of the metrics reported by the p2p subsystem
+///
+/// All methods have empty default implementations so that an implementation only
+/// needs to handle the metrics it's interested in.
+pub trait P2pMetrics: Send + Sync {
+    /// Connection to a peer was established and the peer accepted
+    fn connection_opened(&self, _direction: Direction) {}
+
+    /// Connection to an accepted peer was closed
+    fn connection_closed(&self, _direction: Direction) {}
+
+    /// Message of `size` encoded bytes was sent to a peer
+    fn message_sent(&self, _kind: MessageKind, _size: usize) {}
+
+    /// Message of `size` encoded bytes was received from a peer
+    fn message_received(&self, _kind: MessageKind, _size: usize) {}
+
+    /// Peer misbehaved and `score` was added to its misbehavior score
+    fn peer_misbehaved(&self, _score: u32) {}
+
+    /// Sync state of the local node changed
+    fn sync_state_changed(&self, _state: SyncState) {}
+
+    /// Block downloaded during syncing was processed by chainstate
+    fn block_downloaded(&self) {}
+
+    /// Number of downloaded blocks that wait for their parent to be processed
+    fn orphan_blocks(&self, _count: usize) {}
+
+    /// Announced block was rejected because its parent is not known
+    fn orphan_block_announced(&self) {}
+}
+
+/// Implementation that discards all reports
+struct NoMetrics;
+
+impl P2pMetrics for NoMetrics {}
+
+lazy_static! {
+    static ref METRICS: RwLock<Arc<dyn P2pMetrics>> = RwLock::new(Arc::new(NoMetrics));
+}
+
+/// Install the implementation the p2p subsystem reports its metrics to
+pub fn set_metrics(metrics: Arc<dyn P2pMetrics>) {
+    *METRICS.write().expect("metrics lock to not be poisoned") = metrics;
+}
+
+/// Implementation the metrics are reported to
+pub fn metrics() -> Arc<dyn P2pMetrics> {
+    Arc::clone(&METRICS.read().expect("metrics lock to not be poisoned"))
+}
+
+/// Encode a message that is sent to a peer and report its size
+pub(crate) fn encode_sent(message: &message::Message) -> Vec<u8> {
+    let encoded = message.encode();
+    metrics().message_sent(MessageKind::of(message), encoded.len());
+    encoded
+}
+
+/// Report the size of a decoded message that was received from a peer
+pub(crate) fn received(message: &message::Message, size: usize) {
+    metrics().message_received(MessageKind::of(message), size);
+}
+
+/// Values collected by [`Counters`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MetricValues {
+    pub connections_opened: HashMap<Direction, u64>,
+    pub connections_closed: HashMap<Direction, u64>,
+    pub bytes_sent: HashMap<MessageKind, u64>,
+    pub bytes_received: HashMap<MessageKind, u64>,
+    pub misbehavior_reports: u64,
+    pub misbehavior_score: u64,
+    pub sync_state: Option<SyncState>,
+    pub blocks_downloaded: u64,
+    pub orphan_blocks: usize,
+    pub orphan_blocks_announced: u64,
+}
+
+/// Implementation that keeps the metrics in memory
+#[derive(Default)]
+pub struct Counters {
+    values: Mutex<MetricValues>,
+}
+
+impl Counters {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Copy of the values collected so far
+    pub fn values(&self) -> MetricValues {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MetricValues> {
+        self.values.lock().expect("metrics lock to not be poisoned")
+    }
+}
+
+impl P2pMetrics for Counters {
+    fn connection_opened(&self, direction: Direction) {
+        *self.lock().connections_opened.entry(direction).or_default() += 1;
+    }
+
+    fn connection_closed(&self, direction: Direction) {
+        *self.lock().connections_closed.entry(direction).or_default() += 1;
+    }
+
+    fn message_sent(&self, kind: MessageKind, size: usize) {
+        *self.lock().bytes_sent.entry(kind).or_default() += size as u64;
+    }
+
+    fn message_received(&self, kind: MessageKind, size: usize) {
+        *self.lock().bytes_received.entry(kind).or_default() += size as u64;
+    }
+
+    fn peer_misbehaved(&self, score: u32) {
+        let mut values = self.lock();
+        values.misbehavior_reports += 1;
+        values.misbehavior_score += score as u64;
+    }
+
+    fn sync_state_changed(&self, state: SyncState) {
+        self.lock().sync_state = Some(state);
+    }
+
+    fn block_downloaded(&self) {
+        self.lock().blocks_downloaded += 1;
+    }
+
+    fn orphan_blocks(&self, count: usize) {
+        self.lock().orphan_blocks = count;
+    }
+
+    fn orphan_block_announced(&self) {
+        self.lock().orphan_blocks_announced += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Message, MessageType, SyncingMessage, SyncingRequest};
+
+    #[test]
+    fn counters() {
+        let counters = Counters::new();
+        let message = Message {
+            magic: [1, 2, 3, 4],
+            msg: MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetAddr)),
+        };
+        let size = message.encode().len();
+
+        counters.connection_opened(Direction::Inbound);
+        counters.connection_opened(Direction::Inbound);
+        counters.connection_closed(Direction::Inbound);
+        counters.message_sent(MessageKind::of(&message), size);
+        counters.message_sent(MessageKind::of(&message), size);
+        counters.message_received(MessageKind::Blocks, 100);
+        counters.peer_misbehaved(10);
+        counters.peer_misbehaved(100);
+        counters.sync_state_changed(SyncState::DownloadingBlocks);
+        counters.block_downloaded();
+        counters.orphan_blocks(3);
+        counters.orphan_blocks(2);
+
+        let values = counters.values();
+        assert_eq!(values.connections_opened[&Direction::Inbound], 2);
+        assert_eq!(values.connections_closed[&Direction::Inbound], 1);
+        assert!(!values.connections_opened.contains_key(&Direction::Outbound));
+        assert_eq!(values.bytes_sent[&MessageKind::GetAddr], 2 * size as u64);
+        assert_eq!(values.bytes_received[&MessageKind::Blocks], 100);
+        assert_eq!(
+            (values.misbehavior_reports, values.misbehavior_score),
+            (2, 110)
+        );
+        assert_eq!(values.sync_state, Some(SyncState::DownloadingBlocks));
+        assert_eq!(values.blocks_downloaded, 1);
+        assert_eq!(values.orphan_blocks, 2);
+        assert_eq!(values.orphan_blocks_announced, 0);
+    }
+}
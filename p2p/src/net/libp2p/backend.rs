@@ -20,7 +20,10 @@
 
 use crate::{
     error::{self, Libp2pError, P2pError},
-    net::libp2p::{types, SyncResponse},
+    net::{
+        self,
+        libp2p::{types, SyncResponse, REQ_RESP_MAX_PENDING},
+    },
 };
 use futures::StreamExt;
 use libp2p::{
@@ -74,8 +77,17 @@ pub struct Backend {
     /// Set of established connections
     pub(super) established_conns: HashSet<PeerId>,
 
-    /// Set of pending requests
-    pub(super) pending_reqs: HashMap<RequestId, ResponseChannel<SyncResponse>>,
+    /// Set of pending inbound requests and the peers who sent them
+    pub(super) pending_reqs: HashMap<RequestId, (PeerId, ResponseChannel<SyncResponse>)>,
+
+    /// Set of inbound requests, per peer, that haven't been responded to yet
+    pub(super) inbound_reqs: HashMap<PeerId, HashSet<RequestId>>,
+
+    /// Set of outbound requests, per peer, that haven't been responded to yet
+    pub(super) outbound_reqs: HashMap<PeerId, HashSet<RequestId>>,
+
+    /// Set of inbound requests that were rejected because of too many pending requests
+    pub(super) rejected_reqs: HashSet<RequestId>,
 
     /// Whether mDNS peer events should be relayed to P2P manager
     pub(super) relay_mdns: bool,
@@ -99,10 +111,24 @@ impl Backend {
             pending_conns: HashMap::new(),
             established_conns: HashSet::new(),
             pending_reqs: HashMap::new(),
+            inbound_reqs: HashMap::new(),
+            outbound_reqs: HashMap::new(),
+            rejected_reqs: HashSet::new(),
             relay_mdns,
         }
     }
 
+    /// Report to PeerManager that the peer sent an invalid message
+    pub(super) async fn invalid_message_received(&mut self, peer_id: PeerId) -> error::Result<()> {
+        self.conn_tx
+            .send(types::ConnectivityEvent::Misbehaved {
+                peer_id,
+                behaviour: net::INVALID_MESSAGE_SCORE,
+            })
+            .await
+            .map_err(|_| P2pError::ChannelClosed)
+    }
+
     // TODO: into_fatal()???
     pub async fn run(&mut self) -> error::Result<()> {
         log::debug!("starting event loop");
@@ -269,13 +295,24 @@ impl Backend {
                 peer_id,
                 request,
                 response,
-            } => response
-                .send(Ok(self
-                    .swarm
-                    .behaviour_mut()
-                    .sync
-                    .send_request(&peer_id, *request)))
-                .map_err(|_| P2pError::ChannelClosed),
+            } => {
+                let pending = self.outbound_reqs.entry(peer_id).or_default();
+
+                if pending.len() >= REQ_RESP_MAX_PENDING {
+                    log::warn!(
+                        "peer {:?} has {} unanswered requests, reject new request",
+                        peer_id,
+                        pending.len()
+                    );
+                    return response
+                        .send(Err(P2pError::TooManyPendingRequests))
+                        .map_err(|_| P2pError::ChannelClosed);
+                }
+
+                let request_id = self.swarm.behaviour_mut().sync.send_request(&peer_id, *request);
+                pending.insert(request_id);
+                response.send(Ok(request_id)).map_err(|_| P2pError::ChannelClosed)
+            }
             types::Command::SendResponse {
                 request_id,
                 response,
@@ -285,7 +322,8 @@ impl Backend {
                     log::error!("pending request ({:?}) doesn't exist", request_id);
                     channel.send(Err(P2pError::ChannelClosed)).map_err(|_| P2pError::ChannelClosed)
                 }
-                Some(response_channel) => {
+                Some((peer_id, response_channel)) => {
+                    self.inbound_request_done(&peer_id, &request_id);
                     let res = self
                         .swarm
                         .behaviour_mut()
@@ -309,7 +347,7 @@ impl Backend {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::net::libp2p::{SyncingCodec, SyncingProtocol};
+    use crate::net::libp2p::{SyncRequest, SyncingCodec, SyncingProtocol};
     use libp2p::{
         core::upgrade,
         gossipsub::{Gossipsub, GossipsubConfigBuilder, MessageAuthenticity},
@@ -442,6 +480,91 @@ mod tests {
         assert!(res.unwrap().is_err());
     }
 
+    async fn send_request(backend: &mut Backend, peer_id: PeerId) -> error::Result<RequestId> {
+        let (tx, rx) = oneshot::channel();
+        backend
+            .on_command(types::Command::SendRequest {
+                peer_id,
+                request: Box::new(SyncRequest::new(vec![1, 2, 3])),
+                response: tx,
+            })
+            .await
+            .unwrap();
+        rx.await.unwrap()
+    }
+
+    // verify that the number of unanswered outbound requests per peer is limited
+    // and that the limit is reset when the connection to the peer is closed
+    #[tokio::test]
+    async fn test_outbound_request_limit() {
+        let swarm = make_swarm().await;
+        let (_cmd_tx, cmd_rx) = mpsc::channel(16);
+        let (gossip_tx, _) = mpsc::channel(64);
+        let (conn_tx, _conn_rx) = mpsc::channel(64);
+        let (sync_tx, _) = mpsc::channel(64);
+        let mut backend = Backend::new(swarm, cmd_rx, conn_tx, gossip_tx, sync_tx, false);
+        let peer_id = PeerId::random();
+
+        for _ in 0..REQ_RESP_MAX_PENDING {
+            assert!(send_request(&mut backend, peer_id).await.is_ok());
+        }
+        assert_eq!(
+            send_request(&mut backend, peer_id).await,
+            Err(P2pError::TooManyPendingRequests)
+        );
+
+        // other peers are not affected
+        assert!(send_request(&mut backend, PeerId::random()).await.is_ok());
+
+        backend.on_connection_closed(peer_id).await.unwrap();
+        assert!(send_request(&mut backend, peer_id).await.is_ok());
+    }
+
+    // verify that the number of unanswered inbound requests per peer is limited,
+    // that rejected requests are remembered and that answering a request frees a slot
+    #[tokio::test]
+    async fn test_inbound_request_limit() {
+        let swarm = make_swarm().await;
+        let (_cmd_tx, cmd_rx) = mpsc::channel(16);
+        let (gossip_tx, _) = mpsc::channel(64);
+        let (conn_tx, _conn_rx) = mpsc::channel(64);
+        let (sync_tx, _) = mpsc::channel(64);
+        let mut backend = Backend::new(swarm, cmd_rx, conn_tx, gossip_tx, sync_tx, false);
+        let peer_id = PeerId::random();
+
+        // request IDs can't be constructed directly so allocate them from the behaviour
+        let new_request_id = |backend: &mut Backend| {
+            backend
+                .swarm
+                .behaviour_mut()
+                .sync
+                .send_request(&PeerId::random(), SyncRequest::new(vec![]))
+        };
+
+        let accepted = (0..REQ_RESP_MAX_PENDING)
+            .map(|_| {
+                let request_id = new_request_id(&mut backend);
+                assert!(backend.inbound_request_received(peer_id, request_id));
+                request_id
+            })
+            .collect::<Vec<_>>();
+
+        let rejected = new_request_id(&mut backend);
+        assert!(!backend.inbound_request_received(peer_id, rejected));
+        assert!(backend.rejected_reqs.contains(&rejected));
+
+        // other peers are not affected
+        let request_id = new_request_id(&mut backend);
+        assert!(backend.inbound_request_received(PeerId::random(), request_id));
+
+        backend.inbound_request_done(&peer_id, &accepted[0]);
+        let request_id = new_request_id(&mut backend);
+        assert!(backend.inbound_request_received(peer_id, request_id));
+
+        backend.clear_pending_requests(&peer_id);
+        assert!(!backend.inbound_reqs.contains_key(&peer_id));
+    }
+
     // verify that libp2p is able to notice if the p2p object closes
     // the command tx which signals that it is no longer responsive
     #[tokio::test]
@@ -0,0 +1,179 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistence of the identity keypair, which the peer ID of the node is derived from
+
+use std::{fs, io::Write};
+
+use crypto::{
+    random::make_true_rng,
+    symkey::sealed::{KdfParams, SealedSecret},
+};
+use libp2p::identity;
+use logging::log;
+use serialization::{Decode, DecodeAll, Encode};
+use zeroize::Zeroizing;
+
+use crate::{
+    config::P2pIdentity,
+    error::{self, Libp2pError, P2pError},
+};
+
+/// Contents of the identity file
+#[derive(Encode, Decode)]
+enum IdentityFile {
+    /// Encoded ed25519 keypair, the secret key followed by the public key
+    #[codec(index = 0)]
+    Ed25519(Vec<u8>),
+    /// Encoded ed25519 keypair encrypted with the password
+    #[codec(index = 1)]
+    SealedEd25519(SealedSecret),
+}
+
+fn identity_error(msg: impl Into<String>) -> P2pError {
+    P2pError::Libp2pError(Libp2pError::IdentityError(msg.into()))
+}
+
+/// Load the keypair from the identity file, creating the file if it doesn't exist yet
+///
+/// Without an identity file, a new keypair is generated on every call.
+pub fn load_or_generate(identity: Option<&P2pIdentity>) -> error::Result<identity::Keypair> {
+    match identity {
+        None => Ok(identity::Keypair::generate_ed25519()),
+        Some(identity) if identity.path().exists() => load(identity),
+        Some(identity) => generate(identity),
+    }
+}
+
+fn load(identity: &P2pIdentity) -> error::Result<identity::Keypair> {
+    let data = Zeroizing::new(fs::read(identity.path())?);
+    let mut keypair_bytes = match (
+        IdentityFile::decode_all(&mut &data[..])?,
+        identity.password(),
+    ) {
+        (IdentityFile::Ed25519(bytes), password) => {
+            if password.is_some() {
+                log::warn!(
+                    "P2P identity file {} is not encrypted, the password is not used",
+                    identity.path().display()
+                );
+            }
+            Zeroizing::new(bytes)
+        }
+        (IdentityFile::SealedEd25519(sealed), Some(password)) => sealed
+            .unseal(password.as_bytes())
+            .map_err(|_| identity_error("wrong password for the identity file"))?,
+        (IdentityFile::SealedEd25519(_), None) => {
+            return Err(identity_error(
+                "the identity file is encrypted and no password is given",
+            ))
+        }
+    };
+
+    let keypair = identity::ed25519::Keypair::decode(&mut keypair_bytes[..])
+        .map_err(|e| identity_error(e.to_string()))?;
+    Ok(identity::Keypair::Ed25519(keypair))
+}
+
+fn generate(identity: &P2pIdentity) -> error::Result<identity::Keypair> {
+    let keypair = identity::ed25519::Keypair::generate();
+    let keypair_bytes = Zeroizing::new(keypair.encode().to_vec());
+    let file = match identity.password() {
+        None => IdentityFile::Ed25519(keypair_bytes.to_vec()),
+        Some(password) => IdentityFile::SealedEd25519(
+            SealedSecret::seal(
+                &mut make_true_rng(),
+                &keypair_bytes,
+                password.as_bytes(),
+                KdfParams::default(),
+            )
+            .map_err(|e| identity_error(format!("{:?}", e)))?,
+        ),
+    };
+    let data = Zeroizing::new(file.encode());
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(identity.path())?.write_all(&data)?;
+
+    log::info!(
+        "generated new P2P identity in {}",
+        identity.path().display()
+    );
+    Ok(identity::Keypair::Ed25519(keypair))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_identity_file() {
+        let first = load_or_generate(None).unwrap();
+        let second = load_or_generate(None).unwrap();
+        assert_ne!(first.public(), second.public());
+    }
+
+    #[test]
+    fn identity_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        for password in [None, Some("password".to_owned())] {
+            let path = dir.path().join(format!("identity-{}", password.is_some()));
+            let identity = P2pIdentity::new(path.clone(), password);
+
+            let generated = load_or_generate(Some(&identity)).unwrap();
+            assert!(path.exists());
+            let loaded = load_or_generate(Some(&identity)).unwrap();
+            assert_eq!(
+                generated.public().to_peer_id(),
+                loaded.public().to_peer_id()
+            );
+        }
+    }
+
+    #[test]
+    fn encrypted_identity_needs_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("identity");
+        let identity = P2pIdentity::new(path.clone(), Some("password".to_owned()));
+        let generated = load_or_generate(Some(&identity)).unwrap();
+
+        let wrong_password = P2pIdentity::new(path.clone(), Some("passw0rd".to_owned()));
+        assert!(matches!(
+            load_or_generate(Some(&wrong_password)),
+            Err(P2pError::Libp2pError(Libp2pError::IdentityError(_)))
+        ));
+        let no_password = P2pIdentity::new(path, None);
+        assert!(matches!(
+            load_or_generate(Some(&no_password)),
+            Err(P2pError::Libp2pError(Libp2pError::IdentityError(_)))
+        ));
+
+        assert_eq!(
+            load_or_generate(Some(&identity)).unwrap().public(),
+            generated.public()
+        );
+    }
+
+    #[test]
+    fn corrupted_identity_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("identity");
+        fs::write(&path, [1, 2, 3]).unwrap();
+        assert!(load_or_generate(Some(&P2pIdentity::new(path, None))).is_err());
+    }
+}
@@ -18,13 +18,14 @@
 // Author(s): A. Altonen
 use crate::{
     error::{self, Libp2pError, P2pError, ProtocolError},
-    message,
+    message, metrics,
     net::{
         self, libp2p::sync::*, ConnectivityEvent, ConnectivityService, NetworkingService,
-        PubSubEvent, PubSubService, PubSubTopic, SyncingCodecService, SyncingEvent,
+        PubSubEvent, PubSubService, PubSubTopic, SyncingEvent, SyncingService,
     },
 };
 use async_trait::async_trait;
+use common::chain::services::Services;
 use itertools::*;
 use libp2p::{
     core::{upgrade, PeerId},
@@ -32,7 +33,6 @@ use libp2p::{
         Gossipsub, GossipsubConfigBuilder, MessageAuthenticity, MessageId, ValidationMode,
     },
     identify::{Identify, IdentifyConfig, IdentifyInfo},
-    identity,
     mdns::Mdns,
     mplex,
     multiaddr::Protocol,
@@ -43,12 +43,13 @@ use libp2p::{
     Multiaddr, Transport,
 };
 use logging::log;
-use serialization::{Decode, Encode};
 use std::{iter, num::NonZeroU32, sync::Arc, time::Duration};
 use tokio::sync::{mpsc, oneshot};
 
 mod backend;
+mod identity_file;
 mod proto;
+mod socks5;
 mod sync;
 mod types;
 
@@ -65,6 +66,7 @@ const PING_MAX_RETRIES: u32 = 3;
 
 /// Request-response configuration
 const REQ_RESP_TIMEOUT: Duration = Duration::from_secs(10);
+const REQ_RESP_MAX_PENDING: usize = 16;
 
 // TODO: think about channel sizes
 const CHANNEL_SIZE: usize = 64;
@@ -205,31 +207,72 @@ where
 
     fn try_into(self) -> Result<net::PeerInfo<T>, Self::Error> {
         let proto = self.protocol_version.clone();
-        let (version, magic_bytes) =
-            match sscanf::scanf!(proto, "/{}/{}.{}.{}-{:x}", String, u8, u8, u16, u32) {
-                Err(_err) => Err(P2pError::ProtocolError(ProtocolError::InvalidProtocol)),
-                Ok((proto, maj, min, pat, magic)) => {
-                    if proto != "mintlayer" {
-                        return Err(P2pError::ProtocolError(ProtocolError::InvalidProtocol));
-                    }
-
-                    Ok((
-                        common::primitives::version::SemVer::new(maj, min, pat),
-                        magic.to_le_bytes(),
-                    ))
+        let (version, magic_bytes, services) = match sscanf::scanf!(
+            proto,
+            "/{}/{}.{}.{}-{:x}/{:x}",
+            String,
+            u8,
+            u8,
+            u16,
+            u32,
+            u64
+        ) {
+            Err(_err) => Err(P2pError::ProtocolError(ProtocolError::InvalidProtocol)),
+            Ok((proto, maj, min, pat, magic, services)) => {
+                if proto != "mintlayer" {
+                    return Err(P2pError::ProtocolError(ProtocolError::InvalidProtocol));
                 }
-            }?;
+
+                Ok((
+                    common::primitives::version::SemVer::new(maj, min, pat),
+                    magic.to_le_bytes(),
+                    Services::from_bits(services),
+                ))
+            }
+        }?;
 
         Ok(net::PeerInfo {
             peer_id: PeerId::from_public_key(&self.public_key),
             magic_bytes,
             version,
             agent: Some(self.agent_version),
+            services,
             protocols: self.protocols,
+            observed_addr: None,
         })
     }
 }
 
+/// Combine the IP address a remote peer observed the local node at with the local
+/// listening port and peer ID
+///
+/// The port of the observed address is not used as for outbound connections
+/// it is the ephemeral port the connection was opened from.
+fn external_address(observed: &Multiaddr, bind_addr: &Multiaddr) -> Option<Multiaddr> {
+    let ip = observed
+        .iter()
+        .find(|proto| matches!(proto, Protocol::Ip4(_) | Protocol::Ip6(_)))?;
+    let port = bind_addr
+        .iter()
+        .find(|proto| matches!(proto, Protocol::Tcp(port) if *port != 0))?;
+    let peer_id = bind_addr.iter().find(|proto| matches!(proto, Protocol::P2p(_)))?;
+
+    Some(Multiaddr::empty().with(ip).with(port).with(peer_id))
+}
+
+impl<T> Libp2pConnectivityHandle<T>
+where
+    T: NetworkingService<Address = Multiaddr, PeerId = PeerId>,
+    IdentifyInfo: TryInto<net::PeerInfo<T>, Error = P2pError>,
+{
+    fn peer_info(&self, info: IdentifyInfo) -> error::Result<net::PeerInfo<T>> {
+        let observed_addr = external_address(&info.observed_addr, &self.bind_addr);
+        let mut peer_info: net::PeerInfo<T> = info.try_into()?;
+        peer_info.observed_addr = observed_addr;
+        Ok(peer_info)
+    }
+}
+
 #[async_trait]
 impl NetworkingService for Libp2pService {
     type Address = Multiaddr;
@@ -240,30 +283,59 @@ impl NetworkingService for Libp2pService {
     type MessageId = MessageId;
     type ConnectivityHandle = Libp2pConnectivityHandle<Self>;
     type PubSubHandle = Libp2pPubSubHandle<Self>;
-    type SyncingCodecHandle = Libp2pSyncHandle<Self>;
+    type SyncingHandle = Libp2pSyncHandle<Self>;
+
+    // libp2p can only dial addresses that contain the peer ID of the remote node
+    fn from_socket_addr(_addr: std::net::SocketAddr) -> Option<Self::Address> {
+        None
+    }
+
+    fn to_socket_addr(addr: &Self::Address) -> Option<std::net::SocketAddr> {
+        let mut components = addr.iter();
+
+        let ip: std::net::IpAddr = match components.next()? {
+            Protocol::Ip4(ip) => ip.into(),
+            Protocol::Ip6(ip) => ip.into(),
+            _ => return None,
+        };
+
+        match components.next()? {
+            Protocol::Tcp(port) => Some(std::net::SocketAddr::new(ip, port)),
+            _ => None,
+        }
+    }
+
+    fn peer_id_from_addr(addr: &Self::Address) -> Option<Self::PeerId> {
+        match addr.iter().last()? {
+            Protocol::P2p(hash) => PeerId::from_multihash(hash).ok(),
+            _ => None,
+        }
+    }
 
     async fn start(
         bind_addr: Self::Address,
         strategies: &[Self::DiscoveryStrategy],
         topics: &[PubSubTopic],
         chain_config: Arc<common::chain::ChainConfig>,
+        p2p_config: Arc<crate::config::P2pConfig>,
         timeout: std::time::Duration,
     ) -> error::Result<(
         Self::ConnectivityHandle,
         Self::PubSubHandle,
-        Self::SyncingCodecHandle,
+        Self::SyncingHandle,
     )> {
-        let id_keys = identity::Keypair::generate_ed25519();
+        let id_keys = identity_file::load_or_generate(p2p_config.identity.as_ref())?;
         let peer_id = id_keys.public().to_peer_id();
+        log::info!("local peer id {}", peer_id);
         let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(&id_keys)?;
 
-        let transport = TcpConfig::new()
-            .nodelay(true)
-            .upgrade(upgrade::Version::V1)
-            .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
-            .multiplex(mplex::MplexConfig::new())
-            .outbound_timeout(timeout)
-            .boxed();
+        let transport =
+            socks5::Socks5Transport::new(TcpConfig::new().nodelay(true), p2p_config.proxy)
+                .upgrade(upgrade::Version::V1)
+                .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+                .multiplex(mplex::MplexConfig::new())
+                .outbound_timeout(timeout)
+                .boxed();
 
         let swarm = {
             let gossipsub_config = GossipsubConfigBuilder::default()
@@ -275,13 +347,15 @@ impl NetworkingService for Libp2pService {
                 .expect("configuration to be valid");
 
             // TODO: impl display for semver/magic bytes?
+            // identify has no field for the services so they're appended to the protocol
             let version = chain_config.version();
             let protocol = format!(
-                "/mintlayer/{}.{}.{}-{:x}",
+                "/mintlayer/{}.{}.{}-{:x}/{:x}",
                 version.major,
                 version.minor,
                 version.patch,
                 chain_config.magic_bytes_as_u32(),
+                p2p_config.services.bits(),
             );
             let mut req_cfg = RequestResponseConfig::default();
             req_cfg.set_request_timeout(REQ_RESP_TIMEOUT);
@@ -296,7 +370,10 @@ impl NetworkingService for Libp2pService {
                             NonZeroU32::new(PING_MAX_RETRIES).expect("max failures > 0"),
                         ),
                 ),
-                identify: Identify::new(IdentifyConfig::new(protocol, id_keys.public())),
+                identify: Identify::new(
+                    IdentifyConfig::new(protocol, id_keys.public())
+                        .with_agent_version(p2p_config.user_agent.clone()),
+                ),
                 sync: RequestResponse::new(
                     SyncingCodec(),
                     iter::once((SyncingProtocol(), ProtocolSupport::Full)),
@@ -361,7 +438,7 @@ impl NetworkingService for Libp2pService {
                 gossip_rx,
                 _marker: Default::default(),
             },
-            Self::SyncingCodecHandle {
+            Self::SyncingHandle {
                 cmd_tx,
                 sync_rx,
                 _marker: Default::default(),
@@ -410,7 +487,7 @@ where
             .map_err(|e| e)? // channel closed
             .map_err(|e| e)?; // command failure
 
-        Ok(info.try_into()?)
+        self.peer_info(info)
     }
 
     async fn disconnect(&mut self, peer_id: T::PeerId) -> error::Result<()> {
@@ -439,13 +516,13 @@ where
         match self.conn_rx.recv().await.ok_or(P2pError::ChannelClosed)? {
             types::ConnectivityEvent::ConnectionAccepted { peer_info } => {
                 Ok(ConnectivityEvent::ConnectionAccepted {
-                    peer_info: (*peer_info).try_into()?,
+                    peer_info: self.peer_info(*peer_info)?,
                 })
             }
             types::ConnectivityEvent::IncomingConnection { addr, peer_info } => {
                 Ok(ConnectivityEvent::IncomingConnection {
                     addr,
-                    peer_info: (*peer_info).try_into()?,
+                    peer_info: self.peer_info(*peer_info)?,
                 })
             }
             types::ConnectivityEvent::ConnectionClosed { peer_id } => {
@@ -463,6 +540,12 @@ where
             types::ConnectivityEvent::Error { peer_id, error } => {
                 Ok(ConnectivityEvent::Error { peer_id, error })
             }
+            types::ConnectivityEvent::PingResponse { peer_id, rtt } => {
+                Ok(ConnectivityEvent::PingResponse { peer_id, rtt })
+            }
+            types::ConnectivityEvent::PingTimeout { peer_id } => {
+                Ok(ConnectivityEvent::PingTimeout { peer_id })
+            }
             types::ConnectivityEvent::Misbehaved { peer_id, behaviour } => {
                 Ok(ConnectivityEvent::Misbehaved { peer_id, behaviour })
             }
@@ -489,7 +572,7 @@ where
         self.cmd_tx
             .send(types::Command::SendMessage {
                 topic,
-                message: message.encode(),
+                message: metrics::encode_sent(&message),
                 response: tx,
             })
             .await?;
@@ -537,7 +620,7 @@ where
 
 // TODO: move services to separate files + unit tests?
 #[async_trait]
-impl<T> SyncingCodecService<T> for Libp2pSyncHandle<T>
+impl<T> SyncingService<T> for Libp2pSyncHandle<T>
 where
     T: NetworkingService<PeerId = PeerId, MessageId = MessageId, RequestId = RequestId> + Send,
 {
@@ -550,7 +633,7 @@ where
         self.cmd_tx
             .send(types::Command::SendRequest {
                 peer_id,
-                request: Box::new(SyncRequest::new(message.encode())),
+                request: Box::new(SyncRequest::new(metrics::encode_sent(&message))),
                 response: tx,
             })
             .await?;
@@ -569,7 +652,7 @@ where
         self.cmd_tx
             .send(types::Command::SendResponse {
                 request_id,
-                response: Box::new(SyncResponse::new(message.encode())),
+                response: Box::new(SyncResponse::new(metrics::encode_sent(&message))),
                 channel: tx,
             })
             .await?;
@@ -586,7 +669,8 @@ where
                 request_id,
                 request,
             } => {
-                let request = message::Message::decode(&mut &(*request)[..]).map_err(|err| {
+                let request_len = request.len();
+                let request = message::Message::decode_checked(&request).map_err(|err| {
                     log::error!(
                         "invalid request received from peer {:?}: {:?}",
                         peer_id,
@@ -594,6 +678,7 @@ where
                     );
                     P2pError::ProtocolError(ProtocolError::InvalidMessage)
                 })?;
+                metrics::received(&request, request_len);
 
                 Ok(SyncingEvent::Request {
                     peer_id,
@@ -606,7 +691,8 @@ where
                 request_id,
                 response,
             } => {
-                let response = message::Message::decode(&mut &(*response)[..]).map_err(|err| {
+                let response_len = response.len();
+                let response = message::Message::decode_checked(&response).map_err(|err| {
                     log::error!(
                         "invalid response received from peer {:?}: {:?}",
                         peer_id,
@@ -614,6 +700,7 @@ where
                     );
                     P2pError::ProtocolError(ProtocolError::InvalidMessage)
                 })?;
+                metrics::received(&response, response_len);
 
                 Ok(SyncingEvent::Response {
                     peer_id,
@@ -639,6 +726,7 @@ where
 mod tests {
     use super::*;
     use crate::net;
+    use serialization::{Decode, Encode};
     use std::time::Duration;
     use tokio::net::TcpListener;
 
@@ -648,6 +736,33 @@ mod tests {
         value: u128,
     }
 
+    #[test]
+    fn external_addresses() {
+        let peer_id = PeerId::random();
+        let bind_addr: Multiaddr = format!("/ip6/::/tcp/3031/p2p/{}", peer_id).parse().unwrap();
+
+        assert_eq!(
+            external_address(&"/ip4/1.2.3.4/tcp/55555".parse().unwrap(), &bind_addr),
+            Some(format!("/ip4/1.2.3.4/tcp/3031/p2p/{}", peer_id).parse().unwrap())
+        );
+        assert_eq!(
+            external_address(&"/ip6/2001:db8::1/tcp/55555".parse().unwrap(), &bind_addr),
+            Some(format!("/ip6/2001:db8::1/tcp/3031/p2p/{}", peer_id).parse().unwrap())
+        );
+        assert_eq!(
+            external_address(&"/dns4/example.com/tcp/55555".parse().unwrap(), &bind_addr),
+            None
+        );
+        assert_eq!(external_address(&Multiaddr::empty(), &bind_addr), None);
+
+        // the listening port is not known
+        let bind_addr: Multiaddr = format!("/ip6/::/tcp/0/p2p/{}", peer_id).parse().unwrap();
+        assert_eq!(
+            external_address(&"/ip4/1.2.3.4/tcp/55555".parse().unwrap(), &bind_addr),
+            None
+        );
+    }
+
     #[tokio::test]
     async fn test_connect_new() {
         let config = Arc::new(common::chain::config::create_mainnet());
@@ -656,6 +771,7 @@ mod tests {
             &[],
             &[],
             config,
+            Default::default(),
             Duration::from_secs(10),
         )
         .await;
@@ -672,6 +788,7 @@ mod tests {
             &[],
             &[],
             Arc::clone(&config),
+            Default::default(),
             Duration::from_secs(10),
         )
         .await;
@@ -682,6 +799,7 @@ mod tests {
             &[],
             &[],
             config,
+            Default::default(),
             Duration::from_secs(10),
         )
         .await;
@@ -704,6 +822,7 @@ mod tests {
             &[],
             &[],
             Arc::clone(&config),
+            Default::default(),
             Duration::from_secs(10),
         )
         .await;
@@ -712,6 +831,7 @@ mod tests {
             &[],
             &[],
             Arc::clone(&config),
+            Default::default(),
             Duration::from_secs(10),
         )
         .await;
@@ -740,6 +860,7 @@ mod tests {
             &[],
             &[],
             config,
+            Default::default(),
             Duration::from_secs(10),
         )
         .await
@@ -829,10 +950,54 @@ mod tests {
                 && self.magic_bytes == other.magic_bytes
                 && self.version == other.version
                 && self.agent == other.agent
+                && self.services == other.services
                 && self.protocols == other.protocols
         }
     }
 
+    #[test]
+    fn test_to_socket_addr() {
+        assert_eq!(
+            Libp2pService::to_socket_addr(&"/ip4/127.0.0.1/tcp/9090".parse().unwrap()),
+            Some("127.0.0.1:9090".parse().unwrap())
+        );
+        assert_eq!(
+            Libp2pService::to_socket_addr(
+                &"/ip6/::1/tcp/9091/p2p/12D3KooWE3kBRAnn6jxZMdK1JMWx1iHtR1NKzXSRv5HLTmfD9u9c"
+                    .parse()
+                    .unwrap()
+            ),
+            Some("[::1]:9091".parse().unwrap())
+        );
+        assert_eq!(
+            Libp2pService::to_socket_addr(&"/ip4/127.0.0.1/udp/9090/quic".parse().unwrap()),
+            None
+        );
+        assert_eq!(
+            Libp2pService::to_socket_addr(&"/dns4/example.com/tcp/9090".parse().unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_peer_id_from_addr() {
+        let peer_id: PeerId =
+            "12D3KooWE3kBRAnn6jxZMdK1JMWx1iHtR1NKzXSRv5HLTmfD9u9c".parse().unwrap();
+
+        assert_eq!(
+            Libp2pService::peer_id_from_addr(
+                &"/ip6/::1/tcp/9091/p2p/12D3KooWE3kBRAnn6jxZMdK1JMWx1iHtR1NKzXSRv5HLTmfD9u9c"
+                    .parse()
+                    .unwrap()
+            ),
+            Some(peer_id)
+        );
+        assert_eq!(
+            Libp2pService::peer_id_from_addr(&"/ip4/127.0.0.1/tcp/9090".parse().unwrap()),
+            None
+        );
+    }
+
     // verify that vector of address (that all belong to one peer) parse into one `net::Peer` entry
     #[test]
     fn test_parse_peers_valid_1_peer() {
@@ -930,6 +1095,7 @@ mod tests {
             &[],
             &[],
             config,
+            Default::default(),
             Duration::from_secs(2),
         )
         .await
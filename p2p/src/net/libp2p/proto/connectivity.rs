@@ -111,6 +111,7 @@ impl Backend {
 
     pub async fn on_connection_closed(&mut self, peer_id: PeerId) -> error::Result<()> {
         self.established_conns.remove(&peer_id);
+        self.clear_pending_requests(&peer_id);
         self.conn_tx
             .send(types::ConnectivityEvent::ConnectionClosed { peer_id })
             .await
@@ -18,11 +18,11 @@
 use crate::{
     error::{self, P2pError, ProtocolError},
     message::Message,
+    metrics,
     net::libp2p::{backend::Backend, types},
 };
 use libp2p::gossipsub::GossipsubEvent;
 use logging::log;
-use serialization::Decode;
 
 impl Backend {
     pub async fn on_gossipsub_event(&mut self, event: GossipsubEvent) -> error::Result<()> {
@@ -51,8 +51,11 @@ impl Backend {
                     propagation_source
                 );
 
-                let message = match Message::decode(&mut &message.data[..]) {
-                    Ok(data) => data,
+                let message = match Message::decode_checked(&message.data) {
+                    Ok(data) => {
+                        metrics::received(&data, message.data.len());
+                        data
+                    }
                     Err(_) => {
                         log::warn!(
                             "received invalid message, propagation source: {:?}",
@@ -62,13 +65,7 @@ impl Backend {
                         if self.swarm.is_connected(&propagation_source) {
                             log::info!("notify swarm manager about invalid message");
 
-                            // TODO: implement peer profiling
-                            self.conn_tx
-                                .send(types::ConnectivityEvent::Misbehaved {
-                                    peer_id: propagation_source,
-                                    behaviour: 0,
-                                })
-                                .await?;
+                            self.invalid_message_received(propagation_source).await?;
                         }
 
                         return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
@@ -252,7 +249,7 @@ mod tests {
             conn_rx.try_recv(),
             Ok(types::ConnectivityEvent::Misbehaved {
                 peer_id: *backend2.swarm.local_peer_id(),
-                behaviour: 0u32,
+                behaviour: net::INVALID_MESSAGE_SCORE,
             })
         );
     }
@@ -28,9 +28,16 @@ impl Backend {
                 peer,
                 result: Result::Ok(ping::Success::Ping { rtt }),
             } => {
-                // TODO: report rtt to swarm manager?
                 log::debug!("peer {:?} responded to ping, rtt {:?}", peer, rtt);
-                Ok(())
+
+                // the frontend learns about the peer only after identify has finished
+                if !self.established_conns.contains(&peer) {
+                    return Ok(());
+                }
+                self.conn_tx
+                    .send(types::ConnectivityEvent::PingResponse { peer_id: peer, rtt })
+                    .await
+                    .map_err(P2pError::from)
             }
             ping::Event {
                 peer,
@@ -44,7 +51,14 @@ impl Backend {
                 result: Result::Err(ping::Failure::Timeout),
             } => {
                 log::warn!("ping timeout for peer {:?}", peer);
-                Ok(())
+
+                if !self.established_conns.contains(&peer) {
+                    return Ok(());
+                }
+                self.conn_tx
+                    .send(types::ConnectivityEvent::PingTimeout { peer_id: peer })
+                    .await
+                    .map_err(P2pError::from)
             }
             ping::Event {
                 peer,
@@ -76,7 +90,7 @@ mod tests {
     use libp2p::{
         ping,
         swarm::{SwarmBuilder, SwarmEvent},
-        Multiaddr,
+        Multiaddr, PeerId,
     };
     use std::time::Duration;
 
@@ -127,6 +141,62 @@ mod tests {
         }
     }
 
+    // verify that ping results are reported to the frontend
+    #[tokio::test]
+    async fn test_ping_results_reported() {
+        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+        let (mut backend, _, mut conn_rx, _gossip_rx, _) =
+            util::make_libp2p(common::chain::config::create_mainnet(), addr, &[]).await;
+        let peer = PeerId::random();
+
+        // results of peers that haven't finished identify are not reported
+        assert_eq!(
+            backend
+                .on_ping_event(ping::Event {
+                    peer,
+                    result: Err(ping::Failure::Timeout),
+                })
+                .await,
+            Ok(())
+        );
+        assert!(conn_rx.try_recv().is_err());
+
+        backend.established_conns.insert(peer);
+
+        assert_eq!(
+            backend
+                .on_ping_event(ping::Event {
+                    peer,
+                    result: Ok(ping::Success::Ping {
+                        rtt: Duration::from_millis(150)
+                    }),
+                })
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            conn_rx.try_recv(),
+            Ok(types::ConnectivityEvent::PingResponse {
+                peer_id: peer,
+                rtt: Duration::from_millis(150)
+            })
+        );
+
+        assert_eq!(
+            backend
+                .on_ping_event(ping::Event {
+                    peer,
+                    result: Err(ping::Failure::Timeout),
+                })
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            conn_rx.try_recv(),
+            Ok(types::ConnectivityEvent::PingTimeout { peer_id: peer })
+        );
+    }
+
     #[tokio::test]
     async fn test_remote_doesnt_respond() {
         // TODO: add better test utilites
@@ -16,15 +16,71 @@
 // Author(s): A. Altonen
 use crate::{
     error::{self, P2pError},
-    net::libp2p::{backend::Backend, types, SyncRequest, SyncResponse},
+    message::Message,
+    net::libp2p::{backend::Backend, types, SyncRequest, SyncResponse, REQ_RESP_MAX_PENDING},
     net::RequestResponseError,
 };
-use libp2p::request_response::{
-    InboundFailure, OutboundFailure, RequestResponseEvent, RequestResponseMessage,
+use libp2p::{
+    request_response::{
+        InboundFailure, OutboundFailure, RequestId, RequestResponseEvent, RequestResponseMessage,
+    },
+    PeerId,
 };
 use logging::log;
 
 impl Backend {
+    /// Remove outbound request from the set of requests waiting for a response
+    fn outbound_request_done(&mut self, peer_id: &PeerId, request_id: &RequestId) {
+        if let Some(pending) = self.outbound_reqs.get_mut(peer_id) {
+            pending.remove(request_id);
+
+            if pending.is_empty() {
+                self.outbound_reqs.remove(peer_id);
+            }
+        }
+    }
+
+    /// Check whether an inbound request from `peer_id` can be accepted
+    ///
+    /// If the peer already has the maximum number of unanswered requests, the request
+    /// is recorded as rejected so the resulting `ResponseOmission` can be told apart
+    /// from a response that was omitted by mistake.
+    pub fn inbound_request_received(&mut self, peer_id: PeerId, request_id: RequestId) -> bool {
+        let pending = self.inbound_reqs.entry(peer_id).or_default();
+
+        if pending.len() >= REQ_RESP_MAX_PENDING {
+            log::warn!(
+                "peer {:?} has {} unanswered requests, reject request {:?}",
+                peer_id,
+                pending.len(),
+                request_id
+            );
+            self.rejected_reqs.insert(request_id);
+            return false;
+        }
+
+        pending.insert(request_id);
+        true
+    }
+
+    /// Remove inbound request from the set of requests waiting for a response
+    pub fn inbound_request_done(&mut self, peer_id: &PeerId, request_id: &RequestId) {
+        if let Some(pending) = self.inbound_reqs.get_mut(peer_id) {
+            pending.remove(request_id);
+
+            if pending.is_empty() {
+                self.inbound_reqs.remove(peer_id);
+            }
+        }
+    }
+
+    /// Forget all pending requests of a peer whose connection was closed
+    pub fn clear_pending_requests(&mut self, peer_id: &PeerId) {
+        self.outbound_reqs.remove(peer_id);
+        self.inbound_reqs.remove(peer_id);
+        self.pending_reqs.retain(|_, (peer, _)| peer != peer_id);
+    }
+
     pub async fn on_sync_event(
         &mut self,
         event: RequestResponseEvent<SyncRequest, SyncResponse>,
@@ -36,7 +92,17 @@ impl Backend {
                     request,
                     channel,
                 } => {
-                    self.pending_reqs.insert(request_id, channel);
+                    // drop the response channel which causes libp2p to notify
+                    // the remote peer that the request was not answered
+                    if let Err(err) = Message::check_limits(&request) {
+                        log::warn!("invalid request from peer {:?}: {:?}", peer, err);
+                        return self.invalid_message_received(peer).await;
+                    }
+                    if !self.inbound_request_received(peer, request_id) {
+                        return Ok(());
+                    }
+
+                    self.pending_reqs.insert(request_id, (peer, channel));
                     self.sync_tx
                         .send(types::SyncingEvent::Request {
                             peer_id: peer,
@@ -49,15 +115,23 @@ impl Backend {
                 RequestResponseMessage::Response {
                     request_id,
                     response,
-                } => self
-                    .sync_tx
-                    .send(types::SyncingEvent::Response {
-                        peer_id: peer,
-                        request_id,
-                        response: Box::new(response),
-                    })
-                    .await
-                    .map_err(|_| P2pError::ChannelClosed),
+                } => {
+                    self.outbound_request_done(&peer, &request_id);
+
+                    // the request is left unanswered and times out in SyncManager
+                    if let Err(err) = Message::check_limits(&response) {
+                        log::warn!("invalid response from peer {:?}: {:?}", peer, err);
+                        return self.invalid_message_received(peer).await;
+                    }
+                    self.sync_tx
+                        .send(types::SyncingEvent::Response {
+                            peer_id: peer,
+                            request_id,
+                            response: Box::new(response),
+                        })
+                        .await
+                        .map_err(|_| P2pError::ChannelClosed)
+                }
             },
             RequestResponseEvent::ResponseSent {
                 peer: _,
@@ -71,6 +145,8 @@ impl Backend {
                 request_id,
                 error,
             } => {
+                self.outbound_request_done(&peer, &request_id);
+
                 match error {
                     OutboundFailure::Timeout => self
                         .sync_tx
@@ -105,6 +181,10 @@ impl Backend {
                 request_id,
                 error,
             } => {
+                self.pending_reqs.remove(&request_id);
+                self.inbound_request_done(&peer, &request_id);
+                let rejected = self.rejected_reqs.remove(&request_id);
+
                 match error {
                     InboundFailure::Timeout => self
                         .sync_tx
@@ -125,7 +205,14 @@ impl Backend {
                         .await
                         .map_err(|_| P2pError::ChannelClosed),
                     InboundFailure::ResponseOmission => {
-                        log::error!("CRITICAL(??): response omitted!");
+                        if rejected {
+                            log::debug!(
+                                "request {:?} rejected, too many pending requests",
+                                request_id
+                            );
+                        } else {
+                            log::error!("CRITICAL(??): response omitted!");
+                        }
                         Ok(())
                     }
                     InboundFailure::UnsupportedProtocols => {
@@ -0,0 +1,335 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transport that routes outbound connections through a SOCKS5 proxy
+//!
+//! Inbound connections are accepted by the wrapped transport as usual. Outbound
+//! connections are dialed to the proxy and the destination is given to it as an
+//! IP address or a hostname, so `.onion` addresses can be dialed through Tor and
+//! hostnames are never resolved locally.
+
+use futures::{
+    future::BoxFuture,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    FutureExt,
+};
+use libp2p::{
+    core::transport::{Transport, TransportError},
+    multiaddr::Protocol,
+    Multiaddr,
+};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+const SOCKS_VERSION: u8 = 0x05;
+const AUTH_NONE: u8 = 0x00;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+const REPLY_SUCCEEDED: u8 = 0x00;
+
+/// Destination of a connection made through the proxy
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Destination {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+impl Destination {
+    /// Get the destination of a multiaddress that the proxy can connect to
+    ///
+    /// Supported forms are `/ip4`, `/ip6`, `/dns`, `/dns4` and `/dns6` followed by `/tcp`,
+    /// and `/onion3`. Trailing `/p2p` component is ignored.
+    fn from_multiaddr(addr: &Multiaddr) -> Option<Self> {
+        let mut components = addr.iter();
+
+        let host = match components.next()? {
+            Protocol::Ip4(ip) => Err(IpAddr::V4(ip)),
+            Protocol::Ip6(ip) => Err(IpAddr::V6(ip)),
+            Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host) => {
+                Ok(host.to_string())
+            }
+            onion @ Protocol::Onion3(_) => {
+                // the hostname is the base32-encoded key which is also used in the textual form
+                let text = onion.to_string();
+                let (host, port) = text.strip_prefix("/onion3/")?.split_once(':')?;
+                return Some(Destination::Domain(
+                    format!("{}.onion", host),
+                    port.parse().ok()?,
+                ));
+            }
+            _ => return None,
+        };
+
+        let port = match components.next()? {
+            Protocol::Tcp(port) => port,
+            _ => return None,
+        };
+
+        Some(match host {
+            Ok(host) => Destination::Domain(host, port),
+            Err(ip) => Destination::Ip(SocketAddr::new(ip, port)),
+        })
+    }
+
+    /// Encode the destination as the address part of a SOCKS5 request
+    fn encode(&self) -> io::Result<Vec<u8>> {
+        let (mut buf, port) = match self {
+            Destination::Ip(SocketAddr::V4(addr)) => (
+                [&[ATYP_IPV4][..], &addr.ip().octets()].concat(),
+                addr.port(),
+            ),
+            Destination::Ip(SocketAddr::V6(addr)) => (
+                [&[ATYP_IPV6][..], &addr.ip().octets()].concat(),
+                addr.port(),
+            ),
+            Destination::Domain(host, port) => {
+                let len = u8::try_from(host.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "hostname is too long")
+                })?;
+                ([&[ATYP_DOMAIN, len][..], host.as_bytes()].concat(), *port)
+            }
+        };
+
+        buf.extend_from_slice(&port.to_be_bytes());
+        Ok(buf)
+    }
+}
+
+/// Ask the proxy to connect `stream` to `dest`
+async fn connect<S>(mut stream: S, dest: Destination) -> io::Result<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let protocol_error = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+    stream.write_all(&[SOCKS_VERSION, 1, AUTH_NONE]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [SOCKS_VERSION, AUTH_NONE] {
+        return Err(protocol_error("proxy requires authentication"));
+    }
+
+    stream
+        .write_all(&[&[SOCKS_VERSION, CMD_CONNECT, 0x00][..], &dest.encode()?].concat())
+        .await?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(protocol_error("invalid proxy reply"));
+    }
+    if reply[1] != REPLY_SUCCEEDED {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy failed to connect, reply code {}", reply[1]),
+        ));
+    }
+
+    // skip the address the proxy bound for the connection
+    let addr_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(protocol_error("invalid address type in proxy reply")),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(stream)
+}
+
+/// Transport wrapper that dials all outbound connections through a SOCKS5 proxy
+///
+/// If no proxy is configured, the wrapped transport is used as is.
+#[derive(Debug, Clone)]
+pub struct Socks5Transport<T> {
+    inner: T,
+    proxy: Option<SocketAddr>,
+}
+
+impl<T> Socks5Transport<T> {
+    pub fn new(inner: T, proxy: Option<SocketAddr>) -> Self {
+        Self { inner, proxy }
+    }
+}
+
+impl<T> Transport for Socks5Transport<T>
+where
+    T: Transport<Error = io::Error>,
+    T::Dial: Send + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = T::Output;
+    type Error = io::Error;
+    type Listener = T::Listener;
+    type ListenerUpgrade = T::ListenerUpgrade;
+    type Dial = BoxFuture<'static, io::Result<T::Output>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        self.inner.listen_on(addr)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let proxy = match self.proxy {
+            Some(proxy) => proxy,
+            None => return self.inner.dial(addr).map(FutureExt::boxed),
+        };
+
+        let dest = Destination::from_multiaddr(&addr)
+            .ok_or(TransportError::MultiaddrNotSupported(addr))?;
+        let proxy_addr = Multiaddr::empty()
+            .with(match proxy.ip() {
+                IpAddr::V4(ip) => Protocol::Ip4(ip),
+                IpAddr::V6(ip) => Protocol::Ip6(ip),
+            })
+            .with(Protocol::Tcp(proxy.port()));
+        let dial = self.inner.dial(proxy_addr).map_err(|err| match err {
+            // the address that isn't supported is the proxy address, not the dialed address
+            TransportError::MultiaddrNotSupported(_) => TransportError::Other(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "proxy address not supported by the transport",
+            )),
+            err => err,
+        })?;
+
+        Ok(async move { connect(dial.await?, dest).await }.boxed())
+    }
+
+    fn dial_as_listener(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        // connections through the proxy can't be used for hole punching
+        self.dial(addr)
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::tcp::TcpConfig;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+    };
+
+    const ONION: &str = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+
+    #[test]
+    fn destinations() {
+        let dest = |addr: &str| Destination::from_multiaddr(&addr.parse().unwrap());
+
+        assert_eq!(
+            dest("/ip4/1.2.3.4/tcp/8888"),
+            Some(Destination::Ip("1.2.3.4:8888".parse().unwrap()))
+        );
+        assert_eq!(
+            dest("/ip6/::1/tcp/8888/p2p/12D3KooWGBS2QhYFMpQ2JbyQbwxAwfnQ1vETc9F4fSrsSUQKEZS8"),
+            Some(Destination::Ip("[::1]:8888".parse().unwrap()))
+        );
+        assert_eq!(
+            dest("/dns4/seed.mintlayer.org/tcp/3031"),
+            Some(Destination::Domain("seed.mintlayer.org".to_string(), 3031))
+        );
+        assert_eq!(
+            dest(&format!("/onion3/{}:1234", ONION)),
+            Some(Destination::Domain(format!("{}.onion", ONION), 1234))
+        );
+        assert_eq!(dest("/ip4/1.2.3.4/udp/8888"), None);
+        assert_eq!(dest("/memory/1"), None);
+    }
+
+    #[test]
+    fn encode_destinations() {
+        assert_eq!(
+            Destination::Ip("1.2.3.4:258".parse().unwrap()).encode().unwrap(),
+            vec![ATYP_IPV4, 1, 2, 3, 4, 1, 2]
+        );
+        assert_eq!(
+            Destination::Domain("a.b".to_string(), 80).encode().unwrap(),
+            vec![ATYP_DOMAIN, 3, b'a', b'.', b'b', 0, 80]
+        );
+        assert!(Destination::Domain("a".repeat(256), 80).encode().is_err());
+    }
+
+    // serve a single connection with a minimal SOCKS5 proxy that echoes the data it receives
+    async fn run_proxy(listener: TcpListener) -> Vec<u8> {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0u8; 3];
+        socket.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [SOCKS_VERSION, 1, AUTH_NONE]);
+        socket.write_all(&[SOCKS_VERSION, AUTH_NONE]).await.unwrap();
+
+        let mut request = [0u8; 5];
+        socket.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request[..4], &[SOCKS_VERSION, CMD_CONNECT, 0, ATYP_DOMAIN]);
+        let mut host = vec![0u8; request[4] as usize + 2];
+        socket.read_exact(&mut host).await.unwrap();
+        socket
+            .write_all(&[SOCKS_VERSION, REPLY_SUCCEEDED, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+
+        let mut data = [0u8; 4];
+        socket.read_exact(&mut data).await.unwrap();
+        socket.write_all(&data).await.unwrap();
+        host
+    }
+
+    #[tokio::test]
+    async fn dial_onion_through_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        let server = tokio::spawn(run_proxy(listener));
+
+        let transport = Socks5Transport::new(TcpConfig::new(), Some(proxy));
+        let mut stream = transport
+            .dial(format!("/onion3/{}:1234", ONION).parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut data = [0u8; 4];
+        stream.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"ping");
+
+        let host = server.await.unwrap();
+        assert_eq!(
+            &host[..host.len() - 2],
+            format!("{}.onion", ONION).as_bytes()
+        );
+        assert_eq!(&host[host.len() - 2..], &1234u16.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn onion_requires_proxy() {
+        let transport = Socks5Transport::new(TcpConfig::new(), None);
+
+        assert!(std::matches!(
+            transport.dial(format!("/onion3/{}:1234", ONION).parse().unwrap()),
+            Err(TransportError::MultiaddrNotSupported(_))
+        ));
+    }
+}
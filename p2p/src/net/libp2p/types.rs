@@ -32,6 +32,7 @@ use libp2p::{
     request_response::{RequestId, RequestResponse, RequestResponseEvent},
     Multiaddr, NetworkBehaviour, PeerId,
 };
+use std::time::Duration;
 use tokio::sync::oneshot;
 
 // TODO: rename `response` -> `channel`
@@ -117,6 +118,12 @@ pub enum ConnectivityEvent {
         error: error::P2pError,
     },
 
+    /// Peer responded to a ping
+    PingResponse { peer_id: PeerId, rtt: Duration },
+
+    /// Peer didn't respond to a ping in time
+    PingTimeout { peer_id: PeerId },
+
     /// Peer misbehaved
     Misbehaved { peer_id: PeerId, behaviour: u32 },
 }
@@ -14,24 +14,132 @@
 // limitations under the License.
 //
 // Author(s): A. Altonen
+
+//! Backend of the mock networking service
+//!
+//! The backend accepts and opens connections over its [transport](super::transport), TCP
+//! or a simulated network, and runs one task per connection
+//! that reads and writes the frames of the connection. All other state, the peers,
+//! the pending requests and the pubsub messages waiting for validation, is owned by
+//! the backend and only modified from its event loop, so the order in which events
+//! are handled only depends on the order in which they are received. The event
+//! sources are polled in a fixed order for the same reason.
+
 use crate::{
-    error::{self, P2pError},
-    net::mock::types,
+    config::P2pConfig,
+    error::{self, P2pError, ProtocolError},
+    message, metrics,
+    net::{
+        self,
+        mock::{
+            socket::HandshakeRole,
+            transport::{Dialer, Listener, PeerSocket},
+            types, MockMessageId, MockRequestId,
+        },
+    },
+};
+use common::{
+    chain::{services::Services, ChainConfig},
+    primitives::version::SemVer,
 };
-use futures::FutureExt;
+use crypto::key::PrivateKey;
 use logging::log;
-use std::{io::ErrorKind, net::SocketAddr};
-use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::mpsc,
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+    io::ErrorKind,
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
 };
+use tokio::{sync::mpsc, time::Instant};
+
+/// How often the pending requests and pings are checked
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often each peer is pinged
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum number of pubsub message IDs remembered for ignoring duplicate announcements
+const MAX_SEEN_MESSAGES: usize = 1024;
+
+/// Protocols the mock backend implements
+///
+/// The names are the ones the libp2p backend uses for the same protocols,
+/// so that peers are validated the same way regardless of the backend.
+const PROTOCOLS: [&str; 2] = ["/mintlayer/sync/0.1.0", "/meshsub/1.1.0"];
+
+/// Information the local node sends in its `Hello` message
+#[derive(Clone)]
+struct LocalInfo {
+    magic_bytes: [u8; 4],
+    version: SemVer,
+    agent: String,
+    services: Services,
+    listen_port: u16,
+}
+
+impl LocalInfo {
+    fn hello(&self, observed_addr: SocketAddr) -> types::PeerMessage {
+        types::PeerMessage::Hello {
+            magic_bytes: self.magic_bytes,
+            version: self.version,
+            agent: self.agent.clone(),
+            services: self.services,
+            protocols: PROTOCOLS.iter().map(|protocol| protocol.to_string()).collect(),
+            listen_port: self.listen_port,
+            observed_addr: observed_addr.to_string(),
+        }
+    }
+}
+
+/// Events sent to the backend by the tasks it spawns
+enum Event {
+    /// Handshake with a remote peer finished
+    ///
+    /// `response` is set for outbound connections and receives the peer information.
+    Connected {
+        socket: PeerSocket,
+        peer_info: types::PeerInfo,
+        response: Option<tokio::sync::oneshot::Sender<error::Result<types::PeerInfo>>>,
+    },
+
+    /// Message received from a peer
+    Message {
+        conn_id: u64,
+        peer_id: SocketAddr,
+        message: types::PeerMessage,
+    },
+
+    /// Connection to a peer was closed by the remote peer or because of an error
+    Closed { conn_id: u64, peer_id: SocketAddr },
+}
+
+struct PeerContext {
+    /// Unique ID of the connection, used to ignore events of a previous connection
+    conn_id: u64,
+
+    /// TX channel for sending messages to the connection task
+    tx: mpsc::UnboundedSender<types::PeerMessage>,
+
+    /// Nonce and send time of the ping that hasn't been answered yet
+    ping: Option<(u64, Instant)>,
+
+    /// When the peer was last pinged
+    last_ping: Instant,
+}
+
+struct PendingRequest {
+    peer_id: SocketAddr,
+    sent: Instant,
+}
 
 pub struct Backend {
     /// Socket address of the backend
     addr: SocketAddr,
 
-    /// Socket for listening to incoming connections
-    socket: TcpListener,
+    /// Listener for incoming connections
+    listener: Listener,
 
     /// RX channel for receiving commands from the frontend
     cmd_rx: mpsc::Receiver<types::Command>,
@@ -40,66 +148,823 @@ pub struct Backend {
     conn_tx: mpsc::Sender<types::ConnectivityEvent>,
 
     /// TX channel for sending events to the frontend
-    _pubsub_tx: mpsc::Sender<types::PubSubEvent>,
+    pubsub_tx: mpsc::Sender<types::PubSubEvent>,
+
+    /// TX channel for sending events to the frontend
+    sync_tx: mpsc::Sender<types::SyncingEvent>,
 
     /// Timeout for outbound operations
-    timeout: std::time::Duration,
+    timeout: Duration,
+
+    /// Static key used to authenticate the connections
+    static_key: PrivateKey,
+
+    /// Information sent to peers in the `Hello` message
+    local_info: LocalInfo,
+
+    /// Pubsub topics the local node is subscribed to
+    topics: HashSet<net::PubSubTopic>,
+
+    /// Channel for receiving events from the connection tasks
+    event_tx: mpsc::UnboundedSender<Event>,
+    event_rx: mpsc::UnboundedReceiver<Event>,
+
+    /// Connected peers
+    peers: HashMap<SocketAddr, PeerContext>,
+
+    /// Requests sent to peers that haven't been answered yet
+    outbound_requests: HashMap<u64, PendingRequest>,
+
+    /// Requests received from peers and the IDs the peers gave to them
+    inbound_requests: HashMap<u64, (SocketAddr, u64)>,
+
+    /// Pubsub messages waiting for validation and the peers they were received from
+    pending_validation: HashMap<u64, (SocketAddr, Vec<u8>)>,
+
+    /// IDs of the pubsub messages that have been received or published, oldest first
+    seen_messages: VecDeque<u64>,
+
+    /// Counter used for connection and request IDs and ping nonces
+    next_id: u64,
+}
+
+/// ID of a pubsub message, derived from its contents so duplicates can be detected
+fn message_id(message: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    message.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Pubsub topic of `message` or `None` if the message can't be published
+pub fn message_topic(message: &message::Message) -> Option<net::PubSubTopic> {
+    match message.msg {
+        message::MessageType::PubSub(message::PubSubMessage::Block(_)) => {
+            Some(net::PubSubTopic::Blocks)
+        }
+        message::MessageType::Syncing(_) => None,
+    }
+}
+
+/// Read and write the frames of one connection until it's closed
+///
+/// The task exits without an event if the backend drops the TX end of `rx`,
+/// i.e., when the local node closes the connection.
+async fn run_connection(
+    conn_id: u64,
+    peer_id: SocketAddr,
+    mut socket: PeerSocket,
+    mut rx: mpsc::UnboundedReceiver<types::PeerMessage>,
+    event_tx: mpsc::UnboundedSender<Event>,
+) {
+    loop {
+        tokio::select! {
+            biased;
+            message = socket.recv_peer_message() => match message {
+                Ok(message) => {
+                    if event_tx.send(Event::Message { conn_id, peer_id, message }).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    log::debug!("connection to peer {} closed: {:?}", peer_id, err);
+                    break;
+                }
+            },
+            message = rx.recv() => match message {
+                Some(message) => {
+                    if let Err(err) = socket.send_peer_message(&message).await {
+                        log::debug!("failed to send message to peer {}: {:?}", peer_id, err);
+                        break;
+                    }
+                }
+                None => return,
+            },
+        }
+    }
+
+    let _ = event_tx.send(Event::Closed { conn_id, peer_id });
 }
 
 impl Backend {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         addr: SocketAddr,
-        socket: TcpListener,
+        listener: Listener,
         cmd_rx: mpsc::Receiver<types::Command>,
         conn_tx: mpsc::Sender<types::ConnectivityEvent>,
-        _pubsub_tx: mpsc::Sender<types::PubSubEvent>,
-        _sync_tx: mpsc::Sender<types::SyncingEvent>,
-        timeout: std::time::Duration,
+        pubsub_tx: mpsc::Sender<types::PubSubEvent>,
+        sync_tx: mpsc::Sender<types::SyncingEvent>,
+        timeout: Duration,
+        static_key: PrivateKey,
+        config: Arc<ChainConfig>,
+        p2p_config: Arc<P2pConfig>,
+        topics: &[net::PubSubTopic],
     ) -> Self {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+
         Self {
             addr,
-            socket,
+            listener,
             cmd_rx,
             conn_tx,
-            _pubsub_tx,
+            pubsub_tx,
+            sync_tx,
             timeout,
+            static_key,
+            local_info: LocalInfo {
+                magic_bytes: *config.magic_bytes(),
+                version: *config.version(),
+                agent: p2p_config.user_agent.clone(),
+                services: p2p_config.services,
+                listen_port: addr.port(),
+            },
+            topics: topics.iter().cloned().collect(),
+            event_tx,
+            event_rx,
+            peers: HashMap::new(),
+            outbound_requests: HashMap::new(),
+            inbound_requests: HashMap::new(),
+            pending_validation: HashMap::new(),
+            seen_messages: VecDeque::new(),
+            next_id: 0,
         }
     }
 
     pub async fn run(&mut self) -> error::Result<()> {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+
         loop {
             tokio::select! {
-                event = self.socket.accept() => match event {
-                    Ok(socket) => self.conn_tx.send(types::ConnectivityEvent::IncomingConnection {
-                        peer_id: socket.1,
-                        socket: socket.0,
-                    }).await?,
+                biased;
+                event = self.listener.accept() => match event {
+                    Ok((socket, remote_addr)) => self.on_inbound_connection(socket, remote_addr),
                     Err(e) => {
                         log::error!("accept() failed: {:?}", e);
                         return Err(P2pError::SocketError(e.kind()));
                     }
                 },
-                event = self.cmd_rx.recv().fuse() => match event.ok_or(P2pError::ChannelClosed)? {
-                    types::Command::Connect { addr, response } => {
-                        if self.addr == addr {
-                            let _ = response.send(Err(P2pError::SocketError(ErrorKind::AddrNotAvailable)));
-                            continue;
+                event = self.event_rx.recv() => {
+                    self.on_event(event.ok_or(P2pError::ChannelClosed)?).await;
+                }
+                command = self.cmd_rx.recv() => {
+                    self.on_command(command.ok_or(P2pError::ChannelClosed)?).await;
+                }
+                _ = heartbeat.tick() => self.on_heartbeat().await,
+            }
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Run the handshake and exchange `Hello` messages with the remote peer
+    ///
+    /// For outbound connections the peer is identified by the address it was dialed at.
+    /// For inbound connections the remote address has an ephemeral port, so the peer is
+    /// identified by the remote IP address and the port the peer says it listens on.
+    async fn establish(
+        mut socket: PeerSocket,
+        role: HandshakeRole,
+        static_key: &PrivateKey,
+        local_info: &LocalInfo,
+        remote_addr: SocketAddr,
+    ) -> error::Result<(PeerSocket, types::PeerInfo)> {
+        socket.handshake(role, static_key).await?;
+        socket.send_peer_message(&local_info.hello(remote_addr)).await?;
+
+        match socket.recv_peer_message().await? {
+            types::PeerMessage::Hello {
+                magic_bytes,
+                version,
+                agent,
+                services,
+                protocols,
+                listen_port,
+                observed_addr,
+            } => {
+                let peer_id = match role {
+                    HandshakeRole::Initiator => remote_addr,
+                    HandshakeRole::Responder => SocketAddr::new(remote_addr.ip(), listen_port),
+                };
+
+                Ok((
+                    socket,
+                    types::PeerInfo {
+                        peer_id,
+                        magic_bytes,
+                        version,
+                        agent,
+                        services,
+                        protocols,
+                        observed_addr: observed_addr.parse().ok(),
+                    },
+                ))
+            }
+            _ => Err(P2pError::ProtocolError(ProtocolError::InvalidMessage)),
+        }
+    }
+
+    /// Open a connection to `addr` and run the handshake as the initiator
+    async fn connect(
+        dialer: &Dialer,
+        addr: SocketAddr,
+        static_key: &PrivateKey,
+        local_info: &LocalInfo,
+    ) -> error::Result<(PeerSocket, types::PeerInfo)> {
+        let socket = dialer.dial(addr).await?;
+        Self::establish(
+            socket,
+            HandshakeRole::Initiator,
+            static_key,
+            local_info,
+            addr,
+        )
+        .await
+    }
+
+    /// Run the handshake of an inbound connection in a separate task so a slow peer
+    /// can't block the backend
+    fn on_inbound_connection(&self, socket: PeerSocket, remote_addr: SocketAddr) {
+        let event_tx = self.event_tx.clone();
+        let static_key = self.static_key.clone();
+        let local_info = self.local_info.clone();
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            match tokio::time::timeout(
+                timeout,
+                Self::establish(
+                    socket,
+                    HandshakeRole::Responder,
+                    &static_key,
+                    &local_info,
+                    remote_addr,
+                ),
+            )
+            .await
+            {
+                Ok(Ok((socket, peer_info))) => {
+                    let _ = event_tx.send(Event::Connected {
+                        socket,
+                        peer_info,
+                        response: None,
+                    });
+                }
+                Ok(Err(e)) => log::warn!("handshake with {} failed: {:?}", remote_addr, e),
+                Err(_) => log::warn!("handshake with {} timed out", remote_addr),
+            }
+        });
+    }
+
+    async fn on_event(&mut self, event: Event) {
+        match event {
+            Event::Connected {
+                socket,
+                peer_info,
+                response,
+            } => self.on_connected(socket, peer_info, response).await,
+            Event::Message {
+                conn_id,
+                peer_id,
+                message,
+            } => {
+                if self.peers.get(&peer_id).map(|peer| peer.conn_id) == Some(conn_id) {
+                    self.on_peer_message(peer_id, message).await;
+                }
+            }
+            Event::Closed { conn_id, peer_id } => {
+                if self.peers.get(&peer_id).map(|peer| peer.conn_id) == Some(conn_id) {
+                    self.peers.remove(&peer_id);
+                    self.on_connection_closed(peer_id).await;
+                }
+            }
+        }
+    }
+
+    /// Register the peer and spawn a task for its connection
+    async fn on_connected(
+        &mut self,
+        socket: PeerSocket,
+        peer_info: types::PeerInfo,
+        response: Option<tokio::sync::oneshot::Sender<error::Result<types::PeerInfo>>>,
+    ) {
+        let peer_id = peer_info.peer_id;
+
+        if self.peers.contains_key(&peer_id) {
+            log::debug!("peer {} already connected, closing new connection", peer_id);
+            if let Some(response) = response {
+                let _ = response.send(Err(P2pError::PeerExists));
+            }
+            return;
+        }
+
+        let conn_id = self.next_id();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run_connection(
+            conn_id,
+            peer_id,
+            socket,
+            rx,
+            self.event_tx.clone(),
+        ));
+        self.peers.insert(
+            peer_id,
+            PeerContext {
+                conn_id,
+                tx,
+                ping: None,
+                last_ping: Instant::now(),
+            },
+        );
+
+        match response {
+            Some(response) => {
+                if response.send(Ok(peer_info)).is_err() {
+                    log::debug!("connect request for {} was dropped", peer_id);
+                    self.peers.remove(&peer_id);
+                }
+            }
+            None => {
+                let _ = self
+                    .conn_tx
+                    .send(types::ConnectivityEvent::IncomingConnection {
+                        addr: peer_id,
+                        peer_info,
+                    })
+                    .await;
+            }
+        }
+    }
+
+    /// Fail the requests sent to the peer and notify the frontend
+    async fn on_connection_closed(&mut self, peer_id: SocketAddr) {
+        let failed: Vec<u64> = self
+            .outbound_requests
+            .iter()
+            .filter(|(_, request)| request.peer_id == peer_id)
+            .map(|(request_id, _)| *request_id)
+            .collect();
+
+        for request_id in failed {
+            self.outbound_requests.remove(&request_id);
+            let _ = self
+                .sync_tx
+                .send(types::SyncingEvent::Error {
+                    peer_id,
+                    request_id: MockRequestId(request_id),
+                    error: net::RequestResponseError::ConnectionClosed,
+                })
+                .await;
+        }
+
+        self.inbound_requests.retain(|_, (peer, _)| *peer != peer_id);
+        self.pending_validation.retain(|_, (peer, _)| *peer != peer_id);
+
+        let _ = self.conn_tx.send(types::ConnectivityEvent::ConnectionClosed { peer_id }).await;
+    }
+
+    async fn misbehaved(&mut self, peer_id: SocketAddr) {
+        let _ = self
+            .conn_tx
+            .send(types::ConnectivityEvent::Misbehaved {
+                peer_id,
+                behaviour: net::INVALID_MESSAGE_SCORE,
+            })
+            .await;
+    }
+
+    fn send_to(&self, peer_id: &SocketAddr, message: types::PeerMessage) -> error::Result<()> {
+        self.peers
+            .get(peer_id)
+            .ok_or(P2pError::PeerDoesntExist)?
+            .tx
+            .send(message)
+            .map_err(|_| P2pError::PeerDisconnected)
+    }
+
+    /// Remember the ID of a pubsub message, returns `false` if it has already been seen
+    fn mark_seen(&mut self, message_id: u64) -> bool {
+        if self.seen_messages.contains(&message_id) {
+            return false;
+        }
+
+        if self.seen_messages.len() >= MAX_SEEN_MESSAGES {
+            self.seen_messages.pop_front();
+        }
+        self.seen_messages.push_back(message_id);
+        true
+    }
+
+    /// Send a pubsub message to all peers except `source`
+    fn flood(&self, message: &[u8], source: Option<SocketAddr>) -> usize {
+        self.peers
+            .iter()
+            .filter(|(peer_id, _)| Some(**peer_id) != source)
+            .filter(|(_, peer)| {
+                peer.tx
+                    .send(types::PeerMessage::Announcement {
+                        message: message.to_vec(),
+                    })
+                    .is_ok()
+            })
+            .count()
+    }
+
+    async fn on_peer_message(&mut self, peer_id: SocketAddr, message: types::PeerMessage) {
+        match message {
+            types::PeerMessage::Hello { .. } => {
+                log::warn!("peer {} sent a second hello", peer_id);
+                self.misbehaved(peer_id).await;
+            }
+            types::PeerMessage::Request {
+                request_id: remote_id,
+                message,
+            } => {
+                let request = match message::Message::decode_checked(&message) {
+                    Ok(request) => {
+                        metrics::received(&request, message.len());
+                        request
+                    }
+                    Err(err) => {
+                        log::warn!("invalid request from peer {}: {:?}", peer_id, err);
+                        return self.misbehaved(peer_id).await;
+                    }
+                };
+
+                let request_id = self.next_id();
+                self.inbound_requests.insert(request_id, (peer_id, remote_id));
+                let _ = self
+                    .sync_tx
+                    .send(types::SyncingEvent::Request {
+                        peer_id,
+                        request_id: MockRequestId(request_id),
+                        request,
+                    })
+                    .await;
+            }
+            types::PeerMessage::Response {
+                request_id,
+                message,
+            } => {
+                match self.outbound_requests.get(&request_id) {
+                    Some(request) if request.peer_id == peer_id => {
+                        self.outbound_requests.remove(&request_id);
+                    }
+                    _ => {
+                        log::debug!("unexpected response {} from peer {}", request_id, peer_id);
+                        return;
+                    }
+                }
+
+                let response = match message::Message::decode_checked(&message) {
+                    Ok(response) => {
+                        metrics::received(&response, message.len());
+                        response
+                    }
+                    Err(err) => {
+                        log::warn!("invalid response from peer {}: {:?}", peer_id, err);
+                        return self.misbehaved(peer_id).await;
+                    }
+                };
+
+                let _ = self
+                    .sync_tx
+                    .send(types::SyncingEvent::Response {
+                        peer_id,
+                        request_id: MockRequestId(request_id),
+                        response,
+                    })
+                    .await;
+            }
+            types::PeerMessage::Announcement { message } => {
+                let message_id = message_id(&message);
+                if !self.mark_seen(message_id) {
+                    return;
+                }
+
+                let decoded = match message::Message::decode_checked(&message) {
+                    Ok(decoded) => {
+                        metrics::received(&decoded, message.len());
+                        decoded
+                    }
+                    Err(err) => {
+                        log::warn!("invalid announcement from peer {}: {:?}", peer_id, err);
+                        return self.misbehaved(peer_id).await;
+                    }
+                };
+
+                match message_topic(&decoded) {
+                    Some(topic) if self.topics.contains(&topic) => {}
+                    Some(_) => return,
+                    None => return self.misbehaved(peer_id).await,
+                }
+
+                self.pending_validation.insert(message_id, (peer_id, message));
+                let _ = self
+                    .pubsub_tx
+                    .send(types::PubSubEvent::MessageReceived {
+                        peer_id,
+                        message_id: MockMessageId(message_id),
+                        message: decoded,
+                    })
+                    .await;
+            }
+            types::PeerMessage::Ping { nonce } => {
+                let _ = self.send_to(&peer_id, types::PeerMessage::Pong { nonce });
+            }
+            types::PeerMessage::Pong { nonce } => {
+                let peer = match self.peers.get_mut(&peer_id) {
+                    Some(peer) => peer,
+                    None => return,
+                };
+
+                if let Some((expected, sent)) = peer.ping {
+                    if expected == nonce {
+                        peer.ping = None;
+                        let _ = self
+                            .conn_tx
+                            .send(types::ConnectivityEvent::PingResponse {
+                                peer_id,
+                                rtt: sent.elapsed(),
+                            })
+                            .await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn on_command(&mut self, command: types::Command) {
+        match command {
+            types::Command::Connect { addr, response } => {
+                if self.addr == addr {
+                    let _ = response.send(Err(P2pError::SocketError(ErrorKind::AddrNotAvailable)));
+                    return;
+                }
+
+                if self.peers.contains_key(&addr) {
+                    let _ = response.send(Err(P2pError::PeerExists));
+                    return;
+                }
+
+                let dialer = self.listener.dialer();
+                let event_tx = self.event_tx.clone();
+                let static_key = self.static_key.clone();
+                let local_info = self.local_info.clone();
+                let timeout = self.timeout;
+
+                tokio::spawn(async move {
+                    match tokio::time::timeout(
+                        timeout,
+                        Self::connect(&dialer, addr, &static_key, &local_info),
+                    )
+                    .await
+                    {
+                        Ok(Ok((socket, peer_info))) => {
+                            let _ = event_tx.send(Event::Connected {
+                                socket,
+                                peer_info,
+                                response: Some(response),
+                            });
+                        }
+                        Ok(Err(err)) => {
+                            let _ = response.send(Err(err));
+                        }
+                        Err(_) => {
+                            let _ = response
+                                .send(Err(P2pError::SocketError(ErrorKind::ConnectionRefused)));
                         }
+                    }
+                });
+            }
+            types::Command::Disconnect { peer_id, response } => {
+                let res = match self.peers.remove(&peer_id) {
+                    Some(_) => {
+                        self.on_connection_closed(peer_id).await;
+                        Ok(())
+                    }
+                    None => Err(P2pError::PeerDoesntExist),
+                };
+                let _ = response.send(res);
+            }
+            types::Command::SendRequest {
+                peer_id,
+                message,
+                response,
+            } => {
+                let request_id = self.next_id();
+                let res = self
+                    .send_to(
+                        &peer_id,
+                        types::PeerMessage::Request {
+                            request_id,
+                            message: metrics::encode_sent(&message),
+                        },
+                    )
+                    .map(|_| {
+                        self.outbound_requests.insert(
+                            request_id,
+                            PendingRequest {
+                                peer_id,
+                                sent: Instant::now(),
+                            },
+                        );
+                        MockRequestId(request_id)
+                    });
+                let _ = response.send(res);
+            }
+            types::Command::SendResponse {
+                request_id,
+                message,
+                response,
+            } => {
+                let res = match self.inbound_requests.remove(&request_id.0) {
+                    Some((peer_id, remote_id)) => self.send_to(
+                        &peer_id,
+                        types::PeerMessage::Response {
+                            request_id: remote_id,
+                            message: metrics::encode_sent(&message),
+                        },
+                    ),
+                    None => Err(P2pError::InvalidData),
+                };
+                let _ = response.send(res);
+            }
+            types::Command::AnnounceData { message, response } => {
+                let message = metrics::encode_sent(&message);
+                self.mark_seen(message_id(&message));
 
-                        tokio::select! {
-                            _ = tokio::time::sleep(self.timeout) => {
-                                let _ = response.send(Err(
-                                    P2pError::SocketError(std::io::ErrorKind::ConnectionRefused))
-                                );
-                            }
-                            res = TcpStream::connect(addr) => match res {
-                                Ok(socket) => { let _ = response.send(Ok(socket)); },
-                                Err(e) => { let _ = response.send(Err(e.into())); },
-                            }
+                let res = match self.flood(&message, None) {
+                    0 => Err(P2pError::NoPeers),
+                    _ => Ok(()),
+                };
+                let _ = response.send(res);
+            }
+            types::Command::ReportValidationResult {
+                message_id,
+                result,
+                response,
+            } => {
+                let res = match self.pending_validation.remove(&message_id.0) {
+                    Some((source, message)) => {
+                        if let net::ValidationResult::Accept = result {
+                            self.flood(&message, Some(source));
                         }
+                        Ok(())
+                    }
+                    None => Err(P2pError::InvalidData),
+                };
+                let _ = response.send(res);
+            }
+        }
+    }
+
+    /// Time out the requests and pings that haven't been answered and ping the peers
+    async fn on_heartbeat(&mut self) {
+        let timeout = self.timeout;
+        let expired: Vec<(u64, SocketAddr)> = self
+            .outbound_requests
+            .iter()
+            .filter(|(_, request)| request.sent.elapsed() >= timeout)
+            .map(|(request_id, request)| (*request_id, request.peer_id))
+            .collect();
+
+        for (request_id, peer_id) in expired {
+            self.outbound_requests.remove(&request_id);
+            let _ = self
+                .sync_tx
+                .send(types::SyncingEvent::Error {
+                    peer_id,
+                    request_id: MockRequestId(request_id),
+                    error: net::RequestResponseError::Timeout,
+                })
+                .await;
+        }
+
+        let mut timed_out = Vec::new();
+        let mut nonce = self.next_id;
+        for (peer_id, peer) in self.peers.iter_mut() {
+            match peer.ping {
+                Some((_, sent)) if sent.elapsed() >= timeout => {
+                    peer.ping = None;
+                    timed_out.push(*peer_id);
+                }
+                Some(_) => {}
+                None if peer.last_ping.elapsed() >= PING_INTERVAL => {
+                    nonce += 1;
+                    if peer.tx.send(types::PeerMessage::Ping { nonce }).is_ok() {
+                        peer.ping = Some((nonce, Instant::now()));
+                        peer.last_ping = Instant::now();
                     }
                 }
+                None => {}
             }
         }
+        self.next_id = nonce;
+
+        for peer_id in timed_out {
+            let _ = self.conn_tx.send(types::ConnectivityEvent::PingTimeout { peer_id }).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::chain::config;
+    use tokio::{net::TcpListener, sync::oneshot};
+
+    async fn make_backend() -> (
+        SocketAddr,
+        mpsc::Sender<types::Command>,
+        mpsc::Receiver<types::ConnectivityEvent>,
+    ) {
+        let (static_key, _) = PrivateKey::new(crypto::key::KeyKind::RistrettoSchnorr);
+        let socket = TcpListener::bind("[::1]:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let (cmd_tx, cmd_rx) = mpsc::channel(16);
+        let (conn_tx, conn_rx) = mpsc::channel(16);
+        let (pubsub_tx, _) = mpsc::channel(16);
+        let (sync_tx, _) = mpsc::channel(16);
+
+        tokio::spawn(async move {
+            let mut backend = Backend::new(
+                addr,
+                Listener::Tcp(socket),
+                cmd_rx,
+                conn_tx,
+                pubsub_tx,
+                sync_tx,
+                Duration::from_secs(10),
+                static_key,
+                Arc::new(config::create_mainnet()),
+                Default::default(),
+                &[],
+            );
+            let _ = backend.run().await;
+        });
+
+        (addr, cmd_tx, conn_rx)
+    }
+
+    // connect two backends and verify that both learn the information of the other node
+    #[tokio::test]
+    async fn connect_and_exchange_hello() {
+        let (addr1, _cmd_tx1, mut conn_rx1) = make_backend().await;
+        let (addr2, cmd_tx2, _conn_rx2) = make_backend().await;
+        let config = config::create_mainnet();
+
+        let (tx, rx) = oneshot::channel();
+        assert!(cmd_tx2
+            .send(types::Command::Connect {
+                addr: addr1,
+                response: tx,
+            })
+            .await
+            .is_ok());
+        let outbound = rx.await.unwrap().unwrap();
+        assert_eq!(outbound.peer_id, addr1);
+        assert_eq!(&outbound.magic_bytes, config.magic_bytes());
+        assert_eq!(&outbound.version, config.version());
+        assert_eq!(outbound.protocols, PROTOCOLS.to_vec());
+        assert_eq!(
+            outbound.observed_addr.map(|addr| addr.ip()),
+            Some(addr2.ip())
+        );
+
+        // the inbound peer is identified by its listening port, not the ephemeral port
+        match conn_rx1.recv().await.unwrap() {
+            types::ConnectivityEvent::IncomingConnection { addr, peer_info } => {
+                assert_eq!(addr, addr2);
+                assert_eq!(peer_info.peer_id, addr2);
+                assert_eq!(peer_info.observed_addr, Some(addr1));
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        // a second connection to the same peer is rejected
+        let (tx, rx) = oneshot::channel();
+        assert!(cmd_tx2
+            .send(types::Command::Connect {
+                addr: addr1,
+                response: tx,
+            })
+            .await
+            .is_ok());
+        assert_eq!(rx.await.unwrap(), Err(P2pError::PeerExists));
+    }
+
+    #[tokio::test]
+    async fn connect_to_self() {
+        let (addr, cmd_tx, _conn_rx) = make_backend().await;
+
+        let (tx, rx) = oneshot::channel();
+        assert!(cmd_tx.send(types::Command::Connect { addr, response: tx }).await.is_ok());
+        assert_eq!(
+            rx.await.unwrap().err(),
+            Some(P2pError::SocketError(ErrorKind::AddrNotAvailable))
+        );
     }
 }
@@ -15,13 +15,15 @@
 //
 // Author(s): A. Altonen
 use crate::{
-    error, message,
+    error::{self, P2pError, ProtocolError},
+    message,
     net::{
         ConnectivityEvent, ConnectivityService, NetworkingService, PeerInfo, PubSubEvent,
-        PubSubService, PubSubTopic, SyncingCodecService, SyncingEvent, ValidationResult,
+        PubSubService, PubSubTopic, SyncingEvent, SyncingService, ValidationResult,
     },
 };
 use async_trait::async_trait;
+use crypto::key::{KeyKind, PrivateKey};
 use logging::log;
 use std::{net::SocketAddr, sync::Arc};
 use tokio::{
@@ -30,6 +32,9 @@ use tokio::{
 };
 
 pub mod backend;
+pub mod sim;
+pub mod socket;
+pub mod transport;
 pub mod types;
 
 #[derive(Debug)]
@@ -38,7 +43,7 @@ pub enum MockDiscoveryStrategy {}
 #[derive(Debug)]
 pub struct MockService;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MockMessageId(u64);
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -55,7 +60,7 @@ where
     cmd_tx: mpsc::Sender<types::Command>,
 
     /// RX channel for receiving connectivity events from mock backend
-    _conn_rx: mpsc::Receiver<types::ConnectivityEvent>,
+    conn_rx: mpsc::Receiver<types::ConnectivityEvent>,
     _marker: std::marker::PhantomData<fn() -> T>,
 }
 
@@ -64,24 +69,54 @@ where
     T: NetworkingService,
 {
     /// TX channel for sending commands to mock backend
-    _cmd_tx: mpsc::Sender<types::Command>,
+    cmd_tx: mpsc::Sender<types::Command>,
 
     /// RX channel for receiving pubsub events from mock backend
-    _pubsub_rx: mpsc::Receiver<types::PubSubEvent>,
+    pubsub_rx: mpsc::Receiver<types::PubSubEvent>,
     _marker: std::marker::PhantomData<fn() -> T>,
 }
 
-pub struct MockSyncingCodecHandle<T>
+pub struct MockSyncingHandle<T>
 where
     T: NetworkingService,
 {
     /// TX channel for sending commands to mock backend
-    _cmd_tx: mpsc::Sender<types::Command>,
+    cmd_tx: mpsc::Sender<types::Command>,
 
-    _sync_rx: mpsc::Receiver<types::SyncingEvent>,
+    /// RX channel for receiving syncing events from mock backend
+    sync_rx: mpsc::Receiver<types::SyncingEvent>,
     _marker: std::marker::PhantomData<fn() -> T>,
 }
 
+/// Send `command` to the backend and wait for its response
+async fn send_command<R>(
+    cmd_tx: &mpsc::Sender<types::Command>,
+    command: impl FnOnce(oneshot::Sender<error::Result<R>>) -> types::Command,
+) -> error::Result<R> {
+    let (tx, rx) = oneshot::channel();
+    cmd_tx.send(command(tx)).await?;
+
+    // The outer error means the channel was closed, the inner one that the command failed
+    rx.await?
+}
+
+impl<T> From<types::PeerInfo> for PeerInfo<T>
+where
+    T: NetworkingService<Address = SocketAddr, PeerId = SocketAddr, ProtocolId = String>,
+{
+    fn from(info: types::PeerInfo) -> Self {
+        PeerInfo {
+            peer_id: info.peer_id,
+            magic_bytes: info.magic_bytes,
+            version: info.version,
+            agent: Some(info.agent),
+            services: info.services,
+            protocols: info.protocols,
+            observed_addr: info.observed_addr,
+        }
+    }
+}
+
 #[async_trait]
 impl NetworkingService for MockService {
     type Address = SocketAddr;
@@ -92,46 +127,109 @@ impl NetworkingService for MockService {
     type MessageId = MockMessageId;
     type ConnectivityHandle = MockConnectivityHandle<Self>;
     type PubSubHandle = MockPubSubHandle<Self>;
-    type SyncingCodecHandle = MockSyncingCodecHandle<Self>;
+    type SyncingHandle = MockSyncingHandle<Self>;
+
+    fn from_socket_addr(addr: SocketAddr) -> Option<Self::Address> {
+        Some(addr)
+    }
+
+    fn to_socket_addr(addr: &Self::Address) -> Option<SocketAddr> {
+        Some(*addr)
+    }
+
+    // peers are identified by their socket address
+    fn peer_id_from_addr(addr: &Self::Address) -> Option<Self::PeerId> {
+        Some(*addr)
+    }
 
     async fn start(
         addr: Self::Address,
         _strategies: &[Self::DiscoveryStrategy],
-        _topics: &[PubSubTopic],
-        _config: Arc<common::chain::ChainConfig>,
+        topics: &[PubSubTopic],
+        config: Arc<common::chain::ChainConfig>,
+        p2p_config: Arc<crate::config::P2pConfig>,
         timeout: std::time::Duration,
     ) -> error::Result<(
         Self::ConnectivityHandle,
         Self::PubSubHandle,
-        Self::SyncingCodecHandle,
+        Self::SyncingHandle,
+    )> {
+        let listener = TcpListener::bind(addr).await?;
+        Self::start_backend(
+            transport::Listener::Tcp(listener),
+            topics,
+            config,
+            p2p_config,
+            timeout,
+        )
+    }
+}
+
+impl MockService {
+    /// Start the service over a simulated network instead of TCP
+    pub fn start_simulated(
+        network: &sim::SimNetwork,
+        addr: SocketAddr,
+        topics: &[PubSubTopic],
+        config: Arc<common::chain::ChainConfig>,
+        p2p_config: Arc<crate::config::P2pConfig>,
+        timeout: std::time::Duration,
+    ) -> error::Result<(
+        MockConnectivityHandle<Self>,
+        MockPubSubHandle<Self>,
+        MockSyncingHandle<Self>,
+    )> {
+        let listener = network.bind(addr)?;
+        Self::start_backend(
+            transport::Listener::Sim(listener),
+            topics,
+            config,
+            p2p_config,
+            timeout,
+        )
+    }
+
+    fn start_backend(
+        listener: transport::Listener,
+        topics: &[PubSubTopic],
+        config: Arc<common::chain::ChainConfig>,
+        p2p_config: Arc<crate::config::P2pConfig>,
+        timeout: std::time::Duration,
+    ) -> error::Result<(
+        MockConnectivityHandle<Self>,
+        MockPubSubHandle<Self>,
+        MockSyncingHandle<Self>,
     )> {
         let (cmd_tx, cmd_rx) = mpsc::channel(16);
-        let (conn_tx, _conn_rx) = mpsc::channel(16);
-        let (pubsub_tx, _pubsub_rx) = mpsc::channel(16);
-        let (sync_tx, _sync_rx) = mpsc::channel(16);
-        let socket = TcpListener::bind(addr).await?;
+        let (conn_tx, conn_rx) = mpsc::channel(16);
+        let (pubsub_tx, pubsub_rx) = mpsc::channel(16);
+        let (sync_tx, sync_rx) = mpsc::channel(16);
+        let addr = listener.local_addr()?;
+        let (static_key, _) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let mut backend = backend::Backend::new(
+            addr, listener, cmd_rx, conn_tx, pubsub_tx, sync_tx, timeout, static_key, config,
+            p2p_config, topics,
+        );
 
         tokio::spawn(async move {
-            let mut mock =
-                backend::Backend::new(addr, socket, cmd_rx, conn_tx, pubsub_tx, sync_tx, timeout);
-            let _ = mock.run().await;
+            let _ = backend.run().await;
         });
 
         Ok((
-            Self::ConnectivityHandle {
+            MockConnectivityHandle {
                 addr,
                 cmd_tx: cmd_tx.clone(),
-                _conn_rx,
+                conn_rx,
                 _marker: Default::default(),
             },
-            Self::PubSubHandle {
-                _cmd_tx: cmd_tx.clone(),
-                _pubsub_rx,
+            MockPubSubHandle {
+                cmd_tx: cmd_tx.clone(),
+                pubsub_rx,
                 _marker: Default::default(),
             },
-            Self::SyncingCodecHandle {
-                _cmd_tx: cmd_tx,
-                _sync_rx,
+            MockSyncingHandle {
+                cmd_tx,
+                sync_rx,
                 _marker: Default::default(),
             },
         ))
@@ -141,33 +239,27 @@ impl NetworkingService for MockService {
 #[async_trait]
 impl<T> ConnectivityService<T> for MockConnectivityHandle<T>
 where
-    T: NetworkingService<Address = SocketAddr, PeerId = SocketAddr> + Send,
+    T: NetworkingService<Address = SocketAddr, PeerId = SocketAddr, ProtocolId = String> + Send,
 {
     async fn connect(&mut self, addr: T::Address) -> error::Result<PeerInfo<T>> {
         log::debug!("try to establish outbound connection, address {:?}", addr);
 
-        let (tx, rx) = oneshot::channel();
-        self.cmd_tx.send(types::Command::Connect { addr, response: tx }).await?;
-
-        let _ = rx
-            .await
-            .map_err(|e| e)? // channel closed
-            .map_err(|e| e)?; // command failure
-
-        todo!();
-        // Ok(
-        // PeerInfo {
-        // id: addr,
-        // net: chain::config::ChainType,
-        // version: primitives::version::SemVer,
-        // agent: Option<String>,
-        // protocols: Vec<String>,
-        // }
-        // )
+        send_command(&self.cmd_tx, |response| types::Command::Connect {
+            addr,
+            response,
+        })
+        .await
+        .map(PeerInfo::from)
     }
 
-    async fn disconnect(&mut self, _peer_id: T::PeerId) -> error::Result<()> {
-        todo!();
+    async fn disconnect(&mut self, peer_id: T::PeerId) -> error::Result<()> {
+        log::debug!("disconnect peer {:?}", peer_id);
+
+        send_command(&self.cmd_tx, |response| types::Command::Disconnect {
+            peer_id,
+            response,
+        })
+        .await
     }
 
     fn local_addr(&self) -> &T::Address {
@@ -179,188 +271,305 @@ where
     }
 
     async fn poll_next(&mut self) -> error::Result<ConnectivityEvent<T>> {
-        todo!();
-        // match self.conn_rx.recv().await.ok_or(P2pError::ChannelClosed)? {
-        //     types::ConnectivityEvent::IncomingConnection { peer_id, socket } => {
-        //         Ok(ConnectivityEvent::IncomingConnection {
-        //             peer_id,
-        //             socket: MockSocket { socket },
-        //         })
-        //     }
-        // }
+        match self.conn_rx.recv().await.ok_or(P2pError::ChannelClosed)? {
+            types::ConnectivityEvent::IncomingConnection { addr, peer_info } => {
+                Ok(ConnectivityEvent::IncomingConnection {
+                    addr,
+                    peer_info: peer_info.into(),
+                })
+            }
+            types::ConnectivityEvent::ConnectionClosed { peer_id } => {
+                Ok(ConnectivityEvent::ConnectionClosed { peer_id })
+            }
+            types::ConnectivityEvent::PingResponse { peer_id, rtt } => {
+                Ok(ConnectivityEvent::PingResponse { peer_id, rtt })
+            }
+            types::ConnectivityEvent::PingTimeout { peer_id } => {
+                Ok(ConnectivityEvent::PingTimeout { peer_id })
+            }
+            types::ConnectivityEvent::Misbehaved { peer_id, behaviour } => {
+                Ok(ConnectivityEvent::Misbehaved { peer_id, behaviour })
+            }
+        }
     }
 }
 
 #[async_trait]
 impl<T> PubSubService<T> for MockPubSubHandle<T>
 where
-    T: NetworkingService<PeerId = SocketAddr> + Send,
+    T: NetworkingService<PeerId = SocketAddr, MessageId = MockMessageId> + Send,
 {
-    async fn publish(&mut self, _message: message::Message) -> error::Result<()> {
-        todo!();
+    async fn publish(&mut self, message: message::Message) -> error::Result<()> {
+        // TODO: add support for transactions in the future
+        if backend::message_topic(&message).is_none() {
+            return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
+        }
+
+        send_command(&self.cmd_tx, |response| types::Command::AnnounceData {
+            message,
+            response,
+        })
+        .await
     }
 
     async fn report_validation_result(
         &mut self,
-        _source: T::PeerId,
-        _msg_id: T::MessageId,
-        _result: ValidationResult,
+        source: T::PeerId,
+        message_id: T::MessageId,
+        result: ValidationResult,
     ) -> error::Result<()> {
-        todo!();
+        log::debug!(
+            "report validation result: {:?} {:?} {:?}",
+            source,
+            message_id,
+            result,
+        );
+
+        send_command(&self.cmd_tx, |response| {
+            types::Command::ReportValidationResult {
+                message_id,
+                result,
+                response,
+            }
+        })
+        .await
     }
 
     async fn poll_next(&mut self) -> error::Result<PubSubEvent<T>> {
-        todo!();
+        match self.pubsub_rx.recv().await.ok_or(P2pError::ChannelClosed)? {
+            types::PubSubEvent::MessageReceived {
+                peer_id,
+                message_id,
+                message,
+            } => Ok(PubSubEvent::MessageReceived {
+                peer_id,
+                message_id,
+                message,
+            }),
+        }
     }
 }
 
 #[async_trait]
-impl<T> SyncingCodecService<T> for MockSyncingCodecHandle<T>
+impl<T> SyncingService<T> for MockSyncingHandle<T>
 where
     T: NetworkingService<PeerId = SocketAddr, RequestId = MockRequestId> + Send,
 {
     async fn send_request(
         &mut self,
-        _peer_id: T::PeerId,
-        _message: message::Message,
+        peer_id: T::PeerId,
+        message: message::Message,
     ) -> error::Result<T::RequestId> {
-        todo!();
+        send_command(&self.cmd_tx, |response| types::Command::SendRequest {
+            peer_id,
+            message,
+            response,
+        })
+        .await
     }
 
     async fn send_response(
         &mut self,
-        _request_id: T::RequestId,
-        _message: message::Message,
+        request_id: T::RequestId,
+        message: message::Message,
     ) -> error::Result<()> {
-        todo!();
+        send_command(&self.cmd_tx, |response| types::Command::SendResponse {
+            request_id,
+            message,
+            response,
+        })
+        .await
     }
 
     async fn poll_next(&mut self) -> error::Result<SyncingEvent<T>> {
-        todo!();
+        match self.sync_rx.recv().await.ok_or(P2pError::ChannelClosed)? {
+            types::SyncingEvent::Request {
+                peer_id,
+                request_id,
+                request,
+            } => Ok(SyncingEvent::Request {
+                peer_id,
+                request_id,
+                request,
+            }),
+            types::SyncingEvent::Response {
+                peer_id,
+                request_id,
+                response,
+            } => Ok(SyncingEvent::Response {
+                peer_id,
+                request_id,
+                response,
+            }),
+            types::SyncingEvent::Error {
+                peer_id,
+                request_id,
+                error,
+            } => Ok(SyncingEvent::Error {
+                peer_id,
+                request_id,
+                error,
+            }),
+        }
     }
 }
 
-/*
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::net::mock::MockService;
-    use crate::net::ConnectivityEvent;
-    use crate::peer::{Peer, PeerRole};
+    use crate::message::{MessageType, SyncingMessage, SyncingRequest, SyncingResponse};
     use common::chain::config;
-    use serialization::{Decode, Encode};
-    use std::net::SocketAddr;
-    use std::sync::Arc;
-    use tokio::io::{AsyncReadExt, AsyncWriteExt};
-    use tokio::net::{TcpStream, UdpSocket};
-
-    #[derive(Debug, Encode, Decode, PartialEq, Eq)]
-    struct Transaction {
-        hash: u64,
-        value: u128,
-    }
-
-    #[tokio::test]
-    async fn test_new() {
-        let srv_ipv4 = MockService::start(
-            "127.0.0.1:5555".parse().unwrap(),
-            &[],
-            &[],
-            std::time::Duration::from_secs(10),
-        )
-        .await;
-        assert!(srv_ipv4.is_ok());
-
-        // address already in use
-        let err = MockService::start(
-            "127.0.0.1:5555".parse().unwrap(),
-            &[],
+    use std::time::Duration;
+
+    async fn make_service(
+        timeout: Duration,
+    ) -> (
+        MockConnectivityHandle<MockService>,
+        MockPubSubHandle<MockService>,
+        MockSyncingHandle<MockService>,
+    ) {
+        MockService::start(
+            "[::1]:0".parse().unwrap(),
             &[],
-            std::time::Duration::from_secs(10),
+            &[PubSubTopic::Blocks],
+            Arc::new(config::create_mainnet()),
+            Default::default(),
+            timeout,
         )
-        .await;
-        assert!(err.is_err());
+        .await
+        .unwrap()
+    }
 
-        // bind to IPv6 localhost
-        let srv_ipv6 = MockService::start(
-            "[::1]:5555".parse().unwrap(),
-            &[],
-            &[],
-            std::time::Duration::from_secs(10),
-        )
-        .await;
-        assert!(srv_ipv6.is_ok());
+    async fn connect(
+        conn1: &mut MockConnectivityHandle<MockService>,
+        conn2: &mut MockConnectivityHandle<MockService>,
+    ) {
+        let (res1, res2) = tokio::join!(conn1.connect(*conn2.local_addr()), conn2.poll_next());
+        assert_eq!(res1.unwrap().peer_id, *conn2.local_addr());
+        match res2.unwrap() {
+            ConnectivityEvent::IncomingConnection { addr, peer_info } => {
+                assert_eq!(addr, *conn1.local_addr());
+                assert_eq!(peer_info.peer_id, *conn1.local_addr());
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
 
-        // address already in use
-        let s_ipv6 = MockService::start(
-            "[::1]:5555".parse().unwrap(),
-            &[],
-            &[],
-            std::time::Duration::from_secs(10),
-        )
-        .await;
-        assert!(s_ipv6.is_err());
+    fn get_headers() -> message::Message {
+        message::Message {
+            magic: *config::create_mainnet().magic_bytes(),
+            msg: MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetHeaders {
+                locator: vec![],
+            })),
+        }
     }
 
     #[tokio::test]
-    async fn test_connect() {
-        use tokio::net::TcpListener;
-
-        // create `TcpListener`, spawn a task, and start accepting connections
-        let addr: SocketAddr = "127.0.0.1:6666".parse().unwrap();
-        let server = TcpListener::bind(addr).await.unwrap();
+    async fn connect_and_disconnect() {
+        let (mut conn1, _, _) = make_service(Duration::from_secs(10)).await;
+        let (mut conn2, _, _) = make_service(Duration::from_secs(10)).await;
+
+        // nothing is listening on the port
+        assert_eq!(
+            conn1.connect("[::1]:1".parse().unwrap()).await.err(),
+            Some(P2pError::SocketError(std::io::ErrorKind::ConnectionRefused))
+        );
+
+        connect(&mut conn1, &mut conn2).await;
+
+        // both sides are notified when the connection is closed
+        let peer_id = *conn2.local_addr();
+        assert_eq!(conn1.disconnect(peer_id).await, Ok(()));
+        assert!(matches!(
+            conn1.poll_next().await,
+            Ok(ConnectivityEvent::ConnectionClosed { peer_id: id }) if id == peer_id
+        ));
+        assert!(matches!(
+            conn2.poll_next().await,
+            Ok(ConnectivityEvent::ConnectionClosed { .. })
+        ));
+        assert_eq!(
+            conn1.disconnect(peer_id).await,
+            Err(P2pError::PeerDoesntExist)
+        );
+    }
 
-        tokio::spawn(async move {
-            loop {
-                if server.accept().await.is_ok() {}
+    #[tokio::test]
+    async fn request_response() {
+        let (mut conn1, _, mut sync1) = make_service(Duration::from_secs(10)).await;
+        let (mut conn2, _, mut sync2) = make_service(Duration::from_secs(10)).await;
+        connect(&mut conn1, &mut conn2).await;
+
+        let request_id = sync1.send_request(*conn2.local_addr(), get_headers()).await.unwrap();
+
+        let (peer_id, remote_id) = match sync2.poll_next().await.unwrap() {
+            SyncingEvent::Request {
+                peer_id,
+                request_id,
+                request,
+            } => {
+                assert_eq!(request, get_headers());
+                (peer_id, request_id)
             }
-        });
-
-        // create service that is used for testing `connect()`
-        let srv = MockService::start(
-            "127.0.0.1:7777".parse().unwrap(),
-            &[],
-            &[],
-            std::time::Duration::from_secs(10),
-        )
-        .await;
-        assert!(srv.is_ok());
-        let (mut srv, _) = srv.unwrap();
-
-        // try to connect to self, should fail
-        let res = srv.connect("127.0.0.1:7777".parse().unwrap()).await;
-        println!("{:?}", res);
-        assert!(res.is_err());
-
-        // try to connect to an address that (hopefully)
-        // doesn't have a `TcpListener` running, should fail
-        let res = srv.connect("127.0.0.1:1".parse().unwrap()).await;
-        assert!(res.is_err());
-
-        // try to connect to the `TcpListener` that was spawned above, should succeeed
-        let res = srv.connect("127.0.0.1:6666".parse().unwrap()).await;
-        assert!(res.is_ok());
+            event => panic!("unexpected event: {:?}", event),
+        };
+        assert_eq!(peer_id, *conn1.local_addr());
+
+        let response = message::Message {
+            magic: *config::create_mainnet().magic_bytes(),
+            msg: MessageType::Syncing(SyncingMessage::Response(SyncingResponse::Headers {
+                headers: vec![],
+            })),
+        };
+        sync2.send_response(remote_id, response.clone()).await.unwrap();
+
+        match sync1.poll_next().await.unwrap() {
+            SyncingEvent::Response {
+                peer_id,
+                request_id: id,
+                response: received,
+            } => {
+                assert_eq!(peer_id, *conn2.local_addr());
+                assert_eq!(id, request_id);
+                assert_eq!(received, response);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        // each request is answered only once
+        assert_eq!(
+            sync2.send_response(remote_id, response).await,
+            Err(P2pError::InvalidData)
+        );
     }
 
     #[tokio::test]
-    async fn test_accept() {
-        // create service that is used for testing `accept()`
-        let addr: SocketAddr = "[::1]:9999".parse().unwrap();
-        let (mut srv, _) = MockService::start(
-            "[::1]:9999".parse().unwrap(),
-            &[],
-            &[],
-            std::time::Duration::from_secs(10),
-        )
-        .await
-        .unwrap();
+    async fn request_timeout() {
+        let (mut conn1, _, mut sync1) = make_service(Duration::from_secs(1)).await;
+        let (mut conn2, _, _sync2) = make_service(Duration::from_secs(1)).await;
+        connect(&mut conn1, &mut conn2).await;
+
+        let request_id = sync1.send_request(*conn2.local_addr(), get_headers()).await.unwrap();
+        match sync1.poll_next().await.unwrap() {
+            SyncingEvent::Error {
+                request_id: id,
+                error,
+                ..
+            } => {
+                assert_eq!(id, request_id);
+                assert_eq!(error, crate::net::RequestResponseError::Timeout);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
 
-        let (acc, con) = tokio::join!(srv.poll_next(), TcpStream::connect(addr));
-        assert!(acc.is_ok());
-        assert!(con.is_ok());
-        let acc: ConnectivityEvent<MockService> = acc.unwrap();
+    #[tokio::test]
+    async fn request_to_unknown_peer() {
+        let (_, _, mut sync) = make_service(Duration::from_secs(10)).await;
 
-        // TODO: is there any sensible way to make `accept()` fail?
+        assert_eq!(
+            sync.send_request("[::1]:1".parse().unwrap(), get_headers()).await,
+            Err(P2pError::PeerDoesntExist)
+        );
     }
 }
-*/
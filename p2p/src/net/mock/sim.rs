@@ -0,0 +1,489 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic network simulation for the mock backend
+//!
+//! A [SimNetwork] replaces the TCP connections of the mock backend with in-memory links.
+//! Every message sent over a link goes through the router of the network, which uses an RNG
+//! seeded from the [SimConfig] to pick the latency of the message and whether it is dropped.
+//! Like with TCP, the messages of a link are delivered in the order they were sent, so the
+//! RNG decides how the messages of different links interleave.
+//!
+//! All timing uses the tokio clock. On a single-threaded runtime with the clock paused, e.g.,
+//! in a `#[tokio::test(start_paused = true)]` test, the simulated time only advances when all
+//! tasks are idle and the message delivery of a run is reproduced by reusing its seed. The
+//! randomness of the nodes themselves is not controlled by the seed: the peer manager selects
+//! peers at random and the managers pick among their ready events at random, as
+//! `tokio::select!` does.
+//!
+//! Simulated connections skip the encryption handshake of the TCP transport.
+
+use crate::net::mock::types::PeerMessage;
+use logging::log;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+use tokio::{
+    sync::{mpsc, Notify},
+    time::Instant,
+};
+
+/// Default lowest latency of a message
+pub const DEFAULT_MIN_LATENCY: Duration = Duration::from_millis(5);
+
+/// Default highest latency of a message
+pub const DEFAULT_MAX_LATENCY: Duration = Duration::from_millis(50);
+
+/// First port assigned to the listeners bound to port 0
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// Simulation parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimConfig {
+    seed: u64,
+    min_latency: Duration,
+    max_latency: Duration,
+    drop_probability: f64,
+}
+
+impl SimConfig {
+    /// Default latencies and no dropped messages
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            min_latency: DEFAULT_MIN_LATENCY,
+            max_latency: DEFAULT_MAX_LATENCY,
+            drop_probability: 0.0,
+        }
+    }
+
+    /// Latency of each message, picked uniformly from `min..=max`
+    pub fn with_latency(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "minimum latency above maximum");
+        self.min_latency = min;
+        self.max_latency = max;
+        self
+    }
+
+    /// Probability of a message being dropped, the end of a connection is never dropped
+    pub fn with_drop_probability(mut self, drop_probability: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&drop_probability),
+            "drop probability not in [0, 1]"
+        );
+        self.drop_probability = drop_probability;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// Message waiting in the router, `None` closes the link
+struct Delivery {
+    at: Instant,
+    /// Order in which the deliveries were queued, breaks the ties of `at`
+    seq: u64,
+    link: u64,
+    message: Option<PeerMessage>,
+}
+
+impl PartialEq for Delivery {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delivery {}
+
+impl PartialOrd for Delivery {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delivery {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+/// One direction of a connection
+struct Link {
+    /// TX end of the receiving connection
+    tx: mpsc::UnboundedSender<PeerMessage>,
+
+    /// When the last message queued on the link is delivered
+    last_delivery: Instant,
+}
+
+struct State {
+    config: SimConfig,
+    rng: StdRng,
+    listeners: HashMap<SocketAddr, mpsc::UnboundedSender<(SimConnection, SocketAddr)>>,
+    links: HashMap<u64, Link>,
+    queue: BinaryHeap<Reverse<Delivery>>,
+    next_id: u64,
+    next_port: u16,
+    notify: Arc<Notify>,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        // let the router see that the network is gone
+        self.notify.notify_one();
+    }
+}
+
+/// Simulated network the mock backends connect over
+///
+/// Cloning the network gives another handle to the same network.
+#[derive(Clone)]
+pub struct SimNetwork {
+    state: Arc<Mutex<State>>,
+    /// Wakes the router up when a delivery is queued
+    notify: Arc<Notify>,
+}
+
+impl SimNetwork {
+    /// Create the network and spawn its router, which runs until the network is dropped
+    pub fn start(config: SimConfig) -> Self {
+        let notify = Arc::new(Notify::new());
+        let network = Self {
+            state: Arc::new(Mutex::new(State {
+                rng: StdRng::seed_from_u64(config.seed),
+                config,
+                listeners: HashMap::new(),
+                links: HashMap::new(),
+                queue: BinaryHeap::new(),
+                next_id: 0,
+                next_port: FIRST_EPHEMERAL_PORT,
+                notify: Arc::clone(&notify),
+            })),
+            notify,
+        };
+        tokio::spawn(run_router(
+            Arc::downgrade(&network.state),
+            Arc::clone(&network.notify),
+        ));
+        network
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("Simulated network lock poisoned")
+    }
+
+    /// Listen for connections at `addr`, a free port is assigned if the port is 0
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<SimListener> {
+        let mut state = self.lock();
+        let addr = if addr.port() == 0 {
+            loop {
+                let candidate = SocketAddr::new(addr.ip(), state.next_port);
+                state.next_port = state.next_port.checked_add(1).ok_or(io::ErrorKind::AddrInUse)?;
+                if !state.listeners.contains_key(&candidate) {
+                    break candidate;
+                }
+            }
+        } else {
+            addr
+        };
+        if state.listeners.contains_key(&addr) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        state.listeners.insert(addr, tx);
+        Ok(SimListener {
+            network: self.clone(),
+            addr,
+            rx,
+        })
+    }
+
+    /// Open a connection from the listener at `from` to the listener at `to`
+    pub fn connect(&self, from: SocketAddr, to: SocketAddr) -> io::Result<SimConnection> {
+        let mut state = self.lock();
+        let listener = state.listeners.get(&to).ok_or(io::ErrorKind::ConnectionRefused)?.clone();
+
+        let (local_tx, local_rx) = mpsc::unbounded_channel();
+        let (remote_tx, remote_rx) = mpsc::unbounded_channel();
+        let (outbound, inbound) = (state.next_id(), state.next_id());
+        let now = Instant::now();
+        state.links.insert(
+            outbound,
+            Link {
+                tx: remote_tx,
+                last_delivery: now,
+            },
+        );
+        state.links.insert(
+            inbound,
+            Link {
+                tx: local_tx,
+                last_delivery: now,
+            },
+        );
+        drop(state);
+
+        let remote = SimConnection {
+            network: self.clone(),
+            send_link: inbound,
+            recv_link: outbound,
+            rx: remote_rx,
+        };
+        listener.send((remote, from)).map_err(|_| io::ErrorKind::ConnectionRefused)?;
+        Ok(SimConnection {
+            network: self.clone(),
+            send_link: outbound,
+            recv_link: inbound,
+            rx: local_rx,
+        })
+    }
+
+    /// Queue a message on a link, `None` closes the link after the messages queued before
+    fn queue(&self, link: u64, message: Option<PeerMessage>) -> io::Result<()> {
+        let mut state = self.lock();
+        let state = &mut *state;
+        let config = &state.config;
+        let latency = state.rng.gen_range(config.min_latency..=config.max_latency);
+        let dropped = message.is_some() && state.rng.gen_bool(config.drop_probability);
+
+        let last_delivery = match state.links.get_mut(&link) {
+            Some(link) => &mut link.last_delivery,
+            None => return Err(io::ErrorKind::ConnectionReset.into()),
+        };
+        if dropped {
+            log::trace!("simulated network dropped a message of link {}", link);
+            return Ok(());
+        }
+        let at = std::cmp::max(Instant::now() + latency, *last_delivery);
+        *last_delivery = at;
+
+        let seq = state.next_id();
+        state.queue.push(Reverse(Delivery {
+            at,
+            seq,
+            link,
+            message,
+        }));
+        self.notify.notify_one();
+        Ok(())
+    }
+}
+
+/// Deliver the queued messages when their time comes
+async fn run_router(state: Weak<Mutex<State>>, notify: Arc<Notify>) {
+    loop {
+        let next = {
+            let state = match state.upgrade() {
+                Some(state) => state,
+                None => return,
+            };
+            let mut state = state.lock().expect("Simulated network lock poisoned");
+            let now = Instant::now();
+            while state.queue.peek().map_or(false, |Reverse(delivery)| delivery.at <= now) {
+                let Reverse(delivery) = state.queue.pop().expect("queue not to be empty");
+                match delivery.message {
+                    Some(message) => {
+                        if let Some(link) = state.links.get(&delivery.link) {
+                            // the receiving connection may be gone already
+                            let _ = link.tx.send(message);
+                        }
+                    }
+                    None => {
+                        state.links.remove(&delivery.link);
+                    }
+                }
+            }
+            state.queue.peek().map(|Reverse(delivery)| delivery.at)
+        };
+
+        match next {
+            Some(at) => tokio::select! {
+                _ = tokio::time::sleep_until(at) => {}
+                _ = notify.notified() => {}
+            },
+            None => notify.notified().await,
+        }
+    }
+}
+
+/// Listener of a simulated network, stops listening when dropped
+pub struct SimListener {
+    network: SimNetwork,
+    addr: SocketAddr,
+    rx: mpsc::UnboundedReceiver<(SimConnection, SocketAddr)>,
+}
+
+impl SimListener {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn network(&self) -> &SimNetwork {
+        &self.network
+    }
+
+    /// Accept a connection, returns the connection and the address of the remote listener
+    pub async fn accept(&mut self) -> io::Result<(SimConnection, SocketAddr)> {
+        self.rx.recv().await.ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl Drop for SimListener {
+    fn drop(&mut self) {
+        self.network.lock().listeners.remove(&self.addr);
+    }
+}
+
+/// Connection over a simulated network, closed when dropped
+pub struct SimConnection {
+    network: SimNetwork,
+    send_link: u64,
+    recv_link: u64,
+    rx: mpsc::UnboundedReceiver<PeerMessage>,
+}
+
+impl SimConnection {
+    pub fn send(&self, message: PeerMessage) -> io::Result<()> {
+        self.network.queue(self.send_link, Some(message))
+    }
+
+    /// Receive a message, `None` if the remote peer closed the connection
+    pub async fn recv(&mut self) -> Option<PeerMessage> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for SimConnection {
+    fn drop(&mut self) {
+        // the remote peer gets the messages sent so far before the connection closes
+        let _ = self.network.queue(self.send_link, None);
+        self.network.lock().links.remove(&self.recv_link);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ping(nonce: u64) -> PeerMessage {
+        PeerMessage::Ping { nonce }
+    }
+
+    /// Send messages over two connections to the same listener and record when and over
+    /// which connection each one arrives
+    async fn deliveries(config: SimConfig) -> Vec<(u64, Duration)> {
+        let network = SimNetwork::start(config);
+        let mut listener = network.bind("[::1]:1".parse().unwrap()).unwrap();
+        let sender1 = network.connect("[::1]:2".parse().unwrap(), listener.local_addr()).unwrap();
+        let sender2 = network.connect("[::1]:3".parse().unwrap(), listener.local_addr()).unwrap();
+        let (mut receiver1, _) = listener.accept().await.unwrap();
+        let (mut receiver2, _) = listener.accept().await.unwrap();
+
+        let start = Instant::now();
+        for nonce in 0..20 {
+            sender1.send(ping(nonce)).unwrap();
+            sender2.send(ping(100 + nonce)).unwrap();
+        }
+        drop((sender1, sender2));
+
+        let mut received = Vec::new();
+        let (mut open1, mut open2) = (true, true);
+        while open1 || open2 {
+            // the messages delivered at the same time are recorded in a fixed order
+            let message = tokio::select! {
+                biased;
+                message = receiver1.recv(), if open1 => message.or_else(|| {
+                    open1 = false;
+                    None
+                }),
+                message = receiver2.recv(), if open2 => message.or_else(|| {
+                    open2 = false;
+                    None
+                }),
+            };
+            if let Some(PeerMessage::Ping { nonce }) = message {
+                received.push((nonce, start.elapsed()));
+            }
+        }
+        received
+    }
+
+    // the same seed gives the same delivery order and times, the order within a connection
+    // is preserved
+    #[tokio::test(start_paused = true)]
+    async fn deterministic_delivery() {
+        let first = deliveries(SimConfig::new(1)).await;
+        assert_eq!(first.len(), 40);
+        assert_eq!(first, deliveries(SimConfig::new(1)).await);
+        assert_ne!(first, deliveries(SimConfig::new(2)).await);
+
+        for offset in [0, 100] {
+            let nonces: Vec<u64> = first
+                .iter()
+                .map(|(nonce, _)| *nonce)
+                .filter(|nonce| (offset..offset + 100).contains(nonce))
+                .collect();
+            assert_eq!(nonces, (offset..offset + 20).collect::<Vec<_>>());
+        }
+        assert!(first.iter().all(|(_, elapsed)| *elapsed >= DEFAULT_MIN_LATENCY));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_messages() {
+        let config = SimConfig::new(1).with_drop_probability(1.0);
+        assert!(deliveries(config).await.is_empty());
+
+        let config = SimConfig::new(1).with_drop_probability(0.5);
+        let received = deliveries(config).await.len();
+        assert!(received > 0 && received < 40);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn listener_addresses() {
+        let network = SimNetwork::start(SimConfig::new(1));
+        let addr: SocketAddr = "[::1]:1".parse().unwrap();
+        let listener = network.bind(addr).unwrap();
+        assert_eq!(
+            network.bind(addr).err().map(|e| e.kind()),
+            Some(io::ErrorKind::AddrInUse)
+        );
+
+        let any = network.bind("[::1]:0".parse().unwrap()).unwrap();
+        assert_eq!(any.local_addr().port(), FIRST_EPHEMERAL_PORT);
+
+        drop(listener);
+        assert_eq!(
+            network.connect(any.local_addr(), addr).err().map(|e| e.kind()),
+            Some(io::ErrorKind::ConnectionRefused)
+        );
+        assert!(network.bind(addr).is_ok());
+    }
+}
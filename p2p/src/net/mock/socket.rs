@@ -0,0 +1,715 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Framed socket for the mock backend
+//!
+//! Each frame consists of a SCALE-encoded `u32` length prefix followed by
+//! the SCALE-encoded message itself.
+//!
+//! After [`MockSocket::handshake()`] the frame payloads are encrypted with
+//! ChaCha20-Poly1305, using separate keys and counter nonces for each direction.
+
+use crate::{
+    error::{self, P2pError, ProtocolError},
+    message,
+    net::mock::types::{HandshakeMessage, PeerMessage},
+};
+use crypto::{
+    hash::{Blake2b32Stream, StreamHasher},
+    key::{KeyKind, PrivateKey, PublicKey},
+    symkey::{SymmetricKey, TAG_SIZE},
+};
+use logging::log;
+use serialization::{Decode, Encode};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Default maximum size of an encoded message
+pub const MESSAGE_MAX_SIZE: usize = 10 * 1024 * 1024;
+
+/// Size of the length prefix of each frame
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Size of the chunk read from the socket at once
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Domain separator for the hashes computed during the handshake
+const HANDSHAKE_CONTEXT: &[u8] = b"mintlayer-mock-handshake";
+
+/// Which side of the connection runs the handshake
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HandshakeRole {
+    /// Side that opened the connection
+    Initiator,
+
+    /// Side that accepted the connection
+    Responder,
+}
+
+impl HandshakeRole {
+    fn label(&self) -> &'static [u8] {
+        match self {
+            HandshakeRole::Initiator => b"initiator",
+            HandshakeRole::Responder => b"responder",
+        }
+    }
+}
+
+/// Keys and nonces used to encrypt the frames after the handshake
+struct FrameCipher {
+    send_key: SymmetricKey,
+    send_nonce: u64,
+    recv_key: SymmetricKey,
+    recv_nonce: u64,
+}
+
+/// Hash of the context and the ephemeral keys that the handshake signatures commit to
+fn handshake_hash(initiator_key: &PublicKey, responder_key: &PublicKey) -> Vec<u8> {
+    Blake2b32Stream::new()
+        .write(HANDSHAKE_CONTEXT)
+        .write(initiator_key.encode())
+        .write(responder_key.encode())
+        .finalize()
+        .to_vec()
+}
+
+/// Data signed by `role` to prove the ownership of its static key
+fn signed_data(role: HandshakeRole, hash: &[u8]) -> Vec<u8> {
+    [hash, role.label()].concat()
+}
+
+/// Derive the key that `role` uses to encrypt the frames it sends
+fn derive_key(role: HandshakeRole, secret: &[u8; 32], hash: &[u8]) -> SymmetricKey {
+    let key = Blake2b32Stream::new().write(secret).write(hash).write(role.label()).finalize();
+    SymmetricKey::new(&key.into())
+}
+
+fn next_nonce(nonce: &mut u64) -> error::Result<u64> {
+    let current = *nonce;
+    *nonce = nonce
+        .checked_add(1)
+        .ok_or(P2pError::ProtocolError(ProtocolError::InvalidState))?;
+    Ok(current)
+}
+
+pub struct MockSocket {
+    /// Underlying TCP stream
+    socket: TcpStream,
+
+    /// Bytes read from the socket that don't yet form a full frame
+    buffer: Vec<u8>,
+
+    /// Maximum size of an encoded message, excluding the length prefix
+    max_message_size: usize,
+
+    /// Frame encryption, enabled by the handshake
+    cipher: Option<FrameCipher>,
+
+    /// Static key of the remote peer, known after the handshake
+    remote_key: Option<PublicKey>,
+}
+
+impl MockSocket {
+    pub fn new(socket: TcpStream) -> Self {
+        Self {
+            socket,
+            buffer: Vec::new(),
+            max_message_size: MESSAGE_MAX_SIZE,
+            cipher: None,
+            remote_key: None,
+        }
+    }
+
+    /// Set the maximum size of a message that can be sent or received
+    ///
+    /// # Panics
+    /// Panics if `max_message_size` doesn't fit in the `u32` length prefix
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        assert!(
+            max_message_size <= u32::MAX as usize,
+            "maximum message size must fit in the length prefix"
+        );
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    /// Get the static key of the remote peer if the handshake has been done
+    pub fn remote_key(&self) -> Option<&PublicKey> {
+        self.remote_key.as_ref()
+    }
+
+    /// Establish an encrypted connection and authenticate the remote peer
+    ///
+    /// Both sides generate an ephemeral key and the frame keys are derived from the
+    /// Diffie-Hellman secret of the ephemeral keys. The static keys are then proven by
+    /// signing the hash of the ephemeral keys, which binds them to this connection.
+    /// The static key of the initiator is sent encrypted.
+    ///
+    /// Returns the static key of the remote peer.
+    pub async fn handshake(
+        &mut self,
+        role: HandshakeRole,
+        static_key: &PrivateKey,
+    ) -> error::Result<PublicKey> {
+        let (ephemeral_key, ephemeral_pubkey) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let sign = |role, hash: &[u8]| {
+            static_key.sign_message(&signed_data(role, hash)).map_err(|err| {
+                log::error!("failed to sign handshake: {:?}", err);
+                P2pError::ProtocolError(ProtocolError::AuthenticationFailed)
+            })
+        };
+
+        let (remote_ephemeral, hash) = match role {
+            HandshakeRole::Initiator => {
+                self.send_handshake(HandshakeMessage::Hello {
+                    ephemeral_key: ephemeral_pubkey.clone(),
+                })
+                .await?;
+
+                let (remote_ephemeral, remote_key, signature) = match self.recv_handshake().await? {
+                    HandshakeMessage::HelloAck {
+                        ephemeral_key,
+                        static_key,
+                        signature,
+                    } => (ephemeral_key, static_key, signature),
+                    _ => return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage)),
+                };
+
+                let hash = handshake_hash(&ephemeral_pubkey, &remote_ephemeral);
+                if !remote_key
+                    .verify_message(&signature, &signed_data(HandshakeRole::Responder, &hash))
+                {
+                    return Err(P2pError::ProtocolError(ProtocolError::AuthenticationFailed));
+                }
+                self.remote_key = Some(remote_key);
+
+                (remote_ephemeral, hash)
+            }
+            HandshakeRole::Responder => {
+                let remote_ephemeral = match self.recv_handshake().await? {
+                    HandshakeMessage::Hello { ephemeral_key } => ephemeral_key,
+                    _ => return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage)),
+                };
+
+                let hash = handshake_hash(&remote_ephemeral, &ephemeral_pubkey);
+                self.send_handshake(HandshakeMessage::HelloAck {
+                    ephemeral_key: ephemeral_pubkey,
+                    static_key: PublicKey::from_private_key(static_key),
+                    signature: sign(HandshakeRole::Responder, &hash)?,
+                })
+                .await?;
+
+                (remote_ephemeral, hash)
+            }
+        };
+
+        let remote_role = match role {
+            HandshakeRole::Initiator => HandshakeRole::Responder,
+            HandshakeRole::Responder => HandshakeRole::Initiator,
+        };
+        let secret = ephemeral_key
+            .shared_secret(&remote_ephemeral)
+            .ok_or(P2pError::ProtocolError(ProtocolError::AuthenticationFailed))?;
+        self.cipher = Some(FrameCipher {
+            send_key: derive_key(role, &secret, &hash),
+            send_nonce: 0,
+            recv_key: derive_key(remote_role, &secret, &hash),
+            recv_nonce: 0,
+        });
+
+        match role {
+            HandshakeRole::Initiator => {
+                self.send_handshake(HandshakeMessage::Auth {
+                    static_key: PublicKey::from_private_key(static_key),
+                    signature: sign(HandshakeRole::Initiator, &hash)?,
+                })
+                .await?;
+            }
+            HandshakeRole::Responder => {
+                let (remote_key, signature) = match self.recv_handshake().await? {
+                    HandshakeMessage::Auth {
+                        static_key,
+                        signature,
+                    } => (static_key, signature),
+                    _ => return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage)),
+                };
+
+                if !remote_key
+                    .verify_message(&signature, &signed_data(HandshakeRole::Initiator, &hash))
+                {
+                    return Err(P2pError::ProtocolError(ProtocolError::AuthenticationFailed));
+                }
+                self.remote_key = Some(remote_key);
+            }
+        }
+
+        Ok(self.remote_key.clone().expect("remote key to be known"))
+    }
+
+    async fn send_handshake(&mut self, message: HandshakeMessage) -> error::Result<()> {
+        self.send_frame(message.encode()).await
+    }
+
+    async fn recv_handshake(&mut self) -> error::Result<HandshakeMessage> {
+        let payload = self.recv_frame().await?;
+        HandshakeMessage::decode(&mut &payload[..]).map_err(P2pError::from)
+    }
+
+    /// Encode `message` and send it to the remote peer
+    pub async fn send(&mut self, message: message::Message) -> error::Result<()> {
+        self.send_encoded(message.encode()).await
+    }
+
+    /// Send a message of the mock protocol to the remote peer
+    pub async fn send_peer_message(&mut self, message: &PeerMessage) -> error::Result<()> {
+        self.send_encoded(message.encode()).await
+    }
+
+    async fn send_encoded(&mut self, encoded: Vec<u8>) -> error::Result<()> {
+        if encoded.len() > self.max_message_size {
+            log::error!(
+                "message size ({} bytes) exceeds maximum ({} bytes)",
+                encoded.len(),
+                self.max_message_size,
+            );
+            return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
+        }
+
+        self.send_frame(encoded).await
+    }
+
+    /// Encrypt the payload if the handshake has been done and send it as one frame
+    async fn send_frame(&mut self, payload: Vec<u8>) -> error::Result<()> {
+        let payload = match self.cipher.as_mut() {
+            Some(cipher) => cipher
+                .send_key
+                .encrypt(next_nonce(&mut cipher.send_nonce)?, &payload)
+                .map_err(|_| P2pError::ProtocolError(ProtocolError::InvalidMessage))?,
+            None => payload,
+        };
+
+        let mut frame = Vec::with_capacity(LENGTH_PREFIX_SIZE + payload.len());
+        (payload.len() as u32).encode_to(&mut frame);
+        frame.extend_from_slice(&payload);
+
+        self.socket.write_all(&frame).await?;
+        self.socket.flush().await.map_err(P2pError::from)
+    }
+
+    /// Receive the next message from the remote peer
+    ///
+    /// The function reads from the socket until a full frame has been received,
+    /// possibly over multiple reads. Any bytes following the frame are kept
+    /// in the buffer and used for the next call.
+    pub async fn recv(&mut self) -> error::Result<message::Message> {
+        let payload = self.recv_frame().await?;
+        message::Message::decode_checked(&payload).map_err(P2pError::ProtocolError)
+    }
+
+    /// Receive the next message of the mock protocol from the remote peer
+    ///
+    /// The function is cancellation safe: if it's used in `tokio::select!` and another
+    /// branch completes first, the bytes read so far are kept for the next call.
+    pub async fn recv_peer_message(&mut self) -> error::Result<PeerMessage> {
+        let payload = self.recv_frame().await?;
+        PeerMessage::decode(&mut &payload[..]).map_err(P2pError::from)
+    }
+
+    /// Receive the next frame and decrypt it if the handshake has been done
+    async fn recv_frame(&mut self) -> error::Result<Vec<u8>> {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+
+        let payload = loop {
+            if let Some(payload) = self.decode_frame()? {
+                break payload;
+            }
+
+            match self.socket.read(&mut chunk).await? {
+                0 => return Err(P2pError::PeerDisconnected),
+                n => self.buffer.extend_from_slice(&chunk[..n]),
+            }
+        };
+
+        match self.cipher.as_mut() {
+            Some(cipher) => cipher
+                .recv_key
+                .decrypt(next_nonce(&mut cipher.recv_nonce)?, &payload)
+                .map_err(|_| {
+                    log::error!("failed to decrypt frame");
+                    P2pError::ProtocolError(ProtocolError::InvalidMessage)
+                }),
+            None => Ok(payload),
+        }
+    }
+
+    /// Try to take the payload of one frame from the buffer
+    ///
+    /// Returns `Ok(None)` if the buffer doesn't hold a full frame yet
+    fn decode_frame(&mut self) -> error::Result<Option<Vec<u8>>> {
+        if self.buffer.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+
+        let overhead = if self.cipher.is_some() { TAG_SIZE } else { 0 };
+        let size = u32::decode(&mut &self.buffer[..LENGTH_PREFIX_SIZE])? as usize;
+        if size > self.max_message_size + overhead {
+            log::error!(
+                "received frame size ({} bytes) exceeds maximum ({} bytes)",
+                size,
+                self.max_message_size + overhead,
+            );
+            return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
+        }
+
+        if self.buffer.len() < LENGTH_PREFIX_SIZE + size {
+            return Ok(None);
+        }
+
+        let frame: Vec<u8> = self.buffer.drain(..LENGTH_PREFIX_SIZE + size).collect();
+        Ok(Some(frame[LENGTH_PREFIX_SIZE..].to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{
+        MessageType, PubSubMessage, SyncingMessage, SyncingRequest, SyncingResponse,
+    };
+    use common::{
+        chain::{
+            block::{consensus_data::ConsensusData, Block, BlockHeader},
+            signature::inputsig::InputWitness,
+            Destination, OutPointSourceId, Transaction, TxInput, TxOutput,
+        },
+        primitives::{Amount, Id, H256},
+    };
+    use proptest::{collection::vec, prelude::*};
+    use tokio::net::TcpListener;
+
+    async fn make_socket_pair() -> (MockSocket, TcpStream) {
+        let listener = TcpListener::bind("[::1]:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (accepted, connected) = tokio::join!(listener.accept(), TcpStream::connect(addr));
+        (MockSocket::new(accepted.unwrap().0), connected.unwrap())
+    }
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn gen_id<T: std::fmt::Debug>() -> impl Strategy<Value = Id<T>> {
+        any::<[u8; 32]>().prop_map(|bytes| Id::new(&H256::from(bytes)))
+    }
+
+    fn gen_transaction() -> impl Strategy<Value = Transaction> {
+        let input =
+            (gen_id(), any::<u32>(), any::<Option<Vec<u8>>>()).prop_map(|(id, index, witness)| {
+                TxInput::new(
+                    OutPointSourceId::Transaction(id),
+                    index,
+                    InputWitness::NoSignature(witness),
+                )
+            });
+        let output = any::<u128>().prop_map(|atoms| {
+            TxOutput::new(Amount::from_atoms(atoms), Destination::AnyoneCanSpend)
+        });
+
+        (
+            any::<u32>(),
+            vec(input, 0..4),
+            vec(output, 0..4),
+            any::<u32>(),
+        )
+            .prop_map(|(flags, inputs, outputs, lock_time)| {
+                Transaction::new(flags, inputs, outputs, lock_time).unwrap()
+            })
+    }
+
+    fn gen_block() -> impl Strategy<Value = Block> {
+        (
+            vec(gen_transaction(), 0..4),
+            proptest::option::of(gen_id()),
+            any::<u32>(),
+        )
+            .prop_map(|(transactions, prev_block, time)| {
+                Block::new(transactions, prev_block, time, ConsensusData::None).unwrap()
+            })
+    }
+
+    fn gen_header() -> impl Strategy<Value = BlockHeader> {
+        gen_block().prop_map(|block| block.header().clone())
+    }
+
+    fn gen_message() -> impl Strategy<Value = message::Message> {
+        let msg = prop_oneof![
+            vec(gen_header(), 0..4).prop_map(|locator| MessageType::Syncing(
+                SyncingMessage::Request(SyncingRequest::GetHeaders { locator })
+            )),
+            vec(gen_id(), 0..32).prop_map(|block_ids| MessageType::Syncing(
+                SyncingMessage::Request(SyncingRequest::GetBlocks { block_ids })
+            )),
+            gen_header().prop_map(|header| MessageType::Syncing(SyncingMessage::Request(
+                SyncingRequest::AnnounceHeader { header }
+            ))),
+            vec(gen_header(), 0..4).prop_map(|headers| MessageType::Syncing(
+                SyncingMessage::Response(SyncingResponse::Headers { headers })
+            )),
+            vec(gen_block(), 0..4).prop_map(|blocks| MessageType::Syncing(
+                SyncingMessage::Response(SyncingResponse::Blocks { blocks })
+            )),
+            gen_block().prop_map(|block| MessageType::PubSub(PubSubMessage::Block(block))),
+        ];
+
+        (any::<[u8; 4]>(), msg).prop_map(|(magic, msg)| message::Message { magic, msg })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn prop_send_recv_roundtrip(messages in vec(gen_message(), 1..8)) {
+            run(async {
+                let (mut socket1, stream) = make_socket_pair().await;
+                let mut socket2 = MockSocket::new(stream);
+
+                for message in messages {
+                    socket2.send(message.clone()).await.unwrap();
+                    assert_eq!(socket1.recv().await.unwrap(), message);
+                }
+            })
+        }
+
+        // send multiple messages in one go and verify they're read out one by one
+        #[test]
+        fn prop_multiple_frames_in_one_read(messages in vec(gen_message(), 1..8)) {
+            run(async {
+                let (mut socket1, stream) = make_socket_pair().await;
+                let mut socket2 = MockSocket::new(stream);
+
+                for message in messages.iter() {
+                    socket2.send(message.clone()).await.unwrap();
+                }
+
+                for message in messages {
+                    assert_eq!(socket1.recv().await.unwrap(), message);
+                }
+            })
+        }
+
+        // write the frame in arbitrary chunks and verify the partial reads are buffered correctly
+        #[test]
+        fn prop_partial_reads(message in gen_message(), chunk_size in 1usize..64) {
+            run(async {
+                let (mut socket, mut stream) = make_socket_pair().await;
+                let encoded = message.encode();
+                let mut frame = (encoded.len() as u32).encode();
+                frame.extend_from_slice(&encoded);
+
+                let writer = async {
+                    for chunk in frame.chunks(chunk_size) {
+                        stream.write_all(chunk).await.unwrap();
+                        stream.flush().await.unwrap();
+                    }
+                };
+                let (_, res) = tokio::join!(writer, socket.recv());
+                assert_eq!(res.unwrap(), message);
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn message_too_large() {
+        let (socket1, stream) = make_socket_pair().await;
+        let mut socket2 = MockSocket::new(stream).with_max_message_size(16);
+        let message = message::Message {
+            magic: [1, 2, 3, 4],
+            msg: MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetBlocks {
+                block_ids: vec![Id::new(&H256::random())],
+            })),
+        };
+
+        assert_eq!(
+            socket2.send(message.clone()).await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidMessage))
+        );
+
+        // receiver rejects the frame based on the length prefix
+        let mut socket2 = socket2.with_max_message_size(MESSAGE_MAX_SIZE);
+        socket2.send(message).await.unwrap();
+        let mut socket1 = socket1.with_max_message_size(16);
+        assert_eq!(
+            socket1.recv().await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidMessage))
+        );
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    #[cfg(target_pointer_width = "64")]
+    async fn max_message_size_too_large() {
+        let (socket, _stream) = make_socket_pair().await;
+        let _ = socket.with_max_message_size(u32::MAX as usize + 1);
+    }
+
+    #[tokio::test]
+    async fn remote_closed() {
+        let (mut socket, stream) = make_socket_pair().await;
+        drop(stream);
+
+        assert_eq!(socket.recv().await, Err(P2pError::PeerDisconnected));
+    }
+
+    async fn make_handshaked_pair() -> (MockSocket, MockSocket, PublicKey, PublicKey) {
+        let (mut responder, stream) = make_socket_pair().await;
+        let mut initiator = MockSocket::new(stream);
+        let (responder_key, responder_pubkey) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let (initiator_key, initiator_pubkey) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+
+        let (res1, res2) = tokio::join!(
+            initiator.handshake(HandshakeRole::Initiator, &initiator_key),
+            responder.handshake(HandshakeRole::Responder, &responder_key),
+        );
+        assert_eq!(res1, Ok(responder_pubkey.clone()));
+        assert_eq!(res2, Ok(initiator_pubkey.clone()));
+
+        (initiator, responder, initiator_pubkey, responder_pubkey)
+    }
+
+    #[tokio::test]
+    async fn handshake_and_encrypted_messages() {
+        let (mut initiator, mut responder, initiator_pubkey, responder_pubkey) =
+            make_handshaked_pair().await;
+        assert_eq!(initiator.remote_key(), Some(&responder_pubkey));
+        assert_eq!(responder.remote_key(), Some(&initiator_pubkey));
+
+        let message = message::Message {
+            magic: [1, 2, 3, 4],
+            msg: MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetBlocks {
+                block_ids: vec![Id::new(&H256::random())],
+            })),
+        };
+
+        for _ in 0..3 {
+            initiator.send(message.clone()).await.unwrap();
+            assert_eq!(responder.recv().await.unwrap(), message);
+
+            responder.send(message.clone()).await.unwrap();
+            assert_eq!(initiator.recv().await.unwrap(), message);
+        }
+    }
+
+    #[tokio::test]
+    async fn tampered_frame_rejected() {
+        let (mut initiator, mut responder, _, _) = make_handshaked_pair().await;
+        let message = message::Message {
+            magic: [1, 2, 3, 4],
+            msg: MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetBlocks {
+                block_ids: vec![],
+            })),
+        };
+
+        let cipher = initiator.cipher.as_mut().unwrap();
+        let mut payload = cipher
+            .send_key
+            .encrypt(
+                next_nonce(&mut cipher.send_nonce).unwrap(),
+                &message.encode(),
+            )
+            .unwrap();
+        payload[0] ^= 1;
+
+        let mut frame = (payload.len() as u32).encode();
+        frame.extend_from_slice(&payload);
+        initiator.socket.write_all(&frame).await.unwrap();
+
+        assert_eq!(
+            responder.recv().await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidMessage))
+        );
+    }
+
+    // the responder signs the handshake with a key other than the one it advertises
+    #[tokio::test]
+    async fn handshake_invalid_signature() {
+        let (mut responder, stream) = make_socket_pair().await;
+        let mut initiator = MockSocket::new(stream);
+        let (initiator_key, _) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let (other_key, _) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let (_, static_pubkey) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+
+        let fake_responder = async {
+            let remote_ephemeral = match responder.recv_handshake().await.unwrap() {
+                HandshakeMessage::Hello { ephemeral_key } => ephemeral_key,
+                _ => panic!("invalid message"),
+            };
+            let (_, ephemeral_pubkey) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+            let hash = handshake_hash(&remote_ephemeral, &ephemeral_pubkey);
+
+            responder
+                .send_handshake(HandshakeMessage::HelloAck {
+                    ephemeral_key: ephemeral_pubkey,
+                    static_key: static_pubkey,
+                    signature: other_key
+                        .sign_message(&signed_data(HandshakeRole::Responder, &hash))
+                        .unwrap(),
+                })
+                .await
+                .unwrap();
+        };
+
+        let (res, _) = tokio::join!(
+            initiator.handshake(HandshakeRole::Initiator, &initiator_key),
+            fake_responder,
+        );
+        assert_eq!(
+            res,
+            Err(P2pError::ProtocolError(ProtocolError::AuthenticationFailed))
+        );
+        assert_eq!(initiator.remote_key(), None);
+    }
+
+    #[tokio::test]
+    async fn handshake_unexpected_message() {
+        let (mut responder, stream) = make_socket_pair().await;
+        let mut initiator = MockSocket::new(stream);
+        let (initiator_key, initiator_pubkey) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+        let (responder_key, _) = PrivateKey::new(KeyKind::RistrettoSchnorr);
+
+        initiator
+            .send_handshake(HandshakeMessage::Auth {
+                static_key: initiator_pubkey,
+                signature: initiator_key.sign_message(b"message").unwrap(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            responder.handshake(HandshakeRole::Responder, &responder_key).await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidMessage))
+        );
+    }
+}
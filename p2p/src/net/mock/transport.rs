@@ -0,0 +1,127 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transports of the mock backend
+//!
+//! The backend runs over TCP or over a [SimNetwork] when the tests need to control the
+//! delivery of the messages.
+
+use crate::{
+    error::{self, P2pError},
+    net::mock::{
+        sim::{SimConnection, SimListener, SimNetwork},
+        socket::{HandshakeRole, MockSocket},
+        types::PeerMessage,
+    },
+};
+use crypto::key::PrivateKey;
+use std::{io, net::SocketAddr};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Listener for the inbound connections
+pub enum Listener {
+    Tcp(TcpListener),
+    Sim(SimListener),
+}
+
+impl Listener {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr(),
+            Listener::Sim(listener) => Ok(listener.local_addr()),
+        }
+    }
+
+    /// Accept a connection, returns the socket and the address of the remote peer
+    pub async fn accept(&mut self) -> io::Result<(PeerSocket, SocketAddr)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (socket, addr) = listener.accept().await?;
+                Ok((PeerSocket::Tcp(Box::new(MockSocket::new(socket))), addr))
+            }
+            Listener::Sim(listener) => {
+                let (connection, addr) = listener.accept().await?;
+                Ok((PeerSocket::Sim(connection), addr))
+            }
+        }
+    }
+
+    /// Dialer for the outbound connections over the same transport
+    pub fn dialer(&self) -> Dialer {
+        match self {
+            Listener::Tcp(_) => Dialer::Tcp,
+            Listener::Sim(listener) => {
+                Dialer::Sim(listener.network().clone(), listener.local_addr())
+            }
+        }
+    }
+}
+
+/// Opens the outbound connections
+#[derive(Clone)]
+pub enum Dialer {
+    Tcp,
+    /// Simulated network and the address of the local listener
+    Sim(SimNetwork, SocketAddr),
+}
+
+impl Dialer {
+    pub async fn dial(&self, addr: SocketAddr) -> error::Result<PeerSocket> {
+        match self {
+            Dialer::Tcp => Ok(PeerSocket::Tcp(Box::new(MockSocket::new(
+                TcpStream::connect(addr).await?,
+            )))),
+            Dialer::Sim(network, local_addr) => {
+                Ok(PeerSocket::Sim(network.connect(*local_addr, addr)?))
+            }
+        }
+    }
+}
+
+/// Connection with a remote peer
+pub enum PeerSocket {
+    Tcp(Box<MockSocket>),
+    Sim(SimConnection),
+}
+
+impl PeerSocket {
+    /// Run the handshake of the transport, simulated connections have none
+    pub async fn handshake(
+        &mut self,
+        role: HandshakeRole,
+        static_key: &PrivateKey,
+    ) -> error::Result<()> {
+        match self {
+            PeerSocket::Tcp(socket) => socket.handshake(role, static_key).await.map(|_| ()),
+            PeerSocket::Sim(_) => Ok(()),
+        }
+    }
+
+    pub async fn send_peer_message(&mut self, message: &PeerMessage) -> error::Result<()> {
+        match self {
+            PeerSocket::Tcp(socket) => socket.send_peer_message(message).await,
+            PeerSocket::Sim(connection) => connection.send(message.clone()).map_err(P2pError::from),
+        }
+    }
+
+    pub async fn recv_peer_message(&mut self) -> error::Result<PeerMessage> {
+        match self {
+            PeerSocket::Tcp(socket) => socket.recv_peer_message().await,
+            PeerSocket::Sim(connection) => {
+                connection.recv().await.ok_or(P2pError::PeerDisconnected)
+            }
+        }
+    }
+}
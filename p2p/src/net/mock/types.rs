@@ -14,32 +14,172 @@
 // limitations under the License.
 //
 // Author(s): A. Altonen
-use crate::{error, message, net};
+use crate::{
+    error, message,
+    net::{
+        self,
+        mock::{MockMessageId, MockRequestId},
+    },
+};
+use common::{chain::services::Services, primitives::version::SemVer};
+use crypto::key::{PublicKey, Signature};
+use serialization::{Decode, Encode};
 use std::net::SocketAddr;
-use tokio::{net::TcpStream, sync::oneshot};
+use tokio::sync::oneshot;
 
 pub enum Command {
     Connect {
         addr: SocketAddr,
-        response: oneshot::Sender<error::Result<TcpStream>>,
+        response: oneshot::Sender<error::Result<PeerInfo>>,
+    },
+    Disconnect {
+        peer_id: SocketAddr,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    SendRequest {
+        peer_id: SocketAddr,
+        message: message::Message,
+        response: oneshot::Sender<error::Result<MockRequestId>>,
+    },
+    SendResponse {
+        request_id: MockRequestId,
+        message: message::Message,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    AnnounceData {
+        message: message::Message,
+        response: oneshot::Sender<error::Result<()>>,
+    },
+    ReportValidationResult {
+        message_id: MockMessageId,
+        result: net::ValidationResult,
+        response: oneshot::Sender<error::Result<()>>,
     },
 }
 
+/// Information the remote peer sent in its `Hello` message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub peer_id: SocketAddr,
+    pub magic_bytes: [u8; 4],
+    pub version: SemVer,
+    pub agent: String,
+    pub services: Services,
+    pub protocols: Vec<String>,
+    pub observed_addr: Option<SocketAddr>,
+}
+
+#[derive(Debug)]
 pub enum ConnectivityEvent {
     IncomingConnection {
+        addr: SocketAddr,
+        peer_info: PeerInfo,
+    },
+    ConnectionClosed {
+        peer_id: SocketAddr,
+    },
+    PingResponse {
+        peer_id: SocketAddr,
+        rtt: std::time::Duration,
+    },
+    PingTimeout {
+        peer_id: SocketAddr,
+    },
+    Misbehaved {
         peer_id: SocketAddr,
-        socket: TcpStream,
+        behaviour: u32,
     },
 }
 
 // TODO: use two events, one for txs and one for blocks?
+#[derive(Debug)]
 pub enum PubSubEvent {
     /// Message received from one of the pubsub topics
     MessageReceived {
         peer_id: SocketAddr,
-        topic: net::PubSubTopic,
+        message_id: MockMessageId,
         message: message::Message,
     },
 }
 
-pub enum SyncingEvent {}
+#[derive(Debug)]
+pub enum SyncingEvent {
+    Request {
+        peer_id: SocketAddr,
+        request_id: MockRequestId,
+        request: message::Message,
+    },
+    Response {
+        peer_id: SocketAddr,
+        request_id: MockRequestId,
+        response: message::Message,
+    },
+    Error {
+        peer_id: SocketAddr,
+        request_id: MockRequestId,
+        error: net::RequestResponseError,
+    },
+}
+
+/// Messages exchanged with a peer after the handshake
+///
+/// The p2p messages are carried as encoded bytes so that their limits can be checked
+/// with [`message::Message::decode_checked()`] before they are decoded.
+#[derive(Debug, Encode, Decode, Clone, PartialEq, Eq)]
+pub enum PeerMessage {
+    /// Information about the sending node, the first message sent by both sides
+    #[codec(index = 0)]
+    Hello {
+        magic_bytes: [u8; 4],
+        version: SemVer,
+        agent: String,
+        services: Services,
+        protocols: Vec<String>,
+        listen_port: u16,
+        observed_addr: String,
+    },
+
+    /// Request of the syncing protocol, answered with a `Response` with the same ID
+    #[codec(index = 1)]
+    Request { request_id: u64, message: Vec<u8> },
+
+    #[codec(index = 2)]
+    Response { request_id: u64, message: Vec<u8> },
+
+    /// Message published to a pubsub topic, flooded to all peers once validated
+    #[codec(index = 3)]
+    Announcement { message: Vec<u8> },
+
+    #[codec(index = 4)]
+    Ping { nonce: u64 },
+
+    #[codec(index = 5)]
+    Pong { nonce: u64 },
+}
+
+/// Messages of the handshake that establishes an encrypted connection
+///
+/// The initiator and the responder exchange ephemeral keys from which the keys used
+/// to encrypt the frames are derived. Both sides then prove the ownership of their
+/// static keys by signing the ephemeral keys of the connection.
+#[derive(Debug, Encode, Decode, Clone, PartialEq, Eq)]
+pub enum HandshakeMessage {
+    /// Ephemeral key of the initiator
+    #[codec(index = 0)]
+    Hello { ephemeral_key: PublicKey },
+
+    /// Ephemeral and static keys of the responder
+    #[codec(index = 1)]
+    HelloAck {
+        ephemeral_key: PublicKey,
+        static_key: PublicKey,
+        signature: Signature,
+    },
+
+    /// Static key of the initiator
+    #[codec(index = 2)]
+    Auth {
+        static_key: PublicKey,
+        signature: Signature,
+    },
+}
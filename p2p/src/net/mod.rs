@@ -16,7 +16,7 @@
 // Author(s): A. Altonen
 use crate::{error, message};
 use async_trait::async_trait;
-use common::primitives;
+use common::{chain::services::Services, primitives};
 use std::{fmt::Debug, hash::Hash, sync::Arc};
 
 pub mod libp2p;
@@ -55,9 +55,15 @@ where
     /// User agent of the peer
     pub agent: Option<String>,
 
+    /// Services the peer provides
+    pub services: Services,
+
     // TODO: protocolid must not generic!
     /// List of supported protocols
     pub protocols: Vec<T::ProtocolId>,
+
+    /// Address the local node may be reachable at, according to what the peer observed
+    pub observed_addr: Option<T::Address>,
 }
 
 // TODO: rename to `SwarmEvent`!
@@ -111,6 +117,21 @@ where
         error: error::P2pError,
     },
 
+    /// Peer responded to a ping
+    PingResponse {
+        /// Unique ID of the peer
+        peer_id: T::PeerId,
+
+        /// Round-trip time of the ping
+        rtt: std::time::Duration,
+    },
+
+    /// Peer didn't respond to a ping in time
+    PingTimeout {
+        /// Unique ID of the peer
+        peer_id: T::PeerId,
+    },
+
     /// Peer misbehaved
     Misbehaved {
         /// Unique ID of the peer
@@ -121,6 +142,10 @@ where
     },
 }
 
+/// Misbehavior score of a peer that sent a message that couldn't be decoded
+/// or that exceeded the limits of its type
+pub const INVALID_MESSAGE_SCORE: u32 = 100;
+
 // TODO: separate events for blocks and transactions?
 #[derive(Debug)]
 pub enum PubSubEvent<T>
@@ -201,14 +226,14 @@ pub trait NetworkingService {
     /// Unique ID assigned to a peer on the network
     type PeerId: Send + Copy + PartialEq + Eq + Hash + Debug + Sync + ToString;
 
-    // TODO:
+    /// Unique ID assigned to each outbound and inbound request
     type RequestId: Send + Debug + Eq + Hash + Sync;
 
     /// Enum of different peer discovery strategies that the implementation provides
     type DiscoveryStrategy;
 
     /// Id that identifies a protocol
-    type ProtocolId: Debug + Send + Clone + PartialEq;
+    type ProtocolId: Debug + Send + Clone + PartialEq + AsRef<str>;
 
     /// Handle for sending/receiving connecitivity-related events
     type ConnectivityHandle: Send;
@@ -216,29 +241,44 @@ pub trait NetworkingService {
     /// Handle for sending/receiving pubsub-related events
     type PubSubHandle: Send;
 
-    // TODO:
-    type SyncingCodecHandle: Send;
+    /// Handle for sending/receiving request-response messages
+    type SyncingHandle: Send;
 
     /// Unique ID assigned to each pubsub message
     type MessageId: Send + Clone + Debug;
 
+    /// Convert a resolved socket address into an address the implementation can dial
+    ///
+    /// Returns `None` if the implementation needs more information to establish
+    /// a connection than what the socket address provides.
+    fn from_socket_addr(addr: std::net::SocketAddr) -> Option<Self::Address>;
+
+    /// Get the socket address of an address, if it contains one
+    fn to_socket_addr(addr: &Self::Address) -> Option<std::net::SocketAddr>;
+
+    /// Get the ID of the peer an address belongs to, if the address identifies the peer
+    fn peer_id_from_addr(addr: &Self::Address) -> Option<Self::PeerId>;
+
     /// Initialize the network service provider
     ///
     /// # Arguments
     /// `bind_addr` - socket address for incoming P2P traffic
     /// `strategies` - list of strategies that are used for peer discovery
     /// `topics` - list of pubsub topics that the implementation should subscribe to
+    /// `chain_config` - consensus parameters of the chain
+    /// `p2p_config` - settings of the local node, such as its identity and proxy
     /// `timeout` - timeout for outbound connections
     async fn start(
         bind_addr: Self::Address,
         strategies: &[Self::DiscoveryStrategy],
         topics: &[PubSubTopic],
         chain_config: Arc<common::chain::ChainConfig>,
+        p2p_config: Arc<crate::config::P2pConfig>,
         timeout: std::time::Duration,
     ) -> error::Result<(
         Self::ConnectivityHandle,
         Self::PubSubHandle,
-        Self::SyncingCodecHandle,
+        Self::SyncingHandle,
     )>;
 }
 
@@ -306,25 +346,47 @@ where
     async fn poll_next(&mut self) -> error::Result<PubSubEvent<T>>;
 }
 
+/// SyncingService provides an interface through which the sync manager can exchange
+/// header and block requests/responses with remote peers
+///
+/// Each outbound request is assigned a unique ID by the backend which is used to
+/// match the eventual response (or error) to the request. The number of unanswered
+/// requests per peer is limited by the backend.
 #[async_trait]
-pub trait SyncingCodecService<T>
+pub trait SyncingService<T>
 where
     T: NetworkingService,
 {
-    // TODO:
+    /// Send a request to remote peer
+    ///
+    /// Returns the ID assigned to the request or `P2pError::TooManyPendingRequests`
+    /// if the peer already has the maximum number of unanswered requests
+    ///
+    /// # Arguments
+    /// `peer_id` - Peer ID of the remote peer
+    /// `message` - request to send
     async fn send_request(
         &mut self,
         peer_id: T::PeerId,
         message: message::Message,
     ) -> error::Result<T::RequestId>;
 
-    // TODO:
+    /// Send a response to a previously received request
+    ///
+    /// # Arguments
+    /// `request_id` - ID of the inbound request
+    /// `message` - response to send
     async fn send_response(
         &mut self,
         request_id: T::RequestId,
         message: message::Message,
     ) -> error::Result<()>;
 
-    // TODO:
+    /// Poll syncing-related events from the network service provider
+    ///
+    /// There are three types of events that can be received:
+    /// - inbound requests
+    /// - responses to outbound requests
+    /// - errors (timeouts, closed connections) related to a request
     async fn poll_next(&mut self) -> error::Result<SyncingEvent<T>>;
 }
@@ -0,0 +1,142 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Port mapping through a UPnP Internet Gateway Device
+//!
+//! Nodes behind a home router are not reachable from the outside unless the router
+//! forwards the p2p port to them. The port mapping subsystem asks the gateway to do that
+//! and keeps renewing the mapping for as long as the node is running.
+
+use logging::log;
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    time::Duration,
+};
+use subsystem::subsystem::{CallRequest, ShutdownRequest};
+
+/// How long the gateway keeps the mapping if it is not renewed
+const LEASE_DURATION: Duration = Duration::from_secs(20 * 60);
+
+/// How often the mapping is renewed, well before the lease expires
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How long to wait for a gateway to answer the discovery request
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Description of the mapping shown in the gateway's configuration
+const DESCRIPTION: &str = "mintlayer";
+
+#[derive(thiserror::Error, Debug)]
+pub enum PortMapError {
+    #[error("Gateway not found: {0}")]
+    Search(#[from] igd::SearchError),
+    #[error("Failed to determine the local address: {0}")]
+    LocalAddress(#[from] io::Error),
+    #[error("Failed to add port mapping: {0}")]
+    AddPort(#[from] igd::AddPortError),
+    #[error("Failed to remove port mapping: {0}")]
+    RemovePort(#[from] igd::RemovePortError),
+}
+
+/// Local address the node uses to communicate with `gateway`
+///
+/// No packets are sent, connecting a UDP socket only selects the route.
+fn local_addr_towards(gateway: SocketAddrV4) -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(gateway)?;
+
+    match socket.local_addr()? {
+        SocketAddr::V4(addr) => Ok(*addr.ip()),
+        SocketAddr::V6(addr) => Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("unexpected IPv6 address {addr}"),
+        )),
+    }
+}
+
+fn search_gateway() -> Result<igd::Gateway, PortMapError> {
+    igd::search_gateway(igd::SearchOptions {
+        timeout: Some(SEARCH_TIMEOUT),
+        ..Default::default()
+    })
+    .map_err(PortMapError::from)
+}
+
+/// Ask the gateway to forward TCP `port` on its external address to the same port
+/// on the local node
+///
+/// Returns the external address the node is reachable at.
+fn add_mapping(port: u16) -> Result<SocketAddrV4, PortMapError> {
+    let gateway = search_gateway()?;
+    let local_addr = SocketAddrV4::new(local_addr_towards(gateway.addr)?, port);
+
+    gateway.add_port(
+        igd::PortMappingProtocol::TCP,
+        port,
+        local_addr,
+        LEASE_DURATION.as_secs() as u32,
+        DESCRIPTION,
+    )?;
+
+    let external_ip = gateway.get_external_ip().unwrap_or(Ipv4Addr::UNSPECIFIED);
+    Ok(SocketAddrV4::new(external_ip, port))
+}
+
+fn remove_mapping(port: u16) -> Result<(), PortMapError> {
+    search_gateway()?
+        .remove_port(igd::PortMappingProtocol::TCP, port)
+        .map_err(PortMapError::from)
+}
+
+/// Run the port mapping subsystem
+///
+/// The mapping for `port` is requested at startup and renewed every `REFRESH_INTERVAL`.
+/// Failures are not fatal, the node simply isn't reachable through the gateway, and the
+/// mapping is retried on the next refresh. The mapping is removed on shutdown.
+pub async fn run(port: u16, mut call_rq: CallRequest<()>, mut shutdown_rq: ShutdownRequest) {
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = refresh.tick() => {
+                match tokio::task::spawn_blocking(move || add_mapping(port)).await {
+                    Ok(Ok(addr)) => log::debug!("port {port} mapped to external address {addr}"),
+                    Ok(Err(err)) => log::warn!("port mapping failed: {err}"),
+                    Err(err) => log::error!("port mapping task failed: {err}"),
+                }
+            }
+            () = shutdown_rq.recv() => break,
+            call = call_rq.recv() => call(&mut ()).await,
+        }
+    }
+
+    if let Ok(Err(err)) = tokio::task::spawn_blocking(move || remove_mapping(port)).await {
+        log::debug!("failed to remove port mapping: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_address() {
+        assert_eq!(
+            local_addr_towards(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1900)).unwrap(),
+            Ipv4Addr::LOCALHOST
+        );
+    }
+}
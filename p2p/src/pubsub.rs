@@ -18,10 +18,11 @@ use crate::{
     error::{self, P2pError},
     event,
     message::{self, Message, MessageType, PubSubMessage},
+    metrics,
     net::{self, NetworkingService, PubSubService},
 };
 use chainstate::{chainstate_interface, BlockError, ChainstateError::ProcessBlockError};
-use common::chain::ChainConfig;
+use common::{chain::ChainConfig, primitives::Idable};
 use futures::FutureExt;
 use logging::log;
 use std::sync::Arc;
@@ -38,6 +39,7 @@ where
     pubsub_handle: T::PubSubHandle,
     chainstate_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
     rx_pubsub: mpsc::Receiver<event::PubSubControlEvent>,
+    tx_swarm: mpsc::Sender<event::SwarmEvent<T>>,
 }
 
 impl<T> PubSubMessageHandler<T>
@@ -50,12 +52,14 @@ where
         pubsub_handle: T::PubSubHandle,
         chainstate_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
         rx_pubsub: mpsc::Receiver<event::PubSubControlEvent>,
+        tx_swarm: mpsc::Sender<event::SwarmEvent<T>>,
     ) -> Self {
         Self {
             config,
             pubsub_handle,
             chainstate_handle,
             rx_pubsub,
+            tx_swarm,
         }
     }
 
@@ -157,6 +161,7 @@ where
 
                     match message {
                         PubSubMessage::Block(block) => {
+                            let block_id = block.get_id();
                             let result = match self
                                 .chainstate_handle
                                 .call_mut(move |this| {
@@ -167,6 +172,22 @@ where
                                 Ok(_) => net::ValidationResult::Accept,
                                 Err(ProcessBlockError(BlockError::BlockAlreadyExists(_id))) =>
                                     net::ValidationResult::Accept, // TODO: ignore?
+                                Err(ProcessBlockError(BlockError::IllegalOrphan)) => {
+                                    metrics::metrics().orphan_block_announced();
+                                    log::debug!(
+                                        "orphan block {:?} announced by peer {:?}",
+                                        block_id,
+                                        peer_id
+                                    );
+                                    self.tx_swarm
+                                        .send(event::SwarmEvent::OrphanBlock {
+                                            peer_id,
+                                            block_id: block_id.clone(),
+                                        })
+                                        .await
+                                        .map_err(P2pError::from)?;
+                                    net::ValidationResult::Reject
+                                }
                                 Err(err) => {
                                     // TODO: report misbehaviour to swarm manager and close connection
                                     log::error!(
@@ -179,6 +200,12 @@ where
                                 net::ValidationResult::Reject
                                 }
                             };
+                            if matches!(result, net::ValidationResult::Accept) {
+                                self.tx_swarm
+                                    .send(event::SwarmEvent::BlockAnnounced { peer_id, block_id })
+                                    .await
+                                    .map_err(P2pError::from)?;
+                            }
                             self.pubsub_handle
                                 .report_validation_result(peer_id, message_id, result)
                                 .await?;
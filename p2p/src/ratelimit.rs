@@ -0,0 +1,122 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Token bucket used to limit how often a peer can send expensive messages
+
+use std::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    /// Number of tokens currently available
+    tokens: f64,
+
+    /// Maximum number of tokens the bucket can hold
+    capacity: f64,
+
+    /// Number of tokens added to the bucket per second
+    rate: f64,
+
+    /// Time when the bucket was last replenished
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Create a full bucket
+    ///
+    /// # Arguments
+    /// `capacity` - maximum number of tokens the bucket can hold
+    /// `rate` - number of tokens added to the bucket per second
+    /// `now` - current time
+    pub fn new(capacity: f64, rate: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            capacity,
+            rate,
+            updated: now,
+        }
+    }
+
+    /// Set the number of tokens the bucket initially holds
+    pub fn with_tokens(mut self, tokens: f64) -> Self {
+        self.tokens = f64::min(tokens, self.capacity);
+        self
+    }
+
+    fn replenish(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.tokens = f64::min(
+            self.tokens + elapsed.as_secs_f64() * self.rate,
+            self.capacity,
+        );
+        self.updated = std::cmp::max(self.updated, now);
+    }
+
+    /// Consume as many of the `count` tokens as are available and return how many were consumed
+    pub fn consume(&mut self, count: usize, now: Instant) -> usize {
+        self.replenish(now);
+
+        let allowed = std::cmp::min(count, self.tokens as usize);
+        self.tokens -= allowed as f64;
+        allowed
+    }
+
+    /// Consume `count` tokens if they are all available
+    pub fn try_consume(&mut self, count: usize, now: Instant) -> bool {
+        self.replenish(now);
+
+        if self.tokens < count as f64 {
+            return false;
+        }
+        self.tokens -= count as f64;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn partial_consume() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(100.0, 0.1, now).with_tokens(1.0);
+
+        assert_eq!(bucket.consume(10, now), 1);
+        assert_eq!(bucket.consume(10, now), 0);
+        assert_eq!(bucket.consume(10, now + Duration::from_secs(50)), 5);
+
+        // the number of tokens is capped
+        assert_eq!(
+            bucket.consume(1000, now + Duration::from_secs(100_000)),
+            100
+        );
+    }
+
+    #[test]
+    fn all_or_nothing() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(5.0, 1.0, now);
+
+        assert!(bucket.try_consume(3, now));
+        assert!(!bucket.try_consume(3, now));
+        assert!(bucket.try_consume(2, now));
+        assert!(!bucket.try_consume(1, now));
+        assert!(bucket.try_consume(1, now + Duration::from_secs(1)));
+
+        // time going backwards doesn't add tokens
+        assert!(!bucket.try_consume(1, now));
+    }
+}
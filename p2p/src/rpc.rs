@@ -15,10 +15,36 @@
 //
 // Author(s): L. Kuklinek, A. Altonen
 
-use crate::{error::P2pError, net::NetworkingService};
+use crate::{
+    error::P2pError,
+    event::{BannedAddress, ConnectedPeer, PeerInfo, PeerStats},
+    net::NetworkingService,
+    sync::SyncState,
+};
 use std::{fmt::Debug, str::FromStr};
 use subsystem::subsystem::CallError;
 
+/// Error codes of the p2p RPC errors, see [`rpc::error_code`] for the code space
+///
+/// The errors carry [`rpc::ErrorData`] with the name of the [`P2pError`] variant.
+pub mod error_code {
+    /// The address or peer ID couldn't be parsed
+    pub const INVALID_ADDRESS: i32 = -3001;
+    /// The peer isn't connected
+    pub const PEER_NOT_FOUND: i32 = -3002;
+    /// The peer is already connected
+    pub const PEER_EXISTS: i32 = -3003;
+    /// The address of the peer is banned
+    pub const PEER_BANNED: i32 = -3004;
+    /// No peers are connected
+    pub const NO_PEERS: i32 = -3005;
+    /// Connecting to or communicating with the peer failed
+    pub const NETWORK_ERROR: i32 = -3006;
+    /// The request couldn't be served because of an internal error, such as a failure of
+    /// another subsystem
+    pub const INTERNAL_ERROR: i32 = -3007;
+}
+
 #[rpc::rpc(server, namespace = "p2p")]
 trait P2pRpc {
     /// Connect to remote node
@@ -41,9 +67,35 @@ trait P2pRpc {
     #[method(name = "get_peer_id")]
     async fn get_peer_id(&self) -> rpc::Result<String>;
 
-    /// Get peer IDs of connected peers
+    /// Get peer IDs and round-trip times of connected peers
     #[method(name = "get_connected_peers")]
-    async fn get_connected_peers(&self) -> rpc::Result<Vec<String>>;
+    async fn get_connected_peers(&self) -> rpc::Result<Vec<ConnectedPeer>>;
+
+    /// Get address, version, uptime and other details of connected peers
+    #[method(name = "get_peer_info")]
+    async fn get_peer_info(&self) -> rpc::Result<Vec<PeerInfo>>;
+
+    /// Get bytes and messages exchanged with connected peers
+    #[method(name = "get_peer_stats")]
+    async fn get_peer_stats(&self) -> rpc::Result<Vec<PeerStats>>;
+
+    /// Get the syncing state of the local node
+    #[method(name = "get_sync_state")]
+    async fn get_sync_state(&self) -> rpc::Result<SyncState>;
+
+    /// Ban an IP address and disconnect the peers connected from it
+    ///
+    /// The ban lasts `duration_secs` seconds, 24 hours if not given.
+    #[method(name = "ban")]
+    async fn ban(&self, addr: String, duration_secs: Option<u64>) -> rpc::Result<()>;
+
+    /// Lift the ban of an IP address, returns `false` if the address wasn't banned
+    #[method(name = "unban")]
+    async fn unban(&self, addr: String) -> rpc::Result<bool>;
+
+    /// Get the banned IP addresses and the time left until their ban expires
+    #[method(name = "get_banned")]
+    async fn get_banned(&self) -> rpc::Result<Vec<BannedAddress>>;
 }
 
 #[async_trait::async_trait]
@@ -80,13 +132,114 @@ where
         handle_error(res)
     }
 
-    async fn get_connected_peers(&self) -> rpc::Result<Vec<String>> {
+    async fn get_connected_peers(&self) -> rpc::Result<Vec<ConnectedPeer>> {
         let res = self.call_async(|this| Box::pin(this.get_connected_peers())).await;
         handle_error(res)
     }
+
+    async fn get_peer_info(&self) -> rpc::Result<Vec<PeerInfo>> {
+        let res = self.call_async(|this| Box::pin(this.get_peer_info())).await;
+        handle_error(res)
+    }
+
+    async fn get_peer_stats(&self) -> rpc::Result<Vec<PeerStats>> {
+        let res = self.call_async(|this| Box::pin(this.get_peer_stats())).await;
+        handle_error(res)
+    }
+
+    async fn get_sync_state(&self) -> rpc::Result<SyncState> {
+        let res = self.call_async(|this| Box::pin(this.get_sync_state())).await;
+        handle_error(res)
+    }
+
+    async fn ban(&self, addr: String, duration_secs: Option<u64>) -> rpc::Result<()> {
+        let res = self.call_async_mut(move |this| Box::pin(this.ban(addr, duration_secs))).await;
+        handle_error(res)
+    }
+
+    async fn unban(&self, addr: String) -> rpc::Result<bool> {
+        let res = self.call_async_mut(|this| Box::pin(this.unban(addr))).await;
+        handle_error(res)
+    }
+
+    async fn get_banned(&self) -> rpc::Result<Vec<BannedAddress>> {
+        let res = self.call_async(|this| Box::pin(this.get_banned())).await;
+        handle_error(res)
+    }
+}
+
+fn p2p_error_code(e: &P2pError) -> i32 {
+    match e {
+        P2pError::InvalidAddress | P2pError::InvalidPeerId => error_code::INVALID_ADDRESS,
+        P2pError::PeerDoesntExist => error_code::PEER_NOT_FOUND,
+        P2pError::PeerExists => error_code::PEER_EXISTS,
+        P2pError::PeerBanned => error_code::PEER_BANNED,
+        P2pError::NoPeers => error_code::NO_PEERS,
+        P2pError::SocketError(_)
+        | P2pError::PeerDisconnected
+        | P2pError::DecodeFailure(_)
+        | P2pError::ProtocolError(_)
+        | P2pError::TimeError(_)
+        | P2pError::Libp2pError(_) => error_code::NETWORK_ERROR,
+        P2pError::Unknown(_)
+        | P2pError::ChannelClosed
+        | P2pError::InvalidData
+        | P2pError::SubsystemFailure
+        | P2pError::ChainstateError(_)
+        | P2pError::DatabaseFailure
+        | P2pError::TooManyPendingRequests => error_code::INTERNAL_ERROR,
+    }
+}
+
+/// Report a p2p error with its code and the name of the error as its kind
+fn p2p_error(e: P2pError) -> rpc::Error {
+    rpc::error(p2p_error_code(&e), &e, (&e).into())
 }
 
 fn handle_error<T>(e: Result<Result<T, P2pError>, CallError>) -> rpc::Result<T> {
-    e.map_err(rpc::Error::to_call_error)
-        .and_then(|r| r.map_err(rpc::Error::to_call_error))
+    e.map_err(rpc::subsystem_unavailable)?.map_err(p2p_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::types::error::CallError as RpcCallError;
+
+    #[test]
+    fn structured_errors() {
+        let error_object = |res: rpc::Result<()>| match res {
+            Err(rpc::Error::Call(RpcCallError::Custom(e))) => {
+                (e.code(), e.data().map(|data| data.get().to_owned()))
+            }
+            res => panic!("expected a custom error, got {:?}", res),
+        };
+
+        assert_eq!(
+            error_object(handle_error(Ok(Err(P2pError::InvalidAddress)))),
+            (
+                error_code::INVALID_ADDRESS,
+                Some(r#"{"kind":"invalid_address"}"#.to_owned())
+            )
+        );
+        assert_eq!(
+            error_object(handle_error(Ok(Err(P2pError::PeerDoesntExist)))),
+            (
+                error_code::PEER_NOT_FOUND,
+                Some(r#"{"kind":"peer_doesnt_exist"}"#.to_owned())
+            )
+        );
+        assert_eq!(
+            error_object(handle_error(Ok(Err(P2pError::ChainstateError(
+                chainstate::ChainstateError::FailedToInitializeChainstate(String::new())
+            ))))),
+            (
+                error_code::INTERNAL_ERROR,
+                Some(r#"{"kind":"chainstate_error"}"#.to_owned())
+            )
+        );
+        assert_eq!(
+            error_object(handle_error::<()>(Err(CallError::SubsystemDead))).0,
+            rpc::error_code::SUBSYSTEM_UNAVAILABLE
+        );
+    }
 }
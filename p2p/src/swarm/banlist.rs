@@ -0,0 +1,116 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! IP addresses banned by the node operator
+//!
+//! Connections from banned addresses are refused and banned addresses are not dialed.
+//! A ban is lifted when it expires or when the operator removes it.
+
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// Ban duration used if the operator doesn't specify one
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct BanList {
+    /// Banned addresses and the time their ban expires
+    banned: BTreeMap<IpAddr, Instant>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self {
+            banned: BTreeMap::new(),
+        }
+    }
+
+    /// Ban an address until `until`, replacing any earlier ban of the address
+    pub fn ban(&mut self, ip: IpAddr, until: Instant) {
+        self.banned.insert(ip, until);
+    }
+
+    /// Lift the ban of an address
+    ///
+    /// Returns `false` if the address wasn't banned.
+    pub fn unban(&mut self, ip: &IpAddr) -> bool {
+        self.banned.remove(ip).is_some()
+    }
+
+    /// Check whether an address is banned at `now`
+    pub fn is_banned(&self, ip: &IpAddr, now: Instant) -> bool {
+        matches!(self.banned.get(ip), Some(until) if *until > now)
+    }
+
+    /// Forget the bans that have expired by `now`
+    pub fn expire(&mut self, now: Instant) {
+        self.banned.retain(|_, until| *until > now);
+    }
+
+    /// Banned addresses and the time left until their ban expires
+    pub fn banned(&self, now: Instant) -> impl Iterator<Item = (IpAddr, Duration)> + '_ {
+        self.banned
+            .iter()
+            .filter(move |(_, until)| **until > now)
+            .map(move |(ip, until)| (*ip, until.duration_since(now)))
+    }
+}
+
+impl Default for BanList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ban_expires() {
+        let mut banlist = BanList::new();
+        let now = Instant::now();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        banlist.ban(ip, now + Duration::from_secs(60));
+        assert!(banlist.is_banned(&ip, now));
+        assert!(!banlist.is_banned(&"10.0.0.2".parse().unwrap(), now));
+        assert_eq!(
+            banlist.banned(now).collect::<Vec<_>>(),
+            vec![(ip, Duration::from_secs(60))]
+        );
+
+        let later = now + Duration::from_secs(60);
+        assert!(!banlist.is_banned(&ip, later));
+        assert_eq!(banlist.banned(later).count(), 0);
+
+        banlist.expire(later);
+        assert!(!banlist.unban(&ip));
+    }
+
+    #[test]
+    fn unban() {
+        let mut banlist = BanList::new();
+        let now = Instant::now();
+        let ip: IpAddr = "::1".parse().unwrap();
+
+        assert!(!banlist.unban(&ip));
+        banlist.ban(ip, now + DEFAULT_BAN_DURATION);
+        assert!(banlist.unban(&ip));
+        assert!(!banlist.is_banned(&ip, now));
+    }
+}
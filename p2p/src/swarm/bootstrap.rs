@@ -0,0 +1,107 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Seed nodes used to join the network when no other peers are known
+
+use crate::net::NetworkingService;
+use common::chain::ChainConfig;
+use logging::log;
+use std::{fmt::Debug, net::SocketAddr, str::FromStr};
+
+/// Resolve DNS seeds into socket addresses
+///
+/// Seeds that fail to resolve are skipped.
+///
+/// # Arguments
+/// `seeds` - hostnames of the DNS seeds
+/// `port` - port that is used for all resolved addresses
+pub async fn resolve_dns_seeds(seeds: &[String], port: u16) -> Vec<SocketAddr> {
+    let mut addrs = Vec::new();
+
+    for seed in seeds {
+        match tokio::net::lookup_host((seed.as_str(), port)).await {
+            Ok(resolved) => addrs.extend(resolved),
+            Err(err) => log::warn!("failed to resolve dns seed {}: {:?}", seed, err),
+        }
+    }
+
+    addrs
+}
+
+/// Collect the addresses of the seed nodes of the chain
+///
+/// Fixed seeds are parsed into addresses of the networking service and DNS seeds
+/// are resolved and converted using [`NetworkingService::from_socket_addr()`].
+/// Duplicate addresses are removed.
+pub async fn seed_addresses<T>(config: &ChainConfig) -> Vec<T::Address>
+where
+    T: NetworkingService,
+    T::Address: FromStr,
+    <T::Address as FromStr>::Err: Debug,
+{
+    let mut addrs: Vec<T::Address> = Vec::new();
+
+    for seed in config.fixed_seeds() {
+        match seed.parse::<T::Address>() {
+            Ok(addr) if !addrs.contains(&addr) => addrs.push(addr),
+            Ok(_) => {}
+            Err(err) => log::error!("invalid fixed seed {}: {:?}", seed, err),
+        }
+    }
+
+    for addr in resolve_dns_seeds(config.dns_seeds(), config.p2p_port()).await {
+        match T::from_socket_addr(addr) {
+            Some(addr) if !addrs.contains(&addr) => addrs.push(addr),
+            Some(_) => {}
+            None => log::trace!("address {:?} can't be dialed by the backend", addr),
+        }
+    }
+
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::{libp2p::Libp2pService, mock::MockService};
+    use common::chain::config::ChainConfigBuilder;
+
+    #[tokio::test]
+    async fn resolve_localhost() {
+        let addrs = resolve_dns_seeds(&["localhost".to_string()], 8888).await;
+
+        assert!(!addrs.is_empty());
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback() && addr.port() == 8888));
+    }
+
+    #[tokio::test]
+    async fn invalid_seeds_are_skipped() {
+        let config = ChainConfigBuilder::test_chain()
+            .with_fixed_seeds(vec![
+                "[::1]:8888".to_string(),
+                "invalid address".to_string(),
+                "[::1]:8888".to_string(),
+                "127.0.0.1:9999".to_string(),
+            ])
+            .build();
+
+        assert_eq!(
+            seed_addresses::<MockService>(&config).await,
+            vec!["[::1]:8888".parse().unwrap(), "127.0.0.1:9999".parse().unwrap(),],
+        );
+        // the seeds are not valid multiaddresses
+        assert!(seed_addresses::<Libp2pService>(&config).await.is_empty());
+    }
+}
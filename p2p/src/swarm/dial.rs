@@ -0,0 +1,190 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Outbound dials that failed
+//!
+//! Failed dials are counted per address. Addresses that were given by the user or that
+//! belong to seed nodes are queued and dialed again once their backoff has elapsed, and
+//! an address that keeps failing is given up on. For discovered peers the failure count
+//! is used to demote the addresses that can't be dialed.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Backoff after the first failed dial
+pub const BASE_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Maximum backoff between two dials of a queued address
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+
+/// Number of consecutive failures after which a queued address is no longer dialed
+pub const MAX_RETRIES: u32 = 8;
+
+/// Number of consecutive failures after which an address of a discovered peer is removed
+pub const MAX_ADDR_FAILURES: u32 = 3;
+
+/// Time to wait before dialing an address that has failed `failures` times in a row
+pub fn backoff(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    BASE_BACKOFF.saturating_mul(1 << exponent).min(MAX_BACKOFF)
+}
+
+pub struct DialQueue<A> {
+    /// Consecutive failed dials of the addresses
+    failures: HashMap<A, u32>,
+
+    /// Addresses waiting to be dialed again and the time they are dialed
+    queue: HashMap<A, Instant>,
+}
+
+impl<A> DialQueue<A>
+where
+    A: Hash + Eq + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            failures: HashMap::new(),
+            queue: HashMap::new(),
+        }
+    }
+
+    /// Count a failed dial, returns the number of consecutive failures of the address
+    pub fn failed(&mut self, addr: A) -> u32 {
+        let failures = self.failures.entry(addr).or_default();
+        *failures += 1;
+        *failures
+    }
+
+    /// Forget the failures of an address that was dialed successfully
+    pub fn succeeded(&mut self, addr: &A) {
+        self.forget(addr);
+    }
+
+    /// Forget an address that is no longer dialed
+    pub fn forget(&mut self, addr: &A) {
+        self.failures.remove(addr);
+        self.queue.remove(addr);
+    }
+
+    /// Queue an address to be dialed again once the backoff of its last failure has elapsed
+    ///
+    /// Addresses that haven't failed are not queued. Returns `false` if the address has
+    /// failed too many times and was given up on.
+    pub fn retry(&mut self, addr: A, now: Instant) -> bool {
+        let failures = self.failures(&addr);
+        if failures == 0 {
+            return true;
+        }
+
+        if failures > MAX_RETRIES {
+            self.forget(&addr);
+            return false;
+        }
+
+        self.queue.insert(addr, now + backoff(failures));
+        true
+    }
+
+    /// Remove the queued addresses whose backoff has elapsed so that they can be dialed
+    pub fn ready(&mut self, now: Instant) -> Vec<A> {
+        let ready: Vec<A> = self
+            .queue
+            .iter()
+            .filter(|(_, retry_at)| **retry_at <= now)
+            .map(|(addr, _)| addr.clone())
+            .collect();
+
+        for addr in ready.iter() {
+            self.queue.remove(addr);
+        }
+        ready
+    }
+
+    /// Whether the address is waiting to be dialed again
+    pub fn is_queued(&self, addr: &A) -> bool {
+        self.queue.contains_key(addr)
+    }
+
+    /// Number of consecutive failed dials of the address
+    pub fn failures(&self, addr: &A) -> u32 {
+        self.failures.get(addr).copied().unwrap_or(0)
+    }
+}
+
+impl<A> Default for DialQueue<A>
+where
+    A: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        assert_eq!(backoff(1), BASE_BACKOFF);
+        assert_eq!(backoff(3), BASE_BACKOFF * 4);
+        assert_eq!(backoff(12), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn failed_dials_retried() {
+        let now = Instant::now();
+        let mut dials = DialQueue::new();
+
+        // nothing to retry before the address has failed
+        assert!(dials.retry("a", now));
+        assert!(!dials.is_queued(&"a"));
+
+        assert_eq!(dials.failed("a"), 1);
+        assert!(dials.retry("a", now));
+        assert!(dials.is_queued(&"a"));
+        assert!(dials.ready(now + BASE_BACKOFF - Duration::from_secs(1)).is_empty());
+        assert_eq!(dials.ready(now + BASE_BACKOFF), vec!["a"]);
+        assert!(!dials.is_queued(&"a"));
+
+        // the failures are remembered while the address is retried
+        assert_eq!(dials.failed("a"), 2);
+        assert!(dials.retry("a", now));
+        assert!(dials.ready(now + BASE_BACKOFF).is_empty());
+        assert_eq!(dials.ready(now + BASE_BACKOFF * 2), vec!["a"]);
+
+        dials.succeeded(&"a");
+        assert_eq!(dials.failures(&"a"), 0);
+    }
+
+    #[test]
+    fn given_up_after_max_retries() {
+        let now = Instant::now();
+        let mut dials = DialQueue::new();
+
+        for _ in 0..MAX_RETRIES {
+            dials.failed("a");
+            assert!(dials.retry("a", now));
+        }
+        dials.failed("a");
+        assert!(!dials.retry("a", now));
+        assert!(!dials.is_queued(&"a"));
+        assert_eq!(dials.failures(&"a"), 0);
+    }
+}
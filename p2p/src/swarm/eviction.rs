@@ -0,0 +1,192 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Selection of the inbound peer that is disconnected to make room for a new peer
+//!
+//! The selection follows the approach of Bitcoin Core: peers with properties that are
+//! hard for an attacker to fake are protected from eviction and the peer to evict is
+//! chosen from the netgroup with the most remaining connections. This way an attacker
+//! can't take over all inbound slots simply by opening many connections.
+
+use super::netgroup::NetGroup;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+/// Number of peers from distinct netgroups that are protected
+const PROTECTED_BY_NETGROUP: usize = 4;
+
+/// Number of peers with the lowest latency that are protected
+const PROTECTED_BY_PING: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct EvictionCandidate<P> {
+    /// Unique ID of the peer
+    pub peer_id: P,
+
+    /// Time when the connection was established
+    pub connected_since: Instant,
+
+    /// Round-trip time of the connection, if it has been measured
+    pub ping: Option<Duration>,
+
+    /// Netgroup of the peer address
+    pub netgroup: NetGroup,
+}
+
+/// Protect `count` candidates that come first in the order given by `key`
+fn protect<P, K: Ord>(
+    candidates: &mut Vec<EvictionCandidate<P>>,
+    count: usize,
+    key: impl FnMut(&EvictionCandidate<P>) -> K,
+) {
+    candidates.sort_by_key(key);
+    candidates.drain(..std::cmp::min(count, candidates.len()));
+}
+
+/// Select the inbound peer that should be evicted, if any
+///
+/// The following peers are protected from eviction:
+///  - peers from [`PROTECTED_BY_NETGROUP`] distinct netgroups, chosen using a keyed hash
+///    so that an attacker can't predict which netgroups are protected
+///  - [`PROTECTED_BY_PING`] peers with the lowest round-trip time
+///  - half of the remaining peers that have been connected the longest
+///
+/// The youngest peer of the netgroup with the most remaining peers is then selected.
+/// If all peers are protected, `None` is returned and the new peer should be rejected.
+///
+/// # Arguments
+/// `candidates` - inbound peers that can be evicted
+/// `netgroup_key` - random key that is used to select the protected netgroups
+pub fn select_for_eviction<P: Copy>(
+    mut candidates: Vec<EvictionCandidate<P>>,
+    netgroup_key: u64,
+) -> Option<P> {
+    // protect one peer from each of the selected netgroups
+    let mut keyed = candidates
+        .drain(..)
+        .map(|candidate| {
+            let mut hasher = DefaultHasher::new();
+            netgroup_key.hash(&mut hasher);
+            candidate.netgroup.hash(&mut hasher);
+            (hasher.finish(), candidate)
+        })
+        .collect::<Vec<_>>();
+    keyed.sort_by_key(|(key, candidate)| (*key, candidate.connected_since));
+
+    let mut protected_groups = 0;
+    let mut prev_key = None;
+    for (key, candidate) in keyed {
+        if protected_groups < PROTECTED_BY_NETGROUP && prev_key != Some(key) {
+            protected_groups += 1;
+            prev_key = Some(key);
+            continue;
+        }
+        candidates.push(candidate);
+    }
+
+    // only peers whose latency has been measured can be protected by it
+    let measured = candidates.iter().filter(|candidate| candidate.ping.is_some()).count();
+    protect(
+        &mut candidates,
+        std::cmp::min(PROTECTED_BY_PING, measured),
+        |candidate| (candidate.ping.is_none(), candidate.ping),
+    );
+    let half = candidates.len() / 2;
+    protect(&mut candidates, half, |candidate| candidate.connected_since);
+
+    let mut groups: BTreeMap<NetGroup, Vec<EvictionCandidate<P>>> = BTreeMap::new();
+    for candidate in candidates {
+        groups.entry(candidate.netgroup).or_default().push(candidate);
+    }
+
+    // prefer the netgroup with the most peers and, if there are several, the one
+    // with the most recent connection
+    groups
+        .into_values()
+        .map(|group| {
+            let size = group.len();
+            let youngest = group
+                .into_iter()
+                .max_by_key(|candidate| candidate.connected_since)
+                .expect("group to contain peers");
+            (size, youngest)
+        })
+        .max_by_key(|(size, youngest)| (*size, youngest.connected_since))
+        .map(|(_, candidate)| candidate.peer_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(
+        peer_id: u32,
+        connected_since: Instant,
+        ping: Option<u64>,
+        netgroup: [u8; 2],
+    ) -> EvictionCandidate<u32> {
+        EvictionCandidate {
+            peer_id,
+            connected_since,
+            ping: ping.map(Duration::from_millis),
+            netgroup: NetGroup::Ipv4(netgroup),
+        }
+    }
+
+    #[test]
+    fn too_few_candidates() {
+        let now = Instant::now();
+
+        assert_eq!(select_for_eviction::<u32>(vec![], 0), None);
+
+        // every peer is in a different netgroup so they're all protected
+        let candidates = (0..PROTECTED_BY_NETGROUP as u32)
+            .map(|i| candidate(i, now, None, [i as u8, 0]))
+            .collect();
+        assert_eq!(select_for_eviction(candidates, 0), None);
+    }
+
+    #[test]
+    fn evict_youngest_from_largest_netgroup() {
+        let now = Instant::now();
+        let at = |secs| now + Duration::from_secs(secs);
+
+        // the oldest peer of both netgroups and the oldest half of the rest are protected,
+        // leaving peers 9..14 from the first netgroup and 21..24 from the second
+        let mut candidates =
+            (0..14).map(|i| candidate(i, at(i as u64), None, [1, 1])).collect::<Vec<_>>();
+        candidates.extend((20..24).map(|i| candidate(i, at(10 + i as u64), None, [2, 2])));
+
+        for key in 0..16 {
+            assert_eq!(select_for_eviction(candidates.clone(), key), Some(13));
+        }
+    }
+
+    #[test]
+    fn low_latency_peers_protected() {
+        let now = Instant::now();
+
+        // all peers are in the same netgroup and connected at the same time
+        let candidates = (0..PROTECTED_BY_PING as u32 + 3)
+            .map(|i| candidate(i, now, Some(if i < 3 { 1000 } else { 1 }), [1, 1]))
+            .collect::<Vec<_>>();
+
+        let evicted = select_for_eviction(candidates, 0).unwrap();
+        assert!(evicted < 3);
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Addresses the local node is reachable at from other nodes
+//!
+//! Addresses come either from the configuration or from the peers that report which
+//! address they observed the local node at. An observed address is advertised only after
+//! peers from several netgroups have reported it, so a single peer can't make the local
+//! node advertise an address of its choosing.
+
+use super::netgroup::NetGroup;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+/// Number of netgroups that must report an address before it is advertised
+pub const MIN_CONFIRMATIONS: usize = 2;
+
+/// Maximum number of observed addresses that are tracked
+const MAX_OBSERVED: usize = 16;
+
+pub struct ExternalAddresses<A> {
+    /// Addresses given in the configuration, always advertised
+    configured: Vec<A>,
+
+    /// Observed addresses and the netgroups of the peers that reported them
+    observed: HashMap<A, HashSet<NetGroup>>,
+}
+
+impl<A> ExternalAddresses<A>
+where
+    A: Hash + Eq + Clone,
+{
+    pub fn new(configured: Vec<A>) -> Self {
+        Self {
+            configured,
+            observed: HashMap::new(),
+        }
+    }
+
+    /// Record that a peer in netgroup `reporter` observed the local node at `addr`
+    ///
+    /// Addresses in a local netgroup are ignored as they are not reachable from the outside,
+    /// e.g., they are internal addresses of a NAT. If the maximum number of addresses is
+    /// already tracked, an address reported by a single netgroup is replaced.
+    ///
+    /// Returns `true` if the address became confirmed.
+    pub fn observed(&mut self, addr: A, group: NetGroup, reporter: NetGroup) -> bool {
+        if matches!(group, NetGroup::Local | NetGroup::Unknown) {
+            return false;
+        }
+
+        if !self.observed.contains_key(&addr) && self.observed.len() >= MAX_OBSERVED {
+            let weak = self
+                .observed
+                .iter()
+                .find(|(_, reporters)| reporters.len() < MIN_CONFIRMATIONS)
+                .map(|(addr, _)| addr.clone());
+            match weak {
+                Some(weak) => self.observed.remove(&weak),
+                None => return false,
+            };
+        }
+
+        let reporters = self.observed.entry(addr).or_default();
+        reporters.insert(reporter) && reporters.len() == MIN_CONFIRMATIONS
+    }
+
+    /// Number of netgroups that have reported `addr`
+    pub fn confidence(&self, addr: &A) -> usize {
+        self.observed.get(addr).map_or(0, HashSet::len)
+    }
+
+    /// Addresses that are advertised to other peers
+    pub fn addresses(&self) -> Vec<&A> {
+        let confirmed = self
+            .observed
+            .iter()
+            .filter(|(addr, reporters)| {
+                reporters.len() >= MIN_CONFIRMATIONS && !self.configured.contains(addr)
+            })
+            .map(|(addr, _)| addr);
+
+        self.configured.iter().chain(confirmed).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(ip: &str) -> NetGroup {
+        NetGroup::from_ip(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn confirmed_by_netgroups() {
+        let mut external = ExternalAddresses::new(vec!["configured"]);
+        assert_eq!(external.addresses(), vec![&"configured"]);
+
+        // reports from the same netgroup count once
+        assert!(!external.observed("addr", group("1.1.1.1"), group("2.2.1.1")));
+        assert!(!external.observed("addr", group("1.1.1.1"), group("2.2.2.2")));
+        assert_eq!(external.confidence(&"addr"), 1);
+        assert_eq!(external.addresses(), vec![&"configured"]);
+
+        assert!(external.observed("addr", group("1.1.1.1"), group("3.3.3.3")));
+        assert!(!external.observed("addr", group("1.1.1.1"), group("4.4.4.4")));
+        assert_eq!(external.confidence(&"addr"), 3);
+        assert_eq!(external.addresses(), vec![&"configured", &"addr"]);
+    }
+
+    #[test]
+    fn local_addresses_ignored() {
+        let mut external = ExternalAddresses::new(vec![]);
+
+        for reporter in ["1.1.1.1", "2.2.2.2", "3.3.3.3"] {
+            assert!(!external.observed("nat", group("192.168.0.2"), group(reporter)));
+            assert!(!external.observed("unknown", NetGroup::Unknown, group(reporter)));
+        }
+        assert_eq!(external.confidence(&"nat"), 0);
+        assert!(external.addresses().is_empty());
+    }
+
+    #[test]
+    fn tracked_addresses_capped() {
+        let mut external = ExternalAddresses::new(vec![]);
+
+        for i in 0..MAX_OBSERVED {
+            external.observed(i, group("1.1.1.1"), group("2.2.2.2"));
+            external.observed(i, group("1.1.1.1"), group("3.3.3.3"));
+        }
+
+        // all tracked addresses are confirmed so new addresses are ignored
+        assert!(!external.observed(MAX_OBSERVED, group("1.1.1.1"), group("2.2.2.2")));
+        assert_eq!(external.confidence(&MAX_OBSERVED), 0);
+
+        // an unconfirmed address is replaced by a new address
+        external.observed.remove(&0);
+        assert!(!external.observed(MAX_OBSERVED, group("1.1.1.1"), group("2.2.2.2")));
+        assert!(!external.observed(MAX_OBSERVED + 1, group("1.1.1.1"), group("2.2.2.2")));
+        assert_eq!(external.confidence(&MAX_OBSERVED), 0);
+        assert_eq!(external.confidence(&(MAX_OBSERVED + 1)), 1);
+        assert_eq!(external.observed.len(), MAX_OBSERVED);
+        assert_eq!(external.addresses().len(), MAX_OBSERVED - 1);
+    }
+}
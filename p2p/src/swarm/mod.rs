@@ -15,22 +15,95 @@
 //
 // Author(s): A. Altonen
 use crate::{
+    config::{IpPreference, P2pConfig},
     error::{self, FatalError, P2pError, ProtocolError},
     event,
+    message::MAX_ADDR_PER_MESSAGE,
+    metrics::{self, Direction},
     net::{self, ConnectivityService, NetworkingService},
+    ratelimit,
+};
+use common::{
+    chain::{
+        block::Block,
+        services::Services,
+        whitelist::{self, PeerPermission},
+        ChainConfig,
+    },
+    primitives::Id,
 };
-use common::chain::ChainConfig;
 use futures::FutureExt;
 use logging::log;
+use rand::seq::IteratorRandom;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     fmt::Debug,
+    hash::Hash,
+    net::IpAddr,
     str::FromStr,
     sync::Arc,
+    time::{Duration, Instant},
 };
 use tokio::sync::mpsc;
 
-const MAX_ACTIVE_CONNECTIONS: usize = 32;
+pub mod banlist;
+pub mod bootstrap;
+pub mod dial;
+pub mod eviction;
+pub mod external;
+pub mod netgroup;
+pub mod selection;
+pub mod stale;
+
+/// Maximum number of connections established by the local node
+const MAX_OUTBOUND_CONNECTIONS: usize = 8;
+
+/// Maximum number of discovered peers, random peers are forgotten to make room for more
+const MAX_DISCOVERED_PEERS: usize = 4096;
+
+/// Maximum number of addresses of each IP version kept for a discovered peer
+const MAX_PEER_ADDRESSES: usize = 16;
+
+/// Maximum number of connections established by remote nodes
+const MAX_INBOUND_CONNECTIONS: usize = 24;
+
+/// Interval at which outbound connections are maintained
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval at which some of the outbound peers are replaced with new peers
+const OUTBOUND_ROTATION_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Number of outbound peers that are replaced at each rotation
+const OUTBOUND_ROTATION_COUNT: usize = 2;
+
+/// Interval at which a short-lived connection is made to validate a discovered address
+const FEELER_INTERVAL: Duration = Duration::from_secs(2 * 60);
+
+/// Number of unsolicited addresses per second a peer is allowed to send on average
+const ADDR_RATE_PER_SEC: f64 = 0.1;
+
+/// Number of consecutive unanswered pings after which a peer is disconnected
+const MAX_MISSED_PINGS: usize = 3;
+
+/// Time after which a peer that is being disconnected is forgotten even if the network
+/// service hasn't reported the connection closed
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Which side of the connection opened it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PeerRole {
+    Inbound,
+    Outbound,
+}
+
+impl From<PeerRole> for Direction {
+    fn from(role: PeerRole) -> Self {
+        match role {
+            PeerRole::Inbound => Direction::Inbound,
+            PeerRole::Outbound => Direction::Outbound,
+        }
+    }
+}
 
 // TODO: store active address
 // TODO: store other discovered addresses
@@ -39,7 +112,94 @@ struct PeerContext<T>
 where
     T: NetworkingService,
 {
-    _info: net::PeerInfo<T>,
+    info: net::PeerInfo<T>,
+
+    /// Which side of the connection opened it
+    role: PeerRole,
+
+    /// Time when the connection was established
+    connected_since: Instant,
+
+    /// Round-trip time of the connection, if it has been measured
+    ping: Option<Duration>,
+
+    /// Number of consecutive pings the peer has failed to respond to
+    missed_pings: usize,
+
+    /// Netgroup of the peer address
+    netgroup: netgroup::NetGroup,
+
+    /// Address of the peer, for inbound peers the address the connection came from
+    addr: Option<T::Address>,
+
+    /// Number of unsolicited addresses the peer is allowed to send
+    addr_tokens: ratelimit::TokenBucket,
+
+    /// Permissions granted to the peer by the whitelist
+    permissions: BTreeSet<PeerPermission>,
+
+    /// Last valid block the peer announced
+    last_block: Option<Id<Block>>,
+
+    /// Sum of the misbehavior reported for the peer
+    misbehavior_score: u32,
+}
+
+impl<T> PeerContext<T>
+where
+    T: NetworkingService,
+{
+    fn new(info: net::PeerInfo<T>, role: PeerRole, netgroup: netgroup::NetGroup) -> Self {
+        let now = Instant::now();
+
+        Self {
+            info,
+            role,
+            connected_since: now,
+            ping: None,
+            missed_pings: 0,
+            netgroup,
+            addr: None,
+            addr_tokens: ratelimit::TokenBucket::new(
+                MAX_ADDR_PER_MESSAGE as f64,
+                ADDR_RATE_PER_SEC,
+                now,
+            )
+            .with_tokens(1.0),
+            permissions: BTreeSet::new(),
+            last_block: None,
+            misbehavior_score: 0,
+        }
+    }
+
+    fn with_addr(mut self, addr: T::Address) -> Self {
+        self.addr = Some(addr);
+        self
+    }
+
+    fn with_permissions(mut self, permissions: BTreeSet<PeerPermission>) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    fn has_permission(&self, permission: PeerPermission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    fn peer_info(&self) -> event::PeerInfo {
+        event::PeerInfo {
+            peer_id: self.info.peer_id.to_string(),
+            address: self.addr.as_ref().map(ToString::to_string),
+            inbound: self.role == PeerRole::Inbound,
+            version: self.info.version.into(),
+            agent: self.info.agent.clone(),
+            services: self.info.services.to_string(),
+            uptime_secs: self.connected_since.elapsed().as_secs(),
+            last_block: self.last_block.clone(),
+            ping_ms: self.ping.map(|rtt| rtt.as_millis() as u64),
+            misbehavior_score: self.misbehavior_score,
+        }
+    }
 }
 
 enum PeerAddrInfo<T>
@@ -55,6 +215,53 @@ where
     },
 }
 
+impl<T> PeerAddrInfo<T>
+where
+    T: NetworkingService,
+{
+    fn is_empty(&self) -> bool {
+        match self {
+            PeerAddrInfo::Raw { ip4, ip6 } => ip4.is_empty() && ip6.is_empty(),
+        }
+    }
+
+    /// Remove an address of the peer
+    fn remove(&mut self, addr: &T::Address) {
+        match self {
+            PeerAddrInfo::Raw { ip4, ip6 } => {
+                ip4.remove(addr);
+                ip6.remove(addr);
+            }
+        }
+    }
+
+    /// Add the addresses of `other` to the addresses of the peer
+    fn merge(&mut self, other: Self) {
+        match (self, other) {
+            (
+                PeerAddrInfo::Raw { ip4, ip6 },
+                PeerAddrInfo::Raw {
+                    ip4: other_ip4,
+                    ip6: other_ip6,
+                },
+            ) => {
+                ip4.extend(other_ip4);
+                ip6.extend(other_ip6);
+            }
+        }
+    }
+}
+
+/// Add addresses of a discovered peer, keeping at most [`MAX_PEER_ADDRESSES`] of them
+fn add_addresses<A: Clone + Eq + Hash>(addrs: &mut HashSet<A>, new: &[A]) {
+    for addr in new {
+        if addrs.len() >= MAX_PEER_ADDRESSES {
+            break;
+        }
+        addrs.insert(addr.clone());
+    }
+}
+
 pub struct PeerManager<T>
 where
     T: NetworkingService,
@@ -62,20 +269,89 @@ where
     /// Chain config
     config: Arc<ChainConfig>,
 
+    /// P2P config
+    p2p_config: Arc<P2pConfig>,
+
     /// Handle for sending/receiving connectivity events
     handle: T::ConnectivityHandle,
 
     /// Hashmap for peer information
     peers: HashMap<T::PeerId, PeerContext<T>>,
 
-    /// Hashmap of discovered peers we don't have an active connection with
+    /// Hashmap of discovered peers we don't have an active connection with,
+    /// at most [`MAX_DISCOVERED_PEERS`] of them
     discovered: HashMap<T::PeerId, PeerAddrInfo<T>>,
 
+    /// Discovered peers that failed or whose addresses expired, waiting to be retried
+    stale: stale::StalePeers<T::PeerId, PeerAddrInfo<T>>,
+
+    /// Failed dials per address and the addresses waiting to be dialed again
+    dials: dial::DialQueue<T::Address>,
+
+    /// Peers whose connection is being closed and the time the disconnect was started
+    disconnecting: HashMap<T::PeerId, Instant>,
+
+    /// Addresses the local node is reachable at
+    external: external::ExternalAddresses<T::Address>,
+
+    /// IP addresses banned by the node operator
+    banned: banlist::BanList,
+
+    /// Services of the peers the local node has connected to, kept while they are connected
+    /// or discovered
+    services: HashMap<T::PeerId, Services>,
+
     /// RX channel for receiving control events
     rx_swarm: mpsc::Receiver<event::SwarmEvent<T>>,
 
     /// TX channel for sending events to SyncManager
     tx_sync: mpsc::Sender<event::SyncControlEvent<T>>,
+
+    /// Time when some of the outbound peers are replaced next
+    next_rotation: Instant,
+
+    /// Time when the next feeler connection is made
+    next_feeler: Instant,
+
+    /// Random key used to select the netgroups that are protected from eviction
+    netgroup_key: u64,
+
+    /// Whether chainstate is still in initial block download, reported by SyncManager
+    initial_block_download: bool,
+}
+
+/// Verify that the peer runs a supported version and speaks all required protocols
+///
+/// The magic bytes of the peer are checked separately.
+pub fn validate_peer_info<T: NetworkingService>(
+    config: &ChainConfig,
+    peer_info: &net::PeerInfo<T>,
+) -> error::Result<()> {
+    if peer_info.version < *config.min_peer_version() {
+        log::error!(
+            "peer {:?} runs unsupported version {:?}, minimum {:?}",
+            peer_info.peer_id,
+            peer_info.version,
+            config.min_peer_version(),
+        );
+        return Err(P2pError::ProtocolError(ProtocolError::InvalidVersion));
+    }
+
+    if let Some(missing) = config.required_peer_protocols().iter().find(|required| {
+        !peer_info
+            .protocols
+            .iter()
+            .any(|protocol| protocol.as_ref() == required.as_str())
+    }) {
+        log::error!(
+            "peer {:?} doesn't support required protocol {}",
+            peer_info.peer_id,
+            missing,
+        );
+        return Err(P2pError::ProtocolError(ProtocolError::InvalidProtocol));
+    }
+
+    Ok(())
 }
 
 impl<T> PeerManager<T>
@@ -87,18 +363,374 @@ where
 {
     pub fn new(
         config: Arc<ChainConfig>,
+        p2p_config: Arc<P2pConfig>,
         handle: T::ConnectivityHandle,
         rx_swarm: mpsc::Receiver<event::SwarmEvent<T>>,
         tx_sync: mpsc::Sender<event::SyncControlEvent<T>>,
     ) -> Self {
+        let configured = p2p_config
+            .external_addresses
+            .iter()
+            .filter_map(|addr| match addr.parse::<T::Address>() {
+                Ok(addr) => Some(addr),
+                Err(err) => {
+                    log::error!("invalid external address {}: {:?}", addr, err);
+                    None
+                }
+            })
+            .collect();
+
         Self {
+            external: external::ExternalAddresses::new(configured),
             config,
+            p2p_config,
             handle,
             rx_swarm,
             tx_sync,
-            peers: HashMap::with_capacity(MAX_ACTIVE_CONNECTIONS),
+            peers: HashMap::with_capacity(MAX_OUTBOUND_CONNECTIONS + MAX_INBOUND_CONNECTIONS),
             discovered: HashMap::new(),
+            stale: stale::StalePeers::new(),
+            dials: dial::DialQueue::new(),
+            disconnecting: HashMap::new(),
+            banned: banlist::BanList::new(),
+            services: HashMap::new(),
+            next_rotation: Instant::now() + OUTBOUND_ROTATION_INTERVAL,
+            next_feeler: Instant::now() + FEELER_INTERVAL,
+            netgroup_key: rand::random(),
+            initial_block_download: true,
+        }
+    }
+
+    /// Get the netgroup of a peer address
+    fn netgroup(addr: &T::Address) -> netgroup::NetGroup {
+        T::to_socket_addr(addr).map_or(netgroup::NetGroup::Unknown, |addr| {
+            netgroup::NetGroup::from_ip(&addr.ip())
+        })
+    }
+
+    /// Get the permissions the whitelist grants to a peer address
+    fn permissions(&self, addr: &T::Address) -> BTreeSet<PeerPermission> {
+        T::to_socket_addr(addr).map_or_else(BTreeSet::new, |addr| {
+            whitelist::permissions_of(&self.p2p_config.whitelist, &addr.ip())
+        })
+    }
+
+    /// Check whether the IP address of a peer address is banned
+    fn is_banned(&self, addr: &T::Address) -> bool {
+        T::to_socket_addr(addr).map_or(false, |addr| {
+            self.banned.is_banned(&addr.ip(), Instant::now())
+        })
+    }
+
+    /// Ban an IP address and disconnect the peers connected from it
+    async fn ban(&mut self, ip: IpAddr, duration: Duration) -> error::Result<()> {
+        log::info!("ban address {} for {:?}", ip, duration);
+        self.banned.ban(ip, Instant::now() + duration);
+
+        let banned_peers = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| {
+                peer.addr
+                    .as_ref()
+                    .and_then(T::to_socket_addr)
+                    .map_or(false, |addr| addr.ip() == ip)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+
+        for peer_id in banned_peers {
+            logging::debug!(peer_id = peer_id; "disconnect banned peer");
+            self.disconnect_peer(peer_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Select the inbound peer that is disconnected to make room for a new inbound peer
+    ///
+    /// Whitelisted peers with the `nolimit` permission are never evicted.
+    fn select_eviction_candidate(&self) -> Option<T::PeerId> {
+        let candidates = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.role == PeerRole::Inbound)
+            .filter(|(_, peer)| !peer.has_permission(PeerPermission::NoLimit))
+            .map(|(peer_id, peer)| eviction::EvictionCandidate {
+                peer_id: *peer_id,
+                connected_since: peer.connected_since,
+                ping: peer.ping,
+                netgroup: peer.netgroup,
+            })
+            .collect();
+
+        eviction::select_for_eviction(candidates, self.netgroup_key)
+    }
+
+    /// Close the connection to a peer
+    async fn disconnect_peer(&mut self, peer_id: T::PeerId) -> error::Result<()> {
+        if let Err(err) = self.handle.disconnect(peer_id).await {
+            logging::error!(peer_id = peer_id; "failed to disconnect peer: {:?}", err);
+        }
+
+        self.peer_disconnecting(peer_id).await
+    }
+
+    /// Move the peer to the disconnecting state after its connection has been asked to close
+    ///
+    /// SyncManager is told to stop exchanging messages with the peer right away. The peer
+    /// is forgotten when the network service reports the connection closed or, if that
+    /// never happens, after [`DISCONNECT_TIMEOUT`].
+    async fn peer_disconnecting(&mut self, peer_id: T::PeerId) -> error::Result<()> {
+        let known = self.remove_peer(&peer_id);
+        if self.disconnecting.insert(peer_id, Instant::now()).is_some() || !known {
+            return Ok(());
+        }
+
+        self.tx_sync
+            .send(event::SyncControlEvent::Disconnecting(peer_id))
+            .await
+            .map_err(P2pError::from)
+    }
+
+    /// Remove an accepted peer, returns `false` if the peer was not known
+    fn remove_peer(&mut self, peer_id: &T::PeerId) -> bool {
+        let removed = self.peers.remove(peer_id);
+        self.forget_services(peer_id);
+        match removed {
+            Some(peer) => {
+                metrics::metrics().connection_closed(peer.role.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget the peers whose connection hasn't been reported closed in time
+    async fn expire_disconnecting(&mut self, now: Instant) -> error::Result<()> {
+        let expired = self
+            .disconnecting
+            .iter()
+            .filter(|(_, since)| now.saturating_duration_since(**since) >= DISCONNECT_TIMEOUT)
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+
+        for peer_id in expired {
+            logging::warn!(peer_id = peer_id; "connection to peer was not closed in time");
+
+            self.disconnecting.remove(&peer_id);
+            self.tx_sync
+                .send(event::SyncControlEvent::Disconnected(peer_id))
+                .await
+                .map_err(P2pError::from)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add to the misbehavior score of a peer
+    fn peer_misbehaved(&mut self, peer_id: T::PeerId, score: u32) {
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            peer.misbehavior_score = peer.misbehavior_score.saturating_add(score);
+            metrics::metrics().peer_misbehaved(score);
+        }
+    }
+
+    /// Number of active connections that have the given role
+    fn connection_count(&self, role: PeerRole) -> usize {
+        self.peers.values().filter(|peer| peer.role == role).count()
+    }
+
+    /// Forget the services of a peer that is neither connected nor discovered
+    fn forget_services(&mut self, peer_id: &T::PeerId) {
+        if !self.peers.contains_key(peer_id) && !self.discovered.contains_key(peer_id) {
+            self.services.remove(peer_id);
+        }
+    }
+
+    /// Check whether a peer may provide the services required from outbound peers
+    ///
+    /// The services of a peer are only known once the local node has connected to it,
+    /// peers that haven't been connected to yet are assumed to provide them.
+    fn may_provide_required_services(&self, peer_id: &T::PeerId) -> bool {
+        self.services.get(peer_id).map_or(true, |services| {
+            services.contains(self.p2p_config.required_services)
+        })
+    }
+
+    /// Check whether a peer may provide full blocks, which is what initial block download needs
+    fn may_provide_full_blocks(&self, peer_id: &T::PeerId) -> bool {
+        self.services
+            .get(peer_id)
+            .map_or(true, |services| services.contains(Services::FULL_BLOCKS))
+    }
+
+    /// Select the address that is used to connect to a discovered peer
+    ///
+    /// The addresses that have failed to be dialed the least number of times in a row are
+    /// preferred. If the peer has both IPv4 and IPv6 addresses, the configured IP preference
+    /// decides between them.
+    fn select_address(&self, info: &PeerAddrInfo<T>) -> Arc<T::Address> {
+        let (ip4, ip6) = match info {
+            PeerAddrInfo::Raw { ip4, ip6 } => (ip4, ip6),
+        };
+        assert!(!ip4.is_empty() || !ip6.is_empty());
+        let failures = |addr: &Arc<T::Address>| self.dials.failures(addr);
+
+        let (preferred, other) = match self.p2p_config.ip_preference {
+            IpPreference::PreferIpv4 => (ip4, ip6),
+            IpPreference::PreferIpv6 => (ip6, ip4),
+            IpPreference::Any => {
+                let least = ip4.iter().chain(ip6.iter()).map(failures).min();
+                return Arc::clone(
+                    ip4.iter()
+                        .chain(ip6.iter())
+                        .filter(|addr| Some(failures(addr)) == least)
+                        .choose(&mut rand::thread_rng())
+                        .expect("addresses to exist"),
+                );
+            }
+        };
+
+        Arc::clone(
+            preferred
+                .iter()
+                .map(|addr| (addr, false))
+                .chain(other.iter().map(|addr| (addr, true)))
+                .min_by_key(|(addr, is_other)| (failures(addr), *is_other))
+                .map(|(addr, _)| addr)
+                .expect("addresses to exist"),
+        )
+    }
+
+    /// Replace the address of a manually added peer with its address of the preferred
+    /// IP version, if the peer has been discovered with one
+    fn preferred_address(&self, addr: T::Address) -> T::Address {
+        let info = match T::peer_id_from_addr(&addr).and_then(|id| self.discovered.get(&id)) {
+            Some(info) => info,
+            None => return addr,
+        };
+
+        let prefer_ipv4 = match self.p2p_config.ip_preference {
+            IpPreference::PreferIpv4 => true,
+            IpPreference::PreferIpv6 => false,
+            IpPreference::Any => return addr,
+        };
+        let is_ipv4 = |addr: &T::Address| T::to_socket_addr(addr).map(|addr| addr.is_ipv4());
+
+        let preferred = self.select_address(info);
+        if is_ipv4(&addr) != Some(prefer_ipv4) && is_ipv4(&preferred) == Some(prefer_ipv4) {
+            (*preferred).clone()
+        } else {
+            addr
+        }
+    }
+
+    /// Dial an address, failed dials are counted per address
+    ///
+    /// Banned addresses are not dialed.
+    async fn dial(&mut self, addr: &T::Address) -> error::Result<net::PeerInfo<T>> {
+        if self.is_banned(addr) {
+            log::debug!("address {:?} is banned, don't dial it", addr);
+            return Err(P2pError::PeerBanned);
+        }
+
+        match self.handle.connect(addr.clone()).await {
+            Ok(info) => {
+                self.dials.succeeded(addr);
+                Ok(info)
+            }
+            Err(err) => {
+                let failures = self.dials.failed(addr.clone());
+                log::debug!(
+                    "failed to dial address {:?}, {} failures in a row",
+                    addr,
+                    failures
+                );
+                Err(err)
+            }
+        }
+    }
+
+    /// Queue an address that failed to be dialed to be dialed again after a backoff
+    ///
+    /// Used for the addresses that are not known as addresses of discovered peers, i.e.,
+    /// the addresses given by the user and the addresses of the seed nodes.
+    fn retry_dial(&mut self, addr: T::Address) {
+        if !self.dials.retry(addr.clone(), Instant::now()) {
+            log::info!("address {:?} failed too many times, give up", addr);
+        }
+    }
+
+    /// Dial the queued addresses whose backoff has elapsed
+    async fn retry_dials(&mut self, now: Instant) -> error::Result<()> {
+        for addr in self.dials.ready(now) {
+            if matches!(T::peer_id_from_addr(&addr), Some(id) if self.peers.contains_key(&id)) {
+                self.dials.succeeded(&addr);
+                continue;
+            }
+
+            log::debug!("dial address {:?} again", addr);
+            if let Err(err) = self.connect_outbound(addr.clone()).await {
+                log::debug!("failed to connect to {:?}: {:?}", addr, err);
+                self.retry_dial(addr);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Establish an outbound connection and inform SyncManager about the new peer
+    ///
+    /// The connection is closed if the peer doesn't provide the required services.
+    async fn connect_outbound(&mut self, addr: T::Address) -> error::Result<()> {
+        let netgroup = Self::netgroup(&addr);
+        let info = self.dial(&addr).await?;
+        let peer_id = info.peer_id;
+        self.services.insert(peer_id, info.services);
+
+        if !info.services.contains(self.p2p_config.required_services) {
+            logging::debug!(
+                peer_id = peer_id;
+                "peer doesn't provide the required services, ours {}, theirs {}",
+                self.p2p_config.required_services,
+                info.services
+            );
+            self.handle.disconnect(peer_id).await?;
+            return Err(P2pError::ProtocolError(ProtocolError::MissingServices));
+        }
+
+        self.address_observed(&info, netgroup);
+
+        if self
+            .peers
+            .insert(
+                peer_id,
+                PeerContext::new(info, PeerRole::Outbound, netgroup).with_addr(addr),
+            )
+            .is_some()
+        {
+            logging::error!(peer_id = peer_id; "peer already exists");
+            return Err(P2pError::PeerExists);
         }
+
+        self.outbound_peer_connected(peer_id).await
+    }
+
+    /// Inform SyncManager about a new outbound peer and ask the peer for addresses
+    ///
+    /// Only outbound peers are asked for addresses so that a remote node can't
+    /// fill the set of discovered peers simply by connecting to the local node.
+    async fn outbound_peer_connected(&mut self, peer_id: T::PeerId) -> error::Result<()> {
+        metrics::metrics().connection_opened(Direction::Outbound);
+        self.tx_sync
+            .send(event::SyncControlEvent::Connected(peer_id))
+            .await
+            .map_err(P2pError::from)?;
+        self.tx_sync
+            .send(event::SyncControlEvent::GetAddr(peer_id))
+            .await
+            .map_err(P2pError::from)
     }
 
     /// Handle swarm control event
@@ -108,15 +740,23 @@ where
     ) -> error::Result<()> {
         match event.ok_or(P2pError::ChannelClosed)? {
             event::SwarmEvent::Connect(addr, response) => {
+                let addr = self.preferred_address(addr);
                 log::debug!(
                     "try to establish outbound connection to peer at address {:?}",
                     addr
                 );
 
-                match self.handle.connect(addr.clone()).await {
-                    Ok(_info) => {
-                        let peer_id = _info.peer_id;
-                        match self.peers.insert(peer_id, PeerContext { _info }) {
+                match self.dial(&addr).await {
+                    Ok(info) => {
+                        let peer_id = info.peer_id;
+                        let netgroup = Self::netgroup(&addr);
+                        self.services.insert(peer_id, info.services);
+                        self.address_observed(&info, netgroup);
+                        match self.peers.insert(
+                            peer_id,
+                            PeerContext::new(info, PeerRole::Outbound, netgroup)
+                                .with_addr(addr.clone()),
+                        ) {
                             Some(_) => {
                                 log::error!("peer already exists");
                                 response
@@ -129,24 +769,23 @@ where
                                     "connection established successfully to peer {:?}",
                                     addr
                                 );
-                                self.tx_sync
-                                    .send(event::SyncControlEvent::Connected(peer_id))
-                                    .await
-                                    .map_err(P2pError::from)?;
+                                self.outbound_peer_connected(peer_id).await?;
                                 response.send(Ok(())).map_err(|_| P2pError::ChannelClosed)
                             }
                         }
                     }
                     Err(err) => {
                         log::error!("failed to establish outbound connection: {:?}", err);
+                        self.retry_dial(addr);
                         response.send(Err(err)).map_err(|_| P2pError::ChannelClosed)
                     }
                 }
             }
-            // TODO: pending disconnection events
-            event::SwarmEvent::Disconnect(peer_id, response) => response
-                .send(self.handle.disconnect(peer_id).await)
-                .map_err(|_| P2pError::ChannelClosed),
+            event::SwarmEvent::Disconnect(peer_id, response) => {
+                let res = self.handle.disconnect(peer_id).await;
+                self.peer_disconnecting(peer_id).await?;
+                response.send(res).map_err(|_| P2pError::ChannelClosed)
+            }
             event::SwarmEvent::GetPeerCount(response) => {
                 response.send(self.peers.len()).map_err(|_| P2pError::ChannelClosed)
             }
@@ -157,86 +796,472 @@ where
                 .send(self.handle.peer_id().to_string())
                 .map_err(|_| P2pError::ChannelClosed),
             event::SwarmEvent::GetConnectedPeers(response) => {
-                let peers = self.peers.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+                let peers = self
+                    .peers
+                    .iter()
+                    .map(|(id, peer)| event::ConnectedPeer {
+                        peer_id: id.to_string(),
+                        ping_ms: peer.ping.map(|rtt| rtt.as_millis() as u64),
+                    })
+                    .collect::<Vec<_>>();
                 response.send(peers).map_err(|_| P2pError::ChannelClosed)
             }
+            event::SwarmEvent::GetPeerInfo(response) => response
+                .send(self.peers.values().map(|peer| peer.peer_info()).collect())
+                .map_err(|_| P2pError::ChannelClosed),
+            event::SwarmEvent::InitialBlockDownloadDone => {
+                log::info!("initial block download done, stop preferring full block peers");
+                self.initial_block_download = false;
+                Ok(())
+            }
+            event::SwarmEvent::BlockAnnounced { peer_id, block_id } => {
+                if let Some(peer) = self.peers.get_mut(&peer_id) {
+                    peer.last_block = Some(block_id);
+                }
+                Ok(())
+            }
+            // the ancestors are requested by SyncManager over the syncing protocol
+            event::SwarmEvent::OrphanBlock { peer_id, block_id } => self
+                .tx_sync
+                .send(event::SyncControlEvent::OrphanBlock { peer_id, block_id })
+                .await
+                .map_err(P2pError::from),
+            // traffic is accounted by SyncManager which answers the query directly
+            event::SwarmEvent::GetPeerStats(response) => self
+                .tx_sync
+                .send(event::SyncControlEvent::GetPeerStats(response))
+                .await
+                .map_err(P2pError::from),
+            event::SwarmEvent::GetSyncState(response) => self
+                .tx_sync
+                .send(event::SyncControlEvent::GetSyncState(response))
+                .await
+                .map_err(P2pError::from),
+            event::SwarmEvent::Ban(ip, duration, response) => {
+                self.ban(ip, duration).await?;
+                response.send(()).map_err(|_| P2pError::ChannelClosed)
+            }
+            event::SwarmEvent::Unban(ip, response) => {
+                let unbanned = self.banned.unban(&ip);
+                if unbanned {
+                    log::info!("unban address {}", ip);
+                }
+                response.send(unbanned).map_err(|_| P2pError::ChannelClosed)
+            }
+            event::SwarmEvent::GetBanned(response) => {
+                let banned = self
+                    .banned
+                    .banned(Instant::now())
+                    .map(|(ip, remaining)| event::BannedAddress {
+                        address: ip.to_string(),
+                        ban_remaining_secs: remaining.as_secs(),
+                    })
+                    .collect::<Vec<_>>();
+                response.send(banned).map_err(|_| P2pError::ChannelClosed)
+            }
+            event::SwarmEvent::Misbehaved { peer_id, score } => {
+                self.peer_misbehaved(peer_id, score);
+                Ok(())
+            }
+            event::SwarmEvent::GetAddresses(peer_id, response) => response
+                .send(self.sample_addresses(&peer_id))
+                .map_err(|_| P2pError::ChannelClosed),
+            event::SwarmEvent::AddressesReceived {
+                peer_id,
+                addrs,
+                solicited,
+            } => self.addresses_received(peer_id, addrs, solicited).await,
         }
     }
 
-    /// Try to establish new outbound connections if the total number of
-    /// active connections the local node has is below threshold
-    ///
-    // TODO: ugly, refactor
-    // TODO: move this to its own file?
-    #[allow(dead_code)]
-    async fn auto_connect(&mut self) -> error::Result<()> {
-        // we have enough active connections
-        if self.peers.len() >= MAX_ACTIVE_CONNECTIONS {
-            return Ok(());
-        }
-        log::debug!("try to establish more outbound connections");
-
-        // we don't know of any peers
-        if self.discovered.is_empty() {
-            log::error!(
-                "# of connections below threshold ({} < {}) but no peers",
-                self.peers.len(),
-                MAX_ACTIVE_CONNECTIONS,
-            );
-            return Err(P2pError::NoPeers);
+    /// Record the address a peer observed the local node at
+    fn address_observed(&mut self, info: &net::PeerInfo<T>, reporter: netgroup::NetGroup) {
+        if let Some(addr) = &info.observed_addr {
+            if self.external.observed(addr.clone(), Self::netgroup(addr), reporter) {
+                log::info!(
+                    "external address {:?} confirmed, start advertising it",
+                    addr
+                );
+            }
         }
+    }
 
-        let npeers = std::cmp::min(
-            self.discovered.len(),
-            MAX_ACTIVE_CONNECTIONS - self.peers.len(),
+    /// Select a random sample of known addresses that is advertised to `peer_id`
+    ///
+    /// The sample consists of the external addresses of the local node followed by
+    /// the addresses of outbound peers and discovered peers, the addresses of `peer_id`
+    /// itself are left out.
+    fn sample_addresses(&self, peer_id: &T::PeerId) -> Vec<String> {
+        let local = self.external.addresses();
+        let connected = self
+            .peers
+            .iter()
+            .filter(|(id, peer)| *id != peer_id && peer.role == PeerRole::Outbound)
+            .filter_map(|(_, peer)| peer.addr.as_ref());
+        let discovered = self.discovered.iter().filter(|(id, _)| *id != peer_id).flat_map(
+            |(_, info)| match info {
+                PeerAddrInfo::Raw { ip4, ip6 } => ip4.iter().chain(ip6.iter()).map(|addr| &**addr),
+            },
         );
 
-        // TODO: improve peer selection
-        let mut iter = self.discovered.iter();
+        let sample = connected.chain(discovered).choose_multiple(
+            &mut rand::thread_rng(),
+            MAX_ADDR_PER_MESSAGE.saturating_sub(local.len()),
+        );
 
-        #[allow(clippy::needless_collect)]
-        let peers: Vec<(T::PeerId, Arc<T::Address>)> = (0..npeers)
-            .map(|i| {
-                let peer_info = iter.nth(i).expect("Peer to exist");
+        local
+            .into_iter()
+            .chain(sample)
+            .take(MAX_ADDR_PER_MESSAGE)
+            .map(|addr| addr.to_string())
+            .collect()
+    }
 
-                let (ip4, ip6) = match peer_info.1 {
-                    PeerAddrInfo::Raw { ip4, ip6 } => (ip4, ip6),
-                };
-                assert!(!ip4.is_empty() || !ip6.is_empty());
+    /// Add the addresses received from a peer to the set of discovered peers
+    ///
+    /// Unsolicited addresses are rate limited so that a peer can't flood the local node
+    /// with addresses, addresses exceeding the limit are ignored. Peers that send more
+    /// than [`MAX_ADDR_PER_MESSAGE`] addresses at once are disconnected unless they have
+    /// the `noban` permission.
+    async fn addresses_received(
+        &mut self,
+        peer_id: T::PeerId,
+        addrs: Vec<String>,
+        solicited: bool,
+    ) -> error::Result<()> {
+        let noban = self
+            .peers
+            .get(&peer_id)
+            .map_or(false, |peer| peer.has_permission(PeerPermission::NoBan));
+
+        if addrs.len() > MAX_ADDR_PER_MESSAGE && !noban {
+            logging::error!(
+                peer_id = peer_id;
+                "peer sent {} addresses, maximum is {}",
+                addrs.len(),
+                MAX_ADDR_PER_MESSAGE
+            );
+            self.disconnect_peer(peer_id).await?;
+            return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
+        }
 
-                // TODO: let user specify their preference?
-                let addr = if ip6.is_empty() {
-                    Arc::clone(ip4.iter().next().expect("ip4 empty"))
-                } else {
-                    Arc::clone(ip6.iter().next().expect("ip6 empty"))
-                };
+        let allowed = match self.peers.get_mut(&peer_id) {
+            Some(_) if solicited => std::cmp::min(addrs.len(), MAX_ADDR_PER_MESSAGE),
+            Some(peer) => peer.addr_tokens.consume(addrs.len(), Instant::now()),
+            None => return Ok(()),
+        };
+        if allowed < addrs.len() {
+            logging::debug!(
+                peer_id = peer_id;
+                "peer exceeded address rate limit, ignore {} addresses",
+                addrs.len() - allowed
+            );
+        }
 
-                (*peer_info.0, addr)
+        let local_peer_id = *self.handle.peer_id();
+        let peers = addrs
+            .iter()
+            .take(allowed)
+            .filter_map(|addr| match addr.parse::<T::Address>() {
+                Ok(addr) => Some(addr),
+                Err(err) => {
+                    logging::debug!(
+                        peer_id = peer_id;
+                        "peer sent invalid address {}: {:?}",
+                        addr,
+                        err
+                    );
+                    None
+                }
             })
-            .collect::<_>();
-
-        for (id, addr) in peers {
-            log::trace!("try to connect to peer {:?}, address {:?}", id, addr);
-
-            // TODO: don't remove entry but modify it
-            self.discovered.remove(&id);
-            self.handle
-                .connect((*addr).clone())
-                .await
-                .map(|_info| {
-                    let id = _info.peer_id;
-                    match self.peers.insert(id, PeerContext { _info }) {
-                        Some(_) => panic!("peer already exists"),
-                        None => {}
+            .filter_map(|addr| {
+                let id = T::peer_id_from_addr(&addr).filter(|id| *id != local_peer_id)?;
+                let ipv4 = T::to_socket_addr(&addr)?.is_ipv4();
+                let addr = vec![Arc::new(addr)];
+
+                Some(if ipv4 {
+                    net::AddrInfo {
+                        id,
+                        ip4: addr,
+                        ip6: vec![],
+                    }
+                } else {
+                    net::AddrInfo {
+                        id,
+                        ip4: vec![],
+                        ip6: addr,
                     }
                 })
-                .map_err(|err| {
-                    log::error!("failed to establish outbound connection: {:?}", err);
-                    err
-                })?;
-        }
+            })
+            .collect::<Vec<_>>();
 
-        Ok(())
+        self.peer_discovered(&peers)
+    }
+
+    /// Try to establish new outbound connections if the number of outbound
+    /// connections the local node has is below threshold
+    ///
+    /// The peers are selected so that the outbound connections are spread over netgroups,
+    /// see [`selection::select_outbound`]. Peers known to lack the required services
+    /// are skipped. During initial block download, the peers that may provide full blocks
+    /// are preferred, if there are any.
+    async fn auto_connect(&mut self) -> error::Result<()> {
+        let outbound = self.connection_count(PeerRole::Outbound);
+
+        // we have enough active connections
+        if outbound >= MAX_OUTBOUND_CONNECTIONS {
+            return Ok(());
+        }
+        log::debug!("try to establish more outbound connections");
+
+        // we don't know of any peers, try the seed nodes
+        if self.discovered.is_empty() {
+            return self.bootstrap().await;
+        }
+
+        let mut candidates = self
+            .discovered
+            .iter()
+            .filter(|(peer_id, _)| self.may_provide_required_services(peer_id))
+            .collect::<Vec<_>>();
+        if self.initial_block_download
+            && candidates.iter().any(|(peer_id, _)| self.may_provide_full_blocks(peer_id))
+        {
+            candidates.retain(|(peer_id, _)| self.may_provide_full_blocks(peer_id));
+        }
+        let candidates = candidates
+            .into_iter()
+            .map(|(peer_id, info)| {
+                let addr = self.select_address(info);
+                let netgroup = Self::netgroup(&addr);
+                ((*peer_id, addr), netgroup)
+            })
+            .collect();
+        let connected = self
+            .peers
+            .values()
+            .filter(|peer| peer.role == PeerRole::Outbound)
+            .map(|peer| peer.netgroup);
+        let peers = selection::select_outbound(
+            candidates,
+            connected,
+            MAX_OUTBOUND_CONNECTIONS - outbound,
+            &mut rand::thread_rng(),
+        );
+
+        for (id, addr) in peers {
+            let info = self.discovered.remove(&id).expect("peer to exist");
+            log::trace!("try to connect to peer {:?}, address {:?}", id, addr);
+
+            match self.connect_outbound((*addr).clone()).await {
+                Ok(()) => self.stale.succeeded(&id),
+                // the peer is reachable, it's just not useful as an outbound peer
+                Err(P2pError::ProtocolError(ProtocolError::MissingServices)) => {
+                    self.stale.succeeded(&id);
+                    self.discovered.insert(id, info);
+                }
+                Err(err) => {
+                    log::error!("failed to establish outbound connection: {:?}", err);
+                    self.dial_failed(id, info, &addr);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Disconnect some of the outbound peers so that their slots are filled with new peers
+    ///
+    /// Peers are only rotated if all outbound slots are in use and there are
+    /// discovered peers to replace them with.
+    async fn rotate_outbound(&mut self) -> error::Result<()> {
+        if self.connection_count(PeerRole::Outbound) < MAX_OUTBOUND_CONNECTIONS
+            || self.discovered.is_empty()
+        {
+            return Ok(());
+        }
+
+        let peers = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.role == PeerRole::Outbound)
+            .map(|(peer_id, _)| *peer_id)
+            .choose_multiple(&mut rand::thread_rng(), OUTBOUND_ROTATION_COUNT);
+
+        for peer_id in peers {
+            logging::debug!(peer_id = peer_id; "rotate outbound peer");
+            self.disconnect_peer(peer_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Make a short-lived connection to a random discovered peer to check that its address
+    /// is still valid, peers that can't be connected to are moved to the stale bucket
+    async fn feeler_connect(&mut self) -> error::Result<()> {
+        let peer_id = match self
+            .discovered
+            .keys()
+            .filter(|peer_id| !self.peers.contains_key(peer_id))
+            .copied()
+            .choose(&mut rand::thread_rng())
+        {
+            Some(peer_id) => peer_id,
+            None => return Ok(()),
+        };
+        let addr = self.select_address(self.discovered.get(&peer_id).expect("peer to exist"));
+
+        match self.dial(&addr).await {
+            Ok(info) => {
+                logging::debug!(peer_id = peer_id; "feeler connection to peer succeeded");
+                self.stale.succeeded(&peer_id);
+                self.services.insert(info.peer_id, info.services);
+                self.handle.disconnect(info.peer_id).await
+            }
+            Err(err) => {
+                logging::debug!(
+                    peer_id = peer_id;
+                    "feeler connection to peer at address {:?} failed: {:?}",
+                    addr,
+                    err
+                );
+                if let Some(info) = self.discovered.remove(&peer_id) {
+                    self.dial_failed(peer_id, info, &addr);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Move a discovered peer to the stale bucket, it's retried once its backoff has elapsed
+    fn peer_failed(&mut self, peer_id: T::PeerId, info: PeerAddrInfo<T>) {
+        if !self.stale.failed(peer_id, info, Instant::now(), PeerAddrInfo::merge) {
+            logging::debug!(peer_id = peer_id; "peer failed too many times, forget it");
+        }
+        self.forget_services(&peer_id);
+    }
+
+    /// Move a discovered peer that couldn't be connected to at `addr` to the stale bucket
+    ///
+    /// An address that has failed to be dialed [`dial::MAX_ADDR_FAILURES`] times in a row
+    /// is removed from the addresses of the peer so that its other addresses are used.
+    /// A peer with no addresses left is forgotten.
+    fn dial_failed(&mut self, peer_id: T::PeerId, mut info: PeerAddrInfo<T>, addr: &T::Address) {
+        if self.dials.failures(addr) >= dial::MAX_ADDR_FAILURES {
+            logging::debug!(
+                peer_id = peer_id;
+                "address {:?} of peer failed too many times, remove it",
+                addr
+            );
+            info.remove(addr);
+            self.dials.forget(addr);
+        }
+
+        if info.is_empty() {
+            logging::debug!(peer_id = peer_id; "peer has no addresses left, forget it");
+            self.stale.succeeded(&peer_id);
+            self.forget_services(&peer_id);
+            return;
+        }
+        self.peer_failed(peer_id, info);
+    }
+
+    /// Return the stale peers whose backoff has elapsed to the set of discovered peers
+    fn retry_stale(&mut self, now: Instant) {
+        for (peer_id, info) in self.stale.ready(now) {
+            if self.peers.contains_key(&peer_id) {
+                continue;
+            }
+
+            match self.discovered.get_mut(&peer_id) {
+                Some(discovered) => discovered.merge(info),
+                None => {
+                    self.discovered.insert(peer_id, info);
+                }
+            }
+        }
+        self.limit_discovered();
+    }
+
+    /// Forget random discovered peers so that at most [`MAX_DISCOVERED_PEERS`] are left
+    fn limit_discovered(&mut self) {
+        let excess = self.discovered.len().saturating_sub(MAX_DISCOVERED_PEERS);
+        if excess == 0 {
+            return;
+        }
+
+        log::debug!("too many discovered peers, forget {} of them", excess);
+        let forgotten =
+            self.discovered.keys().copied().choose_multiple(&mut rand::thread_rng(), excess);
+        for peer_id in forgotten {
+            self.discovered.remove(&peer_id);
+            self.forget_services(&peer_id);
+        }
+    }
+
+    /// Maintain outbound connections
+    ///
+    /// Replaces some of the outbound peers periodically, makes occasional feeler connections,
+    /// forgets the expired bans, retries the stale peers and the failed dials whose backoff
+    /// has elapsed and tops up the outbound connections if the number of connections is
+    /// below threshold.
+    async fn heartbeat(&mut self) -> error::Result<()> {
+        let now = Instant::now();
+        self.expire_disconnecting(now).await?;
+        self.banned.expire(now);
+        self.retry_stale(now);
+        self.retry_dials(now).await?;
+
+        if now >= self.next_rotation {
+            self.next_rotation = now + OUTBOUND_ROTATION_INTERVAL;
+            self.rotate_outbound().await?;
+        }
+
+        if now >= self.next_feeler {
+            self.next_feeler = now + FEELER_INTERVAL;
+            self.feeler_connect().await?;
+        }
+
+        match self.auto_connect().await {
+            // reported by `bootstrap()`, retried on next heartbeat
+            Err(P2pError::NoPeers) => Ok(()),
+            res => res,
+        }
+    }
+
+    /// Establish outbound connections to the seed nodes of the chain
+    ///
+    /// Used when the local node doesn't know of any other peers, e.g., on first start.
+    async fn bootstrap(&mut self) -> error::Result<()> {
+        let seeds = bootstrap::seed_addresses::<T>(&self.config).await;
+
+        if seeds.is_empty() {
+            log::error!(
+                "# of outbound connections below threshold ({} < {}) but no peers or seed nodes",
+                self.connection_count(PeerRole::Outbound),
+                MAX_OUTBOUND_CONNECTIONS,
+            );
+            return Err(P2pError::NoPeers);
+        }
+
+        log::info!("bootstrap from {} seed nodes", seeds.len());
+
+        // seed nodes that failed are dialed again once their backoff has elapsed
+        let count =
+            MAX_OUTBOUND_CONNECTIONS.saturating_sub(self.connection_count(PeerRole::Outbound));
+        let seeds = seeds
+            .into_iter()
+            .filter(|addr| !self.dials.is_queued(addr))
+            .take(count)
+            .collect::<Vec<_>>();
+        for addr in seeds {
+            if let Err(err) = self.connect_outbound(addr.clone()).await {
+                log::warn!("failed to connect to seed node {:?}: {:?}", addr, err);
+                self.retry_dial(addr);
+            }
+        }
+
+        Ok(())
     }
 
     /// Update the list of peers we know about or update a known peers list of addresses
@@ -256,17 +1281,68 @@ where
                 PeerAddrInfo::Raw { ip4, ip6 } => {
                     log::trace!("discovered ipv4 {:#?}, ipv6 {:#?}", ip4, ip6);
 
-                    ip4.extend(info.ip4.clone());
-                    ip6.extend(info.ip6.clone());
+                    add_addresses(ip4, &info.ip4);
+                    add_addresses(ip6, &info.ip6);
                 }
             }
         }
+        self.limit_discovered();
+
+        Ok(())
+    }
+
+    /// Disconnect the peer if it has failed to respond to too many pings in a row
+    async fn ping_timeout(&mut self, peer_id: T::PeerId) -> error::Result<()> {
+        let missed_pings = match self.peers.get_mut(&peer_id) {
+            Some(peer) => {
+                peer.missed_pings += 1;
+                peer.missed_pings
+            }
+            None => return Ok(()),
+        };
+
+        if missed_pings >= MAX_MISSED_PINGS {
+            logging::warn!(
+                peer_id = peer_id;
+                "peer failed to respond to {} pings, close connection",
+                missed_pings
+            );
+            return self.disconnect_peer(peer_id).await;
+        }
 
         Ok(())
     }
 
-    // TODO: implement
-    fn peer_expired(&mut self, _peers: &[net::AddrInfo<T>]) -> error::Result<()> {
+    /// Remove expired addresses from the discovered peers
+    ///
+    /// The expired addresses are moved to the stale bucket so that they are retried
+    /// later in case the peer comes back. A peer with no addresses left is removed
+    /// from the set of discovered peers.
+    fn peer_expired(&mut self, peers: &[net::AddrInfo<T>]) -> error::Result<()> {
+        log::info!("{} peers expired", peers.len());
+
+        for info in peers.iter() {
+            let discovered = match self.discovered.get_mut(&info.id) {
+                Some(discovered) => discovered,
+                None => continue,
+            };
+
+            let expired = match discovered {
+                PeerAddrInfo::Raw { ip4, ip6 } => PeerAddrInfo::Raw {
+                    ip4: info.ip4.iter().filter(|addr| ip4.remove(*addr)).cloned().collect(),
+                    ip6: info.ip6.iter().filter(|addr| ip6.remove(*addr)).cloned().collect(),
+                },
+            };
+
+            if discovered.is_empty() {
+                self.discovered.remove(&info.id);
+                self.forget_services(&info.id);
+            }
+            if !expired.is_empty() {
+                self.peer_failed(info.id, expired);
+            }
+        }
+
         Ok(())
     }
 
@@ -275,38 +1351,78 @@ where
         match event {
             net::ConnectivityEvent::IncomingConnection { peer_info, addr } => {
                 let peer_id = peer_info.peer_id;
-                log::debug!(
-                    "incoming connection from peer {:?}, address {:?}",
-                    peer_id,
+                logging::debug!(
+                    peer_id = peer_id;
+                    "incoming connection from peer, address {:?}",
                     addr
                 );
 
-                if self.peers.get(&peer_id).is_some() {
-                    log::error!("peer {:?} re-established connection", peer_id);
-                    return self.handle.disconnect(peer_id).await;
-                }
-
-                if self.peers.len() == MAX_ACTIVE_CONNECTIONS {
-                    log::warn!("maximum number of connections reached, close new connection with peer {:?}", peer_id);
-                    // TODO: save peer information for later?
-                    // TODO: i.e., consider this a peer discovery event?
+                if self.peers.contains_key(&peer_id) || self.disconnecting.contains_key(&peer_id) {
+                    logging::error!(peer_id = peer_id; "peer re-established connection");
                     return self.handle.disconnect(peer_id).await;
                 }
 
                 if peer_info.magic_bytes != *self.config.magic_bytes() {
-                    log::error!(
-                        "peer {:?} is in different network, ours {:?}, theirs {:?}",
-                        peer_id,
+                    logging::error!(
+                        peer_id = peer_id;
+                        "peer is in different network, ours {:?}, theirs {:?}",
                         peer_info.magic_bytes,
                         self.config.chain_type()
                     );
                     return Err(P2pError::ProtocolError(ProtocolError::DifferentNetwork));
                 }
 
-                // TODO: check supported protocols
-                // TODO: check version
+                if let Err(err) = validate_peer_info(&self.config, &peer_info) {
+                    self.handle.disconnect(peer_id).await?;
+                    return Err(err);
+                }
+
+                let permissions = self.permissions(&addr);
+                if self.is_banned(&addr) && !permissions.contains(&PeerPermission::NoBan) {
+                    logging::info!(peer_id = peer_id; "close connection with banned peer");
+                    return self.handle.disconnect(peer_id).await;
+                }
+
+                self.address_observed(&peer_info, Self::netgroup(&addr));
+                if !permissions.is_empty() {
+                    logging::debug!(
+                        peer_id = peer_id;
+                        "peer is whitelisted with permissions {:?}",
+                        permissions
+                    );
+                }
+
+                if self.connection_count(PeerRole::Inbound) >= MAX_INBOUND_CONNECTIONS
+                    && !permissions.contains(&PeerPermission::NoLimit)
+                {
+                    match self.select_eviction_candidate() {
+                        Some(evicted) => {
+                            logging::info!(
+                                peer_id = peer_id;
+                                "maximum number of inbound connections reached, evict peer {:?} to make room for peer",
+                                evicted
+                            );
+                            self.disconnect_peer(evicted).await?;
+                        }
+                        None => {
+                            logging::warn!(
+                                peer_id = peer_id;
+                                "maximum number of inbound connections reached, close new connection with peer"
+                            );
+                            // TODO: save peer information for later?
+                            // TODO: i.e., consider this a peer discovery event?
+                            return self.handle.disconnect(peer_id).await;
+                        }
+                    }
+                }
 
-                self.peers.insert(peer_id, PeerContext { _info: peer_info });
+                self.peers.insert(
+                    peer_id,
+                    PeerContext::new(peer_info, PeerRole::Inbound, Self::netgroup(&addr))
+                        .with_permissions(permissions)
+                        .with_addr(addr),
+                );
+                metrics::metrics().connection_opened(Direction::Inbound);
                 self.tx_sync
                     .send(event::SyncControlEvent::Connected(peer_id))
                     .await
@@ -314,58 +1430,83 @@ where
             }
             net::ConnectivityEvent::ConnectionAccepted { peer_info } => {
                 let peer_id = peer_info.peer_id;
-                log::debug!("outbound connection accepted by peer {:?}", peer_id);
+                logging::debug!(peer_id = peer_id; "outbound connection accepted by peer");
 
-                if self.peers.get(&peer_id).is_some() {
-                    log::error!("peer {:?} re-established connection", peer_id);
+                if self.peers.contains_key(&peer_id) || self.disconnecting.contains_key(&peer_id) {
+                    logging::error!(peer_id = peer_id; "peer re-established connection");
                     return self.handle.disconnect(peer_id).await;
                 }
 
-                if self.peers.len() == MAX_ACTIVE_CONNECTIONS {
-                    log::warn!("maximum number of connections reached, close new connection with peer {:?}", peer_id);
+                if self.connection_count(PeerRole::Outbound) >= MAX_OUTBOUND_CONNECTIONS {
+                    logging::warn!(
+                        peer_id = peer_id;
+                        "maximum number of outbound connections reached, close new connection with peer"
+                    );
                     // TODO: save peer information for later?
                     // TODO: i.e., consider this a peer discovery event?
                     return self.handle.disconnect(peer_id).await;
                 }
 
                 if peer_info.magic_bytes != *self.config.magic_bytes() {
-                    log::error!(
-                        "peer {:?} is in different network, ours {:?}, theirs {:?}",
-                        peer_id,
+                    logging::error!(
+                        peer_id = peer_id;
+                        "peer is in different network, ours {:?}, theirs {:?}",
                         peer_info.magic_bytes,
                         self.config.chain_type()
                     );
                     return Err(P2pError::ProtocolError(ProtocolError::DifferentNetwork));
                 }
 
-                // TODO: check supported protocols
-                // TODO: check version
+                if let Err(err) = validate_peer_info(&self.config, &peer_info) {
+                    self.handle.disconnect(peer_id).await?;
+                    return Err(err);
+                }
 
-                self.peers.insert(peer_id, PeerContext { _info: peer_info });
-                self.tx_sync
-                    .send(event::SyncControlEvent::Connected(peer_id))
-                    .await
-                    .map_err(P2pError::from)
+                // the address of the peer is not known
+                self.services.insert(peer_id, peer_info.services);
+                self.address_observed(&peer_info, netgroup::NetGroup::Unknown);
+                self.peers.insert(
+                    peer_id,
+                    PeerContext::new(peer_info, PeerRole::Outbound, netgroup::NetGroup::Unknown),
+                );
+                self.outbound_peer_connected(peer_id).await
             }
             net::ConnectivityEvent::ConnectionClosed { peer_id } => {
-                log::debug!("connection closed for peer {:?}", peer_id);
-                self.tx_sync
-                    .send(event::SyncControlEvent::Disconnected(peer_id))
-                    .await
-                    .map_err(P2pError::from)?;
-                self.peers.remove(&peer_id);
+                logging::debug!(peer_id = peer_id; "connection closed for peer");
+
+                // feeler connections are not known to SyncManager
+                let disconnecting = self.disconnecting.remove(&peer_id).is_some();
+                if self.remove_peer(&peer_id) || disconnecting {
+                    self.tx_sync
+                        .send(event::SyncControlEvent::Disconnected(peer_id))
+                        .await
+                        .map_err(P2pError::from)?;
+                }
                 Ok(())
             }
             net::ConnectivityEvent::Discovered { peers } => self.peer_discovered(&peers),
             net::ConnectivityEvent::Expired { peers } => self.peer_expired(&peers),
+            net::ConnectivityEvent::PingResponse { peer_id, rtt } => {
+                if let Some(peer) = self.peers.get_mut(&peer_id) {
+                    peer.ping = Some(rtt);
+                    peer.missed_pings = 0;
+                }
+                Ok(())
+            }
+            net::ConnectivityEvent::PingTimeout { peer_id } => self.ping_timeout(peer_id).await,
             net::ConnectivityEvent::Disconnected { .. } => Ok(()),
-            net::ConnectivityEvent::Misbehaved { .. } => Ok(()),
+            net::ConnectivityEvent::Misbehaved { peer_id, behaviour } => {
+                self.peer_misbehaved(peer_id, behaviour);
+                Ok(())
+            }
             net::ConnectivityEvent::Error { .. } => Ok(()),
         }
     }
 
     /// PeerManager event loop
     pub async fn run(&mut self) -> error::Result<()> {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+
         loop {
             tokio::select! {
                 event = self.rx_swarm.recv().fuse() => {
@@ -377,6 +1518,9 @@ where
                         log::error!("failed to read network event: {:?}", e);
                         return Err(e);
                     }
+                },
+                _ = heartbeat.tick() => {
+                    self.heartbeat().await.map_fatal_err()?;
                 }
             }
         }
@@ -403,11 +1547,27 @@ mod tests {
         <T as NetworkingService>::Address: FromStr,
         <<T as NetworkingService>::Address as FromStr>::Err: Debug,
     {
+        make_swarm_manager_with_p2p_config(addr, config, P2pConfig::default()).await
+    }
+
+    async fn make_swarm_manager_with_p2p_config<T>(
+        addr: T::Address,
+        config: Arc<common::chain::ChainConfig>,
+        p2p_config: P2pConfig,
+    ) -> PeerManager<T>
+    where
+        T: NetworkingService + 'static,
+        T::ConnectivityHandle: ConnectivityService<T>,
+        <T as NetworkingService>::Address: FromStr,
+        <<T as NetworkingService>::Address as FromStr>::Err: Debug,
+    {
+        let p2p_config = Arc::new(p2p_config);
         let (conn, _, _) = T::start(
             addr,
             &[],
             &[],
             Arc::clone(&config),
+            Arc::clone(&p2p_config),
             std::time::Duration::from_secs(10),
         )
         .await
@@ -421,7 +1581,7 @@ mod tests {
             }
         });
 
-        PeerManager::<T>::new(Arc::clone(&config), conn, rx, tx_sync)
+        PeerManager::<T>::new(config, p2p_config, conn, rx, tx_sync)
     }
 
     // try to connect to an address that no one listening on and verify it fails
@@ -432,183 +1592,1191 @@ mod tests {
         let mut swarm = make_swarm_manager::<MockService>(addr, config).await;
         let (tx, rx) = oneshot::channel();
 
-        let addr: SocketAddr = "[::1]:1".parse().unwrap();
-        swarm
-            .on_swarm_control_event(Some(event::SwarmEvent::Connect(addr, tx)))
-            .await
-            .unwrap();
+        let addr: SocketAddr = "[::1]:1".parse().unwrap();
+        swarm
+            .on_swarm_control_event(Some(event::SwarmEvent::Connect(addr, tx)))
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.await.unwrap(),
+            Err(P2pError::SocketError(std::io::ErrorKind::ConnectionRefused))
+        );
+    }
+
+    // try to connect to an address that no one listening on and verify it fails
+    #[tokio::test]
+    async fn test_swarm_connect_libp2p() {
+        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+        let config = Arc::new(config::create_mainnet());
+        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config).await;
+        let (tx, rx) = oneshot::channel();
+
+        let addr: Multiaddr =
+            "/ip6/::1/tcp/6666/p2p/12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ"
+                .parse()
+                .unwrap();
+        swarm
+            .on_swarm_control_event(Some(event::SwarmEvent::Connect(addr, tx)))
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.await.unwrap(),
+            Err(P2pError::SocketError(std::io::ErrorKind::ConnectionRefused))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_peer_discovered_libp2p() {
+        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+        let config = Arc::new(config::create_mainnet());
+        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config).await;
+
+        let id_1: libp2p::PeerId =
+            "12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ".parse().unwrap();
+        let id_2: libp2p::PeerId =
+            "12D3KooWE3kBRAnn6jxZMdK1JMWx1iHtR1NKzXSRv5HLTmfD9u9c".parse().unwrap();
+        let id_3: libp2p::PeerId =
+            "12D3KooWGK4RzvNeioS9aXdzmYXU3mgDrRPjQd8SVyXCkHNxLbWN".parse().unwrap();
+
+        // check that peer with `id` has the correct ipv4 and ipv6 addresses
+        let check_peer =
+            |discovered: &HashMap<
+                <Libp2pService as NetworkingService>::PeerId,
+                PeerAddrInfo<Libp2pService>,
+            >,
+             id: libp2p::PeerId,
+             ip4: Vec<Arc<<Libp2pService as NetworkingService>::Address>>,
+             ip6: Vec<Arc<<Libp2pService as NetworkingService>::Address>>| {
+                let (p_ip4, p_ip6) = match discovered.get(&id).unwrap() {
+                    PeerAddrInfo::Raw { ip4, ip6 } => (ip4, ip6),
+                };
+
+                assert_eq!(ip4.len(), p_ip4.len());
+                assert_eq!(ip6.len(), p_ip6.len());
+
+                for ip in ip4.iter() {
+                    assert!(p_ip4.contains(ip));
+                }
+
+                for ip in ip6.iter() {
+                    assert!(p_ip6.contains(ip));
+                }
+            };
+
+        // first add two new peers, both with ipv4 and ipv6 address
+        swarm
+            .peer_discovered(&[
+                net::AddrInfo {
+                    id: id_1,
+                    ip4: vec![Arc::new("/ip4/127.0.0.1/tcp/9090".parse().unwrap())],
+                    ip6: vec![Arc::new("/ip6/::1/tcp/9091".parse().unwrap())],
+                },
+                net::AddrInfo {
+                    id: id_2,
+                    ip4: vec![Arc::new("/ip4/127.0.0.1/tcp/9092".parse().unwrap())],
+                    ip6: vec![Arc::new("/ip6/::1/tcp/9093".parse().unwrap())],
+                },
+            ])
+            .unwrap();
+
+        assert_eq!(swarm.peers.len(), 0);
+        assert_eq!(swarm.discovered.len(), 2);
+
+        check_peer(
+            &swarm.discovered,
+            id_1,
+            vec![Arc::new("/ip4/127.0.0.1/tcp/9090".parse().unwrap())],
+            vec![Arc::new("/ip6/::1/tcp/9091".parse().unwrap())],
+        );
+
+        check_peer(
+            &swarm.discovered,
+            id_2,
+            vec![Arc::new("/ip4/127.0.0.1/tcp/9092".parse().unwrap())],
+            vec![Arc::new("/ip6/::1/tcp/9093".parse().unwrap())],
+        );
+
+        // then discover one new peer and two additional ipv6 addresses for peer 1
+        swarm
+            .peer_discovered(&[
+                net::AddrInfo {
+                    id: id_1,
+                    ip4: vec![],
+                    ip6: vec![
+                        Arc::new("/ip6/::1/tcp/9094".parse().unwrap()),
+                        Arc::new("/ip6/::1/tcp/9095".parse().unwrap()),
+                    ],
+                },
+                net::AddrInfo {
+                    id: id_3,
+                    ip4: vec![Arc::new("/ip4/127.0.0.1/tcp/9096".parse().unwrap())],
+                    ip6: vec![Arc::new("/ip6/::1/tcp/9097".parse().unwrap())],
+                },
+            ])
+            .unwrap();
+
+        check_peer(
+            &swarm.discovered,
+            id_1,
+            vec![Arc::new("/ip4/127.0.0.1/tcp/9090".parse().unwrap())],
+            vec![
+                Arc::new("/ip6/::1/tcp/9091".parse().unwrap()),
+                Arc::new("/ip6/::1/tcp/9094".parse().unwrap()),
+                Arc::new("/ip6/::1/tcp/9095".parse().unwrap()),
+            ],
+        );
+
+        check_peer(
+            &swarm.discovered,
+            id_3,
+            vec![Arc::new("/ip4/127.0.0.1/tcp/9096".parse().unwrap())],
+            vec![Arc::new("/ip6/::1/tcp/9097".parse().unwrap())],
+        );
+    }
+
+    // verify that the number of discovered peers and the number of their addresses are limited
+    #[tokio::test]
+    async fn discovered_peers_limited() {
+        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+        let config = Arc::new(config::create_mainnet());
+        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config).await;
+
+        let peers = (0..MAX_DISCOVERED_PEERS + 10)
+            .map(|i| net::AddrInfo {
+                id: PeerId::random(),
+                ip4: vec![Arc::new(format!("/ip4/1.2.3.4/tcp/{}", 1000 + i).parse().unwrap())],
+                ip6: vec![],
+            })
+            .collect::<Vec<_>>();
+        for chunk in peers.chunks(MAX_ADDR_PER_MESSAGE) {
+            swarm.peer_discovered(chunk).unwrap();
+        }
+        assert_eq!(swarm.discovered.len(), MAX_DISCOVERED_PEERS);
+
+        let id = *swarm.discovered.keys().next().unwrap();
+        let addrs = (0..MAX_PEER_ADDRESSES * 2)
+            .map(|i| Arc::new(format!("/ip4/1.2.3.5/tcp/{}", 1000 + i).parse().unwrap()))
+            .collect::<Vec<_>>();
+        swarm
+            .peer_discovered(&[net::AddrInfo {
+                id,
+                ip4: addrs,
+                ip6: vec![],
+            }])
+            .unwrap();
+        match swarm.discovered.get(&id).unwrap() {
+            PeerAddrInfo::Raw { ip4, .. } => assert_eq!(ip4.len(), MAX_PEER_ADDRESSES),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peer_expired_libp2p() {
+        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+        let config = Arc::new(config::create_mainnet());
+        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config).await;
+
+        let id_1: libp2p::PeerId =
+            "12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ".parse().unwrap();
+        let id_2: libp2p::PeerId =
+            "12D3KooWE3kBRAnn6jxZMdK1JMWx1iHtR1NKzXSRv5HLTmfD9u9c".parse().unwrap();
+        let id_3: libp2p::PeerId =
+            "12D3KooWGK4RzvNeioS9aXdzmYXU3mgDrRPjQd8SVyXCkHNxLbWN".parse().unwrap();
+
+        let addrs = |addrs: &[&str]| -> Vec<Arc<Multiaddr>> {
+            addrs.iter().map(|addr| Arc::new(addr.parse().unwrap())).collect()
+        };
+
+        // check that peer with `id` has the correct ipv4 and ipv6 addresses
+        let check_peer = |swarm: &PeerManager<Libp2pService>,
+                          id: libp2p::PeerId,
+                          ip4: Vec<Arc<Multiaddr>>,
+                          ip6: Vec<Arc<Multiaddr>>| {
+            let (p_ip4, p_ip6) = match swarm.discovered.get(&id).unwrap() {
+                PeerAddrInfo::Raw { ip4, ip6 } => (ip4, ip6),
+            };
+
+            assert_eq!(p_ip4, &ip4.into_iter().collect::<HashSet<_>>());
+            assert_eq!(p_ip6, &ip6.into_iter().collect::<HashSet<_>>());
+        };
+
+        swarm
+            .peer_discovered(&[
+                net::AddrInfo {
+                    id: id_1,
+                    ip4: addrs(&["/ip4/127.0.0.1/tcp/9090"]),
+                    ip6: addrs(&["/ip6/::1/tcp/9091", "/ip6/::1/tcp/9092"]),
+                },
+                net::AddrInfo {
+                    id: id_2,
+                    ip4: addrs(&["/ip4/127.0.0.1/tcp/9093"]),
+                    ip6: addrs(&["/ip6/::1/tcp/9094"]),
+                },
+            ])
+            .unwrap();
+        assert_eq!(swarm.discovered.len(), 2);
+
+        // one address of peer 1 and all addresses of peer 2 expire,
+        // the expiry of an unknown peer is ignored
+        swarm
+            .peer_expired(&[
+                net::AddrInfo {
+                    id: id_1,
+                    ip4: vec![],
+                    ip6: addrs(&["/ip6/::1/tcp/9092"]),
+                },
+                net::AddrInfo {
+                    id: id_2,
+                    ip4: addrs(&["/ip4/127.0.0.1/tcp/9093"]),
+                    ip6: addrs(&["/ip6/::1/tcp/9094"]),
+                },
+                net::AddrInfo {
+                    id: id_3,
+                    ip4: addrs(&["/ip4/127.0.0.1/tcp/9095"]),
+                    ip6: vec![],
+                },
+            ])
+            .unwrap();
+
+        assert_eq!(swarm.discovered.len(), 1);
+        check_peer(
+            &swarm,
+            id_1,
+            addrs(&["/ip4/127.0.0.1/tcp/9090"]),
+            addrs(&["/ip6/::1/tcp/9091"]),
+        );
+        assert!(swarm.stale.contains(&id_1));
+        assert!(swarm.stale.contains(&id_2));
+        assert!(!swarm.stale.contains(&id_3));
+
+        // the expired addresses are not retried before the backoff has elapsed
+        swarm.retry_stale(Instant::now());
+        assert_eq!(swarm.discovered.len(), 1);
+
+        swarm.retry_stale(Instant::now() + stale::BASE_BACKOFF);
+        assert_eq!(swarm.discovered.len(), 2);
+        check_peer(
+            &swarm,
+            id_1,
+            addrs(&["/ip4/127.0.0.1/tcp/9090"]),
+            addrs(&["/ip6/::1/tcp/9091", "/ip6/::1/tcp/9092"]),
+        );
+        check_peer(
+            &swarm,
+            id_2,
+            addrs(&["/ip4/127.0.0.1/tcp/9093"]),
+            addrs(&["/ip6/::1/tcp/9094"]),
+        );
+
+        // a peer that keeps failing is forgotten, peer 2 has already failed once
+        for _ in 0..stale::MAX_FAILURES {
+            swarm
+                .peer_expired(&[net::AddrInfo {
+                    id: id_2,
+                    ip4: addrs(&["/ip4/127.0.0.1/tcp/9093"]),
+                    ip6: addrs(&["/ip6/::1/tcp/9094"]),
+                }])
+                .unwrap();
+            swarm.retry_stale(Instant::now() + stale::MAX_BACKOFF);
+        }
+        assert!(!swarm.discovered.contains_key(&id_2));
+        assert!(!swarm.stale.contains(&id_2));
+    }
+
+    // verify that if the node is aware of any peers on the network,
+    // call to `auto_connect()` will establish a connection with them
+    #[tokio::test]
+    async fn test_auto_connect_mock() {
+        let config = Arc::new(config::create_mainnet());
+        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config.clone()).await;
+        let mut swarm2 =
+            make_swarm_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), config)
+                .await;
+
+        let addr = swarm2.handle.local_addr().clone();
+        let id: PeerId = if let Some(Protocol::P2p(peer)) = addr.iter().last() {
+            PeerId::from_multihash(peer).unwrap()
+        } else {
+            panic!("invalid multiaddr");
+        };
+
+        tokio::spawn(async move {
+            log::debug!("staring libp2p service");
+            loop {
+                assert!(swarm2.handle.poll_next().await.is_ok());
+            }
+        });
+
+        // "discover" the other libp2p service
+        swarm
+            .peer_discovered(&[net::AddrInfo {
+                id,
+                ip4: vec![],
+                ip6: vec![Arc::new(addr)],
+            }])
+            .unwrap();
+        swarm.auto_connect().await.unwrap();
+        assert_eq!(swarm.peers.len(), 1);
+    }
+
+    // verify that a peer that doesn't provide the required services is disconnected
+    // and not selected again, but is still remembered as a discovered peer
+    #[tokio::test]
+    async fn auto_connect_required_services() {
+        let mut swarm = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let mut swarm2 = make_swarm_manager_with_p2p_config::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+            P2pConfig {
+                services: Services::HEADERS,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let addr = swarm2.handle.local_addr().clone();
+        let id = Libp2pService::peer_id_from_addr(&addr).unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let _ = swarm2.handle.poll_next().await;
+            }
+        });
+
+        swarm
+            .peer_discovered(&[net::AddrInfo {
+                id,
+                ip4: vec![],
+                ip6: vec![Arc::new(addr.clone())],
+            }])
+            .unwrap();
+        swarm.auto_connect().await.unwrap();
+        assert!(swarm.peers.is_empty());
+        assert_eq!(swarm.services.get(&id), Some(&Services::HEADERS));
+        assert!(swarm.discovered.contains_key(&id));
+        assert!(!swarm.may_provide_required_services(&id));
+
+        // the services are forgotten with the peer
+        swarm
+            .peer_expired(&[net::AddrInfo {
+                id,
+                ip4: vec![],
+                ip6: vec![Arc::new(addr)],
+            }])
+            .unwrap();
+        assert!(!swarm.discovered.contains_key(&id));
+        assert_eq!(swarm.services.get(&id), None);
+    }
+
+    // verify that the services of a peer are forgotten once it's disconnected,
+    // unless it's still a discovered peer
+    #[tokio::test]
+    async fn services_forgotten_on_disconnect() {
+        let mut swarm = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let (id_1, id_2) = (PeerId::random(), PeerId::random());
+
+        swarm
+            .peer_discovered(&[net::AddrInfo {
+                id: id_1,
+                ip4: vec![],
+                ip6: vec![Arc::new("/ip6/::1/tcp/9090".parse().unwrap())],
+            }])
+            .unwrap();
+        swarm.services.insert(id_1, Services::HEADERS);
+        swarm.services.insert(id_2, Services::HEADERS);
+
+        assert!(!swarm.remove_peer(&id_1));
+        assert!(!swarm.remove_peer(&id_2));
+        assert_eq!(swarm.services.get(&id_1), Some(&Services::HEADERS));
+        assert_eq!(swarm.services.get(&id_2), None);
+    }
+
+    // verify that during initial block download the peers that may provide full blocks are
+    // preferred over the peers known to provide only the required services
+    #[tokio::test]
+    async fn auto_connect_prefers_full_blocks_during_ibd() {
+        let mut swarm = make_swarm_manager_with_p2p_config::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+            P2pConfig {
+                required_services: Services::HEADERS,
+                ..Default::default()
+            },
+        )
+        .await;
+        let mut swarm2 = make_swarm_manager_with_p2p_config::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+            P2pConfig {
+                services: Services::HEADERS,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let addr = swarm2.handle.local_addr().clone();
+        let headers_only = Libp2pService::peer_id_from_addr(&addr).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let _ = swarm2.handle.poll_next().await;
+            }
+        });
+
+        let unreachable: PeerId =
+            "12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ".parse().unwrap();
+        swarm
+            .peer_discovered(&[
+                net::AddrInfo {
+                    id: headers_only,
+                    ip4: vec![],
+                    ip6: vec![Arc::new(addr)],
+                },
+                net::AddrInfo {
+                    id: unreachable,
+                    ip4: vec![],
+                    ip6: vec![Arc::new(
+                        "/ip6/::1/tcp/1/p2p/12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ"
+                            .parse()
+                            .unwrap(),
+                    )],
+                },
+            ])
+            .unwrap();
+        swarm.services.insert(headers_only, Services::HEADERS);
+
+        swarm.auto_connect().await.unwrap();
+        assert!(swarm.peers.is_empty());
+        assert!(swarm.stale.contains(&unreachable));
+
+        // once initial block download is done, the peers providing the required services will do
+        assert_eq!(
+            swarm
+                .on_swarm_control_event(Some(event::SwarmEvent::InitialBlockDownloadDone))
+                .await,
+            Ok(())
+        );
+        swarm.auto_connect().await.unwrap();
+        assert!(swarm.peers.contains_key(&headers_only));
+    }
+
+    // verify that if the node doesn't know of any peers and the chain has no seed nodes,
+    // `auto_connect()` fails
+    #[tokio::test]
+    async fn test_auto_connect_no_peers() {
+        let config = Arc::new(config::create_mainnet());
+        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config).await;
+
+        assert_eq!(swarm.auto_connect().await, Err(P2pError::NoPeers));
+        assert!(swarm.peers.is_empty());
+    }
+
+    // verify that if the node doesn't know of any peers,
+    // `auto_connect()` establishes a connection with the seed nodes
+    #[tokio::test]
+    async fn test_auto_connect_fixed_seeds() {
+        let mut swarm2 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+
+        let config = Arc::new(
+            config::ChainConfigBuilder::test_chain()
+                .with_fixed_seeds(vec![swarm2.handle.local_addr().to_string()])
+                .build(),
+        );
+        let mut swarm =
+            make_swarm_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), config)
+                .await;
+
+        tokio::spawn(async move {
+            loop {
+                assert!(swarm2.handle.poll_next().await.is_ok());
+            }
+        });
+
+        swarm.auto_connect().await.unwrap();
+        assert_eq!(swarm.peers.len(), 1);
+    }
+
+    // verify that the configured IP version is used to connect to peers that have both
+    // IPv4 and IPv6 addresses, including peers connected to manually
+    #[tokio::test]
+    async fn ip_preference() {
+        let id = PeerId::random();
+        let ip4: Multiaddr = format!("/ip4/1.2.3.4/tcp/3031/p2p/{}", id).parse().unwrap();
+        let ip6: Multiaddr = format!("/ip6/2001:db8::1/tcp/3031/p2p/{}", id).parse().unwrap();
+        let other: Multiaddr =
+            format!("/ip4/1.2.3.4/tcp/3031/p2p/{}", PeerId::random()).parse().unwrap();
+
+        for (preference, expected) in
+            [(IpPreference::PreferIpv4, &ip4), (IpPreference::PreferIpv6, &ip6)]
+        {
+            let mut swarm = make_swarm_manager_with_p2p_config::<Libp2pService>(
+                test_utils::make_address("/ip6/::1/tcp/"),
+                Arc::new(config::create_mainnet()),
+                P2pConfig {
+                    ip_preference: preference,
+                    ..Default::default()
+                },
+            )
+            .await;
+            swarm
+                .peer_discovered(&[net::AddrInfo {
+                    id,
+                    ip4: vec![Arc::new(ip4.clone())],
+                    ip6: vec![Arc::new(ip6.clone())],
+                }])
+                .unwrap();
+
+            let info = swarm.discovered.get(&id).unwrap();
+            assert_eq!(*swarm.select_address(info), *expected);
+            assert_eq!(swarm.preferred_address(ip4.clone()), *expected);
+            assert_eq!(swarm.preferred_address(ip6.clone()), *expected);
+            assert_eq!(swarm.preferred_address(other.clone()), other);
+        }
+    }
+
+    fn add_fake_peer(swarm: &mut PeerManager<Libp2pService>, role: PeerRole) -> PeerId {
+        let peer_id = PeerId::random();
+        swarm.peers.insert(
+            peer_id,
+            PeerContext::new(
+                net::PeerInfo {
+                    peer_id,
+                    magic_bytes: *swarm.config.magic_bytes(),
+                    version: common::primitives::version::SemVer::new(0, 1, 0),
+                    agent: None,
+                    services: swarm.p2p_config.services,
+                    protocols: vec![],
+                    observed_addr: None,
+                },
+                role,
+                netgroup::NetGroup::Local,
+            ),
+        );
+        peer_id
+    }
+
+    fn make_libp2p_addrs(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| format!("/ip4/1.2.3.4/tcp/{}/p2p/{}", 1000 + i, PeerId::random()))
+            .collect()
+    }
+
+    // verify that unsolicited addresses are rate limited and that solicited addresses are not
+    #[tokio::test]
+    async fn addresses_rate_limited() {
+        let mut swarm = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let peer_id = add_fake_peer(&mut swarm, PeerRole::Inbound);
+
+        // only one unsolicited address is accepted from a new peer
+        assert_eq!(
+            swarm.addresses_received(peer_id, make_libp2p_addrs(5), false).await,
+            Ok(())
+        );
+        assert_eq!(swarm.discovered.len(), 1);
+
+        assert_eq!(
+            swarm.addresses_received(peer_id, make_libp2p_addrs(5), true).await,
+            Ok(())
+        );
+        assert_eq!(swarm.discovered.len(), 6);
+
+        // addresses that don't identify the peer are ignored
+        assert_eq!(
+            swarm
+                .addresses_received(peer_id, vec!["/ip4/1.2.3.4/tcp/1000".to_string()], true)
+                .await,
+            Ok(())
+        );
+        assert_eq!(swarm.discovered.len(), 6);
+
+        // peers that send too many addresses are disconnected
+        assert_eq!(
+            swarm
+                .addresses_received(peer_id, make_libp2p_addrs(MAX_ADDR_PER_MESSAGE + 1), true)
+                .await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidMessage))
+        );
+        assert!(!swarm.peers.contains_key(&peer_id));
+    }
+
+    // verify that the address sample is capped and doesn't contain the addresses
+    // of the peer that asked for it
+    #[tokio::test]
+    async fn sample_addresses() {
+        let mut swarm = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let peer_id = add_fake_peer(&mut swarm, PeerRole::Inbound);
+
+        assert!(swarm.sample_addresses(&peer_id).is_empty());
+
+        let addrs = make_libp2p_addrs(MAX_ADDR_PER_MESSAGE + 10);
+        assert_eq!(
+            swarm.addresses_received(peer_id, addrs[..10].to_vec(), true).await,
+            Ok(())
+        );
+        let requester: PeerId = addrs[0]
+            .parse::<Multiaddr>()
+            .ok()
+            .and_then(|addr| Libp2pService::peer_id_from_addr(&addr))
+            .unwrap();
+
+        let sample = swarm.sample_addresses(&requester);
+        assert_eq!(sample.len(), 9);
+        assert!(!sample.contains(&addrs[0]));
+        assert!(sample.iter().all(|addr| addrs.contains(addr)));
+
+        assert_eq!(
+            swarm.addresses_received(peer_id, addrs[10..].to_vec(), true).await,
+            Ok(())
+        );
+        assert_eq!(
+            swarm.sample_addresses(&requester).len(),
+            MAX_ADDR_PER_MESSAGE
+        );
+    }
+
+    // verify that configured and confirmed external addresses are advertised
+    // and that addresses observed by a single netgroup are not
+    #[tokio::test]
+    async fn external_addresses_advertised() {
+        let configured = make_libp2p_addrs(1);
+        let mut swarm = make_swarm_manager_with_p2p_config::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+            P2pConfig {
+                external_addresses: vec![configured[0].clone(), "invalid".into()],
+                ..Default::default()
+            },
+        )
+        .await;
+        let peer_id = add_fake_peer(&mut swarm, PeerRole::Inbound);
+        assert_eq!(swarm.sample_addresses(&peer_id), configured);
+
+        let observed = format!("/ip4/5.6.7.8/tcp/3031/p2p/{}", PeerId::random());
+        let mut info = net::PeerInfo::<Libp2pService> {
+            peer_id,
+            magic_bytes: *swarm.config.magic_bytes(),
+            version: common::primitives::version::SemVer::new(0, 1, 0),
+            agent: None,
+            services: Services::empty(),
+            protocols: vec![],
+            observed_addr: Some(observed.parse().unwrap()),
+        };
+
+        swarm.address_observed(&info, netgroup::NetGroup::Ipv4([1, 1]));
+        swarm.address_observed(&info, netgroup::NetGroup::Ipv4([1, 1]));
+        assert_eq!(swarm.sample_addresses(&peer_id).len(), 1);
+
+        swarm.address_observed(&info, netgroup::NetGroup::Ipv4([2, 2]));
+        assert_eq!(
+            swarm.sample_addresses(&peer_id),
+            vec![configured[0].clone(), observed]
+        );
+
+        // addresses behind a NAT are never advertised
+        info.observed_addr =
+            Some(format!("/ip4/192.168.1.1/tcp/3031/p2p/{}", PeerId::random()).parse().unwrap());
+        swarm.address_observed(&info, netgroup::NetGroup::Ipv4([1, 1]));
+        swarm.address_observed(&info, netgroup::NetGroup::Ipv4([2, 2]));
+        assert_eq!(swarm.sample_addresses(&peer_id).len(), 2);
+    }
+
+    // verify that round-trip times are recorded and reported and that a peer
+    // is disconnected after too many consecutive unanswered pings
+    #[tokio::test]
+    async fn ping_tracking() {
+        let mut swarm = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let peer_id = add_fake_peer(&mut swarm, PeerRole::Outbound);
+        let rtt = Duration::from_millis(150);
+
+        assert_eq!(
+            swarm
+                .on_network_event(net::ConnectivityEvent::PingResponse { peer_id, rtt })
+                .await,
+            Ok(())
+        );
+        assert_eq!(swarm.peers[&peer_id].ping, Some(rtt));
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        assert_eq!(
+            swarm
+                .on_swarm_control_event(Some(event::SwarmEvent::GetConnectedPeers(tx)))
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            rx.await.unwrap(),
+            vec![event::ConnectedPeer {
+                peer_id: peer_id.to_string(),
+                ping_ms: Some(150),
+            }]
+        );
+
+        // a response resets the count of missed pings
+        for _ in 0..MAX_MISSED_PINGS - 1 {
+            assert_eq!(
+                swarm.on_network_event(net::ConnectivityEvent::PingTimeout { peer_id }).await,
+                Ok(())
+            );
+        }
+        assert_eq!(
+            swarm
+                .on_network_event(net::ConnectivityEvent::PingResponse { peer_id, rtt })
+                .await,
+            Ok(())
+        );
+
+        for _ in 0..MAX_MISSED_PINGS {
+            assert!(swarm.peers.contains_key(&peer_id));
+            assert_eq!(
+                swarm.on_network_event(net::ConnectivityEvent::PingTimeout { peer_id }).await,
+                Ok(())
+            );
+        }
+        assert!(!swarm.peers.contains_key(&peer_id));
+    }
+
+    // verify that peer info reports the connection details, the last announced block
+    // and the misbehavior of the peer
+    #[tokio::test]
+    async fn peer_info() {
+        let mut swarm = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let peer_id = add_fake_peer(&mut swarm, PeerRole::Inbound);
+        let block_id: Id<Block> = Id::new(&common::primitives::H256::random());
+
+        assert_eq!(
+            swarm
+                .on_swarm_control_event(Some(event::SwarmEvent::BlockAnnounced {
+                    peer_id,
+                    block_id: block_id.clone(),
+                }))
+                .await,
+            Ok(())
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                swarm
+                    .on_network_event(net::ConnectivityEvent::Misbehaved {
+                        peer_id,
+                        behaviour: 10,
+                    })
+                    .await,
+                Ok(())
+            );
+        }
+
+        let (tx, rx) = oneshot::channel();
+        assert_eq!(
+            swarm.on_swarm_control_event(Some(event::SwarmEvent::GetPeerInfo(tx))).await,
+            Ok(())
+        );
+        assert_eq!(
+            rx.await.unwrap(),
+            vec![event::PeerInfo {
+                peer_id: peer_id.to_string(),
+                address: None,
+                inbound: true,
+                version: "0.1.0".to_string(),
+                agent: None,
+                services: "full-blocks,headers".to_string(),
+                uptime_secs: 0,
+                last_block: Some(block_id),
+                ping_ms: None,
+                misbehavior_score: 20,
+            }]
+        );
+    }
+
+    // verify that when inbound slots are full, an existing inbound peer is evicted
+    // to make room for the new peer
+    #[tokio::test]
+    async fn inbound_connection_eviction() {
+        let config = Arc::new(config::create_mainnet());
+        let mut swarm1 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            config.clone(),
+        )
+        .await;
+        let mut swarm2 =
+            make_swarm_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), config)
+                .await;
+
+        for _ in 0..MAX_INBOUND_CONNECTIONS {
+            add_fake_peer(&mut swarm2, PeerRole::Inbound);
+        }
+
+        let (_conn1_res, conn2_res) = tokio::join!(
+            swarm1.handle.connect(swarm2.handle.local_addr().clone()),
+            swarm2.handle.poll_next()
+        );
+        let conn2_res: net::ConnectivityEvent<Libp2pService> = conn2_res.unwrap();
+        assert!(std::matches!(
+            conn2_res,
+            net::ConnectivityEvent::IncomingConnection { .. }
+        ));
+        assert_eq!(swarm2.on_network_event(conn2_res).await, Ok(()));
+        assert!(swarm2.peers.contains_key(swarm1.handle.peer_id()));
+        assert_eq!(
+            swarm2.connection_count(PeerRole::Inbound),
+            MAX_INBOUND_CONNECTIONS
+        );
+        assert_eq!(swarm2.connection_count(PeerRole::Outbound), 0);
+    }
+
+    // verify that whitelisted peers bypass the inbound connection limit, aren't evicted
+    // and aren't disconnected for sending too many addresses
+    #[tokio::test]
+    async fn whitelisted_peers() {
+        let config = Arc::new(config::create_mainnet());
+        let p2p_config = P2pConfig {
+            whitelist: vec!["::1".parse().unwrap()],
+            ..Default::default()
+        };
+        let mut swarm1 = make_swarm_manager_with_p2p_config::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            config.clone(),
+            p2p_config.clone(),
+        )
+        .await;
+        let mut swarm2 = make_swarm_manager_with_p2p_config::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            config,
+            p2p_config,
+        )
+        .await;
+
+        for _ in 0..MAX_INBOUND_CONNECTIONS {
+            add_fake_peer(&mut swarm2, PeerRole::Inbound);
+        }
+
+        let (_conn1_res, conn2_res) = tokio::join!(
+            swarm1.handle.connect(swarm2.handle.local_addr().clone()),
+            swarm2.handle.poll_next()
+        );
+        assert_eq!(swarm2.on_network_event(conn2_res.unwrap()).await, Ok(()));
+        assert_eq!(
+            swarm2.connection_count(PeerRole::Inbound),
+            MAX_INBOUND_CONNECTIONS + 1
+        );
+
+        let peer_id = *swarm1.handle.peer_id();
+        assert!(swarm2.peers[&peer_id].has_permission(PeerPermission::NoLimit));
+        assert!(swarm2.peers[&peer_id].has_permission(PeerPermission::NoBan));
+        assert_ne!(swarm2.select_eviction_candidate(), Some(peer_id));
+
+        for peer in swarm2.peers.values_mut() {
+            peer.permissions.insert(PeerPermission::NoLimit);
+        }
+        assert_eq!(swarm2.select_eviction_candidate(), None);
+
+        assert_eq!(
+            swarm2
+                .addresses_received(peer_id, make_libp2p_addrs(MAX_ADDR_PER_MESSAGE + 1), true)
+                .await,
+            Ok(())
+        );
+        assert!(swarm2.peers.contains_key(&peer_id));
+        assert_eq!(swarm2.discovered.len(), MAX_ADDR_PER_MESSAGE);
+    }
+
+    // verify that the peers connected from a banned address are disconnected, that
+    // banned addresses are not dialed and that their inbound connections are closed
+    #[tokio::test]
+    async fn banned_peers() {
+        let config = Arc::new(config::create_mainnet());
+        let mut swarm1 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            config.clone(),
+        )
+        .await;
+        let mut swarm2 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            config.clone(),
+        )
+        .await;
+        let mut swarm3 =
+            make_swarm_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), config)
+                .await;
+
+        let (_conn1_res, conn2_res) = tokio::join!(
+            swarm1.handle.connect(swarm2.handle.local_addr().clone()),
+            swarm2.handle.poll_next()
+        );
+        assert_eq!(swarm2.on_network_event(conn2_res.unwrap()).await, Ok(()));
+        let peer_id = *swarm1.handle.peer_id();
+        assert!(swarm2.peers.contains_key(&peer_id));
+
+        let ip: IpAddr = "::1".parse().unwrap();
+        let (tx, rx) = oneshot::channel();
+        assert_eq!(
+            swarm2
+                .on_swarm_control_event(Some(event::SwarmEvent::Ban(
+                    ip,
+                    banlist::DEFAULT_BAN_DURATION,
+                    tx
+                )))
+                .await,
+            Ok(())
+        );
+        assert_eq!(rx.await, Ok(()));
+        assert!(!swarm2.peers.contains_key(&peer_id));
+        assert!(swarm2.disconnecting.contains_key(&peer_id));
+
+        let (tx, rx) = oneshot::channel();
+        swarm2
+            .on_swarm_control_event(Some(event::SwarmEvent::GetBanned(tx)))
+            .await
+            .unwrap();
+        let banned = rx.await.unwrap();
+        assert_eq!(banned.len(), 1);
+        assert_eq!(banned[0].address, "::1");
+
+        let addr = swarm3.handle.local_addr().clone();
+        assert_eq!(swarm2.dial(&addr).await, Err(P2pError::PeerBanned));
+        assert_eq!(swarm2.dials.failures(&addr), 0);
+
+        let (_conn3_res, _) = tokio::join!(
+            swarm3.handle.connect(swarm2.handle.local_addr().clone()),
+            async {
+                loop {
+                    match swarm2.handle.poll_next().await.unwrap() {
+                        event @ net::ConnectivityEvent::IncomingConnection { .. } => {
+                            assert_eq!(swarm2.on_network_event(event).await, Ok(()));
+                            break;
+                        }
+                        event => {
+                            let _ = swarm2.on_network_event(event).await;
+                        }
+                    }
+                }
+            }
+        );
+        assert!(!swarm2.peers.contains_key(swarm3.handle.peer_id()));
+
+        let (tx, rx) = oneshot::channel();
+        swarm2
+            .on_swarm_control_event(Some(event::SwarmEvent::Unban(ip, tx)))
+            .await
+            .unwrap();
+        assert_eq!(rx.await, Ok(true));
+
+        let (tx, rx) = oneshot::channel();
+        swarm2
+            .on_swarm_control_event(Some(event::SwarmEvent::Unban(ip, tx)))
+            .await
+            .unwrap();
+        assert_eq!(rx.await, Ok(false));
+    }
+
+    // verify that a disconnected peer is tracked until the connection is reported
+    // closed and that it's forgotten if that never happens
+    #[tokio::test]
+    async fn pending_disconnects() {
+        let mut swarm = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let peer1 = add_fake_peer(&mut swarm, PeerRole::Inbound);
+        let peer2 = add_fake_peer(&mut swarm, PeerRole::Outbound);
+
+        let (tx, _rx) = oneshot::channel();
+        assert_eq!(
+            swarm
+                .on_swarm_control_event(Some(event::SwarmEvent::Disconnect(peer1, tx)))
+                .await,
+            Ok(())
+        );
+        assert_eq!(swarm.disconnect_peer(peer2).await, Ok(()));
+        assert!(swarm.peers.is_empty());
+        assert_eq!(swarm.disconnecting.len(), 2);
+
+        assert_eq!(
+            swarm
+                .on_network_event(net::ConnectivityEvent::ConnectionClosed { peer_id: peer1 })
+                .await,
+            Ok(())
+        );
+        assert!(!swarm.disconnecting.contains_key(&peer1));
+
+        let now = Instant::now();
+        assert_eq!(swarm.expire_disconnecting(now).await, Ok(()));
+        assert!(swarm.disconnecting.contains_key(&peer2));
         assert_eq!(
-            rx.await.unwrap(),
-            Err(P2pError::SocketError(std::io::ErrorKind::ConnectionRefused))
+            swarm.expire_disconnecting(now + DISCONNECT_TIMEOUT).await,
+            Ok(())
         );
+        assert!(swarm.disconnecting.is_empty());
     }
 
-    // try to connect to an address that no one listening on and verify it fails
+    // verify that outbound peers are rotated only if all outbound slots are full
+    // and there are discovered peers to replace them with
     #[tokio::test]
-    async fn test_swarm_connect_libp2p() {
-        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+    async fn rotate_outbound_peers() {
         let config = Arc::new(config::create_mainnet());
-        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config).await;
-        let (tx, rx) = oneshot::channel();
+        let mut swarm =
+            make_swarm_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), config)
+                .await;
+
+        for _ in 0..MAX_OUTBOUND_CONNECTIONS {
+            add_fake_peer(&mut swarm, PeerRole::Outbound);
+        }
+        let inbound = add_fake_peer(&mut swarm, PeerRole::Inbound);
+
+        swarm.rotate_outbound().await.unwrap();
+        assert_eq!(
+            swarm.connection_count(PeerRole::Outbound),
+            MAX_OUTBOUND_CONNECTIONS
+        );
 
-        let addr: Multiaddr =
-            "/ip6/::1/tcp/6666/p2p/12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ"
-                .parse()
-                .unwrap();
         swarm
-            .on_swarm_control_event(Some(event::SwarmEvent::Connect(addr, tx)))
-            .await
+            .peer_discovered(&[net::AddrInfo {
+                id: PeerId::random(),
+                ip4: vec![],
+                ip6: vec![Arc::new("/ip6/::1/tcp/9091".parse().unwrap())],
+            }])
             .unwrap();
+        swarm.rotate_outbound().await.unwrap();
         assert_eq!(
-            rx.await.unwrap(),
-            Err(P2pError::SocketError(std::io::ErrorKind::ConnectionRefused))
+            swarm.connection_count(PeerRole::Outbound),
+            MAX_OUTBOUND_CONNECTIONS - OUTBOUND_ROTATION_COUNT
         );
+        assert!(swarm.peers.contains_key(&inbound));
     }
 
+    // verify that a discovered address that can't be connected to is forgotten
     #[tokio::test]
-    async fn test_peer_discovered_libp2p() {
-        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
+    async fn feeler_connection_unreachable() {
         let config = Arc::new(config::create_mainnet());
-        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config).await;
-
-        let id_1: libp2p::PeerId =
-            "12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ".parse().unwrap();
-        let id_2: libp2p::PeerId =
-            "12D3KooWE3kBRAnn6jxZMdK1JMWx1iHtR1NKzXSRv5HLTmfD9u9c".parse().unwrap();
-        let id_3: libp2p::PeerId =
-            "12D3KooWGK4RzvNeioS9aXdzmYXU3mgDrRPjQd8SVyXCkHNxLbWN".parse().unwrap();
-
-        // check that peer with `id` has the correct ipv4 and ipv6 addresses
-        let check_peer =
-            |discovered: &HashMap<
-                <Libp2pService as NetworkingService>::PeerId,
-                PeerAddrInfo<Libp2pService>,
-            >,
-             id: libp2p::PeerId,
-             ip4: Vec<Arc<<Libp2pService as NetworkingService>::Address>>,
-             ip6: Vec<Arc<<Libp2pService as NetworkingService>::Address>>| {
-                let (p_ip4, p_ip6) = match discovered.get(&id).unwrap() {
-                    PeerAddrInfo::Raw { ip4, ip6 } => (ip4, ip6),
-                };
-
-                assert_eq!(ip4.len(), p_ip4.len());
-                assert_eq!(ip6.len(), p_ip6.len());
-
-                for ip in ip4.iter() {
-                    assert!(p_ip4.contains(ip));
-                }
-
-                for ip in ip6.iter() {
-                    assert!(p_ip6.contains(ip));
-                }
-            };
+        let mut swarm =
+            make_swarm_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), config)
+                .await;
 
-        // first add two new peers, both with ipv4 and ipv6 address
+        let id: PeerId = "12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ".parse().unwrap();
         swarm
-            .peer_discovered(&[
-                net::AddrInfo {
-                    id: id_1,
-                    ip4: vec![Arc::new("/ip4/127.0.0.1/tcp/9090".parse().unwrap())],
-                    ip6: vec![Arc::new("/ip6/::1/tcp/9091".parse().unwrap())],
-                },
-                net::AddrInfo {
-                    id: id_2,
-                    ip4: vec![Arc::new("/ip4/127.0.0.1/tcp/9092".parse().unwrap())],
-                    ip6: vec![Arc::new("/ip6/::1/tcp/9093".parse().unwrap())],
-                },
-            ])
+            .peer_discovered(&[net::AddrInfo {
+                id,
+                ip4: vec![],
+                ip6: vec![Arc::new(
+                    "/ip6/::1/tcp/1/p2p/12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ"
+                        .parse()
+                        .unwrap(),
+                )],
+            }])
             .unwrap();
 
-        assert_eq!(swarm.peers.len(), 0);
-        assert_eq!(swarm.discovered.len(), 2);
-
-        check_peer(
-            &swarm.discovered,
-            id_1,
-            vec![Arc::new("/ip4/127.0.0.1/tcp/9090".parse().unwrap())],
-            vec![Arc::new("/ip6/::1/tcp/9091".parse().unwrap())],
-        );
+        swarm.feeler_connect().await.unwrap();
+        assert!(swarm.discovered.is_empty());
+        assert!(swarm.peers.is_empty());
+        assert!(swarm.stale.contains(&id));
+        assert_eq!(swarm.stale.failures(&id), 1);
+
+        // the peer is retried once its backoff has elapsed
+        swarm.retry_stale(Instant::now() + stale::BASE_BACKOFF);
+        assert!(swarm.discovered.contains_key(&id));
+        swarm.feeler_connect().await.unwrap();
+        assert_eq!(swarm.stale.failures(&id), 2);
+    }
 
-        check_peer(
-            &swarm.discovered,
-            id_2,
-            vec![Arc::new("/ip4/127.0.0.1/tcp/9092".parse().unwrap())],
-            vec![Arc::new("/ip6/::1/tcp/9093".parse().unwrap())],
-        );
+    // verify that an address given by the user that can't be dialed is dialed again
+    // once its backoff has elapsed
+    #[tokio::test]
+    async fn failed_dial_retried() {
+        let addr: SocketAddr = test_utils::make_address("[::1]:");
+        let config = Arc::new(config::create_mainnet());
+        let mut swarm = make_swarm_manager::<MockService>(addr, config).await;
+        let (tx, rx) = oneshot::channel();
 
-        // then discover one new peer and two additional ipv6 addresses for peer 1
+        let addr: SocketAddr = "[::1]:1".parse().unwrap();
         swarm
-            .peer_discovered(&[
-                net::AddrInfo {
-                    id: id_1,
-                    ip4: vec![],
-                    ip6: vec![
-                        Arc::new("/ip6/::1/tcp/9094".parse().unwrap()),
-                        Arc::new("/ip6/::1/tcp/9095".parse().unwrap()),
-                    ],
-                },
-                net::AddrInfo {
-                    id: id_3,
-                    ip4: vec![Arc::new("/ip4/127.0.0.1/tcp/9096".parse().unwrap())],
-                    ip6: vec![Arc::new("/ip6/::1/tcp/9097".parse().unwrap())],
-                },
-            ])
+            .on_swarm_control_event(Some(event::SwarmEvent::Connect(addr, tx)))
+            .await
             .unwrap();
+        assert!(rx.await.unwrap().is_err());
+        assert!(swarm.dials.is_queued(&addr));
+        assert_eq!(swarm.dials.failures(&addr), 1);
 
-        check_peer(
-            &swarm.discovered,
-            id_1,
-            vec![Arc::new("/ip4/127.0.0.1/tcp/9090".parse().unwrap())],
-            vec![
-                Arc::new("/ip6/::1/tcp/9091".parse().unwrap()),
-                Arc::new("/ip6/::1/tcp/9094".parse().unwrap()),
-                Arc::new("/ip6/::1/tcp/9095".parse().unwrap()),
-            ],
-        );
+        // nothing is dialed before the backoff has elapsed
+        swarm.retry_dials(Instant::now()).await.unwrap();
+        assert_eq!(swarm.dials.failures(&addr), 1);
 
-        check_peer(
-            &swarm.discovered,
-            id_3,
-            vec![Arc::new("/ip4/127.0.0.1/tcp/9096".parse().unwrap())],
-            vec![Arc::new("/ip6/::1/tcp/9097".parse().unwrap())],
-        );
+        swarm.retry_dials(Instant::now() + dial::BASE_BACKOFF).await.unwrap();
+        assert!(swarm.dials.is_queued(&addr));
+        assert_eq!(swarm.dials.failures(&addr), 2);
     }
 
-    // verify that if the node is aware of any peers on the network,
-    // call to `auto_connect()` will establish a connection with them
+    // verify that an address of a discovered peer that can't be dialed is demoted
+    // and eventually removed while the other addresses of the peer are kept
     #[tokio::test]
-    async fn test_auto_connect_mock() {
-        let config = Arc::new(config::create_mainnet());
-        let addr: Multiaddr = test_utils::make_address("/ip6/::1/tcp/");
-        let mut swarm = make_swarm_manager::<Libp2pService>(addr, config.clone()).await;
-        let mut swarm2 =
-            make_swarm_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), config)
-                .await;
-
-        let addr = swarm2.handle.local_addr().clone();
-        let id: PeerId = if let Some(Protocol::P2p(peer)) = addr.iter().last() {
-            PeerId::from_multihash(peer).unwrap()
-        } else {
-            panic!("invalid multiaddr");
-        };
-
-        tokio::spawn(async move {
-            log::debug!("staring libp2p service");
-            loop {
-                assert!(swarm2.handle.poll_next().await.is_ok());
-            }
-        });
+    async fn unreachable_address_demoted() {
+        let mut swarm = make_swarm_manager_with_p2p_config::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+            P2pConfig {
+                ip_preference: IpPreference::PreferIpv6,
+                ..Default::default()
+            },
+        )
+        .await;
 
-        // "discover" the other libp2p service
+        let id: PeerId = "12D3KooWRn14SemPVxwzdQNg8e8Trythiww1FWrNfPbukYBmZEbJ".parse().unwrap();
+        let ip4: Multiaddr = format!("/ip4/127.0.0.1/tcp/1/p2p/{}", id).parse().unwrap();
+        let ip6: Multiaddr = format!("/ip6/::1/tcp/1/p2p/{}", id).parse().unwrap();
         swarm
             .peer_discovered(&[net::AddrInfo {
                 id,
-                ip4: vec![],
-                ip6: vec![Arc::new(addr)],
+                ip4: vec![Arc::new(ip4.clone())],
+                ip6: vec![Arc::new(ip6.clone())],
             }])
             .unwrap();
-        swarm.auto_connect().await.unwrap();
-        assert_eq!(swarm.peers.len(), 1);
+
+        swarm.feeler_connect().await.unwrap();
+        assert_eq!(swarm.dials.failures(&ip6), 1);
+        swarm.retry_stale(Instant::now() + stale::MAX_BACKOFF);
+        assert_eq!(*swarm.select_address(&swarm.discovered[&id]), ip4);
+
+        // the addresses are tried in turns until the preferred one has failed too many times
+        for _ in 0..2 * (dial::MAX_ADDR_FAILURES - 1) {
+            swarm.feeler_connect().await.unwrap();
+            swarm.retry_stale(Instant::now() + stale::MAX_BACKOFF);
+        }
+        match &swarm.discovered[&id] {
+            PeerAddrInfo::Raw {
+                ip4: addrs4,
+                ip6: addrs6,
+            } => {
+                assert!(addrs6.is_empty());
+                assert!(addrs4.contains(&ip4));
+            }
+        }
+        assert_eq!(swarm.dials.failures(&ip6), 0);
     }
 
     #[tokio::test]
@@ -648,7 +2816,7 @@ mod tests {
         let mut swarm2 = make_swarm_manager::<Libp2pService>(
             test_utils::make_address("/ip6/::1/tcp/"),
             Arc::new(
-                common::chain::config::TestChainConfig::new()
+                common::chain::config::ChainConfigBuilder::test_chain()
                     .with_magic_bytes([1, 2, 3, 4])
                     .build(),
             ),
@@ -698,7 +2866,7 @@ mod tests {
         let mut swarm2 = make_swarm_manager::<Libp2pService>(
             test_utils::make_address("/ip6/::1/tcp/"),
             Arc::new(
-                common::chain::config::TestChainConfig::new()
+                common::chain::config::ChainConfigBuilder::test_chain()
                     .with_magic_bytes([1, 2, 3, 4])
                     .build(),
             ),
@@ -720,6 +2888,99 @@ mod tests {
         );
     }
 
+    // verify that a peer running an older version than the minimum supported one is rejected
+    #[tokio::test]
+    async fn connect_outbound_unsupported_version() {
+        let mut swarm1 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(
+                common::chain::config::ChainConfigBuilder::test_chain()
+                    .with_min_peer_version(common::primitives::version::SemVer::new(0, 2, 0))
+                    .build(),
+            ),
+        )
+        .await;
+        let mut swarm2 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+
+        let (conn1_res, _conn2_res) = tokio::join!(
+            swarm1.handle.connect(swarm2.handle.local_addr().clone()),
+            swarm2.handle.poll_next()
+        );
+
+        assert_eq!(
+            swarm1
+                .on_network_event(net::ConnectivityEvent::ConnectionAccepted {
+                    peer_info: conn1_res.unwrap()
+                })
+                .await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidVersion))
+        );
+        assert!(swarm1.peers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn connect_inbound_unsupported_version() {
+        let mut swarm1 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let mut swarm2 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(
+                common::chain::config::ChainConfigBuilder::test_chain()
+                    .with_min_peer_version(common::primitives::version::SemVer::new(1, 0, 0))
+                    .build(),
+            ),
+        )
+        .await;
+
+        let (_conn1_res, conn2_res) = tokio::join!(
+            swarm1.handle.connect(swarm2.handle.local_addr().clone()),
+            swarm2.handle.poll_next()
+        );
+        let conn2_res: net::ConnectivityEvent<Libp2pService> = conn2_res.unwrap();
+        assert_eq!(
+            swarm2.on_network_event(conn2_res).await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidVersion))
+        );
+        assert!(swarm2.peers.is_empty());
+    }
+
+    // verify that a peer which doesn't support all required protocols is rejected
+    #[tokio::test]
+    async fn connect_inbound_missing_protocol() {
+        let mut swarm1 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(config::create_mainnet()),
+        )
+        .await;
+        let mut swarm2 = make_swarm_manager::<Libp2pService>(
+            test_utils::make_address("/ip6/::1/tcp/"),
+            Arc::new(
+                common::chain::config::ChainConfigBuilder::test_chain()
+                    .with_required_peer_protocols(vec!["/mintlayer/unknown/0.1.0".to_string()])
+                    .build(),
+            ),
+        )
+        .await;
+
+        let (_conn1_res, conn2_res) = tokio::join!(
+            swarm1.handle.connect(swarm2.handle.local_addr().clone()),
+            swarm2.handle.poll_next()
+        );
+        let conn2_res: net::ConnectivityEvent<Libp2pService> = conn2_res.unwrap();
+        assert_eq!(
+            swarm2.on_network_event(conn2_res).await,
+            Err(P2pError::ProtocolError(ProtocolError::InvalidProtocol))
+        );
+        assert!(swarm2.peers.is_empty());
+    }
+
     #[tokio::test]
     async fn remote_closes_connection() {
         let mut swarm1 = make_swarm_manager::<Libp2pService>(
@@ -0,0 +1,96 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::IpAddr;
+
+/// Group of network addresses that are likely controlled by the same operator
+///
+/// Peers in the same group are treated as one when diversifying connections.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NetGroup {
+    /// Loopback, private and other non-routable addresses
+    Local,
+
+    /// IPv4 /16 network
+    Ipv4([u8; 2]),
+
+    /// IPv6 /32 network
+    Ipv6([u8; 4]),
+
+    /// Address whose IP couldn't be determined
+    Unknown,
+}
+
+impl NetGroup {
+    pub fn from_ip(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) => {
+                if ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+                {
+                    return NetGroup::Local;
+                }
+
+                let octets = ip.octets();
+                NetGroup::Ipv4([octets[0], octets[1]])
+            }
+            IpAddr::V6(ip) => {
+                if let Some(ip) = ip.to_ipv4_mapped() {
+                    return Self::from_ip(&IpAddr::V4(ip));
+                }
+
+                // unique local (fc00::/7) and link-local (fe80::/10) addresses
+                let segments = ip.segments();
+                if ip.is_loopback()
+                    || ip.is_unspecified()
+                    || (segments[0] & 0xfe00) == 0xfc00
+                    || (segments[0] & 0xffc0) == 0xfe80
+                {
+                    return NetGroup::Local;
+                }
+
+                let octets = ip.octets();
+                NetGroup::Ipv6([octets[0], octets[1], octets[2], octets[3]])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netgroups() {
+        let group = |ip: &str| NetGroup::from_ip(&ip.parse().unwrap());
+
+        assert_eq!(group("127.0.0.1"), NetGroup::Local);
+        assert_eq!(group("192.168.1.1"), NetGroup::Local);
+        assert_eq!(group("::1"), NetGroup::Local);
+        assert_eq!(group("fd00::1"), NetGroup::Local);
+        assert_eq!(group("fe80::1"), NetGroup::Local);
+
+        assert_eq!(group("1.2.3.4"), NetGroup::Ipv4([1, 2]));
+        assert_eq!(group("1.2.200.200"), group("1.2.3.4"));
+        assert_ne!(group("1.3.3.4"), group("1.2.3.4"));
+        assert_eq!(group("::ffff:1.2.3.4"), group("1.2.3.4"));
+
+        assert_eq!(
+            group("2001:db8:1::1"),
+            NetGroup::Ipv6([0x20, 0x01, 0x0d, 0xb8])
+        );
+        assert_eq!(group("2001:db8:ffff::1"), group("2001:db8:1::1"));
+        assert_ne!(group("2001:db9::1"), group("2001:db8::1"));
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Selection of the discovered peers the local node opens outbound connections to
+//!
+//! Outbound peers are how the node learns about the state of the network, so an attacker
+//! who controls all of them can feed the node a false view of the chain. Getting addresses
+//! from many netgroups is much harder than getting many addresses from one netgroup, so
+//! outbound connections are spread over as many netgroups as possible.
+
+use super::netgroup::NetGroup;
+use rand::{seq::SliceRandom, Rng};
+use std::collections::HashMap;
+
+/// Maximum number of outbound connections to peers in the same netgroup
+pub const MAX_OUTBOUND_PER_NETGROUP: usize = 1;
+
+/// Whether the number of connections to `netgroup` is limited
+///
+/// Local and unknown addresses can't be grouped by operator, so they are not limited.
+/// They are only used once all routable netgroups have been exhausted.
+fn is_limited(netgroup: &NetGroup) -> bool {
+    matches!(netgroup, NetGroup::Ipv4(_) | NetGroup::Ipv6(_))
+}
+
+/// Select at most `count` peers to open outbound connections to
+///
+/// Candidates are considered in random order and a candidate is skipped if the node
+/// already has [`MAX_OUTBOUND_PER_NETGROUP`] connections to its netgroup, counting both
+/// the existing outbound connections and the candidates selected before it.
+///
+/// # Arguments
+/// `candidates` - discovered peers and the netgroups of their addresses
+/// `connected` - netgroups of the current outbound peers
+/// `count` - number of free outbound slots
+pub fn select_outbound<P>(
+    mut candidates: Vec<(P, NetGroup)>,
+    connected: impl IntoIterator<Item = NetGroup>,
+    count: usize,
+    rng: &mut impl Rng,
+) -> Vec<P> {
+    let mut groups: HashMap<NetGroup, usize> = HashMap::new();
+    for netgroup in connected {
+        *groups.entry(netgroup).or_default() += 1;
+    }
+
+    candidates.shuffle(rng);
+    candidates.sort_by_key(|(_, netgroup)| !is_limited(netgroup));
+
+    candidates
+        .into_iter()
+        .filter(|(_, netgroup)| {
+            let connections = groups.entry(*netgroup).or_default();
+            if is_limited(netgroup) && *connections >= MAX_OUTBOUND_PER_NETGROUP {
+                return false;
+            }
+            *connections += 1;
+            true
+        })
+        .map(|(peer_id, _)| peer_id)
+        .take(count)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(ip: &str) -> NetGroup {
+        NetGroup::from_ip(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn one_peer_per_netgroup() {
+        let candidates = vec![
+            (1, group("1.1.1.1")),
+            (2, group("1.1.2.2")),
+            (3, group("2.2.2.2")),
+            (4, group("3.3.3.3")),
+            (5, group("2001:db8::1")),
+            (6, group("2001:db8:1::1")),
+        ];
+
+        for _ in 0..16 {
+            let mut selected = select_outbound(candidates.clone(), [], 8, &mut rand::thread_rng());
+            selected.sort_unstable();
+
+            assert_eq!(selected.len(), 4);
+            assert!(selected.contains(&1) ^ selected.contains(&2));
+            assert!(selected.contains(&3) && selected.contains(&4));
+            assert!(selected.contains(&5) ^ selected.contains(&6));
+        }
+
+        // netgroups of existing outbound peers are taken into account
+        let selected = select_outbound(
+            candidates,
+            [group("1.1.3.3"), group("3.3.0.1")],
+            8,
+            &mut rand::thread_rng(),
+        );
+        assert_eq!(selected.len(), 2);
+        assert!(selected.contains(&3));
+    }
+
+    #[test]
+    fn routable_addresses_preferred() {
+        let candidates = vec![
+            (1, group("127.0.0.1")),
+            (2, group("192.168.0.1")),
+            (3, NetGroup::Unknown),
+            (4, group("1.1.1.1")),
+            (5, group("2.2.2.2")),
+        ];
+
+        for _ in 0..16 {
+            let mut selected = select_outbound(candidates.clone(), [], 2, &mut rand::thread_rng());
+            selected.sort_unstable();
+            assert_eq!(selected, vec![4, 5]);
+        }
+
+        // local addresses are not limited
+        let selected = select_outbound(candidates, [], 8, &mut rand::thread_rng());
+        assert_eq!(selected.len(), 5);
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Discovered peers that couldn't be connected to or whose addresses expired
+//!
+//! Such peers are moved to the stale bucket and retried once their backoff has elapsed.
+//! The backoff doubles after each consecutive failure and a peer that keeps failing
+//! is eventually forgotten.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Backoff after the first failure
+pub const BASE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Maximum backoff between two retries
+pub const MAX_BACKOFF: Duration = Duration::from_secs(4 * 60 * 60);
+
+/// Number of consecutive failures after which a peer is forgotten
+pub const MAX_FAILURES: u32 = 8;
+
+/// Time to wait before retrying a peer that has failed `failures` times in a row
+pub fn backoff(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    BASE_BACKOFF.saturating_mul(1 << exponent).min(MAX_BACKOFF)
+}
+
+struct StaleEntry<I> {
+    info: I,
+    retry_at: Instant,
+}
+
+pub struct StalePeers<P, I> {
+    /// Peers waiting for their backoff to elapse
+    waiting: HashMap<P, StaleEntry<I>>,
+
+    /// Consecutive failures of the peers, kept while a peer is retried
+    failures: HashMap<P, u32>,
+}
+
+impl<P, I> StalePeers<P, I>
+where
+    P: Hash + Eq + Copy,
+{
+    pub fn new() -> Self {
+        Self {
+            waiting: HashMap::new(),
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Move a peer to the stale bucket after a failure
+    ///
+    /// If the peer is already waiting, `merge` is used to add `info` to the information
+    /// that is stored for it.
+    ///
+    /// Returns `false` if the peer has failed too many times and was forgotten.
+    pub fn failed(
+        &mut self,
+        peer_id: P,
+        info: I,
+        now: Instant,
+        merge: impl FnOnce(&mut I, I),
+    ) -> bool {
+        let failures = self.failures.entry(peer_id).or_default();
+        *failures += 1;
+
+        if *failures > MAX_FAILURES {
+            self.failures.remove(&peer_id);
+            self.waiting.remove(&peer_id);
+            return false;
+        }
+
+        let retry_at = now + backoff(*failures);
+        match self.waiting.get_mut(&peer_id) {
+            Some(entry) => {
+                merge(&mut entry.info, info);
+                entry.retry_at = retry_at;
+            }
+            None => {
+                self.waiting.insert(peer_id, StaleEntry { info, retry_at });
+            }
+        }
+
+        true
+    }
+
+    /// Forget the failures of a peer that was connected to successfully
+    pub fn succeeded(&mut self, peer_id: &P) {
+        self.failures.remove(peer_id);
+        self.waiting.remove(peer_id);
+    }
+
+    /// Remove the peers whose backoff has elapsed so that they can be retried
+    pub fn ready(&mut self, now: Instant) -> Vec<(P, I)> {
+        let ready: Vec<P> = self
+            .waiting
+            .iter()
+            .filter(|(_, entry)| entry.retry_at <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+
+        ready
+            .into_iter()
+            .filter_map(|peer_id| self.waiting.remove(&peer_id).map(|entry| (peer_id, entry.info)))
+            .collect()
+    }
+
+    /// Whether the peer is waiting for its backoff to elapse
+    pub fn contains(&self, peer_id: &P) -> bool {
+        self.waiting.contains_key(peer_id)
+    }
+
+    /// Number of consecutive failures of the peer
+    pub fn failures(&self, peer_id: &P) -> u32 {
+        self.failures.get(peer_id).copied().unwrap_or(0)
+    }
+}
+
+impl<P, I> Default for StalePeers<P, I>
+where
+    P: Hash + Eq + Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(info: &mut Vec<&'static str>, other: Vec<&'static str>) {
+        info.extend(other)
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        assert_eq!(backoff(1), BASE_BACKOFF);
+        assert_eq!(backoff(2), BASE_BACKOFF * 2);
+        assert_eq!(backoff(4), BASE_BACKOFF * 8);
+        assert_eq!(backoff(10), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+
+        let now = Instant::now();
+        let mut stale = StalePeers::new();
+        assert!(stale.failed(1, vec!["a"], now, merge));
+        assert!(stale.contains(&1));
+        assert!(stale.ready(now + BASE_BACKOFF - Duration::from_secs(1)).is_empty());
+        assert_eq!(stale.ready(now + BASE_BACKOFF), vec![(1, vec!["a"])]);
+        assert!(!stale.contains(&1));
+
+        // the failures are remembered while the peer is retried
+        assert!(stale.failed(1, vec!["a"], now, merge));
+        assert_eq!(stale.failures(&1), 2);
+        assert!(stale.ready(now + BASE_BACKOFF).is_empty());
+        assert_eq!(stale.ready(now + BASE_BACKOFF * 2).len(), 1);
+
+        stale.succeeded(&1);
+        assert_eq!(stale.failures(&1), 0);
+    }
+
+    #[test]
+    fn addresses_merged() {
+        let now = Instant::now();
+        let mut stale = StalePeers::new();
+
+        assert!(stale.failed(1, vec!["a"], now, merge));
+        assert!(stale.failed(1, vec!["b"], now, merge));
+        assert!(stale.failed(2, vec!["c"], now, merge));
+
+        let mut ready = stale.ready(now + MAX_BACKOFF);
+        ready.sort();
+        assert_eq!(ready, vec![(1, vec!["a", "b"]), (2, vec!["c"])]);
+    }
+
+    #[test]
+    fn forgotten_after_max_failures() {
+        let now = Instant::now();
+        let mut stale = StalePeers::new();
+
+        for _ in 0..MAX_FAILURES {
+            assert!(stale.failed(1, vec!["a"], now, merge));
+        }
+        assert!(!stale.failed(1, vec!["a"], now, merge));
+        assert!(!stale.contains(&1));
+        assert_eq!(stale.failures(&1), 0);
+    }
+}
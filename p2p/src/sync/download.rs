@@ -0,0 +1,441 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Block download queue used during initial block download
+//!
+//! The queue holds the headers of the best header chain that the local node knows of
+//! and tracks which of the corresponding blocks have been requested, from which peer,
+//! and which have been received. Blocks are requested in parallel from all peers that
+//! announced them but only within a moving window starting at the first block that
+//! hasn't been given to chainstate yet, and they are released in chain order.
+
+use crate::net::NetworkingService;
+use common::{
+    chain::block::{Block, BlockHeader},
+    primitives::{BlockHeight, Id, Idable},
+};
+use logging::log;
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Download state of a single block
+enum BlockState<T: NetworkingService> {
+    /// Block hasn't been requested yet
+    Queued,
+
+    /// Block has been requested from a peer
+    InFlight { peer_id: T::PeerId, since: Instant },
+
+    /// Block has been received but its parent hasn't been processed yet
+    Received(Block),
+}
+
+struct QueueEntry<T: NetworkingService> {
+    /// Header of the block
+    header: BlockHeader,
+
+    /// Download state of the block
+    state: BlockState<T>,
+
+    /// Peer that failed to deliver the block, if any
+    failed_peer: Option<T::PeerId>,
+}
+
+pub struct BlockDownloader<T: NetworkingService> {
+    /// Headers of the best known chain whose blocks haven't been processed yet,
+    /// the first entry is always the next block to be given to chainstate
+    queue: VecDeque<QueueEntry<T>>,
+
+    /// Height of the last header in the queue
+    target_height: Option<BlockHeight>,
+
+    /// Blocks each peer has announced and can thus be asked for
+    peers: HashMap<T::PeerId, BTreeSet<Id<Block>>>,
+
+    /// Number of blocks, counting from the start of the queue, that can be requested
+    window_size: usize,
+
+    /// Maximum number of blocks that can be requested from a single peer at a time
+    max_in_flight: usize,
+}
+
+impl<T: NetworkingService> BlockDownloader<T> {
+    pub fn new(window_size: usize, max_in_flight: usize) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            target_height: None,
+            peers: HashMap::new(),
+            window_size,
+            max_in_flight,
+        }
+    }
+
+    /// Returns `true` if there are no blocks to download or process
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Height of the chain that is being downloaded
+    pub fn target_height(&self) -> Option<BlockHeight> {
+        self.target_height
+    }
+
+    /// Number of blocks that have been requested from `peer_id` but not received
+    pub fn in_flight(&self, peer_id: &T::PeerId) -> usize {
+        self.queue
+            .iter()
+            .filter(|entry| {
+                std::matches!(&entry.state, BlockState::InFlight { peer_id: id, .. } if id == peer_id)
+            })
+            .count()
+    }
+
+    /// Number of received blocks that wait for their parent to be given to chainstate
+    pub fn orphans(&self) -> usize {
+        self.queue
+            .iter()
+            .filter(|entry| std::matches!(entry.state, BlockState::Received(_)))
+            .count()
+    }
+
+    /// Register headers announced by a peer
+    ///
+    /// `headers` must be a connected chain of headers unknown to the local node and
+    /// `tip_height` the height of the last header. If the chain is higher than the chain
+    /// currently being downloaded, the queue switches to it while keeping the progress
+    /// made on the common prefix of the chains.
+    pub fn add_headers(
+        &mut self,
+        peer_id: T::PeerId,
+        headers: Vec<BlockHeader>,
+        tip_height: BlockHeight,
+    ) {
+        self.peers
+            .entry(peer_id)
+            .or_default()
+            .extend(headers.iter().map(|header| header.get_id()));
+
+        if headers.is_empty() || self.target_height >= Some(tip_height) {
+            return;
+        }
+
+        log::debug!(
+            "switch block download to chain announced by peer {:?}, tip height {}",
+            peer_id,
+            tip_height
+        );
+
+        let same_parent = std::matches!(
+            self.queue.front(),
+            Some(entry) if entry.header.get_prev_block_id() == headers[0].get_prev_block_id()
+        );
+        let common = if same_parent {
+            self.queue
+                .iter()
+                .zip(headers.iter())
+                .take_while(|(entry, header)| entry.header.get_id() == header.get_id())
+                .count()
+        } else {
+            0
+        };
+
+        self.queue.truncate(common);
+        self.queue.extend(headers.into_iter().skip(common).map(|header| QueueEntry {
+            header,
+            state: BlockState::Queued,
+            failed_peer: None,
+        }));
+        self.target_height = Some(tip_height);
+    }
+
+    /// Forget a peer and put the blocks requested from it back in the queue
+    pub fn remove_peer(&mut self, peer_id: &T::PeerId) {
+        self.peers.remove(peer_id);
+
+        for entry in self.queue.iter_mut() {
+            if std::matches!(&entry.state, BlockState::InFlight { peer_id: id, .. } if id == peer_id)
+            {
+                entry.state = BlockState::Queued;
+            }
+        }
+    }
+
+    /// Select the next blocks to request and mark them as requested
+    ///
+    /// Each block within the download window that hasn't been requested is assigned to
+    /// the least busy peer that announced it, preferring peers that haven't failed to
+    /// deliver the block before.
+    pub fn schedule(&mut self, now: Instant) -> Vec<(T::PeerId, Id<Block>)> {
+        let mut in_flight: HashMap<T::PeerId, usize> =
+            self.peers.keys().map(|peer_id| (*peer_id, self.in_flight(peer_id))).collect();
+        let mut scheduled = Vec::new();
+
+        for entry in self.queue.iter_mut().take(self.window_size) {
+            if !std::matches!(entry.state, BlockState::Queued) {
+                continue;
+            }
+
+            let id = entry.header.get_id();
+            let peer_id = in_flight
+                .iter()
+                .filter(|(peer_id, count)| {
+                    **count < self.max_in_flight
+                        && std::matches!(self.peers.get(peer_id), Some(known) if known.contains(&id))
+                })
+                .min_by_key(|(peer_id, count)| (Some(**peer_id) == entry.failed_peer, **count))
+                .map(|(peer_id, _)| *peer_id);
+
+            if let Some(peer_id) = peer_id {
+                *in_flight.get_mut(&peer_id).expect("peer to exist") += 1;
+                entry.state = BlockState::InFlight {
+                    peer_id,
+                    since: now,
+                };
+                scheduled.push((peer_id, id));
+            }
+        }
+
+        scheduled
+    }
+
+    /// Mark a requested block as failed so it's requested again, preferably from another peer
+    pub fn request_failed(&mut self, peer_id: &T::PeerId, block_id: &Id<Block>) {
+        if let Some(entry) = self.queue.iter_mut().find(|entry| &entry.header.get_id() == block_id)
+        {
+            if std::matches!(&entry.state, BlockState::InFlight { peer_id: id, .. } if id == peer_id)
+            {
+                entry.state = BlockState::Queued;
+                entry.failed_peer = Some(*peer_id);
+            }
+        }
+    }
+
+    /// Find blocks that have been in flight for longer than `timeout`
+    ///
+    /// The blocks are put back in the queue so they can be requested from another peer.
+    /// Returns the peers that stalled and the blocks they failed to deliver.
+    pub fn stalled(&mut self, now: Instant, timeout: Duration) -> Vec<(T::PeerId, Id<Block>)> {
+        let mut stalled = Vec::new();
+
+        for entry in self.queue.iter_mut() {
+            if let BlockState::InFlight { peer_id, since } = entry.state {
+                if now.saturating_duration_since(since) >= timeout {
+                    stalled.push((peer_id, entry.header.get_id()));
+                    entry.state = BlockState::Queued;
+                    entry.failed_peer = Some(peer_id);
+                }
+            }
+        }
+
+        stalled
+    }
+
+    /// Store a received block
+    ///
+    /// Returns `false` if the block isn't part of the chain being downloaded
+    /// or if it has already been received.
+    pub fn block_received(&mut self, block: Block) -> bool {
+        let id = block.get_id();
+
+        match self.queue.iter_mut().find(|entry| entry.header.get_id() == id) {
+            Some(entry) if !std::matches!(entry.state, BlockState::Received(_)) => {
+                entry.state = BlockState::Received(block);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Take the next block that can be given to chainstate, if it has been received
+    pub fn next_block(&mut self) -> Option<Block> {
+        if !std::matches!(self.queue.front()?.state, BlockState::Received(_)) {
+            return None;
+        }
+
+        let entry = self.queue.pop_front().expect("entry to exist");
+        if self.queue.is_empty() {
+            self.target_height = None;
+        }
+
+        match entry.state {
+            BlockState::Received(block) => Some(block),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::mock::MockService;
+    use common::chain::block::consensus_data::ConsensusData;
+    use std::net::SocketAddr;
+
+    fn make_chain(prev: Option<Id<Block>>, timestamp: u32, len: usize) -> Vec<Block> {
+        let mut prev = prev;
+
+        (0..len as u32)
+            .map(|i| {
+                let block =
+                    Block::new(vec![], prev.clone(), timestamp + i, ConsensusData::None).unwrap();
+                prev = Some(block.get_id());
+                block
+            })
+            .collect()
+    }
+
+    fn headers(blocks: &[Block]) -> Vec<BlockHeader> {
+        blocks.iter().map(|block| block.header().clone()).collect()
+    }
+
+    fn peer(port: u16) -> SocketAddr {
+        format!("[::1]:{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn blocks_released_in_order() {
+        let mut downloader = BlockDownloader::<MockService>::new(8, 8);
+        let blocks = make_chain(None, 0, 4);
+        let now = Instant::now();
+
+        downloader.add_headers(peer(1), headers(&blocks), BlockHeight::new(4));
+        assert_eq!(downloader.schedule(now).len(), 4);
+        assert_eq!(downloader.in_flight(&peer(1)), 4);
+
+        // receive blocks in reverse order, nothing is released until the first block arrives
+        for block in blocks.iter().skip(1).rev() {
+            assert!(downloader.block_received(block.clone()));
+            assert!(downloader.next_block().is_none());
+        }
+        assert_eq!(downloader.orphans(), 3);
+        assert!(downloader.block_received(blocks[0].clone()));
+        assert!(!downloader.block_received(blocks[0].clone()));
+        assert_eq!(downloader.orphans(), 4);
+
+        for block in blocks.iter() {
+            assert_eq!(downloader.next_block().as_ref(), Some(block));
+        }
+        assert!(downloader.next_block().is_none());
+        assert_eq!(downloader.orphans(), 0);
+        assert!(downloader.is_empty());
+        assert_eq!(downloader.target_height(), None);
+    }
+
+    #[test]
+    fn window_and_peer_limits() {
+        let mut downloader = BlockDownloader::<MockService>::new(6, 2);
+        let blocks = make_chain(None, 0, 10);
+        let now = Instant::now();
+
+        downloader.add_headers(peer(1), headers(&blocks), BlockHeight::new(10));
+        downloader.add_headers(peer(2), headers(&blocks), BlockHeight::new(10));
+        downloader.add_headers(peer(3), headers(&blocks[9..]), BlockHeight::new(1));
+
+        // peer 3 only knows a block outside of the window
+        assert_eq!(downloader.schedule(now).len(), 4);
+        assert_eq!(downloader.in_flight(&peer(1)), 2);
+        assert_eq!(downloader.in_flight(&peer(2)), 2);
+        assert_eq!(downloader.in_flight(&peer(3)), 0);
+        assert!(downloader.schedule(now).is_empty());
+
+        // window moves forward only when blocks are released
+        for block in blocks.iter().take(4).skip(1) {
+            assert!(downloader.block_received(block.clone()));
+        }
+        assert_eq!(downloader.schedule(now).len(), 2);
+        assert!(downloader.schedule(now).is_empty());
+        assert!(downloader.next_block().is_none());
+
+        assert!(downloader.block_received(blocks[0].clone()));
+        assert_eq!((0..5).filter_map(|_| downloader.next_block()).count(), 4);
+
+        // peers 1 and 2 get two more blocks and peer 3 gets the last block
+        assert_eq!(downloader.schedule(now).len(), 3);
+        assert_eq!(downloader.in_flight(&peer(3)), 1);
+    }
+
+    #[test]
+    fn switch_to_higher_chain() {
+        let mut downloader = BlockDownloader::<MockService>::new(16, 16);
+        let common = make_chain(None, 0, 2);
+        let mut chain1 = common.clone();
+        chain1.extend(make_chain(Some(common[1].get_id()), 100, 2));
+        let mut chain2 = common.clone();
+        chain2.extend(make_chain(Some(common[1].get_id()), 200, 3));
+        let now = Instant::now();
+
+        downloader.add_headers(peer(1), headers(&chain1), BlockHeight::new(4));
+        assert_eq!(downloader.schedule(now).len(), 4);
+        assert!(downloader.block_received(chain1[0].clone()));
+
+        // lower chain is ignored
+        downloader.add_headers(peer(2), headers(&chain1[..2]), BlockHeight::new(2));
+        assert_eq!(downloader.target_height(), Some(BlockHeight::new(4)));
+
+        // higher chain replaces the tail but keeps the common prefix
+        downloader.add_headers(peer(2), headers(&chain2), BlockHeight::new(5));
+        assert_eq!(downloader.target_height(), Some(BlockHeight::new(5)));
+        assert_eq!(downloader.in_flight(&peer(1)), 1);
+        assert_eq!(downloader.schedule(now).len(), 3);
+        assert!(!downloader.block_received(chain1[3].clone()));
+
+        for block in chain2.iter().skip(1) {
+            assert!(downloader.block_received(block.clone()));
+        }
+        let released = (0..5).filter_map(|_| downloader.next_block()).collect::<Vec<_>>();
+        assert_eq!(released, chain2);
+    }
+
+    #[test]
+    fn stalled_download_moves_to_another_peer() {
+        let mut downloader = BlockDownloader::<MockService>::new(16, 16);
+        let blocks = make_chain(None, 0, 1);
+        let now = Instant::now();
+        let timeout = Duration::from_secs(5);
+
+        downloader.add_headers(peer(1), headers(&blocks), BlockHeight::new(1));
+        assert_eq!(
+            downloader.schedule(now),
+            vec![(peer(1), blocks[0].get_id())]
+        );
+        downloader.add_headers(peer(2), headers(&blocks), BlockHeight::new(1));
+
+        assert!(downloader.stalled(now + Duration::from_secs(1), timeout).is_empty());
+        assert_eq!(
+            downloader.stalled(now + timeout, timeout),
+            vec![(peer(1), blocks[0].get_id())]
+        );
+        assert_eq!(
+            downloader.schedule(now + timeout),
+            vec![(peer(2), blocks[0].get_id())]
+        );
+
+        // the request fails also for peer 2 so the block is requested from peer 1 again
+        downloader.request_failed(&peer(2), &blocks[0].get_id());
+        assert_eq!(
+            downloader.schedule(now + timeout),
+            vec![(peer(1), blocks[0].get_id())]
+        );
+
+        // peer disconnects and its requests are dropped
+        downloader.remove_peer(&peer(1));
+        assert_eq!(
+            downloader.schedule(now + timeout),
+            vec![(peer(2), blocks[0].get_id())]
+        );
+    }
+}
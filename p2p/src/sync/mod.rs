@@ -18,26 +18,31 @@ use crate::{
     error::{self, FatalError, P2pError, ProtocolError},
     event,
     message::{Message, MessageType, SyncingMessage, SyncingRequest, SyncingResponse},
-    net::{self, NetworkingService, SyncingCodecService},
+    metrics,
+    net::{self, NetworkingService, SyncingService},
 };
 use chainstate::{
-    chainstate_interface, BlockError, BlockSource, ChainstateError::ProcessBlockError,
+    chainstate_interface, BlockError, BlockSource,
+    ChainstateError::{FailedToReadProperty, ProcessBlockError},
 };
 use common::{
     chain::{
         block::{Block, BlockHeader},
         config::ChainConfig,
     },
-    primitives::{Id, Idable},
+    primitives::{BlockHeight, Id, Idable},
 };
 use futures::FutureExt;
 use logging::log;
+use serialization::Encode;
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     sync::Arc,
+    time::{Duration, Instant},
 };
 use tokio::sync::{mpsc, oneshot};
 
+pub mod download;
 pub mod peer;
 
 // TODO: from config? global constant?
@@ -46,6 +51,42 @@ const HEADER_LIMIT: usize = 2000;
 // TODO: this comes from spec?
 const RETRY_LIMIT: usize = 3;
 
+/// Number of blocks, starting from the first unprocessed block, that can be downloaded at a time
+const DOWNLOAD_WINDOW: usize = 128;
+
+/// Maximum number of blocks that can be requested from a single peer at a time
+const MAX_BLOCKS_IN_FLIGHT: usize = 8;
+
+/// Time after which a block request is considered stalled and the block is requested again
+const BLOCK_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time after which an unanswered header request is considered timed out
+const HEADER_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval at which stalled block downloads and timed out requests are checked
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time without any blocks being received after which block download is considered stalled
+const SYNC_STALL_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// Misbehavior score reported to PeerManager when a peer fails to answer a request in time
+const REQUEST_TIMEOUT_SCORE: u32 = 10;
+
+/// Maximum number of blocks a peer can request in a burst
+const MAX_BLOCK_REQUEST_BURST: usize = 500;
+
+/// Number of blocks per second a peer is allowed to request on average
+const BLOCK_REQUEST_RATE_PER_SEC: f64 = 50.0;
+
+/// Maximum number of blocks remembered per peer to avoid announcing blocks the peer already has
+const MAX_KNOWN_BLOCKS: usize = 1024;
+
+/// Maximum number of orphan blocks of a single peer whose ancestors are requested at a time
+const MAX_ORPHAN_REQUESTS: usize = 8;
+
+/// Size of the channel receiving new tips from chainstate
+const CHAINSTATE_EVENT_CHANNEL_SIZE: usize = 64;
+
 // TODO: add more tests
 // TODO: split syncing into separate files
 // TODO: match against error in `run()` and deal with `ProtocolError`
@@ -80,17 +121,22 @@ impl<T> FatalError for error::Result<T> {
 }
 
 enum RequestType {
-    GetHeaders,
-    GetBlocks(Vec<Id<Block>>),
+    Headers,
+    Blocks(Vec<Id<Block>>),
+    Addr,
+    Announcement,
 }
 
 struct PendingRequest<T: NetworkingService> {
-    _peer_id: T::PeerId,
+    peer_id: T::PeerId,
     request_type: RequestType,
     retry_count: usize,
+
+    /// Time when the request was sent
+    sent: Instant,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum SyncState {
     /// Local node's state is uninitialized
     Uninitialized,
@@ -98,6 +144,9 @@ pub enum SyncState {
     /// Downloading blocks from remote node(s)
     DownloadingBlocks,
 
+    /// Downloading blocks but none have been received in a while
+    Stalled,
+
     /// Local block index is fully synced
     Idle,
 }
@@ -121,7 +170,7 @@ where
     state: SyncState,
 
     /// Handle for sending/receiving connectivity events
-    handle: T::SyncingCodecHandle,
+    handle: T::SyncingHandle,
 
     /// RX channel for receiving control events
     rx_sync: mpsc::Receiver<event::SyncControlEvent<T>>,
@@ -135,22 +184,35 @@ where
     /// Hashmap of connected peers
     peers: HashMap<T::PeerId, peer::PeerContext<T>>,
 
+    /// Peers whose connection is being closed, messages from them are ignored
+    disconnecting: HashSet<T::PeerId>,
+
     /// Subsystem handle to Chainstate
     chainstate_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
 
     /// Pending requests
     requests: HashMap<T::RequestId, PendingRequest<T>>,
+
+    /// Blocks of the best known header chain that are being downloaded
+    downloader: download::BlockDownloader<T>,
+
+    /// Time when new headers or blocks were last received
+    last_progress: Instant,
+
+    /// Whether chainstate is in initial block download, see
+    /// [`is_initial_block_download`](chainstate_interface::ChainstateInterface::is_initial_block_download)
+    initial_block_download: bool,
 }
 
 // TODO: refactor this code
 impl<T> SyncManager<T>
 where
     T: NetworkingService,
-    T::SyncingCodecHandle: SyncingCodecService<T>,
+    T::SyncingHandle: SyncingService<T>,
 {
     pub fn new(
         config: Arc<ChainConfig>,
-        handle: T::SyncingCodecHandle,
+        handle: T::SyncingHandle,
         chainstate_handle: subsystem::Handle<Box<dyn chainstate_interface::ChainstateInterface>>,
         rx_sync: mpsc::Receiver<event::SyncControlEvent<T>>,
         tx_swarm: mpsc::Sender<event::SwarmEvent<T>>,
@@ -164,8 +226,12 @@ where
             tx_pubsub,
             chainstate_handle,
             peers: Default::default(),
+            disconnecting: HashSet::new(),
             requests: HashMap::new(),
+            downloader: download::BlockDownloader::new(DOWNLOAD_WINDOW, MAX_BLOCKS_IN_FLIGHT),
+            last_progress: Instant::now(),
             state: SyncState::Uninitialized,
+            initial_block_download: true,
         }
     }
 
@@ -173,38 +239,90 @@ where
         &self.state
     }
 
-    pub fn handle_mut(&mut self) -> &mut T::SyncingCodecHandle {
+    pub fn handle_mut(&mut self) -> &mut T::SyncingHandle {
         &mut self.handle
     }
 
+    /// Send a request to the peer and account it in the traffic of the peer
+    async fn send_request(
+        &mut self,
+        peer_id: T::PeerId,
+        request: SyncingRequest,
+    ) -> error::Result<T::RequestId> {
+        let message = Message {
+            magic: *self.config.magic_bytes(),
+            msg: MessageType::Syncing(SyncingMessage::Request(request)),
+        };
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            peer.record_sent(message.encoded_size());
+        }
+
+        self.handle.send_request(peer_id, message).await
+    }
+
+    /// Respond to a request of the peer and account the response in the traffic of the peer
+    async fn send_response(
+        &mut self,
+        peer_id: T::PeerId,
+        request_id: T::RequestId,
+        response: SyncingResponse,
+    ) -> error::Result<()> {
+        let message = Message {
+            magic: *self.config.magic_bytes(),
+            msg: MessageType::Syncing(SyncingMessage::Response(response)),
+        };
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            peer.record_sent(message.encoded_size());
+        }
+
+        self.handle.send_response(request_id, message).await
+    }
+
+    /// Ask PeerManager to close the connection to the peer
+    async fn disconnect_peer(&mut self, peer_id: T::PeerId) -> error::Result<()> {
+        self.unregister_peer(peer_id);
+        self.disconnecting.insert(peer_id);
+
+        let (tx, rx) = oneshot::channel();
+        self.tx_swarm
+            .send(event::SwarmEvent::Disconnect(peer_id, tx))
+            .await
+            .map_err(P2pError::from)?;
+        rx.await.map_err(P2pError::from)?
+    }
+
+    /// Report to PeerManager that the peer misbehaved
+    async fn peer_misbehaved(&mut self, peer_id: T::PeerId, score: u32) -> error::Result<()> {
+        self.tx_swarm
+            .send(event::SwarmEvent::Misbehaved { peer_id, score })
+            .await
+            .map_err(P2pError::from)
+    }
+
     pub async fn send_header_request(
         &mut self,
         peer_id: T::PeerId,
         locator: Vec<BlockHeader>,
         retry_count: usize,
     ) -> error::Result<()> {
-        let peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
-        let request_id = self
-            .handle
-            .send_request(
-                peer_id,
-                Message {
-                    magic: *self.config.magic_bytes(),
-                    msg: MessageType::Syncing(SyncingMessage::Request(
-                        SyncingRequest::GetHeaders { locator },
-                    )),
-                },
-            )
-            .await?;
+        if !self.peers.contains_key(&peer_id) {
+            return Err(P2pError::PeerDoesntExist);
+        }
+
+        let request_id = self.send_request(peer_id, SyncingRequest::GetHeaders { locator }).await?;
         self.requests.insert(
             request_id,
             PendingRequest {
-                _peer_id: peer_id,
-                request_type: RequestType::GetHeaders,
+                peer_id,
+                request_type: RequestType::Headers,
                 retry_count,
+                sent: Instant::now(),
             },
         );
-        peer.set_state(peer::PeerSyncState::UploadingHeaders);
+        self.peers
+            .get_mut(&peer_id)
+            .expect("peer to exist")
+            .set_state(peer::PeerSyncState::UploadingHeaders);
 
         Ok(())
     }
@@ -215,38 +333,336 @@ where
         block_id: Id<Block>,
         retry_count: usize,
     ) -> error::Result<()> {
-        let peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
+        if !self.peers.contains_key(&peer_id) {
+            return Err(P2pError::PeerDoesntExist);
+        }
+
         let request_id = self
-            .handle
             .send_request(
                 peer_id,
-                Message {
-                    magic: *self.config.magic_bytes(),
-                    msg: MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetBlocks {
-                        block_ids: vec![block_id.clone()],
-                    })),
+                SyncingRequest::GetBlocks {
+                    block_ids: vec![block_id.clone()],
                 },
             )
             .await?;
         self.requests.insert(
             request_id,
             PendingRequest {
-                _peer_id: peer_id,
-                request_type: RequestType::GetBlocks(vec![block_id.clone()]),
+                peer_id,
+                request_type: RequestType::Blocks(vec![block_id.clone()]),
                 retry_count,
+                sent: Instant::now(),
+            },
+        );
+        self.peers
+            .get_mut(&peer_id)
+            .expect("peer to exist")
+            .set_state(peer::PeerSyncState::UploadingBlocks);
+
+        Ok(())
+    }
+
+    /// Ask a peer for addresses of other nodes it knows about
+    pub async fn send_addr_request(&mut self, peer_id: T::PeerId) -> error::Result<()> {
+        if !self.peers.contains_key(&peer_id) {
+            return Err(P2pError::PeerDoesntExist);
+        }
+
+        let request_id = self.send_request(peer_id, SyncingRequest::GetAddr).await?;
+        self.requests.insert(
+            request_id,
+            PendingRequest {
+                peer_id,
+                request_type: RequestType::Addr,
+                retry_count: 0,
+                sent: Instant::now(),
             },
         );
-        peer.set_state(peer::PeerSyncState::UploadingBlocks(block_id));
 
         Ok(())
     }
 
+    /// Request the next blocks of the best header chain from the peers that announced them
+    async fn schedule_block_requests(&mut self) -> error::Result<()> {
+        for (peer_id, block_id) in self.downloader.schedule(Instant::now()) {
+            logging::trace!(block_id = block_id, peer_id = peer_id; "request block from peer");
+
+            if let Err(err) = self.send_block_request(peer_id, block_id.clone(), 0).await {
+                logging::error!(
+                    block_id = block_id, peer_id = peer_id;
+                    "failed to request block from peer: {:?}",
+                    err
+                );
+                self.downloader.request_failed(&peer_id, &block_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ask idle peers whether they know of any new headers
+    async fn request_new_headers(&mut self) -> error::Result<()> {
+        let idle = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.state() == &peer::PeerSyncState::Idle)
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+
+        for peer_id in idle {
+            let locator = self.chainstate_handle.call(|this| this.get_locator()).await??;
+            self.peers
+                .get_mut(&peer_id)
+                .expect("peer to exist")
+                .set_locator(locator.clone());
+            self.send_header_request(peer_id, locator, 0).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Tell PeerManager once chainstate has left initial block download
+    ///
+    /// Chainstate doesn't return to initial block download, so this is only done once.
+    async fn check_initial_block_download(&mut self) -> error::Result<()> {
+        if !self.initial_block_download {
+            return Ok(());
+        }
+
+        self.initial_block_download =
+            self.chainstate_handle.call(|this| this.is_initial_block_download()).await?;
+        if self.initial_block_download {
+            return Ok(());
+        }
+        self.tx_swarm
+            .send(event::SwarmEvent::InitialBlockDownloadDone)
+            .await
+            .map_err(P2pError::from)
+    }
+
+    /// Request the missing ancestors of an orphan block from the peer that sent it
+    ///
+    /// The headers are requested with the current locator so that the response connects the
+    /// orphan to the local chain, after which its blocks are downloaded as usual. A single
+    /// header request is outstanding per peer and it resolves all the orphans of the peer.
+    /// Once the peer has [`MAX_ORPHAN_REQUESTS`] orphans waiting, further orphans are ignored.
+    async fn request_orphan_ancestors(
+        &mut self,
+        peer_id: T::PeerId,
+        block_id: Id<Block>,
+    ) -> error::Result<()> {
+        let peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
+        let requested =
+            peer.orphans() > 0 || peer.state() == &peer::PeerSyncState::UploadingHeaders;
+        if !peer.add_orphan(block_id.clone()) {
+            logging::debug!(
+                peer_id = peer_id, block_id = block_id;
+                "too many orphan blocks from peer, ignore orphan block"
+            );
+            return Ok(());
+        }
+        if requested {
+            return Ok(());
+        }
+
+        logging::debug!(
+            peer_id = peer_id, block_id = block_id;
+            "request ancestors of orphan block from peer"
+        );
+        let locator = self.chainstate_handle.call(|this| this.get_locator()).await??;
+        self.peers
+            .get_mut(&peer_id)
+            .expect("peer to exist")
+            .set_locator(locator.clone());
+        self.send_header_request(peer_id, locator, 0).await
+    }
+
+    /// Announce a new tip of the local chain to the synced peers that don't have it yet
+    ///
+    /// The header is sent directly to the peers so that they don't have to wait for the block
+    /// to be flooded over pubsub or to poll for new headers. Nothing is announced during
+    /// initial block download as the tip changes constantly.
+    async fn announce_tip(&mut self, block_id: Id<Block>) -> error::Result<()> {
+        if self.state != SyncState::Idle {
+            return Ok(());
+        }
+
+        let peers = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| {
+                peer.state() == &peer::PeerSyncState::Idle && !peer.knows_block(&block_id)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+        if peers.is_empty() {
+            return Ok(());
+        }
+
+        let id = block_id.clone();
+        let header = self
+            .chainstate_handle
+            .call(move |this| this.get_block(id))
+            .await??
+            .ok_or(P2pError::InvalidData)?
+            .header()
+            .clone();
+
+        for peer_id in peers {
+            logging::trace!(block_id = block_id, peer_id = peer_id; "announce block to peer");
+
+            self.peers
+                .get_mut(&peer_id)
+                .expect("peer to exist")
+                .add_known_block(block_id.clone());
+            let request_id = self
+                .send_request(
+                    peer_id,
+                    SyncingRequest::AnnounceHeader {
+                        header: header.clone(),
+                    },
+                )
+                .await?;
+            self.requests.insert(
+                request_id,
+                PendingRequest {
+                    peer_id,
+                    request_type: RequestType::Announcement,
+                    retry_count: 0,
+                    sent: Instant::now(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Handle the requests that haven't been answered in time
+    ///
+    /// Header requests are sent again and blocks that haven't been received are requested
+    /// again, preferably from other peers. The peers that failed to answer are penalized.
+    async fn check_request_timeouts(&mut self) -> error::Result<()> {
+        let now = Instant::now();
+
+        let (expired, pending): (HashMap<_, _>, HashMap<_, _>) =
+            std::mem::take(&mut self.requests).into_iter().partition(|(_, request)| {
+                matches!(request.request_type, RequestType::Headers)
+                    && now.saturating_duration_since(request.sent) >= HEADER_REQUEST_TIMEOUT
+            });
+        self.requests = pending;
+
+        for (request_id, request) in expired {
+            log::warn!(
+                "header request {:?} for peer {:?} timed out",
+                request_id,
+                request.peer_id
+            );
+            self.request_timed_out(request).await?;
+        }
+
+        let stalled = self.downloader.stalled(now, BLOCK_DOWNLOAD_TIMEOUT);
+
+        for (peer_id, block_id) in stalled.iter() {
+            logging::warn!(
+                block_id = block_id, peer_id = peer_id;
+                "download of block from peer stalled"
+            );
+            self.peer_misbehaved(*peer_id, REQUEST_TIMEOUT_SCORE).await?;
+        }
+
+        if !stalled.is_empty() {
+            self.update_peer_states();
+            self.schedule_block_requests().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle a request that the peer failed to answer in time
+    ///
+    /// The peer is penalized and the request is sent again. Headers are requested from
+    /// another idle peer if there is one. If the peer has failed to answer the same request
+    /// [`RETRY_LIMIT`] times, the connection to it is closed.
+    async fn request_timed_out(&mut self, request: PendingRequest<T>) -> error::Result<()> {
+        let peer_id = request.peer_id;
+        if !self.peers.contains_key(&peer_id) {
+            return Ok(());
+        }
+        self.peer_misbehaved(peer_id, REQUEST_TIMEOUT_SCORE).await?;
+
+        if request.retry_count == RETRY_LIMIT {
+            logging::error!(
+                peer_id = peer_id;
+                "peer failed to respond to request, close connection"
+            );
+            return self.disconnect_peer(peer_id).await;
+        }
+
+        match request.request_type {
+            RequestType::Headers => {
+                let other = self
+                    .peers
+                    .iter()
+                    .find(|(id, peer)| {
+                        **id != peer_id && peer.state() == &peer::PeerSyncState::Idle
+                    })
+                    .map(|(id, _)| *id);
+                let locator = self.chainstate_handle.call(|this| this.get_locator()).await??;
+
+                match other {
+                    Some(other) => {
+                        log::debug!("request headers from peer {:?} instead", other);
+                        self.peers
+                            .get_mut(&peer_id)
+                            .expect("peer to exist")
+                            .set_state(peer::PeerSyncState::Idle);
+                        self.peers
+                            .get_mut(&other)
+                            .expect("peer to exist")
+                            .set_locator(locator.clone());
+                        self.send_header_request(other, locator, 0).await
+                    }
+                    None => {
+                        self.peers
+                            .get_mut(&peer_id)
+                            .expect("peer to exist")
+                            .set_locator(locator.clone());
+                        self.send_header_request(peer_id, locator, request.retry_count + 1).await
+                    }
+                }
+            }
+            RequestType::Blocks(block_ids) => {
+                // try to download the blocks from another peer
+                for block_id in block_ids.iter() {
+                    self.downloader.request_failed(&peer_id, block_id);
+                }
+                self.update_peer_states();
+                self.schedule_block_requests().await
+            }
+            // addresses are requested only once per connection
+            RequestType::Addr => Ok(()),
+            // a missed announcement is recovered when the peer asks for headers
+            RequestType::Announcement => Ok(()),
+        }
+    }
+
+    /// Mark peers that have no blocks in flight as idle
+    fn update_peer_states(&mut self) {
+        for (peer_id, peer) in self.peers.iter_mut() {
+            if peer.state() == &peer::PeerSyncState::UploadingBlocks
+                && self.downloader.in_flight(peer_id) == 0
+            {
+                peer.set_state(peer::PeerSyncState::Idle);
+            }
+        }
+    }
+
     pub async fn register_peer(&mut self, peer_id: T::PeerId) -> error::Result<()> {
-        log::info!("register peer {:?} to sync manager", peer_id);
+        logging::info!(peer_id = peer_id; "register peer to sync manager");
 
         match self.peers.entry(peer_id) {
             Entry::Occupied(_) => {
-                log::error!("peer {:?} already known by sync manager", peer_id);
+                logging::error!(peer_id = peer_id; "peer already known by sync manager");
                 Err(P2pError::PeerExists)
             }
             Entry::Vacant(entry) => {
@@ -258,9 +674,11 @@ where
     }
 
     pub fn unregister_peer(&mut self, peer_id: T::PeerId) {
-        log::info!("unregister peer {:?}", peer_id);
+        logging::info!(peer_id = peer_id; "unregister peer");
 
         self.peers.remove(&peer_id);
+        self.requests.retain(|_, request| request.peer_id != peer_id);
+        self.downloader.remove_peer(&peer_id);
     }
 
     pub async fn process_header_request(
@@ -269,23 +687,14 @@ where
         request_id: T::RequestId,
         locator: Vec<BlockHeader>,
     ) -> error::Result<()> {
-        log::trace!(
-            "received a header request from peer {:?}, locator {:#?}",
-            peer_id,
+        logging::trace!(
+            peer_id = peer_id;
+            "received a header request from peer, locator {:#?}",
             locator
         );
 
         let headers = self.chainstate_handle.call(move |this| this.get_headers(locator)).await??;
-        self.handle
-            .send_response(
-                request_id,
-                Message {
-                    magic: *self.config.magic_bytes(),
-                    msg: MessageType::Syncing(SyncingMessage::Response(SyncingResponse::Headers {
-                        headers,
-                    })),
-                },
-            )
+        self.send_response(peer_id, request_id, SyncingResponse::Headers { headers })
             .await
     }
 
@@ -295,10 +704,10 @@ where
         request_id: T::RequestId,
         headers: Vec<Id<Block>>,
     ) -> error::Result<()> {
-        log::trace!(
-            "received a block request from peer {:?}, header counter {}",
-            peer_id,
-            headers.len(),
+        logging::trace!(
+            peer_id = peer_id;
+            "received a block request from peer, header counter {}",
+            headers.len()
         );
 
         if headers.len() != 1 {
@@ -306,7 +715,16 @@ where
             return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
         }
 
-        let _peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
+        let peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
+        if !peer.consume_block_tokens(headers.len(), Instant::now()) {
+            logging::warn!(
+                peer_id = peer_id;
+                "peer exceeded the block request rate, close connection"
+            );
+            self.disconnect_peer(peer_id).await?;
+            return Err(P2pError::ProtocolError(ProtocolError::RateLimitExceeded));
+        }
+
         let block_id = headers.get(0).expect("header to exist").clone();
         let block = self
             .chainstate_handle
@@ -322,17 +740,130 @@ where
                 P2pError::InvalidData
             })?;
 
-        self.handle
-            .send_response(
-                request_id,
-                Message {
-                    magic: *self.config.magic_bytes(),
-                    msg: MessageType::Syncing(SyncingMessage::Response(SyncingResponse::Blocks {
-                        blocks: vec![block],
-                    })),
-                },
-            )
+        self.send_response(
+            peer_id,
+            request_id,
+            SyncingResponse::Blocks {
+                blocks: vec![block],
+            },
+        )
+        .await
+    }
+
+    /// Answer an address request with a sample of the addresses known to PeerManager
+    async fn process_addr_request(
+        &mut self,
+        peer_id: T::PeerId,
+        request_id: T::RequestId,
+    ) -> error::Result<()> {
+        logging::trace!(peer_id = peer_id; "received an address request from peer");
+
+        let (tx, rx) = oneshot::channel();
+        self.tx_swarm
+            .send(event::SwarmEvent::GetAddresses(peer_id, tx))
             .await
+            .map_err(P2pError::from)?;
+        let addrs = rx.await.map_err(P2pError::from)?;
+
+        self.send_response(peer_id, request_id, SyncingResponse::Addr { addrs }).await
+    }
+
+    /// Pass addresses received from a peer to PeerManager
+    async fn process_addresses(
+        &mut self,
+        peer_id: T::PeerId,
+        addrs: Vec<String>,
+        solicited: bool,
+    ) -> error::Result<()> {
+        logging::trace!(
+            peer_id = peer_id;
+            "received {} addresses from peer, solicited {}",
+            addrs.len(),
+            solicited
+        );
+
+        self.tx_swarm
+            .send(event::SwarmEvent::AddressesReceived {
+                peer_id,
+                addrs,
+                solicited,
+            })
+            .await
+            .map_err(P2pError::from)
+    }
+
+    /// Download the block a peer announced if it extends the local chain
+    ///
+    /// If the parent of the announced block is not known, the local node has fallen behind
+    /// the peer and the missing headers are requested from it.
+    async fn process_header_announcement(
+        &mut self,
+        peer_id: T::PeerId,
+        request_id: T::RequestId,
+        header: BlockHeader,
+    ) -> error::Result<()> {
+        let block_id = header.get_id();
+        logging::trace!(peer_id = peer_id, block_id = block_id; "peer announced block");
+
+        self.peers
+            .get_mut(&peer_id)
+            .ok_or(P2pError::PeerDoesntExist)?
+            .add_known_block(block_id);
+        self.send_response(peer_id, request_id, SyncingResponse::HeaderAck).await?;
+
+        let unknown = match self
+            .chainstate_handle
+            .call({
+                let header = header.clone();
+                |this| this.filter_already_existing_blocks(vec![header])
+            })
+            .await?
+        {
+            Ok(unknown) => unknown,
+            // the parent of the block isn't known either, its headers are requested below
+            Err(FailedToReadProperty(BlockError::NotFound)) => vec![header],
+            Err(e) => return Err(P2pError::ChainstateError(e)),
+        };
+        let header = match unknown.into_iter().next() {
+            Some(header) => header,
+            None => return Ok(()),
+        };
+
+        let prev_id = header.get_prev_block_id().clone().ok_or_else(|| {
+            logging::error!(peer_id = peer_id; "peer announced a header without previous id");
+            P2pError::ProtocolError(ProtocolError::InvalidMessage)
+        })?;
+        let prev_height = match self
+            .chainstate_handle
+            .call(move |this| this.get_block_height_in_main_chain(&prev_id))
+            .await?
+        {
+            Ok(height) => height,
+            Err(FailedToReadProperty(BlockError::NotFound)) => None,
+            Err(e) => return Err(P2pError::ChainstateError(e)),
+        };
+
+        match prev_height {
+            Some(height) => {
+                let tip_height = height.checked_add(1).ok_or(P2pError::InvalidData)?;
+                self.last_progress = Instant::now();
+                self.downloader.add_headers(peer_id, vec![header], tip_height);
+                self.schedule_block_requests().await
+            }
+            None => {
+                let peer = self.peers.get(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
+                if peer.state() != &peer::PeerSyncState::Idle {
+                    return Ok(());
+                }
+
+                let locator = self.chainstate_handle.call(|this| this.get_locator()).await??;
+                self.peers
+                    .get_mut(&peer_id)
+                    .expect("peer to exist")
+                    .set_locator(locator.clone());
+                self.send_header_request(peer_id, locator, 0).await
+            }
+        }
     }
 
     // TODO: get rid of the awful `set_state()` calls
@@ -344,12 +875,13 @@ where
     ) -> error::Result<()> {
         let peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
 
-        log::debug!(
-            "initialize peer {:?} state, number of headers: {}",
-            peer_id,
-            headers.len(),
+        logging::debug!(
+            peer_id = peer_id;
+            "initialize peer state, number of headers: {}",
+            headers.len()
         );
         log::trace!("headers: {:#?}", headers);
+        peer.clear_orphans();
 
         if headers.len() > HEADER_LIMIT {
             // TODO: ban peer
@@ -361,7 +893,7 @@ where
             return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
         }
         if headers.is_empty() {
-            log::debug!("local node is in sync with peer {:?}", peer_id);
+            logging::debug!(peer_id = peer_id; "local node is in sync with peer");
             peer.set_state(peer::PeerSyncState::Idle);
             return Ok(());
         }
@@ -374,7 +906,7 @@ where
             .clone()
             .ok_or_else(|| {
                 // TODO: ban peer
-                log::error!("peer {:?} sent a header with invalid previous id", peer_id);
+                logging::error!(peer_id = peer_id; "peer sent a header with invalid previous id");
                 P2pError::ProtocolError(ProtocolError::InvalidMessage)
             })?;
 
@@ -382,9 +914,9 @@ where
             && self.config.genesis_block_id() != prev_id
         {
             // TODO: ban peer
-            log::error!(
-                "peer {:?} sent headers that don't attach to the sent locator or to genesis block",
-                peer_id
+            logging::error!(
+                peer_id = peer_id;
+                "peer sent headers that don't attach to the sent locator or to genesis block"
             );
 
             return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
@@ -392,7 +924,7 @@ where
 
         for header in &headers {
             if header.get_prev_block_id() != &Some(prev_id) {
-                log::error!("peer {:?} sent headers that are out of order", peer_id);
+                logging::error!(peer_id = peer_id; "peer sent headers that are out of order");
                 return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
             }
             prev_id = header.get_id();
@@ -403,133 +935,96 @@ where
             .call(|this| this.filter_already_existing_blocks(headers))
             .await??;
 
-        peer.register_header_response(&unknown_headers);
-        if let Some(header) = peer.get_header_for_download() {
-            let request_id = self
-                .handle
-                .send_request(
-                    peer_id,
-                    Message {
-                        magic: *self.config.magic_bytes(),
-                        msg: MessageType::Syncing(SyncingMessage::Request(
-                            SyncingRequest::GetBlocks {
-                                block_ids: vec![header.get_id()],
-                            },
-                        )),
-                    },
-                )
-                .await?;
-            self.requests.insert(
-                request_id,
-                PendingRequest {
-                    _peer_id: peer_id,
-                    request_type: RequestType::GetBlocks(vec![header.get_id()]),
-                    retry_count: 0,
-                },
-            );
-            peer.set_state(peer::PeerSyncState::UploadingBlocks(header.get_id()));
-        } else {
-            peer.set_state(peer::PeerSyncState::Idle);
-        }
+        // the headers may fork off a side chain, so take the parent height from its block index
+        let tip_height = match unknown_headers.first().and_then(|h| h.get_prev_block_id().clone()) {
+            Some(prev_id) => self
+                .chainstate_handle
+                .call(move |this| this.get_block_index(&prev_id))
+                .await??
+                .ok_or_else(|| {
+                    // TODO: ban peer
+                    logging::error!(peer_id = peer_id; "peer sent headers with an unknown parent");
+                    P2pError::ProtocolError(ProtocolError::InvalidMessage)
+                })?
+                .get_block_height()
+                .checked_add(unknown_headers.len() as u64)
+                .ok_or(P2pError::InvalidData)?,
+            None => BlockHeight::zero(),
+        };
 
-        Ok(())
+        if !unknown_headers.is_empty() {
+            self.last_progress = Instant::now();
+        }
+        if let Some(last) = unknown_headers.last() {
+            peer.add_known_block(last.get_id());
+        }
+        peer.set_state(peer::PeerSyncState::Idle);
+        self.downloader.add_headers(peer_id, unknown_headers, tip_height);
+        self.schedule_block_requests().await
     }
 
-    // TODO: create process_block function?
     async fn process_block_response(
         &mut self,
         peer_id: T::PeerId,
         blocks: Vec<Block>,
     ) -> error::Result<()> {
-        log::trace!("received {} blocks from peer {:?}", blocks.len(), peer_id,);
+        logging::trace!(peer_id = peer_id; "received {} blocks from peer", blocks.len());
 
         if blocks.len() != 1 {
-            log::error!("expected 1 block, received {} blocks", blocks.len());
-            return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
-        }
-
-        let peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
-
-        // TODO: check error, ban peer
-        let header = blocks.get(0).expect("block to exist").header().clone();
-        let block = blocks.into_iter().next().expect("block to exist");
-        let result = self
-            .chainstate_handle
-            .call_mut(move |this| this.process_block(block, BlockSource::Peer))
-            .await?;
-
-        match result {
-            Ok(_) => {}
-            Err(ProcessBlockError(BlockError::BlockAlreadyExists(id))) => {
-                log::debug!("block {:?} already exists", id)
-            }
-            Err(e) => return Err(P2pError::ChainstateError(e)),
-        }
-
-        let next_header = peer.register_block_response(&header);
-        match next_header {
-            Ok(Some(next_block)) => {
-                let request_id = self
-                    .handle
-                    .send_request(
-                        peer_id,
-                        Message {
-                            magic: *self.config.magic_bytes(),
-                            msg: MessageType::Syncing(SyncingMessage::Request(
-                                SyncingRequest::GetBlocks {
-                                    block_ids: vec![next_block.get_id()],
-                                },
-                            )),
-                        },
-                    )
-                    .await?;
-                self.requests.insert(
-                    request_id,
-                    PendingRequest {
-                        _peer_id: peer_id,
-                        request_type: RequestType::GetBlocks(vec![next_block.get_id()]),
-                        retry_count: 0,
-                    },
-                );
-                peer.set_state(peer::PeerSyncState::UploadingBlocks(next_block.get_id()));
-                Ok(())
-            }
-            Ok(None) => {
-                // last block from peer, ask if peer knows of any new headers
-                let locator = self.chainstate_handle.call(|this| this.get_locator()).await??;
-                peer.set_locator(locator.clone());
-                let request_id = self
-                    .handle
-                    .send_request(
-                        peer_id,
-                        Message {
-                            magic: *self.config.magic_bytes(),
-                            msg: MessageType::Syncing(SyncingMessage::Request(
-                                SyncingRequest::GetHeaders { locator },
-                            )),
-                        },
-                    )
-                    .await?;
-                self.requests.insert(
-                    request_id,
-                    PendingRequest {
-                        _peer_id: peer_id,
-                        request_type: RequestType::GetHeaders,
-                        retry_count: 0,
-                    },
-                );
-                peer.set_state(peer::PeerSyncState::UploadingHeaders);
-                Ok(())
-            }
-            Err(e) => {
-                // TODO: ban peer
-                log::error!(
-                    "peer sent invalid or unexpected data, close connection: {:?}",
-                    e
-                );
-                Ok(())
+            log::error!("expected 1 block, received {} blocks", blocks.len());
+            return Err(P2pError::ProtocolError(ProtocolError::InvalidMessage));
+        }
+
+        let peer = self.peers.get_mut(&peer_id).ok_or(P2pError::PeerDoesntExist)?;
+
+        // TODO: ban peer
+        let block = blocks.into_iter().next().expect("block to exist");
+        peer.add_known_block(block.get_id());
+        if self.downloader.block_received(block) {
+            self.last_progress = Instant::now();
+        } else {
+            logging::error!(peer_id = peer_id; "peer sent a block that was not expected");
+        }
+        self.update_peer_states();
+
+        // give chainstate all blocks whose parents have been processed
+        let mut processed = false;
+        while let Some(block) = self.downloader.next_block() {
+            processed = true;
+            let block_id = block.get_id();
+            let result = self
+                .chainstate_handle
+                .call_mut(move |this| this.process_block(block, BlockSource::Peer))
+                .await?;
+
+            match result {
+                Ok(_) => metrics::metrics().block_downloaded(),
+                Err(ProcessBlockError(BlockError::BlockAlreadyExists(id))) => {
+                    log::debug!("block {:?} already exists", id)
+                }
+                Err(ProcessBlockError(BlockError::LocalOrphan | BlockError::IllegalOrphan)) => {
+                    self.request_orphan_ancestors(peer_id, block_id).await?
+                }
+                Err(e) => return Err(P2pError::ChainstateError(e)),
             }
         }
+        metrics::metrics().orphan_blocks(self.downloader.orphans());
+
+        if processed && self.downloader.is_empty() {
+            // all blocks downloaded, ask if peers know of any new headers
+            return self.request_new_headers().await;
+        }
+
+        self.schedule_block_requests().await
+    }
+
+    /// `DownloadingBlocks`, or `Stalled` if no blocks have been received in a while
+    fn downloading_state(&self) -> SyncState {
+        if self.last_progress.elapsed() >= SYNC_STALL_TIMEOUT {
+            SyncState::Stalled
+        } else {
+            SyncState::DownloadingBlocks
+        }
     }
 
     // if all peers are idling, then it means we're idling -> fully synced
@@ -539,10 +1034,15 @@ where
             return Ok(());
         }
 
+        if !self.downloader.is_empty() {
+            self.state = self.downloading_state();
+            return Ok(());
+        }
+
         for peer in self.peers.values() {
             match peer.state() {
-                peer::PeerSyncState::UploadingBlocks(_) => {
-                    self.state = SyncState::DownloadingBlocks;
+                peer::PeerSyncState::UploadingBlocks => {
+                    self.state = self.downloading_state();
                     return Ok(());
                 }
                 peer::PeerSyncState::UploadingHeaders | peer::PeerSyncState::Unknown => {
@@ -573,6 +1073,15 @@ where
             SyncingRequest::GetBlocks { block_ids } => {
                 self.process_block_request(peer_id, request_id, block_ids).await
             }
+            SyncingRequest::GetAddr => self.process_addr_request(peer_id, request_id).await,
+            SyncingRequest::Addr { addrs } => {
+                self.process_addresses(peer_id, addrs, false).await?;
+                self.send_response(peer_id, request_id, SyncingResponse::Addr { addrs: vec![] })
+                    .await
+            }
+            SyncingRequest::AnnounceHeader { header } => {
+                self.process_header_announcement(peer_id, request_id, header).await
+            }
         }
     }
 
@@ -588,6 +1097,8 @@ where
             SyncingResponse::Blocks { blocks } => {
                 self.process_block_response(peer_id, blocks).await
             }
+            SyncingResponse::Addr { addrs } => self.process_addresses(peer_id, addrs, true).await,
+            SyncingResponse::HeaderAck => Ok(()),
         }
     }
 
@@ -600,46 +1111,16 @@ where
         match error {
             net::RequestResponseError::ConnectionClosed => {
                 self.unregister_peer(peer_id);
+                self.schedule_block_requests().await?;
             }
             net::RequestResponseError::Timeout => {
                 if let Some(request) = self.requests.remove(&request_id) {
-                    log::warn!(
-                        "outbound request {:?} for peer {:?} timed out",
-                        request_id,
-                        peer_id
+                    logging::warn!(
+                        peer_id = peer_id;
+                        "outbound request {:?} for peer timed out",
+                        request_id
                     );
-
-                    if request.retry_count == RETRY_LIMIT {
-                        log::error!(
-                            "peer {:?} failed to respond to request, close connection",
-                            peer_id
-                        );
-                        self.unregister_peer(peer_id);
-                        let (tx, rx) = oneshot::channel();
-                        self.tx_swarm
-                            .send(event::SwarmEvent::Disconnect(peer_id, tx))
-                            .await
-                            .map_err(P2pError::from)?;
-                        return rx.await.map_err(P2pError::from)?;
-                    }
-
-                    match request.request_type {
-                        RequestType::GetHeaders => {
-                            let locator =
-                                self.chainstate_handle.call(|this| this.get_locator()).await??;
-                            self.send_header_request(peer_id, locator, request.retry_count + 1)
-                                .await?;
-                        }
-                        RequestType::GetBlocks(block_ids) => {
-                            assert_eq!(block_ids.len(), 1);
-                            self.send_block_request(
-                                peer_id,
-                                block_ids.get(0).expect("block id to exist").clone(),
-                                request.retry_count + 1,
-                            )
-                            .await?;
-                        }
-                    }
+                    self.request_timed_out(request).await?;
                 }
             }
         }
@@ -648,6 +1129,44 @@ where
 
     /// Handle incoming block/header request/response
     pub async fn on_syncing_event(&mut self, event: net::SyncingEvent<T>) -> error::Result<()> {
+        match &event {
+            net::SyncingEvent::Request { peer_id, .. } if self.disconnecting.contains(peer_id) => {
+                logging::debug!(peer_id = peer_id; "ignore request from disconnecting peer");
+                return Ok(());
+            }
+            net::SyncingEvent::Response {
+                peer_id,
+                request_id,
+                ..
+            }
+            | net::SyncingEvent::Error {
+                peer_id,
+                request_id,
+                ..
+            } if self.disconnecting.contains(peer_id) => {
+                logging::debug!(peer_id = peer_id; "ignore response from disconnecting peer");
+                self.requests.remove(request_id);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if let net::SyncingEvent::Request {
+            peer_id,
+            request: message,
+            ..
+        }
+        | net::SyncingEvent::Response {
+            peer_id,
+            response: message,
+            ..
+        } = &event
+        {
+            if let Some(peer) = self.peers.get_mut(peer_id) {
+                peer.record_received(message.encoded_size());
+            }
+        }
+
         match event {
             net::SyncingEvent::Request {
                 peer_id,
@@ -658,10 +1177,10 @@ where
                         magic: _,
                     },
             } => {
-                log::debug!(
-                    "process incoming request (id {:?}) from peer {:?}",
-                    request_id,
-                    peer_id
+                logging::debug!(
+                    peer_id = peer_id;
+                    "process incoming request (id {:?}) from peer",
+                    request_id
                 );
                 self.process_request(peer_id, request_id, message).await
             }
@@ -674,11 +1193,12 @@ where
                         magic: _,
                     },
             } => {
-                log::debug!(
-                    "process incoming response (id {:?}) from peer {:?}",
-                    request_id,
-                    peer_id
+                logging::debug!(
+                    peer_id = peer_id;
+                    "process incoming response (id {:?}) from peer",
+                    request_id
                 );
+                self.requests.remove(&request_id);
                 self.process_response(peer_id, message).await
             }
             net::SyncingEvent::Error {
@@ -688,7 +1208,7 @@ where
             } => self.process_error(peer_id, request_id, error).await,
             net::SyncingEvent::Request { peer_id, .. }
             | net::SyncingEvent::Response { peer_id, .. } => {
-                log::error!("received an invalid message from peer {:?}", peer_id);
+                logging::error!(peer_id = peer_id; "received an invalid message from peer");
                 // TODO: disconnect peer and ban it
                 // TODO: send `Misbehaved` event to PeerManager
                 Err(P2pError::ProtocolError(ProtocolError::InvalidMessage))
@@ -699,10 +1219,29 @@ where
     /// Handle control-related sync event from P2P/PeerManager
     async fn on_control_event(&mut self, event: event::SyncControlEvent<T>) -> error::Result<()> {
         match event {
-            event::SyncControlEvent::Connected(peer_id) => self.register_peer(peer_id).await,
+            event::SyncControlEvent::Connected(peer_id) => {
+                self.disconnecting.remove(&peer_id);
+                self.register_peer(peer_id).await
+            }
+            event::SyncControlEvent::Disconnecting(peer_id) => {
+                self.unregister_peer(peer_id);
+                self.disconnecting.insert(peer_id);
+                self.schedule_block_requests().await
+            }
             event::SyncControlEvent::Disconnected(peer_id) => {
                 self.unregister_peer(peer_id);
-                Ok(())
+                self.disconnecting.remove(&peer_id);
+                self.schedule_block_requests().await
+            }
+            event::SyncControlEvent::GetAddr(peer_id) => self.send_addr_request(peer_id).await,
+            event::SyncControlEvent::OrphanBlock { peer_id, block_id } => {
+                self.request_orphan_ancestors(peer_id, block_id).await
+            }
+            event::SyncControlEvent::GetPeerStats(response) => response
+                .send(self.peers.values().map(|peer| peer.stats()).collect())
+                .map_err(|_| P2pError::ChannelClosed),
+            event::SyncControlEvent::GetSyncState(response) => {
+                response.send(self.state).map_err(|_| P2pError::ChannelClosed)
             }
         }
     }
@@ -710,6 +1249,26 @@ where
     /// Run SyncManager event loop
     pub async fn run(&mut self) -> error::Result<()> {
         log::info!("starting sync manager event loop");
+        let mut stall_check = tokio::time::interval(STALL_CHECK_INTERVAL);
+
+        let (tx_tip, mut rx_tip) = mpsc::channel(CHAINSTATE_EVENT_CHANNEL_SIZE);
+        let subscribe_func =
+            Arc::new(
+                move |chainstate_event: chainstate::ChainstateEvent| match chainstate_event {
+                    chainstate::ChainstateEvent::NewTip(block_id, _) => {
+                        futures::executor::block_on(async {
+                            if let Err(e) = tx_tip.send(block_id).await {
+                                log::error!("sync manager closed: {:?}", e)
+                            }
+                        });
+                    }
+                },
+            );
+        self.chainstate_handle
+            .call_mut(|this| this.subscribe_to_events(subscribe_func))
+            .await
+            .map_err(|_| P2pError::SubsystemFailure)?;
+        self.check_initial_block_download().await?;
 
         loop {
             tokio::select! {
@@ -719,9 +1278,20 @@ where
                 res = self.rx_sync.recv().fuse() => {
                     self.on_control_event(res.ok_or(P2pError::ChannelClosed)?).await.map_fatal_err()?;
                 }
+                _ = stall_check.tick() => {
+                    self.check_request_timeouts().await.map_fatal_err()?;
+                }
+                res = rx_tip.recv().fuse() => {
+                    self.check_initial_block_download().await.map_fatal_err()?;
+                    self.announce_tip(res.ok_or(P2pError::ChannelClosed)?).await.map_fatal_err()?;
+                }
             }
 
+            let state = self.state;
             self.check_state().await.map_fatal_err()?;
+            if self.state != state {
+                metrics::metrics().sync_state_changed(self.state);
+            }
         }
     }
 }
@@ -748,7 +1318,7 @@ mod tests {
     where
         T: NetworkingService,
         T::ConnectivityHandle: ConnectivityService<T>,
-        T::SyncingCodecHandle: SyncingCodecService<T>,
+        T::SyncingHandle: SyncingService<T>,
     {
         let (tx_p2p_sync, rx_p2p_sync) = mpsc::channel(16);
         let (tx_pubsub, rx_pubsub) = mpsc::channel(16);
@@ -765,6 +1335,7 @@ mod tests {
             &[],
             &[],
             Arc::clone(&config),
+            Default::default(),
             std::time::Duration::from_secs(10),
         )
         .await
@@ -845,6 +1416,114 @@ mod tests {
         assert!(mgr.peers.is_empty());
     }
 
+    // verify that a disconnecting peer is unregistered and tracked until
+    // the connection is reported closed
+    #[tokio::test]
+    async fn test_peer_disconnecting() {
+        let (mut mgr, _, _, _, _) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let peer_id = PeerId::random();
+
+        assert_eq!(
+            mgr.on_control_event(event::SyncControlEvent::Connected(peer_id)).await,
+            Ok(())
+        );
+        assert_eq!(
+            mgr.on_control_event(event::SyncControlEvent::Disconnecting(peer_id)).await,
+            Ok(())
+        );
+        assert!(mgr.peers.is_empty());
+        assert!(mgr.disconnecting.contains(&peer_id));
+
+        // the peer reconnected before the old connection was reported closed
+        assert_eq!(
+            mgr.on_control_event(event::SyncControlEvent::Connected(peer_id)).await,
+            Ok(())
+        );
+        assert_eq!(mgr.peers.len(), 1);
+        assert!(mgr.disconnecting.is_empty());
+
+        assert_eq!(
+            mgr.on_control_event(event::SyncControlEvent::Disconnecting(peer_id)).await,
+            Ok(())
+        );
+        assert_eq!(
+            mgr.on_control_event(event::SyncControlEvent::Disconnected(peer_id)).await,
+            Ok(())
+        );
+        assert!(mgr.peers.is_empty());
+        assert!(mgr.disconnecting.is_empty());
+    }
+
+    // verify that a header request that isn't answered in time is sent to another peer
+    // and that the peer that failed to answer is penalized
+    #[tokio::test]
+    async fn header_request_timeout() {
+        let (mut mgr, _, _, _, mut swarm_rx) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+
+        for peer_id in [peer1, peer2] {
+            assert_eq!(
+                mgr.on_control_event(event::SyncControlEvent::Connected(peer_id)).await,
+                Ok(())
+            );
+        }
+        mgr.peers.get_mut(&peer2).unwrap().set_state(peer::PeerSyncState::Idle);
+        mgr.requests.retain(|_, request| request.peer_id == peer1);
+
+        // nothing has timed out yet
+        assert_eq!(mgr.check_request_timeouts().await, Ok(()));
+        assert!(swarm_rx.try_recv().is_err());
+
+        for request in mgr.requests.values_mut() {
+            request.sent -= HEADER_REQUEST_TIMEOUT;
+        }
+        assert_eq!(mgr.check_request_timeouts().await, Ok(()));
+
+        assert!(matches!(
+            swarm_rx.try_recv(),
+            Ok(SwarmEvent::Misbehaved { peer_id, score })
+                if peer_id == peer1 && score == REQUEST_TIMEOUT_SCORE
+        ));
+        assert_eq!(mgr.requests.len(), 1);
+        assert!(mgr
+            .requests
+            .values()
+            .all(|request| request.peer_id == peer2 && request.retry_count == 0));
+        assert_eq!(mgr.peers[&peer1].state(), &peer::PeerSyncState::Idle);
+    }
+
+    // verify that block download with no progress is reported as stalled
+    #[tokio::test]
+    async fn sync_stalled() {
+        let (mut mgr, _, _, _, _) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let peer_id = PeerId::random();
+
+        assert_eq!(
+            mgr.on_control_event(event::SyncControlEvent::Connected(peer_id)).await,
+            Ok(())
+        );
+        mgr.peers
+            .get_mut(&peer_id)
+            .unwrap()
+            .set_state(peer::PeerSyncState::UploadingBlocks);
+        assert_eq!(mgr.check_state().await, Ok(()));
+        assert_eq!(mgr.state(), &SyncState::DownloadingBlocks);
+
+        mgr.last_progress -= SYNC_STALL_TIMEOUT;
+        assert_eq!(mgr.check_state().await, Ok(()));
+
+        let (tx, rx) = oneshot::channel();
+        assert_eq!(
+            mgr.on_control_event(event::SyncControlEvent::GetSyncState(tx)).await,
+            Ok(())
+        );
+        assert_eq!(rx.await, Ok(SyncState::Stalled));
+    }
+
     #[tokio::test]
     async fn test_request_response() {
         let (mut mgr1, mut conn1, _, _, _) =
@@ -1005,7 +1684,7 @@ mod tests {
     // which makes the request to be rejected and to time out in the sender end
     #[tokio::test]
     async fn test_request_timeout_error() {
-        let (mut mgr1, mut conn1, _, _, _) =
+        let (mut mgr1, mut conn1, _, _, _swarm_rx) =
             make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
         let (mut mgr2, mut conn2, _, _, _) =
             make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
@@ -1071,6 +1750,13 @@ mod tests {
                 }
             }
 
+            // each timeout is reported as misbehavior before the connection is closed
+            for _ in 0..4 {
+                assert!(std::matches!(
+                    swarm_rx.try_recv(),
+                    Ok(SwarmEvent::Misbehaved { .. })
+                ));
+            }
             let (_tx, rx) = oneshot::channel();
             assert!(std::matches!(
                 swarm_rx.try_recv(),
@@ -1090,4 +1776,305 @@ mod tests {
             ));
         }
     }
+
+    // verify that a new tip is announced only to synced peers that don't know of it
+    #[tokio::test]
+    async fn tip_announced_once() {
+        let (mut mgr, _, _, _, _) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+
+        for peer_id in [peer1, peer2] {
+            assert_eq!(
+                mgr.on_control_event(event::SyncControlEvent::Connected(peer_id)).await,
+                Ok(())
+            );
+        }
+        mgr.peers.get_mut(&peer1).unwrap().set_state(peer::PeerSyncState::Idle);
+        mgr.requests.clear();
+        let tip = mgr.config.genesis_block_id();
+
+        // nothing is announced during initial block download
+        assert_eq!(mgr.announce_tip(tip.clone()).await, Ok(()));
+        assert!(mgr.requests.is_empty());
+
+        mgr.state = SyncState::Idle;
+        for _ in 0..2 {
+            assert_eq!(mgr.announce_tip(tip.clone()).await, Ok(()));
+            assert_eq!(mgr.requests.len(), 1);
+            assert!(mgr.requests.values().all(|request| request.peer_id == peer1
+                && matches!(request.request_type, RequestType::Announcement)));
+        }
+        assert!(mgr.peers[&peer1].knows_block(&tip));
+        assert!(!mgr.peers[&peer2].knows_block(&tip));
+    }
+
+    // verify that the ancestors of orphan blocks are requested once per peer and that the
+    // number of orphans waiting for their ancestors is limited
+    #[tokio::test]
+    async fn orphan_ancestors_requested() {
+        let (mut mgr, _, _, _, _) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let peer_id = PeerId::random();
+        let orphans = (0..=MAX_ORPHAN_REQUESTS as u64)
+            .map(|i| Id::<Block>::new(&common::primitives::H256::from_low_u64_be(i)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            mgr.on_control_event(SyncControlEvent::Connected(peer_id)).await,
+            Ok(())
+        );
+        mgr.peers.get_mut(&peer_id).unwrap().set_state(peer::PeerSyncState::Idle);
+        mgr.requests.clear();
+
+        for block_id in &orphans {
+            assert_eq!(
+                mgr.on_control_event(SyncControlEvent::OrphanBlock {
+                    peer_id,
+                    block_id: block_id.clone(),
+                })
+                .await,
+                Ok(())
+            );
+            assert_eq!(mgr.requests.len(), 1);
+            assert!(
+                mgr.requests.values().all(|request| request.peer_id == peer_id
+                    && matches!(request.request_type, RequestType::Headers))
+            );
+        }
+        assert_eq!(mgr.peers[&peer_id].orphans(), MAX_ORPHAN_REQUESTS);
+
+        // the header response resolves the orphans
+        mgr.requests.clear();
+        assert_eq!(mgr.process_header_response(peer_id, vec![]).await, Ok(()));
+        assert_eq!(mgr.peers[&peer_id].orphans(), 0);
+
+        assert_eq!(
+            mgr.on_control_event(SyncControlEvent::OrphanBlock {
+                peer_id,
+                block_id: orphans[0].clone(),
+            })
+            .await,
+            Ok(())
+        );
+        assert_eq!(mgr.requests.len(), 1);
+
+        assert_eq!(
+            mgr.on_control_event(SyncControlEvent::OrphanBlock {
+                peer_id: PeerId::random(),
+                block_id: orphans[0].clone(),
+            })
+            .await,
+            Err(P2pError::PeerDoesntExist)
+        );
+    }
+
+    // create a block spending all outputs of `parent`, keeping one atom of each as the fee
+    fn make_block(parent: &Block, time_offset: u32) -> Block {
+        use common::{
+            chain::{
+                signature::inputsig::InputWitness, Destination, OutPointSourceId, Transaction,
+                TxInput, TxOutput,
+            },
+            primitives::Amount,
+        };
+
+        let (inputs, outputs) = parent
+            .transactions()
+            .iter()
+            .flat_map(|tx| {
+                tx.get_outputs().iter().enumerate().map(|(index, output)| {
+                    (
+                        TxInput::new(
+                            OutPointSourceId::Transaction(tx.get_id()),
+                            index as u32,
+                            InputWitness::NoSignature(None),
+                        ),
+                        TxOutput::new(
+                            (output.get_value() - Amount::from_atoms(1)).unwrap(),
+                            Destination::AnyoneCanSpend,
+                        ),
+                    )
+                })
+            })
+            .unzip();
+        Block::new(
+            vec![Transaction::new(0, inputs, outputs, 0).unwrap()],
+            Some(parent.get_id()),
+            parent.block_time() + time_offset,
+            common::chain::block::ConsensusData::None,
+        )
+        .unwrap()
+    }
+
+    // verify that headers forking off a known side chain get the height of the side chain
+    // and replace a shorter chain that is being downloaded
+    #[tokio::test]
+    async fn headers_fork_off_side_chain() {
+        let (mut mgr, _, _, _, _) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let peer_id = PeerId::random();
+        assert_eq!(
+            mgr.on_control_event(SyncControlEvent::Connected(peer_id)).await,
+            Ok(())
+        );
+
+        // main chain: genesis, a1, a2, a3; side chain: genesis, a1, b2
+        let genesis = mgr.config.genesis_block().clone();
+        let mut main_chain = vec![make_block(&genesis, 1)];
+        for _ in 0..3 {
+            main_chain.push(make_block(main_chain.last().unwrap(), 1));
+        }
+        let side_block = make_block(&main_chain[0], 2);
+        for block in main_chain[..3].iter().chain(std::iter::once(&side_block)) {
+            let block = block.clone();
+            mgr.chainstate_handle
+                .call_mut(move |this| this.process_block(block, BlockSource::Local))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(
+            mgr.chainstate_handle.call(|this| this.get_best_block_id()).await.unwrap(),
+            Ok(main_chain[2].get_id())
+        );
+
+        // a peer announces a4 on top of the main chain
+        let headers = main_chain.iter().map(|block| block.header().clone()).collect();
+        assert_eq!(mgr.process_header_response(peer_id, headers).await, Ok(()));
+        assert_eq!(mgr.downloader.target_height(), Some(BlockHeight::new(4)));
+
+        // the chain a1, b2, c3, c4, c5 is longer and replaces it
+        let mut fork = vec![main_chain[0].clone(), side_block];
+        for _ in 0..3 {
+            fork.push(make_block(fork.last().unwrap(), 1));
+        }
+        let headers = fork.iter().map(|block| block.header().clone()).collect();
+        assert_eq!(mgr.process_header_response(peer_id, headers).await, Ok(()));
+        assert_eq!(mgr.downloader.target_height(), Some(BlockHeight::new(5)));
+    }
+
+    // verify that a peer can be asked for addresses and that the received
+    // addresses are passed on to PeerManager
+    #[tokio::test]
+    async fn addr_request_response() {
+        let (mut mgr1, mut conn1, _, _, mut swarm_rx1) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let (mut mgr2, mut conn2, _, _, mut swarm_rx2) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+
+        connect_services::<Libp2pService>(&mut conn1, &mut conn2).await;
+        let peer1_id = *conn1.peer_id();
+        let peer2_id = *conn2.peer_id();
+        let addr = format!("/ip6/::1/tcp/8888/p2p/{}", PeerId::random());
+
+        let addrs = vec![addr.clone()];
+        tokio::spawn(async move {
+            match swarm_rx2.recv().await {
+                Some(SwarmEvent::GetAddresses(peer_id, response)) => {
+                    assert_eq!(peer_id, peer1_id);
+                    response.send(addrs).unwrap();
+                }
+                _ => panic!("invalid event received"),
+            }
+        });
+        tokio::spawn(async move {
+            loop {
+                let event = mgr2.handle.poll_next().await.unwrap();
+                let _ = mgr2.on_syncing_event(event).await;
+            }
+        });
+
+        mgr1.register_peer(peer2_id).await.unwrap();
+        assert_eq!(
+            mgr1.on_control_event(SyncControlEvent::GetAddr(peer2_id)).await,
+            Ok(())
+        );
+        loop {
+            tokio::select! {
+                event = mgr1.handle.poll_next() => {
+                    let _ = mgr1.on_syncing_event(event.unwrap()).await;
+                }
+                event = swarm_rx1.recv() => match event {
+                    Some(SwarmEvent::AddressesReceived { peer_id, addrs, solicited }) => {
+                        assert_eq!(peer_id, peer2_id);
+                        assert_eq!(addrs, vec![addr]);
+                        assert!(solicited);
+                        break;
+                    }
+                    _ => panic!("invalid event received"),
+                }
+            }
+        }
+    }
+
+    // verify that traffic is accounted and that a peer requesting blocks too fast is disconnected
+    #[tokio::test]
+    async fn block_request_rate_limited() {
+        let (mut mgr1, mut conn1, _, _, _) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+        let (mut mgr2, mut conn2, _, _, mut swarm_rx2) =
+            make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/")).await;
+
+        connect_services::<Libp2pService>(&mut conn1, &mut conn2).await;
+        let peer1_id = *conn1.peer_id();
+        let peer2_id = *conn2.peer_id();
+
+        // mgr2 sends a header request to peer1 and drains the block tokens of peer1
+        // so far into the future that they're not replenished during the test
+        mgr2.register_peer(peer1_id).await.unwrap();
+        assert!(mgr2.peers.get_mut(&peer1_id).unwrap().consume_block_tokens(
+            MAX_BLOCK_REQUEST_BURST,
+            Instant::now() + Duration::from_secs(3600)
+        ));
+
+        let request = SyncingRequest::GetBlocks {
+            block_ids: vec![mgr2.config.genesis_block_id()],
+        };
+        mgr1.handle
+            .send_request(
+                peer2_id,
+                Message {
+                    magic: *mgr1.config.magic_bytes(),
+                    msg: MessageType::Syncing(SyncingMessage::Request(request.clone())),
+                },
+            )
+            .await
+            .unwrap();
+
+        let event = loop {
+            if let event @ net::SyncingEvent::Request { .. } =
+                mgr2.handle.poll_next().await.unwrap()
+            {
+                break event;
+            }
+        };
+
+        let (tx, rx) = oneshot::channel();
+        assert_eq!(
+            mgr2.on_control_event(SyncControlEvent::GetPeerStats(tx)).await,
+            Ok(())
+        );
+        let stats = rx.await.unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].peer_id, peer1_id.to_string());
+        assert_eq!((stats[0].messages_sent, stats[0].messages_received), (1, 0));
+        assert!(stats[0].bytes_sent > 0);
+
+        tokio::spawn(async move {
+            match swarm_rx2.recv().await {
+                Some(SwarmEvent::Disconnect(peer_id, response)) => {
+                    assert_eq!(peer_id, peer1_id);
+                    response.send(Ok(())).unwrap();
+                }
+                _ => panic!("invalid event received"),
+            }
+        });
+        assert_eq!(
+            mgr2.on_syncing_event(event).await,
+            Err(P2pError::ProtocolError(ProtocolError::RateLimitExceeded))
+        );
+        assert!(mgr2.peers.is_empty());
+    }
 }
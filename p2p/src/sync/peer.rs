@@ -14,13 +14,15 @@
 // limitations under the License.
 //
 // Author(s): A. Altonen
-use crate::{error, net::NetworkingService, P2pError};
+use crate::{event, net::NetworkingService, ratelimit::TokenBucket};
 use common::{
     chain::block::{Block, BlockHeader},
-    primitives::{Id, Idable},
+    primitives::Id,
+};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    time::Instant,
 };
-use logging::log;
-use std::collections::VecDeque;
 
 /// State of the peer
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -29,7 +31,7 @@ pub enum PeerSyncState {
     Unknown,
 
     /// Peer is uploading blocks to local node
-    UploadingBlocks(Id<Block>),
+    UploadingBlocks,
 
     /// Peer is uploading headers to local node
     UploadingHeaders,
@@ -44,7 +46,7 @@ where
     T: NetworkingService,
 {
     /// Unique peer ID
-    _peer_id: T::PeerId,
+    peer_id: T::PeerId,
 
     /// State of the peer
     state: PeerSyncState,
@@ -53,59 +55,42 @@ where
     /// Used to verify header response and pick unknown headers
     locator: Vec<BlockHeader>,
 
-    /// List of block headers indicating which blocks
-    /// still need to be downloaded from the remote peer
-    work: VecDeque<BlockHeader>,
+    /// Number of blocks the peer is allowed to request
+    block_tokens: TokenBucket,
+
+    /// Traffic exchanged with the peer
+    stats: event::PeerStats,
+
+    /// Blocks the peer is known to have, either because it sent or announced them
+    /// to the local node or because the local node announced them to the peer
+    known_blocks: BTreeSet<Id<Block>>,
+
+    /// Insertion order of `known_blocks`, used to forget the oldest blocks first
+    known_blocks_order: VecDeque<Id<Block>>,
+
+    /// Orphan blocks received from the peer whose ancestors have been requested from it
+    orphans: BTreeSet<Id<Block>>,
 }
 
 impl<T> PeerContext<T>
 where
     T: NetworkingService,
 {
-    pub fn new(_peer_id: T::PeerId, locator: Vec<BlockHeader>) -> Self {
+    pub fn new(peer_id: T::PeerId, locator: Vec<BlockHeader>) -> Self {
         Self {
-            _peer_id,
+            peer_id,
             locator,
             state: PeerSyncState::Unknown,
-            work: VecDeque::new(),
-        }
-    }
-
-    pub fn register_header_response(&mut self, headers: &[BlockHeader]) {
-        self.state = PeerSyncState::Idle;
-        self.work = VecDeque::from(headers.to_vec());
-    }
-
-    pub fn get_header_for_download(&mut self) -> Option<BlockHeader> {
-        self.get_next_block()
-    }
-
-    pub fn register_block_response(
-        &mut self,
-        header: &BlockHeader,
-    ) -> error::Result<Option<BlockHeader>> {
-        match &self.state {
-            PeerSyncState::UploadingBlocks(expected) => {
-                if expected != &header.get_id() {
-                    log::error!(
-                        "peer sent us the wrong header, expected {:?}, got {:?}",
-                        expected,
-                        header
-                    );
-                    return Err(P2pError::InvalidData);
-                }
-            }
-            _ => {
-                log::error!("received a header from peer while not expecting it");
-                return Err(P2pError::InvalidData);
-            }
+            block_tokens: TokenBucket::new(
+                super::MAX_BLOCK_REQUEST_BURST as f64,
+                super::BLOCK_REQUEST_RATE_PER_SEC,
+                Instant::now(),
+            ),
+            stats: Default::default(),
+            known_blocks: BTreeSet::new(),
+            known_blocks_order: VecDeque::new(),
+            orphans: BTreeSet::new(),
         }
-
-        Ok(self.get_next_block())
-    }
-
-    fn get_next_block(&mut self) -> Option<BlockHeader> {
-        self.work.pop_front()
     }
 
     /// Set peer state
@@ -125,13 +110,81 @@ where
     pub fn locator(&self) -> &Vec<BlockHeader> {
         &self.locator
     }
+
+    /// Consume tokens for `count` requested blocks, returns `false` if the peer
+    /// has exceeded its block request rate
+    pub fn consume_block_tokens(&mut self, count: usize, now: Instant) -> bool {
+        self.block_tokens.try_consume(count, now)
+    }
+
+    /// Account a message of `size` bytes that was sent to the peer
+    pub fn record_sent(&mut self, size: usize) {
+        self.stats.bytes_sent += size as u64;
+        self.stats.messages_sent += 1;
+    }
+
+    /// Account a message of `size` bytes that was received from the peer
+    pub fn record_received(&mut self, size: usize) {
+        self.stats.bytes_received += size as u64;
+        self.stats.messages_received += 1;
+    }
+
+    /// Remember that the peer has the block
+    ///
+    /// At most [`super::MAX_KNOWN_BLOCKS`] blocks are remembered, the oldest ones are forgotten.
+    pub fn add_known_block(&mut self, block_id: Id<Block>) {
+        if !self.known_blocks.insert(block_id.clone()) {
+            return;
+        }
+        self.known_blocks_order.push_back(block_id);
+
+        if self.known_blocks_order.len() > super::MAX_KNOWN_BLOCKS {
+            if let Some(oldest) = self.known_blocks_order.pop_front() {
+                self.known_blocks.remove(&oldest);
+            }
+        }
+    }
+
+    /// Check whether the peer is known to have the block
+    pub fn knows_block(&self, block_id: &Id<Block>) -> bool {
+        self.known_blocks.contains(block_id)
+    }
+
+    /// Remember an orphan block whose ancestors are requested from the peer
+    ///
+    /// Returns `false` if the peer already has [`super::MAX_ORPHAN_REQUESTS`] orphan blocks
+    /// waiting for their ancestors.
+    pub fn add_orphan(&mut self, block_id: Id<Block>) -> bool {
+        if self.orphans.len() >= super::MAX_ORPHAN_REQUESTS && !self.orphans.contains(&block_id) {
+            return false;
+        }
+        self.orphans.insert(block_id);
+        true
+    }
+
+    /// Number of orphan blocks waiting for their ancestors to be received from the peer
+    pub fn orphans(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Forget the orphan blocks, the headers of their ancestors have been received
+    pub fn clear_orphans(&mut self) {
+        self.orphans.clear();
+    }
+
+    /// Get traffic statistics of the peer
+    pub fn stats(&self) -> event::PeerStats {
+        event::PeerStats {
+            peer_id: self.peer_id.to_string(),
+            ..self.stats.clone()
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::net::mock::MockService;
-    use common::chain::block::consensus_data::ConsensusData;
     use std::net::SocketAddr;
 
     fn new_mock_peersyncstate() -> PeerContext<MockService> {
@@ -148,11 +201,59 @@ mod tests {
     #[test]
     fn test_set_state() {
         let mut peer = new_mock_peersyncstate();
-        let header =
-            Block::new(vec![], None, 1337u32, ConsensusData::None).unwrap().header().clone();
 
         assert_eq!(peer.state, PeerSyncState::Unknown);
-        peer.set_state(PeerSyncState::UploadingBlocks(header.get_id()));
-        assert_eq!(peer.state, PeerSyncState::UploadingBlocks(header.get_id()));
+        peer.set_state(PeerSyncState::UploadingBlocks);
+        assert_eq!(peer.state, PeerSyncState::UploadingBlocks);
+    }
+
+    #[test]
+    fn traffic_accounted() {
+        let mut peer = new_mock_peersyncstate();
+
+        peer.record_sent(100);
+        peer.record_sent(20);
+        peer.record_received(7);
+
+        let stats = peer.stats();
+        assert_eq!(stats.peer_id, peer.peer_id.to_string());
+        assert_eq!((stats.bytes_sent, stats.messages_sent), (120, 2));
+        assert_eq!((stats.bytes_received, stats.messages_received), (7, 1));
+    }
+
+    #[test]
+    fn known_blocks_bounded() {
+        let mut peer = new_mock_peersyncstate();
+        let ids = (0..=super::super::MAX_KNOWN_BLOCKS as u64)
+            .map(|i| Id::<Block>::new(&common::primitives::H256::from_low_u64_be(i)))
+            .collect::<Vec<_>>();
+
+        for id in &ids {
+            peer.add_known_block(id.clone());
+        }
+        peer.add_known_block(ids[1].clone());
+
+        assert!(!peer.knows_block(&ids[0]));
+        assert!(ids[1..].iter().all(|id| peer.knows_block(id)));
+        assert_eq!(peer.known_blocks.len(), super::super::MAX_KNOWN_BLOCKS);
+    }
+
+    #[test]
+    fn orphans_bounded() {
+        let mut peer = new_mock_peersyncstate();
+        let ids = (0..=super::super::MAX_ORPHAN_REQUESTS as u64)
+            .map(|i| Id::<Block>::new(&common::primitives::H256::from_low_u64_be(i)))
+            .collect::<Vec<_>>();
+
+        for id in &ids[1..] {
+            assert!(peer.add_orphan(id.clone()));
+        }
+        assert!(!peer.add_orphan(ids[0].clone()));
+        assert!(peer.add_orphan(ids[1].clone()));
+        assert_eq!(peer.orphans(), super::super::MAX_ORPHAN_REQUESTS);
+
+        peer.clear_orphans();
+        assert_eq!(peer.orphans(), 0);
+        assert!(peer.add_orphan(ids[0].clone()));
     }
 }
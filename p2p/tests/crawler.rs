@@ -0,0 +1,149 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate test_utils;
+
+use common::chain::{config::ChainConfig, services::Services};
+use p2p::{
+    config::P2pConfig,
+    crawler::{Crawler, CrawlerConfig},
+    message::{Message, MessageType, SyncingMessage, SyncingRequest, SyncingResponse},
+    net::{
+        mock::{MockConnectivityHandle, MockPubSubHandle, MockService, MockSyncingHandle},
+        ConnectivityService, NetworkingService, SyncingEvent, SyncingService,
+    },
+};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start a node that answers the address requests with `addrs`, returns its address
+async fn start_node(
+    config: Arc<ChainConfig>,
+    p2p_config: P2pConfig,
+    addrs: Vec<String>,
+) -> SocketAddr {
+    let (conn, pubsub, sync) = MockService::start(
+        test_utils::make_address("[::1]:"),
+        &[],
+        &[],
+        Arc::clone(&config),
+        Arc::new(p2p_config),
+        TIMEOUT,
+    )
+    .await
+    .unwrap();
+    let addr = *conn.local_addr();
+    tokio::spawn(serve_addresses(config, conn, pubsub, sync, addrs));
+    addr
+}
+
+async fn serve_addresses(
+    config: Arc<ChainConfig>,
+    mut conn: MockConnectivityHandle<MockService>,
+    _pubsub: MockPubSubHandle<MockService>,
+    mut sync: MockSyncingHandle<MockService>,
+    addrs: Vec<String>,
+) {
+    loop {
+        tokio::select! {
+            event = conn.poll_next() => {
+                event.unwrap();
+            }
+            event = sync.poll_next() => {
+                let (request_id, request) = match event.unwrap() {
+                    SyncingEvent::Request { request_id, request, .. } => (request_id, request),
+                    _ => continue,
+                };
+                if request.msg
+                    != MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetAddr))
+                {
+                    continue;
+                }
+
+                let response = Message {
+                    magic: *config.magic_bytes(),
+                    msg: MessageType::Syncing(SyncingMessage::Response(SyncingResponse::Addr {
+                        addrs: addrs.clone(),
+                    })),
+                };
+                sync.send_response(request_id, response).await.unwrap();
+            }
+        }
+    }
+}
+
+async fn start_crawler(config: Arc<ChainConfig>) -> Crawler<MockService> {
+    Crawler::start(
+        config,
+        Default::default(),
+        CrawlerConfig::default(),
+        test_utils::make_address("[::1]:"),
+        TIMEOUT,
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn crawl_follows_addresses() {
+    let config = Arc::new(common::chain::config::create_mainnet());
+    let unreachable: SocketAddr = test_utils::make_address("[::1]:");
+    let node2 = start_node(
+        Arc::clone(&config),
+        Default::default(),
+        vec![unreachable.to_string()],
+    )
+    .await;
+    let node1 = start_node(
+        Arc::clone(&config),
+        Default::default(),
+        vec![node2.to_string()],
+    )
+    .await;
+
+    let mut crawler = start_crawler(Arc::clone(&config)).await;
+    assert!(crawler.add_address(node1));
+    assert!(!crawler.add_address(node1));
+    assert_eq!(crawler.crawl().await.unwrap(), 3);
+
+    let mut good = crawler.good_addresses();
+    good.sort();
+    let mut expected = vec![node1, node2];
+    expected.sort();
+    assert_eq!(good, expected);
+
+    let (_, info) = crawler.nodes().find(|(addr, _)| **addr == unreachable).unwrap();
+    assert!(!info.good);
+    assert_eq!(info.failures, 1);
+
+    // nothing is due until the good nodes are recrawled or the failed node is retried
+    assert_eq!(crawler.crawl().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn nodes_without_required_services_are_not_good() {
+    let config = Arc::new(common::chain::config::create_mainnet());
+    let node_p2p_config = P2pConfig {
+        services: Services::HEADERS,
+        ..Default::default()
+    };
+    let node = start_node(Arc::clone(&config), node_p2p_config, vec![]).await;
+
+    let mut crawler = start_crawler(config).await;
+    crawler.add_address(node);
+    assert_eq!(crawler.crawl().await.unwrap(), 1);
+    assert!(crawler.good_addresses().is_empty());
+}
@@ -43,6 +43,7 @@ async fn test_libp2p_gossipsub() {
         &[],
         &[PubSubTopic::Blocks],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
@@ -53,6 +54,7 @@ async fn test_libp2p_gossipsub() {
         &[],
         &[PubSubTopic::Blocks],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
@@ -158,6 +160,7 @@ async fn test_libp2p_gossipsub_3_peers() {
         &[],
         &[PubSubTopic::Blocks],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
@@ -171,6 +174,7 @@ async fn test_libp2p_gossipsub_3_peers() {
                 &[],
                 &[PubSubTopic::Blocks],
                 Arc::clone(&config),
+                Default::default(),
                 std::time::Duration::from_secs(10),
             )
             .await
@@ -299,6 +303,7 @@ async fn test_libp2p_gossipsub_invalid_data() {
         &[],
         &[PubSubTopic::Blocks],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
@@ -326,6 +331,7 @@ async fn test_libp2p_gossipsub_too_big_message() {
         &[],
         &[PubSubTopic::Blocks],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
@@ -336,6 +342,7 @@ async fn test_libp2p_gossipsub_too_big_message() {
         &[],
         &[PubSubTopic::Blocks],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
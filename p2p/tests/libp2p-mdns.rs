@@ -33,6 +33,7 @@ async fn test_libp2p_peer_discovery() {
         &[Libp2pDiscoveryStrategy::MulticastDns],
         &[],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
@@ -44,6 +45,7 @@ async fn test_libp2p_peer_discovery() {
         &[Libp2pDiscoveryStrategy::MulticastDns],
         &[],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
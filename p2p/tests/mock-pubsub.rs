@@ -0,0 +1,156 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate test_utils;
+
+use common::chain::block::{consensus_data::ConsensusData, Block};
+use p2p::{
+    error::P2pError,
+    message::{self, MessageType, PubSubMessage},
+    net::{
+        mock::{MockConnectivityHandle, MockPubSubHandle, MockService},
+        ConnectivityEvent, ConnectivityService, NetworkingService, PubSubEvent, PubSubService,
+        PubSubTopic, ValidationResult,
+    },
+};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+async fn make_service() -> (
+    MockConnectivityHandle<MockService>,
+    MockPubSubHandle<MockService>,
+) {
+    let (conn, pubsub, _) = MockService::start(
+        test_utils::make_address("[::1]:"),
+        &[],
+        &[PubSubTopic::Blocks],
+        Arc::new(common::chain::config::create_mainnet()),
+        Default::default(),
+        Duration::from_secs(10),
+    )
+    .await
+    .unwrap();
+
+    (conn, pubsub)
+}
+
+async fn connect_peers(
+    peer1: &mut MockConnectivityHandle<MockService>,
+    peer2: &mut MockConnectivityHandle<MockService>,
+) {
+    let (peer1_res, peer2_res) =
+        tokio::join!(peer1.connect(*peer2.local_addr()), peer2.poll_next());
+
+    assert!(peer1_res.is_ok());
+    assert!(std::matches!(
+        peer2_res,
+        Ok(ConnectivityEvent::IncomingConnection { .. })
+    ));
+}
+
+fn make_block(time: u32) -> message::Message {
+    message::Message {
+        magic: [0, 1, 2, 3],
+        msg: MessageType::PubSub(PubSubMessage::Block(
+            Block::new(vec![], None, time, ConsensusData::None).unwrap(),
+        )),
+    }
+}
+
+async fn receive_block(pubsub: &mut MockPubSubHandle<MockService>) -> (SocketAddr, u32) {
+    match pubsub.poll_next().await.unwrap() {
+        PubSubEvent::MessageReceived {
+            peer_id,
+            message:
+                message::Message {
+                    msg: MessageType::PubSub(PubSubMessage::Block(block)),
+                    ..
+                },
+            message_id,
+        } => {
+            pubsub
+                .report_validation_result(peer_id, message_id, ValidationResult::Accept)
+                .await
+                .unwrap();
+            (peer_id, block.block_time())
+        }
+        _ => panic!("invalid message received"),
+    }
+}
+
+// verify that a published message is flooded through the network and not sent back
+#[tokio::test]
+async fn test_mock_pubsub_flood() {
+    let (mut conn1, mut pubsub1) = make_service().await;
+    let (mut conn2, mut pubsub2) = make_service().await;
+    let (mut conn3, mut pubsub3) = make_service().await;
+
+    assert_eq!(
+        pubsub1.publish(make_block(1336)).await,
+        Err(P2pError::NoPeers)
+    );
+
+    // peer1 <-> peer2 <-> peer3
+    connect_peers(&mut conn1, &mut conn2).await;
+    connect_peers(&mut conn2, &mut conn3).await;
+
+    pubsub1.publish(make_block(1337)).await.unwrap();
+    assert_eq!(
+        receive_block(&mut pubsub2).await,
+        (*conn1.local_addr(), 1337)
+    );
+    assert_eq!(
+        receive_block(&mut pubsub3).await,
+        (*conn2.local_addr(), 1337)
+    );
+
+    // the message peer3 published reaches peer1 but peer3 doesn't receive it back
+    pubsub3.publish(make_block(1338)).await.unwrap();
+    assert_eq!(receive_block(&mut pubsub2).await.1, 1338);
+    assert_eq!(receive_block(&mut pubsub1).await.1, 1338);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), pubsub3.poll_next())
+            .await
+            .is_err()
+    );
+}
+
+// verify that a rejected message is not relayed
+#[tokio::test]
+async fn test_mock_pubsub_rejected() {
+    let (mut conn1, mut pubsub1) = make_service().await;
+    let (mut conn2, mut pubsub2) = make_service().await;
+    let (mut conn3, mut pubsub3) = make_service().await;
+
+    connect_peers(&mut conn1, &mut conn2).await;
+    connect_peers(&mut conn2, &mut conn3).await;
+
+    pubsub1.publish(make_block(1337)).await.unwrap();
+    match pubsub2.poll_next().await.unwrap() {
+        PubSubEvent::MessageReceived {
+            peer_id,
+            message_id,
+            ..
+        } => pubsub2
+            .report_validation_result(peer_id, message_id, ValidationResult::Reject)
+            .await
+            .unwrap(),
+    }
+
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), pubsub3.poll_next())
+            .await
+            .is_err()
+    );
+}
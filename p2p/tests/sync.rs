@@ -26,7 +26,7 @@ use p2p::{
     message::{Message, MessageType, SyncingMessage, SyncingRequest, SyncingResponse},
     net::{
         self, libp2p::Libp2pService, ConnectivityEvent, ConnectivityService, NetworkingService,
-        SyncingCodecService,
+        SyncingService,
     },
     sync::SyncManager,
     sync::SyncState,
@@ -52,7 +52,7 @@ async fn make_sync_manager<T>(
 where
     T: NetworkingService,
     T::ConnectivityHandle: ConnectivityService<T>,
-    T::SyncingCodecHandle: SyncingCodecService<T>,
+    T::SyncingHandle: SyncingService<T>,
 {
     let (tx_p2p_sync, rx_p2p_sync) = mpsc::channel(16);
     let (tx_pubsub, rx_pubsub) = mpsc::channel(16);
@@ -64,6 +64,7 @@ where
         &[],
         &[],
         Arc::clone(&config),
+        Default::default(),
         std::time::Duration::from_secs(10),
     )
     .await
@@ -155,7 +156,7 @@ async fn process_header_request<T>(
 ) -> Result<(), P2pError>
 where
     T: NetworkingService,
-    T::SyncingCodecHandle: SyncingCodecService<T>,
+    T::SyncingHandle: SyncingService<T>,
 {
     match mgr.handle_mut().poll_next().await.unwrap() {
         net::SyncingEvent::Request {
@@ -191,7 +192,7 @@ where
 async fn advance_mgr_state<T>(mgr: &mut SyncManager<T>) -> Result<(), P2pError>
 where
     T: NetworkingService,
-    T::SyncingCodecHandle: SyncingCodecService<T>,
+    T::SyncingHandle: SyncingService<T>,
 {
     let event = mgr.handle_mut().poll_next().await.unwrap();
     mgr.on_syncing_event(event).await.unwrap();
@@ -1514,3 +1515,105 @@ async fn test_connect_disconnect_resyncing() {
     assert!(same_tip(&mgr1_handle, &mgr2_handle).await);
     assert_eq!(mgr1.state(), &SyncState::Idle);
 }
+
+// local and remote nodes are in sync and remote announces a new block with its header
+//
+// the local node acknowledges the announcement and downloads the block from the remote
+#[tokio::test]
+async fn block_announced_with_header() {
+    let config = Arc::new(common::chain::config::create_unit_test_config());
+    let (handle1, handle2) = init_chainstate_2(Arc::clone(&config), 8).await;
+    let mgr1_handle = handle1.clone();
+    let mgr2_handle = handle2.clone();
+
+    let (mut mgr1, mut conn1, _, _pubsub, _) =
+        make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), handle1)
+            .await;
+    let (mut mgr2, mut conn2, _, _, _) =
+        make_sync_manager::<Libp2pService>(test_utils::make_address("/ip6/::1/tcp/"), handle2)
+            .await;
+
+    connect_services::<Libp2pService>(&mut conn1, &mut conn2).await;
+    assert_eq!(mgr1.register_peer(*conn2.peer_id()).await, Ok(()));
+
+    let (res1, res2) = tokio::join!(
+        process_header_request(&mut mgr2, &mgr2_handle),
+        advance_mgr_state(&mut mgr1)
+    );
+    assert_eq!(res1, Ok(()));
+    assert_eq!(res2, Ok(()));
+    assert_eq!(mgr1.state(), &SyncState::Idle);
+
+    // remote adds a new block and announces it
+    let id = get_tip(&mgr2_handle).await;
+    let parent = mgr2_handle.call(move |this| this.get_block(id)).await.unwrap().unwrap();
+    let block = util::create_block(Arc::clone(&config), &parent.unwrap());
+    util::import_blocks(&mgr2_handle, vec![block.clone()]).await;
+
+    mgr2.handle_mut()
+        .send_request(
+            *conn1.peer_id(),
+            Message {
+                magic: *config.magic_bytes(),
+                msg: MessageType::Syncing(SyncingMessage::Request(
+                    SyncingRequest::AnnounceHeader {
+                        header: block.header().clone(),
+                    },
+                )),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(advance_mgr_state(&mut mgr1).await, Ok(()));
+
+    let mut acked = false;
+    let mut requested = false;
+
+    while !(acked && requested) {
+        match mgr2.handle_mut().poll_next().await.unwrap() {
+            net::SyncingEvent::Response {
+                response:
+                    Message {
+                        msg:
+                            MessageType::Syncing(SyncingMessage::Response(SyncingResponse::HeaderAck)),
+                        ..
+                    },
+                ..
+            } => acked = true,
+            net::SyncingEvent::Request {
+                request_id,
+                request:
+                    Message {
+                        msg:
+                            MessageType::Syncing(SyncingMessage::Request(SyncingRequest::GetBlocks {
+                                block_ids,
+                            })),
+                        magic,
+                    },
+                ..
+            } => {
+                assert_eq!(block_ids, vec![block.get_id()]);
+                requested = true;
+
+                mgr2.handle_mut()
+                    .send_response(
+                        request_id,
+                        Message {
+                            magic,
+                            msg: MessageType::Syncing(SyncingMessage::Response(
+                                SyncingResponse::Blocks {
+                                    blocks: vec![block.clone()],
+                                },
+                            )),
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+            msg => panic!("invalid message received: {:?}", msg),
+        }
+    }
+
+    assert_eq!(advance_mgr_state(&mut mgr1).await, Ok(()));
+    assert!(same_tip(&mgr1_handle, &mgr2_handle).await);
+}
@@ -0,0 +1,102 @@
+//! Structured errors of the RPC methods
+
+use jsonrpsee::types::{
+    error::{CallError, INVALID_PARAMS_CODE},
+    ErrorObject,
+};
+
+use crate::Error;
+
+/// Error codes of the node's RPC errors
+///
+/// Errors reported by the node's methods carry a stable numeric code that clients can branch
+/// on, and [`ErrorData`](crate::ErrorData) naming the exact error. The standard JSON-RPC errors use the
+/// -32768..=-32000 range, the codes defined by the node are grouped by the subsystem reporting
+/// the error:
+///
+/// | Codes         | Subsystem                          |
+/// |---------------|------------------------------------|
+/// | -999..=-900   | RPC server, the codes defined here |
+/// | -1999..=-1000 | chainstate                         |
+/// | -2999..=-2000 | mempool, reserved                  |
+/// | -3999..=-3000 | p2p                                |
+/// | -4999..=-4000 | wallet                             |
+///
+/// Once assigned, a code keeps its meaning.
+pub mod error_code {
+    /// The subsystem serving the call has shut down
+    pub const SUBSYSTEM_UNAVAILABLE: i32 = -900;
+}
+
+/// Machine-readable data attached to the errors reported with the node's codes
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ErrorData {
+    /// Name of the error in snake case, such as `block_already_exists`
+    pub kind: &'static str,
+}
+
+/// Error with one of the node's codes, `kind` tells apart errors reported with the same code
+pub fn error(code: i32, message: impl ToString, kind: &'static str) -> Error {
+    Error::Call(CallError::Custom(ErrorObject::owned(
+        code,
+        message.to_string(),
+        Some(ErrorData { kind }),
+    )))
+}
+
+/// Error reporting invalid method parameters, with the standard JSON-RPC code
+pub fn invalid_params(message: impl ToString) -> Error {
+    Error::Call(CallError::Custom(ErrorObject::owned(
+        INVALID_PARAMS_CODE,
+        message.to_string(),
+        None::<()>,
+    )))
+}
+
+/// Error reporting that a call couldn't be dispatched to the subsystem serving it
+pub fn subsystem_unavailable(e: subsystem::subsystem::CallError) -> Error {
+    let kind = match e {
+        subsystem::subsystem::CallError::SubsystemDead => "subsystem_dead",
+    };
+    error(error_code::SUBSYSTEM_UNAVAILABLE, e, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn error_object(e: Error) -> (i32, String, Option<Value>) {
+        match e {
+            Error::Call(CallError::Custom(e)) => (
+                e.code(),
+                e.message().to_owned(),
+                e.data().map(|data| serde_json::from_str(data.get()).unwrap()),
+            ),
+            e => panic!("expected a custom error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn structured_errors() {
+        assert_eq!(
+            error_object(error(-1002, "Block already exists", "block_already_exists")),
+            (
+                -1002,
+                "Block already exists".to_owned(),
+                Some(json!({ "kind": "block_already_exists" }))
+            )
+        );
+
+        assert_eq!(
+            error_object(invalid_params("Invalid verbosity: 2")),
+            (INVALID_PARAMS_CODE, "Invalid verbosity: 2".to_owned(), None)
+        );
+
+        let (code, _, data) = error_object(subsystem_unavailable(
+            subsystem::subsystem::CallError::SubsystemDead,
+        ));
+        assert_eq!(code, error_code::SUBSYSTEM_UNAVAILABLE);
+        assert_eq!(data, Some(json!({ "kind": "subsystem_dead" })));
+    }
+}
@@ -2,16 +2,34 @@ use std::net::SocketAddr;
 
 use jsonrpsee::http_server::HttpServerBuilder;
 use jsonrpsee::http_server::HttpServerHandle;
+use jsonrpsee::ws_server::WsServerBuilder;
+use jsonrpsee::ws_server::WsServerHandle;
 
 pub use jsonrpsee::core::server::rpc_module::Methods;
 pub use jsonrpsee::core::Error;
 pub use jsonrpsee::proc_macros::rpc;
+pub use jsonrpsee::PendingSubscription;
 
 use logging::log;
 
+mod error;
+pub mod rest;
+
+pub use error::{error, error_code, invalid_params, subsystem_unavailable, ErrorData};
+
 /// The Result type with RPC-specific error.
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Label of the resource claimed by expensive methods, such as those decoding whole blocks
+///
+/// A method claims it with `#[method(name = "...", resources("expensive" = 1))]`. Calls that
+/// would exceed [`Builder::with_max_expensive_calls`] units at once are rejected as busy. The
+/// REST endpoints served by such methods are limited the same way.
+pub const EXPENSIVE_CALLS: &str = "expensive";
+
+/// Default number of expensive method calls served at once
+pub const DEFAULT_MAX_EXPENSIVE_CALLS: u16 = 4;
+
 #[rpc(server, namespace = "example_server")]
 trait RpcInfo {
     #[method(name = "protocol_version")]
@@ -28,6 +46,10 @@ impl RpcInfoServer for RpcInfo {
 /// The RPC subsystem builder. Used to populate the RPC server with method handlers.
 pub struct Builder {
     address: SocketAddr,
+    ws_address: Option<SocketAddr>,
+    rest_address: Option<SocketAddr>,
+    batch_requests: bool,
+    max_expensive_calls: u16,
     methods: Methods,
 }
 
@@ -35,7 +57,14 @@ impl Builder {
     /// New builder with no methods
     pub fn new_empty(address: SocketAddr) -> Self {
         let methods = Methods::new();
-        Self { address, methods }
+        Self {
+            address,
+            ws_address: None,
+            rest_address: None,
+            batch_requests: true,
+            max_expensive_calls: DEFAULT_MAX_EXPENSIVE_CALLS,
+            methods,
+        }
     }
 
     /// New builder pre-populated with RPC info methods
@@ -43,6 +72,30 @@ impl Builder {
         Self::new_empty(address).register(RpcInfo.into_rpc())
     }
 
+    /// Also serve the methods over WebSocket, which is required for subscriptions
+    pub fn with_ws_address(mut self, ws_address: Option<SocketAddr>) -> Self {
+        self.ws_address = ws_address;
+        self
+    }
+
+    /// Also serve the read-only REST endpoints, see [`rest`]
+    pub fn with_rest_address(mut self, rest_address: Option<SocketAddr>) -> Self {
+        self.rest_address = rest_address;
+        self
+    }
+
+    /// Whether JSON-RPC batch arrays are accepted, they are by default
+    pub fn with_batch_requests(mut self, batch_requests: bool) -> Self {
+        self.batch_requests = batch_requests;
+        self
+    }
+
+    /// Number of units of [`EXPENSIVE_CALLS`] available at once on each server
+    pub fn with_max_expensive_calls(mut self, max_expensive_calls: u16) -> Self {
+        self.max_expensive_calls = max_expensive_calls;
+        self
+    }
+
     /// Add methods handlers to the RPC server
     pub fn register(mut self, methods: impl Into<Methods>) -> Self {
         self.methods.merge(methods).expect("Duplicate RPC methods");
@@ -51,7 +104,7 @@ impl Builder {
 
     /// Build the RPC server and get the RPC object
     pub async fn build(self) -> anyhow::Result<Rpc> {
-        Rpc::new(&self.address, self.methods).await
+        Rpc::new(self).await
     }
 }
 
@@ -59,19 +112,64 @@ impl Builder {
 pub struct Rpc {
     address: SocketAddr,
     handle: HttpServerHandle,
+    ws: Option<(SocketAddr, WsServerHandle)>,
+    rest: Option<rest::RestServer>,
 }
 
 impl Rpc {
-    async fn new(addr: &SocketAddr, methods: Methods) -> anyhow::Result<Self> {
-        let server = HttpServerBuilder::default().build(addr).await?;
+    async fn new(builder: Builder) -> anyhow::Result<Self> {
+        let ws = match builder.ws_address {
+            Some(ws_addr) => {
+                let server = WsServerBuilder::default()
+                    .batch_requests_supported(builder.batch_requests)
+                    .register_resource(EXPENSIVE_CALLS, builder.max_expensive_calls, 0)?
+                    .build(ws_addr)
+                    .await?;
+                let address = server.local_addr()?;
+                Some((address, server.start(builder.methods.clone())?))
+            }
+            None => None,
+        };
+
+        let rest = builder
+            .rest_address
+            .map(|rest_addr| {
+                rest::RestServer::start(
+                    &rest_addr,
+                    builder.methods.clone(),
+                    builder.max_expensive_calls,
+                )
+            })
+            .transpose()?;
+
+        let server = HttpServerBuilder::default()
+            .batch_requests_supported(builder.batch_requests)
+            .register_resource(EXPENSIVE_CALLS, builder.max_expensive_calls, 0)?
+            .build(builder.address)
+            .await?;
         let address = server.local_addr()?;
-        let handle = server.start(methods)?;
-        Ok(Self { address, handle })
+        let handle = server.start(builder.methods)?;
+        Ok(Self {
+            address,
+            handle,
+            ws,
+            rest,
+        })
     }
 
     pub fn address(&self) -> &SocketAddr {
         &self.address
     }
+
+    /// Address of the WebSocket server, if enabled
+    pub fn ws_address(&self) -> Option<&SocketAddr> {
+        self.ws.as_ref().map(|(address, _)| address)
+    }
+
+    /// Address of the REST server, if enabled
+    pub fn rest_address(&self) -> Option<&SocketAddr> {
+        self.rest.as_ref().map(rest::RestServer::address)
+    }
 }
 
 #[async_trait::async_trait]
@@ -81,15 +179,25 @@ impl subsystem::Subsystem for Rpc {
             Ok(stop) => stop.await.unwrap_or_else(|e| log::error!("RPC join error: {}", e)),
             Err(e) => log::error!("RPC stop handle acquisition failed: {}", e),
         }
+        if let Some((_, handle)) = self.ws {
+            match handle.stop() {
+                Ok(stop) => stop.await,
+                Err(e) => log::error!("RPC WebSocket stop handle acquisition failed: {}", e),
+            }
+        }
+        if let Some(rest) = self.rest {
+            rest.stop().await;
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use jsonrpsee::core::client::ClientT;
+    use jsonrpsee::core::client::{ClientT, Subscription, SubscriptionClientT};
     use jsonrpsee::http_client::HttpClientBuilder;
     use jsonrpsee::rpc_params;
+    use jsonrpsee::ws_client::WsClientBuilder;
 
     #[rpc(server, namespace = "some_subsystem")]
     pub trait SubsystemRpc {
@@ -98,10 +206,17 @@ mod tests {
 
         #[method(name = "add")]
         fn add(&self, a: u64, b: u64) -> crate::Result<u64>;
+
+        #[subscription(name = "subscribe_count", item = u64)]
+        fn subscribe_count(&self, to: u64);
+
+        #[method(name = "slow", resources("expensive" = 1))]
+        async fn slow(&self) -> crate::Result<()>;
     }
 
     pub struct SubsystemRpcImpl;
 
+    #[async_trait::async_trait]
     impl SubsystemRpcServer for SubsystemRpcImpl {
         fn name(&self) -> crate::Result<String> {
             Ok("sub1".into())
@@ -110,6 +225,19 @@ mod tests {
         fn add(&self, a: u64, b: u64) -> crate::Result<u64> {
             Ok(a + b)
         }
+
+        fn subscribe_count(&self, pending: PendingSubscription, to: u64) {
+            if let Some(mut sink) = pending.accept() {
+                tokio::spawn(async move {
+                    sink.pipe_from_stream(futures::stream::iter(1..=to)).await;
+                });
+            }
+        }
+
+        async fn slow(&self) -> crate::Result<()> {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            Ok(())
+        }
     }
 
     #[tokio::test]
@@ -134,4 +262,102 @@ mod tests {
         subsystem::Subsystem::shutdown(rpc).await;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn rpc_ws_subscription() -> anyhow::Result<()> {
+        let rpc = Builder::new("127.0.0.1:0".parse().unwrap())
+            .with_ws_address(Some("127.0.0.1:0".parse().unwrap()))
+            .register(SubsystemRpcImpl.into_rpc())
+            .build()
+            .await?;
+
+        let url = format!("ws://{}", rpc.ws_address().unwrap());
+        let client = WsClientBuilder::default().build(url).await?;
+        let response: Result<u64> = client.request("some_subsystem_add", rpc_params!(2, 5)).await;
+        assert_eq!(response.unwrap(), 7);
+
+        let mut sub: Subscription<u64> = client
+            .subscribe(
+                "some_subsystem_subscribe_count",
+                rpc_params!(3),
+                "some_subsystem_unsubscribe_count",
+            )
+            .await?;
+        for expected in 1..=3 {
+            assert_eq!(sub.next().await.unwrap()?, expected);
+        }
+
+        // Subscriptions are not available over HTTP
+        let url = format!("http://{}", rpc.address());
+        let client = HttpClientBuilder::default().build(url)?;
+        let response: Result<u64> =
+            client.request("some_subsystem_subscribe_count", rpc_params!(3)).await;
+        assert!(response.is_err());
+
+        subsystem::Subsystem::shutdown(rpc).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rpc_batch_requests() -> anyhow::Result<()> {
+        let batch = || {
+            vec![
+                ("some_subsystem_add", rpc_params!(1, 2)),
+                ("some_subsystem_add", rpc_params!(3, 4)),
+            ]
+        };
+
+        let rpc = Builder::new("127.0.0.1:0".parse().unwrap())
+            .register(SubsystemRpcImpl.into_rpc())
+            .build()
+            .await?;
+        let client = HttpClientBuilder::default().build(format!("http://{}", rpc.address()))?;
+        let response: Result<Vec<u64>> = client.batch_request(batch()).await;
+        assert_eq!(response.unwrap(), vec![3, 7]);
+        subsystem::Subsystem::shutdown(rpc).await;
+
+        let rpc = Builder::new("127.0.0.1:0".parse().unwrap())
+            .with_batch_requests(false)
+            .register(SubsystemRpcImpl.into_rpc())
+            .build()
+            .await?;
+        let client = HttpClientBuilder::default().build(format!("http://{}", rpc.address()))?;
+        let response: Result<Vec<u64>> = client.batch_request(batch()).await;
+        assert!(response.is_err());
+        subsystem::Subsystem::shutdown(rpc).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rpc_expensive_calls_limited() -> anyhow::Result<()> {
+        let rpc = Builder::new("127.0.0.1:0".parse().unwrap())
+            .with_max_expensive_calls(1)
+            .register(SubsystemRpcImpl.into_rpc())
+            .build()
+            .await?;
+        let url = format!("http://{}", rpc.address());
+        let client1 = HttpClientBuilder::default().build(&url)?;
+        let client2 = HttpClientBuilder::default().build(&url)?;
+
+        let (response1, response2): (Result<()>, Result<()>) = tokio::join!(
+            client1.request("some_subsystem_slow", rpc_params!()),
+            async {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                client2.request("some_subsystem_slow", rpc_params!()).await
+            }
+        );
+        assert!(response1.is_ok());
+        assert!(response2.is_err());
+
+        // Cheap methods are not limited
+        let (response1, response2): (Result<()>, Result<u64>) = tokio::join!(
+            client1.request("some_subsystem_slow", rpc_params!()),
+            client2.request("some_subsystem_add", rpc_params!(2, 5))
+        );
+        assert!(response1.is_ok());
+        assert_eq!(response2.unwrap(), 7);
+
+        subsystem::Subsystem::shutdown(rpc).await;
+        Ok(())
+    }
 }
@@ -0,0 +1,362 @@
+//! Read-only REST interface
+//!
+//! The endpoints are served by calling the registered RPC methods, so they return the same data
+//! as the corresponding RPC calls:
+//!
+//! - `GET /block/<id>` calls `chainstate_block`
+//! - `GET /tx/<id>` calls `chainstate_transaction`
+//! - `GET /chain/tip` calls `chainstate_best_block_id` and `chainstate_best_block_height`
+//!
+//! Blocks and transactions are returned as JSON by default. Appending `.bin` to the path returns
+//! their SCALE encoding and appending `.hex` returns it hex-encoded.
+//!
+//! Block and transaction requests count against the same
+//! [`EXPENSIVE_CALLS`](crate::EXPENSIVE_CALLS) limit as the corresponding RPC methods. Requests
+//! over the limit get a `503 Service Unavailable` response.
+
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use jsonrpsee::core::server::rpc_module::Methods;
+use jsonrpsee::types::error::{CallError, INVALID_PARAMS_CODE};
+use logging::log;
+use serde_json::{json, Value};
+use tokio::{
+    sync::{oneshot, Semaphore},
+    task::JoinHandle,
+};
+
+use crate::Error;
+
+/// Encoding of a block or transaction in a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Binary,
+    Hex,
+}
+
+impl Format {
+    /// Split the format extension from the last path segment
+    fn from_path(segment: &str) -> (&str, Self) {
+        if let Some(id) = segment.strip_suffix(".bin") {
+            (id, Self::Binary)
+        } else if let Some(id) = segment.strip_suffix(".hex") {
+            (id, Self::Hex)
+        } else {
+            (segment.strip_suffix(".json").unwrap_or(segment), Self::Json)
+        }
+    }
+
+    /// Verbosity passed to the RPC method
+    fn verbosity(self) -> u8 {
+        match self {
+            Self::Json => 1,
+            Self::Binary | Self::Hex => 0,
+        }
+    }
+}
+
+/// Running REST server
+pub struct RestServer {
+    address: SocketAddr,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl RestServer {
+    /// Start serving the REST endpoints using the given RPC methods
+    ///
+    /// At most `max_expensive_calls` block or transaction requests are served at once.
+    pub fn start(
+        address: &SocketAddr,
+        methods: Methods,
+        max_expensive_calls: u16,
+    ) -> anyhow::Result<Self> {
+        let expensive_calls = Arc::new(Semaphore::new(max_expensive_calls.into()));
+        let make_service = make_service_fn(move |_| {
+            let methods = methods.clone();
+            let expensive_calls = Arc::clone(&expensive_calls);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let methods = methods.clone();
+                    let expensive_calls = Arc::clone(&expensive_calls);
+                    async move {
+                        Ok::<_, Infallible>(
+                            handle_request(&methods, &expensive_calls, request).await,
+                        )
+                    }
+                }))
+            }
+        });
+
+        let server = hyper::Server::try_bind(address)?.serve(make_service);
+        let address = server.local_addr();
+        let (stop, stopped) = oneshot::channel();
+        let server = server.with_graceful_shutdown(async {
+            let _ = stopped.await;
+        });
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!("REST server error: {}", e);
+            }
+        });
+
+        Ok(Self {
+            address,
+            stop,
+            task,
+        })
+    }
+
+    pub fn address(&self) -> &SocketAddr {
+        &self.address
+    }
+
+    /// Stop the server and wait until it has shut down
+    pub async fn stop(self) {
+        let _ = self.stop.send(());
+        self.task.await.unwrap_or_else(|e| log::error!("REST join error: {}", e));
+    }
+}
+
+async fn handle_request(
+    methods: &Methods,
+    expensive_calls: &Semaphore,
+    request: Request<Body>,
+) -> Response<Body> {
+    if request.method() != Method::GET {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported");
+    }
+
+    let segments = request.uri().path().trim_matches('/').split('/').collect::<Vec<_>>();
+
+    // The methods behind these endpoints claim `EXPENSIVE_CALLS`, which `Methods::call` ignores
+    let _permit = match segments.as_slice() {
+        ["block", _] | ["tx", _] => match expensive_calls.try_acquire() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                return error_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Server is busy, try again later",
+                )
+            }
+        },
+        _ => None,
+    };
+
+    let result = match segments.as_slice() {
+        ["block", id] => encoded_item(methods, "chainstate_block", id).await,
+        ["tx", id] => encoded_item(methods, "chainstate_transaction", id).await,
+        ["chain", "tip"] => chain_tip(methods).await,
+        _ => return error_response(StatusCode::NOT_FOUND, "Unknown endpoint"),
+    };
+
+    result.unwrap_or_else(|e| match e {
+        Error::Call(CallError::Custom(e)) if e.code() == INVALID_PARAMS_CODE => {
+            error_response(StatusCode::BAD_REQUEST, e.message())
+        }
+        e => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    })
+}
+
+/// Block or transaction, encoded as requested by the path
+async fn encoded_item(
+    methods: &Methods,
+    method: &str,
+    segment: &str,
+) -> crate::Result<Response<Body>> {
+    let (id, format) = Format::from_path(segment);
+    let item: Value = methods.call(method, (id, format.verbosity())).await?;
+
+    let response = match (format, item) {
+        (_, Value::Null) => error_response(StatusCode::NOT_FOUND, "Not found"),
+        (Format::Json, item) => json_response(&item),
+        (Format::Hex, Value::String(hex)) => response(StatusCode::OK, "text/plain", hex.into()),
+        (Format::Binary, Value::String(hex)) => {
+            let data = hex::decode(hex).map_err(|e| Error::Custom(e.to_string()))?;
+            response(StatusCode::OK, "application/octet-stream", data.into())
+        }
+        (_, item) => return Err(Error::Custom(format!("Unexpected encoding: {}", item))),
+    };
+    Ok(response)
+}
+
+async fn chain_tip(methods: &Methods) -> crate::Result<Response<Body>> {
+    let id: Value = methods.call("chainstate_best_block_id", [(); 0]).await?;
+    let height: Value = methods.call("chainstate_best_block_height", [(); 0]).await?;
+    Ok(json_response(&json!({ "id": id, "height": height })))
+}
+
+fn json_response(value: &Value) -> Response<Body> {
+    response(StatusCode::OK, "application/json", value.to_string().into())
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    let body = json!({ "error": message }).to_string();
+    response(status, "application/json", body.into())
+}
+
+fn response(status: StatusCode, content_type: &'static str, body: Body) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(body)
+        .expect("Response with a valid status and header")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc;
+
+    #[rpc(server, namespace = "chainstate")]
+    trait FakeChainstateRpc {
+        #[method(name = "best_block_id")]
+        fn best_block_id(&self) -> crate::Result<String>;
+
+        #[method(name = "best_block_height")]
+        fn best_block_height(&self) -> crate::Result<u64>;
+
+        #[method(name = "block")]
+        fn block(&self, block_id: String, verbosity: u8) -> crate::Result<Option<Value>>;
+    }
+
+    struct FakeChainstate;
+
+    impl FakeChainstateRpcServer for FakeChainstate {
+        fn best_block_id(&self) -> crate::Result<String> {
+            Ok("beef".into())
+        }
+
+        fn best_block_height(&self) -> crate::Result<u64> {
+            Ok(7)
+        }
+
+        fn block(&self, block_id: String, verbosity: u8) -> crate::Result<Option<Value>> {
+            Ok((block_id == "beef").then(|| match verbosity {
+                0 => Value::from("0102ff"),
+                _ => json!({ "id": block_id }),
+            }))
+        }
+    }
+
+    async fn get(server: &RestServer, path: &str) -> (StatusCode, String, Vec<u8>) {
+        let uri = format!("http://{}{}", server.address(), path).parse().unwrap();
+        let response = hyper::Client::new().get(uri).await.unwrap();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_owned();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, content_type, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn rest_endpoints() {
+        let server = RestServer::start(
+            &"127.0.0.1:0".parse().unwrap(),
+            FakeChainstate.into_rpc().into(),
+            crate::DEFAULT_MAX_EXPENSIVE_CALLS,
+        )
+        .unwrap();
+
+        let (status, _, body) = get(&server, "/chain/tip").await;
+        assert_eq!(status, StatusCode::OK);
+        let tip: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tip, json!({ "id": "beef", "height": 7 }));
+
+        let (status, content_type, body) = get(&server, "/block/beef").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({ "id": "beef" })
+        );
+
+        let (status, content_type, body) = get(&server, "/block/beef.bin").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/octet-stream");
+        assert_eq!(body, vec![0x01, 0x02, 0xff]);
+
+        let (status, _, body) = get(&server, "/block/beef.hex").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"0102ff");
+
+        let (status, _, _) = get(&server, "/block/dead").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _, _) = get(&server, "/block/beef.json/1").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _, _) = get(&server, "/mempool").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // The fake chainstate has no transaction method
+        let (status, _, _) = get(&server, "/tx/beef").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        server.stop().await;
+    }
+
+    #[rpc(server, namespace = "chainstate")]
+    trait SlowChainstateRpc {
+        #[method(name = "best_block_id")]
+        fn best_block_id(&self) -> crate::Result<String>;
+
+        #[method(name = "best_block_height")]
+        fn best_block_height(&self) -> crate::Result<u64>;
+
+        #[method(name = "block", resources("expensive" = 1))]
+        async fn block(&self, block_id: String, verbosity: u8) -> crate::Result<Option<Value>>;
+    }
+
+    struct SlowChainstate;
+
+    #[async_trait::async_trait]
+    impl SlowChainstateRpcServer for SlowChainstate {
+        fn best_block_id(&self) -> crate::Result<String> {
+            Ok("beef".into())
+        }
+
+        fn best_block_height(&self) -> crate::Result<u64> {
+            Ok(7)
+        }
+
+        async fn block(&self, block_id: String, _verbosity: u8) -> crate::Result<Option<Value>> {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            Ok(Some(json!({ "id": block_id })))
+        }
+    }
+
+    #[tokio::test]
+    async fn rest_expensive_calls_limited() {
+        let server = RestServer::start(
+            &"127.0.0.1:0".parse().unwrap(),
+            SlowChainstate.into_rpc().into(),
+            1,
+        )
+        .unwrap();
+
+        let ((status1, _, _), (status2, _, _)) = tokio::join!(get(&server, "/block/beef"), async {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            get(&server, "/block/beef.hex").await
+        });
+        assert_eq!(status1, StatusCode::OK);
+        assert_eq!(status2, StatusCode::SERVICE_UNAVAILABLE);
+
+        // Cheap endpoints are not limited
+        let ((status1, _, _), (status2, _, _)) =
+            tokio::join!(get(&server, "/block/beef"), get(&server, "/chain/tip"));
+        assert_eq!(status1, StatusCode::OK);
+        assert_eq!(status2, StatusCode::OK);
+
+        // The limit is released once the expensive call completes
+        let (status, _, _) = get(&server, "/block/beef").await;
+        assert_eq!(status, StatusCode::OK);
+
+        server.stop().await;
+    }
+}
@@ -56,6 +56,9 @@ pub trait Context {
     /// Maximum number of elements on the stack
     const MAX_STACK_ELEMENTS: usize = 1000;
 
+    /// Maximum number of non-push operations per script, public keys of multisig included
+    const MAX_OPS_PER_SCRIPT: usize = 201;
+
     /// Maximum number of public keys per multisig
     const MAX_PUBKEYS_PER_MULTISIG: usize;
 
@@ -52,6 +52,8 @@ pub enum Error {
     StackSize,
     #[error("Maximum script size exceeded.")]
     ScriptSize,
+    #[error("Maximum number of operations exceeded.")]
+    OpCount,
     #[error("Incorrect number of public keys for multisig")]
     PubkeyCount,
     #[error("Incorrect number of signatures for multisig")]
@@ -227,12 +227,21 @@ pub fn run_script<'a, Ctx: Context>(
     let mut subscript: &[u8] = instr_iter.subscript();
     let mut cur_instr_num = 0u32;
     let mut codesep_idx = u32::MAX;
+    let mut op_count = 0usize;
     let mut exec_stack = ExecStack::default();
     let mut alt_stack = Stack::<'a>::default();
 
     while let Some(instr) = instr_iter.next() {
         let instr = instr?;
 
+        // Count all non-push operations, including those in branches that are not executed.
+        if let Instruction::Op(opcode) = instr {
+            if !matches!(opcode.classify(), opcodes::Class::PushNum(_)) {
+                op_count += 1;
+                ensure!(op_count <= Ctx::MAX_OPS_PER_SCRIPT, Error::OpCount);
+            }
+        }
+
         let executing = exec_stack.executing();
         match instr {
             Instruction::PushBytes(data) => {
@@ -278,6 +287,8 @@ pub fn run_script<'a, Ctx: Context>(
                             ensure!(nkey >= 0, Error::PubkeyCount);
                             let nkey = nkey as usize;
                             ensure!(nkey <= Ctx::MAX_PUBKEYS_PER_MULTISIG, Error::PubkeyCount);
+                            op_count += nkey;
+                            ensure!(op_count <= Ctx::MAX_OPS_PER_SCRIPT, Error::OpCount);
                             let keys = stack.top_slice(0..nkey)?.iter().map(AsRef::as_ref);
 
                             // Extract signatures
@@ -672,6 +683,32 @@ mod test {
         test_case(&[sig1, sig2, sig3, sig0][..], Ok(false));
     }
 
+    #[test]
+    fn unit_op_count_limit() {
+        use opcodes::all::*;
+        let max_ops = <TestContext as Context>::MAX_OPS_PER_SCRIPT;
+        let script_with_nops = |count: usize| {
+            (0..count).fold(Builder::new(), |b, _| b.push_opcode(OP_NOP)).into_script()
+        };
+
+        let script = script_with_nops(max_ops);
+        let result = run_script(&TestContext::default(), &script, vec![].into());
+        assert_eq!(result, Ok(vec![].into()));
+
+        let script = script_with_nops(max_ops + 1);
+        let result = run_script(&TestContext::default(), &script, vec![].into());
+        assert_eq!(result, Err(Error::OpCount));
+
+        // Pushes do not count
+        let script = (0..max_ops)
+            .fold(Builder::new().push_int(1), |b, _| {
+                b.push_opcode(OP_DROP).push_int(1)
+            })
+            .into_script();
+        let result = run_script(&TestContext::default(), &script, vec![].into());
+        assert_eq!(result, Ok(vec![script::build_scriptint(1).into()].into()));
+    }
+
     #[test]
     fn unit_conditional_altstack() {
         use opcodes::all::*;
@@ -852,10 +889,17 @@ mod test {
         }
 
         #[test]
-        fn prop_stack_limit(use_alt in gen_vec(prop::bool::ANY, 499)) {
-            let mut builder = Builder::new().push_int(0).push_int(0).push_int(0);
+        fn prop_stack_limit(use_alt in gen_vec(prop::bool::ANY, 200)) {
+            // The operation limit now stops a script long before it could grow the stack past
+            // its limit with OP_2DUP, so fill the stack with pushes, which don't count towards
+            // the operation limit. The at most 200 moves to the alt stack stay below the 201
+            // operations allowed, and the 1001 elements across both stacks exceed the stack limit.
+            let mut builder = Builder::new();
+            for _ in use_alt.len()..=<TestContext as Context>::MAX_STACK_ELEMENTS {
+                builder = builder.push_int(0);
+            }
             for alt in use_alt {
-                builder = builder.push_opcode(opcodes::all::OP_2DUP);
+                builder = builder.push_int(0);
                 if alt {
                     builder = builder.push_opcode(opcodes::all::OP_TOALTSTACK);
                 }
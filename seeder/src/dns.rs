@@ -0,0 +1,296 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! DNS seed
+//!
+//! A minimal authoritative DNS server that answers the `A` and `AAAA` queries for the seed host
+//! name with a random sample of the good nodes. Queries for other names are answered with
+//! `NXDOMAIN`. Only UDP is served and the answers are limited to what fits in a 512 byte
+//! message, so the answers are never truncated.
+
+use rand::seq::SliceRandom;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock},
+};
+use tokio::net::UdpSocket;
+
+/// Maximum size of a DNS message over UDP
+const MAX_MESSAGE_SIZE: usize = 512;
+
+const HEADER_SIZE: usize = 12;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+
+const RCODE_FORMAT_ERROR: u8 = 1;
+const RCODE_NAME_ERROR: u8 = 3;
+const RCODE_NOT_IMPLEMENTED: u8 = 4;
+
+/// Addresses of the good nodes, updated by the crawler
+pub type SeedAddresses = Arc<RwLock<Vec<IpAddr>>>;
+
+pub struct DnsServer {
+    socket: UdpSocket,
+    /// Host name the queries are answered for, in lowercase without the trailing dot
+    host: String,
+    ttl: u32,
+    addresses: SeedAddresses,
+}
+
+impl DnsServer {
+    pub async fn bind(
+        addr: SocketAddr,
+        host: &str,
+        ttl: u32,
+        addresses: SeedAddresses,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr).await?,
+            host: host.trim_end_matches('.').to_ascii_lowercase(),
+            ttl,
+            addresses,
+        })
+    }
+
+    /// Answer the queries until the socket fails
+    pub async fn run(self) -> io::Result<()> {
+        let mut buf = [0u8; MAX_MESSAGE_SIZE];
+        loop {
+            let (len, peer) = self.socket.recv_from(&mut buf).await?;
+            let addresses = self.addresses.read().expect("seed addresses lock poisoned").clone();
+            if let Some(response) = respond(&buf[..len], &self.host, self.ttl, &addresses) {
+                self.socket.send_to(&response, peer).await?;
+            }
+        }
+    }
+}
+
+/// Build the response to a query, `None` if the message isn't answered at all
+fn respond(query: &[u8], host: &str, ttl: u32, addresses: &[IpAddr]) -> Option<Vec<u8>> {
+    if query.len() < HEADER_SIZE || query[2] & 0x80 != 0 {
+        // too short to be answered or a response itself
+        return None;
+    }
+
+    let opcode = (query[2] >> 3) & 0x0f;
+    if opcode != 0 {
+        return Some(header(query, RCODE_NOT_IMPLEMENTED, 0, 0));
+    }
+    let question_count = u16::from_be_bytes([query[4], query[5]]);
+    let (name, question_end) = match parse_name(query) {
+        Some(parsed) if question_count == 1 && query.len() >= parsed.1 + 4 => parsed,
+        _ => return Some(header(query, RCODE_FORMAT_ERROR, 0, 0)),
+    };
+    let question = &query[HEADER_SIZE..question_end + 4];
+
+    if name != host {
+        let mut response = header(query, RCODE_NAME_ERROR, 1, 0);
+        response.extend_from_slice(question);
+        return Some(response);
+    }
+
+    let qtype = u16::from_be_bytes([query[question_end], query[question_end + 1]]);
+    let qclass = u16::from_be_bytes([query[question_end + 2], query[question_end + 3]]);
+    if qclass != CLASS_IN && qclass != CLASS_ANY {
+        let mut response = header(query, 0, 1, 0);
+        response.extend_from_slice(question);
+        return Some(response);
+    }
+    let mut records: Vec<&IpAddr> = addresses
+        .iter()
+        .filter(|addr| match qtype {
+            TYPE_A => addr.is_ipv4(),
+            TYPE_AAAA => addr.is_ipv6(),
+            TYPE_ANY => true,
+            _ => false,
+        })
+        .collect();
+    records.shuffle(&mut rand::thread_rng());
+
+    let mut answers = Vec::new();
+    let mut answer_count = 0;
+    let mut size = HEADER_SIZE + question.len();
+    for addr in records {
+        let record = record(addr, ttl);
+        if size + record.len() > MAX_MESSAGE_SIZE {
+            continue;
+        }
+        size += record.len();
+        answers.extend_from_slice(&record);
+        answer_count += 1;
+    }
+
+    let mut response = header(query, 0, 1, answer_count);
+    response.extend_from_slice(question);
+    response.extend_from_slice(&answers);
+    Some(response)
+}
+
+/// Header of the response to `query`, authoritative and echoing the ID, the opcode and the
+/// recursion desired flag of the query
+fn header(query: &[u8], rcode: u8, question_count: u16, answer_count: u16) -> Vec<u8> {
+    let mut header = Vec::with_capacity(MAX_MESSAGE_SIZE);
+    header.extend_from_slice(&query[0..2]);
+    header.push(0x80 | (query[2] & 0x78) | 0x04 | (query[2] & 0x01));
+    header.push(rcode);
+    header.extend_from_slice(&question_count.to_be_bytes());
+    header.extend_from_slice(&answer_count.to_be_bytes());
+    header.extend_from_slice(&[0, 0, 0, 0]);
+    header
+}
+
+/// Parse the name of the question, returns it in lowercase and the position following it
+///
+/// Compressed names are not expected in a question and are rejected.
+fn parse_name(query: &[u8]) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut pos = HEADER_SIZE;
+    loop {
+        let len = *query.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        let label = query.get(pos..pos + len)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        pos += len;
+    }
+    Some((labels.join("."), pos))
+}
+
+/// Answer record for an address, its name pointing to the name of the question
+fn record(addr: &IpAddr, ttl: u32) -> Vec<u8> {
+    let (record_type, data) = match addr {
+        IpAddr::V4(addr) => (TYPE_A, addr.octets().to_vec()),
+        IpAddr::V6(addr) => (TYPE_AAAA, addr.octets().to_vec()),
+    };
+
+    let mut record = vec![0xc0, HEADER_SIZE as u8];
+    record.extend_from_slice(&record_type.to_be_bytes());
+    record.extend_from_slice(&CLASS_IN.to_be_bytes());
+    record.extend_from_slice(&ttl.to_be_bytes());
+    record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    record.extend_from_slice(&data);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const HOST: &str = "seed.example.org";
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        query
+    }
+
+    fn answer_count(response: &[u8]) -> u16 {
+        u16::from_be_bytes([response[6], response[7]])
+    }
+
+    fn rcode(response: &[u8]) -> u8 {
+        response[3] & 0x0f
+    }
+
+    fn addresses() -> Vec<IpAddr> {
+        vec![
+            Ipv4Addr::new(10, 0, 0, 1).into(),
+            Ipv4Addr::new(10, 0, 0, 2).into(),
+            Ipv6Addr::LOCALHOST.into(),
+        ]
+    }
+
+    #[test]
+    fn address_records() {
+        let query = query(HOST, TYPE_A);
+        let response = respond(&query, HOST, 60, &addresses()).unwrap();
+        assert_eq!(&response[0..2], &[0x12, 0x34]);
+        // response, authoritative, recursion desired echoed
+        assert_eq!(response[2], 0x85);
+        assert_eq!(rcode(&response), 0);
+        assert_eq!(answer_count(&response), 2);
+        assert_eq!(&response[HEADER_SIZE..query.len()], &query[HEADER_SIZE..]);
+        assert_eq!(response.len(), query.len() + 2 * 16);
+
+        let response = respond(&self::query(HOST, TYPE_AAAA), HOST, 60, &addresses()).unwrap();
+        assert_eq!(answer_count(&response), 1);
+        assert_eq!(
+            &response[response.len() - 16..],
+            &Ipv6Addr::LOCALHOST.octets()
+        );
+
+        let response = respond(&self::query(HOST, TYPE_ANY), HOST, 60, &addresses()).unwrap();
+        assert_eq!(answer_count(&response), 3);
+    }
+
+    #[test]
+    fn host_name_case_insensitive() {
+        let response = respond(&query("SEED.Example.org", TYPE_A), HOST, 60, &addresses()).unwrap();
+        assert_eq!(rcode(&response), 0);
+        assert_eq!(answer_count(&response), 2);
+    }
+
+    #[test]
+    fn other_names() {
+        let response = respond(&query("example.org", TYPE_A), HOST, 60, &addresses()).unwrap();
+        assert_eq!(rcode(&response), RCODE_NAME_ERROR);
+        assert_eq!(answer_count(&response), 0);
+    }
+
+    #[test]
+    fn answers_fit_message() {
+        let addresses: Vec<IpAddr> =
+            (0..100u16).map(|i| Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, i).into()).collect();
+        let response = respond(&query(HOST, TYPE_AAAA), HOST, 60, &addresses).unwrap();
+        assert!(response.len() <= MAX_MESSAGE_SIZE);
+        assert!(answer_count(&response) > 10);
+    }
+
+    #[test]
+    fn malformed_queries() {
+        assert_eq!(respond(&[0; 4], HOST, 60, &addresses()), None);
+
+        let mut response = query(HOST, TYPE_A);
+        response[2] |= 0x80;
+        assert_eq!(respond(&response, HOST, 60, &addresses()), None);
+
+        let mut truncated = query(HOST, TYPE_A);
+        truncated.truncate(truncated.len() - 2);
+        let response = respond(&truncated, HOST, 60, &addresses()).unwrap();
+        assert_eq!(rcode(&response), RCODE_FORMAT_ERROR);
+
+        let mut status = query(HOST, TYPE_A);
+        status[2] = 2 << 3;
+        let response = respond(&status, HOST, 60, &addresses()).unwrap();
+        assert_eq!(rcode(&response), RCODE_NOT_IMPLEMENTED);
+    }
+}
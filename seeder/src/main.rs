@@ -0,0 +1,152 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Network crawler and DNS seed binary
+
+mod dns;
+mod options;
+
+use common::chain::{config::ChainType, ChainConfig};
+use logging::log;
+use p2p::{
+    crawler::{Crawler, CrawlerConfig},
+    net::{libp2p::Libp2pService, NetworkingService},
+};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Time a node has to accept the connection of the crawler
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type SeederCrawler = Crawler<Libp2pService>;
+
+async fn run() -> anyhow::Result<()> {
+    let opts = options::Options::from_args(std::env::args_os());
+
+    let mut log_config = logging::LogConfig::new();
+    if let Some(filters) = &opts.log_filters {
+        log_config = log_config.with_filters(filters.clone());
+    }
+    logging::init_logging_with_config(&log_config)?;
+    log::trace!("Command line options: {:?}", opts);
+
+    let chain_config = Arc::new(match (&opts.chain_config, opts.net) {
+        (Some(path), _) => ChainConfig::from_file(path)?,
+        (None, ChainType::Mainnet) => common::chain::config::create_mainnet(),
+        (None, ChainType::Testnet) => common::chain::config::create_testnet(),
+        (None, ChainType::Regtest) => common::chain::config::create_regtest(),
+    });
+
+    let crawler_config = CrawlerConfig {
+        recrawl_interval: Duration::from_secs(u64::from(opts.recrawl_interval.get()) * 60),
+        ..CrawlerConfig::default()
+    };
+    let bind_addr = opts
+        .p2p_addr
+        .parse()
+        .map_err(|err| anyhow::anyhow!("Invalid P2P address {}: {}", opts.p2p_addr, err))?;
+    let mut crawler = SeederCrawler::start(
+        Arc::clone(&chain_config),
+        Default::default(),
+        crawler_config,
+        bind_addr,
+        CONNECT_TIMEOUT,
+    )
+    .await?;
+    for seed in &opts.seeds {
+        let addr = seed
+            .parse()
+            .map_err(|err| anyhow::anyhow!("Invalid seed address {}: {}", seed, err))?;
+        crawler.add_address(addr);
+    }
+
+    let seed_addresses = dns::SeedAddresses::default();
+    if let (Some(addr), Some(host)) = (opts.dns_addr, &opts.dns_host) {
+        let server =
+            dns::DnsServer::bind(addr, host, opts.dns_ttl, Arc::clone(&seed_addresses)).await?;
+        log::info!("answering DNS queries for {} at {}", host, addr);
+        tokio::spawn(async move {
+            if let Err(err) = server.run().await {
+                log::error!("DNS server failed: {}", err);
+            }
+        });
+    }
+
+    crawler
+        .run(|crawler| {
+            if let Err(err) = write_dump(&opts.dump_file, crawler) {
+                log::warn!("failed to write {}: {}", opts.dump_file.display(), err);
+            }
+            update_seed_addresses(&chain_config, crawler, &seed_addresses);
+        })
+        .await?;
+    Ok(())
+}
+
+/// Write the crawled nodes to `path`, one per line
+///
+/// The file is replaced at once so that readers never see a partially written dump.
+fn write_dump(path: &Path, crawler: &SeederCrawler) -> io::Result<()> {
+    let mut nodes: Vec<_> = crawler.nodes().collect();
+    nodes.sort_by_key(|(addr, _)| addr.to_string());
+
+    let tmp_path = path.with_extension("tmp");
+    let mut file = io::BufWriter::new(fs::File::create(&tmp_path)?);
+    writeln!(file, "# address good version services agent")?;
+    for (addr, info) in nodes {
+        writeln!(
+            file,
+            "{} {} {} {:?} {}",
+            addr,
+            u8::from(info.good),
+            info.version.map_or_else(|| "-".to_string(), String::from),
+            info.services,
+            info.agent.as_deref().unwrap_or("-"),
+        )?;
+    }
+    file.into_inner()?.sync_all()?;
+    fs::rename(tmp_path, path)
+}
+
+/// Publish the good nodes listening on the default port of the network
+///
+/// DNS answers carry no port, so the nodes listening elsewhere can't be advertised.
+fn update_seed_addresses(
+    chain_config: &ChainConfig,
+    crawler: &SeederCrawler,
+    seed_addresses: &dns::SeedAddresses,
+) {
+    let addresses = crawler
+        .good_addresses()
+        .iter()
+        .filter_map(Libp2pService::to_socket_addr)
+        .filter(|addr| addr.port() == chain_config.p2p_port())
+        .map(|addr| addr.ip())
+        .collect();
+    *seed_addresses.write().expect("seed addresses lock poisoned") = addresses;
+}
+
+#[tokio::main]
+async fn main() {
+    run().await.unwrap_or_else(|err| {
+        eprintln!("ERROR: {}", err);
+        std::process::exit(1)
+    })
+}
@@ -0,0 +1,78 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Seeder configuration options
+
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use strum::VariantNames;
+
+use common::chain::config::ChainType;
+
+/// Mintlayer network crawler, publishes the addresses of the good nodes
+#[derive(clap::Parser, Debug)]
+#[clap(author, version, about)]
+pub struct Options {
+    /// Log levels per target, such as `info,p2p=debug`. Taken from the `RUST_LOG` environment
+    /// variable if not given.
+    #[clap(long, value_name = "FILTERS")]
+    pub log_filters: Option<String>,
+
+    /// Blockchain type
+    #[clap(long, possible_values = ChainType::VARIANTS, default_value = "mainnet")]
+    pub net: ChainType,
+
+    /// Load the chain configuration of a custom network from a TOML or JSON file instead of
+    /// using the one built in for `--net`
+    #[clap(long, value_name = "PATH")]
+    pub chain_config: Option<PathBuf>,
+
+    /// Address to bind P2P to
+    #[clap(long, value_name = "ADDR", default_value = "/ip6/::/tcp/0")]
+    pub p2p_addr: String,
+
+    /// Address of a node to start crawling from, in addition to the fixed seeds of the
+    /// network. Can be given multiple times.
+    #[clap(long = "seed", value_name = "ADDR")]
+    pub seeds: Vec<String>,
+
+    /// File the crawled nodes are written to after each crawl
+    #[clap(long, value_name = "PATH", default_value = "seeder.dump")]
+    pub dump_file: PathBuf,
+
+    /// Minutes between two probes of a good node
+    #[clap(long, value_name = "MINUTES", default_value = "30")]
+    pub recrawl_interval: NonZeroU32,
+
+    /// Address to answer DNS queries at, such as `0.0.0.0:53`
+    #[clap(long, value_name = "ADDR", requires = "dns-host")]
+    pub dns_addr: Option<SocketAddr>,
+
+    /// Host name the DNS queries are answered for, such as `seed.example.org`
+    #[clap(long, value_name = "NAME", requires = "dns-addr")]
+    pub dns_host: Option<String>,
+
+    /// Time to live of the DNS records, in seconds
+    #[clap(long, value_name = "SECS", default_value = "60")]
+    pub dns_ttl: u32,
+}
+
+impl Options {
+    pub fn from_args<A: Into<OsString> + Clone>(args: impl IntoIterator<Item = A>) -> Self {
+        clap::Parser::parse_from(args)
+    }
+}
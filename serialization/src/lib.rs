@@ -5,3 +5,4 @@ pub use parity_scale_codec::{Codec, Decode, DecodeAll, Encode, Input, Output};
 
 // Re-export types
 pub use parity_scale_codec::Error;
+pub use parity_scale_codec::IoReader;
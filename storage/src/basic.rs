@@ -1,7 +1,9 @@
+use crate::journal::{Change, Journal};
 use crate::schema::{self, Schema};
 use crate::Data;
 use common::sync;
 use std::collections::BTreeMap;
+use std::path::Path;
 
 // These store the data
 type StoreMapSingle = BTreeMap<Data, Data>;
@@ -12,6 +14,35 @@ pub enum StoreMap {
     Multi(StoreMapMulti),
 }
 
+/// Size of the contents of a store map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MapStats {
+    /// Number of entries in the map, each value of a key-multivalue map counting as one
+    pub entries: usize,
+    /// Total size of the keys and values in bytes
+    pub bytes: usize,
+}
+
+impl StoreMap {
+    fn stats(&self) -> MapStats {
+        match self {
+            StoreMap::Single(map) => MapStats {
+                entries: map.len(),
+                bytes: map.iter().map(|(key, value)| key.len() + value.len()).sum(),
+            },
+            StoreMap::Multi(map) => MapStats {
+                entries: map.values().map(Vec::len).sum(),
+                bytes: map
+                    .iter()
+                    .map(|(key, values)| {
+                        values.iter().map(|value| key.len() + value.len()).sum::<usize>()
+                    })
+                    .sum(),
+            },
+        }
+    }
+}
+
 // Set of store maps, one per db index.
 type StoreMapSet = BTreeMap<&'static str, StoreMap>;
 
@@ -25,6 +56,34 @@ enum VecDelta<T> {
     Modify { add: Vec<T>, del: Vec<T> },
 }
 
+impl VecDelta<Data> {
+    // Values of a key after applying the delta to its current values
+    fn apply(&self, values: Option<&Vec<Data>>) -> Vec<Data> {
+        match self {
+            VecDelta::Set(new) => new.clone(),
+            VecDelta::Modify { add, del } => {
+                let mut values: Vec<Data> = values
+                    .into_iter()
+                    .flatten()
+                    .filter(|val| !del.contains(val))
+                    .cloned()
+                    .collect();
+                values.extend(add.iter().cloned());
+                values
+            }
+        }
+    }
+}
+
+/// Set the values of a key in a key-multivalue map, removing the key if there are none
+pub fn set_values(store: &mut StoreMapMulti, key: Data, values: Vec<Data>) {
+    if values.is_empty() {
+        store.remove(&key);
+    } else {
+        store.insert(key, values);
+    }
+}
+
 type DeltaMapMulti = BTreeMap<Data, VecDelta<Data>>;
 
 enum DeltaMap {
@@ -36,6 +95,14 @@ enum DeltaMap {
 type DeltaMapSet = BTreeMap<&'static str, DeltaMap>;
 
 impl DeltaMap {
+    // Whether the delta changes anything
+    fn has_changes(&self) -> bool {
+        match self {
+            DeltaMap::Single(delta) => !delta.is_empty(),
+            DeltaMap::Multi(delta) => !delta.is_empty(),
+        }
+    }
+
     // Apply given delta to the store map
     fn apply_to(self, store: &mut StoreMap) {
         match (self, store) {
@@ -47,17 +114,49 @@ impl DeltaMap {
                     };
                 })
             }
-            (DeltaMap::Multi(_delta), StoreMap::Multi(_store)) => {
-                todo!("Key-multivalue map not implemented")
+            (DeltaMap::Multi(delta), StoreMap::Multi(store)) => {
+                delta.into_iter().for_each(|(key, val)| {
+                    let values = val.apply(store.get(&key));
+                    set_values(store, key, values);
+                })
             }
             _ => unreachable!("Map type mismatch"),
         }
     }
 }
 
+// Changes recorded in the delta maps, in the form written to the journal
+fn journal_changes(store: &StoreMapSet, delta: &DeltaMapSet) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (name, delta) in delta {
+        match (delta, &store[name]) {
+            (DeltaMap::Single(delta), StoreMap::Single(_)) => changes.extend(
+                delta.iter().map(|(key, val)| (name.to_string(), key.clone(), val.clone())),
+            ),
+            (DeltaMap::Multi(delta), StoreMap::Multi(store)) => {
+                changes.extend(delta.iter().map(|(key, val)| {
+                    let values = val.apply(store.get(key));
+                    (
+                        name.to_string(),
+                        key.clone(),
+                        crate::journal::encode_values(&values),
+                    )
+                }))
+            }
+            _ => unreachable!("Map type mismatch"),
+        }
+    }
+    changes
+}
+
 /// Store is a collection of key-(multi)value maps
+///
+/// The maps are kept in memory. A store opened with [`Store::open`] also persists the committed
+/// transactions to a journal file.
 pub struct Store<Sch: Schema> {
     maps: sync::Arc<sync::RwLock<StoreMapSet>>,
+    journal: Option<sync::Arc<sync::Mutex<Journal>>>,
+    read_only: bool,
     _phantom: std::marker::PhantomData<fn() -> Sch>,
 }
 
@@ -65,6 +164,8 @@ impl<Sch: Schema> Clone for Store<Sch> {
     fn clone(&self) -> Self {
         Self {
             maps: sync::Arc::clone(&self.maps),
+            journal: self.journal.as_ref().map(sync::Arc::clone),
+            read_only: self.read_only,
             _phantom: Default::default(),
         }
     }
@@ -85,8 +186,12 @@ impl InitStore for () {
 impl<DBIdx: schema::DBIndex, Rest: InitStore> InitStore for (DBIdx, Rest) {
     fn init() -> BTreeMap<&'static str, StoreMap> {
         let mut map = Rest::init();
-        // TODO support multi
-        let orig = map.insert(DBIdx::NAME, StoreMap::Single(BTreeMap::new()));
+        let store_map = if <DBIdx::Kind as schema::MapKind>::MULTI {
+            StoreMap::Multi(BTreeMap::new())
+        } else {
+            StoreMap::Single(BTreeMap::new())
+        };
+        let orig = map.insert(DBIdx::NAME, store_map);
         assert!(orig.is_none(), "DB index names are not unique");
         map
     }
@@ -97,9 +202,59 @@ impl<Sch: InitStore> Store<Sch> {
     pub fn new() -> Self {
         Self {
             maps: sync::Arc::new(sync::RwLock::new(Sch::init())),
+            journal: None,
+            read_only: false,
             _phantom: Default::default(),
         }
     }
+
+    /// Open a store persisted in the journal file at `path`, creating the file if missing
+    ///
+    /// The journal stays locked until the store is dropped, opening it again for writing fails
+    /// with [`Fatal::DatabaseLocked`](crate::error::Fatal::DatabaseLocked) in the meantime.
+    pub fn open(path: &Path) -> crate::Result<Self> {
+        let mut maps = Sch::init();
+        let journal = Journal::open(path, &mut maps)?;
+        Ok(Self {
+            maps: sync::Arc::new(sync::RwLock::new(maps)),
+            journal: Some(sync::Arc::new(sync::Mutex::new(journal))),
+            read_only: false,
+            _phantom: Default::default(),
+        })
+    }
+
+    /// Open a store persisted in the journal file at `path` for reading only
+    ///
+    /// The file is left unmodified. Committing a transaction with changes fails with
+    /// [`Recoverable::ReadOnly`](crate::error::Recoverable::ReadOnly).
+    pub fn open_read_only(path: &Path) -> crate::Result<Self> {
+        let mut maps = Sch::init();
+        crate::journal::read(path, &mut maps)?;
+        Ok(Self {
+            maps: sync::Arc::new(sync::RwLock::new(maps)),
+            journal: None,
+            read_only: true,
+            _phantom: Default::default(),
+        })
+    }
+}
+
+impl<Sch: Schema> Store<Sch> {
+    /// Write a consistent copy of the store contents to a new file at `path`
+    ///
+    /// The copy can be opened with [`Store::open`]. Existing files, such as the journal of the
+    /// store itself, are never overwritten. Writers are blocked while the copy is being written,
+    /// readers can use the store as usual.
+    pub fn backup_to(&self, path: &Path) -> std::io::Result<()> {
+        let maps = self.maps.read().expect("Mutex locked by a crashed thread");
+        crate::journal::write_backup(path, &maps)
+    }
+
+    /// Size of the contents of each map, by the map name
+    pub fn map_stats(&self) -> BTreeMap<&'static str, MapStats> {
+        let maps = self.maps.read().expect("Mutex locked by a crashed thread");
+        maps.iter().map(|(name, map)| (*name, map.stats())).collect()
+    }
 }
 
 impl<'tx, Sch: 'static + Schema> crate::traits::Transactional<'tx, Sch> for Store<Sch> {
@@ -157,6 +312,8 @@ impl<'st, 'm, Sch: Schema> crate::traits::GetMapRef<'m, Sch> for TransactionRo<'
 /// discarded.
 pub struct TransactionRw<'st, Sch: Schema> {
     store: sync::RwLockWriteGuard<'st, StoreMapSet>,
+    journal: Option<&'st sync::Mutex<Journal>>,
+    read_only: bool,
     delta: DeltaMapSet,
     _phantom: std::marker::PhantomData<fn() -> Sch>,
 }
@@ -164,6 +321,8 @@ pub struct TransactionRw<'st, Sch: Schema> {
 impl<'st, Sch: Schema> TransactionRw<'st, Sch> {
     // Start a transaction on given store
     fn start(store: &'st Store<Sch>) -> Self {
+        let journal = store.journal.as_deref();
+        let read_only = store.read_only;
         let store = store.maps.write().expect("Mutex locked by a crashed thread");
         let delta = store
             .iter()
@@ -178,6 +337,8 @@ impl<'st, Sch: Schema> TransactionRw<'st, Sch> {
         let _phantom = Default::default();
         Self {
             store,
+            journal,
+            read_only,
             delta,
             _phantom,
         }
@@ -223,6 +384,18 @@ impl<'st, Sch: Schema> crate::transaction::TransactionRw for TransactionRw<'st,
 
     /// Commit a transaction
     fn commit(mut self) -> Result<(), Self::Error> {
+        if self.read_only && self.delta.values().any(DeltaMap::has_changes) {
+            return Err(crate::Error::Recoverable(
+                crate::error::Recoverable::ReadOnly,
+            ));
+        }
+        if let Some(journal) = self.journal {
+            let changes = journal_changes(&self.store, &self.delta);
+            if !changes.is_empty() {
+                journal.lock().expect("Mutex locked by a crashed thread").append(&changes)?;
+            }
+        }
+
         self.store
             .values_mut()
             .zip(self.delta.into_values())
@@ -13,6 +13,10 @@ pub enum Recoverable {
     #[error("The database has temporarily exhausted some resource")]
     TemporarilyUnavailable,
 
+    /// The store has been opened read-only, so changes can't be committed.
+    #[error("The database is read-only")]
+    ReadOnly,
+
     /// Other recoverable error
     #[error("Unknown database error")]
     Unknown,
@@ -29,6 +33,8 @@ pub enum Fatal {
     InternalError,
     #[error("Database schema does not match database settings or contents")]
     SchemaMismatch,
+    #[error("Database is in use by another process")]
+    DatabaseLocked,
     #[error("Unknown fatal database error")]
     Unknown,
 }
@@ -0,0 +1,471 @@
+//! Append-only journal persisting the contents of a store
+//!
+//! The journal is a file consisting of records. Each record holds the changes made by one
+//! committed transaction: the length and CRC32 checksum of the payload, both little endian `u32`,
+//! followed by the payload itself, which is the SCALE-encoded list of changes. A change of a key
+//! in a key-multivalue map holds all the values the key is mapped to after the change.
+//!
+//! When a store is opened, the records are replayed to reconstruct its contents. The last record
+//! of the journal, if it is incomplete or fails the checksum, has been cut short by a crash while
+//! it was being written, so it is discarded together with the transaction it belongs to. Any
+//! other record failing the checksum means the journal is corrupted and the store isn't opened.
+//! The journal is then compacted into a single record holding the current contents of the store.
+//!
+//! A journal opened for writing is locked through a lock file next to it, so only one process
+//! appends to it at a time.
+
+use crate::basic::{self, StoreMap};
+use crate::error::Fatal;
+use crate::Data;
+use fs2::FileExt;
+use logging::log;
+use serialization::{DecodeAll, Encode};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Change of a single key: name of the map, the key and the new value, or `None` if deleted
+///
+/// The new value of a key in a key-multivalue map is the list of its values encoded with
+/// [`encode_values`].
+pub type Change = (String, Data, Option<Data>);
+
+/// Size of the record header, consisting of the payload length and checksum
+const HEADER_SIZE: usize = 8;
+
+/// Journal file the committed changes are appended to
+pub struct Journal {
+    file: File,
+    // Length of the journal up to the end of the last complete record
+    len: u64,
+    // Set when a failed append couldn't be cut off the journal, nothing is appended after it then
+    poisoned: bool,
+    // Holds the exclusive lock on the journal until the journal is dropped
+    _lock: File,
+}
+
+fn io_error(path: &Path, e: io::Error) -> crate::Error {
+    log::error!("Storage journal {} error: {}", path.display(), e);
+    crate::Error::Fatal(Fatal::InternalError)
+}
+
+fn encode_record(changes: &[Change]) -> Vec<u8> {
+    let payload = changes.encode();
+    let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    record.extend_from_slice(&payload);
+    record
+}
+
+/// The values of a key in a key-multivalue map as a change value, `None` if there are none
+pub fn encode_values(values: &[Data]) -> Option<Data> {
+    (!values.is_empty()).then(|| values.encode())
+}
+
+/// Record at the start of the journal data
+enum Record<'a> {
+    /// Payload of a record with a valid checksum and the data following the record
+    Valid(&'a [u8], &'a [u8]),
+    /// The data ends with a record cut short by a crash
+    Torn,
+    /// The record fails the checksum but more data follows it
+    Corrupted,
+}
+
+/// Split the next record off `data`
+fn next_record(data: &[u8]) -> Record<'_> {
+    let header = match data.get(..HEADER_SIZE) {
+        Some(header) => header,
+        None => return Record::Torn,
+    };
+    let len = u32::from_le_bytes(header[..4].try_into().expect("4 bytes")) as usize;
+    let checksum = u32::from_le_bytes(header[4..].try_into().expect("4 bytes"));
+    let (payload, rest) = match data[HEADER_SIZE..].get(..len) {
+        Some(payload) => (payload, &data[HEADER_SIZE + len..]),
+        None => return Record::Torn,
+    };
+
+    if crc32fast::hash(payload) == checksum {
+        Record::Valid(payload, rest)
+    } else if rest.is_empty() {
+        Record::Torn
+    } else {
+        Record::Corrupted
+    }
+}
+
+/// Apply the changes from a journal record to the store maps
+fn apply(maps: &mut BTreeMap<&'static str, StoreMap>, changes: Vec<Change>) -> crate::Result<()> {
+    for (name, key, value) in changes {
+        match maps.get_mut(name.as_str()) {
+            Some(StoreMap::Single(map)) => {
+                match value {
+                    Some(value) => map.insert(key, value),
+                    None => map.remove(&key),
+                };
+            }
+            Some(StoreMap::Multi(map)) => {
+                let values = match value {
+                    Some(value) => Vec::<Data>::decode_all(&mut &value[..]).map_err(|e| {
+                        log::error!("Storage journal values of map {} invalid: {}", name, e);
+                        crate::Error::Fatal(Fatal::DatabaseCorrupted)
+                    })?,
+                    None => Vec::new(),
+                };
+                basic::set_values(map, key, values);
+            }
+            None => {
+                log::error!("Storage journal refers to an unknown map {}", name);
+                return Err(crate::Error::Fatal(Fatal::SchemaMismatch));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// All entries of the store maps as changes that recreate them
+fn snapshot(maps: &BTreeMap<&'static str, StoreMap>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (name, map) in maps {
+        match map {
+            StoreMap::Single(map) => changes.extend(
+                map.iter()
+                    .map(|(key, value)| (name.to_string(), key.clone(), Some(value.clone()))),
+            ),
+            StoreMap::Multi(map) => changes.extend(
+                map.iter()
+                    .map(|(key, values)| (name.to_string(), key.clone(), encode_values(values))),
+            ),
+        }
+    }
+    changes
+}
+
+/// Path of `path` with `extension` appended
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = OsString::from(path.as_os_str());
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Take the exclusive lock on the journal at `path`
+fn lock(path: &Path) -> crate::Result<File> {
+    let lock_path = with_extension(path, ".lock");
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| io_error(&lock_path, e))?;
+    file.try_lock_exclusive().map_err(|e| {
+        log::error!(
+            "Storage journal {} is in use by another process: {}",
+            path.display(),
+            e
+        );
+        crate::Error::Fatal(Fatal::DatabaseLocked)
+    })?;
+    Ok(file)
+}
+
+/// Write a journal consisting of a single record with the contents of `maps` to `file`
+fn write_record(mut file: File, maps: &BTreeMap<&'static str, StoreMap>) -> io::Result<()> {
+    let changes = snapshot(maps);
+    if !changes.is_empty() {
+        file.write_all(&encode_record(&changes))?;
+    }
+    file.sync_all()
+}
+
+/// Replace the journal at `path` with a single record holding the contents of `maps`
+///
+/// The journal is written next to `path` and only replaces it once it's complete, so an existing
+/// file at `path` is either left intact or replaced with the whole journal.
+fn write_snapshot(path: &Path, maps: &BTreeMap<&'static str, StoreMap>) -> io::Result<()> {
+    let tmp_path = with_extension(path, ".tmp");
+    write_record(File::create(&tmp_path)?, maps)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Write a new journal holding the contents of `maps` to `path`
+///
+/// Fails if there is a file at `path` already, which includes the journal of the store itself.
+/// A partially written journal is removed again.
+pub fn write_backup(path: &Path, maps: &BTreeMap<&'static str, StoreMap>) -> io::Result<()> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let result = write_record(file, maps);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Replay the journal contents `data` read from `path` into `maps`
+fn replay(
+    path: &Path,
+    data: &[u8],
+    maps: &mut BTreeMap<&'static str, StoreMap>,
+) -> crate::Result<()> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        match next_record(rest) {
+            Record::Valid(payload, next) => {
+                let changes = Vec::<Change>::decode_all(&mut &payload[..]).map_err(|e| {
+                    log::error!("Storage journal {} record invalid: {}", path.display(), e);
+                    crate::Error::Fatal(Fatal::DatabaseCorrupted)
+                })?;
+                apply(maps, changes)?;
+                rest = next;
+            }
+            Record::Torn => {
+                log::warn!(
+                    "Discarding {} bytes of an incomplete transaction at the end of \
+                        storage journal {}",
+                    rest.len(),
+                    path.display(),
+                );
+                break;
+            }
+            Record::Corrupted => {
+                log::error!(
+                    "Storage journal {} record at offset {} fails the checksum",
+                    path.display(),
+                    data.len() - rest.len(),
+                );
+                return Err(crate::Error::Fatal(Fatal::DatabaseCorrupted));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replay the existing journal at `path` into `maps`, leaving the file as it is
+pub fn read(path: &Path, maps: &mut BTreeMap<&'static str, StoreMap>) -> crate::Result<()> {
+    let data = fs::read(path).map_err(|e| io_error(path, e))?;
+    replay(path, &data, maps)
+}
+
+impl Journal {
+    /// Open the journal at `path` and replay it into `maps`, creating the journal if missing
+    ///
+    /// Fails with [`Fatal::DatabaseLocked`] if the journal is already open for writing.
+    pub fn open(path: &Path, maps: &mut BTreeMap<&'static str, StoreMap>) -> crate::Result<Self> {
+        let lock = lock(path)?;
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(io_error(path, e)),
+        };
+        replay(path, &data, maps)?;
+
+        write_snapshot(path, maps).map_err(|e| io_error(path, e))?;
+        let file = OpenOptions::new().append(true).open(path).map_err(|e| io_error(path, e))?;
+        let len = file.metadata().map_err(|e| io_error(path, e))?.len();
+        Ok(Self {
+            file,
+            len,
+            poisoned: false,
+            _lock: lock,
+        })
+    }
+
+    /// Durably append the changes of a transaction
+    ///
+    /// If the record fails to be written, the part of it that was written is cut off again, so
+    /// that the records appended later don't follow a torn one. If that fails too, the journal
+    /// refuses any further appends.
+    pub fn append(&mut self, changes: &[Change]) -> crate::Result<()> {
+        if self.poisoned {
+            log::error!("Storage journal ends with a torn record, refusing to append");
+            return Err(crate::Error::Fatal(Fatal::InternalError));
+        }
+
+        let record = encode_record(changes);
+        match self.file.write_all(&record).and_then(|()| self.file.sync_data()) {
+            Ok(()) => {
+                self.len += record.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Storage journal write error: {}", e);
+                let truncated = self.file.set_len(self.len).and_then(|()| self.file.sync_data());
+                if let Err(e) = truncated {
+                    log::error!("Storage journal error cutting off a torn record: {}", e);
+                    self.poisoned = true;
+                }
+                Err(crate::Error::Fatal(Fatal::InternalError))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn maps() -> BTreeMap<&'static str, StoreMap> {
+        [
+            ("A", StoreMap::Single(BTreeMap::new())),
+            ("B", StoreMap::Single(BTreeMap::new())),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn change(name: &str, key: &[u8], value: Option<&[u8]>) -> Change {
+        (name.to_owned(), key.to_vec(), value.map(<[u8]>::to_vec))
+    }
+
+    #[test]
+    fn replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+
+        let mut journal = Journal::open(&path, &mut maps()).unwrap();
+        journal
+            .append(&[change("A", b"k1", Some(b"v1")), change("B", b"k2", Some(b"v2"))])
+            .unwrap();
+        journal
+            .append(&[change("A", b"k1", None), change("A", b"k3", Some(b"v3"))])
+            .unwrap();
+        drop(journal);
+
+        let expected = vec![change("A", b"k3", Some(b"v3")), change("B", b"k2", Some(b"v2"))];
+        let mut replayed = maps();
+        Journal::open(&path, &mut replayed).unwrap();
+        assert_eq!(snapshot(&replayed), expected);
+
+        // The compacted journal has the same contents
+        let mut replayed = maps();
+        Journal::open(&path, &mut replayed).unwrap();
+        assert_eq!(snapshot(&replayed), expected);
+    }
+
+    #[test]
+    fn incomplete_record_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+
+        let mut journal = Journal::open(&path, &mut maps()).unwrap();
+        journal.append(&[change("A", b"k1", Some(b"v1"))]).unwrap();
+        journal.append(&[change("A", b"k2", Some(b"v2"))]).unwrap();
+        drop(journal);
+
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 1).unwrap();
+
+        // Reading doesn't discard the incomplete record from the file
+        let mut replayed = maps();
+        read(&path, &mut replayed).unwrap();
+        assert_eq!(snapshot(&replayed), vec![change("A", b"k1", Some(b"v1"))]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len - 1);
+
+        let mut replayed = maps();
+        Journal::open(&path, &mut replayed).unwrap();
+        assert_eq!(snapshot(&replayed), vec![change("A", b"k1", Some(b"v1"))]);
+    }
+
+    #[test]
+    fn corrupted_record_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+
+        let first = [change("A", b"k1", Some(b"v1"))];
+        let mut journal = Journal::open(&path, &mut maps()).unwrap();
+        journal.append(&first).unwrap();
+        journal.append(&[change("A", b"k2", Some(b"v2"))]).unwrap();
+        journal.append(&[change("A", b"k3", Some(b"v3"))]).unwrap();
+        drop(journal);
+
+        // Flip the last payload byte of the second record
+        let mut data = fs::read(&path).unwrap();
+        let offset = 2 * encode_record(&first).len() - 1;
+        data[offset] ^= 0xff;
+        fs::write(&path, &data).unwrap();
+
+        assert_eq!(
+            read(&path, &mut maps()).err(),
+            Some(crate::Error::Fatal(Fatal::DatabaseCorrupted))
+        );
+        assert_eq!(
+            Journal::open(&path, &mut maps()).err(),
+            Some(crate::Error::Fatal(Fatal::DatabaseCorrupted))
+        );
+        // The journal is left as it was
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn multi_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+        let values = |values: &[&[u8]]| {
+            encode_values(&values.iter().map(|v| v.to_vec()).collect::<Vec<_>>())
+        };
+        let multi_maps = || -> BTreeMap<&'static str, StoreMap> {
+            [("M", StoreMap::Multi(BTreeMap::new()))].into_iter().collect()
+        };
+
+        let mut journal = Journal::open(&path, &mut multi_maps()).unwrap();
+        journal
+            .append(&[
+                ("M".to_owned(), b"k1".to_vec(), values(&[b"v1", b"v2"])),
+                ("M".to_owned(), b"k2".to_vec(), values(&[b"v3"])),
+            ])
+            .unwrap();
+        journal.append(&[("M".to_owned(), b"k2".to_vec(), None)]).unwrap();
+        drop(journal);
+
+        let expected = vec![("M".to_owned(), b"k1".to_vec(), values(&[b"v1", b"v2"]))];
+        let mut replayed = multi_maps();
+        Journal::open(&path, &mut replayed).unwrap();
+        assert_eq!(snapshot(&replayed), expected);
+
+        // The compacted journal has the same contents
+        let mut replayed = multi_maps();
+        Journal::open(&path, &mut replayed).unwrap();
+        assert_eq!(snapshot(&replayed), expected);
+    }
+
+    #[test]
+    fn locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+
+        let mut journal = Journal::open(&path, &mut maps()).unwrap();
+        journal.append(&[change("A", b"k1", Some(b"v1"))]).unwrap();
+        let data = fs::read(&path).unwrap();
+
+        assert_eq!(
+            Journal::open(&path, &mut maps()).err(),
+            Some(crate::Error::Fatal(Fatal::DatabaseLocked))
+        );
+        // The journal is left as it was and can still be read
+        assert_eq!(fs::read(&path).unwrap(), data);
+        let mut replayed = maps();
+        read(&path, &mut replayed).unwrap();
+        assert_eq!(snapshot(&replayed), vec![change("A", b"k1", Some(b"v1"))]);
+
+        // The lock is released with the journal
+        drop(journal);
+        Journal::open(&path, &mut maps()).unwrap();
+    }
+
+    #[test]
+    fn unknown_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+
+        let mut journal = Journal::open(&path, &mut maps()).unwrap();
+        journal.append(&[change("C", b"k1", Some(b"v1"))]).unwrap();
+        drop(journal);
+
+        assert_eq!(
+            Journal::open(&path, &mut maps()).err(),
+            Some(crate::Error::Fatal(Fatal::SchemaMismatch))
+        );
+    }
+}
@@ -8,6 +8,10 @@
 //! For now, only basic storage implementation is provided. It is to be replaced with a proper one
 //! abstracting over storage backend and a more complete feature set.
 //!
+//! The basic storage keeps the data in memory. It can be persisted by opening it with
+//! [`Store::open`], which appends each committed transaction to a journal file and replays the
+//! journal when the store is opened again.
+//!
 //! # Example
 //!
 //! ```
@@ -65,12 +69,13 @@
 
 mod basic;
 pub mod error;
+mod journal;
 pub mod schema;
 pub mod traits;
 pub mod transaction;
 
 // Reexport items from the temporary basic implementation.
-pub use basic::Store;
+pub use basic::{MapStats, Store};
 pub use error::Error;
 pub use transaction::{abort, commit};
 
@@ -110,4 +115,155 @@ mod test {
             assert_eq!(r, Ok(false));
         })
     }
+
+    #[test]
+    fn test_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store");
+
+        let store = MyStore::open(&path).unwrap();
+        let r = store.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().put(b"foo".to_vec(), b"bar".to_vec())?;
+            crate::commit(())
+        });
+        assert_eq!(r, Ok(()));
+        assert_eq!(generic_aborted_write(&store), Ok(()));
+        drop(store);
+
+        let store = MyStore::open(&path).unwrap();
+        let r = store.transaction_ro().run(|tx| {
+            let map = tx.get::<MyMap, _>();
+            Ok((
+                map.get(b"foo")?.map(ToOwned::to_owned),
+                map.get(b"hello")?.is_some(),
+            ))
+        });
+        assert_eq!(r, Ok((Some(b"bar".to_vec()), false)));
+    }
+
+    #[test]
+    fn test_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup");
+
+        let store = MyStore::new();
+        let r = store.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().put(b"foo".to_vec(), b"bar".to_vec())?;
+            crate::commit(())
+        });
+        assert_eq!(r, Ok(()));
+        store.backup_to(&path).unwrap();
+
+        // Changes made after the backup are not in it
+        let r = store.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().del(b"foo")?;
+            crate::commit(())
+        });
+        assert_eq!(r, Ok(()));
+
+        let backup = MyStore::open(&path).unwrap();
+        let r = backup
+            .transaction_ro()
+            .run(|tx| Ok(tx.get::<MyMap, _>().get(b"foo")?.map(ToOwned::to_owned)));
+        assert_eq!(r, Ok(Some(b"bar".to_vec())));
+
+        // The backup is not overwritten
+        assert_eq!(
+            store.backup_to(&path).unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+    }
+
+    #[test]
+    #[cfg(not(loom))]
+    fn test_backup_onto_live_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store");
+
+        let store = MyStore::open(&path).unwrap();
+        let r = store.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().put(b"foo".to_vec(), b"bar".to_vec())?;
+            crate::commit(())
+        });
+        assert_eq!(r, Ok(()));
+        assert_eq!(
+            store.backup_to(&path).unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+
+        // Commits still reach the journal
+        let r = store.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().put(b"hello".to_vec(), b"world".to_vec())?;
+            crate::commit(())
+        });
+        assert_eq!(r, Ok(()));
+        drop(store);
+
+        let store = MyStore::open(&path).unwrap();
+        let r = store.transaction_ro().run(|tx| {
+            let map = tx.get::<MyMap, _>();
+            Ok((
+                map.get(b"foo")?.map(ToOwned::to_owned),
+                map.get(b"hello")?.map(ToOwned::to_owned),
+            ))
+        });
+        assert_eq!(r, Ok((Some(b"bar".to_vec()), Some(b"world".to_vec()))));
+    }
+
+    #[test]
+    fn test_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store");
+
+        let store = MyStore::open(&path).unwrap();
+        let r = store.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().put(b"foo".to_vec(), b"bar".to_vec())?;
+            crate::commit(())
+        });
+        assert_eq!(r, Ok(()));
+
+        let read_only = MyStore::open_read_only(&path).unwrap();
+        let r = read_only
+            .transaction_ro()
+            .run(|tx| Ok(tx.get::<MyMap, _>().get(b"foo")?.map(ToOwned::to_owned)));
+        assert_eq!(r, Ok(Some(b"bar".to_vec())));
+
+        let r = read_only.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().del(b"foo")?;
+            crate::commit(())
+        });
+        assert_eq!(
+            r,
+            Err(crate::Error::Recoverable(
+                crate::error::Recoverable::ReadOnly
+            ))
+        );
+        drop(read_only);
+        drop(store);
+
+        assert!(MyStore::open_read_only(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_map_stats() {
+        let store = MyStore::new();
+        assert_eq!(
+            store.map_stats().get("MyMap"),
+            Some(&crate::MapStats::default())
+        );
+
+        let r = store.transaction_rw().run(|tx| {
+            tx.get_mut::<MyMap, _>().put(b"foo".to_vec(), b"bar".to_vec())?;
+            tx.get_mut::<MyMap, _>().put(b"hello".to_vec(), b"world".to_vec())?;
+            crate::commit(())
+        });
+        assert_eq!(r, Ok(()));
+        assert_eq!(
+            store.map_stats().get("MyMap"),
+            Some(&crate::MapStats {
+                entries: 2,
+                bytes: 16
+            })
+        );
+    }
 }
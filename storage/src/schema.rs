@@ -33,9 +33,16 @@ pub struct Single;
 pub struct Multi;
 
 /// Specifies map kind, either [Single] or [Multi].
-pub trait MapKind {}
-impl MapKind for Single {}
-impl MapKind for Multi {}
+pub trait MapKind {
+    /// Whether keys are mapped to multiple values
+    const MULTI: bool;
+}
+impl MapKind for Single {
+    const MULTI: bool = false;
+}
+impl MapKind for Multi {
+    const MULTI: bool = true;
+}
 
 mod internal {
     use super::*;
@@ -0,0 +1,277 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Subsystem health monitoring
+//!
+//! A subsystem makes progress whenever it takes a call from its queue. Subsystems that do long
+//! running work between calls can also report progress explicitly with
+//! [CallRequest::report_progress](crate::subsystem::CallRequest::report_progress). A subsystem
+//! is stalled if it has calls pending but has not made progress for the stall timeout. An idle
+//! subsystem, with no calls pending, is never stalled.
+//!
+//! The watchdog, enabled in the [ManagerConfig](crate::manager::ManagerConfig), periodically
+//! checks the subsystems and logs the stalled ones, optionally taking further action. Restarting
+//! a single subsystem is not supported since the subsystem state lives in its task;
+//! [StallAction::Shutdown] lets an external supervisor restart the whole application.
+
+use core::time::Duration;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::Instant;
+
+/// Stall timeout used to report the subsystem health when the watchdog is not enabled.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What the watchdog does once it finds a stalled subsystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallAction {
+    /// Only log the stalled subsystem
+    Log,
+    /// Shut the manager down gracefully
+    Shutdown,
+    /// Abort the process
+    Abort,
+}
+
+/// Watchdog configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// Time a subsystem with calls pending may go without progress before it's considered stalled
+    pub stall_timeout: Duration,
+    /// What to do with a stalled subsystem
+    pub action: StallAction,
+}
+
+/// Liveness of a subsystem, updated by the subsystem and its handles.
+pub(crate) struct Liveness {
+    // Reference point of the progress times
+    epoch: Instant,
+    // When the subsystem last made progress, in milliseconds since the epoch
+    last_progress: AtomicU64,
+    // Number of calls sent to the subsystem
+    calls_sent: AtomicUsize,
+    // Number of calls taken from the queue by the subsystem
+    calls_taken: AtomicUsize,
+    // Set once the subsystem task is done
+    terminated: AtomicBool,
+}
+
+impl Liveness {
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            last_progress: AtomicU64::new(0),
+            calls_sent: AtomicUsize::new(0),
+            calls_taken: AtomicUsize::new(0),
+            terminated: AtomicBool::new(false),
+        }
+    }
+
+    fn elapsed_millis(&self) -> u64 {
+        self.epoch.elapsed().as_millis().try_into().unwrap_or(u64::MAX)
+    }
+
+    pub(crate) fn report_progress(&self) {
+        self.last_progress.store(self.elapsed_millis(), Ordering::Relaxed);
+    }
+
+    /// Record a call about to be sent to the subsystem
+    pub(crate) fn call_sent(&self) {
+        let sent = self.calls_sent.fetch_add(1, Ordering::Relaxed);
+        if sent == self.calls_taken.load(Ordering::Relaxed) {
+            // The queue was empty, so far the subsystem has been idle rather than stalled
+            self.report_progress();
+        }
+    }
+
+    /// Record a call taken from the queue by the subsystem
+    pub(crate) fn call_taken(&self) {
+        self.calls_taken.fetch_add(1, Ordering::Relaxed);
+        self.report_progress();
+    }
+
+    pub(crate) fn set_terminated(&self) {
+        self.terminated.store(true, Ordering::Relaxed);
+    }
+
+    fn health(&self, name: &'static str, stall_timeout: Duration) -> SubsystemHealth {
+        let pending_calls = self
+            .calls_sent
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.calls_taken.load(Ordering::Relaxed));
+        let since_progress = Duration::from_millis(
+            self.elapsed_millis().saturating_sub(self.last_progress.load(Ordering::Relaxed)),
+        );
+        let status = if self.terminated.load(Ordering::Relaxed) {
+            Status::Terminated
+        } else if pending_calls > 0 && since_progress > stall_timeout {
+            Status::Stalled
+        } else {
+            Status::Running
+        };
+        SubsystemHealth {
+            name,
+            status,
+            pending_calls,
+            since_progress,
+        }
+    }
+}
+
+/// Health status of a subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Running and making progress, or idle
+    Running,
+    /// Calls are pending but no progress has been made for the stall timeout
+    Stalled,
+    /// The subsystem task is done
+    Terminated,
+}
+
+/// Health of a subsystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsystemHealth {
+    /// Subsystem name
+    pub name: &'static str,
+    /// Health status
+    pub status: Status,
+    /// Number of calls waiting in the subsystem queue
+    pub pending_calls: usize,
+    /// Time since the subsystem last made progress, or since it was last idle
+    pub since_progress: Duration,
+}
+
+/// Liveness of the subsystems registered with a monitor, by subsystem name
+type Subsystems = Arc<Mutex<Vec<(&'static str, Arc<Liveness>)>>>;
+
+/// Reports the health of the subsystems of a manager, including the ones added later on.
+#[derive(Clone)]
+pub struct HealthMonitor {
+    subsystems: Subsystems,
+    stall_timeout: Duration,
+}
+
+impl HealthMonitor {
+    pub(crate) fn new(stall_timeout: Duration) -> Self {
+        Self {
+            subsystems: Arc::new(Mutex::new(Vec::new())),
+            stall_timeout,
+        }
+    }
+
+    pub(crate) fn register(&self, name: &'static str, liveness: Arc<Liveness>) {
+        self.subsystems
+            .lock()
+            .expect("Health monitor lock poisoned")
+            .push((name, liveness));
+    }
+
+    /// Health of the subsystems, in the order they were added in
+    pub fn report(&self) -> Vec<SubsystemHealth> {
+        let subsystems = self.subsystems.lock().expect("Health monitor lock poisoned");
+        subsystems
+            .iter()
+            .map(|&(name, ref liveness)| liveness.health(name, self.stall_timeout))
+            .collect()
+    }
+}
+
+/// Periodically check the subsystem health, logging the subsystems that stall or resume.
+#[cfg(all(feature = "time", not(loom)))]
+pub(crate) async fn run_watchdog(
+    manager_name: &'static str,
+    monitor: HealthMonitor,
+    action: StallAction,
+    shutdown_trigger: crate::manager::ShutdownTrigger,
+) {
+    use logging::log;
+
+    let period = (monitor.stall_timeout / 2).max(Duration::from_millis(1));
+    let mut interval = tokio::time::interval(period);
+    let mut stalled = std::collections::BTreeSet::new();
+    loop {
+        interval.tick().await;
+        for (index, health) in monitor.report().into_iter().enumerate() {
+            if health.status != Status::Stalled {
+                if stalled.remove(&index) {
+                    log::info!("Subsystem {}/{} resumed", manager_name, health.name);
+                }
+                continue;
+            }
+            if !stalled.insert(index) {
+                continue;
+            }
+            log::error!(
+                "Subsystem {}/{} stalled: no progress for {:?} with {} calls pending",
+                manager_name,
+                health.name,
+                health.since_progress,
+                health.pending_calls,
+            );
+            match action {
+                StallAction::Log => {}
+                StallAction::Shutdown => shutdown_trigger.initiate(),
+                StallAction::Abort => {
+                    log::error!("Manager {} aborting on a stalled subsystem", manager_name);
+                    std::process::abort()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stall_detection() {
+        let liveness = Liveness::new();
+        let stall_timeout = Duration::from_millis(5);
+        std::thread::sleep(Duration::from_millis(20));
+
+        // Idle for a while, not stalled
+        let health = liveness.health("test", stall_timeout);
+        assert_eq!(health.status, Status::Running);
+        assert_eq!(health.pending_calls, 0);
+
+        // A call sent to an idle subsystem restarts the clock
+        liveness.call_sent();
+        liveness.call_sent();
+        let health = liveness.health("test", stall_timeout);
+        assert_eq!(health.status, Status::Running);
+        assert_eq!(health.pending_calls, 2);
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            liveness.health("test", stall_timeout).status,
+            Status::Stalled
+        );
+
+        liveness.call_taken();
+        let health = liveness.health("test", stall_timeout);
+        assert_eq!(health.status, Status::Running);
+        assert_eq!(health.pending_calls, 1);
+
+        liveness.set_terminated();
+        assert_eq!(
+            liveness.health("test", stall_timeout).status,
+            Status::Terminated
+        );
+    }
+}
@@ -31,10 +31,22 @@
 //! The shutdown proceeds in three phases:
 //!
 //! 1. As soon as any subsystem terminates, the main task is notified.
-//! 2. The main task broadcasts the shutdown request to all subsystems. The subsystems react to the
-//!    request by shutting themselves down.
+//! 2. The main task sends the shutdown request to the subsystems one by one, in the reverse order
+//!    they were added in, waiting for each to terminate before moving on to the next. This way,
+//!    the subsystems taking outside work (e.g. RPC) stop before the subsystems they depend on.
 //! 3. The main task waits for all subsystems to terminate.
+//!
+//! The whole sequence is subject to the shutdown timeout. The shutdown is initiated by the
+//! termination signals (see [Manager::install_signal_handlers]), by a
+//! [ShutdownTrigger](manager::ShutdownTrigger), or by a future given to
+//! [Manager::main_until].
+//!
+//! ## Health
+//!
+//! The manager tracks whether the subsystems make progress on their calls. The [health] module
+//! reports the subsystem health and the optional watchdog acts on stalled subsystems.
 
+pub mod health;
 pub mod manager;
 pub mod subsystem;
 
@@ -18,11 +18,13 @@
 use core::future::Future;
 use core::time::Duration;
 use futures::future::{select_all, BoxFuture, FutureExt};
-use tokio::sync::{broadcast, mpsc};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::task;
 
 use logging::log;
 
+use crate::health::{HealthMonitor, Liveness, WatchdogConfig, DEFAULT_STALL_TIMEOUT};
 use crate::subsystem::{CallRequest, Handle, ShutdownRequest, Subsystem, SubsystemConfig};
 
 /// Manager configuration options.
@@ -31,6 +33,8 @@ pub struct ManagerConfig {
     name: &'static str,
     /// Shutdown timeout. Set to `None` for no (i.e. unlimited) timeout.
     shutdown_timeout: Option<Duration>,
+    /// Watchdog acting on stalled subsystems. Set to `None` to only report the health.
+    watchdog: Option<WatchdogConfig>,
 }
 
 impl ManagerConfig {
@@ -41,20 +45,25 @@ impl ManagerConfig {
         None
     };
 
-    fn named(name: &'static str) -> Self {
+    /// New configuration with given name, all other options are defaults.
+    pub fn named(name: &'static str) -> Self {
         Self {
             name,
             shutdown_timeout: Self::DEFAULT_SHUTDOWN_TIMEOUT,
+            watchdog: None,
         }
     }
+
+    /// Enable the watchdog, its stall timeout is also used to report the subsystem health.
+    pub fn with_watchdog(mut self, watchdog: WatchdogConfig) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
 }
 
 impl Default for ManagerConfig {
     fn default() -> Self {
-        Self {
-            name: "<manager>",
-            shutdown_timeout: Self::DEFAULT_SHUTDOWN_TIMEOUT,
-        }
+        Self::named("<manager>")
     }
 }
 
@@ -63,6 +72,9 @@ impl Default for ManagerConfig {
 /// An application is composed of a number of long-lived subsystems. The [Manager] type starts
 /// and manages the life cycle of the subsystems. Whenever a subsystem exits, all other subsystems
 /// are requested to terminate and the manager is shut down.
+///
+/// The subsystems are shut down one by one in the reverse order they were added in, so a
+/// subsystem can still use the subsystems added before it while shutting down.
 pub struct Manager {
     // Manager name
     name: &'static str,
@@ -70,8 +82,14 @@ pub struct Manager {
     // Shutdown timeout settings
     shutdown_timeout: Option<Duration>,
 
-    // Used by the manager to order all subsystems to shut down.
-    shutdown_request_tx: broadcast::Sender<()>,
+    // Watchdog settings
+    watchdog: Option<WatchdogConfig>,
+
+    // Health of the subsystems
+    health: HealthMonitor,
+
+    // Used by the manager to order the subsystems to shut down, in the order they were added.
+    subsystems: Vec<SubsystemShutdown>,
 
     // Used by a subsystem to notify the manager it is shutting down. This is taken as a command
     // for all subsystems to shut down. Shutdown completion is detected by all senders having closed
@@ -94,19 +112,23 @@ impl Manager {
         let ManagerConfig {
             name,
             shutdown_timeout,
+            watchdog,
         } = config;
         log::info!("Initializing subsystem manager {}", name);
 
-        let (shutdown_request_tx, _shutdown_request_rx) = broadcast::channel(1);
         let (shutting_down_tx, shutting_down_rx) = mpsc::channel(1);
         let subsystem_tasks = Vec::new();
 
         Self {
             name,
-            shutdown_request_tx,
+            subsystems: Vec::new(),
             shutting_down_tx,
             shutting_down_rx,
             shutdown_timeout,
+            watchdog,
+            health: HealthMonitor::new(
+                watchdog.map_or(DEFAULT_STALL_TIMEOUT, |watchdog| watchdog.stall_timeout),
+            ),
             subsystem_tasks,
         }
     }
@@ -146,18 +168,28 @@ impl Manager {
         // Name strings
         let manager_name = self.name;
         let subsys_name = config.subsystem_name;
+        let call_queue_capacity = config.call_queue_capacity;
         // Shutdown-related channels
         let shutting_down_tx = self.shutting_down_tx.clone();
-        let shutdown_rq = ShutdownRequest(self.shutdown_request_tx.subscribe());
+        let (shutdown_request_tx, shutdown_request_rx) = broadcast::channel(1);
+        let shutdown_rq = ShutdownRequest(shutdown_request_rx);
+        let (terminated_tx, terminated_rx) = oneshot::channel();
         // Call related channels
         let (action_tx, action_rx) = mpsc::channel(config.call_queue_capacity);
-        let call_rq = CallRequest(action_rx);
+        let liveness = Arc::new(Liveness::new());
+        let call_rq = CallRequest(action_rx, Arc::clone(&liveness));
+        self.health.register(subsys_name, Arc::clone(&liveness));
+        let task_liveness = Arc::clone(&liveness);
 
         self.subsystem_tasks.push(Box::pin(async move {
             log::info!("Subsystem {}/{} started", manager_name, subsys_name);
 
             // Perform the subsystem task.
             subsystem(call_rq, shutdown_rq).await;
+            task_liveness.set_terminated();
+
+            // Let the manager proceed with shutting down the subsystems added before this one.
+            std::mem::drop(terminated_tx);
 
             // Signal the intent to shut down to the other parts of the application.
             shutting_down_tx.send(()).await.expect("Subsystem outlived the manager!?");
@@ -168,9 +200,16 @@ impl Manager {
             std::mem::drop(shutting_down_tx);
         }));
 
+        self.subsystems.push(SubsystemShutdown {
+            name: subsys_name,
+            shutdown_request_tx,
+            requested: false,
+            terminated_rx,
+        });
+
         log::info!("Subsystem {}/{} initialized", manager_name, subsys_name);
 
-        Handle::new(action_tx)
+        Handle::new(action_tx, call_queue_capacity, liveness)
     }
 
     /// Add a passive subsystem.
@@ -208,29 +247,48 @@ impl Manager {
         self.add_subsystem_with_config(SubsystemConfig::named(name), subsys)
     }
 
-    /// Install termination signal handlers.
+    /// Add a subsystem that initiates the shutdown once the given future completes.
     ///
-    /// This adds a subsystem that listens for the Ctrl-C signal and exits once it is received,
-    /// signalling all other subsystems and the whole manager to shut down.
-    #[cfg(not(loom))]
-    pub fn install_signal_handlers(&mut self) {
+    /// This lets an embedding application drive the shutdown, e.g. from its own termination
+    /// logic.
+    pub fn add_shutdown_future(
+        &mut self,
+        name: &'static str,
+        future: impl 'static + Send + Future<Output = ()>,
+    ) {
         self.add_raw_subsystem(
-            "ctrl-c",
-            |mut call_rq: CallRequest<()>, mut shutdown_rq| async move {
+            name,
+            |_call_rq: CallRequest<()>, mut shutdown_rq| async move {
                 tokio::select! {
-                    ctrl_c_signal = tokio::signal::ctrl_c() => {
-                        if ctrl_c_signal.is_err() {
-                            log::info!("Ctrl-C signal handler failed");
-                        }
-                    }
-                    () = shutdown_rq.recv() => {},
-                    call = call_rq.recv() => { call(&mut ()); }
+                    () = future => {}
+                    () = shutdown_rq.recv() => {}
                 };
             },
         );
     }
 
-    async fn wait_for_shutdown(mut shutting_down_rx: mpsc::Receiver<()>) {
+    /// Install termination signal handlers.
+    ///
+    /// This adds a subsystem that listens for the Ctrl-C signal, as well as SIGTERM on Unix, and
+    /// exits once one is received, signalling all other subsystems and the whole manager to shut
+    /// down.
+    #[cfg(not(loom))]
+    pub fn install_signal_handlers(&mut self) {
+        self.add_shutdown_future("signals", termination_signal());
+    }
+
+    async fn wait_for_shutdown(
+        subsystems: &mut [SubsystemShutdown],
+        shutting_down_rx: &mut mpsc::Receiver<()>,
+    ) {
+        // Shut the subsystems down one by one, the most recently added first.
+        for subsys in subsystems.iter_mut().rev() {
+            subsys.request_shutdown();
+            // The sender is dropped once the subsystem terminates, even if it panics.
+            let _ = (&mut subsys.terminated_rx).await;
+            log::debug!("Subsystem {} shut down", subsys.name);
+        }
+
         // Wait for the subsystems to go down, signalled by closing the shutting_down channel.
         while let Some(()) = shutting_down_rx.recv().await {}
     }
@@ -238,16 +296,20 @@ impl Manager {
     #[allow(unused)]
     async fn wait_for_shutdown_with_timeout(
         name: &'static str,
-        shutting_down_rx: mpsc::Receiver<()>,
+        subsystems: &mut [SubsystemShutdown],
+        shutting_down_rx: &mut mpsc::Receiver<()>,
         timeout: Option<Duration>,
     ) {
-        let shutdown_future = Self::wait_for_shutdown(shutting_down_rx);
+        let shutdown_future = Self::wait_for_shutdown(subsystems, shutting_down_rx);
         if let Some(timeout) = timeout {
             cfg_if::cfg_if! {
                 if #[cfg(all(feature = "time", not(loom)))] {
                     // Wait for shutdown under a timeout
-                    if let Err(elapsed) = tokio::time::timeout(timeout, shutdown_future).await {
+                    let timed_out = tokio::time::timeout(timeout, shutdown_future).await.is_err();
+                    if timed_out {
                         log::error!("Manager {} shutdown timed out", name);
+                        // Order the subsystems not reached yet to shut down all at once.
+                        subsystems.iter_mut().for_each(SubsystemShutdown::request_shutdown);
                     }
                 } else {
                     // Timeout was requested but is not supported
@@ -265,6 +327,11 @@ impl Manager {
         }
     }
 
+    /// Create a monitor reporting the health of the subsystems
+    pub fn health_monitor(&self) -> HealthMonitor {
+        self.health.clone()
+    }
+
     /// Create a trigger object that can be used to shut down the system
     pub fn make_shutdown_trigger(&self) -> ShutdownTrigger {
         ShutdownTrigger(self.shutting_down_tx.clone())
@@ -284,6 +351,21 @@ impl Manager {
         }
         */
 
+        // Watch the subsystems for stalls until the shutdown starts
+        #[cfg(all(feature = "time", not(loom)))]
+        let watchdog = self.watchdog.map(|watchdog| {
+            task::spawn(crate::health::run_watchdog(
+                self.name,
+                self.health.clone(),
+                watchdog.action,
+                ShutdownTrigger(self.shutting_down_tx.clone()),
+            ))
+        });
+        #[cfg(not(all(feature = "time", not(loom))))]
+        if self.watchdog.is_some() {
+            log::error!("Watchdog support not compiled in");
+        }
+
         // Signal the manager is shut down so it does not wait for itself
         std::mem::drop(self.shutting_down_tx);
 
@@ -322,19 +404,94 @@ impl Manager {
 
         log::info!("Manager {} shutting down", self.name);
 
-        // Order all the remaining subsystems to shut down.
-        let _ = self.shutdown_request_tx.send(());
+        // Stop the watchdog, the shutdown is bounded by its own timeout
+        #[cfg(all(feature = "time", not(loom)))]
+        if let Some(watchdog) = watchdog {
+            watchdog.abort();
+        }
 
-        // Wait for the subsystems to go down.
+        // Order the subsystems to shut down and wait for them to go down.
         Self::wait_for_shutdown_with_timeout(
             self.name,
-            self.shutting_down_rx,
+            &mut self.subsystems,
+            &mut self.shutting_down_rx,
             self.shutdown_timeout,
         )
         .await;
 
         log::info!("Manager {} terminated", self.name);
     }
+
+    /// Run the application main task, shutting down once the given future completes.
+    ///
+    /// Completes when all the subsystems are fully shut down. See [Manager::add_shutdown_future].
+    pub async fn main_until(mut self, shutdown: impl 'static + Send + Future<Output = ()>) {
+        self.add_shutdown_future("shutdown-future", shutdown);
+        self.main().await
+    }
+}
+
+/// Shutdown state of a subsystem, as seen by the manager.
+struct SubsystemShutdown {
+    // Subsystem name
+    name: &'static str,
+
+    // Used to order the subsystem to shut down. It is kept open until the manager terminates.
+    shutdown_request_tx: broadcast::Sender<()>,
+
+    // Whether the shutdown request has been sent.
+    requested: bool,
+
+    // Closed once the subsystem task is done.
+    terminated_rx: oneshot::Receiver<()>,
+}
+
+impl SubsystemShutdown {
+    fn request_shutdown(&mut self) {
+        if !self.requested {
+            self.requested = true;
+            // The receiver is gone if the subsystem has already terminated.
+            let _ = self.shutdown_request_tx.send(());
+        }
+    }
+}
+
+/// Wait for Ctrl-C or, on Unix, SIGTERM.
+///
+/// A signal that can't be listened for is logged and ignored.
+#[cfg(not(loom))]
+async fn termination_signal() {
+    let ctrl_c = async {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => log::info!("Ctrl-C received"),
+            Err(e) => {
+                log::error!("Ctrl-C signal handler failed: {}", e);
+                std::future::pending().await
+            }
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+                log::info!("SIGTERM received");
+            }
+            Err(e) => {
+                log::error!("SIGTERM signal handler failed: {}", e);
+                std::future::pending().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
 }
 
 /// Used to initiate shutdown of manager and subsystems.
@@ -364,6 +521,7 @@ mod test {
         let mut man = Manager::new_with_config(ManagerConfig {
             name: "timeout_test",
             shutdown_timeout: Some(Duration::from_secs(1)),
+            watchdog: None,
         });
 
         man.add_raw_subsystem(
@@ -377,4 +535,50 @@ mod test {
             assert!(logs.iter().any(|entry| entry.body.contains("shutdown timed out")));
         });
     }
+
+    #[tokio::test]
+    async fn shutdown_order() {
+        let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut man = Manager::new("order_test");
+
+        for name in ["first", "second", "third"] {
+            let order = std::sync::Arc::clone(&order);
+            man.add_raw_subsystem(
+                name,
+                move |_call_rq: CallRequest<()>, mut shut_rq| async move {
+                    shut_rq.recv().await;
+                    order.lock().unwrap().push(name);
+                },
+            );
+        }
+        man.main_until(async {}).await;
+
+        assert_eq!(*order.lock().unwrap(), ["third", "second", "first"]);
+    }
+
+    #[cfg(all(feature = "time", not(loom)))]
+    #[tokio::test]
+    async fn watchdog_shutdown() {
+        use crate::health::{StallAction, Status};
+
+        let config = ManagerConfig::named("watchdog_test").with_watchdog(WatchdogConfig {
+            stall_timeout: Duration::from_millis(100),
+            action: StallAction::Shutdown,
+        });
+        let mut man = Manager::new_with_config(config);
+        let handle = man.add_raw_subsystem(
+            "stuck",
+            |_call_rq: CallRequest<()>, mut shut_rq| async move { shut_rq.recv().await },
+        );
+        let monitor = man.health_monitor();
+
+        // The call is never taken, the watchdog shuts the manager down
+        tokio::spawn(async move { handle.call(|_| ()).await });
+        man.main().await;
+
+        let health = monitor.report();
+        assert_eq!(health.len(), 1);
+        assert_eq!(health[0].name, "stuck");
+        assert_eq!(health[0].status, Status::Terminated);
+    }
 }
@@ -15,9 +15,13 @@
 //
 // Author(s): L. Kuklinek
 
+use std::sync::Arc;
+
 use futures::future::BoxFuture;
 use tokio::sync::{broadcast, mpsc, oneshot};
 
+use crate::health::Liveness;
+
 /// Defines hooks into a subsystem lifecycle.
 #[async_trait::async_trait]
 pub trait Subsystem: 'static + Send + Sized {
@@ -59,18 +63,32 @@ impl Default for SubsystemConfig {
 type Action<T, R> = Box<dyn Send + for<'a> FnOnce(&'a mut T) -> BoxFuture<'a, R>>;
 
 /// Call request
-pub struct CallRequest<T>(pub(crate) mpsc::Receiver<Action<T, ()>>);
+pub struct CallRequest<T>(
+    pub(crate) mpsc::Receiver<Action<T, ()>>,
+    pub(crate) Arc<Liveness>,
+);
 
 impl<T: 'static> CallRequest<T> {
     /// Receive an external call to this subsystem.
     pub async fn recv(&mut self) -> Action<T, ()> {
         match self.0.recv().await {
             // We have a call, return it
-            Some(action) => action,
+            Some(action) => {
+                self.1.call_taken();
+                action
+            }
             // All handles to this subsystem dropped, suspend call handling.
             None => std::future::pending().await,
         }
     }
+
+    /// Report progress to the health monitor.
+    ///
+    /// Taking a call counts as progress. Subsystems doing long running work between the calls
+    /// report progress explicitly so they are not considered stalled.
+    pub fn report_progress(&self) {
+        self.1.report_progress()
+    }
 }
 
 /// Shutdown request
@@ -98,12 +116,18 @@ impl ShutdownRequest {
 pub struct Handle<T> {
     // Send the subsystem stuff to do.
     action_tx: mpsc::Sender<Action<T, ()>>,
+    // Capacity of the call request channel.
+    call_queue_capacity: usize,
+    // Liveness of the subsystem, for the health monitor.
+    liveness: Arc<Liveness>,
 }
 
 impl<T> Clone for Handle<T> {
     fn clone(&self) -> Self {
         Self {
             action_tx: self.action_tx.clone(),
+            call_queue_capacity: self.call_queue_capacity,
+            liveness: Arc::clone(&self.liveness),
         }
     }
 }
@@ -116,8 +140,21 @@ pub enum CallError {
 
 impl<T: Send + 'static> Handle<T> {
     /// Crate a new subsystem handle.
-    pub(crate) fn new(action_tx: mpsc::Sender<Action<T, ()>>) -> Self {
-        Self { action_tx }
+    pub(crate) fn new(
+        action_tx: mpsc::Sender<Action<T, ()>>,
+        call_queue_capacity: usize,
+        liveness: Arc<Liveness>,
+    ) -> Self {
+        Self {
+            action_tx,
+            call_queue_capacity,
+            liveness,
+        }
+    }
+
+    /// Number of calls queued in the subsystem call channel, waiting to be handled
+    pub fn pending_calls(&self) -> usize {
+        self.call_queue_capacity - self.action_tx.capacity()
     }
 
     /// Dispatch an async function call to the subsystem
@@ -127,6 +164,7 @@ impl<T: Send + 'static> Handle<T> {
     ) -> Result<R, CallError> {
         let (rtx, rrx) = oneshot::channel::<R>();
 
+        self.liveness.call_sent();
         self.action_tx
             .send(Box::new(move |subsys| {
                 Box::pin(async move {
@@ -178,4 +216,18 @@ mod test {
             SubsystemConfig::DEFAULT_CALL_QUEUE_CAPACITY
         );
     }
+    #[test]
+    fn pending_calls() {
+        let (action_tx, mut action_rx) = mpsc::channel::<Action<(), ()>>(4);
+        let handle = Handle::new(action_tx, 4, Arc::new(Liveness::new()));
+        assert_eq!(handle.pending_calls(), 0);
+
+        let noop = || -> Action<(), ()> { Box::new(|_| Box::pin(async {})) };
+        assert!(handle.action_tx.try_send(noop()).is_ok());
+        assert!(handle.action_tx.try_send(noop()).is_ok());
+        assert_eq!(handle.pending_calls(), 2);
+
+        assert!(action_rx.try_recv().is_ok());
+        assert_eq!(handle.pending_calls(), 1);
+    }
 }
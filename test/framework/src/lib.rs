@@ -0,0 +1,65 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functional test framework
+//!
+//! Runs a network of nodes in the test process. Each node has its own subsystem manager with an
+//! in-memory chainstate and a p2p subsystem on the mock networking backend, and the nodes talk
+//! to each other over local TCP connections. Unlike the Python functional tests, no node binary
+//! is needed, so the scenarios run as ordinary `cargo test` integration tests.
+//!
+//! [TestNetwork::start_simulated] connects the nodes over a simulated network instead of TCP,
+//! where a seeded RNG controls the message latencies and drops. Run on a paused tokio clock,
+//! such a test reproduces the interleaving of the messages of a failed run from its seed.
+//!
+//! The helpers panic on failure, as the tests using them would. The waiting helpers give up
+//! after [TIMEOUT].
+//!
+//! ```ignore
+//! let mut net = TestNetwork::start(2).await;
+//! net.connect(0, 1).await;
+//! net.node_mut(0).mine_blocks(3).await;
+//! net.sync_all().await;
+//! ```
+
+mod network;
+mod node;
+
+use std::time::Duration;
+
+pub use crate::{network::TestNetwork, node::TestNode};
+
+/// How long the waiting helpers wait for a condition before failing the test
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the waiting helpers check the condition
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Wait until the condition is true, panicking with `what` if it isn't before the timeout
+async fn wait_until<F, Fut>(what: impl std::fmt::Display, mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let res = tokio::time::timeout(TIMEOUT, async {
+        while !condition().await {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    })
+    .await;
+    if res.is_err() {
+        panic!("Timed out waiting for {}", what);
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use common::chain::{config::create_regtest, ChainConfig};
+use p2p::net::mock::sim::{SimConfig, SimNetwork};
+
+use crate::{node::TestNode, wait_until};
+
+/// Nodes of a test network, referred to by their index
+///
+/// The network remembers the connections made with [TestNetwork::connect] so a partition can be
+/// healed by reconnecting the nodes. A partition is made by closing connections, the peer
+/// managers may still reconnect the nodes to the addresses they learn from their peers later on.
+pub struct TestNetwork {
+    nodes: Vec<TestNode>,
+    /// Connections as (outbound, inbound) node index pairs
+    connections: BTreeSet<(usize, usize)>,
+    /// Connections closed by the current partition
+    severed: BTreeSet<(usize, usize)>,
+}
+
+impl TestNetwork {
+    /// Start `count` unconnected nodes on regtest
+    pub async fn start(count: usize) -> Self {
+        Self::start_with_config(count, Arc::new(create_regtest())).await
+    }
+
+    pub async fn start_with_config(count: usize, chain_config: Arc<ChainConfig>) -> Self {
+        let mut nodes = Vec::with_capacity(count);
+        for _ in 0..count {
+            nodes.push(TestNode::start(Arc::clone(&chain_config)).await);
+        }
+        Self::from_nodes(nodes)
+    }
+
+    /// Start `count` unconnected nodes on regtest over a simulated network
+    ///
+    /// See [p2p::net::mock::sim] for how the simulation is made deterministic.
+    pub async fn start_simulated(count: usize, config: SimConfig) -> Self {
+        let chain_config = Arc::new(create_regtest());
+        let network = SimNetwork::start(config);
+        let mut nodes = Vec::with_capacity(count);
+        for _ in 0..count {
+            nodes.push(TestNode::start_simulated(Arc::clone(&chain_config), &network).await);
+        }
+        Self::from_nodes(nodes)
+    }
+
+    fn from_nodes(nodes: Vec<TestNode>) -> Self {
+        Self {
+            nodes,
+            connections: BTreeSet::new(),
+            severed: BTreeSet::new(),
+        }
+    }
+
+    /// Start `count` nodes, each connected to the previous one
+    pub async fn start_connected(count: usize) -> Self {
+        let mut net = Self::start(count).await;
+        for index in 1..count {
+            net.connect(index, index - 1).await;
+        }
+        net
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, index: usize) -> &TestNode {
+        &self.nodes[index]
+    }
+
+    pub fn node_mut(&mut self, index: usize) -> &mut TestNode {
+        &mut self.nodes[index]
+    }
+
+    /// Connect node `from` to node `to` and wait until both have accepted the connection
+    pub async fn connect(&mut self, from: usize, to: usize) {
+        let (a, b) = (&self.nodes[from], &self.nodes[to]);
+        a.connect(b).await;
+        wait_until(
+            format!("nodes {} and {} to connect", from, to),
+            move || async move { a.is_connected_to(b).await && b.is_connected_to(a).await },
+        )
+        .await;
+        self.connections.insert((from, to));
+    }
+
+    /// Close the connection between two nodes and wait until both have noticed
+    pub async fn disconnect(&mut self, from: usize, to: usize) {
+        let (a, b) = (&self.nodes[from], &self.nodes[to]);
+        a.disconnect(b).await;
+        wait_until(
+            format!("nodes {} and {} to disconnect", from, to),
+            move || async move { !a.is_connected_to(b).await && !b.is_connected_to(a).await },
+        )
+        .await;
+        self.connections.remove(&(from, to));
+    }
+
+    /// Split the network by closing the connections between the nodes of different groups
+    ///
+    /// The nodes that are in no group keep their connections.
+    pub async fn partition(&mut self, groups: &[&[usize]]) {
+        let group_of = |index: usize| groups.iter().position(|group| group.contains(&index));
+        let crossing: Vec<_> = self
+            .connections
+            .iter()
+            .copied()
+            .filter(|&(from, to)| match (group_of(from), group_of(to)) {
+                (Some(a), Some(b)) => a != b,
+                _ => false,
+            })
+            .collect();
+        for (from, to) in crossing {
+            self.disconnect(from, to).await;
+            self.severed.insert((from, to));
+        }
+    }
+
+    /// Restore the connections closed by the partition
+    pub async fn heal(&mut self) {
+        for (from, to) in std::mem::take(&mut self.severed) {
+            self.connect(from, to).await;
+        }
+    }
+
+    /// Wait until the given nodes have the same best block
+    pub async fn wait_for_sync(&self, indices: &[usize]) {
+        let nodes: Vec<_> = indices.iter().map(|&index| &self.nodes[index]).collect();
+        let nodes = &nodes;
+        wait_until(format!("nodes {:?} to sync", indices), move || async move {
+            let mut tips = Vec::with_capacity(nodes.len());
+            for node in nodes {
+                tips.push(node.best_block_id().await);
+            }
+            tips.windows(2).all(|pair| pair[0] == pair[1])
+        })
+        .await;
+    }
+
+    /// Wait until all nodes have the same best block
+    pub async fn sync_all(&self) {
+        let indices: Vec<_> = (0..self.nodes.len()).collect();
+        self.wait_for_sync(&indices).await;
+    }
+
+    /// Shut all nodes down
+    pub async fn shutdown(self) {
+        for node in self.nodes {
+            node.shutdown().await;
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use chainstate::{BlockSource, ChainstateHandle};
+use common::{
+    chain::{
+        block::{consensus_data::PoWData, Block, ConsensusData},
+        ChainConfig, Destination, Transaction,
+    },
+    primitives::{BlockHeight, Id, Idable},
+    Uint256,
+};
+use p2p::{
+    net::mock::{sim::SimNetwork, MockService},
+    P2pHandle,
+};
+use subsystem::manager::ShutdownTrigger;
+use tokio::task::JoinHandle;
+
+/// A node running in the test process
+pub struct TestNode {
+    chain_config: Arc<ChainConfig>,
+    address: SocketAddr,
+    chainstate: ChainstateHandle,
+    p2p: P2pHandle<MockService>,
+    /// Transactions to be included in the next block mined by the node
+    pending_transactions: Vec<Transaction>,
+    shutdown_trigger: ShutdownTrigger,
+    manager_task: JoinHandle<()>,
+}
+
+impl TestNode {
+    /// Start a node with an empty chainstate, listening on a free local port
+    pub async fn start(chain_config: Arc<ChainConfig>) -> Self {
+        Self::start_inner(chain_config, None).await
+    }
+
+    /// Start a node with an empty chainstate on a simulated network
+    pub async fn start_simulated(chain_config: Arc<ChainConfig>, network: &SimNetwork) -> Self {
+        Self::start_inner(chain_config, Some(network)).await
+    }
+
+    async fn start_inner(chain_config: Arc<ChainConfig>, network: Option<&SimNetwork>) -> Self {
+        let storage = blockchain_storage::Store::new_empty().expect("Storage creation failed");
+
+        let mut manager = subsystem::Manager::new("test-node");
+        let chainstate = manager.add_subsystem(
+            "chainstate",
+            chainstate::make_chainstate(Arc::clone(&chain_config), storage, None)
+                .expect("Chainstate creation failed"),
+        );
+        let p2p = match network {
+            Some(network) => p2p::make_p2p_simulated(
+                Arc::clone(&chain_config),
+                Default::default(),
+                chainstate.clone(),
+                network,
+                "[::1]:0".parse().expect("valid address"),
+            ),
+            None => {
+                let address: SocketAddr = test_utils::make_address("[::1]:");
+                p2p::make_p2p::<MockService>(
+                    Arc::clone(&chain_config),
+                    Default::default(),
+                    chainstate.clone(),
+                    address.to_string(),
+                )
+                .await
+            }
+        };
+        let p2p =
+            manager.add_subsystem("p2p", p2p.expect("The p2p subsystem initialization failed"));
+        let shutdown_trigger = manager.make_shutdown_trigger();
+        let manager_task = tokio::spawn(manager.main());
+
+        let address = p2p
+            .call_async(|this| Box::pin(this.get_bind_address()))
+            .await
+            .expect("P2P subsystem is dead")
+            .expect("Bind address lookup failed")
+            .parse()
+            .expect("Invalid bind address");
+
+        Self {
+            chain_config,
+            address,
+            chainstate,
+            p2p,
+            pending_transactions: Vec::new(),
+            shutdown_trigger,
+            manager_task,
+        }
+    }
+
+    pub fn chain_config(&self) -> &Arc<ChainConfig> {
+        &self.chain_config
+    }
+
+    /// Address the node listens on, also the peer ID other nodes know the node by
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn chainstate(&self) -> &ChainstateHandle {
+        &self.chainstate
+    }
+
+    pub fn p2p(&self) -> &P2pHandle<MockService> {
+        &self.p2p
+    }
+
+    pub async fn best_block_id(&self) -> Id<Block> {
+        self.chainstate
+            .call(|this| this.get_best_block_id())
+            .await
+            .expect("Chainstate subsystem is dead")
+            .expect("Best block ID lookup failed")
+    }
+
+    pub async fn best_block_height(&self) -> BlockHeight {
+        self.chainstate
+            .call(|this| this.get_best_block_height())
+            .await
+            .expect("Chainstate subsystem is dead")
+            .expect("Best block height lookup failed")
+    }
+
+    /// Whether the transaction is in a block of the main chain
+    pub async fn has_transaction(&self, tx_id: Id<Transaction>) -> bool {
+        self.chainstate
+            .call(move |this| this.get_mainchain_tx(&tx_id))
+            .await
+            .expect("Chainstate subsystem is dead")
+            .expect("Transaction lookup failed")
+            .is_some()
+    }
+
+    /// Submit a transaction to the node
+    ///
+    /// There is no mempool yet, the submitted transactions are included in the next block the
+    /// node mines. They are validated when that block is processed.
+    pub fn submit_transaction(&mut self, tx: Transaction) {
+        self.pending_transactions.push(tx);
+    }
+
+    /// Mine a block on top of the current tip, with the submitted transactions
+    pub async fn mine_block(&mut self) -> Id<Block> {
+        let template = self
+            .chainstate
+            .call(|this| this.get_block_template(Destination::AnyoneCanSpend))
+            .await
+            .expect("Chainstate subsystem is dead")
+            .expect("Block template creation failed");
+
+        let transactions = std::mem::take(&mut self.pending_transactions);
+        let mut block = Block::new(
+            transactions,
+            template.prev_block_id(),
+            template.block_time(),
+            template.consensus_data().clone(),
+        )
+        .expect("Block creation failed");
+        if let ConsensusData::PoW(pow_data) = template.consensus_data() {
+            solve_pow(&mut block, pow_data);
+        }
+
+        let block_id = block.get_id();
+        self.chainstate
+            .call_mut(move |this| this.process_block(block, BlockSource::Local))
+            .await
+            .expect("Chainstate subsystem is dead")
+            .expect("Mined block was rejected");
+        block_id
+    }
+
+    /// Mine `count` blocks on top of the current tip, the first one with the submitted
+    /// transactions
+    pub async fn mine_blocks(&mut self, count: usize) -> Vec<Id<Block>> {
+        let mut block_ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            block_ids.push(self.mine_block().await);
+        }
+        block_ids
+    }
+
+    /// Open a connection to another node
+    pub async fn connect(&self, other: &TestNode) {
+        let address = other.address.to_string();
+        self.p2p
+            .call_async_mut(|this| Box::pin(this.connect(address)))
+            .await
+            .expect("P2P subsystem is dead")
+            .expect("Connection failed");
+    }
+
+    /// Close the connection to another node
+    pub async fn disconnect(&self, other: &TestNode) {
+        let peer_id = other.address.to_string();
+        self.p2p
+            .call_async(|this| Box::pin(this.disconnect(peer_id)))
+            .await
+            .expect("P2P subsystem is dead")
+            .expect("Disconnection failed");
+    }
+
+    /// Whether the node has an accepted connection with another node
+    pub async fn is_connected_to(&self, other: &TestNode) -> bool {
+        let peer_id = other.address.to_string();
+        self.p2p
+            .call_async(|this| Box::pin(this.get_connected_peers()))
+            .await
+            .expect("P2P subsystem is dead")
+            .expect("Connected peers lookup failed")
+            .iter()
+            .any(|peer| peer.peer_id == peer_id)
+    }
+
+    /// Shut the node's subsystems down
+    pub async fn shutdown(self) {
+        let Self {
+            shutdown_trigger,
+            manager_task,
+            ..
+        } = self;
+        shutdown_trigger.initiate();
+        // the manager waits for all the triggers to be dropped before it terminates
+        drop(shutdown_trigger);
+        manager_task.await.expect("Subsystem manager panicked");
+    }
+}
+
+/// Search for the nonce that makes the block meet its target
+fn solve_pow(block: &mut Block, pow_data: &PoWData) {
+    let target = Uint256::try_from(pow_data.bits()).expect("Invalid block target");
+    let mut pow_data = pow_data.clone();
+    for nonce in 0u128.. {
+        pow_data.update_nonce(nonce);
+        block.update_consensus_data(ConsensusData::PoW(pow_data.clone()));
+        let hash: Uint256 = block.get_id().get().into();
+        if hash <= target {
+            return;
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use common::primitives::BlockHeight;
+use test_framework::TestNetwork;
+
+// both sides of a partition extend the chain, once the partition heals the nodes of the
+// shorter side reorg to the longer chain
+#[tokio::test]
+async fn reorg_after_partition() {
+    let mut net = TestNetwork::start_connected(3).await;
+    net.node_mut(0).mine_blocks(2).await;
+    net.sync_all().await;
+
+    net.partition(&[&[0], &[1, 2]]).await;
+    let short_tip = net.node_mut(0).mine_blocks(2).await.pop().unwrap();
+    let long_tip = net.node_mut(2).mine_blocks(3).await.pop().unwrap();
+    net.wait_for_sync(&[1, 2]).await;
+    assert_eq!(net.node(0).best_block_id().await, short_tip);
+    assert_eq!(net.node(1).best_block_id().await, long_tip);
+
+    net.heal().await;
+    net.sync_all().await;
+    assert_eq!(net.node(0).best_block_id().await, long_tip);
+    assert_eq!(net.node(0).best_block_height().await, BlockHeight::new(5));
+
+    net.shutdown().await;
+}
+
+// the longer chain mined by an isolated node wins once the node rejoins the network
+#[tokio::test]
+async fn isolated_node_rejoins() {
+    let mut net = TestNetwork::start_connected(3).await;
+
+    net.partition(&[&[0, 1], &[2]]).await;
+    net.node_mut(2).mine_blocks(4).await;
+    let tip = net.node_mut(0).mine_blocks(1).await.pop().unwrap();
+    net.wait_for_sync(&[0, 1]).await;
+    assert_eq!(net.node(1).best_block_id().await, tip);
+
+    net.heal().await;
+    net.sync_all().await;
+    assert_eq!(net.node(0).best_block_height().await, BlockHeight::new(4));
+
+    net.shutdown().await;
+}
@@ -0,0 +1,55 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use common::primitives::BlockHeight;
+use p2p::net::mock::sim::SimConfig;
+use test_framework::TestNetwork;
+
+// the nodes of a simulated network converge after a partition whatever the interleaving of
+// their messages, a failing seed reproduces the same interleaving
+#[tokio::test(start_paused = true)]
+async fn reorg_after_partition_simulated() {
+    for seed in 0..4 {
+        let config =
+            SimConfig::new(seed).with_latency(Duration::from_millis(1), Duration::from_millis(200));
+        let mut net = TestNetwork::start_simulated(4, config).await;
+        for index in 1..4 {
+            net.connect(index, index - 1).await;
+        }
+        net.connect(3, 0).await;
+
+        net.node_mut(0).mine_blocks(2).await;
+        net.sync_all().await;
+
+        net.partition(&[&[0, 1], &[2, 3]]).await;
+        net.node_mut(0).mine_blocks(2).await;
+        net.node_mut(1).mine_blocks(1).await;
+        net.node_mut(3).mine_blocks(4).await;
+        net.wait_for_sync(&[2, 3]).await;
+
+        net.heal().await;
+        net.sync_all().await;
+        assert_eq!(
+            net.node(1).best_block_height().await,
+            BlockHeight::new(6),
+            "seed {}",
+            seed
+        );
+
+        net.shutdown().await;
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use common::{
+    chain::{
+        signature::inputsig::InputWitness, Destination, OutPointSourceId, Transaction, TxInput,
+        TxOutput,
+    },
+    primitives::{BlockHeight, Idable},
+};
+use test_framework::TestNetwork;
+
+// Port of `p2p_syncing_test.py`: blocks mined while the nodes are apart are downloaded once
+// they connect, the blocks mined afterwards are announced
+#[tokio::test]
+async fn sync_after_connect() {
+    let mut net = TestNetwork::start(2).await;
+    net.connect(0, 1).await;
+    net.sync_all().await;
+    assert_eq!(net.node(1).best_block_height().await, BlockHeight::new(0));
+
+    net.disconnect(0, 1).await;
+    net.node_mut(0).mine_blocks(2).await;
+    assert_eq!(net.node(0).best_block_height().await, BlockHeight::new(2));
+    assert_eq!(net.node(1).best_block_height().await, BlockHeight::new(0));
+
+    net.connect(0, 1).await;
+    net.sync_all().await;
+    assert_eq!(net.node(1).best_block_height().await, BlockHeight::new(2));
+
+    let tip = net.node_mut(0).mine_blocks(2).await.pop().unwrap();
+    net.sync_all().await;
+    assert_eq!(net.node(1).best_block_id().await, tip);
+    assert_eq!(net.node(1).best_block_height().await, BlockHeight::new(4));
+
+    net.shutdown().await;
+}
+
+// blocks propagate along a line of nodes, whichever end they are mined at
+#[tokio::test]
+async fn sync_line() {
+    let mut net = TestNetwork::start_connected(4).await;
+
+    net.node_mut(0).mine_blocks(3).await;
+    net.sync_all().await;
+    assert_eq!(net.node(3).best_block_height().await, BlockHeight::new(3));
+
+    let tip = net.node_mut(3).mine_blocks(2).await.pop().unwrap();
+    net.sync_all().await;
+    assert_eq!(net.node(0).best_block_id().await, tip);
+
+    net.shutdown().await;
+}
+
+// a transaction submitted to one node is mined and reaches the other nodes in its block
+#[tokio::test]
+async fn transaction_propagation() {
+    let mut net = TestNetwork::start_connected(2).await;
+
+    let genesis = net.node(0).chain_config().genesis_block().clone();
+    let genesis_tx = &genesis.transactions()[0];
+    let tx = Transaction::new(
+        0,
+        vec![TxInput::new(
+            OutPointSourceId::Transaction(genesis_tx.get_id()),
+            0,
+            InputWitness::NoSignature(None),
+        )],
+        vec![TxOutput::new(
+            genesis_tx.get_outputs()[0].get_value(),
+            Destination::AnyoneCanSpend,
+        )],
+        0,
+    )
+    .unwrap();
+    let tx_id = tx.get_id();
+
+    net.node_mut(0).submit_transaction(tx);
+    net.node_mut(0).mine_block().await;
+    net.sync_all().await;
+    assert!(net.node(0).has_transaction(tx_id.clone()).await);
+    assert!(net.node(1).has_transaction(tx_id).await);
+
+    net.shutdown().await;
+}
@@ -29,6 +29,7 @@ from .util import (
     PortSeed,
     assert_equal,
     check_json_precision,
+    connected_peer_ids,
     get_datadir_path,
     initialize_datadir,
     p2p_port,
@@ -565,8 +566,8 @@ class BitcoinTestFramework(metaclass=BitcoinTestMetaClass):
         addr_a = p2p_url(a) + "/p2p/" + id_a
         ret = self.nodes[b].p2p_connect(addr_a)
         wait_until_helper(lambda:
-            self.nodes[b].p2p_get_connected_peers().count(id_a) != 0 and
-            self.nodes[a].p2p_get_connected_peers().count(id_b) != 0, timeout=60)
+            connected_peer_ids(self.nodes[b]).count(id_a) != 0 and
+            connected_peer_ids(self.nodes[a]).count(id_b) != 0, timeout=60)
 
     def disconnect_nodes(self, a, b):
         id_a = self.nodes[a].p2p_get_peer_id()
@@ -575,8 +576,8 @@ class BitcoinTestFramework(metaclass=BitcoinTestMetaClass):
         self.nodes[a].p2p_disconnect(id_b)
 
         wait_until_helper(lambda:
-            self.nodes[b].p2p_get_connected_peers().count(id_a) == 0 and
-            self.nodes[a].p2p_get_connected_peers().count(id_b) == 0, timeout=60)
+            connected_peer_ids(self.nodes[b]).count(id_a) == 0 and
+            connected_peer_ids(self.nodes[a]).count(id_b) == 0, timeout=60)
 
     def split_network(self):
         """
@@ -327,6 +327,9 @@ def p2p_port(n):
 def p2p_url(n):
     return "/ip4/127.0.0.1/tcp/" + str(p2p_port(n))
 
+def connected_peer_ids(node):
+    return [peer["peer_id"] for peer in node.p2p_get_connected_peers()]
+
 def rpc_port(n):
     return PORT_MIN + PORT_RANGE + n + (MAX_NODES * PortSeed.n) % (PORT_RANGE - 1 - MAX_NODES)
 
@@ -0,0 +1,612 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Coin selection, choosing the outputs of the wallet that a transaction spends
+//!
+//! The strategies work with the effective values of the outputs, their values less the fee of
+//! spending them, so a selection always pays for its own inputs and the outputs worth less than
+//! the fee of spending them are never selected.
+//!
+//! Selections are scored by their waste, the cost of the selection compared to spending the same
+//! outputs at the long term fee rate and without excess: for every input the difference between
+//! its fee and its fee at the long term fee rate, plus either the cost of the change output,
+//! which is the fee of creating it and of spending it later, or the excess left to the fee when
+//! there's no change output.
+
+use common::primitives::{amount::IntType, Amount, SignedAmount};
+use crypto::random::{seq::SliceRandom, Rng};
+
+use crate::{FeeRate, WalletError};
+
+/// Number of steps after which the branch and bound search gives up looking for a better selection
+const BNB_MAX_TRIES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoinSelectionStrategy {
+    /// Spend the outputs with the largest values first, which keeps the number of inputs low
+    LargestFirst,
+    /// Search for the selection with the least waste that needs no change output, falling back
+    /// to random-improve when there's none
+    #[default]
+    BranchAndBound,
+    /// Select random outputs until the target is reached, then keep adding random outputs while
+    /// they bring the change closer to the target, which keeps the outputs of the wallet in the
+    /// range of the payments it makes
+    RandomImprove,
+}
+
+/// Output that the transaction can spend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    pub value: Amount,
+    /// Size of the signed input spending the output
+    pub input_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoinSelectionParams {
+    /// Value the inputs have to cover on top of their own fees: the value of the outputs and the
+    /// fee of the transaction without its inputs and change
+    pub target: Amount,
+    pub fee_rate: FeeRate,
+    /// Fee rate the outputs are expected to be spent at in the long term, inputs spent above it
+    /// count as waste and inputs spent below it as savings
+    pub long_term_fee_rate: FeeRate,
+    /// Size of the change output
+    pub change_output_size: usize,
+    /// Size of the input spending the change output later
+    pub change_input_size: usize,
+}
+
+/// Outputs chosen by a strategy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Indices of the selected candidates
+    pub inputs: Vec<usize>,
+    /// Value of the change output, `None` if the excess is left to the fee
+    pub change: Option<Amount>,
+    pub waste: SignedAmount,
+}
+
+/// Candidate worth spending, with its amounts in atoms
+#[derive(Debug, Clone, Copy)]
+struct Coin {
+    index: usize,
+    /// Value less the fee of spending the output
+    effective_value: IntType,
+    fee: IntType,
+    long_term_fee: IntType,
+}
+
+impl Coin {
+    fn waste(&self) -> i128 {
+        self.fee as i128 - self.long_term_fee as i128
+    }
+}
+
+/// Amounts of the parameters in atoms
+#[derive(Debug, Clone, Copy)]
+struct Costs {
+    target: IntType,
+    /// Fee of the change output
+    change_fee: IntType,
+    /// Fee of the change output and of spending it later at the long term fee rate
+    change_cost: IntType,
+}
+
+/// Choose the candidates spent by a transaction with the strategy
+pub fn select_coins(
+    strategy: CoinSelectionStrategy,
+    candidates: &[Candidate],
+    params: &CoinSelectionParams,
+    rng: &mut impl Rng,
+) -> Result<Selection, WalletError> {
+    let fee = |fee_rate: FeeRate, size| {
+        fee_rate
+            .fee(size)
+            .map(|fee| fee.into_atoms())
+            .ok_or(WalletError::AmountOverflow)
+    };
+    // The sums of the selected values can't overflow once the sum of all the values doesn't
+    Amount::sum(candidates.iter().map(|candidate| candidate.value))
+        .ok_or(WalletError::AmountOverflow)?;
+    let mut coins = Vec::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        let coin_fee = fee(params.fee_rate, candidate.input_size)?;
+        if let Some(effective_value) = candidate.value.into_atoms().checked_sub(coin_fee) {
+            if effective_value > 0 {
+                coins.push(Coin {
+                    index,
+                    effective_value,
+                    fee: coin_fee,
+                    long_term_fee: fee(params.long_term_fee_rate, candidate.input_size)?,
+                });
+            }
+        }
+    }
+    let change_fee = fee(params.fee_rate, params.change_output_size)?;
+    let costs = Costs {
+        target: params.target.into_atoms(),
+        change_fee,
+        change_cost: change_fee
+            .checked_add(fee(params.long_term_fee_rate, params.change_input_size)?)
+            .ok_or(WalletError::AmountOverflow)?,
+    };
+
+    let selected = match strategy {
+        CoinSelectionStrategy::LargestFirst => largest_first(coins.clone(), &costs),
+        CoinSelectionStrategy::BranchAndBound => branch_and_bound(coins.clone(), &costs)
+            .or_else(|| random_improve(coins.clone(), &costs, rng)),
+        CoinSelectionStrategy::RandomImprove => random_improve(coins.clone(), &costs, rng),
+    };
+    match selected {
+        Some(selected) => Ok(finish(&selected, &costs)),
+        None => Err(WalletError::InsufficientFunds {
+            available: Amount::from_atoms(coins.iter().map(|coin| coin.effective_value).sum()),
+            needed: params.target,
+        }),
+    }
+}
+
+/// Turn the coins covering the target into a selection, with a change output when the excess
+/// is worth more than its cost
+fn finish(selected: &[Coin], costs: &Costs) -> Selection {
+    let total: IntType = selected.iter().map(|coin| coin.effective_value).sum();
+    let excess = total - costs.target;
+    let (change, excess_waste) = if excess > costs.change_cost {
+        (
+            Some(Amount::from_atoms(excess - costs.change_fee)),
+            costs.change_cost,
+        )
+    } else {
+        (None, excess)
+    };
+    let waste = selected.iter().map(Coin::waste).sum::<i128>() + excess_waste as i128;
+    Selection {
+        inputs: selected.iter().map(|coin| coin.index).collect(),
+        change,
+        waste: SignedAmount::from_atoms(waste),
+    }
+}
+
+fn largest_first(mut coins: Vec<Coin>, costs: &Costs) -> Option<Vec<Coin>> {
+    coins.sort_by_key(|coin| std::cmp::Reverse(coin.effective_value));
+    let mut total = 0;
+    let mut selected = Vec::new();
+    for coin in coins {
+        if !selected.is_empty() && total >= costs.target {
+            break;
+        }
+        total += coin.effective_value;
+        selected.push(coin);
+    }
+    (!selected.is_empty() && total >= costs.target).then_some(selected)
+}
+
+/// Depth first search over the inclusion and omission of the coins, from the largest one, for
+/// the changeless selection with the least waste
+///
+/// A branch is cut when its coins exceed the target by more than the change cost, when the rest
+/// of the coins can't reach the target, or when its waste is already higher than the best one
+/// while adding inputs can only increase it. For the same reason a branch ends once it reaches
+/// the target, unless some inputs cost less now than in the long term: adding one of them can
+/// lower the waste by more than it adds to the excess.
+fn branch_and_bound(mut coins: Vec<Coin>, costs: &Costs) -> Option<Vec<Coin>> {
+    coins.sort_by_key(|coin| std::cmp::Reverse(coin.effective_value));
+    let upper_bound = costs.target.saturating_add(costs.change_cost);
+    let waste_only_grows = coins.iter().all(|coin| coin.waste() >= 0);
+
+    let mut available: IntType = coins.iter().map(|coin| coin.effective_value).sum();
+    let mut value: IntType = 0;
+    let mut waste: i128 = 0;
+    // Positions of the included coins
+    let mut selection: Vec<usize> = Vec::new();
+    let mut best: Option<(Vec<usize>, i128)> = None;
+
+    let mut pos = 0;
+    for _ in 0..BNB_MAX_TRIES {
+        let best_waste = best.as_ref().map(|(_, best_waste)| *best_waste);
+        let backtrack = if value + available < costs.target
+            || value > upper_bound
+            || (waste_only_grows && best_waste.map_or(false, |best_waste| waste > best_waste))
+        {
+            true
+        } else if value >= costs.target && !selection.is_empty() {
+            let total_waste = waste + (value - costs.target) as i128;
+            if best_waste.map_or(true, |best_waste| total_waste <= best_waste) {
+                best = Some((selection.clone(), total_waste));
+            }
+            waste_only_grows || pos == coins.len()
+        } else {
+            pos == coins.len()
+        };
+
+        if backtrack {
+            let last = match selection.pop() {
+                Some(last) => last,
+                None => break,
+            };
+            // The coins after the last included one are available again in its omission branch
+            available +=
+                coins[last + 1..pos].iter().map(|coin| coin.effective_value).sum::<IntType>();
+            value -= coins[last].effective_value;
+            waste -= coins[last].waste();
+            pos = last + 1;
+        } else {
+            let coin = &coins[pos];
+            available -= coin.effective_value;
+            // Omitting a coin equal to the omitted previous one leads to the same selections
+            let same_as_omitted = pos > 0
+                && selection.last() != Some(&(pos - 1))
+                && coin.effective_value == coins[pos - 1].effective_value
+                && coin.fee == coins[pos - 1].fee;
+            if !same_as_omitted {
+                selection.push(pos);
+                value += coin.effective_value;
+                waste += coin.waste();
+            }
+            pos += 1;
+        }
+    }
+
+    best.map(|(selection, _)| selection.into_iter().map(|pos| coins[pos]).collect())
+}
+
+/// Random selection reaching the target, improved towards a change equal to the target
+fn random_improve(mut coins: Vec<Coin>, costs: &Costs, rng: &mut impl Rng) -> Option<Vec<Coin>> {
+    coins.shuffle(rng);
+    let mut total: IntType = 0;
+    let mut selected = Vec::new();
+    while selected.is_empty() || total < costs.target {
+        let coin = coins.pop()?;
+        total += coin.effective_value;
+        selected.push(coin);
+    }
+
+    let ideal = costs.target.saturating_mul(2);
+    let limit = costs.target.saturating_mul(3);
+    while let Some(coin) = coins.pop() {
+        let improved = total + coin.effective_value;
+        if improved > limit || ideal.abs_diff(improved) >= ideal.abs_diff(total) {
+            break;
+        }
+        total = improved;
+        selected.push(coin);
+    }
+    Some(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::random::{make_pseudo_rng, SeedableRng};
+    use proptest::{collection::vec, prelude::*};
+    use rand::rngs::StdRng;
+
+    const INPUT_SIZE: usize = 100;
+
+    fn params(
+        target: IntType,
+        fee_rate: IntType,
+        long_term_fee_rate: IntType,
+    ) -> CoinSelectionParams {
+        CoinSelectionParams {
+            target: Amount::from_atoms(target),
+            fee_rate: FeeRate::from_atoms_per_kb(Amount::from_atoms(fee_rate)),
+            long_term_fee_rate: FeeRate::from_atoms_per_kb(Amount::from_atoms(long_term_fee_rate)),
+            change_output_size: 40,
+            change_input_size: INPUT_SIZE,
+        }
+    }
+
+    fn candidates(values: &[IntType]) -> Vec<Candidate> {
+        values
+            .iter()
+            .map(|value| Candidate {
+                value: Amount::from_atoms(*value),
+                input_size: INPUT_SIZE,
+            })
+            .collect()
+    }
+
+    fn selected_values(candidates: &[Candidate], selection: &Selection) -> Vec<IntType> {
+        let mut values: Vec<_> = selection
+            .inputs
+            .iter()
+            .map(|index| candidates[*index].value.into_atoms())
+            .collect();
+        values.sort_unstable();
+        values
+    }
+
+    #[test]
+    fn largest_first_selection() {
+        // Spending an input costs 100 atoms at 1000 atoms per kB
+        let candidates = candidates(&[1_000, 50, 5_000, 3_000, 100]);
+        let selection = select_coins(
+            CoinSelectionStrategy::LargestFirst,
+            &candidates,
+            &params(7_000, 1000, 1000),
+            &mut make_pseudo_rng(),
+        )
+        .unwrap();
+        assert_eq!(selected_values(&candidates, &selection), vec![3_000, 5_000]);
+        // 7800 of effective value, the change output costs 40 atoms
+        assert_eq!(selection.change, Some(Amount::from_atoms(760)));
+        assert_eq!(selection.waste, SignedAmount::from_atoms(140));
+
+        // Dust is never selected, even when it's needed to reach the target
+        let res = select_coins(
+            CoinSelectionStrategy::LargestFirst,
+            &candidates,
+            &params(8_950, 1000, 1000),
+            &mut make_pseudo_rng(),
+        );
+        assert!(matches!(
+            res,
+            Err(WalletError::InsufficientFunds { available, .. })
+                if available == Amount::from_atoms(8_700)
+        ));
+    }
+
+    #[test]
+    fn branch_and_bound_changeless() {
+        let candidates = candidates(&[1_100, 2_100, 3_100, 5_100, 8_100]);
+        let selection = select_coins(
+            CoinSelectionStrategy::BranchAndBound,
+            &candidates,
+            &params(9_000, 1000, 1000),
+            &mut make_pseudo_rng(),
+        )
+        .unwrap();
+        // 1000 + 8000 and 1000 + 3000 + 5000 match exactly, the first one has fewer inputs but
+        // the inputs cost as much as in the long term, so the selections have the same waste
+        assert!(selection.inputs.len() == 2 || selection.inputs.len() == 3);
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.waste, SignedAmount::from_atoms(0));
+
+        // At a fee rate above the long term one fewer inputs waste less
+        let selection = select_coins(
+            CoinSelectionStrategy::BranchAndBound,
+            &candidates,
+            &params(9_000, 1000, 500),
+            &mut make_pseudo_rng(),
+        )
+        .unwrap();
+        assert_eq!(selected_values(&candidates, &selection), vec![1_100, 8_100]);
+        assert_eq!(selection.waste, SignedAmount::from_atoms(100));
+
+        // Below the long term fee rate spending more inputs saves on later fees
+        let selection = select_coins(
+            CoinSelectionStrategy::BranchAndBound,
+            &candidates,
+            &params(9_000, 1000, 2000),
+            &mut make_pseudo_rng(),
+        )
+        .unwrap();
+        assert_eq!(
+            selected_values(&candidates, &selection),
+            vec![1_100, 3_100, 5_100]
+        );
+        assert_eq!(selection.waste, SignedAmount::from_atoms(-300));
+    }
+
+    #[test]
+    fn branch_and_bound_falls_back() {
+        // No combination is within the change cost of the target
+        let candidates = candidates(&[10_100, 20_100, 40_100]);
+        let params = params(15_000, 1000, 1000);
+        assert!(branch_and_bound(
+            (0..3)
+                .map(|index| Coin {
+                    index,
+                    effective_value: candidates[index].value.into_atoms() - 100,
+                    fee: 100,
+                    long_term_fee: 100,
+                })
+                .collect(),
+            &Costs {
+                target: 15_000,
+                change_fee: 40,
+                change_cost: 140,
+            },
+        )
+        .is_none());
+        let selection = select_coins(
+            CoinSelectionStrategy::BranchAndBound,
+            &candidates,
+            &params,
+            &mut make_pseudo_rng(),
+        )
+        .unwrap();
+        assert!(selection.change.is_some());
+    }
+
+    #[test]
+    fn branch_and_bound_adds_cheap_inputs() {
+        // Every input costs 138 atoms less than in the long term, so including the smallest coin
+        // lowers the waste even though the two larger ones already reach the target
+        let coins = [1, 4_397, 15_438]
+            .into_iter()
+            .enumerate()
+            .map(|(index, effective_value)| Coin {
+                index,
+                effective_value,
+                fee: 147,
+                long_term_fee: 285,
+            })
+            .collect();
+        let costs = Costs {
+            target: 19_493,
+            change_fee: 58,
+            change_cost: 343,
+        };
+        let selected = branch_and_bound(coins, &costs).unwrap();
+        assert_eq!(selected.len(), 3);
+        assert_eq!(
+            finish(&selected, &costs).waste,
+            SignedAmount::from_atoms(-71)
+        );
+    }
+
+    #[test]
+    fn random_improve_targets_double() {
+        let candidates = candidates(&[1_100; 10]);
+        let params = params(2_000, 1000, 1000);
+        let mut rng = make_pseudo_rng();
+        let selection = select_coins(
+            CoinSelectionStrategy::RandomImprove,
+            &candidates,
+            &params,
+            &mut rng,
+        )
+        .unwrap();
+        // Two inputs reach the target and two more bring the change to the target
+        assert_eq!(selection.inputs.len(), 4);
+        assert_eq!(selection.change, Some(Amount::from_atoms(1_960)));
+    }
+
+    /// Synthetic sets of outputs, with dust, duplicates and outliers
+    fn gen_values() -> impl Strategy<Value = Vec<IntType>> {
+        vec(
+            prop_oneof![
+                0u128..200,
+                1_000u128..1_010,
+                Just(5_000u128),
+                0u128..100_000,
+                1_000_000u128..10_000_000,
+            ],
+            0..40,
+        )
+    }
+
+    fn gen_strategy() -> impl Strategy<Value = CoinSelectionStrategy> {
+        prop_oneof![
+            Just(CoinSelectionStrategy::LargestFirst),
+            Just(CoinSelectionStrategy::BranchAndBound),
+            Just(CoinSelectionStrategy::RandomImprove),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn prop_selection_covers_target(
+            values in gen_values(),
+            target in 0u128..500_000,
+            fee_rate in 0u128..3_000,
+            long_term_fee_rate in 0u128..3_000,
+            strategy in gen_strategy(),
+            seed in any::<u64>(),
+        ) {
+            let candidates = candidates(&values);
+            let params = params(target, fee_rate, long_term_fee_rate);
+            let input_fee = params.fee_rate.fee(INPUT_SIZE).unwrap().into_atoms();
+            let change_fee = params.fee_rate.fee(params.change_output_size).unwrap().into_atoms();
+            let change_cost = change_fee
+                + params.long_term_fee_rate.fee(params.change_input_size).unwrap().into_atoms();
+            let available: IntType =
+                values.iter().filter_map(|value| value.checked_sub(input_fee)).sum();
+            let res = select_coins(
+                strategy,
+                &candidates,
+                &params,
+                &mut StdRng::seed_from_u64(seed),
+            );
+
+            let selection = match res {
+                Ok(selection) => selection,
+                Err(WalletError::InsufficientFunds { available: reported, needed }) => {
+                    // Every strategy finds a selection when the outputs cover the target
+                    prop_assert!(available < target || available == 0);
+                    prop_assert_eq!(reported.into_atoms(), available);
+                    prop_assert_eq!(needed.into_atoms(), target);
+                    return Ok(());
+                }
+                Err(e) => panic!("unexpected error {:?}", e),
+            };
+
+            // The inputs are distinct and worth spending
+            let mut inputs = selection.inputs.clone();
+            inputs.sort_unstable();
+            inputs.dedup();
+            prop_assert_eq!(inputs.len(), selection.inputs.len());
+            prop_assert!(!inputs.is_empty());
+            prop_assert!(inputs.iter().all(|index| values[*index] > input_fee));
+
+            // The inputs pay the target, their fees and the change
+            let total: IntType = inputs.iter().map(|index| values[*index]).sum();
+            let fees = input_fee * inputs.len() as IntType;
+            let excess = total - fees - target;
+            match selection.change {
+                Some(change) => {
+                    prop_assert!(excess > change_cost);
+                    prop_assert_eq!(change.into_atoms(), excess - change_fee);
+                }
+                None => prop_assert!(excess <= change_cost),
+            }
+
+            // The waste matches its definition
+            let long_term_input_fee =
+                params.long_term_fee_rate.fee(INPUT_SIZE).unwrap().into_atoms();
+            let expected_waste = (input_fee as i128 - long_term_input_fee as i128)
+                * inputs.len() as i128
+                + selection.change.map_or(excess, |_| change_cost) as i128;
+            prop_assert_eq!(selection.waste, SignedAmount::from_atoms(expected_waste));
+        }
+
+        #[test]
+        fn prop_branch_and_bound_is_optimal(
+            values in vec(1u128..20_000, 0..12),
+            target in 0u128..40_000,
+            fee_rate in 0u128..3_000,
+            long_term_fee_rate in 0u128..3_000,
+        ) {
+            let costs = Costs {
+                target,
+                change_fee: fee_rate * 40 / 1000,
+                change_cost: fee_rate * 40 / 1000 + long_term_fee_rate * INPUT_SIZE as u128 / 1000,
+            };
+            let fee = fee_rate * INPUT_SIZE as u128 / 1000;
+            let long_term_fee = long_term_fee_rate * INPUT_SIZE as u128 / 1000;
+            let coins: Vec<_> = values
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| **value > fee)
+                .map(|(index, value)| Coin { index, effective_value: value - fee, fee, long_term_fee })
+                .collect();
+
+            // The least waste of the changeless selections, by exhaustive search
+            let best_waste = (1u32..(1 << coins.len()))
+                .filter_map(|mask| {
+                    let selected: Vec<_> = (0..coins.len())
+                        .filter(|pos| mask & (1 << pos) != 0)
+                        .map(|pos| coins[pos])
+                        .collect();
+                    let total: IntType = selected.iter().map(|coin| coin.effective_value).sum();
+                    (total >= target && total - target <= costs.change_cost)
+                        .then(|| finish(&selected, &costs).waste)
+                })
+                .min();
+
+            let found = branch_and_bound(coins, &costs).map(|selected| finish(&selected, &costs));
+            prop_assert_eq!(found.as_ref().map(|selection| selection.waste), best_waste);
+            if let Some(selection) = found {
+                prop_assert_eq!(selection.change, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hierarchical deterministic keys of the wallet
+//!
+//! Keys are derived along the BIP44 path `m/44'/19788'/0'/purpose/index`. Keys are issued in
+//! order, and a window of keys past the last issued one is watched as well, so that a wallet
+//! restored from its seed finds the outputs sent to keys issued by the original wallet.
+//!
+//! A watch-only key chain is made from the extended public key of the account instead of the
+//! seed. It derives the same public keys, but can't sign.
+
+use std::collections::BTreeMap;
+
+use common::{address::pubkeyhash::PublicKeyHash, chain::Destination};
+use crypto::key::{
+    hd::{ChildNumber, DerivationError, DerivationPath, ExtendedPrivateKey, ExtendedPublicKey},
+    PrivateKey, PublicKey,
+};
+use serialization::{Decode, Encode};
+
+use crate::WalletError;
+
+const BIP44_PURPOSE: u32 = 44;
+/// Coin type of the derivation path, "ML" in ASCII
+const BIP44_COIN_TYPE: u32 = 0x4d4c;
+const BIP44_ACCOUNT: u32 = 0;
+
+/// Number of keys past the last issued one that are watched for outputs
+pub const LOOKAHEAD_SIZE: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
+pub enum KeyPurpose {
+    /// Keys of the addresses given out to receive coins
+    #[codec(index = 0)]
+    Receive,
+    /// Keys that the wallet sends change to
+    #[codec(index = 1)]
+    Change,
+}
+
+impl KeyPurpose {
+    const ALL: [KeyPurpose; 2] = [KeyPurpose::Receive, KeyPurpose::Change];
+
+    fn child_number(&self) -> ChildNumber {
+        let index = match self {
+            KeyPurpose::Receive => 0,
+            KeyPurpose::Change => 1,
+        };
+        ChildNumber::from_encoded(index)
+    }
+}
+
+/// Key the keys of a purpose are derived from
+enum ChainKey {
+    Private(ExtendedPrivateKey),
+    Public(ExtendedPublicKey),
+}
+
+struct PurposeKeys {
+    chain_key: ChainKey,
+    /// Number of issued keys, the index of the next key to issue
+    issued: u32,
+    /// Public keys derived so far, including the lookahead window
+    public_keys: Vec<PublicKey>,
+}
+
+impl PurposeKeys {
+    fn derive_public(&self, index: u32) -> Result<PublicKey, WalletError> {
+        let child_number = ChildNumber::normal(index).map_err(WalletError::KeyDerivationError)?;
+        match &self.chain_key {
+            ChainKey::Private(key) => key.derive_child(child_number).map(|key| key.public_key()),
+            ChainKey::Public(key) => key.derive_child(child_number).map(|key| key.public_key()),
+        }
+        .map_err(WalletError::KeyDerivationError)
+    }
+
+    fn derive_private(&self, index: u32) -> Result<PrivateKey, WalletError> {
+        let child_number = ChildNumber::normal(index).map_err(WalletError::KeyDerivationError)?;
+        match &self.chain_key {
+            ChainKey::Private(key) => key
+                .derive_child(child_number)
+                .map(|key| key.private_key())
+                .map_err(WalletError::KeyDerivationError),
+            ChainKey::Public(_) => Err(WalletError::WatchOnly),
+        }
+    }
+}
+
+pub struct KeyChain {
+    /// Extended public key of the account, the parent of the chain keys
+    account_public_key: ExtendedPublicKey,
+    keys: BTreeMap<KeyPurpose, PurposeKeys>,
+    /// Purpose and index of every derived key by the hash of its public key
+    key_hashes: BTreeMap<PublicKeyHash, (KeyPurpose, u32)>,
+}
+
+impl KeyChain {
+    /// Derive the key chain of the account from the seed, with the given numbers of issued keys
+    pub fn new(seed: &[u8], issued_receive: u32, issued_change: u32) -> Result<Self, WalletError> {
+        let account_path = DerivationPath::from(vec![
+            ChildNumber::hardened(BIP44_PURPOSE).map_err(WalletError::KeyDerivationError)?,
+            ChildNumber::hardened(BIP44_COIN_TYPE).map_err(WalletError::KeyDerivationError)?,
+            ChildNumber::hardened(BIP44_ACCOUNT).map_err(WalletError::KeyDerivationError)?,
+        ]);
+        let account_key = ExtendedPrivateKey::new_master(seed)
+            .and_then(|master_key| master_key.derive_path(&account_path))
+            .map_err(WalletError::KeyDerivationError)?;
+        Self::from_chain_keys(
+            account_key.to_extended_public_key(),
+            |purpose| account_key.derive_child(purpose.child_number()).map(ChainKey::Private),
+            issued_receive,
+            issued_change,
+        )
+    }
+
+    /// Watch-only key chain of the account with the extended public key, with the given numbers
+    /// of issued keys
+    pub fn new_watch_only(
+        account_public_key: ExtendedPublicKey,
+        issued_receive: u32,
+        issued_change: u32,
+    ) -> Result<Self, WalletError> {
+        Self::from_chain_keys(
+            account_public_key.clone(),
+            |purpose| account_public_key.derive_child(purpose.child_number()).map(ChainKey::Public),
+            issued_receive,
+            issued_change,
+        )
+    }
+
+    fn from_chain_keys(
+        account_public_key: ExtendedPublicKey,
+        chain_key: impl Fn(KeyPurpose) -> Result<ChainKey, DerivationError>,
+        issued_receive: u32,
+        issued_change: u32,
+    ) -> Result<Self, WalletError> {
+        let mut key_chain = Self {
+            account_public_key,
+            keys: BTreeMap::new(),
+            key_hashes: BTreeMap::new(),
+        };
+        for (purpose, issued) in KeyPurpose::ALL.into_iter().zip([issued_receive, issued_change]) {
+            let chain_key = chain_key(purpose).map_err(WalletError::KeyDerivationError)?;
+            key_chain.keys.insert(
+                purpose,
+                PurposeKeys {
+                    chain_key,
+                    issued,
+                    public_keys: Vec::new(),
+                },
+            );
+            key_chain.fill_lookahead(purpose)?;
+        }
+        Ok(key_chain)
+    }
+
+    fn purpose_keys(&self, purpose: KeyPurpose) -> &PurposeKeys {
+        self.keys.get(&purpose).expect("keys of every purpose are derived")
+    }
+
+    fn purpose_keys_mut(&mut self, purpose: KeyPurpose) -> &mut PurposeKeys {
+        self.keys.get_mut(&purpose).expect("keys of every purpose are derived")
+    }
+
+    /// Derive the keys up to the end of the lookahead window
+    fn fill_lookahead(&mut self, purpose: KeyPurpose) -> Result<(), WalletError> {
+        let keys = self.purpose_keys(purpose);
+        let derived = keys.public_keys.len() as u32;
+        let target = keys.issued.saturating_add(LOOKAHEAD_SIZE);
+        for index in derived..target {
+            let public_key = self.purpose_keys(purpose).derive_public(index)?;
+            self.key_hashes.insert(PublicKeyHash::from(&public_key), (purpose, index));
+            self.purpose_keys_mut(purpose).public_keys.push(public_key);
+        }
+        Ok(())
+    }
+
+    /// Extended public key of the account, to set up a watch-only wallet
+    pub fn account_public_key(&self) -> &ExtendedPublicKey {
+        &self.account_public_key
+    }
+
+    /// Whether the key chain is missing the private keys
+    pub fn is_watch_only(&self) -> bool {
+        self.keys.values().any(|keys| matches!(keys.chain_key, ChainKey::Public(_)))
+    }
+
+    /// Number of keys issued for the purpose
+    pub fn issued(&self, purpose: KeyPurpose) -> u32 {
+        self.purpose_keys(purpose).issued
+    }
+
+    /// Issue the next unused key of the purpose
+    pub fn issue_key(&mut self, purpose: KeyPurpose) -> Result<PublicKey, WalletError> {
+        let keys = self.purpose_keys_mut(purpose);
+        let public_key = keys.public_keys[keys.issued as usize].clone();
+        keys.issued += 1;
+        self.fill_lookahead(purpose)?;
+        Ok(public_key)
+    }
+
+    /// Purpose and index of the key that can spend outputs locked to the destination
+    pub fn find_key(&self, destination: &Destination) -> Option<(KeyPurpose, u32)> {
+        match destination {
+            Destination::Address(public_key_hash) => self.key_hashes.get(public_key_hash),
+            Destination::PublicKey(public_key) => {
+                self.key_hashes.get(&PublicKeyHash::from(public_key))
+            }
+            Destination::ScriptHash(_)
+            | Destination::AnyoneCanSpend
+            | Destination::ClassicMultisig(_) => None,
+        }
+        .copied()
+    }
+
+    /// Whether the wallet holds the key to spend outputs locked to the destination
+    pub fn is_mine(&self, destination: &Destination) -> bool {
+        self.find_key(destination).is_some()
+    }
+
+    /// Mark the key of the destination as used, issuing it and all the keys before it
+    pub fn mark_used(&mut self, destination: &Destination) -> Result<(), WalletError> {
+        if let Some((purpose, index)) = self.find_key(destination) {
+            let keys = self.purpose_keys_mut(purpose);
+            if index >= keys.issued {
+                keys.issued = index + 1;
+                self.fill_lookahead(purpose)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Private and public key that can spend outputs locked to the destination, fails if the
+    /// key chain is watch-only
+    pub fn key_pair(
+        &self,
+        destination: &Destination,
+    ) -> Result<Option<(PrivateKey, PublicKey)>, WalletError> {
+        match self.find_key(destination) {
+            Some((purpose, index)) => self.key_pair_at(purpose, index).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Private and public key of the purpose at the index, fails if the key chain is watch-only
+    pub fn key_pair_at(
+        &self,
+        purpose: KeyPurpose,
+        index: u32,
+    ) -> Result<(PrivateKey, PublicKey), WalletError> {
+        let private_key = self.purpose_keys(purpose).derive_private(index)?;
+        let public_key = PublicKey::from_private_key(&private_key);
+        Ok((private_key, public_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issue_and_recognize_keys() {
+        let seed = [7u8; 32];
+        let mut key_chain = KeyChain::new(&seed, 0, 0).unwrap();
+        let first = key_chain.issue_key(KeyPurpose::Receive).unwrap();
+        let second = key_chain.issue_key(KeyPurpose::Receive).unwrap();
+        let change = key_chain.issue_key(KeyPurpose::Change).unwrap();
+        assert_ne!(first, second);
+        assert_ne!(first, change);
+        assert_eq!(key_chain.issued(KeyPurpose::Receive), 2);
+        assert_eq!(key_chain.issued(KeyPurpose::Change), 1);
+
+        let destination = Destination::Address(PublicKeyHash::from(&second));
+        assert!(key_chain.is_mine(&destination));
+        assert!(key_chain.is_mine(&Destination::PublicKey(first.clone())));
+        let (private_key, public_key) = key_chain.key_pair(&destination).unwrap().unwrap();
+        assert_eq!(public_key, second);
+        assert_eq!(PublicKey::from_private_key(&private_key), second);
+
+        // The keys are derived deterministically from the seed
+        let mut restored = KeyChain::new(&seed, 0, 0).unwrap();
+        assert_eq!(restored.issue_key(KeyPurpose::Receive).unwrap(), first);
+
+        let other = KeyChain::new(&[8u8; 32], 0, 0).unwrap();
+        assert!(!other.is_mine(&destination));
+    }
+
+    #[test]
+    fn watch_only() {
+        let seed = [7u8; 32];
+        let mut key_chain = KeyChain::new(&seed, 0, 0).unwrap();
+        let mut watch_only =
+            KeyChain::new_watch_only(key_chain.account_public_key().clone(), 0, 0).unwrap();
+        assert!(!key_chain.is_watch_only());
+        assert!(watch_only.is_watch_only());
+
+        for purpose in KeyPurpose::ALL {
+            let public_key = key_chain.issue_key(purpose).unwrap();
+            assert_eq!(watch_only.issue_key(purpose).unwrap(), public_key);
+            let destination = Destination::PublicKey(public_key.clone());
+            assert_eq!(watch_only.find_key(&destination), Some((purpose, 0)));
+            assert!(matches!(
+                watch_only.key_pair(&destination),
+                Err(WalletError::WatchOnly)
+            ));
+            assert_eq!(key_chain.key_pair_at(purpose, 0).unwrap().1, public_key);
+        }
+    }
+
+    #[test]
+    fn lookahead_window() {
+        let seed = [7u8; 32];
+        let mut key_chain = KeyChain::new(&seed, 0, 0).unwrap();
+        let keys: Vec<_> = (0..LOOKAHEAD_SIZE + 5)
+            .map(|_| key_chain.issue_key(KeyPurpose::Receive).unwrap())
+            .collect();
+
+        // A restored wallet only watches the lookahead window until one of its keys is used
+        let mut restored = KeyChain::new(&seed, 0, 0).unwrap();
+        let last_in_window = Destination::PublicKey(keys[LOOKAHEAD_SIZE as usize - 1].clone());
+        let past_window = Destination::PublicKey(keys[LOOKAHEAD_SIZE as usize + 4].clone());
+        assert!(restored.is_mine(&last_in_window));
+        assert!(!restored.is_mine(&past_window));
+
+        restored.mark_used(&last_in_window).unwrap();
+        assert_eq!(restored.issued(KeyPurpose::Receive), LOOKAHEAD_SIZE);
+        assert!(restored.is_mine(&past_window));
+    }
+}
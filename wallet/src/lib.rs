@@ -0,0 +1,206 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet subsystem
+//!
+//! The wallet holds HD keys derived from a mnemonic and follows the main chain of the chainstate
+//! to maintain the set of outputs it can spend. Its state is persisted in the wallet file.
+
+pub mod coin_selection;
+pub mod key_chain;
+pub mod partially_signed;
+pub mod rpc;
+mod store;
+mod wallet;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chainstate::{ChainstateError, ChainstateEvent, ChainstateHandle};
+use common::{address::AddressError, chain::ChainConfig, primitives::Amount};
+use crypto::{
+    key::{
+        hd::{DerivationError, ExtendedPublicKey},
+        mnemonic::{Mnemonic, WordCount},
+    },
+    random::make_true_rng,
+};
+use logging::log;
+use subsystem::subsystem::{CallRequest, ShutdownRequest};
+use tokio::sync::mpsc;
+
+pub use crate::{
+    store::WalletUtxo,
+    wallet::{Balance, FeeRate, RescanProgress, RescanStart, Wallet, DEFAULT_FEE_RATE},
+};
+
+#[derive(thiserror::Error, Debug, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum WalletError {
+    #[error("Wallet file I/O error: `{0}`")]
+    IoError(#[from] std::io::Error),
+    #[error("Invalid wallet file: `{0}`")]
+    InvalidWalletFile(String),
+    #[error("Wallet file `{0}` already exists")]
+    WalletFileExists(PathBuf),
+    #[error("The wallet is encrypted and no password is given")]
+    PasswordRequired,
+    #[error("Wrong wallet password")]
+    WrongPassword,
+    #[error("Wallet encryption failed: `{0}`")]
+    EncryptionError(String),
+    #[error("Key derivation failed: `{0:?}`")]
+    KeyDerivationError(DerivationError),
+    #[error("Address error: `{0:?}`")]
+    AddressError(AddressError),
+    #[error("Amount overflow")]
+    AmountOverflow,
+    #[error("Insufficient funds, {available:?} available and {needed:?} needed")]
+    InsufficientFunds { available: Amount, needed: Amount },
+    #[error("Transaction creation failed: `{0}`")]
+    TransactionCreationError(String),
+    #[error("Transaction signing failed: `{0}`")]
+    SigningError(String),
+    #[error("Chainstate error: `{0}`")]
+    ChainstateError(ChainstateError),
+    #[error("Chainstate subsystem call failed")]
+    SubsystemFailure,
+    #[error("The wallet is watch-only and can't sign")]
+    WatchOnly,
+    #[error("Input {0} is not signed")]
+    UnsignedInput(usize),
+}
+
+/// Location of the wallet file and the password its seed is encrypted with
+#[derive(Clone, PartialEq, Eq)]
+pub struct WalletConfig {
+    path: PathBuf,
+    password: Option<String>,
+    watch_only: Option<ExtendedPublicKey>,
+}
+
+impl WalletConfig {
+    pub fn new(path: PathBuf, password: Option<String>) -> Self {
+        Self {
+            path,
+            password,
+            watch_only: None,
+        }
+    }
+
+    /// Create a watch-only wallet from the account public key if the wallet file doesn't exist
+    pub fn with_watch_only(mut self, account_public_key: ExtendedPublicKey) -> Self {
+        self.watch_only = Some(account_public_key);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    pub fn watch_only(&self) -> Option<&ExtendedPublicKey> {
+        self.watch_only.as_ref()
+    }
+}
+
+/// The password is not printed to keep it out of logs
+impl std::fmt::Debug for WalletConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WalletConfig")
+            .field("path", &self.path)
+            .field("encrypted", &self.password.is_some())
+            .field("watch_only", &self.watch_only.is_some())
+            .finish()
+    }
+}
+
+pub type WalletHandle = subsystem::Handle<Wallet>;
+
+/// Open the wallet file, creating a wallet with a new mnemonic if it doesn't exist
+///
+/// The wallet created is watch-only if the config has an account public key.
+pub fn make_wallet(
+    chain_config: Arc<ChainConfig>,
+    chainstate: ChainstateHandle,
+    config: &WalletConfig,
+) -> Result<Wallet, WalletError> {
+    if config.path().exists() {
+        Wallet::open(chain_config, chainstate, config)
+    } else if let Some(account_public_key) = config.watch_only() {
+        Wallet::create_watch_only(chain_config, chainstate, config, account_public_key)
+    } else {
+        let mnemonic = Mnemonic::generate(&mut make_true_rng(), WordCount::Words24);
+        Wallet::create(chain_config, chainstate, config, &mnemonic)
+    }
+}
+
+/// Run the wallet subsystem, keeping the wallet in sync with the chainstate
+///
+/// Add it to the subsystem manager with `Manager::add_raw_subsystem`.
+pub async fn run(
+    mut wallet: Wallet,
+    mut call_rq: CallRequest<Wallet>,
+    mut shutdown_rq: ShutdownRequest,
+) {
+    let (tx_tip, mut rx_tip) = mpsc::unbounded_channel();
+    let subscribe_func = Arc::new(move |event: ChainstateEvent| match event {
+        ChainstateEvent::NewTip(_, _) => {
+            // The receiver is only dropped on shutdown
+            let _ = tx_tip.send(());
+        }
+    });
+    if wallet
+        .chainstate()
+        .call_mut(|this| this.subscribe_to_events(subscribe_func))
+        .await
+        .is_err()
+    {
+        log::error!("wallet failed to subscribe to chainstate events");
+        return;
+    }
+
+    // Catch up with the blocks connected while the wallet was not running
+    let mut needs_sync = true;
+    loop {
+        if needs_sync {
+            if let Err(e) = wallet.sync().await {
+                log::error!("wallet sync failed: {}", e);
+            }
+            needs_sync = false;
+        }
+        // A rescan runs in steps, the pending events and calls are handled between them
+        tokio::select! {
+            biased;
+            Some(()) = rx_tip.recv() => {
+                // Handle all the pending tip changes with a single sync
+                while rx_tip.try_recv().is_ok() {}
+                needs_sync = true;
+            }
+            () = shutdown_rq.recv() => break,
+            call = call_rq.recv() => call(&mut wallet).await,
+            () = std::future::ready(()), if wallet.is_rescanning() => {
+                if let Err(e) = wallet.rescan_step().await {
+                    log::error!("wallet rescan failed: {}", e);
+                }
+            }
+        }
+    }
+}
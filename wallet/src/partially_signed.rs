@@ -0,0 +1,266 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Partially signed transactions, to sign the transactions of a watch-only wallet offline
+//!
+//! The steps are split between the wallets:
+//! 1. The watch-only wallet creates the transaction and fills in the outputs its inputs spend and
+//!    the keys that can spend them.
+//! 2. The signer, which holds the private keys but doesn't follow the chain, signs the inputs
+//!    with the keys filled in.
+//! 3. The watch-only wallet finalizes the transaction once all its inputs are signed.
+//!
+//! The steps exchange the SCALE encoding of [`PartiallySignedTransaction`].
+
+use common::{
+    address::pubkeyhash::PublicKeyHash,
+    chain::{
+        signature::{
+            inputsig::{InputWitness, StandardInputSignature},
+            sighashtype::SigHashType,
+        },
+        Destination, Transaction, TxOutput,
+    },
+    primitives::Amount,
+};
+use crypto::key::PublicKey;
+use serialization::{Decode, Encode};
+
+use crate::{
+    key_chain::{KeyChain, KeyPurpose},
+    WalletError,
+};
+
+/// What the signer needs to know about an input
+#[derive(Debug, Clone, PartialEq, Eq, Default, Encode, Decode)]
+pub struct InputInfo {
+    /// Output spent by the input
+    pub utxo: Option<TxOutput>,
+    /// Purpose and index of the key of the account that can spend the output
+    pub key: Option<(KeyPurpose, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct PartiallySignedTransaction {
+    /// Transaction with the witnesses of the inputs signed so far
+    tx: Transaction,
+    /// Information about the inputs of the transaction, in the same order
+    inputs: Vec<InputInfo>,
+}
+
+impl PartiallySignedTransaction {
+    /// Start signing the transaction, with nothing known about its inputs
+    pub fn new(tx: Transaction) -> Self {
+        let inputs = vec![InputInfo::default(); tx.get_inputs().len()];
+        Self { tx, inputs }
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.tx
+    }
+
+    pub fn inputs(&self) -> &[InputInfo] {
+        &self.inputs
+    }
+
+    /// Record the output spent by the input and the key that can spend it
+    pub fn fill_input(
+        &mut self,
+        input: usize,
+        utxo: TxOutput,
+        key: Option<(KeyPurpose, u32)>,
+    ) -> Result<(), WalletError> {
+        let info = self
+            .inputs
+            .get_mut(input)
+            .ok_or_else(|| WalletError::TransactionCreationError(format!("No input {}", input)))?;
+        *info = InputInfo {
+            utxo: Some(utxo),
+            key,
+        };
+        Ok(())
+    }
+
+    fn is_input_signed(&self, input: usize) -> bool {
+        matches!(
+            self.tx.get_inputs()[input].get_witness(),
+            InputWitness::Standard(_)
+        )
+    }
+
+    /// Whether all the inputs are signed
+    pub fn is_complete(&self) -> bool {
+        (0..self.inputs.len()).all(|input| self.is_input_signed(input))
+    }
+
+    /// Fee paid by the transaction, `None` until the outputs spent by all the inputs are known
+    pub fn fee(&self) -> Option<Amount> {
+        let inputs = self.inputs.iter().map(|info| info.utxo.as_ref().map(|utxo| utxo.get_value()));
+        let total_in = Amount::sum(inputs.collect::<Option<Vec<_>>>()?)?;
+        let total_out = Amount::sum(self.tx.get_outputs().iter().map(|output| output.get_value()))?;
+        total_in - total_out
+    }
+
+    /// Sign the unsigned inputs whose keys belong to the key chain, returns the number of inputs
+    /// signed
+    ///
+    /// Fails if a key filled in doesn't match the output spent by its input.
+    pub fn sign(&mut self, key_chain: &KeyChain) -> Result<usize, WalletError> {
+        let mut signed = 0;
+        for input in 0..self.inputs.len() {
+            let (destination, (purpose, index)) = match &self.inputs[input] {
+                InputInfo {
+                    utxo: Some(utxo),
+                    key: Some(key),
+                } if !self.is_input_signed(input) => (utxo.get_destination().clone(), *key),
+                _ => continue,
+            };
+            let (private_key, public_key) = key_chain.key_pair_at(purpose, index)?;
+            if !can_spend(&destination, &public_key) {
+                return Err(WalletError::SigningError(format!(
+                    "The key of input {} doesn't match the output it spends",
+                    input
+                )));
+            }
+            let signature = StandardInputSignature::produce_signature_for_input(
+                &private_key,
+                SigHashType::default(),
+                destination,
+                &self.tx,
+                input,
+            )
+            .map_err(|e| WalletError::SigningError(e.to_string()))?;
+            self.tx
+                .update_witness(input, InputWitness::Standard(signature))
+                .map_err(|e| WalletError::TransactionCreationError(format!("{:?}", e)))?;
+            signed += 1;
+        }
+        Ok(signed)
+    }
+
+    /// The signed transaction, fails if an input isn't signed yet
+    pub fn finalize(self) -> Result<Transaction, WalletError> {
+        match (0..self.inputs.len()).find(|input| !self.is_input_signed(*input)) {
+            Some(input) => Err(WalletError::UnsignedInput(input)),
+            None => Ok(self.tx),
+        }
+    }
+}
+
+/// Whether the public key can spend the outputs locked to the destination
+fn can_spend(destination: &Destination, public_key: &PublicKey) -> bool {
+    match destination {
+        Destination::PublicKey(key) => key == public_key,
+        Destination::Address(public_key_hash) => {
+            *public_key_hash == PublicKeyHash::from(public_key)
+        }
+        Destination::ScriptHash(_)
+        | Destination::AnyoneCanSpend
+        | Destination::ClassicMultisig(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{
+        chain::{signature::verify_signature, OutPointSourceId, TxInput},
+        primitives::{Id, H256},
+    };
+    use serialization::DecodeAll;
+
+    #[test]
+    fn offline_signing() {
+        let mut signer = KeyChain::new(&[7u8; 32], 0, 0).unwrap();
+        let mut watch_only =
+            KeyChain::new_watch_only(signer.account_public_key().clone(), 0, 0).unwrap();
+        let receive_key = watch_only.issue_key(KeyPurpose::Receive).unwrap();
+        let change_key = watch_only.issue_key(KeyPurpose::Change).unwrap();
+        signer.issue_key(KeyPurpose::Receive).unwrap();
+
+        let utxos = [
+            TxOutput::new(
+                Amount::from_atoms(100),
+                Destination::Address(PublicKeyHash::from(&receive_key)),
+            ),
+            TxOutput::new(Amount::from_atoms(50), Destination::PublicKey(change_key)),
+        ];
+        let inputs = (0..2)
+            .map(|index| {
+                TxInput::new(
+                    OutPointSourceId::Transaction(Id::new(&H256::random())),
+                    index,
+                    InputWitness::NoSignature(None),
+                )
+            })
+            .collect();
+        let outputs = vec![TxOutput::new(Amount::from_atoms(140), Destination::AnyoneCanSpend)];
+        let tx = Transaction::new(0, inputs, outputs, 0).unwrap();
+
+        // The watch-only wallet fills in the inputs but can't sign
+        let mut ptx = PartiallySignedTransaction::new(tx);
+        assert_eq!(ptx.fee(), None);
+        for (input, utxo) in utxos.iter().enumerate() {
+            let key = watch_only.find_key(utxo.get_destination());
+            ptx.fill_input(input, utxo.clone(), key).unwrap();
+        }
+        assert_eq!(ptx.fee(), Some(Amount::from_atoms(10)));
+        assert!(matches!(
+            ptx.clone().sign(&watch_only),
+            Err(WalletError::WatchOnly)
+        ));
+        assert!(matches!(
+            ptx.clone().finalize(),
+            Err(WalletError::UnsignedInput(0))
+        ));
+
+        // The signer gets the encoded transaction, it doesn't need to know the change key
+        let encoded = ptx.encode();
+        let mut ptx = PartiallySignedTransaction::decode_all(&mut &encoded[..]).unwrap();
+        assert_eq!(ptx.sign(&signer).unwrap(), 2);
+        assert!(ptx.is_complete());
+        assert_eq!(ptx.sign(&signer).unwrap(), 0);
+        let tx = ptx.finalize().unwrap();
+        for (input, utxo) in utxos.iter().enumerate() {
+            verify_signature(utxo.get_destination(), &tx, input).unwrap();
+        }
+    }
+
+    #[test]
+    fn mismatched_key() {
+        let signer = KeyChain::new(&[7u8; 32], 0, 0).unwrap();
+        let input = TxInput::new(
+            OutPointSourceId::Transaction(Id::new(&H256::random())),
+            0,
+            InputWitness::NoSignature(None),
+        );
+        let tx = Transaction::new(0, vec![input], Vec::new(), 0).unwrap();
+        let mut ptx = PartiallySignedTransaction::new(tx);
+        let (_, other_key) = signer.key_pair_at(KeyPurpose::Receive, 1).unwrap();
+        let utxo = TxOutput::new(Amount::from_atoms(1), Destination::PublicKey(other_key));
+        ptx.fill_input(0, utxo, Some((KeyPurpose::Receive, 0))).unwrap();
+        assert!(matches!(
+            ptx.sign(&signer),
+            Err(WalletError::SigningError(_))
+        ));
+        assert!(ptx
+            .fill_input(
+                1,
+                TxOutput::new(Amount::from_atoms(1), Destination::AnyoneCanSpend),
+                None
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,637 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet subsystem RPC handler
+//!
+//! The methods are registered with the RPC server of the node, so they are served with the same
+//! settings and access restrictions as the other methods of the node.
+
+use common::{
+    address::Address,
+    chain::{
+        ChainConfig, Destination, OutPoint, OutPointSourceId, Transaction, TxOutput,
+        WitnessTransaction,
+    },
+    primitives::{Amount, BlockHeight, Id, Idable, H256},
+};
+use serialization::{DecodeAll, Encode};
+use subsystem::subsystem::CallError;
+
+use crate::{
+    coin_selection::CoinSelectionStrategy, partially_signed::PartiallySignedTransaction, FeeRate,
+    RescanStart, Wallet, WalletError, WalletHandle, WalletUtxo, DEFAULT_FEE_RATE,
+};
+
+/// Error codes of the wallet RPC errors, see [`rpc::error_code`] for the code space
+///
+/// The errors carry [`rpc::ErrorData`] with the name of the [`WalletError`] variant, or
+/// `invalid_address` for the addresses that couldn't be parsed and `invalid_encoding` for the
+/// partially signed transactions that couldn't be decoded.
+pub mod error_code {
+    /// The wallet file couldn't be read or written
+    pub const WALLET_FILE_ERROR: i32 = -4001;
+    /// The wallet password is missing or wrong
+    pub const WRONG_PASSWORD: i32 = -4002;
+    /// The spendable outputs of the wallet don't cover the amount and the fee
+    pub const INSUFFICIENT_FUNDS: i32 = -4003;
+    /// The address isn't a valid address of the chain
+    pub const INVALID_ADDRESS: i32 = -4004;
+    /// The transaction couldn't be created or signed
+    pub const TRANSACTION_FAILED: i32 = -4005;
+    /// The request couldn't be served because of an internal error, such as a failure of
+    /// another subsystem
+    pub const INTERNAL_ERROR: i32 = -4006;
+    /// The wallet is watch-only, its transactions have to be signed offline
+    pub const WATCH_ONLY: i32 = -4007;
+    /// The partially signed transaction isn't a valid hex-encoded SCALE encoding
+    pub const PARTIALLY_SIGNED_DECODE_FAILED: i32 = -4008;
+}
+
+/// Kind reported with the address parsing failures
+const INVALID_ADDRESS: &str = "invalid_address";
+
+/// Kind reported with the partially signed transaction decoding failures
+const INVALID_ENCODING: &str = "invalid_encoding";
+
+/// Balance returned by the `balance` method
+#[derive(Debug, serde::Serialize)]
+pub struct BalanceInfo {
+    /// Value in atoms of the main chain outputs that no unconfirmed transaction spends, as a
+    /// string since it may not fit a JSON number
+    confirmed: String,
+    /// Value in atoms of the outputs of unconfirmed transactions that no other one spends
+    unconfirmed: String,
+}
+
+/// Unspent output returned by the `list_unspent` method
+#[derive(Debug, serde::Serialize)]
+pub struct UnspentInfo {
+    /// Either `transaction` or `block_reward`
+    source: &'static str,
+    /// ID of the transaction or block that created the output
+    source_id: H256,
+    index: u32,
+    /// Value in atoms, as a string since it may not fit a JSON number
+    value: String,
+    address: String,
+    /// Number of main chain blocks starting from the one containing the output
+    confirmations: u64,
+}
+
+impl UnspentInfo {
+    fn new(
+        chain_config: &ChainConfig,
+        outpoint: &OutPoint,
+        utxo: &WalletUtxo,
+        best_height: BlockHeight,
+    ) -> Result<Self, WalletError> {
+        let (source, source_id) = match outpoint.get_tx_id() {
+            OutPointSourceId::Transaction(id) => ("transaction", id.get()),
+            OutPointSourceId::BlockReward(id) => ("block_reward", id.get()),
+        };
+        // The outputs locked to a public key are shown with the address of its hash, which is the
+        // form the wallet gives out
+        let address = match utxo.output.get_destination() {
+            Destination::PublicKey(public_key) => {
+                Address::from_public_key(chain_config, public_key)
+            }
+            destination => Address::from_destination(chain_config, destination),
+        }
+        .map_err(WalletError::AddressError)?;
+        Ok(Self {
+            source,
+            source_id,
+            index: outpoint.get_output_index(),
+            value: utxo.output.get_value().into_atoms().to_string(),
+            address: address.get().to_owned(),
+            confirmations: u64::from(best_height).saturating_sub(u64::from(utxo.height)) + 1,
+        })
+    }
+}
+
+/// Transaction created by the `send_to_address` method
+#[derive(Debug, serde::Serialize)]
+pub struct SentTransaction {
+    tx_id: Id<Transaction>,
+    /// ID covering the witnesses as well, changes if the transaction is signed again
+    wtx_id: Id<WitnessTransaction>,
+    /// Hex-encoded SCALE encoding of the signed transaction
+    transaction: String,
+}
+
+/// Partially signed transaction returned by the `sign_partially_signed` method
+#[derive(Debug, serde::Serialize)]
+pub struct SignedInfo {
+    /// Hex-encoded SCALE encoding of the partially signed transaction
+    partially_signed: String,
+    /// Whether all the inputs are signed, so the transaction can be finalized
+    complete: bool,
+}
+
+/// Progress returned by the `rescan_progress` method
+#[derive(Debug, serde::Serialize)]
+pub struct RescanProgressInfo {
+    start_height: BlockHeight,
+    /// Height of the next block to rescan
+    next_height: BlockHeight,
+    tip_height: BlockHeight,
+}
+
+#[rpc::rpc(server, namespace = "wallet")]
+trait WalletRpc {
+    /// Issue a new address to receive coins
+    #[method(name = "new_address")]
+    async fn new_address(&self) -> rpc::Result<String>;
+
+    /// Get the confirmed and unconfirmed balance of the wallet
+    #[method(name = "balance")]
+    async fn balance(&self) -> rpc::Result<BalanceInfo>;
+
+    /// List the unspent main chain outputs of the wallet
+    #[method(name = "list_unspent")]
+    async fn list_unspent(&self) -> rpc::Result<Vec<UnspentInfo>>;
+
+    /// Pay the amount in atoms to the address, with the fee rate in atoms per 1000 bytes
+    ///
+    /// The signed transaction is returned and tracked by the wallet until it's included in the
+    /// main chain. The node has no mempool to relay it yet, so it has to be mined by the caller.
+    #[method(name = "send_to_address")]
+    async fn send_to_address(
+        &self,
+        address: String,
+        amount: Amount,
+        fee_rate: Option<Amount>,
+    ) -> rpc::Result<SentTransaction>;
+
+    /// Get the extended public key of the account, to create a watch-only wallet
+    #[method(name = "account_public_key")]
+    async fn account_public_key(&self) -> rpc::Result<String>;
+
+    /// Create a transaction paying the amount in atoms to the address like `send_to_address`, but
+    /// leave its inputs unsigned, returns the hex-encoded partially signed transaction
+    ///
+    /// The wallet can be watch-only, the transaction is signed with `sign_partially_signed` by the
+    /// wallet holding the keys.
+    #[method(name = "create_partially_signed")]
+    async fn create_partially_signed(
+        &self,
+        address: String,
+        amount: Amount,
+        fee_rate: Option<Amount>,
+    ) -> rpc::Result<String>;
+
+    /// Sign the inputs of the partially signed transaction that the wallet has the keys of
+    ///
+    /// The wallet doesn't need to be in sync with the chain, so it can run offline.
+    #[method(name = "sign_partially_signed")]
+    async fn sign_partially_signed(&self, partially_signed: String) -> rpc::Result<SignedInfo>;
+
+    /// Extract the signed transaction from the partially signed transaction, fails if an input
+    /// isn't signed
+    ///
+    /// The transaction is tracked by the wallet until it's included in the main chain.
+    #[method(name = "finalize_partially_signed")]
+    async fn finalize_partially_signed(
+        &self,
+        partially_signed: String,
+    ) -> rpc::Result<SentTransaction>;
+
+    /// Start rescanning the main chain from the height, or from the birthday of the wallet as a
+    /// UNIX timestamp, returns the height of the first block to rescan
+    ///
+    /// The other methods report the state of the wallet before the rescan until it's finished.
+    #[method(name = "rescan")]
+    async fn rescan(
+        &self,
+        height: Option<BlockHeight>,
+        birthday: Option<u32>,
+    ) -> rpc::Result<BlockHeight>;
+
+    /// Get the progress of the rescan, `null` if there's no rescan in progress
+    #[method(name = "rescan_progress")]
+    async fn rescan_progress(&self) -> rpc::Result<Option<RescanProgressInfo>>;
+
+    /// Abort the rescan in progress, returns whether there was one
+    #[method(name = "abort_rescan")]
+    async fn abort_rescan(&self) -> rpc::Result<bool>;
+}
+
+#[async_trait::async_trait]
+impl WalletRpcServer for WalletHandle {
+    async fn new_address(&self) -> rpc::Result<String> {
+        let res = self.call_mut(|this| this.new_address()).await;
+        Ok(handle_error(res)?.get().to_owned())
+    }
+
+    async fn balance(&self) -> rpc::Result<BalanceInfo> {
+        let balance = handle_error(self.call(|this| this.balance()).await)?;
+        Ok(BalanceInfo {
+            confirmed: balance.confirmed.into_atoms().to_string(),
+            unconfirmed: balance.unconfirmed.into_atoms().to_string(),
+        })
+    }
+
+    async fn list_unspent(&self) -> rpc::Result<Vec<UnspentInfo>> {
+        let res = self
+            .call(|this: &Wallet| {
+                let best_height = this.best_block().map_or(BlockHeight::zero(), |(_, h)| h);
+                this.utxos()
+                    .iter()
+                    .map(|(outpoint, utxo)| {
+                        UnspentInfo::new(this.chain_config(), outpoint, utxo, best_height)
+                    })
+                    .collect()
+            })
+            .await;
+        handle_error(res)
+    }
+
+    async fn send_to_address(
+        &self,
+        address: String,
+        amount: Amount,
+        fee_rate: Option<Amount>,
+    ) -> rpc::Result<SentTransaction> {
+        let fee_rate = fee_rate.map_or(DEFAULT_FEE_RATE, FeeRate::from_atoms_per_kb);
+        let res = self
+            .call_mut(move |this| {
+                let destination = parse_address(this.chain_config(), &address)?;
+                this.send_to_address(
+                    destination,
+                    amount,
+                    fee_rate,
+                    CoinSelectionStrategy::default(),
+                )
+                .map_err(wallet_error)
+            })
+            .await;
+        let tx = res.map_err(rpc::subsystem_unavailable)??;
+        Ok(SentTransaction {
+            tx_id: tx.get_id(),
+            wtx_id: tx.get_witness_id(),
+            transaction: hex::encode(tx.encode()),
+        })
+    }
+
+    async fn account_public_key(&self) -> rpc::Result<String> {
+        let res = self.call(|this| this.key_chain().account_public_key().to_string()).await;
+        res.map_err(rpc::subsystem_unavailable)
+    }
+
+    async fn create_partially_signed(
+        &self,
+        address: String,
+        amount: Amount,
+        fee_rate: Option<Amount>,
+    ) -> rpc::Result<String> {
+        let fee_rate = fee_rate.map_or(DEFAULT_FEE_RATE, FeeRate::from_atoms_per_kb);
+        let res = self
+            .call_mut(move |this| {
+                let destination = parse_address(this.chain_config(), &address)?;
+                this.create_partially_signed(
+                    vec![TxOutput::new(amount, destination)],
+                    fee_rate,
+                    CoinSelectionStrategy::default(),
+                )
+                .map_err(wallet_error)
+            })
+            .await;
+        let ptx = res.map_err(rpc::subsystem_unavailable)??;
+        Ok(hex::encode(ptx.encode()))
+    }
+
+    async fn sign_partially_signed(&self, partially_signed: String) -> rpc::Result<SignedInfo> {
+        let mut ptx = decode_partially_signed(&partially_signed)?;
+        let res = self.call(move |this| this.sign_partially_signed(&mut ptx).map(|_| ptx)).await;
+        let ptx = handle_error(res)?;
+        Ok(SignedInfo {
+            partially_signed: hex::encode(ptx.encode()),
+            complete: ptx.is_complete(),
+        })
+    }
+
+    async fn finalize_partially_signed(
+        &self,
+        partially_signed: String,
+    ) -> rpc::Result<SentTransaction> {
+        let ptx = decode_partially_signed(&partially_signed)?;
+        let res = self.call_mut(move |this| this.finalize_partially_signed(ptx)).await;
+        let tx = handle_error(res)?;
+        Ok(SentTransaction {
+            tx_id: tx.get_id(),
+            wtx_id: tx.get_witness_id(),
+            transaction: hex::encode(tx.encode()),
+        })
+    }
+
+    async fn rescan(
+        &self,
+        height: Option<BlockHeight>,
+        birthday: Option<u32>,
+    ) -> rpc::Result<BlockHeight> {
+        let start = match (height, birthday) {
+            (Some(height), None) => RescanStart::Height(height),
+            (None, Some(birthday)) => RescanStart::Birthday(birthday),
+            _ => {
+                return Err(rpc::invalid_params(
+                    "Either the height or the birthday is required",
+                ))
+            }
+        };
+        handle_error(self.call_async_mut(move |this| Box::pin(this.start_rescan(start))).await)
+    }
+
+    async fn rescan_progress(&self) -> rpc::Result<Option<RescanProgressInfo>> {
+        let progress = self.call(|this| this.rescan_progress()).await;
+        Ok(progress
+            .map_err(rpc::subsystem_unavailable)?
+            .map(|progress| RescanProgressInfo {
+                start_height: progress.start_height,
+                next_height: progress.next_height,
+                tip_height: progress.tip_height,
+            }))
+    }
+
+    async fn abort_rescan(&self) -> rpc::Result<bool> {
+        self.call_mut(|this| this.abort_rescan())
+            .await
+            .map_err(rpc::subsystem_unavailable)
+    }
+}
+
+fn wallet_error_code(e: &WalletError) -> i32 {
+    match e {
+        WalletError::IoError(_)
+        | WalletError::InvalidWalletFile(_)
+        | WalletError::WalletFileExists(_) => error_code::WALLET_FILE_ERROR,
+        WalletError::PasswordRequired | WalletError::WrongPassword => error_code::WRONG_PASSWORD,
+        WalletError::InsufficientFunds { .. } => error_code::INSUFFICIENT_FUNDS,
+        WalletError::AmountOverflow
+        | WalletError::TransactionCreationError(_)
+        | WalletError::SigningError(_)
+        | WalletError::UnsignedInput(_) => error_code::TRANSACTION_FAILED,
+        WalletError::WatchOnly => error_code::WATCH_ONLY,
+        WalletError::EncryptionError(_)
+        | WalletError::KeyDerivationError(_)
+        | WalletError::AddressError(_)
+        | WalletError::ChainstateError(_)
+        | WalletError::SubsystemFailure => error_code::INTERNAL_ERROR,
+    }
+}
+
+/// Report a wallet error with its code and the name of the error as its kind
+fn wallet_error(e: WalletError) -> rpc::Error {
+    rpc::error(wallet_error_code(&e), &e, (&e).into())
+}
+
+fn parse_address(chain_config: &ChainConfig, address: &str) -> rpc::Result<Destination> {
+    Address::from_str(chain_config, address)
+        .and_then(|address| address.destination(chain_config))
+        .map_err(|_| {
+            let message = format!("Invalid address: {}", address);
+            rpc::error(error_code::INVALID_ADDRESS, message, INVALID_ADDRESS)
+        })
+}
+
+fn decode_partially_signed(partially_signed: &str) -> rpc::Result<PartiallySignedTransaction> {
+    hex::decode(partially_signed)
+        .ok()
+        .and_then(|encoded| PartiallySignedTransaction::decode_all(&mut &encoded[..]).ok())
+        .ok_or_else(|| {
+            rpc::error(
+                error_code::PARTIALLY_SIGNED_DECODE_FAILED,
+                "Invalid partially signed transaction",
+                INVALID_ENCODING,
+            )
+        })
+}
+
+fn handle_error<T>(e: Result<Result<T, WalletError>, CallError>) -> rpc::Result<T> {
+    e.map_err(rpc::subsystem_unavailable)?.map_err(wallet_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{make_wallet, WalletConfig};
+    use chainstate::BlockSource;
+    use common::chain::{
+        block::{Block, ConsensusData},
+        signature::inputsig::InputWitness,
+        TxInput,
+    };
+    use crypto::key::{KeyKind, PrivateKey};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    /// Code and kind of an error reported by a method
+    fn error_code_and_kind(res: rpc::Result<Value>) -> (i32, Value) {
+        match res {
+            Err(rpc::Error::Call(jsonrpsee::types::error::CallError::Custom(e))) => {
+                let data = e.data().map_or(Value::Null, |data| {
+                    serde_json::from_str::<Value>(data.get()).unwrap()["kind"].clone()
+                });
+                (e.code(), data)
+            }
+            res => panic!("expected a custom error, got {:?}", res),
+        }
+    }
+
+    fn block_with_tx(prev_block: &Block, tx: Transaction, block_time: u32) -> Block {
+        Block::new(
+            vec![tx],
+            Some(prev_block.get_id()),
+            block_time,
+            ConsensusData::None,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rpc_requests() {
+        let storage = blockchain_storage::Store::new_empty().unwrap();
+        let chain_config = Arc::new(common::chain::config::create_unit_test_config());
+        let dir = tempfile::tempdir().unwrap();
+        let mut man = subsystem::Manager::new("rpctest");
+        let chainstate = man.add_subsystem(
+            "chainstate",
+            chainstate::make_chainstate(Arc::clone(&chain_config), storage, None).unwrap(),
+        );
+        let config = WalletConfig::new(dir.path().join("wallet"), None);
+        let wallet = make_wallet(Arc::clone(&chain_config), chainstate.clone(), &config).unwrap();
+        let wallet = man.add_raw_subsystem("wallet", move |call_rq, shutdown_rq| {
+            crate::run(wallet, call_rq, shutdown_rq)
+        });
+
+        let _ = man.add_raw_subsystem(
+            "test",
+            move |_: subsystem::subsystem::CallRequest<()>, _| async move {
+                let rpc = wallet.clone().into_rpc();
+                let sync = || wallet.call_async_mut(|this| Box::pin(this.sync()));
+
+                let address: String = rpc.call("wallet_new_address", [(); 0]).await.unwrap();
+                let destination = Address::from_str(&chain_config, &address)
+                    .unwrap()
+                    .destination(&chain_config)
+                    .unwrap();
+
+                // Pay the genesis output to the wallet
+                let genesis = chain_config.genesis_block();
+                let genesis_tx = &genesis.transactions()[0];
+                let value = genesis_tx.get_outputs()[0].get_value();
+                let tx = Transaction::new(
+                    0,
+                    vec![TxInput::new(
+                        OutPointSourceId::Transaction(genesis_tx.get_id()),
+                        0,
+                        InputWitness::NoSignature(None),
+                    )],
+                    vec![TxOutput::new(value, destination)],
+                    0,
+                )
+                .unwrap();
+                let block = block_with_tx(genesis, tx, genesis.block_time() + 1);
+                let tip = block.clone();
+                chainstate
+                    .call_mut(|this| this.process_block(block, BlockSource::Local))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                sync().await.unwrap().unwrap();
+
+                let balance: Value = rpc.call("wallet_balance", [(); 0]).await.unwrap();
+                assert_eq!(
+                    balance["confirmed"],
+                    Value::from(value.into_atoms().to_string())
+                );
+                assert_eq!(balance["unconfirmed"], Value::from("0"));
+                let unspent: Value = rpc.call("wallet_list_unspent", [(); 0]).await.unwrap();
+                assert_eq!(unspent[0]["address"], Value::from(address));
+                assert_eq!(unspent[0]["confirmations"], Value::from(1));
+
+                let res = rpc.call("wallet_send_to_address", ("foo", "1000", None::<String>)).await;
+                assert_eq!(
+                    error_code_and_kind(res),
+                    (error_code::INVALID_ADDRESS, Value::from(INVALID_ADDRESS))
+                );
+                let (_, other_public_key) = PrivateKey::new(KeyKind::Secp256k1Schnorr);
+                let other_address =
+                    Address::from_public_key(&chain_config, &other_public_key).unwrap();
+                let res = rpc
+                    .call(
+                        "wallet_send_to_address",
+                        (
+                            other_address.get(),
+                            value.into_atoms().to_string(),
+                            None::<String>,
+                        ),
+                    )
+                    .await;
+                assert_eq!(
+                    error_code_and_kind(res),
+                    (
+                        error_code::INSUFFICIENT_FUNDS,
+                        Value::from("insufficient_funds")
+                    )
+                );
+
+                let sent: Value = rpc
+                    .call(
+                        "wallet_send_to_address",
+                        (other_address.get(), "1000", Some("2000")),
+                    )
+                    .await
+                    .unwrap();
+                let tx_hex = sent["transaction"].as_str().unwrap();
+                let tx = Transaction::decode_all(&mut &hex::decode(tx_hex).unwrap()[..]).unwrap();
+                assert_eq!(sent["tx_id"], serde_json::to_value(tx.get_id()).unwrap());
+                assert_eq!(
+                    sent["wtx_id"],
+                    serde_json::to_value(tx.get_witness_id()).unwrap()
+                );
+                let fee = (value
+                    - Amount::sum(tx.get_outputs().iter().map(|o| o.get_value())).unwrap())
+                .unwrap();
+                assert!(
+                    fee >= FeeRate::from_atoms_per_kb(Amount::from_atoms(2000))
+                        .fee(tx.encoded_size())
+                        .unwrap()
+                );
+
+                // The change is unconfirmed until the transaction is mined
+                let balance: Value = rpc.call("wallet_balance", [(); 0]).await.unwrap();
+                assert_eq!(balance["confirmed"], Value::from("0"));
+                let change = (value - fee).and_then(|v| v - Amount::from_atoms(1000)).unwrap();
+                assert_eq!(
+                    balance["unconfirmed"],
+                    Value::from(change.into_atoms().to_string())
+                );
+
+                // The signed transaction is valid
+                let block = block_with_tx(&tip, tx, tip.block_time() + 1);
+                chainstate
+                    .call_mut(|this| this.process_block(block, BlockSource::Local))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                sync().await.unwrap().unwrap();
+                let balance: Value = rpc.call("wallet_balance", [(); 0]).await.unwrap();
+                assert_eq!(
+                    balance["confirmed"],
+                    Value::from(change.into_atoms().to_string())
+                );
+                assert_eq!(balance["unconfirmed"], Value::from("0"));
+
+                // The same wallet signs the partially signed transaction it creates
+                let res = rpc.call("wallet_sign_partially_signed", ["00"]).await;
+                assert_eq!(
+                    error_code_and_kind(res),
+                    (
+                        error_code::PARTIALLY_SIGNED_DECODE_FAILED,
+                        Value::from(INVALID_ENCODING)
+                    )
+                );
+                let ptx: String = rpc
+                    .call(
+                        "wallet_create_partially_signed",
+                        (other_address.get(), "1000", None::<String>),
+                    )
+                    .await
+                    .unwrap();
+                let res = rpc.call("wallet_finalize_partially_signed", [&ptx]).await;
+                assert_eq!(
+                    error_code_and_kind(res),
+                    (
+                        error_code::TRANSACTION_FAILED,
+                        Value::from("unsigned_input")
+                    )
+                );
+                let signed: Value = rpc.call("wallet_sign_partially_signed", [&ptx]).await.unwrap();
+                assert_eq!(signed["complete"], Value::from(true));
+                let sent: Value = rpc
+                    .call(
+                        "wallet_finalize_partially_signed",
+                        [signed["partially_signed"].as_str().unwrap()],
+                    )
+                    .await
+                    .unwrap();
+                assert!(!sent["tx_id"].is_null());
+                let balance: Value = rpc.call("wallet_balance", [(); 0]).await.unwrap();
+                assert_eq!(balance["confirmed"], Value::from("0"));
+            },
+        );
+        man.main().await;
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet file holding the seed and the state of the wallet
+//!
+//! The whole state is written on every save, to a temporary file which then replaces the wallet
+//! file, so the wallet file is never left partially written.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use common::{
+    chain::{block::Block, OutPoint, Transaction, TxOutput},
+    primitives::{BlockHeight, Id},
+};
+use crypto::symkey::sealed::SealedSecret;
+use serialization::{Decode, DecodeAll, Encode};
+use zeroize::Zeroizing;
+
+use crate::WalletError;
+
+/// Entropy of the mnemonic the keys of the wallet are derived from, or the public key they are
+/// derived from for a watch-only wallet
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum StoredSeed {
+    #[codec(index = 0)]
+    Plain(Vec<u8>),
+    /// Entropy encrypted with the wallet password
+    #[codec(index = 1)]
+    Sealed(SealedSecret),
+    /// Extended public key of the account of a watch-only wallet, in the BIP32 serialization
+    #[codec(index = 2)]
+    WatchOnly(Vec<u8>),
+}
+
+/// Unspent output that the wallet can spend
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct WalletUtxo {
+    pub output: TxOutput,
+    /// Height of the block that created the output
+    pub height: BlockHeight,
+    pub is_block_reward: bool,
+}
+
+/// Changes a main chain block made to the wallet, to be reverted when the block is disconnected
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ScannedBlock {
+    pub id: Id<Block>,
+    /// Wallet outputs created by the block
+    pub created: Vec<OutPoint>,
+    /// Wallet outputs spent by the block
+    pub spent: Vec<(OutPoint, WalletUtxo)>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct WalletFile {
+    pub seed: StoredSeed,
+    pub issued_receive_keys: u32,
+    pub issued_change_keys: u32,
+    /// Main chain blocks scanned by the wallet, the block at height `h` is at index `h`
+    pub blocks: Vec<ScannedBlock>,
+    pub utxos: BTreeMap<OutPoint, WalletUtxo>,
+    /// Transactions of the wallet that are not in the main chain yet
+    pub unconfirmed: Vec<Transaction>,
+}
+
+impl WalletFile {
+    pub fn load(path: &Path) -> Result<Self, WalletError> {
+        let data = Zeroizing::new(fs::read(path)?);
+        WalletFile::decode_all(&mut &data[..])
+            .map_err(|e| WalletError::InvalidWalletFile(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), WalletError> {
+        let data = Zeroizing::new(self.encode());
+        let mut tmp_path = PathBuf::from(path);
+        tmp_path.set_extension("tmp");
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
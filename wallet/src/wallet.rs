@@ -0,0 +1,1147 @@
+// Copyright (c) 2022 RBB S.r.l
+// opensource@mintlayer.org
+// SPDX-License-Identifier: MIT
+// Licensed under the MIT License;
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://spdx.org/licenses/MIT
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use chainstate::{chainstate_interface::ChainstateInterface, ChainstateError, ChainstateHandle};
+use common::{
+    address::{pubkeyhash::PublicKeyHash, Address},
+    chain::{
+        block::{Block, ConsensusData},
+        signature::inputsig::{InputSignatureKind, InputWitness},
+        ChainConfig, Destination, OutPoint, OutPointSourceId, Transaction, TxInput, TxOutput,
+    },
+    primitives::{amount::IntType, Amount, BlockHeight, Id, Idable, H256},
+};
+use crypto::{
+    key::{hd::ExtendedPublicKey, mnemonic::Mnemonic},
+    random::{make_pseudo_rng, make_true_rng},
+    symkey::sealed::{KdfParams, SealedSecret},
+};
+use logging::log;
+use serialization::Encode;
+use zeroize::Zeroizing;
+
+use crate::{
+    coin_selection::{select_coins, Candidate, CoinSelectionParams, CoinSelectionStrategy},
+    key_chain::{KeyChain, KeyPurpose},
+    partially_signed::PartiallySignedTransaction,
+    store::{ScannedBlock, StoredSeed, WalletFile, WalletUtxo},
+    WalletConfig, WalletError,
+};
+
+/// Balance of the wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    /// Value of the main chain outputs that no unconfirmed transaction spends
+    pub confirmed: Amount,
+    /// Value of the outputs of unconfirmed transactions that no other one spends
+    pub unconfirmed: Amount,
+}
+
+/// Fee paid per 1000 bytes of a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeeRate {
+    atoms_per_kb: Amount,
+}
+
+impl FeeRate {
+    pub const fn from_atoms_per_kb(atoms_per_kb: Amount) -> Self {
+        Self { atoms_per_kb }
+    }
+
+    pub fn atoms_per_kb(&self) -> Amount {
+        self.atoms_per_kb
+    }
+
+    /// Fee of a transaction of the given size, rounded up
+    pub fn fee(&self, size: usize) -> Option<Amount> {
+        let atoms = self.atoms_per_kb.into_atoms().checked_mul(size as u128)?;
+        Some(Amount::from_atoms(atoms.checked_add(999)? / 1000))
+    }
+}
+
+/// Fee rate of the transactions sent without an explicit one
+pub const DEFAULT_FEE_RATE: FeeRate = FeeRate::from_atoms_per_kb(Amount::from_atoms(1000));
+
+/// Fee rate the outputs of the wallet are expected to be spent at, used to weigh the fees of
+/// spending more inputs now against spending them later
+pub const LONG_TERM_FEE_RATE: FeeRate = DEFAULT_FEE_RATE;
+
+/// Number of blocks a rescan step connects, the wallet serves calls between the steps
+const RESCAN_BATCH_SIZE: usize = 100;
+
+/// How much earlier than the birthday of the wallet a rescan starts, since block timestamps
+/// don't strictly increase along the chain
+const BIRTHDAY_MARGIN: u32 = 24 * 60 * 60;
+
+/// Block a rescan starts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RescanStart {
+    Height(BlockHeight),
+    /// Timestamp of the creation of the first key used with the wallet, no earlier block can
+    /// pay to it
+    Birthday(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RescanProgress {
+    pub start_height: BlockHeight,
+    /// Height of the next block to rescan
+    pub next_height: BlockHeight,
+    /// Height of the main chain tip when the last rescan step ran
+    pub tip_height: BlockHeight,
+}
+
+/// Main chain blocks scanned by the wallet and the outputs of the wallet they leave unspent
+#[derive(Debug, Clone, Default)]
+struct ChainScan {
+    /// The block at height `h` is at index `h`
+    blocks: Vec<ScannedBlock>,
+    utxos: BTreeMap<OutPoint, WalletUtxo>,
+}
+
+/// Scan from an earlier height running next to the main scan, which it replaces once it reaches
+/// the main chain tip
+struct Rescan {
+    scan: ChainScan,
+    start_height: BlockHeight,
+    tip_height: BlockHeight,
+}
+
+/// HD wallet following the main chain of the chainstate
+///
+/// The keys are secp256k1 Schnorr keys, so the wallet can only spend its outputs once the chain
+/// has activated them.
+pub struct Wallet {
+    chain_config: Arc<ChainConfig>,
+    chainstate: ChainstateHandle,
+    path: PathBuf,
+    seed: StoredSeed,
+    key_chain: KeyChain,
+    scan: ChainScan,
+    unconfirmed: BTreeMap<Id<Transaction>, Transaction>,
+    rescan: Option<Rescan>,
+}
+
+impl Wallet {
+    /// Create a new wallet file with keys derived from the mnemonic
+    pub fn create(
+        chain_config: Arc<ChainConfig>,
+        chainstate: ChainstateHandle,
+        config: &WalletConfig,
+        mnemonic: &Mnemonic,
+    ) -> Result<Self, WalletError> {
+        if config.path().exists() {
+            return Err(WalletError::WalletFileExists(config.path().to_owned()));
+        }
+        let entropy = mnemonic.entropy();
+        let seed = match config.password() {
+            None => StoredSeed::Plain(entropy.to_vec()),
+            Some(password) => StoredSeed::Sealed(
+                SealedSecret::seal(
+                    &mut make_true_rng(),
+                    &entropy,
+                    password.as_bytes(),
+                    KdfParams::default(),
+                )
+                .map_err(|e| WalletError::EncryptionError(format!("{:?}", e)))?,
+            ),
+        };
+        let key_chain = KeyChain::new(&mnemonic.to_seed("")[..], 0, 0)?;
+        Self::create_file(chain_config, chainstate, config, seed, key_chain)
+    }
+
+    /// Create a new watch-only wallet file with the keys of the account with the extended public
+    /// key, its transactions are signed offline with [`PartiallySignedTransaction`]
+    pub fn create_watch_only(
+        chain_config: Arc<ChainConfig>,
+        chainstate: ChainstateHandle,
+        config: &WalletConfig,
+        account_public_key: &ExtendedPublicKey,
+    ) -> Result<Self, WalletError> {
+        if config.path().exists() {
+            return Err(WalletError::WalletFileExists(config.path().to_owned()));
+        }
+        let seed = StoredSeed::WatchOnly(account_public_key.encode().to_vec());
+        let key_chain = KeyChain::new_watch_only(account_public_key.clone(), 0, 0)?;
+        Self::create_file(chain_config, chainstate, config, seed, key_chain)
+    }
+
+    fn create_file(
+        chain_config: Arc<ChainConfig>,
+        chainstate: ChainstateHandle,
+        config: &WalletConfig,
+        seed: StoredSeed,
+        key_chain: KeyChain,
+    ) -> Result<Self, WalletError> {
+        let wallet = Self {
+            chain_config,
+            chainstate,
+            path: config.path().to_owned(),
+            seed,
+            key_chain,
+            scan: ChainScan::default(),
+            unconfirmed: BTreeMap::new(),
+            rescan: None,
+        };
+        wallet.save()?;
+        log::info!("created new wallet in {}", config.path().display());
+        Ok(wallet)
+    }
+
+    /// Open an existing wallet file
+    pub fn open(
+        chain_config: Arc<ChainConfig>,
+        chainstate: ChainstateHandle,
+        config: &WalletConfig,
+    ) -> Result<Self, WalletError> {
+        let file = WalletFile::load(config.path())?;
+        let key_chain = match &file.seed {
+            StoredSeed::WatchOnly(account_public_key) => KeyChain::new_watch_only(
+                ExtendedPublicKey::decode(account_public_key)
+                    .map_err(|e| WalletError::InvalidWalletFile(format!("{:?}", e)))?,
+                file.issued_receive_keys,
+                file.issued_change_keys,
+            )?,
+            seed => KeyChain::new(
+                &unseal_mnemonic(seed, config.password())?.to_seed("")[..],
+                file.issued_receive_keys,
+                file.issued_change_keys,
+            )?,
+        };
+
+        Ok(Self {
+            chain_config,
+            chainstate,
+            path: config.path().to_owned(),
+            seed: file.seed,
+            key_chain,
+            scan: ChainScan {
+                blocks: file.blocks,
+                utxos: file.utxos,
+            },
+            unconfirmed: file.unconfirmed.into_iter().map(|tx| (tx.get_id(), tx)).collect(),
+            rescan: None,
+        })
+    }
+
+    fn save(&self) -> Result<(), WalletError> {
+        WalletFile {
+            seed: self.seed.clone(),
+            issued_receive_keys: self.key_chain.issued(KeyPurpose::Receive),
+            issued_change_keys: self.key_chain.issued(KeyPurpose::Change),
+            blocks: self.scan.blocks.clone(),
+            utxos: self.scan.utxos.clone(),
+            unconfirmed: self.unconfirmed.values().cloned().collect(),
+        }
+        .save(&self.path)
+    }
+
+    /// Mnemonic of the wallet, to back it up, watch-only wallets have none
+    pub fn mnemonic(&self, password: Option<&str>) -> Result<Mnemonic, WalletError> {
+        unseal_mnemonic(&self.seed, password)
+    }
+
+    pub fn chain_config(&self) -> &Arc<ChainConfig> {
+        &self.chain_config
+    }
+
+    pub(crate) fn chainstate(&self) -> &ChainstateHandle {
+        &self.chainstate
+    }
+
+    /// Last main chain block scanned by the wallet
+    pub fn best_block(&self) -> Option<(Id<Block>, BlockHeight)> {
+        self.scan.best_block()
+    }
+
+    /// Issue a new address to receive coins
+    pub fn new_address(&mut self) -> Result<Address, WalletError> {
+        let public_key = self.key_chain.issue_key(KeyPurpose::Receive)?;
+        self.save()?;
+        Address::from_public_key_hash(&self.chain_config, &PublicKeyHash::from(&public_key))
+            .map_err(WalletError::AddressError)
+    }
+
+    /// Unspent main chain outputs of the wallet
+    pub fn utxos(&self) -> &BTreeMap<OutPoint, WalletUtxo> {
+        &self.scan.utxos
+    }
+
+    pub fn key_chain(&self) -> &KeyChain {
+        &self.key_chain
+    }
+
+    fn unconfirmed_spends(&self) -> BTreeSet<&OutPoint> {
+        self.unconfirmed
+            .values()
+            .flat_map(|tx| tx.get_inputs().iter().map(|input| input.get_outpoint()))
+            .collect()
+    }
+
+    pub fn balance(&self) -> Result<Balance, WalletError> {
+        let spent = &self.unconfirmed_spends();
+        let key_chain = &self.key_chain;
+        let confirmed = Amount::sum(
+            self.scan
+                .utxos
+                .iter()
+                .filter(|(outpoint, _)| !spent.contains(outpoint))
+                .map(|(_, utxo)| utxo.output.get_value()),
+        )
+        .ok_or(WalletError::AmountOverflow)?;
+        let unconfirmed = Amount::sum(self.unconfirmed.iter().flat_map(|(tx_id, tx)| {
+            tx.get_outputs()
+                .iter()
+                .enumerate()
+                .filter(move |(index, output)| {
+                    let outpoint =
+                        OutPoint::new(OutPointSourceId::Transaction(tx_id.clone()), *index as u32);
+                    key_chain.is_mine(output.get_destination()) && !spent.contains(&outpoint)
+                })
+                .map(|(_, output)| output.get_value())
+        }))
+        .ok_or(WalletError::AmountOverflow)?;
+        Ok(Balance {
+            confirmed,
+            unconfirmed,
+        })
+    }
+
+    /// Outputs that a new transaction can spend: the main chain outputs that no unconfirmed
+    /// transaction spends and without a timelock
+    ///
+    /// Block rewards are left out even once they are mature, since chainstate can't spend them
+    /// yet: it keeps no transaction index for them.
+    pub fn spendable_utxos(&self) -> Vec<(OutPoint, WalletUtxo)> {
+        let spent = self.unconfirmed_spends();
+        self.scan
+            .utxos
+            .iter()
+            .filter(|(outpoint, utxo)| {
+                !spent.contains(outpoint)
+                    && utxo.output.get_timelock().is_none()
+                    && !utxo.is_block_reward
+            })
+            .map(|(outpoint, utxo)| (outpoint.clone(), utxo.clone()))
+            .collect()
+    }
+
+    /// Build and sign a transaction paying to the outputs, spending the outputs of the wallet
+    /// chosen by the coin selection strategy and sending the change to a new change address
+    pub fn create_transaction(
+        &mut self,
+        outputs: Vec<TxOutput>,
+        fee_rate: FeeRate,
+        strategy: CoinSelectionStrategy,
+    ) -> Result<Transaction, WalletError> {
+        if self.key_chain.is_watch_only() {
+            return Err(WalletError::WatchOnly);
+        }
+        let mut ptx = self.create_partially_signed(outputs, fee_rate, strategy)?;
+        ptx.sign(&self.key_chain)?;
+        ptx.finalize()
+    }
+
+    /// Build a transaction like [`Wallet::create_transaction`] but leave its inputs unsigned,
+    /// with the outputs they spend and their keys filled in for the signer
+    pub fn create_partially_signed(
+        &mut self,
+        outputs: Vec<TxOutput>,
+        fee_rate: FeeRate,
+        strategy: CoinSelectionStrategy,
+    ) -> Result<PartiallySignedTransaction, WalletError> {
+        let total_out = Amount::sum(outputs.iter().map(|output| output.get_value()))
+            .ok_or(WalletError::AmountOverflow)?;
+        // Leave room for the longer encoding of the number of inputs
+        let base_size = unsigned_transaction(&[], outputs.clone())?.encoded_size() + 3;
+        let target = fee_rate
+            .fee(base_size)
+            .and_then(|base_fee| total_out + base_fee)
+            .ok_or(WalletError::AmountOverflow)?;
+        // The change destination is not known before the key is issued, but has the same size
+        let change_placeholder = Destination::Address(PublicKeyHash::zero());
+        let placeholder_outpoint =
+            OutPoint::new(OutPointSourceId::Transaction(Id::new(&H256::zero())), 0);
+        let params = CoinSelectionParams {
+            target,
+            fee_rate,
+            long_term_fee_rate: LONG_TERM_FEE_RATE,
+            change_output_size: TxOutput::new(
+                Amount::from_atoms(IntType::MAX),
+                change_placeholder.clone(),
+            )
+            .encoded_size(),
+            change_input_size: estimated_input_size(&placeholder_outpoint, &change_placeholder),
+        };
+
+        let utxos = self.spendable_utxos();
+        let candidates: Vec<_> = utxos
+            .iter()
+            .map(|(outpoint, utxo)| Candidate {
+                value: utxo.output.get_value(),
+                input_size: estimated_input_size(outpoint, utxo.output.get_destination()),
+            })
+            .collect();
+        let selection = select_coins(strategy, &candidates, &params, &mut make_pseudo_rng())?;
+        let selected: Vec<_> = selection.inputs.iter().map(|index| utxos[*index].clone()).collect();
+
+        let mut outputs = outputs;
+        if let Some(change) = selection.change {
+            let change_key = self.key_chain.issue_key(KeyPurpose::Change)?;
+            let change_destination = Destination::Address(PublicKeyHash::from(&change_key));
+            outputs.push(TxOutput::new(change, change_destination));
+        }
+
+        let mut ptx = PartiallySignedTransaction::new(unsigned_transaction(&selected, outputs)?);
+        self.fill_partially_signed(&mut ptx)?;
+        Ok(ptx)
+    }
+
+    /// Fill in the outputs spent by the inputs of the transaction that the wallet knows and the
+    /// keys that can spend them, returns the number of inputs filled in
+    pub fn fill_partially_signed(
+        &self,
+        ptx: &mut PartiallySignedTransaction,
+    ) -> Result<usize, WalletError> {
+        let known: Vec<_> = ptx
+            .transaction()
+            .get_inputs()
+            .iter()
+            .enumerate()
+            .filter_map(|(input, tx_input)| {
+                let output = self.scan.utxos.get(tx_input.get_outpoint())?.output.clone();
+                Some((input, output))
+            })
+            .collect();
+        let filled = known.len();
+        for (input, output) in known {
+            let key = self.key_chain.find_key(output.get_destination());
+            ptx.fill_input(input, output, key)?;
+        }
+        Ok(filled)
+    }
+
+    /// Sign the inputs of the transaction that the keys of the wallet can spend, returns the
+    /// number of inputs signed
+    ///
+    /// The wallet doesn't need to follow the chain, the outputs spent are taken from the partially
+    /// signed transaction.
+    pub fn sign_partially_signed(
+        &self,
+        ptx: &mut PartiallySignedTransaction,
+    ) -> Result<usize, WalletError> {
+        ptx.sign(&self.key_chain)
+    }
+
+    /// Extract the signed transaction and track it like [`Wallet::send_to_address`]
+    pub fn finalize_partially_signed(
+        &mut self,
+        ptx: PartiallySignedTransaction,
+    ) -> Result<Transaction, WalletError> {
+        let tx = ptx.finalize()?;
+        self.add_unconfirmed_transaction(tx.clone())?;
+        Ok(tx)
+    }
+
+    /// Pay the amount to the destination with a new transaction, which the wallet tracks until it
+    /// is included in the main chain
+    ///
+    /// The node has no mempool to submit the transaction to yet, the caller has to get it into a
+    /// block.
+    pub fn send_to_address(
+        &mut self,
+        destination: Destination,
+        amount: Amount,
+        fee_rate: FeeRate,
+        strategy: CoinSelectionStrategy,
+    ) -> Result<Transaction, WalletError> {
+        let tx =
+            self.create_transaction(vec![TxOutput::new(amount, destination)], fee_rate, strategy)?;
+        self.add_unconfirmed_transaction(tx.clone())?;
+        Ok(tx)
+    }
+
+    /// Track a transaction that is not in the main chain yet, returns whether it concerns the
+    /// wallet
+    ///
+    /// The transaction is dropped once it is included in the main chain or a main chain
+    /// transaction spends one of its inputs.
+    pub fn add_unconfirmed_transaction(&mut self, tx: Transaction) -> Result<bool, WalletError> {
+        let tx_id = tx.get_id();
+        let spends_wallet_output = tx.get_inputs().iter().any(|input| {
+            let outpoint = input.get_outpoint();
+            self.scan.utxos.contains_key(outpoint)
+                || match outpoint.get_tx_id() {
+                    OutPointSourceId::Transaction(id) => self.unconfirmed.contains_key(&id),
+                    OutPointSourceId::BlockReward(_) => false,
+                }
+        });
+        let pays_wallet = tx
+            .get_outputs()
+            .iter()
+            .any(|output| self.key_chain.is_mine(output.get_destination()));
+        if (!spends_wallet_output && !pays_wallet) || self.unconfirmed.contains_key(&tx_id) {
+            return Ok(false);
+        }
+
+        for output in tx.get_outputs() {
+            self.key_chain.mark_used(output.get_destination())?;
+        }
+        self.unconfirmed.insert(tx_id, tx);
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Transactions of the wallet that are not in the main chain yet
+    pub fn unconfirmed_transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.unconfirmed.values()
+    }
+
+    /// Catch up with the main chain of the chainstate, disconnecting the scanned blocks that
+    /// left it and connecting the new ones
+    pub async fn sync(&mut self) -> Result<(), WalletError> {
+        let best_block = self.scan.best_block();
+        self.scan
+            .follow_main_chain(
+                &self.chainstate,
+                &mut self.key_chain,
+                &mut self.unconfirmed,
+                usize::MAX,
+            )
+            .await?;
+        if self.scan.best_block() != best_block {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Start replaying the main chain blocks from the given start through the wallet, to find
+    /// the outputs paying to keys the wallet didn't watch when it scanned them, returns the height
+    /// of the first block to rescan
+    ///
+    /// The rescan runs in steps with [`Wallet::rescan_step`] and the wallet keeps its state until
+    /// the rescan reaches the main chain tip, so it can be aborted without effect. Starting a
+    /// rescan replaces the one in progress.
+    pub async fn start_rescan(&mut self, start: RescanStart) -> Result<BlockHeight, WalletError> {
+        let start_height = match start {
+            RescanStart::Height(height) => height,
+            RescanStart::Birthday(birthday) => {
+                let time = birthday.saturating_sub(BIRTHDAY_MARGIN);
+                chainstate_call(&self.chainstate, move |this| first_block_after(this, time)).await?
+            }
+        };
+        let mut scan = self.scan.clone();
+        while scan.next_height() > start_height {
+            scan.disconnect_block();
+        }
+        let start_height = scan.next_height();
+        let tip_height =
+            chainstate_call(&self.chainstate, |this| this.get_best_block_height()).await?;
+        log::info!("wallet rescan started from height {}", start_height);
+        self.rescan = Some(Rescan {
+            scan,
+            start_height,
+            tip_height,
+        });
+        Ok(start_height)
+    }
+
+    /// Whether a rescan is in progress
+    pub fn is_rescanning(&self) -> bool {
+        self.rescan.is_some()
+    }
+
+    pub fn rescan_progress(&self) -> Option<RescanProgress> {
+        self.rescan.as_ref().map(|rescan| RescanProgress {
+            start_height: rescan.start_height,
+            next_height: rescan.scan.next_height(),
+            tip_height: rescan.tip_height,
+        })
+    }
+
+    /// Abort the rescan in progress, returns whether there was one
+    pub fn abort_rescan(&mut self) -> bool {
+        let aborted = self.rescan.take();
+        if let Some(rescan) = &aborted {
+            log::info!(
+                "wallet rescan aborted at height {}",
+                rescan.scan.next_height()
+            );
+        }
+        aborted.is_some()
+    }
+
+    /// Rescan the next batch of blocks, the rescan replaces the state of the wallet once it
+    /// reaches the main chain tip and is dropped if the step fails
+    pub async fn rescan_step(&mut self) -> Result<(), WalletError> {
+        let mut rescan = match self.rescan.take() {
+            Some(rescan) => rescan,
+            None => return Ok(()),
+        };
+        let at_tip = rescan
+            .scan
+            .follow_main_chain(
+                &self.chainstate,
+                &mut self.key_chain,
+                &mut self.unconfirmed,
+                RESCAN_BATCH_SIZE,
+            )
+            .await?;
+        if at_tip {
+            log::info!(
+                "wallet rescan finished at height {}",
+                rescan.scan.next_height()
+            );
+            self.scan = rescan.scan;
+            self.save()?;
+        } else {
+            rescan.tip_height =
+                chainstate_call(&self.chainstate, |this| this.get_best_block_height()).await?;
+            self.rescan = Some(rescan);
+        }
+        Ok(())
+    }
+}
+
+impl ChainScan {
+    /// Last main chain block scanned
+    fn best_block(&self) -> Option<(Id<Block>, BlockHeight)> {
+        self.blocks.last().map(|block| {
+            (
+                block.id.clone(),
+                BlockHeight::new(self.blocks.len() as u64 - 1),
+            )
+        })
+    }
+
+    /// Height of the next block to scan
+    fn next_height(&self) -> BlockHeight {
+        BlockHeight::new(self.blocks.len() as u64)
+    }
+
+    /// Disconnect the scanned blocks that left the main chain and connect up to `max_blocks`
+    /// main chain blocks, returns whether the scan reached the main chain tip
+    async fn follow_main_chain(
+        &mut self,
+        chainstate: &ChainstateHandle,
+        key_chain: &mut KeyChain,
+        unconfirmed: &mut BTreeMap<Id<Transaction>, Transaction>,
+        max_blocks: usize,
+    ) -> Result<bool, WalletError> {
+        let mut connected = 0;
+        'sync: loop {
+            while let Some(block) = self.blocks.last() {
+                // The scanned block is still in the main chain if it's the main chain block
+                // at its height
+                let block_id = block.id.clone();
+                let height = BlockHeight::new(self.blocks.len() as u64 - 1);
+                if chainstate_call(chainstate, move |this| {
+                    this.get_block_id_from_height(&height)
+                })
+                .await?
+                    == Some(block_id)
+                {
+                    break;
+                }
+                self.disconnect_block();
+            }
+
+            while connected < max_blocks {
+                let height = self.next_height();
+                let block_id = match chainstate_call(chainstate, move |this| {
+                    this.get_block_id_from_height(&height)
+                })
+                .await?
+                {
+                    Some(block_id) => block_id,
+                    None => return Ok(true),
+                };
+                let block = chainstate_call(chainstate, move |this| this.get_block(block_id))
+                    .await?
+                    .ok_or(WalletError::ChainstateError(
+                        ChainstateError::FailedToReadProperty(chainstate::BlockError::NotFound),
+                    ))?;
+                if block.prev_block_id() != self.blocks.last().map(|block| block.id.clone()) {
+                    // The main chain changed while connecting, find the fork point again
+                    continue 'sync;
+                }
+                self.connect_block(key_chain, unconfirmed, &block, height)?;
+                connected += 1;
+            }
+            return Ok(false);
+        }
+    }
+
+    fn add_output(
+        &mut self,
+        key_chain: &mut KeyChain,
+        outpoint: OutPoint,
+        output: &TxOutput,
+        height: BlockHeight,
+        is_block_reward: bool,
+        scanned: &mut ScannedBlock,
+    ) -> Result<(), WalletError> {
+        if key_chain.is_mine(output.get_destination()) {
+            key_chain.mark_used(output.get_destination())?;
+            self.utxos.insert(
+                outpoint.clone(),
+                WalletUtxo {
+                    output: output.clone(),
+                    height,
+                    is_block_reward,
+                },
+            );
+            scanned.created.push(outpoint);
+        }
+        Ok(())
+    }
+
+    /// Scan the block, dropping the unconfirmed transactions it includes or conflicts with
+    fn connect_block(
+        &mut self,
+        key_chain: &mut KeyChain,
+        unconfirmed: &mut BTreeMap<Id<Transaction>, Transaction>,
+        block: &Block,
+        height: BlockHeight,
+    ) -> Result<(), WalletError> {
+        let block_id = block.get_id();
+        let mut scanned = ScannedBlock {
+            id: block_id.clone(),
+            created: Vec::new(),
+            spent: Vec::new(),
+        };
+
+        match block.consensus_data() {
+            ConsensusData::PoW(pow_data) => {
+                for (index, output) in pow_data.outputs().iter().enumerate() {
+                    let outpoint = OutPoint::new(
+                        OutPointSourceId::BlockReward(block_id.clone()),
+                        index as u32,
+                    );
+                    self.add_output(key_chain, outpoint, output, height, true, &mut scanned)?;
+                }
+            }
+            ConsensusData::None => {}
+        }
+
+        let mut block_spends = BTreeSet::new();
+        for tx in block.transactions() {
+            let tx_id = tx.get_id();
+            for input in tx.get_inputs() {
+                let outpoint = input.get_outpoint();
+                if let Some(utxo) = self.utxos.remove(outpoint) {
+                    scanned.spent.push((outpoint.clone(), utxo));
+                }
+                block_spends.insert(outpoint.clone());
+            }
+            for (index, output) in tx.get_outputs().iter().enumerate() {
+                let outpoint =
+                    OutPoint::new(OutPointSourceId::Transaction(tx_id.clone()), index as u32);
+                self.add_output(key_chain, outpoint, output, height, false, &mut scanned)?;
+            }
+            unconfirmed.remove(&tx_id);
+        }
+
+        // Transactions double spent by the block can never be confirmed
+        unconfirmed.retain(|_, tx| {
+            !tx.get_inputs().iter().any(|input| block_spends.contains(input.get_outpoint()))
+        });
+
+        self.blocks.push(scanned);
+        Ok(())
+    }
+
+    fn disconnect_block(&mut self) {
+        let scanned = self.blocks.pop().expect("a scanned block to disconnect");
+        for outpoint in scanned.created {
+            self.utxos.remove(&outpoint);
+        }
+        for (outpoint, utxo) in scanned.spent {
+            self.utxos.insert(outpoint, utxo);
+        }
+    }
+}
+
+async fn chainstate_call<R: Send + 'static>(
+    chainstate: &ChainstateHandle,
+    func: impl FnOnce(&dyn ChainstateInterface) -> Result<R, ChainstateError> + Send + 'static,
+) -> Result<R, WalletError> {
+    chainstate
+        .call(move |this| func(this.as_ref()))
+        .await
+        .map_err(|_| WalletError::SubsystemFailure)?
+        .map_err(WalletError::ChainstateError)
+}
+
+/// Height of the first main chain block with a timestamp not earlier than the time, or the height
+/// past the tip if there's none
+///
+/// The timestamps are assumed to increase along the chain.
+fn first_block_after(
+    chainstate: &dyn ChainstateInterface,
+    time: u32,
+) -> Result<BlockHeight, ChainstateError> {
+    let block_time = |height: u64| -> Result<u32, ChainstateError> {
+        let not_found = || ChainstateError::FailedToReadProperty(chainstate::BlockError::NotFound);
+        let block_id = chainstate
+            .get_block_id_from_height(&BlockHeight::new(height))?
+            .ok_or_else(not_found)?;
+        Ok(chainstate.get_block(block_id)?.ok_or_else(not_found)?.block_time())
+    };
+    let (mut low, mut high) = (0, u64::from(chainstate.get_best_block_height()?) + 1);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if block_time(middle)? < time {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(BlockHeight::new(low))
+}
+
+/// Size of the input spending the outpoint locked to the destination once signed
+fn estimated_input_size(outpoint: &OutPoint, destination: &Destination) -> usize {
+    let kind = match destination {
+        Destination::PublicKey(_) => InputSignatureKind::PublicKey,
+        _ => InputSignatureKind::PublicKeyHash,
+    };
+    let unsigned = TxInput::new(
+        outpoint.get_tx_id(),
+        outpoint.get_output_index(),
+        InputWitness::NoSignature(None),
+    );
+    unsigned.encoded_size() - InputWitness::NoSignature(None).encoded_size()
+        + InputWitness::estimated_size(kind)
+}
+
+/// Transaction spending the inputs, without witnesses
+fn unsigned_transaction(
+    inputs: &[(OutPoint, WalletUtxo)],
+    outputs: Vec<TxOutput>,
+) -> Result<Transaction, WalletError> {
+    let inputs = inputs
+        .iter()
+        .map(|(outpoint, _)| {
+            TxInput::new(
+                outpoint.get_tx_id(),
+                outpoint.get_output_index(),
+                InputWitness::NoSignature(None),
+            )
+        })
+        .collect();
+    Transaction::new(0, inputs, outputs, 0)
+        .map_err(|e| WalletError::TransactionCreationError(format!("{:?}", e)))
+}
+
+fn unseal_mnemonic(seed: &StoredSeed, password: Option<&str>) -> Result<Mnemonic, WalletError> {
+    let entropy = match (seed, password) {
+        (StoredSeed::Plain(entropy), _) => Zeroizing::new(entropy.clone()),
+        (StoredSeed::Sealed(sealed), Some(password)) => {
+            sealed.unseal(password.as_bytes()).map_err(|_| WalletError::WrongPassword)?
+        }
+        (StoredSeed::Sealed(_), None) => return Err(WalletError::PasswordRequired),
+        (StoredSeed::WatchOnly(_), _) => return Err(WalletError::WatchOnly),
+    };
+    Mnemonic::from_entropy(&entropy).map_err(|e| WalletError::InvalidWalletFile(format!("{:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_chain::LOOKAHEAD_SIZE, make_wallet};
+    use chainstate::BlockSource;
+    use common::primitives::H256;
+    use std::future::Future;
+
+    async fn with_chainstate<F: 'static + Send + Future<Output = ()>>(
+        proc: impl 'static + Send + FnOnce(ChainstateHandle) -> F,
+    ) {
+        let storage = blockchain_storage::Store::new_empty().unwrap();
+        let cfg = Arc::new(common::chain::config::create_unit_test_config());
+        let mut man = subsystem::Manager::new("wallettest");
+        let handle = man.add_subsystem(
+            "chainstate",
+            chainstate::make_chainstate(cfg, storage, None).unwrap(),
+        );
+        let _ = man.add_raw_subsystem(
+            "test",
+            move |_: subsystem::subsystem::CallRequest<()>, _| proc(handle),
+        );
+        man.main().await;
+    }
+
+    /// Block spending the first output of the first transaction of `parent`
+    fn child_block(parent: &Block, destination: Destination, block_time: u32) -> Block {
+        let parent_tx = &parent.transactions()[0];
+        let tx = Transaction::new(
+            0,
+            vec![TxInput::new(
+                OutPointSourceId::Transaction(parent_tx.get_id()),
+                0,
+                InputWitness::NoSignature(None),
+            )],
+            vec![TxOutput::new(parent_tx.get_outputs()[0].get_value(), destination)],
+            0,
+        )
+        .unwrap();
+        Block::new(
+            vec![tx],
+            Some(parent.get_id()),
+            block_time,
+            ConsensusData::None,
+        )
+        .unwrap()
+    }
+
+    async fn process_block(chainstate: &ChainstateHandle, block: Block) {
+        chainstate
+            .call_mut(|this| this.process_block(block, BlockSource::Local))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn follow_main_chain() {
+        with_chainstate(|chainstate| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let config = WalletConfig::new(dir.path().join("wallet"), Some("password".into()));
+            let chain_config = chainstate.call(|this| this.get_chain_config()).await.unwrap();
+            let genesis = chain_config.genesis_block().clone();
+            let value = genesis.transactions()[0].get_outputs()[0].get_value();
+
+            let mut wallet =
+                make_wallet(Arc::clone(&chain_config), chainstate.clone(), &config).unwrap();
+            wallet.sync().await.unwrap();
+            assert_eq!(
+                wallet.best_block(),
+                Some((chain_config.genesis_block_id(), BlockHeight::zero()))
+            );
+            assert!(wallet.utxos().is_empty());
+
+            let destination = wallet.new_address().unwrap().destination(&chain_config).unwrap();
+            let block = child_block(&genesis, destination, genesis.block_time() + 1);
+            let block_id = block.get_id();
+            process_block(&chainstate, block).await;
+            wallet.sync().await.unwrap();
+            assert_eq!(wallet.best_block(), Some((block_id, BlockHeight::new(1))));
+            assert_eq!(wallet.utxos().len(), 1);
+            assert_eq!(
+                wallet.balance().unwrap(),
+                Balance {
+                    confirmed: value,
+                    unconfirmed: Amount::from_atoms(0),
+                }
+            );
+
+            // The state is persisted in the wallet file, the seed needs the password
+            let mnemonic = wallet.mnemonic(Some("password")).unwrap();
+            assert!(matches!(
+                wallet.mnemonic(None),
+                Err(WalletError::PasswordRequired)
+            ));
+            std::mem::drop(wallet);
+            let wrong_password = WalletConfig::new(config.path().to_owned(), Some("wrong".into()));
+            assert!(matches!(
+                make_wallet(
+                    Arc::clone(&chain_config),
+                    chainstate.clone(),
+                    &wrong_password
+                ),
+                Err(WalletError::WrongPassword)
+            ));
+            let mut wallet =
+                make_wallet(Arc::clone(&chain_config), chainstate.clone(), &config).unwrap();
+            assert_eq!(wallet.mnemonic(Some("password")).unwrap(), mnemonic);
+            assert_eq!(wallet.key_chain().issued(KeyPurpose::Receive), 1);
+            assert_eq!(wallet.balance().unwrap().confirmed, value);
+
+            // A longer chain not paying to the wallet replaces the block
+            let fork = child_block(
+                &genesis,
+                Destination::AnyoneCanSpend,
+                genesis.block_time() + 2,
+            );
+            let fork_tip =
+                child_block(&fork, Destination::AnyoneCanSpend, genesis.block_time() + 3);
+            let fork_tip_id = fork_tip.get_id();
+            process_block(&chainstate, fork).await;
+            process_block(&chainstate, fork_tip).await;
+            wallet.sync().await.unwrap();
+            assert_eq!(
+                wallet.best_block(),
+                Some((fork_tip_id, BlockHeight::new(2)))
+            );
+            assert!(wallet.utxos().is_empty());
+            assert_eq!(wallet.balance().unwrap().confirmed, Amount::from_atoms(0));
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn block_rewards_not_spendable() {
+        with_chainstate(|chainstate| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let config = WalletConfig::new(dir.path().join("wallet"), None);
+            let chain_config = chainstate.call(|this| this.get_chain_config()).await.unwrap();
+            let mut wallet =
+                make_wallet(Arc::clone(&chain_config), chainstate.clone(), &config).unwrap();
+            let destination = wallet.new_address().unwrap().destination(&chain_config).unwrap();
+
+            // A mature reward is tracked but not offered to the coin selection
+            let reward = OutPoint::new(OutPointSourceId::BlockReward(H256::random().into()), 0);
+            wallet.scan.utxos.insert(
+                reward,
+                WalletUtxo {
+                    output: TxOutput::new(Amount::from_atoms(100), destination),
+                    height: BlockHeight::zero(),
+                    is_block_reward: true,
+                },
+            );
+            assert_eq!(wallet.utxos().len(), 1);
+            assert!(wallet.spendable_utxos().is_empty());
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_transactions() {
+        with_chainstate(|chainstate| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let config = WalletConfig::new(dir.path().join("wallet"), None);
+            let chain_config = chainstate.call(|this| this.get_chain_config()).await.unwrap();
+            let genesis = chain_config.genesis_block().clone();
+
+            let mut wallet =
+                make_wallet(Arc::clone(&chain_config), chainstate.clone(), &config).unwrap();
+            let destination = wallet.new_address().unwrap().destination(&chain_config).unwrap();
+            let block = child_block(&genesis, destination, genesis.block_time() + 1);
+            let wallet_tx_id = block.transactions()[0].get_id();
+            process_block(&chainstate, block).await;
+            wallet.sync().await.unwrap();
+
+            // Transactions not concerning the wallet are ignored
+            let unrelated = child_block(&genesis, Destination::AnyoneCanSpend, 0);
+            let unrelated_tx = unrelated.transactions()[0].clone();
+            assert!(!wallet.add_unconfirmed_transaction(unrelated_tx).unwrap());
+
+            // Send a part of the output to another address of the wallet
+            let change = wallet.new_address().unwrap().destination(&chain_config).unwrap();
+            let tx = Transaction::new(
+                0,
+                vec![TxInput::new(
+                    OutPointSourceId::Transaction(wallet_tx_id),
+                    0,
+                    InputWitness::NoSignature(None),
+                )],
+                vec![
+                    TxOutput::new(Amount::from_atoms(30), change),
+                    TxOutput::new(Amount::from_atoms(70), Destination::AnyoneCanSpend),
+                ],
+                0,
+            )
+            .unwrap();
+            assert!(wallet.add_unconfirmed_transaction(tx.clone()).unwrap());
+            assert!(!wallet.add_unconfirmed_transaction(tx).unwrap());
+            assert_eq!(
+                wallet.balance().unwrap(),
+                Balance {
+                    confirmed: Amount::from_atoms(0),
+                    unconfirmed: Amount::from_atoms(30),
+                }
+            );
+            assert_eq!(wallet.unconfirmed_transactions().count(), 1);
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn rescan() {
+        with_chainstate(|chainstate| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let config = WalletConfig::new(dir.path().join("wallet"), None);
+            let chain_config = chainstate.call(|this| this.get_chain_config()).await.unwrap();
+            let genesis = chain_config.genesis_block().clone();
+            let value = genesis.transactions()[0].get_outputs()[0].get_value();
+
+            // A copy of the wallet pays to a key past the lookahead window of the wallet
+            let mut wallet =
+                make_wallet(Arc::clone(&chain_config), chainstate.clone(), &config).unwrap();
+            let copy_config = WalletConfig::new(dir.path().join("copy"), None);
+            let mut copy = Wallet::create(
+                Arc::clone(&chain_config),
+                chainstate.clone(),
+                &copy_config,
+                &wallet.mnemonic(None).unwrap(),
+            )
+            .unwrap();
+            let address = (0..=LOOKAHEAD_SIZE).map(|_| copy.new_address().unwrap()).last();
+            let destination = address.unwrap().destination(&chain_config).unwrap();
+            let block = child_block(&genesis, destination, genesis.block_time() + 1);
+            let block_id = block.get_id();
+            process_block(&chainstate, block).await;
+            wallet.sync().await.unwrap();
+            assert!(wallet.utxos().is_empty());
+
+            // The key is watched once the wallet issues a key, but the block is already scanned
+            wallet.new_address().unwrap();
+            assert_eq!(
+                wallet.start_rescan(RescanStart::Height(BlockHeight::new(1))).await.unwrap(),
+                BlockHeight::new(1)
+            );
+            assert!(wallet.abort_rescan());
+            assert!(!wallet.abort_rescan());
+            assert!(wallet.utxos().is_empty());
+
+            // Nothing to rescan after the tip
+            assert_eq!(
+                wallet.start_rescan(RescanStart::Birthday(u32::MAX)).await.unwrap(),
+                BlockHeight::new(2)
+            );
+            wallet.rescan_step().await.unwrap();
+            assert!(!wallet.is_rescanning());
+            assert!(wallet.utxos().is_empty());
+
+            // The wallet keeps its state until the rescan reaches the tip
+            assert_eq!(
+                wallet.start_rescan(RescanStart::Birthday(genesis.block_time())).await.unwrap(),
+                BlockHeight::zero()
+            );
+            assert_eq!(
+                wallet.rescan_progress(),
+                Some(RescanProgress {
+                    start_height: BlockHeight::zero(),
+                    next_height: BlockHeight::zero(),
+                    tip_height: BlockHeight::new(1),
+                })
+            );
+            assert!(wallet.utxos().is_empty());
+            while wallet.is_rescanning() {
+                wallet.rescan_step().await.unwrap();
+            }
+            assert_eq!(wallet.rescan_progress(), None);
+            assert_eq!(wallet.best_block(), Some((block_id, BlockHeight::new(1))));
+            assert_eq!(wallet.balance().unwrap().confirmed, value);
+        })
+        .await
+    }
+}